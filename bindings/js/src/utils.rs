@@ -75,6 +75,9 @@ pub(crate) fn to_record(schema: &[DynamicField], cols: &[Value]) -> JsValue {
             Value::Date32(_)
             | Value::Date64(_)
             | Value::List(_, _)
+            | Value::Composite(_)
+            | Value::Struct(_, _)
+            | Value::Decimal128(_, _, _)
             | Value::Time32(_, _)
             | Value::Time64(_, _)
             | Value::Timestamp(_, _) => unimplemented!(),
@@ -107,6 +110,9 @@ pub(crate) fn to_record_ref(schema: &[DynamicField], cols: &[ValueRef]) -> JsVal
             ValueRef::Date32(_)
             | ValueRef::Date64(_)
             | ValueRef::List(_, _)
+            | ValueRef::Composite(_)
+            | ValueRef::Struct(_, _)
+            | ValueRef::Decimal128(_, _, _)
             | ValueRef::Time32(_, _)
             | ValueRef::Time64(_, _)
             | ValueRef::Timestamp(_, _) => unimplemented!(),
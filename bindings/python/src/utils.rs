@@ -36,6 +36,9 @@ pub(crate) fn to_dict(
             Value::Date32(_)
             | Value::Date64(_)
             | Value::List(_, _)
+            | Value::Composite(_)
+            | Value::Struct(_, _)
+            | Value::Decimal128(_, _, _)
             | Value::Timestamp(_, _)
             | Value::Time32(_, _)
             | Value::Time64(_, _) => unimplemented!(),
@@ -73,6 +76,9 @@ pub(crate) fn to_dict_ref<'py, 'r>(
             ValueRef::Date32(_)
             | ValueRef::Date64(_)
             | ValueRef::List(_, _)
+            | ValueRef::Composite(_)
+            | ValueRef::Struct(_, _)
+            | ValueRef::Decimal128(_, _, _)
             | ValueRef::Timestamp(_, _)
             | ValueRef::Time32(_, _)
             | ValueRef::Time64(_, _) => unimplemented!(),
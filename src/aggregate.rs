@@ -0,0 +1,565 @@
+//! Aggregates of the records of a [`DB`](crate::DB) that flushes keep up to date, see
+//! [`DbOption::aggregate`](crate::DbOption::aggregate)
+//!
+//! The groups of an aggregate are stored in the manifest and count the newest record of each key
+//! of the SSTables. A flush replaces the records of the keys it writes, and a read replaces the
+//! records of the keys of its group that are still in the memtables.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    mem::size_of,
+    ops::Bound,
+    sync::Arc,
+};
+
+use fusio::{SeqRead, Write};
+use fusio_log::{Decode, Encode};
+use futures_util::StreamExt;
+use parquet::arrow::ProjectionMask;
+
+use crate::{
+    context::Context,
+    fs::FileId,
+    inmem::immutable::ImmutableMemTable,
+    record::{Key as RecordKey, KeyRef, Record, Schema},
+    stream::{merge::MergeStream, record_batch::RecordBatchEntry},
+    version::{edit::VersionEdit, error::VersionError, Version},
+    DbOption,
+};
+
+type Key<R> = <<R as Record>::Schema as Schema>::Key;
+
+/// The group of a key, if it belongs to one
+pub(crate) type GroupOf<R> = Arc<dyn Fn(&Key<R>) -> Option<Key<R>> + Send + Sync>;
+
+/// The value of a record that is summed up, if it has one
+pub(crate) type ValueOf<R> =
+    Arc<dyn for<'r> Fn(<R as Record>::Ref<'r>) -> Option<i64> + Send + Sync>;
+
+/// The groups of an aggregate stored in a version
+pub(crate) type Groups<K> = HashMap<K, Group>;
+
+/// The aggregates of [`DbOption::aggregate`](crate::DbOption::aggregate) by name
+pub(crate) type Aggregates<R> = HashMap<String, MaterializedAggregate<R>>;
+
+/// Count, sum, minimum and maximum of the records of a group, see
+/// [`DB::aggregate`](crate::DB::aggregate)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Aggregate {
+    /// Number of records of the group
+    pub count: u64,
+    /// Sum of the values of the records
+    pub sum: i128,
+    /// Smallest value of the records, `None` if none has a value
+    pub min: Option<i64>,
+    /// Largest value of the records, `None` if none has a value
+    pub max: Option<i64>,
+}
+
+/// State of a group of an aggregate, see [`VersionEdit::AggregateGroup`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Group {
+    count: u64,
+    sum: i128,
+    // number of records with each value, so the minimum and maximum survive removals
+    values: BTreeMap<i64, u64>,
+}
+
+impl Group {
+    /// Whether no record is counted anymore
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub(crate) fn aggregate(&self) -> Aggregate {
+        Aggregate {
+            count: self.count,
+            sum: self.sum,
+            min: self.values.first_key_value().map(|(value, _)| *value),
+            max: self.values.last_key_value().map(|(value, _)| *value),
+        }
+    }
+
+    // Replace the record of a key with the value `previous` by one with the value `value`, `None`
+    // for a key without a record
+    pub(crate) fn replace(&mut self, previous: Option<Option<i64>>, value: Option<Option<i64>>) {
+        if let Some(previous) = previous {
+            self.count = self.count.saturating_sub(1);
+            if let Some(previous) = previous {
+                self.sum -= previous as i128;
+                if let Some(count) = self.values.get_mut(&previous) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.values.remove(&previous);
+                    }
+                }
+            }
+        }
+        if let Some(value) = value {
+            self.count += 1;
+            if let Some(value) = value {
+                self.sum += value as i128;
+                *self.values.entry(value).or_default() += 1;
+            }
+        }
+    }
+}
+
+impl Encode for Group {
+    async fn encode<W>(&self, writer: &mut W) -> Result<(), fusio::Error>
+    where
+        W: Write,
+    {
+        self.count.encode(writer).await?;
+        let (result, _) = writer.write_all(&self.sum.to_le_bytes()[..]).await;
+        result?;
+        (self.values.len() as u32).encode(writer).await?;
+        for (value, count) in &self.values {
+            value.encode(writer).await?;
+            count.encode(writer).await?;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        size_of::<u64>()
+            + size_of::<i128>()
+            + size_of::<u32>()
+            + self.values.len() * (size_of::<i64>() + size_of::<u64>())
+    }
+}
+
+impl Decode for Group {
+    async fn decode<R: SeqRead>(reader: &mut R) -> Result<Self, fusio::Error> {
+        let count = u64::decode(reader).await?;
+        let sum = {
+            let mut buf = [0u8; 16];
+            let (result, _) = reader.read_exact(&mut buf[..]).await;
+            result?;
+            i128::from_le_bytes(buf)
+        };
+        let len = u32::decode(reader).await?;
+        let mut values = BTreeMap::new();
+        for _ in 0..len {
+            let value = i64::decode(reader).await?;
+            values.insert(value, u64::decode(reader).await?);
+        }
+
+        Ok(Group { count, sum, values })
+    }
+}
+
+/// An aggregate registered with [`DbOption::aggregate`](crate::DbOption::aggregate)
+pub(crate) struct MaterializedAggregate<R>
+where
+    R: Record,
+{
+    group_of: GroupOf<R>,
+    value_of: ValueOf<R>,
+}
+
+impl<R> MaterializedAggregate<R>
+where
+    R: Record,
+{
+    pub(crate) fn new(group_of: GroupOf<R>, value_of: ValueOf<R>) -> Self {
+        Self { group_of, value_of }
+    }
+
+    pub(crate) fn group_of(&self, key: &Key<R>) -> Option<Key<R>> {
+        (self.group_of)(key)
+    }
+
+    /// What `record` adds to its group, `None` if the key has no record
+    pub(crate) fn value_of(&self, record: Option<R::Ref<'_>>) -> Option<Option<i64>> {
+        record.map(|record| (self.value_of)(record))
+    }
+}
+
+/// The aggregates of `option` whose functions read records of type `R`
+pub(crate) fn aggregates<R>(option: &DbOption) -> Aggregates<R>
+where
+    R: Record,
+{
+    option
+        .aggregates
+        .iter()
+        .filter_map(|aggregate| {
+            let (group_of, value_of) = aggregate.fns::<R>().ok()?;
+            Some((
+                aggregate.name.clone(),
+                MaterializedAggregate::new(group_of, value_of),
+            ))
+        })
+        .collect()
+}
+
+// The groups that change from the ones stored in a version, for the aggregates it stores
+struct Changes<'v, K> {
+    stored: &'v BTreeMap<String, Groups<K>>,
+    changed: HashMap<(String, K), Group>,
+}
+
+impl<'v, K> Changes<'v, K>
+where
+    K: RecordKey,
+{
+    fn new(stored: &'v BTreeMap<String, Groups<K>>) -> Self {
+        Self {
+            stored,
+            changed: HashMap::new(),
+        }
+    }
+
+    fn replace(
+        &mut self,
+        name: &str,
+        group: K,
+        previous: Option<Option<i64>>,
+        value: Option<Option<i64>>,
+    ) {
+        if previous == value {
+            return;
+        }
+        let Some(groups) = self.stored.get(name) else {
+            return;
+        };
+        self.changed
+            .entry((name.to_string(), group))
+            .or_insert_with_key(|(_, group)| groups.get(group).cloned().unwrap_or_default())
+            .replace(previous, value);
+    }
+
+    fn into_edits(self) -> Vec<VersionEdit<K>> {
+        self.changed
+            .into_iter()
+            .map(|((name, group), state)| VersionEdit::AggregateGroup { name, group, state })
+            .collect()
+    }
+}
+
+/// The newest records of `keys` in the SSTables of `version`, `None` where a key has none or a
+/// range tombstone hides it
+pub(crate) async fn table_records<R>(
+    ctx: &Context<R>,
+    version: &Version<R>,
+    keys: &[&Key<R>],
+    pk_indices: &[usize],
+) -> Result<Vec<Option<RecordBatchEntry<R>>>, VersionError>
+where
+    R: Record,
+{
+    let entries = version
+        .query_many(
+            ctx.storage_manager(),
+            keys,
+            u32::MAX.into(),
+            ProjectionMask::all(),
+            ctx.cache().clone(),
+            pk_indices,
+        )
+        .await?;
+
+    Ok(entries
+        .into_iter()
+        .zip(keys)
+        .map(|(entry, key)| {
+            entry.filter(|entry| {
+                !version
+                    .range_tombstones
+                    .iter()
+                    .any(|tombstone| tombstone.hides(key, entry.ts(), u32::MAX.into()))
+            })
+        })
+        .collect())
+}
+
+// Visit the newest record of each key of the SSTables of `version` in `range` that no range
+// tombstone hides
+async fn scan_tables<R>(
+    ctx: &Context<R>,
+    version: &Version<R>,
+    range: (Bound<&Key<R>>, Bound<&Key<R>>),
+    pk_indices: &[usize],
+    mut visit: impl FnMut(Key<R>, R::Ref<'_>),
+) -> Result<(), VersionError>
+where
+    R: Record,
+{
+    let mut streams = Vec::new();
+    version
+        .streams(
+            ctx,
+            &mut streams,
+            range,
+            u32::MAX.into(),
+            None,
+            ProjectionMask::all(),
+            None,
+            pk_indices,
+            None,
+            None,
+            &[],
+            ctx.storage_manager().readers(),
+        )
+        .await?;
+    let mut stream = MergeStream::<R>::from_vec(streams, u32::MAX.into(), None)
+        .await?
+        .range_tombstones(version.range_tombstones.clone());
+
+    while let Some(entry) = stream.next().await.transpose()? {
+        if let Some(record) = entry.value() {
+            visit(entry.key().value.to_key(), record);
+        }
+    }
+    Ok(())
+}
+
+/// Edits that store the groups of the aggregates `version` does not store yet, counted by a scan
+/// of its SSTables, and remove the stored aggregates that are not registered anymore
+pub(crate) async fn register_edits<R>(
+    aggregates: &Aggregates<R>,
+    ctx: &Context<R>,
+    version: &Version<R>,
+    pk_indices: &[usize],
+) -> Result<Vec<VersionEdit<Key<R>>>, VersionError>
+where
+    R: Record,
+{
+    let mut edits = version
+        .aggregates
+        .keys()
+        .filter(|name| !aggregates.contains_key(*name))
+        .map(|name| VersionEdit::RemoveAggregate { name: name.clone() })
+        .collect::<Vec<_>>();
+    let mut added = aggregates
+        .iter()
+        .filter(|(name, _)| !version.aggregates.contains_key(*name))
+        .map(|(name, aggregate)| (name, aggregate, Groups::new()))
+        .collect::<Vec<_>>();
+    if added.is_empty() {
+        return Ok(edits);
+    }
+
+    scan_tables(
+        ctx,
+        version,
+        (Bound::Unbounded, Bound::Unbounded),
+        pk_indices,
+        |key, record| {
+            for (_, aggregate, groups) in added.iter_mut() {
+                if let Some(group) = aggregate.group_of(&key) {
+                    groups
+                        .entry(group)
+                        .or_default()
+                        .replace(None, aggregate.value_of(Some(record.clone())));
+                }
+            }
+        },
+    )
+    .await?;
+    for (name, _, groups) in added {
+        edits.push(VersionEdit::NewAggregate { name: name.clone() });
+        edits.extend(
+            groups
+                .into_iter()
+                .map(|(group, state)| VersionEdit::AggregateGroup {
+                    name: name.clone(),
+                    group,
+                    state,
+                }),
+        );
+    }
+    Ok(edits)
+}
+
+/// Edits of the groups stored in `version` for flushing `batches` to an SSTable, whose newest
+/// record of each key replaces the one of the SSTables of `version`
+pub(crate) async fn flush_edits<R>(
+    aggregates: &Aggregates<R>,
+    ctx: &Context<R>,
+    version: &Version<R>,
+    pk_indices: &[usize],
+    batches: &[(
+        Option<FileId>,
+        ImmutableMemTable<<R::Schema as Schema>::Columns>,
+    )],
+) -> Result<Vec<VersionEdit<Key<R>>>, VersionError>
+where
+    R: Record,
+{
+    if aggregates.is_empty() || version.aggregates.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut keys = batches
+        .iter()
+        .flat_map(|(_, batch)| batch.sample_keys(1))
+        .collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+
+    let stored = table_records(ctx, version, &keys.iter().collect::<Vec<_>>(), pk_indices).await?;
+    let mut changes = Changes::new(&version.aggregates);
+    for (key, stored) in keys.iter().zip(stored) {
+        let flushed = batches
+            .iter()
+            .rev()
+            .find_map(|(_, batch)| batch.get(key, u32::MAX.into(), ProjectionMask::all()));
+        for (name, aggregate) in aggregates {
+            if let Some(group) = aggregate.group_of(key) {
+                changes.replace(
+                    name,
+                    group,
+                    aggregate.value_of(stored.as_ref().and_then(RecordBatchEntry::get)),
+                    aggregate.value_of(flushed.as_ref().and_then(RecordBatchEntry::get)),
+                );
+            }
+        }
+    }
+    Ok(changes.into_edits())
+}
+
+/// Edits of the groups stored in `version` for a range tombstone of `range`, which hides the
+/// records of its SSTables in the range
+pub(crate) async fn range_edits<R>(
+    aggregates: &Aggregates<R>,
+    ctx: &Context<R>,
+    version: &Version<R>,
+    range: (Bound<&Key<R>>, Bound<&Key<R>>),
+    pk_indices: &[usize],
+) -> Result<Vec<VersionEdit<Key<R>>>, VersionError>
+where
+    R: Record,
+{
+    if aggregates.is_empty() || version.aggregates.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut changes = Changes::new(&version.aggregates);
+
+    scan_tables(ctx, version, range, pk_indices, |key, record| {
+        for (name, aggregate) in aggregates {
+            if let Some(group) = aggregate.group_of(&key) {
+                changes.replace(name, group, aggregate.value_of(Some(record.clone())), None);
+            }
+        }
+    })
+    .await?;
+    Ok(changes.into_edits())
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::ops::Bound;
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::{Aggregate, Group};
+    use crate::{
+        executor::tokio::TokioExecutor,
+        inmem::immutable::tests::TestSchema,
+        tests::{Test, TestRef},
+        DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn aggregates_follow_writes() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        // sum up `vu32` by the first letter of the keys
+        .aggregate::<Test, _, _>(
+            "by_letter",
+            |key: &String| key.get(..1).map(str::to_string),
+            |record: TestRef<'_>| record.vu32.map(i64::from),
+        );
+        let expected = Aggregate {
+            count: 2,
+            sum: 5,
+            min: Some(2),
+            max: Some(3),
+        };
+        let record = |key: &str, vu32: u32| Test {
+            vstring: key.to_string(),
+            vu32,
+            vbool: None,
+        };
+        {
+            let db: DB<Test, TokioExecutor> =
+                DB::new(option.clone(), TokioExecutor::default(), TestSchema)
+                    .await
+                    .unwrap();
+
+            db.insert_batch(
+                [
+                    record("a1", 1),
+                    record("a2", 5),
+                    record("a3", 3),
+                    record("b1", 7),
+                ]
+                .into_iter(),
+            )
+            .await
+            .unwrap();
+            db.flush().await.unwrap();
+            // overwrites and removals take the old values out again
+            db.insert(record("a1", 2)).await.unwrap();
+            db.remove("a2".to_string()).await.unwrap();
+            db.flush_wal().await.unwrap();
+
+            assert_eq!(
+                db.aggregate("by_letter", &"a".to_string()).await.unwrap(),
+                Some(expected.clone())
+            );
+        }
+
+        // the groups of the flushed table are stored in the manifest, the writes after it are
+        // recovered from the write ahead log
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        let stored = |version: &crate::version::Version<Test>| {
+            version.aggregates["by_letter"]
+                .get("a")
+                .map(Group::aggregate)
+        };
+        let a = stored(&db.current_manifest().await).unwrap();
+        assert_eq!((a.count, a.sum), (3, 9));
+        assert_eq!(
+            db.aggregate("by_letter", &"a".to_string()).await.unwrap(),
+            Some(expected.clone())
+        );
+        let b = db
+            .aggregate("by_letter", &"b".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((b.count, b.sum), (1, 7));
+        assert_eq!(
+            db.aggregate("by_letter", &"c".to_string()).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            db.aggregate("unknown", &"a".to_string()).await.unwrap(),
+            None
+        );
+
+        db.flush().await.unwrap();
+        assert_eq!(stored(&db.current_manifest().await), Some(expected));
+
+        // the records of the tables in a removed range are taken out
+        db.delete_range((
+            Bound::Included("a".to_string()),
+            Bound::Excluded("b".to_string()),
+        ))
+        .await
+        .unwrap();
+        assert_eq!(stored(&db.current_manifest().await), None);
+        assert_eq!(
+            db.aggregate("by_letter", &"a".to_string()).await.unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! Supervision of the background tasks of a [`DB`](crate::DB)
+
+use std::{
+    any::Any,
+    fmt::{self, Display, Formatter},
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use fusio::MaybeSend;
+use futures_util::FutureExt;
+use thiserror::Error;
+use tracing::{error, info_span, Instrument};
+
+use crate::executor::Executor;
+
+/// Background tasks spawned by a [`DB`](crate::DB)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BackgroundTask {
+    /// Removes WAL and SSTable files that are no longer referenced
+    Cleaner,
+    /// Flushes immutable memtables and runs compactions
+    Compactor,
+}
+
+impl BackgroundTask {
+    /// Name of the task, which is also used for its tracing span
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackgroundTask::Cleaner => "tonbo-cleaner",
+            BackgroundTask::Compactor => "tonbo-compactor",
+        }
+    }
+}
+
+impl Display for BackgroundTask {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Panic of a background task
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+#[error("background task {task} panicked: {message}")]
+pub struct BackgroundError {
+    pub task: BackgroundTask,
+    pub message: String,
+}
+
+/// Liveness of the background tasks and the first panic among them
+#[derive(Debug, Default)]
+pub(crate) struct BackgroundTasks {
+    cleaner_alive: AtomicBool,
+    compactor_alive: AtomicBool,
+    error: Mutex<Option<BackgroundError>>,
+}
+
+impl BackgroundTasks {
+    /// Spawn `future` as `task` on `executor` within a span named after the task. A panic of
+    /// `future` is caught and kept as the background error instead of being lost with the task.
+    pub(crate) fn spawn<E, F>(self: &Arc<Self>, executor: &E, task: BackgroundTask, future: F)
+    where
+        E: Executor,
+        F: Future<Output = ()> + MaybeSend + 'static,
+    {
+        executor.spawn(self.supervise(task, future));
+    }
+
+    /// Mark `task` as alive and wrap `future`, so the task is marked as dead and its panic is
+    /// kept once `future` ends. [`Self::spawn`] runs the wrapped future on its own task, a
+    /// [`TonboRuntime`](crate::runtime::TonboRuntime) runs it together with the tasks of its
+    /// other DBs.
+    pub(crate) fn supervise<F>(
+        self: &Arc<Self>,
+        task: BackgroundTask,
+        future: F,
+    ) -> impl Future<Output = ()> + MaybeSend + 'static
+    where
+        F: Future<Output = ()> + MaybeSend + 'static,
+    {
+        self.alive(task).store(true, Ordering::Release);
+
+        let tasks = self.clone();
+        async move {
+            let span = info_span!("background", task = task.name());
+            if let Err(panic) = AssertUnwindSafe(future.instrument(span))
+                .catch_unwind()
+                .await
+            {
+                let err = BackgroundError {
+                    task,
+                    message: panic_message(panic.as_ref()),
+                };
+                error!("[Background Task Error]: {}", err);
+                tasks
+                    .error
+                    .lock()
+                    .expect("background error lock should not be poisoned")
+                    .get_or_insert(err);
+            }
+            tasks.alive(task).store(false, Ordering::Release);
+        }
+    }
+
+    pub(crate) fn is_alive(&self, task: BackgroundTask) -> bool {
+        self.alive(task).load(Ordering::Acquire)
+    }
+
+    pub(crate) fn error(&self) -> Option<BackgroundError> {
+        self.error
+            .lock()
+            .expect("background error lock should not be poisoned")
+            .clone()
+    }
+
+    fn alive(&self, task: BackgroundTask) -> &AtomicBool {
+        match task {
+            BackgroundTask::Cleaner => &self.cleaner_alive,
+            BackgroundTask::Compactor => &self.compactor_alive,
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::{BackgroundError, BackgroundTask, BackgroundTasks};
+    use crate::executor::tokio::TokioExecutor;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn panic_becomes_background_error() {
+        let tasks = Arc::new(BackgroundTasks::default());
+        let (tx, rx) = flume::bounded::<()>(1);
+
+        tasks.spawn(
+            &TokioExecutor::default(),
+            BackgroundTask::Cleaner,
+            async move {
+                let _ = rx.recv_async().await;
+                panic!("cleaner failed");
+            },
+        );
+        assert!(tasks.is_alive(BackgroundTask::Cleaner));
+        assert!(!tasks.is_alive(BackgroundTask::Compactor));
+        assert_eq!(tasks.error(), None);
+
+        tx.send(()).unwrap();
+        while tasks.is_alive(BackgroundTask::Cleaner) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            tasks.error(),
+            Some(BackgroundError {
+                task: BackgroundTask::Cleaner,
+                message: "cleaner failed".into(),
+            })
+        );
+    }
+}
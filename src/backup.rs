@@ -0,0 +1,869 @@
+//! Streaming backups of a [`DB`](crate::DB)
+//!
+//! A backup is a single archive with a tar-like framing that can be written through any
+//! [`fusio::Write`], so it can be piped to arbitrary destinations without staging a copy on disk:
+//!
+//! ```text
+//! archive  := header manifest table* end
+//! header   := "TONBOBAK" format_version: u8
+//! manifest := 1u8 edit_num: u32 VersionEdit*
+//! table    := 2u8 level: u8 gen: [u8; 16] size: u64 bytes[size] crc32: u32
+//! end      := 0u8
+//! ```
+//!
+//! The manifest comes first and describes every table of the archive with a
+//! `VersionEdit::Add`, so readers know which tables to expect before they are streamed.
+//!
+//! Besides archives, [`DB::restore`](crate::DB::restore) accepts plain copies of a DB
+//! directory, see [`BackupSource`]. Both kinds of backups can be checked with [`verify_backup`]
+//! without restoring them.
+
+use std::{collections::HashMap, sync::Arc};
+
+use fusio::{fs::OpenOptions, path::Path, DynFs, IoBufMut, Read, SeqRead, Write};
+use fusio_dispatch::FsOptions;
+use fusio_log::{error::LogError, Decode, Encode, Options};
+use futures_util::StreamExt;
+use parquet::{errors::ParquetError, file::metadata::ParquetMetaDataReader};
+use thiserror::Error;
+
+use crate::{
+    error::{fusio_kind, parquet_kind, source_kind, ErrorKind},
+    fs::{manager::StoreManager, parse_file_id, FileId, FileType},
+    option::DbOption,
+    record::{Key, Record, Schema},
+    version::{edit::VersionEdit, error::VersionError, timestamp::Timestamp, Version, MAX_LEVEL},
+};
+
+pub(crate) const BACKUP_MAGIC: &[u8; 8] = b"TONBOBAK";
+pub(crate) const BACKUP_FORMAT_VERSION: u8 = 1;
+// Size of the buffer used to copy tables in and out of an archive
+const BACKUP_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Frame tags of a backup archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum BackupTag {
+    End = 0,
+    Manifest = 1,
+    Table = 2,
+}
+
+/// Location of a backup
+#[derive(Debug, Clone)]
+pub enum BackupSource {
+    /// An archive written by [`DB::backup_to`](crate::DB::backup_to)
+    Archive { path: Path, fs: FsOptions },
+    /// A copy of a DB directory, e.g. a checkpoint or an object store prefix it was synced to.
+    ///
+    /// The version logs are expected in `version/` and all SSTables at the root of `path`, which
+    /// is the layout of a DB without dedicated level paths.
+    Directory { path: Path, fs: FsOptions },
+}
+
+/// Summary of a backup archive
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackupReport {
+    /// Number of SSTables in the backup
+    pub tables: usize,
+    /// Total size in bytes of the SSTables in the backup
+    pub table_bytes: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("backup fusio error: {0}")]
+    Fusio(#[from] fusio::Error),
+    #[error("backup version error: {0}")]
+    Version(#[from] VersionError),
+    #[error("backup is not a tonbo archive")]
+    InvalidMagic,
+    #[error("backup format version {0} is not supported")]
+    UnsupportedVersion(u8),
+    #[error("backup archive is corrupted: {0}")]
+    Corrupted(String),
+    #[error("backup parquet error: {0}")]
+    Parquet(#[from] ParquetError),
+    #[error("backup log error: {0}")]
+    Logger(#[from] LogError),
+    #[error("backup checksum mismatch of table {gen} at level {level}")]
+    Checksum { level: usize, gen: FileId },
+    #[error("backup is missing table {gen} at level {level}")]
+    MissingTable { level: usize, gen: FileId },
+    #[error("restore target already contains a database")]
+    TargetNotEmpty,
+}
+
+impl BackupError {
+    pub(crate) fn kind(&self) -> ErrorKind {
+        match self {
+            BackupError::Fusio(err) => fusio_kind(err),
+            BackupError::Version(err) => err.kind(),
+            BackupError::Parquet(err) => parquet_kind(err),
+            BackupError::Logger(err) => source_kind(err),
+            BackupError::InvalidMagic
+            | BackupError::Corrupted(_)
+            | BackupError::Checksum { .. }
+            | BackupError::MissingTable { .. } => ErrorKind::Corruption,
+            BackupError::UnsupportedVersion(_) | BackupError::TargetNotEmpty => {
+                ErrorKind::InvalidArgument
+            }
+        }
+    }
+}
+
+/// Parquet files start and end with this magic
+const PARQUET_MAGIC: &[u8; 4] = b"PAR1";
+// Footer of a parquet file: metadata length and magic
+const PARQUET_FOOTER_LEN: usize = 8;
+
+/// Checksum and parquet framing of a table that is read chunk by chunk
+#[derive(Default)]
+struct TableCheck {
+    hasher: crc32fast::Hasher,
+    head: Vec<u8>,
+    tail: Vec<u8>,
+    size: u64,
+}
+
+impl TableCheck {
+    fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+        if self.head.len() < PARQUET_MAGIC.len() {
+            let len = (PARQUET_MAGIC.len() - self.head.len()).min(chunk.len());
+            self.head.extend_from_slice(&chunk[..len]);
+        }
+        self.tail.extend_from_slice(chunk);
+        if self.tail.len() > PARQUET_FOOTER_LEN {
+            self.tail.drain(..self.tail.len() - PARQUET_FOOTER_LEN);
+        }
+        self.size += chunk.len() as u64;
+    }
+
+    /// Check the parquet magic at both ends of the table and return the length of its metadata.
+    fn metadata_len(&self, level: usize, gen: FileId) -> Result<u64, BackupError> {
+        let invalid = || {
+            BackupError::Corrupted(format!(
+                "table {gen} at level {level} is not a parquet file"
+            ))
+        };
+
+        if self.head != PARQUET_MAGIC[..]
+            || self.tail.len() != PARQUET_FOOTER_LEN
+            || self.tail[4..] != PARQUET_MAGIC[..]
+        {
+            return Err(invalid());
+        }
+        let metadata_len =
+            u32::from_le_bytes(self.tail[..4].try_into().expect("footer length is 8")) as u64;
+        if metadata_len + (PARQUET_MAGIC.len() + PARQUET_FOOTER_LEN) as u64 > self.size {
+            return Err(invalid());
+        }
+
+        Ok(metadata_len)
+    }
+
+    fn crc(self) -> u32 {
+        self.hasher.finalize()
+    }
+}
+
+/// Sequential reader over a file that only supports positional reads
+struct FileReader<F> {
+    file: F,
+    pos: u64,
+}
+
+impl<F> FileReader<F> {
+    fn new(file: F) -> Self {
+        FileReader { file, pos: 0 }
+    }
+
+    /// Read from the start of the file again
+    fn rewind(&mut self) {
+        self.pos = 0;
+    }
+}
+
+impl<F: Read> SeqRead for FileReader<F> {
+    async fn read_exact<B: IoBufMut>(&mut self, buf: B) -> (Result<(), fusio::Error>, B) {
+        let len = buf.bytes_init() as u64;
+        let (result, buf) = self.file.read_exact_at(buf, self.pos).await;
+        self.pos += len;
+        (result, buf)
+    }
+}
+
+/// Copy all of `file` into `writer` chunk by chunk, returning its size and crc32.
+async fn copy_file<F, W>(
+    file: &mut F,
+    writer: &mut W,
+    buf: &mut [u8],
+) -> Result<(u64, u32), BackupError>
+where
+    F: Read,
+    W: Write,
+{
+    let size = file.size().await?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut pos = 0;
+    while pos < size {
+        let len = (size - pos).min(buf.len() as u64) as usize;
+        let (result, _) = file.read_exact_at(&mut buf[..len], pos).await;
+        result?;
+        hasher.update(&buf[..len]);
+        let (result, _) = writer.write_all(&buf[..len]).await;
+        result?;
+        pos += len as u64;
+    }
+
+    Ok((size, hasher.finalize()))
+}
+
+/// Fold `edits` into the `VersionEdit::Add`s of the live tables in their original order, followed
+/// by the latest schema and timestamp, as a new version log would start.
+fn compact_edits<K: Key>(edits: Vec<VersionEdit<K>>) -> Result<Vec<VersionEdit<K>>, BackupError> {
+    let mut adds = Vec::new();
+    let mut latest_ts = Timestamp::from(0);
+    let mut latest_schema = None;
+
+    for edit in edits {
+        match edit {
+            VersionEdit::Add { level, mut scope } => {
+                if level as usize >= MAX_LEVEL {
+                    return Err(BackupError::Corrupted(format!(
+                        "table {} at level {level} exceeds the maximum level",
+                        scope.gen
+                    )));
+                }
+                // the WALs of a backup are never restored
+                scope.wal_ids = None;
+                adds.push(VersionEdit::Add { level, scope });
+            }
+            VersionEdit::Remove { gen, .. } => adds
+                .retain(|edit| !matches!(edit, VersionEdit::Add { scope, .. } if scope.gen == gen)),
+            VersionEdit::LatestTimeStamp { ts } => latest_ts = latest_ts.max(ts),
+            // the WALs a point-in-time recovery cut are not part of the backup
+            VersionEdit::NewLogLength { .. } | VersionEdit::RecoveredUntil { .. } => (),
+            VersionEdit::DeleteRange { tombstone } => {
+                adds.push(VersionEdit::DeleteRange { tombstone })
+            }
+            VersionEdit::NewSchema { schema } => latest_schema = Some(schema),
+            edit @ (VersionEdit::NewAggregate { .. }
+            | VersionEdit::AggregateGroup { .. }
+            | VersionEdit::RemoveAggregate { .. }) => adds.push(edit),
+        }
+    }
+    if let Some(schema) = latest_schema {
+        adds.push(VersionEdit::NewSchema { schema });
+    }
+    adds.push(VersionEdit::LatestTimeStamp { ts: latest_ts });
+    adds.push(VersionEdit::NewLogLength { len: 0 });
+
+    Ok(adds)
+}
+
+/// Levels of the tables added by compacted `edits`
+fn table_levels<K: Key>(edits: &[VersionEdit<K>]) -> HashMap<FileId, usize> {
+    edits
+        .iter()
+        .filter_map(|edit| match edit {
+            VersionEdit::Add { level, scope } => Some((scope.gen, *level as usize)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Write `version` and all SSTables it references into `writer` as a backup archive.
+///
+/// The caller must keep `version` alive until this returns, which prevents the cleaner from
+/// removing the referenced SSTables while they are copied.
+pub(crate) async fn write_backup<R, W>(
+    writer: &mut W,
+    version: &Version<R>,
+    manager: &StoreManager,
+) -> Result<BackupReport, BackupError>
+where
+    R: Record,
+    W: Write,
+{
+    let option = version.option();
+    let mut report = BackupReport::default();
+
+    let (result, _) = writer.write_all(&BACKUP_MAGIC[..]).await;
+    result?;
+    BACKUP_FORMAT_VERSION.encode(writer).await?;
+
+    let edits = version.to_edits();
+    (BackupTag::Manifest as u8).encode(writer).await?;
+    (edits.len() as u32).encode(writer).await?;
+    for edit in edits.iter() {
+        edit.encode(writer).await?;
+    }
+
+    let mut buf = vec![0u8; BACKUP_CHUNK_SIZE];
+    for (level, scopes) in version.level_slice.iter().enumerate() {
+        for scope in scopes {
+            let (fs, path) = manager.table(&option, scope.gen, level);
+            let mut file = fs
+                .open_options(&path, FileType::Parquet.open_options(true))
+                .await?;
+
+            (BackupTag::Table as u8).encode(writer).await?;
+            (level as u8).encode(writer).await?;
+            let (result, _) = writer.write_all(&scope.gen.to_bytes()[..]).await;
+            result?;
+            file.size().await?.encode(writer).await?;
+            let (size, crc) = copy_file(&mut file, writer, &mut buf).await?;
+            crc.encode(writer).await?;
+
+            report.tables += 1;
+            report.table_bytes += size;
+        }
+    }
+    (BackupTag::End as u8).encode(writer).await?;
+    writer.flush().await?;
+
+    Ok(report)
+}
+
+/// Restore `source` into the empty DB location of `option`.
+///
+/// Tables are placed at the paths configured for their levels and a fresh version log is written
+/// for them, so the DB can be opened with `option` afterwards. Archives are verified before any
+/// of their tables is written, and the tables and the log written by a restore that fails are
+/// removed again, so the location stays empty for another attempt.
+pub(crate) async fn restore<R: Record>(
+    option: &DbOption,
+    manager: &StoreManager,
+    source: BackupSource,
+) -> Result<BackupReport, BackupError> {
+    let base_fs = manager.base_fs();
+    let version_dir = option.version_log_dir_path();
+    base_fs.create_dir_all(&version_dir).await?;
+    if base_fs.list(&version_dir).await?.next().await.is_some() {
+        return Err(BackupError::TargetNotEmpty);
+    }
+
+    let log_path = option.version_log_path(option.generate_file_id());
+    let mut written = Vec::new();
+    let result = async {
+        let (edits, report) = match source {
+            BackupSource::Archive { path, fs } => {
+                let file = fs
+                    .parse()?
+                    .open_options(&path, OpenOptions::default().read(true))
+                    .await?;
+                let mut reader = FileReader::new(file);
+                verify_archive::<R, _>(&mut reader).await?;
+                reader.rewind();
+                restore_archive::<R, _>(&mut reader, option, manager, &mut written).await?
+            }
+            BackupSource::Directory { path, fs } => {
+                restore_directory::<R>(&path, fs, option, manager, &mut written).await?
+            }
+        };
+
+        let mut log = Options::new(log_path.clone())
+            .build_with_fs::<VersionEdit<<R::Schema as Schema>::Key>>(base_fs.clone())
+            .await?;
+        log.write_batch(edits.iter()).await?;
+        log.close().await?;
+
+        Ok::<_, BackupError>(report)
+    }
+    .await;
+    if result.is_err() {
+        for (level, gen) in written {
+            let _ = manager
+                .level_fs(option, level)
+                .remove(&option.table_path(gen, level))
+                .await;
+        }
+        let _ = base_fs.remove(&log_path).await;
+    }
+
+    result
+}
+
+/// Read the header and manifest of an archive.
+async fn read_manifest<R, S>(
+    reader: &mut S,
+) -> Result<Vec<VersionEdit<<R::Schema as Schema>::Key>>, BackupError>
+where
+    R: Record,
+    S: SeqRead,
+{
+    let mut magic = [0u8; BACKUP_MAGIC.len()];
+    let (result, _) = reader.read_exact(&mut magic[..]).await;
+    result?;
+    if &magic != BACKUP_MAGIC {
+        return Err(BackupError::InvalidMagic);
+    }
+    let version = u8::decode(reader).await?;
+    if version != BACKUP_FORMAT_VERSION {
+        return Err(BackupError::UnsupportedVersion(version));
+    }
+    if u8::decode(reader).await? != BackupTag::Manifest as u8 {
+        return Err(BackupError::Corrupted("manifest is missing".into()));
+    }
+
+    let edit_num = u32::decode(reader).await?;
+    let mut edits = Vec::with_capacity(edit_num as usize);
+    for _ in 0..edit_num {
+        edits.push(VersionEdit::decode(reader).await?);
+    }
+    compact_edits(edits)
+}
+
+/// Read the header of the next table frame, or `None` at the end of the archive.
+async fn read_table_header<S: SeqRead>(
+    reader: &mut S,
+) -> Result<Option<(usize, FileId, u64)>, BackupError> {
+    match u8::decode(reader).await? {
+        tag if tag == BackupTag::End as u8 => Ok(None),
+        tag if tag == BackupTag::Table as u8 => {
+            let level = u8::decode(reader).await? as usize;
+            let mut gen = [0u8; 16];
+            let (result, _) = reader.read_exact(&mut gen[..]).await;
+            result?;
+            let size = u64::decode(reader).await?;
+
+            Ok(Some((level, FileId::from_bytes(gen), size)))
+        }
+        tag => Err(BackupError::Corrupted(format!("unknown frame tag {tag}"))),
+    }
+}
+
+// Write the tables of the archive of `reader` and collect them in `written`, which also holds
+// the table that was being written if it fails
+async fn restore_archive<R, S>(
+    reader: &mut S,
+    option: &DbOption,
+    manager: &StoreManager,
+    written: &mut Vec<(usize, FileId)>,
+) -> Result<(Vec<VersionEdit<<R::Schema as Schema>::Key>>, BackupReport), BackupError>
+where
+    R: Record,
+    S: SeqRead,
+{
+    let edits = read_manifest::<R, S>(reader).await?;
+    let mut tables = table_levels(&edits);
+    let mut report = BackupReport::default();
+
+    let mut buf = vec![0u8; BACKUP_CHUNK_SIZE];
+    while let Some((level, gen, size)) = read_table_header(reader).await? {
+        if tables.remove(&gen) != Some(level) {
+            return Err(BackupError::Corrupted(format!(
+                "table {gen} at level {level} is not in the manifest"
+            )));
+        }
+        written.push((level, gen));
+        let mut file = manager
+            .level_fs(option, level)
+            .open_options(
+                &option.table_path(gen, level),
+                FileType::Parquet.open_options(false),
+            )
+            .await?;
+
+        let mut hasher = crc32fast::Hasher::new();
+        let mut remaining = size;
+        while remaining > 0 {
+            let len = remaining.min(buf.len() as u64) as usize;
+            let (result, _) = reader.read_exact(&mut buf[..len]).await;
+            result?;
+            hasher.update(&buf[..len]);
+            let (result, _) = file.write_all(&buf[..len]).await;
+            result?;
+            remaining -= len as u64;
+        }
+        file.close().await?;
+        if hasher.finalize() != u32::decode(reader).await? {
+            return Err(BackupError::Checksum { level, gen });
+        }
+
+        report.tables += 1;
+        report.table_bytes += size;
+    }
+    if let Some((gen, level)) = tables.into_iter().next() {
+        return Err(BackupError::MissingTable { level, gen });
+    }
+
+    Ok((edits, report))
+}
+
+/// Read the edits of the version log a DB would recover from in the directory at `path`.
+async fn recover_directory_edits<R: Record>(
+    path: &Path,
+    fs_option: FsOptions,
+    fs: &Arc<dyn DynFs>,
+) -> Result<Vec<VersionEdit<<R::Schema as Schema>::Key>>, BackupError> {
+    let version_dir = path.child("version");
+    let mut log_ids = Vec::new();
+    let mut log_stream = fs.list(&version_dir).await?;
+    while let Some(file_meta) = log_stream.next().await {
+        if let Some(log_id) = parse_file_id(&file_meta?.path, FileType::Log)
+            .map_err(|err| BackupError::Corrupted(err.to_string()))?
+        {
+            log_ids.push(log_id);
+        }
+    }
+    log_ids.sort();
+
+    // Like `VersionSet::new`, prefer the second newest log, the newest may be partially written
+    let log_id = match log_ids.len() {
+        0 => return Err(BackupError::Corrupted("version log is missing".into())),
+        1 => log_ids[0],
+        len => log_ids[len - 2],
+    };
+    let edits = VersionEdit::recover(
+        version_dir.child(format!("{}.{}", log_id, FileType::Log)),
+        fs_option,
+    )
+    .await;
+
+    compact_edits(edits)
+}
+
+// Copy the tables of the directory at `path` and collect them in `written`, like
+// `restore_archive`
+async fn restore_directory<R: Record>(
+    path: &Path,
+    fs_option: FsOptions,
+    option: &DbOption,
+    manager: &StoreManager,
+    written: &mut Vec<(usize, FileId)>,
+) -> Result<(Vec<VersionEdit<<R::Schema as Schema>::Key>>, BackupReport), BackupError> {
+    let fs = fs_option.clone().parse()?;
+    let edits = recover_directory_edits::<R>(path, fs_option, &fs).await?;
+    let mut report = BackupReport::default();
+
+    let mut buf = vec![0u8; BACKUP_CHUNK_SIZE];
+    for (gen, level) in table_levels(&edits) {
+        let mut src = fs
+            .open_options(
+                &path.child(format!("{}.{}", gen, FileType::Parquet)),
+                FileType::Parquet.open_options(true),
+            )
+            .await
+            .map_err(|_| BackupError::MissingTable { level, gen })?;
+        written.push((level, gen));
+        let mut dst = manager
+            .level_fs(option, level)
+            .open_options(
+                &option.table_path(gen, level),
+                FileType::Parquet.open_options(false),
+            )
+            .await?;
+        let (size, _) = copy_file(&mut src, &mut dst, &mut buf).await?;
+        dst.close().await?;
+
+        report.tables += 1;
+        report.table_bytes += size;
+    }
+
+    Ok((edits, report))
+}
+
+/// Check a backup without restoring it.
+///
+/// For archives, the manifest is decoded, every table it lists must be present, and the
+/// checksums and parquet framing of all tables are validated. Directory copies have no checksums
+/// of their own, so the parquet metadata of every table is decoded instead.
+pub async fn verify_backup<R: Record>(source: BackupSource) -> Result<BackupReport, BackupError> {
+    match source {
+        BackupSource::Archive { path, fs } => {
+            let file = fs
+                .parse()?
+                .open_options(&path, OpenOptions::default().read(true))
+                .await?;
+            verify_archive::<R, _>(&mut FileReader::new(file)).await
+        }
+        BackupSource::Directory { path, fs } => verify_directory::<R>(&path, fs).await,
+    }
+}
+
+async fn verify_archive<R, S>(reader: &mut S) -> Result<BackupReport, BackupError>
+where
+    R: Record,
+    S: SeqRead,
+{
+    let edits = read_manifest::<R, S>(reader).await?;
+    let mut tables = table_levels(&edits);
+    let mut report = BackupReport::default();
+
+    let mut buf = vec![0u8; BACKUP_CHUNK_SIZE];
+    while let Some((level, gen, size)) = read_table_header(reader).await? {
+        if tables.remove(&gen) != Some(level) {
+            return Err(BackupError::Corrupted(format!(
+                "table {gen} at level {level} is not in the manifest"
+            )));
+        }
+
+        let mut check = TableCheck::default();
+        let mut remaining = size;
+        while remaining > 0 {
+            let len = remaining.min(buf.len() as u64) as usize;
+            let (result, _) = reader.read_exact(&mut buf[..len]).await;
+            result?;
+            check.update(&buf[..len]);
+            remaining -= len as u64;
+        }
+        // a checksum mismatch is reported before any framing issue it may have caused
+        let framing = check.metadata_len(level, gen);
+        if check.crc() != u32::decode(reader).await? {
+            return Err(BackupError::Checksum { level, gen });
+        }
+        framing?;
+
+        report.tables += 1;
+        report.table_bytes += size;
+    }
+    if let Some((gen, level)) = tables.into_iter().next() {
+        return Err(BackupError::MissingTable { level, gen });
+    }
+
+    Ok(report)
+}
+
+async fn verify_directory<R: Record>(
+    path: &Path,
+    fs_option: FsOptions,
+) -> Result<BackupReport, BackupError> {
+    let fs = fs_option.clone().parse()?;
+    let edits = recover_directory_edits::<R>(path, fs_option, &fs).await?;
+    let mut report = BackupReport::default();
+
+    for (gen, level) in table_levels(&edits) {
+        let mut file = fs
+            .open_options(
+                &path.child(format!("{}.{}", gen, FileType::Parquet)),
+                FileType::Parquet.open_options(true),
+            )
+            .await
+            .map_err(|_| BackupError::MissingTable { level, gen })?;
+        let size = file.size().await?;
+
+        // only the magic and the metadata are read, the data pages are left untouched
+        let mut check = TableCheck::default();
+        let mut head = [0u8; PARQUET_MAGIC.len()];
+        let mut tail = [0u8; PARQUET_FOOTER_LEN];
+        if size >= (head.len() + tail.len()) as u64 {
+            let (result, _) = file.read_exact_at(&mut head[..], 0).await;
+            result?;
+            let (result, _) = file
+                .read_exact_at(&mut tail[..], size - tail.len() as u64)
+                .await;
+            result?;
+            check.head.extend_from_slice(&head);
+            check.tail.extend_from_slice(&tail);
+            check.size = size;
+        }
+        let metadata_len = check.metadata_len(level, gen)?;
+
+        let metadata = vec![0u8; metadata_len as usize];
+        let (result, metadata) = file
+            .read_exact_at(metadata, size - PARQUET_FOOTER_LEN as u64 - metadata_len)
+            .await;
+        result?;
+        ParquetMetaDataReader::decode_metadata(&metadata)?;
+
+        report.tables += 1;
+        report.table_bytes += size;
+    }
+
+    Ok(report)
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::io::Cursor;
+
+    use fusio::path::Path;
+    use fusio_dispatch::FsOptions;
+    use tempfile::TempDir;
+
+    use super::{verify_backup, BackupError, BackupSource, BACKUP_MAGIC};
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema,
+        record::test::test_items, tests::Test, DbError, DbOption, DB,
+    };
+
+    async fn open_db(temp_dir: &TempDir) -> DB<Test, TokioExecutor> {
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap()
+    }
+
+    async fn restore_db(
+        temp_dir: &TempDir,
+        source: BackupSource,
+    ) -> Result<DB<Test, TokioExecutor>, DbError> {
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        DB::restore(option, TokioExecutor::default(), TestSchema, source).await
+    }
+
+    async fn assert_restored(db: &DB<Test, TokioExecutor>) {
+        for item in test_items(0u32..32) {
+            let vu32 = db
+                .get(&item.vstring, |entry| entry.get().vu32)
+                .await
+                .unwrap();
+            assert_eq!(vu32, Some(item.vu32));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn backup_to_writer() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+
+        for item in test_items(0u32..32) {
+            db.insert(item).await.unwrap();
+        }
+
+        let mut buf = Vec::new();
+        let report = db.backup_to(&mut Cursor::new(&mut buf)).await.unwrap();
+
+        assert_eq!(report.tables, 1);
+        assert!(report.table_bytes > 0);
+        assert_eq!(&buf[..BACKUP_MAGIC.len()], &BACKUP_MAGIC[..]);
+        assert!(buf.len() as u64 > report.table_bytes);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn restore_from_archive() {
+        let source_dir = TempDir::new().unwrap();
+        let db = open_db(&source_dir).await;
+        for item in test_items(0u32..32) {
+            db.insert(item).await.unwrap();
+        }
+        let mut buf = Vec::new();
+        db.backup_to(&mut Cursor::new(&mut buf)).await.unwrap();
+
+        let archive_dir = TempDir::new().unwrap();
+        let archive_path = archive_dir.path().join("backup");
+        std::fs::write(&archive_path, &buf).unwrap();
+
+        let target_dir = TempDir::new().unwrap();
+        let restored = restore_db(
+            &target_dir,
+            BackupSource::Archive {
+                path: Path::from_filesystem_path(&archive_path).unwrap(),
+                fs: FsOptions::Local,
+            },
+        )
+        .await
+        .unwrap();
+        assert_restored(&restored).await;
+
+        // a corrupted archive must not be restored
+        let len = buf.len();
+        buf[len - 8] ^= 0xff;
+        std::fs::write(&archive_path, &buf).unwrap();
+        let target_dir = TempDir::new().unwrap();
+        let result = restore_db(
+            &target_dir,
+            BackupSource::Archive {
+                path: Path::from_filesystem_path(&archive_path).unwrap(),
+                fs: FsOptions::Local,
+            },
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(DbError::Backup(BackupError::Checksum { level: 0, .. }))
+        ));
+        // no table was written, so the intact archive can be restored into the same target
+        assert!(std::fs::read_dir(target_dir.path())
+            .unwrap()
+            .all(|entry| entry.unwrap().path().extension() != Some("parquet".as_ref())));
+        buf[len - 8] ^= 0xff;
+        std::fs::write(&archive_path, &buf).unwrap();
+        let restored = restore_db(
+            &target_dir,
+            BackupSource::Archive {
+                path: Path::from_filesystem_path(&archive_path).unwrap(),
+                fs: FsOptions::Local,
+            },
+        )
+        .await
+        .unwrap();
+        assert_restored(&restored).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn restore_from_directory() {
+        let source_dir = TempDir::new().unwrap();
+        let db = open_db(&source_dir).await;
+        for item in test_items(0u32..32) {
+            db.insert(item).await.unwrap();
+        }
+        db.flush().await.unwrap();
+
+        let source = BackupSource::Directory {
+            path: Path::from_filesystem_path(source_dir.path()).unwrap(),
+            fs: FsOptions::Local,
+        };
+        let target_dir = TempDir::new().unwrap();
+        let restored = restore_db(&target_dir, source.clone()).await.unwrap();
+        assert_restored(&restored).await;
+
+        // the target already holds a DB now
+        assert!(matches!(
+            restore_db(&target_dir, source).await,
+            Err(DbError::Backup(BackupError::TargetNotEmpty))
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn verify_backups() {
+        let source_dir = TempDir::new().unwrap();
+        let db = open_db(&source_dir).await;
+        for item in test_items(0u32..32) {
+            db.insert(item).await.unwrap();
+        }
+        let mut buf = Vec::new();
+        let report = db.backup_to(&mut Cursor::new(&mut buf)).await.unwrap();
+
+        let directory = BackupSource::Directory {
+            path: Path::from_filesystem_path(source_dir.path()).unwrap(),
+            fs: FsOptions::Local,
+        };
+        assert_eq!(verify_backup::<Test>(directory).await.unwrap(), report);
+
+        let archive_dir = TempDir::new().unwrap();
+        let archive_path = archive_dir.path().join("backup");
+        std::fs::write(&archive_path, &buf).unwrap();
+        let archive = BackupSource::Archive {
+            path: Path::from_filesystem_path(&archive_path).unwrap(),
+            fs: FsOptions::Local,
+        };
+        assert_eq!(
+            verify_backup::<Test>(archive.clone()).await.unwrap(),
+            report
+        );
+
+        let len = buf.len();
+        buf[len - 8] ^= 0xff;
+        std::fs::write(&archive_path, &buf).unwrap();
+        assert!(matches!(
+            verify_backup::<Test>(archive).await,
+            Err(BackupError::Checksum { level: 0, .. })
+        ));
+    }
+}
@@ -0,0 +1,263 @@
+//! Inserts of Arrow record batches
+//!
+//! [`DB::insert_record_batch`](crate::DB::insert_record_batch) and
+//! [`Transaction::insert_record_batch`](crate::transaction::Transaction::insert_record_batch)
+//! write each row of a [`RecordBatch`] as a record. The batch has the columns of the schema of
+//! the DB, without `_null` and `_ts`, e.g. a batch read from another Arrow source:
+//!
+//! ```ignore
+//! let batch = RecordBatch::try_new(schema, vec![ids, names])?;
+//! db.insert_record_batch(&batch).await?;
+//! ```
+
+use std::sync::Arc;
+
+use arrow::{
+    array::{ArrayRef, BooleanArray, RecordBatch, UInt32Array},
+    datatypes::DataType,
+    error::ArrowError,
+};
+use parquet::arrow::ProjectionMask;
+use thiserror::Error;
+
+use crate::{
+    error::{arrow_kind, ErrorKind},
+    magic::USER_COLUMN_OFFSET,
+    record::{Record, RecordRef, Schema},
+};
+
+#[derive(Debug, Error)]
+pub enum BatchError {
+    #[error("record batch has the columns {found:?}, not the columns {expected:?} of the schema")]
+    Columns {
+        expected: Vec<String>,
+        found: Vec<String>,
+    },
+    #[error("column {name} of the record batch is {found}, not {expected}")]
+    Type {
+        name: String,
+        expected: DataType,
+        found: DataType,
+    },
+    #[error("column {0} of the record batch has nulls, but is not nullable")]
+    Null(String),
+    #[error("batch arrow error: {0}")]
+    Arrow(#[from] ArrowError),
+}
+
+impl BatchError {
+    /// Category of the error, a batch that does not match the schema is
+    /// [`ErrorKind::InvalidArgument`]
+    pub(crate) fn kind(&self) -> ErrorKind {
+        match self {
+            BatchError::Arrow(err) => arrow_kind(err),
+            BatchError::Columns { .. } | BatchError::Type { .. } | BatchError::Null(_) => {
+                ErrorKind::InvalidArgument
+            }
+        }
+    }
+}
+
+/// Records of the rows of `batch`, whose columns are those of `schema` without `_null` and `_ts`
+///
+/// Each row is copied out of the columns by [`RecordRef::to_owned_record`].
+pub(crate) fn records<R: Record>(
+    schema: &R::Schema,
+    batch: &RecordBatch,
+) -> Result<Vec<R>, BatchError> {
+    let full_schema = schema.arrow_schema();
+    let fields = &full_schema.fields()[USER_COLUMN_OFFSET..];
+    let batch_schema = batch.schema();
+    if batch_schema.fields().len() != fields.len()
+        || batch_schema
+            .fields()
+            .iter()
+            .zip(fields)
+            .any(|(column, field)| column.name() != field.name())
+    {
+        return Err(BatchError::Columns {
+            expected: fields.iter().map(|field| field.name().clone()).collect(),
+            found: batch_schema
+                .fields()
+                .iter()
+                .map(|column| column.name().clone())
+                .collect(),
+        });
+    }
+    for (column, field) in batch.columns().iter().zip(fields) {
+        if column.data_type() != field.data_type() {
+            return Err(BatchError::Type {
+                name: field.name().clone(),
+                expected: field.data_type().clone(),
+                found: column.data_type().clone(),
+            });
+        }
+        if !field.is_nullable() && column.null_count() > 0 {
+            return Err(BatchError::Null(field.name().clone()));
+        }
+    }
+
+    // the rows are read as records of a batch of the schema, whose `_ts` is not part of a record
+    let rows = batch.num_rows();
+    let columns = [
+        Arc::new(BooleanArray::from(vec![false; rows])) as ArrayRef,
+        Arc::new(UInt32Array::from(vec![0; rows])),
+    ]
+    .into_iter()
+    .chain(batch.columns().iter().cloned())
+    .collect::<Vec<_>>();
+    let full_batch = RecordBatch::try_new(full_schema.clone(), columns)?;
+    let projection_mask = ProjectionMask::all();
+    let records = (0..rows)
+        .filter_map(|offset| {
+            R::Ref::from_record_batch(&full_batch, offset, &projection_mask, full_schema)
+                .get()
+                .and_then(|record| record.to_owned_record())
+        })
+        .collect();
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{BooleanArray, RecordBatch, StringArray, UInt32Array},
+        datatypes::{DataType, Field, Schema as ArrowSchema},
+    };
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::{records, BatchError};
+    use crate::{
+        error::ErrorKind, executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema,
+        tests::Test, DbError, DbOption, DB,
+    };
+
+    fn batch(vstring: Vec<&str>, vu32: Vec<Option<u32>>, vbool: Vec<Option<bool>>) -> RecordBatch {
+        let schema = Arc::new(ArrowSchema::new(vec![
+            Field::new("vstring", DataType::Utf8, false),
+            Field::new("vu32", DataType::UInt32, true),
+            Field::new("vbool", DataType::Boolean, true),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(vstring)),
+                Arc::new(UInt32Array::from(vu32)),
+                Arc::new(BooleanArray::from(vbool)),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn records_of_batch() {
+        let records = records::<Test>(
+            &TestSchema,
+            &batch(
+                vec!["a", "b"],
+                vec![Some(1), Some(2)],
+                vec![Some(true), None],
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            records,
+            vec![
+                Test {
+                    vstring: "a".to_string(),
+                    vu32: 1,
+                    vbool: Some(true),
+                },
+                Test {
+                    vstring: "b".to_string(),
+                    vu32: 2,
+                    vbool: None,
+                },
+            ]
+        );
+
+        assert!(matches!(
+            records::<Test>(&TestSchema, &batch(vec!["c"], vec![None], vec![None])),
+            Err(BatchError::Null(ref name)) if name == "vu32"
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn insert_record_batch() {
+        let temp_dir = TempDir::new().unwrap();
+        let db: DB<Test, TokioExecutor> = DB::new(
+            DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &TestSchema,
+            ),
+            TokioExecutor::default(),
+            TestSchema,
+        )
+        .await
+        .unwrap();
+
+        let ts = u32::from(db.ctx.load_ts());
+        db.insert_record_batch(&batch(
+            vec!["a", "b", "c"],
+            vec![Some(1), Some(2), Some(3)],
+            vec![Some(true), None, Some(false)],
+        ))
+        .await
+        .unwrap();
+        // the rows share the timestamp of their commit
+        assert_eq!(u32::from(db.ctx.load_ts()), ts + 1);
+        for (key, vu32, vbool) in [("a", 1, Some(true)), ("b", 2, None), ("c", 3, Some(false))] {
+            let record = db
+                .get(&key.to_string(), |entry| {
+                    let record = entry.get();
+                    Some((record.vu32, record.vbool))
+                })
+                .await
+                .unwrap();
+            assert_eq!(record, Some((Some(vu32), vbool)), "key {key}");
+        }
+
+        let mut txn = db.transaction().await;
+        txn.insert_record_batch(&batch(vec!["d"], vec![Some(4)], vec![None]))
+            .await
+            .unwrap();
+        txn.commit().await.unwrap();
+        let vu32 = db
+            .get(&"d".to_string(), |entry| entry.get().vu32)
+            .await
+            .unwrap();
+        assert_eq!(vu32, Some(4));
+
+        // `vu32` is not nullable
+        let err = db
+            .insert_record_batch(&batch(vec!["e"], vec![None], vec![None]))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidArgument);
+        assert!(matches!(
+            err,
+            crate::transaction::CommitError::Database(DbError::Batch(BatchError::Null(ref name)))
+                if name == "vu32"
+        ));
+
+        let columns = RecordBatch::try_new(
+            Arc::new(ArrowSchema::new(vec![Field::new(
+                "vstring",
+                DataType::Utf8,
+                false,
+            )])),
+            vec![Arc::new(StringArray::from(vec!["f"]))],
+        )
+        .unwrap();
+        assert!(matches!(
+            db.insert_record_batch(&columns).await,
+            Err(crate::transaction::CommitError::Database(DbError::Batch(
+                BatchError::Columns { .. }
+            )))
+        ));
+    }
+}
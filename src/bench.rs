@@ -0,0 +1,361 @@
+//! Workload generator and bench harness to reproduce performance numbers of a [`DB`] and tune
+//! its [`DbOption`](crate::DbOption) against a storage backend
+//!
+//! A [`Workload`] runs a mix of point reads and writes of records with a `UInt64` key and a
+//! `Binary` value of [`Workload::schema`], with keys drawn from a [`KeyDistribution`]. Runs are
+//! reproducible: the same seed draws the same operations.
+//!
+//! ```ignore
+//! let workload = Workload::new(1_000_000).distribution(KeyDistribution::zipfian()).read_ratio(0.9);
+//! let option = DbOption::new(path, &Workload::schema()).immutable_chunk_num(8);
+//! let db = DB::new(option, TokioExecutor::default(), Workload::schema()).await?;
+//! println!("{}", workload.run(&db).await?);
+//! ```
+
+use std::{
+    fmt::{self, Display, Formatter},
+    time::{Duration, Instant},
+};
+
+use arrow::datatypes::DataType;
+use fastrand::Rng;
+
+use crate::{
+    executor::Executor,
+    record::{DynRecord, DynSchema, DynamicField, Value},
+    transaction::CommitError,
+    DB,
+};
+
+/// How the keys of the operations of a [`Workload`] are drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyDistribution {
+    /// Every key is as likely
+    Uniform,
+    /// The `n`th most frequent key is drawn with a probability proportional to `1 / n^theta`,
+    /// `theta` in `(0, 1)`. Key 0 is the most frequent one.
+    Zipfian { theta: f64 },
+}
+
+impl KeyDistribution {
+    /// Zipfian distribution with the skew YCSB uses, `theta = 0.99`
+    pub fn zipfian() -> Self {
+        KeyDistribution::Zipfian { theta: 0.99 }
+    }
+}
+
+/// Draws keys in `0..count` from a [`KeyDistribution`], see "Quickly Generating Billion-Record
+/// Synthetic Databases" by Gray et al.
+enum KeyGenerator {
+    Uniform {
+        count: u64,
+    },
+    Zipfian {
+        count: u64,
+        theta: f64,
+        alpha: f64,
+        zeta: f64,
+        eta: f64,
+    },
+}
+
+impl KeyGenerator {
+    fn new(distribution: KeyDistribution, count: u64) -> Self {
+        match distribution {
+            KeyDistribution::Uniform => KeyGenerator::Uniform { count },
+            KeyDistribution::Zipfian { theta } => {
+                assert!(
+                    theta > 0.0 && theta < 1.0,
+                    "zipfian theta {theta} is not in (0, 1)"
+                );
+                let zeta_of = |n: u64| (1..=n).map(|i| 1.0 / (i as f64).powf(theta)).sum::<f64>();
+                let zeta = zeta_of(count);
+                let zeta_2 = zeta_of(2.min(count));
+
+                KeyGenerator::Zipfian {
+                    count,
+                    theta,
+                    alpha: 1.0 / (1.0 - theta),
+                    zeta,
+                    eta: (1.0 - (2.0 / count as f64).powf(1.0 - theta)) / (1.0 - zeta_2 / zeta),
+                }
+            }
+        }
+    }
+
+    fn next(&self, rng: &mut Rng) -> u64 {
+        match *self {
+            KeyGenerator::Uniform { count } => rng.u64(0..count),
+            KeyGenerator::Zipfian {
+                count,
+                theta,
+                alpha,
+                zeta,
+                eta,
+            } => {
+                let u = rng.f64();
+                let uz = u * zeta;
+                if uz < 1.0 {
+                    0
+                } else if uz < 1.0 + 0.5f64.powf(theta) {
+                    1.min(count - 1)
+                } else {
+                    ((count as f64 * (eta * u - eta + 1.0).powf(alpha)) as u64).min(count - 1)
+                }
+            }
+        }
+    }
+}
+
+/// A reproducible mix of reads and writes, see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct Workload {
+    key_count: u64,
+    distribution: KeyDistribution,
+    value_size: usize,
+    read_ratio: f64,
+    operations: usize,
+    preload: bool,
+    seed: u64,
+}
+
+impl Workload {
+    /// Workload over the keys `0..key_count`, which are all written before the measured
+    /// operations run. Defaults to uniform keys, 100 byte values, half reads and `key_count`
+    /// operations.
+    pub fn new(key_count: u64) -> Self {
+        assert!(key_count > 0, "a workload needs at least one key");
+        Self {
+            key_count,
+            distribution: KeyDistribution::Uniform,
+            value_size: 100,
+            read_ratio: 0.5,
+            operations: key_count as usize,
+            preload: true,
+            seed: 0,
+        }
+    }
+
+    pub fn distribution(self, distribution: KeyDistribution) -> Self {
+        Self {
+            distribution,
+            ..self
+        }
+    }
+
+    /// Size of the written values in bytes
+    pub fn value_size(self, value_size: usize) -> Self {
+        Self { value_size, ..self }
+    }
+
+    /// Share of the operations that are reads, the others are writes
+    pub fn read_ratio(self, read_ratio: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&read_ratio),
+            "read ratio {read_ratio} is not in [0, 1]"
+        );
+        Self { read_ratio, ..self }
+    }
+
+    /// Number of measured operations
+    pub fn operations(self, operations: usize) -> Self {
+        Self { operations, ..self }
+    }
+
+    /// Whether every key is written before the measured operations run, so reads find records
+    pub fn preload(self, preload: bool) -> Self {
+        Self { preload, ..self }
+    }
+
+    /// Seed of the random keys, values and operations
+    pub fn seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// Schema of the records of the workload: a `UInt64` key and a `Binary` value
+    pub fn schema() -> DynSchema {
+        DynSchema::new(
+            &[
+                DynamicField::new("key".into(), DataType::UInt64, false),
+                DynamicField::new("value".into(), DataType::Binary, false),
+            ],
+            0,
+        )
+    }
+
+    /// Run the workload against `db`, which has to use [`Workload::schema`]
+    pub async fn run<E>(&self, db: &DB<DynRecord, E>) -> Result<BenchReport, CommitError<DynRecord>>
+    where
+        E: Executor + Send + Sync + 'static,
+    {
+        // values are drawn apart from the operations, so a run draws the same operations with
+        // and without preloading
+        let mut rng = Rng::with_seed(self.seed);
+        let mut values = Rng::with_seed(!self.seed);
+        let keys = KeyGenerator::new(self.distribution, self.key_count);
+
+        if self.preload {
+            const PRELOAD_BATCH: u64 = 1024;
+
+            for start in (0..self.key_count).step_by(PRELOAD_BATCH as usize) {
+                let end = (start + PRELOAD_BATCH).min(self.key_count);
+                let records = (start..end)
+                    .map(|key| self.record(key, &mut values))
+                    .collect::<Vec<_>>();
+                db.insert_batch(records.into_iter()).await?;
+            }
+        }
+
+        let mut report = BenchReport::default();
+        let mut reads = Vec::new();
+        let mut writes = Vec::new();
+        let started = Instant::now();
+        for _ in 0..self.operations {
+            let key = keys.next(&mut rng);
+
+            if rng.f64() < self.read_ratio {
+                let begin = Instant::now();
+                let found = db.get(&Value::UInt64(key), |_| Some(())).await?.is_some();
+                reads.push(begin.elapsed());
+                report.found += found as usize;
+            } else {
+                let record = self.record(key, &mut values);
+                let begin = Instant::now();
+                db.insert(record).await?;
+                writes.push(begin.elapsed());
+            }
+        }
+        report.elapsed = started.elapsed();
+        report.reads = Latencies::of(reads);
+        report.writes = Latencies::of(writes);
+
+        Ok(report)
+    }
+
+    fn record(&self, key: u64, rng: &mut Rng) -> DynRecord {
+        let value = (0..self.value_size).map(|_| rng.u8(..)).collect();
+        DynRecord::new(vec![Value::UInt64(key), Value::Binary(value)], 0)
+    }
+}
+
+/// Latencies of one kind of operation of a [`BenchReport`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Latencies {
+    /// Number of operations
+    pub count: usize,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Latencies {
+    fn of(mut latencies: Vec<Duration>) -> Self {
+        if latencies.is_empty() {
+            return Latencies::default();
+        }
+        latencies.sort_unstable();
+        let count = latencies.len();
+        let percentile = |p: usize| latencies[(count * p / 100).min(count - 1)];
+
+        Latencies {
+            count,
+            mean: latencies.iter().sum::<Duration>() / count as u32,
+            p50: percentile(50),
+            p99: percentile(99),
+            max: latencies[count - 1],
+        }
+    }
+}
+
+/// Outcome of a [`Workload::run`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BenchReport {
+    /// Time the measured operations took, without the preload
+    pub elapsed: Duration,
+    pub reads: Latencies,
+    pub writes: Latencies,
+    /// Number of reads that found a record
+    pub found: usize,
+}
+
+impl BenchReport {
+    /// Measured operations per second
+    pub fn ops_per_sec(&self) -> f64 {
+        (self.reads.count + self.writes.count) as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl Display for BenchReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} ops in {:?} ({:.0} ops/s)",
+            self.reads.count + self.writes.count,
+            self.elapsed,
+            self.ops_per_sec()
+        )?;
+        for (name, latencies) in [("reads", &self.reads), ("writes", &self.writes)] {
+            writeln!(
+                f,
+                "{name}: {} (mean {:?}, p50 {:?}, p99 {:?}, max {:?})",
+                latencies.count, latencies.mean, latencies.p50, latencies.p99, latencies.max
+            )?;
+        }
+        write!(f, "found: {}", self.found)
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use fastrand::Rng;
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::{KeyDistribution, KeyGenerator, Workload};
+    use crate::{executor::tokio::TokioExecutor, record::DynRecord, DbOption, DB};
+
+    #[test]
+    fn zipfian_is_skewed() {
+        let count = 1000;
+        let mut rng = Rng::with_seed(7);
+        let keys = KeyGenerator::new(KeyDistribution::zipfian(), count);
+        let mut hits = vec![0usize; count as usize];
+        for _ in 0..100_000 {
+            hits[keys.next(&mut rng) as usize] += 1;
+        }
+
+        // the hottest key alone is drawn far more often than the coldest half of the keys
+        assert!(hits[0] > hits[500..].iter().sum::<usize>());
+        assert!(hits[0] > hits[1] && hits[1] > hits[10]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn run_workload() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &Workload::schema(),
+        );
+        let db: DB<DynRecord, TokioExecutor> =
+            DB::new(option, TokioExecutor::default(), Workload::schema())
+                .await
+                .unwrap();
+
+        let workload = Workload::new(500)
+            .distribution(KeyDistribution::zipfian())
+            .value_size(16)
+            .read_ratio(0.8)
+            .operations(1000)
+            .seed(3);
+        let report = workload.run(&db).await.unwrap();
+        assert_eq!(report.reads.count + report.writes.count, 1000);
+        // every key was preloaded
+        assert_eq!(report.found, report.reads.count);
+        assert!(report.reads.p50 <= report.reads.p99);
+
+        // the same seed draws the same operations
+        let again = workload.preload(false).run(&db).await.unwrap();
+        assert_eq!(again.reads.count, report.reads.count);
+    }
+}
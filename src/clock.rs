@@ -0,0 +1,119 @@
+//! The wall clock of a [`DB`](crate::DB), set with
+//! [`DbOption::time_source`](crate::DbOption::time_source)
+//!
+//! Every read of the current time goes through the [`TimeSource`] of the DB: the time part of
+//! new file ids, and with it the age of WALs and pinned tables, and the expiry of records. A
+//! [`ManualClock`] lets tests and simulations run in virtual time and replay it exactly.
+
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::fs::FileId;
+
+/// Source of the current time of a [`DB`](crate::DB)
+pub trait TimeSource: Debug + Send + Sync {
+    /// Milliseconds since the UNIX epoch
+    fn now_ms(&self) -> u64;
+}
+
+/// The time of the system
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl TimeSource for SystemClock {
+    fn now_ms(&self) -> u64 {
+        // read through ULID, which reads the time on every target, including wasm
+        FileId::new().timestamp_ms()
+    }
+}
+
+/// A clock that only moves when it is told to. Clones share the time.
+#[derive(Debug, Default, Clone)]
+pub struct ManualClock {
+    now_ms: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new(now_ms: u64) -> Self {
+        Self {
+            now_ms: Arc::new(AtomicU64::new(now_ms)),
+        }
+    }
+
+    pub fn set(&self, now_ms: u64) {
+        self.now_ms.store(now_ms, Ordering::Release);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now_ms
+            .fetch_add(by.as_millis() as u64, Ordering::AcqRel);
+    }
+}
+
+impl TimeSource for ManualClock {
+    fn now_ms(&self) -> u64 {
+        self.now_ms.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{str::FromStr, time::Duration};
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::ManualClock;
+    use crate::{
+        executor::tokio::TokioExecutor,
+        fs::FileId,
+        inmem::immutable::tests::TestSchema,
+        tests::{Test, TestRef},
+        DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn virtual_time() {
+        let temp_dir = TempDir::new().unwrap();
+        let clock = ManualClock::new(5_000);
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .time_source(clock.clone())
+        // `vu32` is the time the record expires at
+        .expires_at::<Test, _>(|record: TestRef<'_>| record.vu32.map(u64::from));
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+
+        // the ids of new files carry the time of the clock
+        for entry in std::fs::read_dir(temp_dir.path().join("wal")).unwrap() {
+            let name = entry.unwrap().file_name().into_string().unwrap();
+            let id = FileId::from_str(name.trim_end_matches(".wal")).unwrap();
+            assert_eq!(id.timestamp_ms(), 5_000);
+        }
+
+        db.insert(Test {
+            vstring: "a".to_string(),
+            vu32: 6_000,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        assert_eq!(db.remove_expired().await.unwrap(), 0);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(db.remove_expired().await.unwrap(), 1);
+        assert!(db
+            .get(&"a".to_string(), |_| Some(()))
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
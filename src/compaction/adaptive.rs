@@ -0,0 +1,318 @@
+//! Tuning of the flush and compaction thresholds of a [`DB`](crate::DB) while it runs, set with
+//! [`DbOption::adaptive_tuning`](crate::DbOption::adaptive_tuning)
+//!
+//! A static configuration fits one workload: a backfill wants large memtable buffers and rare
+//! compactions, while steady state reads want few tables per lookup. The tuner observes every
+//! flush of the DB and, once per window, moves the thresholds one step within the bounds of
+//! [`AdaptiveTuning`]:
+//!
+//! - when a lookup has to check more than [`AdaptiveTuning::max_read_amplification`] tables, levels
+//!   are compacted earlier,
+//! - otherwise, when the window saw at least [`AdaptiveTuning::busy_flushes`] flushes or flushes
+//!   fell behind the writes, more memtables are buffered and levels are compacted later,
+//! - otherwise the thresholds go back towards their lower bounds.
+
+use std::{
+    ops::RangeInclusive,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use crate::{
+    clock::TimeSource, compaction::leveled::LeveledOptions, record::Record, version::Version,
+};
+
+/// Bounds and signals of the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct AdaptiveTuning {
+    immutable_chunk_max_num: Option<RangeInclusive<usize>>,
+    major_threshold_with_sst_size: Option<RangeInclusive<usize>>,
+    window: Duration,
+    busy_flushes: usize,
+    max_read_amplification: usize,
+}
+
+impl Default for AdaptiveTuning {
+    fn default() -> Self {
+        Self {
+            immutable_chunk_max_num: None,
+            major_threshold_with_sst_size: None,
+            window: Duration::from_secs(60),
+            busy_flushes: 4,
+            max_read_amplification: 8,
+        }
+    }
+}
+
+impl AdaptiveTuning {
+    /// Tune [`DbOption::immutable_chunk_max_num`](crate::DbOption::immutable_chunk_max_num)
+    /// within `bounds`, not tuned by default
+    pub fn immutable_chunk_max_num(mut self, bounds: RangeInclusive<usize>) -> Self {
+        assert!(!bounds.is_empty(), "bounds {bounds:?} are empty");
+        self.immutable_chunk_max_num = Some(bounds);
+        self
+    }
+
+    /// Tune [`LeveledOptions::major_threshold_with_sst_size`] within `bounds`, not tuned by
+    /// default
+    pub fn major_threshold_with_sst_size(mut self, bounds: RangeInclusive<usize>) -> Self {
+        assert!(
+            !bounds.is_empty() && *bounds.start() > 0,
+            "bounds {bounds:?} are empty or start at 0"
+        );
+        self.major_threshold_with_sst_size = Some(bounds);
+        self
+    }
+
+    /// Time between two adjustments, 60 seconds by default
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Number of flushes in a window that marks a write heavy phase, 4 by default
+    pub fn busy_flushes(mut self, busy_flushes: usize) -> Self {
+        self.busy_flushes = busy_flushes;
+        self
+    }
+
+    /// Number of tables a lookup may have to check before levels are compacted earlier, 8 by
+    /// default. A lookup checks every table of level 0 and one table of every deeper level.
+    pub fn max_read_amplification(mut self, max_read_amplification: usize) -> Self {
+        self.max_read_amplification = max_read_amplification;
+        self
+    }
+}
+
+/// Current thresholds of an [`AdaptiveTuning`], see
+/// [`DB::tuned_thresholds`](crate::DB::tuned_thresholds)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TunedThresholds {
+    pub immutable_chunk_max_num: usize,
+    /// `None` unless the DB uses leveled compaction
+    pub major_threshold_with_sst_size: Option<usize>,
+}
+
+/// Thresholds of an [`AdaptiveTuning`] and the flushes observed in the current window
+#[derive(Clone, Default)]
+pub(crate) struct AdaptiveTuner {
+    inner: Option<Arc<TunerInner>>,
+}
+
+struct TunerInner {
+    tuning: AdaptiveTuning,
+    time_source: Arc<dyn TimeSource>,
+    immutable_chunk_max_num: AtomicUsize,
+    major_threshold_with_sst_size: Option<AtomicUsize>,
+    window: Mutex<Window>,
+}
+
+struct Window {
+    start_ms: u64,
+    flushes: usize,
+    // Whether immutable memtables were waiting beyond the limit after a flush
+    behind: bool,
+}
+
+impl AdaptiveTuner {
+    /// Tuner starting from the thresholds the DB was configured with, clamped into the bounds of
+    /// `tuning`
+    pub(crate) fn new(
+        tuning: AdaptiveTuning,
+        time_source: Arc<dyn TimeSource>,
+        immutable_chunk_max_num: usize,
+        leveled: Option<&LeveledOptions>,
+    ) -> Self {
+        let clamp = |value: usize, bounds: &Option<RangeInclusive<usize>>| match bounds {
+            Some(bounds) => value.clamp(*bounds.start(), *bounds.end()),
+            None => value,
+        };
+        let immutable_chunk_max_num =
+            clamp(immutable_chunk_max_num, &tuning.immutable_chunk_max_num);
+        let major_threshold_with_sst_size = leveled.map(|options| {
+            AtomicUsize::new(clamp(
+                options.major_threshold(),
+                &tuning.major_threshold_with_sst_size,
+            ))
+        });
+
+        Self {
+            inner: Some(Arc::new(TunerInner {
+                window: Mutex::new(Window {
+                    start_ms: time_source.now_ms(),
+                    flushes: 0,
+                    behind: false,
+                }),
+                tuning,
+                time_source,
+                immutable_chunk_max_num: AtomicUsize::new(immutable_chunk_max_num),
+                major_threshold_with_sst_size,
+            })),
+        }
+    }
+
+    pub(crate) fn thresholds(&self) -> Option<TunedThresholds> {
+        self.inner.as_ref().map(|inner| TunedThresholds {
+            immutable_chunk_max_num: inner.immutable_chunk_max_num.load(Ordering::Acquire),
+            major_threshold_with_sst_size: inner
+                .major_threshold_with_sst_size
+                .as_ref()
+                .map(|threshold| threshold.load(Ordering::Acquire)),
+        })
+    }
+
+    /// `configured`, the number of immutable memtables that triggers a flush, unless it is tuned
+    pub(crate) fn immutable_chunk_max_num(&self, configured: usize) -> usize {
+        match &self.inner {
+            Some(inner) if inner.tuning.immutable_chunk_max_num.is_some() => {
+                inner.immutable_chunk_max_num.load(Ordering::Acquire)
+            }
+            _ => configured,
+        }
+    }
+
+    /// `options` with the tuned major compaction threshold
+    pub(crate) fn leveled(&self, options: &LeveledOptions) -> LeveledOptions {
+        let threshold = self.inner.as_ref().and_then(|inner| {
+            inner.tuning.major_threshold_with_sst_size.as_ref()?;
+            inner.major_threshold_with_sst_size.as_ref()
+        });
+        match threshold {
+            Some(threshold) => options
+                .clone()
+                .major_threshold_with_sst_size(threshold.load(Ordering::Acquire)),
+            None => options.clone(),
+        }
+    }
+
+    /// Observe the DB after a flush task, which `flushed` memtables if it changed the version,
+    /// and adjust the thresholds once the window is over
+    pub(crate) fn observe<R: Record>(
+        &self,
+        flushed: bool,
+        waiting_immutables: usize,
+        version: &Version<R>,
+    ) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+        let now_ms = inner.time_source.now_ms();
+        let mut window = inner.window.lock().expect("tuner lock should not fail");
+        window.flushes += flushed as usize;
+        window.behind |= waiting_immutables > inner.immutable_chunk_max_num.load(Ordering::Acquire);
+        if now_ms.saturating_sub(window.start_ms) < inner.tuning.window.as_millis() as u64 {
+            return;
+        }
+
+        let read_amplification = version.tables_len(0)
+            + (1..version.level_slice.len())
+                .filter(|level| version.tables_len(*level) > 0)
+                .count();
+        let tuning = &inner.tuning;
+        if read_amplification > tuning.max_read_amplification {
+            step(
+                inner.major_threshold_with_sst_size.as_ref(),
+                tuning.major_threshold_with_sst_size.as_ref(),
+                false,
+            );
+        } else {
+            let busy = window.flushes >= tuning.busy_flushes || window.behind;
+            step(
+                Some(&inner.immutable_chunk_max_num),
+                tuning.immutable_chunk_max_num.as_ref(),
+                busy,
+            );
+            step(
+                inner.major_threshold_with_sst_size.as_ref(),
+                tuning.major_threshold_with_sst_size.as_ref(),
+                busy,
+            );
+        }
+        *window = Window {
+            start_ms: now_ms,
+            flushes: 0,
+            behind: false,
+        };
+    }
+}
+
+// Moves `value` one step up or down within `bounds`, if it is tuned
+fn step(value: Option<&AtomicUsize>, bounds: Option<&RangeInclusive<usize>>, up: bool) {
+    let (Some(value), Some(bounds)) = (value, bounds) else {
+        return;
+    };
+    let current = value.load(Ordering::Acquire);
+    let next = if up {
+        current.saturating_add(1)
+    } else {
+        current.saturating_sub(1)
+    };
+    value.store(
+        next.clamp(*bounds.start(), *bounds.end()),
+        Ordering::Release,
+    );
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::time::Duration;
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::{AdaptiveTuning, TunedThresholds};
+    use crate::{
+        clock::ManualClock, executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema,
+        record::test::test_items, tests::Test, DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tune_with_flush_rate() {
+        let temp_dir = TempDir::new().unwrap();
+        let clock = ManualClock::new(0);
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .time_source(clock.clone())
+        .immutable_chunk_max_num(1)
+        .adaptive_tuning(
+            AdaptiveTuning::default()
+                .immutable_chunk_max_num(1..=4)
+                .major_threshold_with_sst_size(2..=6)
+                .window(Duration::from_secs(1))
+                .busy_flushes(2),
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        let thresholds = |immutable_chunk_max_num, major_threshold_with_sst_size| {
+            Some(TunedThresholds {
+                immutable_chunk_max_num,
+                major_threshold_with_sst_size: Some(major_threshold_with_sst_size),
+            })
+        };
+        assert_eq!(db.tuned_thresholds(), thresholds(1, 4));
+
+        // a flush task is observed after it is done, which the next flush waits for
+        for (start, clock_advance) in [(0, 0), (10, 2)] {
+            clock.advance(Duration::from_secs(clock_advance));
+            for item in test_items(start..start + 10) {
+                db.insert(item).await.unwrap();
+            }
+            db.flush().await.unwrap();
+        }
+        db.flush().await.unwrap();
+        // two flushes in the window are a write heavy phase
+        assert_eq!(db.tuned_thresholds(), thresholds(2, 5));
+
+        clock.advance(Duration::from_secs(2));
+        db.flush().await.unwrap();
+        db.flush().await.unwrap();
+        // a window without flushes goes back towards the lower bounds
+        assert_eq!(db.tuned_thresholds(), thresholds(1, 4));
+    }
+}
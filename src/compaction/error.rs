@@ -1,6 +1,12 @@
 use thiserror::Error;
 
-use crate::{manifest::ManifestStorageError, record::Record, CommitError};
+use crate::{
+    error::{fusio_kind, io_kind, parquet_kind, source_kind, ErrorKind},
+    fs::FileId,
+    manifest::ManifestStorageError,
+    record::Record,
+    CommitError,
+};
 
 #[derive(Debug, Error)]
 pub enum CompactionError<R>
@@ -23,4 +29,31 @@ where
     Commit(#[from] CommitError<R>),
     #[error("the level being compacted does not have a table")]
     EmptyLevel,
+    #[error("records of table {gen} at level {level} do not match their checksum")]
+    Checksum { level: usize, gen: FileId },
+}
+
+impl<R> CompactionError<R>
+where
+    R: Record,
+{
+    /// Category of the error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            CompactionError::Io(err) => io_kind(err),
+            CompactionError::Parquet(err) => parquet_kind(err),
+            CompactionError::Fusio(err) => fusio_kind(err),
+            CompactionError::Manifest(err) => err.kind(),
+            CompactionError::Logger(err) => source_kind(err),
+            CompactionError::ChannelClose => ErrorKind::Io,
+            CompactionError::Commit(err) => err.kind(),
+            CompactionError::EmptyLevel => ErrorKind::InvalidArgument,
+            CompactionError::Checksum { .. } => ErrorKind::Corruption,
+        }
+    }
+
+    /// Whether the compaction may succeed if it is run again, see [`ErrorKind::is_retryable`]
+    pub fn is_retryable(&self) -> bool {
+        self.kind().is_retryable()
+    }
 }
@@ -0,0 +1,50 @@
+//! Hook into the records that a major compaction rewrites, set with
+//! [`DbOption::compaction_filter`](crate::DbOption::compaction_filter)
+//!
+//! Compactions read every record of the tables they merge anyway, so a filter can remove or
+//! rewrite records on the way, e.g. to purge records marked as deleted or to strip fields that
+//! must not be kept in older tables, without a scan and a write of its own:
+//!
+//! ```ignore
+//! struct PurgeDeleted;
+//!
+//! impl CompactionFilter<User> for PurgeDeleted {
+//!     fn filter(&self, _level: usize, user: UserRef<'_>) -> Decision<User> {
+//!         match user.deleted {
+//!             Some(true) => Decision::Remove,
+//!             _ => Decision::Keep,
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::sync::Arc;
+
+use crate::record::Record;
+
+/// What a major compaction writes for a record, see [`CompactionFilter`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision<R> {
+    /// Write the record as it is
+    Keep,
+    /// Write a removal of its key instead, which hides the older versions of the key in deeper
+    /// levels just like [`DB::remove`](crate::DB::remove)
+    Remove,
+    /// Write this record instead, which must have the same key
+    Change(R),
+}
+
+/// Decides what a major compaction writes for each record it rewrites
+///
+/// The filter sees the latest version of each key as of the compaction, including versions that
+/// snapshots and transactions may still read, which then read its decision. Removals of keys
+/// are written as they are, and flushes of memtables to level 0 write records unfiltered.
+pub trait CompactionFilter<R>: Send + Sync + 'static
+where
+    R: Record,
+{
+    /// What to write for `record` into the tables of `level`
+    fn filter(&self, level: usize, record: R::Ref<'_>) -> Decision<R>;
+}
+
+pub(crate) type DynCompactionFilter<R> = Arc<dyn CompactionFilter<R>>;
@@ -7,22 +7,37 @@ use ulid::Ulid;
 
 use super::{CompactionError, Compactor};
 use crate::{
-    compaction::RecordSchema,
+    aggregate,
+    compaction::{RecordSchema, TableContext},
     context::Context,
-    fs::{FileId, FileType},
+    fs::{manager::StoreManager, FileId, FileType},
     inmem::immutable::ImmutableMemTable,
-    ondisk::sstable::{SsTable, SsTableID},
+    ondisk::{
+        checksum::verify_table,
+        sstable::{SsTable, SsTableID},
+    },
     record::{self, Record},
     scope::Scope,
     stream::{level::LevelStream, ScanStream},
     version::{edit::VersionEdit, TransactionTs, Version, MAX_LEVEL},
-    CompactionExecutor, DbOption,
+    CompactionExecutor, DbOption, ParquetLru,
 };
 
 struct LeveledTask {
     input: Vec<(usize, Vec<Ulid>)>,
 }
 
+// Tables a major compaction merges into the level below `level`
+struct CompactionInputs {
+    level: usize,
+    // Ids of the tables of level 0, whose keys overlap
+    gens_l: Vec<FileId>,
+    // Index of the first and the last table of `level` and of the next level, if any, which are
+    // sorted by key below level 0
+    tables_l: (usize, usize),
+    tables_ll: Option<(usize, usize)>,
+}
+
 /// A compactor that enforces a leveled compaction strategy over all SST levels.
 ///
 /// The `LeveledCompactor` drives both minor flush‐to‐level‐0 compactions and
@@ -63,6 +78,12 @@ pub struct LeveledOptions {
     major_default_oldest_table_num: usize,
     /// Maximum number of tables to select for major compaction at level L
     major_l_selection_table_max_num: usize,
+    /// Maximum size in bytes of the input tables of a major compaction
+    max_compaction_bytes: Option<u64>,
+    /// Number of level 0 tables that triggers a major compaction of level 0
+    level0_file_num_compaction_trigger: Option<usize>,
+    /// Share of removals that makes a table compact into the next level
+    tombstone_ratio_compaction_trigger: Option<f64>,
 }
 
 impl Default for LeveledOptions {
@@ -72,6 +93,9 @@ impl Default for LeveledOptions {
             level_sst_magnification: 10,
             major_default_oldest_table_num: 3,
             major_l_selection_table_max_num: 4,
+            max_compaction_bytes: None,
+            level0_file_num_compaction_trigger: None,
+            tombstone_ratio_compaction_trigger: None,
         }
     }
 }
@@ -83,6 +107,10 @@ impl LeveledOptions {
         self
     }
 
+    pub(crate) fn major_threshold(&self) -> usize {
+        self.major_threshold_with_sst_size
+    }
+
     /// Set level SST magnification
     pub fn level_sst_magnification(mut self, value: usize) -> Self {
         self.level_sst_magnification = value;
@@ -94,6 +122,46 @@ impl LeveledOptions {
         self.major_default_oldest_table_num = value;
         self
     }
+
+    /// Bound the input of a major compaction to about `value` bytes
+    ///
+    /// Without a bound, a few tables of level L may overlap wide tables of level L + 1 that all
+    /// have to be rewritten. With it, the tables of level L with the largest keys, or the newest
+    /// tables of level 0, are left to later compactions until the tables of both levels fit into
+    /// the bound. A single table of level L and the tables it overlaps are always compacted, so
+    /// compactions keep making progress.
+    pub fn max_compaction_bytes(mut self, value: u64) -> Self {
+        self.max_compaction_bytes = Some(value);
+        self
+    }
+
+    /// Compact level 0 once it holds `value` tables, even if it is below
+    /// [`LeveledOptions::major_threshold_with_sst_size`]
+    ///
+    /// Every table of level 0 may overlap every other one, so each of them is read by a lookup.
+    /// Frequent flushes of small memtables add many small tables, and this bounds how many a
+    /// read has to check however little data they hold.
+    pub fn level0_file_num_compaction_trigger(mut self, value: usize) -> Self {
+        assert!(value > 0, "level 0 compaction trigger must be at least 1");
+        self.level0_file_num_compaction_trigger = Some(value);
+        self
+    }
+
+    /// Compact a table into the next level once `ratio` of its rows remove their key, even if
+    /// its level is below its size threshold
+    ///
+    /// A removal only frees the space of the values it removes once it is merged with them, which
+    /// takes until the levels holding them grow large enough to be compacted. Under heavy
+    /// deletes, this reclaims that space early. Only tables that overlap a table of a deeper
+    /// level are picked, and not those written before their rows were counted.
+    pub fn tombstone_ratio_compaction_trigger(mut self, ratio: f64) -> Self {
+        assert!(
+            ratio > 0.0 && ratio <= 1.0,
+            "tombstone ratio compaction trigger must be in (0, 1]"
+        );
+        self.tombstone_ratio_compaction_trigger = Some(ratio);
+        self
+    }
 }
 
 impl<R> LeveledCompactor<R>
@@ -136,18 +204,32 @@ where
     ) -> Result<(), CompactionError<R>> {
         // Perform minor compaction if batches are provided
         if let Some(batches) = batches {
-            if let Some(scope) = Self::minor_compaction(
+            let table =
+                TableContext::current(&self.ctx, &self.db_option, &self.record_schema).await;
+            if let Some(scope) = Self::minor_compaction_with(
                 &self.db_option,
                 recover_wal_ids,
                 batches,
                 &self.record_schema,
                 &self.ctx.manager,
+                &table,
             )
             .await?
             {
                 // Update manifest with new L0 SST
                 let version_ref = self.ctx.manifest.current().await;
                 let mut version_edits = vec![VersionEdit::Add { level: 0, scope }];
+                version_edits.extend(
+                    aggregate::flush_edits(
+                        &aggregate::aggregates::<R>(&self.db_option),
+                        &self.ctx,
+                        &version_ref,
+                        self.record_schema.primary_key_indices(),
+                        batches,
+                    )
+                    .await
+                    .map_err(|err| CompactionError::Manifest(err.into()))?,
+                );
                 version_edits.push(VersionEdit::LatestTimeStamp {
                     ts: version_ref.increase_ts(),
                 });
@@ -165,6 +247,17 @@ where
 
         Ok(())
     }
+
+    async fn compact_range(
+        &self,
+        range: (
+            Bound<<R::Schema as RecordSchema>::Key>,
+            Bound<<R::Schema as RecordSchema>::Key>,
+        ),
+    ) -> Result<(), CompactionError<R>> {
+        self.range_compaction((range.0.as_ref(), range.1.as_ref()))
+            .await
+    }
 }
 
 impl<R> CompactionExecutor<R> for LeveledCompactor<R>
@@ -185,6 +278,16 @@ where
     ) -> impl Future<Output = Result<(), CompactionError<R>>> + MaybeSend + 'a {
         <Self as Compactor<R>>::check_then_compaction(self, batches, recover_wal_ids, is_manual)
     }
+
+    fn compact_range<'a>(
+        &'a self,
+        range: (
+            Bound<<R::Schema as RecordSchema>::Key>,
+            Bound<<R::Schema as RecordSchema>::Key>,
+        ),
+    ) -> impl Future<Output = Result<(), CompactionError<R>>> + MaybeSend + 'a {
+        <Self as Compactor<R>>::compact_range(self, range)
+    }
 }
 
 impl<R> LeveledCompactor<R>
@@ -195,8 +298,9 @@ where
     async fn should_major_compact(&self) -> Option<usize> {
         // Check if any level needs major compaction and return the first level that needs it
         let version_ref = self.ctx.manifest.current().await;
+        let options = self.ctx.tuner().leveled(&self.options);
         for level in 0..MAX_LEVEL - 1 {
-            if Self::is_threshold_exceeded_major(&self.options, &version_ref, level) {
+            if Self::is_threshold_exceeded_major(&options, &version_ref, level) {
                 return Some(level);
             }
         }
@@ -229,6 +333,39 @@ where
         None
     }
 
+    // Pick the table whose share of removals exceeds `tombstone_ratio_compaction_trigger` the
+    // most among the tables that overlap a table of a deeper level
+    async fn plan_tombstones(&self) -> Option<LeveledTask> {
+        let trigger = self.options.tombstone_ratio_compaction_trigger?;
+        let version_ref = self.ctx.manifest.current().await;
+        let overlaps_deeper = |level: usize, scope: &Scope<_>| {
+            version_ref.level_slice[level + 1..]
+                .iter()
+                .flatten()
+                .any(|deeper| deeper.min <= scope.max && scope.min <= deeper.max)
+        };
+        let (level, gen, _) = (0..MAX_LEVEL - 1)
+            .flat_map(|level| {
+                version_ref.level_slice[level]
+                    .iter()
+                    .map(move |scope| (level, scope))
+            })
+            .filter_map(|(level, scope)| {
+                let ratio = scope.tombstone_ratio()?;
+                (ratio >= trigger && overlaps_deeper(level, scope))
+                    .then_some((level, scope.gen, ratio))
+            })
+            .max_by(|(_, _, a), (_, _, b)| a.total_cmp(b))?;
+
+        if level == 0 {
+            // newer level 0 tables must not move below older ones that hold the same keys
+            return self.plan_major(0).await;
+        }
+        Some(LeveledTask {
+            input: vec![(level, vec![gen])],
+        })
+    }
+
     async fn execute_major(&self, task: LeveledTask) -> Result<(), CompactionError<R>> {
         let version_ref = self.ctx.manifest.current().await;
         let mut version_edits = vec![];
@@ -286,12 +423,15 @@ where
     }
 
     async fn major_compaction(&self, is_manual: bool) -> Result<(), CompactionError<R>> {
-        while let Some(level) = self.should_major_compact().await {
-            if let Some(task) = self.plan_major(level).await {
-                self.execute_major(task).await?;
-            } else {
+        loop {
+            let task = match self.should_major_compact().await {
+                Some(level) => self.plan_major(level).await,
+                None => self.plan_tombstones().await,
+            };
+            let Some(task) = task else {
                 break;
-            }
+            };
+            self.execute_major(task).await?;
         }
 
         if is_manual {
@@ -305,6 +445,123 @@ where
         Ok(())
     }
 
+    // Compact the tables of each level that hold keys in `range` into the next level, down to the
+    // bottom most level that still holds tables
+    async fn range_compaction(
+        &self,
+        range: (
+            Bound<&<R::Schema as RecordSchema>::Key>,
+            Bound<&<R::Schema as RecordSchema>::Key>,
+        ),
+    ) -> Result<(), CompactionError<R>> {
+        for level in 0..MAX_LEVEL - 1 {
+            let version_ref = self.ctx.manifest.current().await;
+            if level > 0
+                && version_ref.level_slice[level + 1..]
+                    .iter()
+                    .all(Vec::is_empty)
+            {
+                break;
+            }
+            let Some(scopes_l) = Self::range_scopes(&version_ref.level_slice[level], range) else {
+                continue;
+            };
+            let scopes_l = if level == 0 {
+                // newer level 0 tables must not move below older ones that hold the same keys
+                let mut scopes_l = scopes_l;
+                loop {
+                    let min = scopes_l.0.iter().map(|scope| &scope.min).min();
+                    let max = scopes_l.0.iter().map(|scope| &scope.max).max();
+                    let (Some(min), Some(max)) = (min, max) else {
+                        break scopes_l;
+                    };
+                    let overlapping = Self::range_scopes(
+                        &version_ref.level_slice[0],
+                        (Bound::Included(min), Bound::Included(max)),
+                    )
+                    .ok_or(CompactionError::EmptyLevel)?;
+                    if overlapping.0.len() == scopes_l.0.len() {
+                        break scopes_l;
+                    }
+                    scopes_l = overlapping;
+                }
+            } else {
+                scopes_l
+            };
+            let min = scopes_l
+                .0
+                .iter()
+                .map(|scope| &scope.min)
+                .min()
+                .ok_or(CompactionError::EmptyLevel)?;
+            let max = scopes_l
+                .0
+                .iter()
+                .map(|scope| &scope.max)
+                .max()
+                .ok_or(CompactionError::EmptyLevel)?;
+            let scopes_ll = Self::range_scopes(
+                &version_ref.level_slice[level + 1],
+                (Bound::Included(min), Bound::Included(max)),
+            )
+            .unwrap_or((Vec::new(), 0, 0));
+
+            let mut version_edits = vec![];
+            let mut delete_gens = vec![];
+            Self::compact_scopes(
+                &version_ref,
+                &self.db_option,
+                level,
+                scopes_l,
+                scopes_ll,
+                &mut version_edits,
+                &mut delete_gens,
+                &self.record_schema,
+                &self.ctx,
+            )
+            .await?;
+            version_edits.push(VersionEdit::LatestTimeStamp {
+                ts: version_ref.increase_ts(),
+            });
+            self.ctx
+                .manifest
+                .update(version_edits, Some(delete_gens))
+                .await?;
+        }
+
+        self.ctx
+            .manifest
+            .rewrite()
+            .await
+            .map_err(CompactionError::Manifest)
+    }
+
+    // The tables of a level that hold keys in `range`, with the index of the first and the last
+    // of them in the level, `None` if there are none
+    #[allow(clippy::type_complexity)]
+    fn range_scopes<'a>(
+        scopes: &'a [Scope<<R::Schema as RecordSchema>::Key>],
+        range: (
+            Bound<&<R::Schema as RecordSchema>::Key>,
+            Bound<&<R::Schema as RecordSchema>::Key>,
+        ),
+    ) -> Option<(
+        Vec<&'a Scope<<R::Schema as RecordSchema>::Key>>,
+        usize,
+        usize,
+    )> {
+        let mut meet_scopes = Vec::new();
+        let (mut start, mut end) = (None, 0);
+        for (index, scope) in scopes.iter().enumerate() {
+            if scope.meets_range(range) {
+                start.get_or_insert(index);
+                end = index;
+                meet_scopes.push(scope);
+            }
+        }
+        Some((meet_scopes, start?, end))
+    }
+
     // Accumulate all SST files in a stream that fall within the min/max range in `level` and `level
     // + 1`. Then use those files to build the new SST files and delete the olds ones
     //
@@ -319,60 +576,276 @@ where
         mut max: &<R::Schema as RecordSchema>::Key,
         version_edits: &mut Vec<VersionEdit<<R::Schema as RecordSchema>::Key>>,
         delete_gens: &mut Vec<SsTableID>,
-        instance: &R::Schema,
+        instance: &Arc<R::Schema>,
         ctx: &Context<R>,
         target_level: usize,
     ) -> Result<(), CompactionError<R>> {
         let level = target_level;
 
-        let (meet_scopes_l, start_l, end_l) =
+        let (mut meet_scopes_l, start_l, mut end_l) =
             Self::this_level_scopes(version, min, max, level, leveled_options);
-        let (meet_scopes_ll, start_ll, end_ll) =
+        let (mut meet_scopes_ll, mut start_ll, mut end_ll) =
             Self::next_level_scopes(version, &mut min, &mut max, level, &meet_scopes_l)?;
+        if let Some(max_bytes) = leveled_options.max_compaction_bytes {
+            let input_bytes = |scopes_l: &[&Scope<_>], scopes_ll: &[&Scope<_>]| {
+                scopes_l
+                    .iter()
+                    .chain(scopes_ll)
+                    .map(|scope| scope.file_size)
+                    .sum::<u64>()
+            };
+            while meet_scopes_l.len() > 1
+                && input_bytes(&meet_scopes_l, &meet_scopes_ll) > max_bytes
+            {
+                meet_scopes_l.pop();
+                end_l -= 1;
+                (meet_scopes_ll, start_ll, end_ll) =
+                    Self::next_level_scopes(version, &mut min, &mut max, level, &meet_scopes_l)?;
+            }
+        }
+
+        if let ([scope], []) = (&meet_scopes_l[..], &meet_scopes_ll[..]) {
+            if Self::is_trivial_move(option, scope.gen, level) {
+                // the table keeps its file, which is therefore not deleted
+                version_edits.push(VersionEdit::Remove {
+                    level: level as u8,
+                    gen: scope.gen,
+                });
+                version_edits.push(VersionEdit::Add {
+                    level: (level + 1) as u8,
+                    scope: Scope {
+                        wal_ids: None,
+                        ..(*scope).clone()
+                    },
+                });
+                return Ok(());
+            }
+        }
+
+        Self::compact_scopes(
+            version,
+            option,
+            level,
+            (meet_scopes_l, start_l, end_l),
+            (meet_scopes_ll, start_ll, end_ll),
+            version_edits,
+            delete_gens,
+            instance,
+            ctx,
+        )
+        .await
+    }
+
+    // Merge the tables `scopes_l` of `level` with the tables `scopes_ll` of the next level that
+    // overlap them into new tables of the next level, and delete the merged tables. Each group of
+    // scopes comes with the index of its first and last table in its level.
+    #[allow(clippy::too_many_arguments, clippy::type_complexity)]
+    async fn compact_scopes(
+        version: &Version<R>,
+        option: &DbOption,
+        level: usize,
+        (meet_scopes_l, start_l, end_l): (
+            Vec<&Scope<<R::Schema as RecordSchema>::Key>>,
+            usize,
+            usize,
+        ),
+        (meet_scopes_ll, start_ll, end_ll): (
+            Vec<&Scope<<R::Schema as RecordSchema>::Key>>,
+            usize,
+            usize,
+        ),
+        version_edits: &mut Vec<VersionEdit<<R::Schema as RecordSchema>::Key>>,
+        delete_gens: &mut Vec<SsTableID>,
+        instance: &Arc<R::Schema>,
+        ctx: &Context<R>,
+    ) -> Result<(), CompactionError<R>> {
+        if option.paranoid_checks {
+            for scope in meet_scopes_l.iter() {
+                verify_table(option, ctx, instance, scope.gen, level).await?;
+            }
+            for scope in meet_scopes_ll.iter() {
+                verify_table(option, ctx, instance, scope.gen, level + 1).await?;
+            }
+        }
+
+        let inputs = CompactionInputs {
+            level,
+            gens_l: meet_scopes_l.iter().map(|scope| scope.gen).collect(),
+            tables_l: (start_l, end_l),
+            tables_ll: (!meet_scopes_ll.is_empty()).then_some((start_ll, end_ll)),
+        };
+        let table = TableContext::of(version, ctx, option, instance);
+        let cuts = Self::subcompaction_cuts(
+            option.max_subcompactions,
+            meet_scopes_l.iter().chain(meet_scopes_ll.iter()).copied(),
+        );
+
+        if cuts.is_empty() {
+            let streams = Self::input_streams(
+                version,
+                option,
+                &inputs,
+                (Bound::Unbounded, Bound::Unbounded),
+                &ctx.manager,
+                &ctx.parquet_lru,
+                instance.primary_key_indices(),
+            )
+            .await?;
+            // Build the new SSTs
+            <LeveledCompactor<R> as Compactor<R>>::build_tables_with(
+                option,
+                version_edits,
+                level + 1,
+                streams,
+                instance,
+                &ctx.manager,
+                &table,
+            )
+            .await?;
+        } else {
+            let mut ranges = Vec::with_capacity(cuts.len() + 1);
+            let mut lower = Bound::Unbounded;
+            for cut in cuts {
+                ranges.push((lower, Bound::Excluded(cut.clone())));
+                lower = Bound::Included(cut);
+            }
+            ranges.push((lower, Bound::Unbounded));
+
+            // sub-compactions run on other tasks, so they own what they read
+            let version = Arc::new(version.clone());
+            let option = Arc::new(option.clone());
+            let inputs = Arc::new(inputs);
+            let subcompactions = ranges
+                .into_iter()
+                .map(|range| {
+                    let (version, option, inputs, schema, table) = (
+                        version.clone(),
+                        option.clone(),
+                        inputs.clone(),
+                        instance.clone(),
+                        table.clone(),
+                    );
+                    let (manager, parquet_lru) = (ctx.manager.clone(), ctx.parquet_lru.clone());
+                    async move {
+                        let streams = Self::input_streams(
+                            &version,
+                            &option,
+                            &inputs,
+                            (range.0.as_ref(), range.1.as_ref()),
+                            &manager,
+                            &parquet_lru,
+                            schema.primary_key_indices(),
+                        )
+                        .await?;
+                        let mut version_edits = Vec::new();
+                        <LeveledCompactor<R> as Compactor<R>>::build_tables_with(
+                            &option,
+                            &mut version_edits,
+                            inputs.level + 1,
+                            streams,
+                            &schema,
+                            &manager,
+                            &table,
+                        )
+                        .await?;
+                        Ok::<_, CompactionError<R>>(version_edits)
+                    }
+                })
+                .collect::<Vec<_>>();
+            // the ranges are disjoint and in order, and so are the tables they build
+            for edits in ctx.subcompactions().run(subcompactions).await {
+                version_edits.extend(edits?);
+            }
+        }
+
+        // Delete old files on both levels
+        for scope in meet_scopes_l {
+            version_edits.push(VersionEdit::Remove {
+                level: level as u8,
+                gen: scope.gen,
+            });
+            delete_gens.push(SsTableID::new(scope.gen, level));
+        }
 
-        let level_path = option.level_fs_path(level).unwrap_or(&option.base_path);
-        let level_fs = ctx.manager.get_fs(level_path);
-        let mut streams = Vec::with_capacity(meet_scopes_l.len() + meet_scopes_ll.len());
+        for scope in meet_scopes_ll {
+            version_edits.push(VersionEdit::Remove {
+                level: (level + 1) as u8,
+                gen: scope.gen,
+            });
+            delete_gens.push(SsTableID::new(scope.gen, level + 1));
+        }
+
+        Ok(())
+    }
+    // Whether the table `gen` of `level` can move to the next level as it is, instead of being
+    // rewritten, when no table of the next level overlaps it. Its records are then not merged, so
+    // tables are rewritten if the compaction filters or expires records, and tables of levels on
+    // other paths or with their own writer properties are rewritten the way the next level
+    // writes them.
+    fn is_trivial_move(option: &DbOption, gen: FileId, level: usize) -> bool {
+        option.compaction_filter.is_none()
+            && !option.drop_expired
+            && option.table_path(gen, level) == option.table_path(gen, level + 1)
+            && std::ptr::eq(
+                option.write_properties(level),
+                option.write_properties(level + 1),
+            )
+    }
+
+    // Streams of the tables of `inputs`, restricted to the keys in `range`
+    async fn input_streams<'a>(
+        version: &'a Version<R>,
+        option: &DbOption,
+        inputs: &CompactionInputs,
+        range: (
+            Bound<&'a <R::Schema as RecordSchema>::Key>,
+            Bound<&'a <R::Schema as RecordSchema>::Key>,
+        ),
+        manager: &Arc<StoreManager>,
+        parquet_lru: &ParquetLru,
+        pk_indices: &'a [usize],
+    ) -> Result<Vec<ScanStream<'a, R>>, CompactionError<R>> {
+        let level = inputs.level;
+        let mut streams = Vec::with_capacity(inputs.gens_l.len() + 1);
 
         // Behaviour for level 0 is different as it is unsorted + has overlapping keys
         if level == 0 {
-            for scope in meet_scopes_l.iter() {
-                let file = level_fs
-                    .open_options(
-                        &option.table_path(scope.gen, level),
-                        FileType::Parquet.open_options(true),
-                    )
+            for gen in inputs.gens_l.iter() {
+                let (fs, path) = manager.table(option, *gen, level);
+                let file = fs
+                    .open_options(&path, FileType::Parquet.open_options(true))
                     .await?;
 
                 streams.push(ScanStream::SsTable {
-                    inner: SsTable::open(ctx.parquet_lru.clone(), scope.gen, file)
+                    inner: SsTable::open(parquet_lru.clone(), *gen, file)
                         .await?
+                        .schema(version.arrow_schema())
                         .scan(
-                            (Bound::Unbounded, Bound::Unbounded),
+                            range,
                             u32::MAX.into(),
                             None,
                             ProjectionMask::all(),
                             None,
-                            instance.primary_key_indices(),
+                            pk_indices,
                         )
                         .await?,
                 });
             }
         } else {
-            let (lower, upper) = <LeveledCompactor<R> as Compactor<R>>::full_scope(&meet_scopes_l)?;
+            let (start_l, end_l) = inputs.tables_l;
             let level_scan_l = LevelStream::new(
                 version,
                 level,
                 start_l,
                 end_l,
-                (Bound::Included(lower), Bound::Included(upper)),
+                range,
                 u32::MAX.into(),
                 None,
                 ProjectionMask::all(),
-                level_fs.clone(),
-                ctx.parquet_lru.clone(),
+                manager.clone(),
+                parquet_lru.clone(),
                 None,
-                instance.primary_key_indices(),
+                pk_indices,
             )
             .ok_or(CompactionError::EmptyLevel)?;
 
@@ -381,26 +854,21 @@ where
             });
         }
 
-        let level_l_path = option.level_fs_path(level + 1).unwrap_or(&option.base_path);
-        let level_l_fs = ctx.manager.get_fs(level_l_path);
-
         // Pushes next level SSTs that fall in the range
-        if !meet_scopes_ll.is_empty() {
-            let (lower, upper) =
-                <LeveledCompactor<R> as Compactor<R>>::full_scope(&meet_scopes_ll)?;
+        if let Some((start_ll, end_ll)) = inputs.tables_ll {
             let level_scan_ll = LevelStream::new(
                 version,
                 level + 1,
                 start_ll,
                 end_ll,
-                (Bound::Included(lower), Bound::Included(upper)),
+                range,
                 u32::MAX.into(),
                 None,
                 ProjectionMask::all(),
-                level_l_fs.clone(),
-                ctx.parquet_lru.clone(),
+                manager.clone(),
+                parquet_lru.clone(),
                 None,
-                instance.primary_key_indices(),
+                pk_indices,
             )
             .ok_or(CompactionError::EmptyLevel)?;
 
@@ -409,36 +877,27 @@ where
             });
         }
 
-        // Build the new SSTs
-        <LeveledCompactor<R> as Compactor<R>>::build_tables(
-            option,
-            version_edits,
-            level + 1,
-            streams,
-            instance,
-            level_l_fs,
-        )
-        .await?;
-
-        // Delete old files on both levels
-        for scope in meet_scopes_l {
-            version_edits.push(VersionEdit::Remove {
-                level: level as u8,
-                gen: scope.gen,
-            });
-            delete_gens.push(SsTableID::new(scope.gen, level));
-        }
-
-        for scope in meet_scopes_ll {
-            version_edits.push(VersionEdit::Remove {
-                level: (level + 1) as u8,
-                gen: scope.gen,
-            });
-            delete_gens.push(SsTableID::new(scope.gen, level + 1));
-        }
+        Ok(streams)
+    }
 
-        Ok(())
+    // Keys that split a compaction of the tables `scopes` into up to `max` ranges, taken evenly
+    // from the smallest keys of the tables
+    fn subcompaction_cuts<'a>(
+        max: usize,
+        scopes: impl Iterator<Item = &'a Scope<<R::Schema as RecordSchema>::Key>>,
+    ) -> Vec<<R::Schema as RecordSchema>::Key> {
+        let mut mins = scopes.map(|scope| &scope.min).collect::<Vec<_>>();
+        mins.sort();
+        mins.dedup();
+        // the smallest key starts the first range anyway
+        let candidates = mins.get(1..).unwrap_or_default();
+        let parts = max.min(candidates.len() + 1);
+
+        (1..parts)
+            .map(|part| candidates[part * candidates.len() / parts].clone())
+            .collect()
     }
+
     // Finds all SST files in the next level that overlap the range of the current level
     fn next_level_scopes<'a>(
         version: &'a Version<R>,
@@ -535,22 +994,36 @@ where
     /// The threshold is calculated by multiplying the base threshold with a magnification factor
     /// that increases exponentially with the level number.
     ///
-    /// Returns true if the number of tables in the level exceeds the threshold.
+    /// Returns true if the number of tables in the level exceeds the threshold, or level 0
+    /// reaches [`LeveledOptions::level0_file_num_compaction_trigger`].
     pub(crate) fn is_threshold_exceeded_major(
         options: &LeveledOptions,
         version: &Version<R>,
         level: usize,
     ) -> bool {
-        Version::<R>::tables_len(version, level)
+        let tables = Version::<R>::tables_len(version, level);
+        if level == 0
+            && options
+                .level0_file_num_compaction_trigger
+                .is_some_and(|trigger| tables >= trigger)
+        {
+            return true;
+        }
+        tables
             >= (options.major_threshold_with_sst_size
                 * options.level_sst_magnification.pow(level as u32))
     }
 }
 #[cfg(all(test, feature = "tokio"))]
 pub(crate) mod tests {
-    use std::sync::{atomic::AtomicU32, Arc};
+    use std::{
+        ops::Bound,
+        sync::{atomic::AtomicU32, Arc},
+    };
 
-    use arrow::{array::Array, datatypes::DataType as ArrayDataType};
+    use arrow::array::Array;
+    #[cfg(feature = "dyn-record")]
+    use arrow::datatypes::DataType as ArrayDataType;
     use flume::bounded;
     use fusio::{disk::TokioFs, fs::OpenOptions, path::Path, DynFs};
     use fusio_dispatch::FsOptions;
@@ -560,6 +1033,8 @@ pub(crate) mod tests {
     use parquet_lru::NoCache;
     use tempfile::TempDir;
 
+    #[cfg(feature = "dyn-record")]
+    use crate::record::{DynRecord, DynSchema, DynamicField, Value};
     use crate::{
         compaction::{
             leveled::{LeveledCompactor, LeveledOptions},
@@ -573,12 +1048,14 @@ pub(crate) mod tests {
             immutable::{tests::TestSchema, ImmutableMemTable},
             mutable::MutableMemTable,
         },
-        record::{DynRecord, DynSchema, DynamicField, Record, Schema, Value},
+        record::{Record, Schema},
         scope::Scope,
+        stream::memory::MemoryBudget,
         tests::Test,
         trigger::{TriggerFactory, TriggerType},
         version::{
-            cleaner::Cleaner, edit::VersionEdit, set::VersionSet, timestamp::Timestamp, Version,
+            cleaner::Cleaner, edit::VersionEdit, hot_range::HotRanges,
+            negative_cache::NegativeCache, set::VersionSet, timestamp::Timestamp, Version,
             MAX_LEVEL,
         },
         wal::log::LogType,
@@ -718,6 +1195,7 @@ pub(crate) mod tests {
         assert_eq!(scope.max, 6.to_string());
     }
 
+    #[cfg(feature = "dyn-record")]
     #[tokio::test(flavor = "multi_thread")]
     async fn dyn_minor_compaction() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -845,6 +1323,9 @@ pub(crate) mod tests {
             Arc::new(NoCache::default()),
             manifest,
             TestSchema.arrow_schema().clone(),
+            NegativeCache::new(0),
+            HotRanges::new(0, 0),
+            MemoryBudget::new(None),
         );
 
         let leveled_options = LeveledOptions {
@@ -859,7 +1340,7 @@ pub(crate) mod tests {
             &max,
             &mut version_edits,
             &mut vec![],
-            &TestSchema,
+            &Arc::new(TestSchema),
             &ctx,
             0,
         )
@@ -894,6 +1375,114 @@ pub(crate) mod tests {
         );
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn major_compaction_within_max_bytes() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = Arc::new(DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        ));
+        let manager = Arc::new(StoreManager::new(FsOptions::Local, vec![]).unwrap());
+        manager
+            .base_fs()
+            .create_dir_all(&option.version_log_dir_path())
+            .await
+            .unwrap();
+        manager
+            .base_fs()
+            .create_dir_all(&option.wal_dir_path())
+            .await
+            .unwrap();
+
+        let ((table_gen_1, _, table_gen_3, _, _), version) =
+            build_version(&option, &manager, &Arc::new(TestSchema)).await;
+
+        let (_, clean_sender) = Cleaner::new(option.clone(), manager.clone());
+        let manifest = Box::new(
+            VersionSet::<Test, TokioExecutor>::new(clean_sender, option.clone(), manager.clone())
+                .await
+                .unwrap(),
+        );
+        let ctx = Context::new(
+            manager.clone(),
+            Arc::new(NoCache::default()),
+            manifest,
+            TestSchema.arrow_schema().clone(),
+            NegativeCache::new(0),
+            HotRanges::new(0, 0),
+            MemoryBudget::new(None),
+        );
+
+        // both tables of level 0 and the two tables of level 1 they overlap take 52 bytes
+        let leveled_options = LeveledOptions::default().max_compaction_bytes(26);
+        let mut version_edits = Vec::new();
+        LeveledCompactor::<Test>::major_compaction_impl(
+            &version,
+            &option,
+            &leveled_options,
+            &2.to_string(),
+            &5.to_string(),
+            &mut version_edits,
+            &mut vec![],
+            &Arc::new(TestSchema),
+            &ctx,
+            0,
+        )
+        .await
+        .unwrap();
+
+        if let VersionEdit::Add { level, scope } = &version_edits[0] {
+            assert_eq!(*level, 1);
+            assert_eq!(scope.min, 1.to_string());
+            assert_eq!(scope.max, 3.to_string());
+        }
+        assert_eq!(
+            version_edits[1..].to_vec(),
+            vec![
+                VersionEdit::Remove {
+                    level: 0,
+                    gen: table_gen_1,
+                },
+                VersionEdit::Remove {
+                    level: 1,
+                    gen: table_gen_3,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn level0_file_num_trigger() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = Arc::new(DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        ));
+        let manager = StoreManager::new(FsOptions::Local, vec![]).unwrap();
+
+        // level 0 holds 2 tables
+        let (_, version) = build_version(&option, &manager, &Arc::new(TestSchema)).await;
+
+        let options = LeveledOptions::default();
+        assert!(!LeveledCompactor::<Test>::is_threshold_exceeded_major(
+            &options, &version, 0
+        ));
+        let options = options.level0_file_num_compaction_trigger(3);
+        assert!(!LeveledCompactor::<Test>::is_threshold_exceeded_major(
+            &options, &version, 0
+        ));
+        let options = options.level0_file_num_compaction_trigger(2);
+        assert!(LeveledCompactor::<Test>::is_threshold_exceeded_major(
+            &options, &version, 0
+        ));
+        // only level 0 is compacted by its table count
+        assert!(!LeveledCompactor::<Test>::is_threshold_exceeded_major(
+            &options.level0_file_num_compaction_trigger(1),
+            &version,
+            1
+        ));
+    }
+
     // https://github.com/tonbo-io/tonbo/pull/139
     #[tokio::test(flavor = "multi_thread")]
     async fn major_panic() {
@@ -983,6 +1572,9 @@ pub(crate) mod tests {
             gen: table_gen0,
             wal_ids: None,
             file_size: 13,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
         version.level_slice[1].push(Scope {
             min: 5.to_string(),
@@ -990,6 +1582,9 @@ pub(crate) mod tests {
             gen: table_gen1,
             wal_ids: None,
             file_size: 13,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
 
         let mut version_edits = Vec::new();
@@ -1007,6 +1602,9 @@ pub(crate) mod tests {
             Arc::new(NoCache::default()),
             manifest,
             TestSchema.arrow_schema().clone(),
+            NegativeCache::new(0),
+            HotRanges::new(0, 0),
+            MemoryBudget::new(None),
         );
         let leveled_options = LeveledOptions {
             major_threshold_with_sst_size: 1,
@@ -1021,7 +1619,7 @@ pub(crate) mod tests {
             &max,
             &mut version_edits,
             &mut vec![],
-            &TestSchema,
+            &Arc::new(TestSchema),
             &ctx,
             0,
         )
@@ -1144,6 +1742,216 @@ pub(crate) mod tests {
         dbg!(version);
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn trivial_move() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .leveled_compaction(LeveledOptions::default().major_threshold_with_sst_size(1));
+        {
+            let db: DB<Test, TokioExecutor> =
+                DB::new(option.clone(), TokioExecutor::default(), TestSchema)
+                    .await
+                    .unwrap();
+            for i in 0..4 {
+                db.insert(Test {
+                    vstring: i.to_string(),
+                    vu32: i,
+                    vbool: None,
+                })
+                .await
+                .unwrap();
+            }
+            let report = db.flush().await.unwrap();
+
+            // the table of level 0 overlaps no table of level 1, so it moves there as it is
+            let version = db.ctx.manifest().current().await;
+            assert!(version.level_slice[0].is_empty());
+            assert_eq!(version.level_slice[1].len(), 1);
+            let gen = version.level_slice[1][0].gen;
+            assert_eq!(report.levels[1].created, vec![gen]);
+            assert!(std::fs::metadata(temp_dir.path().join(format!("{gen}.parquet"))).is_ok());
+        }
+
+        // the moved table is kept when the version log is replayed
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        for i in 0..4 {
+            let vu32 = db
+                .get(&i.to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap();
+            assert_eq!(vu32, Some(i));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tombstone_ratio_compaction() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .leveled_compaction(LeveledOptions::default().tombstone_ratio_compaction_trigger(0.5));
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+
+        for i in 0..4 {
+            db.insert(Test {
+                vstring: i.to_string(),
+                vu32: i,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        db.flush().await.unwrap();
+        db.compact_range((Bound::Unbounded, Bound::Unbounded))
+            .await
+            .unwrap();
+        let version = db.ctx.manifest().current().await;
+        assert_eq!(version.level_slice[1].len(), 1);
+        assert_eq!(version.level_slice[1][0].rows, 4);
+        assert_eq!(version.level_slice[1][0].tombstones, 0);
+        drop(version);
+
+        // a single table of level 0 is below the size threshold, but all its rows are removals
+        for i in 0..3 {
+            db.remove(i.to_string()).await.unwrap();
+        }
+        db.flush().await.unwrap();
+
+        let version = db.ctx.manifest().current().await;
+        assert!(version.level_slice[0].is_empty());
+        assert_eq!(version.level_slice[1].len(), 1);
+        assert_eq!(version.level_slice[1][0].rows, 4);
+        assert_eq!(version.level_slice[1][0].tombstones, 3);
+        // the table of level 1 has no deeper table to merge its removals with
+        assert!(version.level_slice[2].is_empty());
+        for i in 0..4 {
+            let vu32 = db
+                .get(&i.to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap();
+            assert_eq!(vu32, (i == 3).then_some(3));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn subcompactions() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .leveled_compaction(LeveledOptions::default().major_threshold_with_sst_size(2))
+        .max_subcompactions(3);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+
+        // the second flush compacts the tables [0, 8] and [5, 9] of level 0, cut at 5
+        for (keys, vu32) in [([0, 2, 4, 6, 8], 0), ([5, 6, 7, 8, 9], 1)] {
+            for i in keys {
+                db.insert(Test {
+                    vstring: i.to_string(),
+                    vu32,
+                    vbool: None,
+                })
+                .await
+                .unwrap();
+            }
+            db.flush().await.unwrap();
+        }
+
+        let version = db.ctx.manifest().current().await;
+        assert!(version.level_slice[0].is_empty());
+        let scopes = version.level_slice[1]
+            .iter()
+            .map(|scope| (scope.min.clone(), scope.max.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            scopes,
+            vec![
+                ("0".to_string(), "4".to_string()),
+                ("5".to_string(), "9".to_string())
+            ]
+        );
+        for (key, expected) in [("0", 0), ("6", 1), ("8", 1), ("9", 1)] {
+            let vu32 = db
+                .get(&key.to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap();
+            assert_eq!(vu32, Some(expected), "key {key}");
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn compact_range() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+
+        // level 0 holds the tables [0, 1], [5, 6] and [0, 3]
+        for keys in [[0, 1], [5, 6], [0, 3]] {
+            for i in keys {
+                db.insert(Test {
+                    vstring: i.to_string(),
+                    vu32: i * 10 + keys[1],
+                    vbool: None,
+                })
+                .await
+                .unwrap();
+            }
+            db.flush().await.unwrap();
+        }
+        assert_eq!(db.ctx.manifest().current().await.level_slice[0].len(), 3);
+
+        db.compact_range((Bound::Included("5".to_string()), Bound::Unbounded))
+            .await
+            .unwrap();
+        let version = db.ctx.manifest().current().await;
+        assert_eq!(version.level_slice[0].len(), 2);
+        assert_eq!(version.level_slice[1].len(), 1);
+        assert_eq!(version.level_slice[1][0].min, "5".to_string());
+
+        // [0, 3] holds a newer version of 0, so it is compacted along with [0, 1]
+        let report = db
+            .compact_range((
+                Bound::Included("1".to_string()),
+                Bound::Included("1".to_string()),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(report.levels[0].removed.len(), 2);
+        let version = db.ctx.manifest().current().await;
+        assert!(version.level_slice[0].is_empty());
+        assert_eq!(version.level_slice[1].len(), 2);
+        assert!(version.level_slice[2].is_empty());
+
+        let vu32 = db
+            .get(&"0".to_string(), |entry| entry.get().vu32)
+            .await
+            .unwrap();
+        assert_eq!(vu32, Some(3));
+        for key in ["1", "3", "5", "6"] {
+            assert!(db
+                .get(&key.to_string(), |_| Some(()))
+                .await
+                .unwrap()
+                .is_some());
+        }
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_minor_compaction_sorted() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -1,25 +1,32 @@
+pub mod adaptive;
 pub mod error;
+pub mod filter;
 pub mod leveled;
+pub(crate) mod subcompaction;
 pub mod tiered;
 
-use std::sync::Arc;
+use std::{any::Any, sync::Arc, time::Duration};
 
+use arrow::{array::AsArray, datatypes::Schema as ArrowSchema};
 use async_trait::async_trait;
-use fusio::{DynFs, MaybeSend, MaybeSync};
+use fusio::{MaybeSend, MaybeSync};
 use fusio_parquet::writer::AsyncWriter;
 use futures::channel::oneshot;
 use futures_util::StreamExt;
-use parquet::arrow::AsyncArrowWriter;
+use parquet::{arrow::AsyncArrowWriter, errors::ParquetError};
 
 use crate::{
-    compaction::error::CompactionError,
-    fs::{generate_file_id, manager::StoreManager, FileId, FileType},
+    compaction::{error::CompactionError, filter::Decision},
+    context::Context,
+    expiry::{Expired, TableExpiry},
+    fs::{manager::StoreManager, FileId, FileType},
     inmem::immutable::ImmutableMemTable,
+    ondisk::{checksum::RecordChecksum, prefix_bloom::PrefixBloom},
     record::{self, ArrowArrays, ArrowArraysBuilder, KeyRef, Record, Schema as RecordSchema},
     scope::Scope,
     stream::{merge::MergeStream, ScanStream},
-    version::edit::VersionEdit,
-    DbOption,
+    version::{edit::VersionEdit, range_tombstone::RangeTombstone, timestamp::Timestamp, Version},
+    DbError, DbOption,
 };
 
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
@@ -42,9 +49,28 @@ where
         is_manual: bool,
     ) -> Result<(), CompactionError<R>>;
 
+    /// Compact the tables that hold keys in `range`, see
+    /// [`DB::compact_range`](crate::DB::compact_range)
+    ///
+    /// Compactors that cannot compact a part of the keys run a manual compaction of all tables,
+    /// which is what this does unless it is overridden.
+    async fn compact_range(
+        &self,
+        range: (
+            std::ops::Bound<<R::Schema as RecordSchema>::Key>,
+            std::ops::Bound<<R::Schema as RecordSchema>::Key>,
+        ),
+    ) -> Result<(), CompactionError<R>> {
+        let _ = range;
+        self.check_then_compaction(None, None, true).await
+    }
+
     /// Perform minor compaction on immutable memtables to create L0 SST files
     /// Basically the same for all compaction strategies. Think carefully if you want to override
     /// this method.
+    ///
+    /// The table is written as [`TableContext::new`] describes, see
+    /// [`Compactor::minor_compaction_with`] to write it into the current version of a DB.
     async fn minor_compaction(
         option: &DbOption,
         recover_wal_ids: Option<Vec<FileId>>,
@@ -55,6 +81,34 @@ where
         schema: &R::Schema,
         manager: &StoreManager,
     ) -> Result<Option<Scope<<R::Schema as record::Schema>::Key>>, CompactionError<R>>
+    where
+        Self: Sized,
+        <<R as record::Record>::Schema as record::Schema>::Columns: MaybeSend + MaybeSync,
+    {
+        Self::minor_compaction_with(
+            option,
+            recover_wal_ids,
+            batches,
+            schema,
+            manager,
+            &TableContext::new(schema),
+        )
+        .await
+    }
+
+    /// [`Compactor::minor_compaction`] with the schema and the removals of `table`, see
+    /// [`TableContext`]
+    async fn minor_compaction_with(
+        option: &DbOption,
+        recover_wal_ids: Option<Vec<FileId>>,
+        batches: &[(
+            Option<FileId>,
+            ImmutableMemTable<<R::Schema as record::Schema>::Columns>,
+        )],
+        schema: &R::Schema,
+        manager: &StoreManager,
+        table: &TableContext<R>,
+    ) -> Result<Option<Scope<<R::Schema as record::Schema>::Key>>, CompactionError<R>>
     where
         Self: Sized,
         <<R as record::Record>::Schema as record::Schema>::Columns: MaybeSend + MaybeSync,
@@ -65,8 +119,6 @@ where
         use parquet::arrow::ProjectionMask;
 
         if !batches.is_empty() {
-            let level_0_path = option.level_fs_path(0).unwrap_or(&option.base_path);
-            let level_0_fs = manager.get_fs(level_0_path);
             let mut wal_ids = Vec::with_capacity(batches.len());
 
             if let Some(mut recover_wal_ids) = recover_wal_ids {
@@ -90,12 +142,16 @@ where
             }
 
             // Use MergeStream to merge and sort all batches
-            let mut stream = MergeStream::<R>::from_vec(streams, u32::MAX.into(), None).await?;
+            let mut stream = MergeStream::<R>::from_vec(streams, u32::MAX.into(), None)
+                .await?
+                .keep_removed(table.keep_removed);
 
             let mut builder =
                 <R::Schema as RecordSchema>::Columns::builder(schema.arrow_schema().clone(), 0);
             let mut min = None;
             let mut max = None;
+            let mut checksum = RecordChecksum::new(option.record_checksums);
+            let mut expiry = TableExpiry::new(option);
 
             // Collect all sorted entries into a single SST
             while let Some(result) = stream.next().await {
@@ -106,6 +162,8 @@ where
                     min = Some(key.value.clone().to_key())
                 }
                 max = Some(key.value.clone().to_key());
+                checksum.update(&key, &entry.value()).await?;
+                expiry.update(entry.value());
                 builder.push(key, entry.value());
             }
 
@@ -119,8 +177,11 @@ where
                     &mut builder,
                     &mut Some(min),
                     &mut Some(max),
+                    &mut checksum,
+                    &mut expiry,
                     schema,
-                    level_0_fs,
+                    &table.table_schema,
+                    manager,
                 )
                 .await?;
 
@@ -139,36 +200,143 @@ where
         Ok(None)
     }
 
+    /// Merge `streams` into the tables of `level`
+    ///
+    /// The tables are written as [`TableContext::new`] describes, see
+    /// [`Compactor::build_tables_with`] to write them into the current version of a DB.
     async fn build_tables(
         option: &DbOption,
         version_edits: &mut Vec<VersionEdit<<R::Schema as RecordSchema>::Key>>,
         level: usize,
         streams: Vec<ScanStream<'_, R>>,
         schema: &R::Schema,
-        fs: &Arc<dyn DynFs>,
+        manager: &StoreManager,
+    ) -> Result<(), CompactionError<R>>
+    where
+        Self: Sized,
+        <<R as record::Record>::Schema as record::Schema>::Columns: MaybeSend + MaybeSync,
+    {
+        Self::build_tables_with(
+            option,
+            version_edits,
+            level,
+            streams,
+            schema,
+            manager,
+            &TableContext::new(schema),
+        )
+        .await
+    }
+
+    /// [`Compactor::build_tables`] with the schema and the removals of `table`, see
+    /// [`TableContext`]
+    async fn build_tables_with(
+        option: &DbOption,
+        version_edits: &mut Vec<VersionEdit<<R::Schema as RecordSchema>::Key>>,
+        level: usize,
+        streams: Vec<ScanStream<'_, R>>,
+        schema: &R::Schema,
+        manager: &StoreManager,
+        table: &TableContext<R>,
     ) -> Result<(), CompactionError<R>>
     where
         Self: Sized,
         <<R as record::Record>::Schema as record::Schema>::Columns: MaybeSend + MaybeSync,
     {
-        let mut stream = MergeStream::<R>::from_vec(streams, u32::MAX.into(), None).await?;
+        let mut stream = MergeStream::<R>::from_vec(streams, u32::MAX.into(), None)
+            .await?
+            .keep_removed(table.keep_removed)
+            .range_tombstones(table.range_tombstones.clone());
+        let filter = option
+            .compaction_filter
+            .as_ref()
+            .and_then(|filter| filter.filter::<R>().ok());
+        let expired = option
+            .drop_expired
+            .then(|| option.expiry.as_ref()?.expires_at::<R>().ok())
+            .flatten()
+            .map(|expires_at| Expired::new(expires_at, option.now_ms()));
 
         let mut builder =
             <R::Schema as RecordSchema>::Columns::builder(schema.arrow_schema().clone(), 0);
         let mut min = None;
         let mut max = None;
+        let mut checksum = RecordChecksum::new(option.record_checksums);
+        let mut expiry = TableExpiry::new(option);
+        let mut buffered = manager.compaction_memory().track();
 
         while let Some(result) = stream.next().await {
             let entry = result?;
             let key = entry.key();
+            let next = key.value.clone().to_key();
+
+            let at_boundary = match &max {
+                Some(previous) if *previous != next => {
+                    option
+                        .table_boundary
+                        .as_ref()
+                        .is_some_and(|boundary| boundary.is_boundary(previous, &next))
+                        || option
+                            .table_guards
+                            .is_some_and(|guards| guards.is_guard(level, &next))
+                }
+                _ => false,
+            };
+            if at_boundary {
+                Self::build_table(
+                    option,
+                    version_edits,
+                    level,
+                    &mut builder,
+                    &mut min,
+                    &mut max,
+                    &mut checksum,
+                    &mut expiry,
+                    schema,
+                    &table.table_schema,
+                    manager,
+                )
+                .await?;
+            }
 
             if min.is_none() {
-                min = Some(key.value.clone().to_key())
+                min = Some(next.clone())
             }
-            max = Some(key.value.clone().to_key());
-            builder.push(key, entry.value());
-
-            if builder.written_size() >= option.max_sst_file_size {
+            max = Some(next);
+            let value = entry.value();
+            // expired records are removed like records the filter removes
+            let decision = match (&expired, &filter, &value) {
+                (Some(expired), _, Some(record)) if expired.contains(record.clone()) => {
+                    Decision::Remove
+                }
+                (_, Some(filter), Some(record)) => filter.filter(level, record.clone()),
+                _ => Decision::Keep,
+            };
+            let changed = match decision {
+                Decision::Keep => None,
+                Decision::Remove => Some(None),
+                Decision::Change(record) => {
+                    assert!(
+                        record.key() == key.value,
+                        "compaction filter changed the key of a record"
+                    );
+                    Some(Some(record))
+                }
+            };
+            let value = match &changed {
+                Some(record) => record.as_ref().map(R::as_record_ref),
+                None => value,
+            };
+            let removal = value.is_none();
+            checksum.update(&key, &value).await?;
+            expiry.update(value.clone());
+            builder.push(key, value);
+            buffered.resize(builder.written_size());
+
+            // the value a kept removal replaced follows it into the same table
+            if builder.written_size() >= option.max_sst_file_size
+                && !(removal && table.keep_removed.is_some())
+            {
                 Self::build_table(
                     option,
                     version_edits,
@@ -176,8 +344,11 @@ where
                     &mut builder,
                     &mut min,
                     &mut max,
+                    &mut checksum,
+                    &mut expiry,
                     schema,
-                    fs,
+                    &table.table_schema,
+                    manager,
                 )
                 .await?;
             }
@@ -190,8 +361,11 @@ where
                 &mut builder,
                 &mut min,
                 &mut max,
+                &mut checksum,
+                &mut expiry,
                 schema,
-                fs,
+                &table.table_schema,
+                manager,
             )
             .await?;
         }
@@ -223,8 +397,11 @@ where
         builder: &mut <<R::Schema as RecordSchema>::Columns as ArrowArrays>::Builder,
         min: &mut Option<<R::Schema as RecordSchema>::Key>,
         max: &mut Option<<R::Schema as RecordSchema>::Key>,
+        checksum: &mut RecordChecksum,
+        expiry: &mut TableExpiry<R>,
         schema: &R::Schema,
-        fs: &Arc<dyn DynFs>,
+        table_schema: &Arc<ArrowSchema>,
+        manager: &StoreManager,
     ) -> Result<(), CompactionError<R>>
     where
         Self: Sized,
@@ -233,20 +410,37 @@ where
         debug_assert!(min.is_some());
         debug_assert!(max.is_some());
 
-        let gen = generate_file_id();
+        let gen = option.generate_file_id();
         let columns = builder.finish(None);
+        let (fs, path) = manager.new_table(option, gen, level);
         let mut writer = AsyncArrowWriter::try_new(
             AsyncWriter::new(
-                fs.open_options(
-                    &option.table_path(gen, level),
-                    FileType::Parquet.open_options(false),
-                )
-                .await?,
+                fs.open_options(&path, FileType::Parquet.open_options(false))
+                    .await?,
             ),
-            schema.arrow_schema().clone(),
-            Some(option.write_parquet_properties.clone()),
+            table_schema.clone(),
+            Some(option.write_properties(level).clone()),
         )?;
-        writer.write(columns.as_record_batch()).await?;
+        if let Some(checksum) = checksum.take() {
+            writer.append_key_value_metadata(checksum);
+        }
+        // the columns only differ from the table schema in the field ids of their fields
+        let batch = columns
+            .as_record_batch()
+            .clone()
+            .with_schema(table_schema.clone())
+            .map_err(ParquetError::from)?;
+        if let Some(prefix_len) = option.prefix_bloom_len {
+            let column = batch.column(schema.primary_key_indices()[0]).as_ref();
+            if let Some(bloom) = PrefixBloom::build(column, prefix_len) {
+                writer.append_key_value_metadata(bloom.to_key_value());
+            }
+        }
+        writer.write(&batch).await?;
+        manager.count_rows_written(batch.num_rows());
+        // the `_null` column marks the rows that remove their key
+        let tombstones = batch.column(0).as_boolean().true_count() as u64;
+        let rows = batch.num_rows() as u64;
 
         let file_size = writer.bytes_written() as u64;
         writer.close().await?;
@@ -258,40 +452,202 @@ where
                 gen,
                 wal_ids: None,
                 file_size,
+                rows,
+                tombstones,
+                expires: expiry.take(),
             },
         });
         Ok(())
     }
 }
 
+/// State of a DB that the tables a flush or compaction writes depend on besides their records,
+/// see [`Compactor::build_tables_with`]
+pub struct TableContext<R: Record> {
+    // Arrow schema the tables are written with, the one of the schema with the field id of each
+    // column, see `SchemaVersion`
+    pub(crate) table_schema: Arc<ArrowSchema>,
+    // Removals at or after it keep the value they replaced, see `DbOption::soft_delete`
+    pub(crate) keep_removed: Option<Timestamp>,
+    // Removals of key ranges whose records are dropped
+    pub(crate) range_tombstones: Vec<RangeTombstone<<R::Schema as RecordSchema>::Key>>,
+}
+
+impl<R: Record> TableContext<R> {
+    /// Write the tables with the Arrow schema of `schema` and drop the values that removals
+    /// replaced, as for a DB without stored schema, soft deletes and range removals
+    pub fn new(schema: &R::Schema) -> Self {
+        Self {
+            table_schema: schema.arrow_schema().clone(),
+            keep_removed: None,
+            range_tombstones: Vec::new(),
+        }
+    }
+
+    /// Write the tables into the current version of the DB of `ctx`, opened with `option`
+    pub async fn current(ctx: &Context<R>, option: &DbOption, schema: &R::Schema) -> Self {
+        Self::of(&ctx.current_manifest().await, ctx, option, schema)
+    }
+
+    pub(crate) fn of(
+        version: &Version<R>,
+        ctx: &Context<R>,
+        option: &DbOption,
+        schema: &R::Schema,
+    ) -> Self {
+        Self {
+            table_schema: version.table_schema(schema),
+            keep_removed: ctx
+                .purge_horizon()
+                .keep_removed_since(option.now_ms(), ctx.load_ts()),
+            range_tombstones: version.range_tombstones.clone(),
+        }
+    }
+}
+
+impl<R: Record> Clone for TableContext<R> {
+    fn clone(&self) -> Self {
+        Self {
+            table_schema: self.table_schema.clone(),
+            keep_removed: self.keep_removed,
+            range_tombstones: self.range_tombstones.clone(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum CompactTask {
     Freeze,
-    Flush(Option<oneshot::Sender<()>>),
+    Flush(Option<oneshot::Sender<CompactionReport>>),
+    /// Run the major compactions that are due, without flushing memtables
+    Compact(oneshot::Sender<CompactionReport>),
+    /// Compact the tables in a key range, a `(Bound<Key>, Bound<Key>)` of the key of the schema,
+    /// which is type erased as tasks are not generic over the key
+    CompactRange(Box<dyn Any + Send>, oneshot::Sender<CompactionReport>),
+    /// Remove the keys in a range, type erased like the range of [`CompactTask::CompactRange`].
+    /// Running it between compactions keeps them from merging the tables of the range while its
+    /// tombstone is written.
+    DeleteRange(Box<dyn Any + Send>, oneshot::Sender<Result<(), DbError>>),
+}
+
+/// What a flush and the compactions it triggered changed, returned by
+/// [`DB::flush`](crate::DB::flush)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactionReport {
+    /// Tables added to and removed from each level, indexed by level
+    pub levels: Vec<LevelChanges>,
+    /// Bytes of the added tables, without the tables that only moved to another level
+    pub bytes_written: u64,
+    /// Rows written to the added tables, after merging the versions of their keys
+    pub rows_written: u64,
+    pub duration: Duration,
+}
+
+/// Tables of a level of a [`CompactionReport`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LevelChanges {
+    pub created: Vec<FileId>,
+    pub removed: Vec<FileId>,
+}
+
+impl CompactionReport {
+    /// Tables that differ between `previous` and `version`
+    pub(crate) fn between<R: Record>(
+        previous: &Version<R>,
+        version: &Version<R>,
+        rows_written: u64,
+        duration: Duration,
+    ) -> Self {
+        let mut bytes_written = 0;
+        let levels = previous
+            .level_slice
+            .iter()
+            .zip(version.level_slice.iter())
+            .map(|(before, after)| {
+                let created = after
+                    .iter()
+                    .filter(|scope| before.iter().all(|old| old.gen != scope.gen))
+                    .inspect(|scope| {
+                        // tables that moved from another level were not written
+                        let moved = previous
+                            .level_slice
+                            .iter()
+                            .flatten()
+                            .any(|old| old.gen == scope.gen);
+                        if !moved {
+                            bytes_written += scope.file_size;
+                        }
+                    })
+                    .map(|scope| scope.gen)
+                    .collect();
+                let removed = before
+                    .iter()
+                    .filter(|scope| after.iter().all(|new| new.gen != scope.gen))
+                    .map(|scope| scope.gen)
+                    .collect();
+                LevelChanges { created, removed }
+            })
+            .collect();
+
+        CompactionReport {
+            levels,
+            bytes_written,
+            rows_written,
+            duration,
+        }
+    }
+
+    /// Whether the flush changed no table
+    pub fn is_empty(&self) -> bool {
+        self.levels
+            .iter()
+            .all(|level| level.created.is_empty() && level.removed.is_empty())
+    }
 }
 
 #[cfg(all(test, feature = "tokio"))]
 pub(crate) mod tests {
-    use std::sync::{atomic::AtomicU32, Arc};
+    use std::{
+        ops::Bound,
+        sync::{atomic::AtomicU32, Arc},
+    };
 
     use flume::bounded;
-    use fusio::DynFs;
+    use fusio::{path::Path, DynFs};
+    use fusio_dispatch::FsOptions;
     use fusio_parquet::writer::AsyncWriter;
-    use parquet::arrow::AsyncArrowWriter;
+    use futures_util::{stream, StreamExt};
+    use parquet::{
+        arrow::{AsyncArrowWriter, ProjectionMask},
+        basic::{Compression, ZstdLevel},
+        file::properties::WriterProperties,
+    };
+    use parquet_lru::NoCache;
 
     use crate::{
+        compaction::{
+            filter::{CompactionFilter, Decision},
+            leveled::{LeveledCompactor, LeveledOptions},
+            Compactor,
+        },
+        executor::tokio::TokioExecutor,
         fs::{generate_file_id, manager::StoreManager, FileId, FileType},
         inmem::{
             immutable::{tests::TestSchema, ImmutableMemTable},
             mutable::MutableMemTable,
         },
-        record::{Record, Schema},
+        ondisk::sstable::SsTable,
+        record::{
+            test::{test_items, TestRef},
+            Record, Schema,
+        },
         scope::Scope,
+        stream::ScanStream,
         tests::Test,
-        trigger::TriggerFactory,
-        version::{timestamp::Timestamp, Version},
+        trigger::{TriggerFactory, TriggerType},
+        version::{edit::VersionEdit, timestamp::Timestamp, Version},
         wal::log::LogType,
-        DbError, DbOption,
+        DbError, DbOption, DB,
     };
 
     async fn build_immutable<R>(
@@ -314,6 +670,44 @@ pub(crate) mod tests {
         Ok(mutable.into_immutable().await.unwrap().1)
     }
 
+    // Merges `records` into the tables of `level` like a major compaction of them
+    async fn build_tables_of(
+        option: &DbOption,
+        manager: &StoreManager,
+        level: usize,
+        records: impl IntoIterator<Item = Test>,
+    ) -> Vec<VersionEdit<String>> {
+        let records = records
+            .into_iter()
+            .map(|record| (LogType::Full, record, Timestamp::from(0)))
+            .collect();
+        let batch =
+            build_immutable::<Test>(option, records, &Arc::new(TestSchema), manager.base_fs())
+                .await
+                .unwrap();
+        let streams = vec![ScanStream::Immutable {
+            inner: stream::iter(batch.scan(
+                (Bound::Unbounded, Bound::Unbounded),
+                u32::MAX.into(),
+                ProjectionMask::all(),
+                None,
+            )),
+        }];
+
+        let mut version_edits = Vec::new();
+        <LeveledCompactor<Test> as Compactor<Test>>::build_tables(
+            option,
+            &mut version_edits,
+            level,
+            streams,
+            &TestSchema,
+            manager,
+        )
+        .await
+        .unwrap();
+        version_edits
+    }
+
     pub(crate) async fn build_parquet_table<R>(
         option: &DbOption,
         gen: FileId,
@@ -565,6 +959,9 @@ pub(crate) mod tests {
             gen: table_gen_1,
             wal_ids: None,
             file_size: 13,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
         version.level_slice[0].push(Scope {
             min: 4.to_string(),
@@ -572,6 +969,9 @@ pub(crate) mod tests {
             gen: table_gen_2,
             wal_ids: None,
             file_size: 13,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
         version.level_slice[1].push(Scope {
             min: 1.to_string(),
@@ -579,6 +979,9 @@ pub(crate) mod tests {
             gen: table_gen_3,
             wal_ids: None,
             file_size: 13,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
         version.level_slice[1].push(Scope {
             min: 4.to_string(),
@@ -586,6 +989,9 @@ pub(crate) mod tests {
             gen: table_gen_4,
             wal_ids: None,
             file_size: 13,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
         version.level_slice[1].push(Scope {
             min: 7.to_string(),
@@ -593,6 +999,9 @@ pub(crate) mod tests {
             gen: table_gen_5,
             wal_ids: None,
             file_size: 13,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
         (
             (
@@ -605,6 +1014,293 @@ pub(crate) mod tests {
             version,
         )
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn flush_reports() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .leveled_compaction(LeveledOptions::default().level0_file_num_compaction_trigger(2));
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+
+        for item in test_items(0u32..10) {
+            db.insert(item).await.unwrap();
+        }
+        let report = db.flush().await.unwrap();
+        assert_eq!(report.levels[0].created.len(), 1);
+        assert!(report.levels[1..]
+            .iter()
+            .all(|level| level.created.is_empty() && level.removed.is_empty()));
+        assert_eq!(report.rows_written, 10);
+        assert!(report.bytes_written > 0);
+        let flushed = report.levels[0].created[0];
+
+        // the second table of level 0 triggers a major compaction of both tables
+        for item in test_items(10u32..20) {
+            db.insert(item).await.unwrap();
+        }
+        let report = db.flush().await.unwrap();
+        assert!(report.levels[0].created.is_empty());
+        assert_eq!(report.levels[0].removed, vec![flushed]);
+        assert!(!report.levels[1].created.is_empty());
+        assert_eq!(report.rows_written, 10 + 20);
+
+        assert!(db.flush().await.unwrap().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn wait_for_compaction() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .immutable_chunk_num(1)
+        .immutable_chunk_max_num(0)
+        .leveled_compaction(LeveledOptions::default().level0_file_num_compaction_trigger(2));
+        option.trigger_type = TriggerType::Length(5);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+
+        // memtables are flushed in the background, some of their flushes run behind the writes
+        for item in test_items(0u32..50) {
+            db.insert(item).await.unwrap();
+        }
+        db.wait_for_compaction().await.unwrap();
+
+        let version = db.ctx.manifest().current().await;
+        assert!(version.level_slice[0].len() < 2);
+        assert!(version.level_slice[1..]
+            .iter()
+            .any(|level| !level.is_empty()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn build_tables_on_boundaries() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .table_boundary(|previous: &String, key: &String| previous[..1] != key[..1]);
+        let manager = StoreManager::new(FsOptions::Local, vec![]).unwrap();
+        manager
+            .base_fs()
+            .create_dir_all(&option.wal_dir_path())
+            .await
+            .unwrap();
+
+        let records = ["a1", "a2", "b1", "c1", "c2"].map(|key| Test {
+            vstring: key.to_string(),
+            vu32: 0,
+            vbool: None,
+        });
+        let version_edits = build_tables_of(&option, &manager, 1, records).await;
+
+        let scopes = version_edits
+            .iter()
+            .map(|edit| match edit {
+                VersionEdit::Add { scope, .. } => (scope.min.as_str(), scope.max.as_str()),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(scopes, vec![("a1", "a2"), ("b1", "b1"), ("c1", "c2")]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn build_tables_at_guards() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .table_guards(7);
+        let guards = option.table_guards.unwrap();
+        let manager = StoreManager::new(FsOptions::Local, vec![]).unwrap();
+        manager
+            .base_fs()
+            .create_dir_all(&option.wal_dir_path())
+            .await
+            .unwrap();
+
+        // level 6 samples about one in four keys as guards
+        let level = 6;
+        let mut cuts = Vec::new();
+        for step in [1, 3] {
+            let keys = (0..200).step_by(step).map(|i| format!("{i:03}"));
+            let records = keys.clone().map(|vstring| Test {
+                vstring,
+                vu32: 0,
+                vbool: None,
+            });
+            let version_edits = build_tables_of(&option, &manager, level, records).await;
+
+            let mins = version_edits
+                .iter()
+                .skip(1)
+                .map(|edit| match edit {
+                    VersionEdit::Add { scope, .. } => scope.min.clone(),
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>();
+            let expected = keys
+                .skip(1)
+                .filter(|key| guards.is_guard(level, key))
+                .collect::<Vec<_>>();
+            assert!(!expected.is_empty());
+            assert_eq!(mins, expected);
+            cuts.push(mins);
+        }
+
+        // a compaction of other keys of the same range cuts at the same guards
+        assert!(cuts[1].iter().all(|key| cuts[0].contains(key)));
+        // guards of a level are guards of all deeper levels
+        assert!(
+            cuts[0]
+                .iter()
+                .filter(|key| guards.is_guard(1, *key))
+                .count()
+                < cuts[0].len()
+        );
+        assert!(cuts[0]
+            .iter()
+            .all(|key| !guards.is_guard(1, key) || guards.is_guard(2, key)));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn build_tables_with_filter() {
+        struct Filter;
+
+        impl CompactionFilter<Test> for Filter {
+            fn filter(&self, level: usize, record: TestRef<'_>) -> Decision<Test> {
+                assert_eq!(level, 1);
+                match record.vu32 {
+                    Some(1) => Decision::Remove,
+                    Some(2) => Decision::Change(Test {
+                        vstring: record.vstring.to_string(),
+                        vu32: 20,
+                        vbool: None,
+                    }),
+                    _ => Decision::Keep,
+                }
+            }
+        }
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .compaction_filter::<Test, _>(Filter);
+        let manager = StoreManager::new(FsOptions::Local, vec![]).unwrap();
+        manager
+            .base_fs()
+            .create_dir_all(&option.wal_dir_path())
+            .await
+            .unwrap();
+
+        let version_edits = build_tables_of(&option, &manager, 1, test_items(0u32..4)).await;
+
+        let gen = match &version_edits[0] {
+            VersionEdit::Add { scope, .. } => scope.gen,
+            _ => unreachable!(),
+        };
+        let (fs, path) = manager.table(&option, gen, 1);
+        let rows = SsTable::<Test>::open(
+            Arc::new(NoCache::default()),
+            gen,
+            fs.open_options(&path, FileType::Parquet.open_options(true))
+                .await
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .scan(
+            (Bound::Unbounded, Bound::Unbounded),
+            u32::MAX.into(),
+            None,
+            ProjectionMask::all(),
+            None,
+            TestSchema.primary_key_indices(),
+        )
+        .await
+        .unwrap()
+        .map(|row| {
+            let row = row.unwrap();
+            (
+                row.key().to_string(),
+                row.get().and_then(|record| record.vu32),
+            )
+        })
+        .collect::<Vec<_>>()
+        .await;
+        assert_eq!(
+            rows,
+            vec![
+                ("0".to_string(), Some(0)),
+                ("1".to_string(), None),
+                ("2".to_string(), Some(20)),
+                ("3".to_string(), Some(3)),
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn build_tables_with_level_properties() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .level_write_properties(
+            1,
+            WriterProperties::builder()
+                .set_compression(Compression::ZSTD(ZstdLevel::default()))
+                .set_max_row_group_size(2)
+                .build(),
+        )
+        .unwrap();
+        let manager = StoreManager::new(FsOptions::Local, vec![]).unwrap();
+        manager
+            .base_fs()
+            .create_dir_all(&option.wal_dir_path())
+            .await
+            .unwrap();
+
+        for level in [0, 1] {
+            let version_edits =
+                build_tables_of(&option, &manager, level, test_items(0u32..5)).await;
+            let [VersionEdit::Add { scope, .. }] = &version_edits[..] else {
+                unreachable!()
+            };
+
+            let (fs, path) = manager.table(&option, scope.gen, level);
+            let metadata = SsTable::<Test>::open(
+                Arc::new(NoCache::default()),
+                scope.gen,
+                fs.open_options(&path, FileType::Parquet.open_options(true))
+                    .await
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .metadata()
+            .await
+            .unwrap();
+            let (compression, row_groups) = if level == 0 {
+                (Compression::LZ4, 1)
+            } else {
+                (Compression::ZSTD(ZstdLevel::default()), 3)
+            };
+            assert_eq!(metadata.num_row_groups(), row_groups);
+            assert_eq!(metadata.row_group(0).column(0).compression(), compression);
+        }
+    }
 }
 
 #[cfg(all(test, feature = "tokio"))]
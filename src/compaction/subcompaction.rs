@@ -0,0 +1,75 @@
+//! Workers that run the sub-compactions of a major compaction in parallel, see
+//! [`DbOption::max_subcompactions`](crate::DbOption::max_subcompactions)
+
+use std::pin::Pin;
+
+use flume::{SendError, Sender};
+use fusio::{dynamic::MaybeSendFuture, MaybeSend};
+use futures::channel::oneshot;
+use futures_util::future::join_all;
+
+use crate::executor::Executor;
+
+type Task = Pin<Box<dyn MaybeSendFuture<Output = ()> + 'static>>;
+
+/// Tasks of the executor of a [`DB`](crate::DB) that run sub-compactions
+///
+/// Without workers, the sub-compactions of a compaction run concurrently on the task of the
+/// compaction, which overlaps their reads and writes but not their merging.
+#[derive(Default)]
+pub(crate) struct SubCompactions {
+    tasks: Option<Sender<Task>>,
+}
+
+impl SubCompactions {
+    /// Spawn `workers` tasks on `executor`, which stop once `self` is dropped
+    pub(crate) fn spawn<E>(executor: &E, workers: usize) -> Self
+    where
+        E: Executor,
+    {
+        if workers == 0 {
+            return Self::default();
+        }
+        let (tasks, task_rx) = flume::unbounded::<Task>();
+        for _ in 0..workers {
+            let task_rx = task_rx.clone();
+            executor.spawn(async move {
+                while let Ok(task) = task_rx.recv_async().await {
+                    task.await;
+                }
+            });
+        }
+
+        Self { tasks: Some(tasks) }
+    }
+
+    /// Run `subcompactions` at the same time and wait for all of them, with their outputs in the
+    /// order of `subcompactions`
+    pub(crate) async fn run<F, T>(&self, subcompactions: Vec<F>) -> Vec<T>
+    where
+        F: MaybeSendFuture<Output = T> + 'static,
+        T: MaybeSend + 'static,
+    {
+        let Some(tasks) = &self.tasks else {
+            return join_all(subcompactions).await;
+        };
+
+        let mut outputs = Vec::with_capacity(subcompactions.len());
+        for subcompaction in subcompactions {
+            let (tx, rx) = oneshot::channel();
+            let task: Task = Box::pin(async move {
+                let _ = tx.send(subcompaction.await);
+            });
+            // the workers only stop along with `self`
+            if let Err(SendError(task)) = tasks.send(task) {
+                task.await;
+            }
+            outputs.push(rx);
+        }
+        join_all(outputs)
+            .await
+            .into_iter()
+            .map(|output| output.expect("sub-compaction should not panic"))
+            .collect()
+    }
+}
@@ -7,11 +7,15 @@ use ulid::Ulid;
 
 use super::{CompactionError, Compactor};
 use crate::{
-    compaction::RecordSchema,
+    aggregate,
+    compaction::{RecordSchema, TableContext},
     context::Context,
     fs::{FileId, FileType},
     inmem::immutable::ImmutableMemTable,
-    ondisk::sstable::{SsTable, SsTableID},
+    ondisk::{
+        checksum::verify_table,
+        sstable::{SsTable, SsTableID},
+    },
     record::{self, Record},
     scope::Scope,
     stream::{level::LevelStream, ScanStream},
@@ -107,18 +111,32 @@ where
     ) -> Result<(), CompactionError<R>> {
         // Perform minor compaction if batches are provided
         if let Some(batches) = batches {
-            if let Some(scope) = Self::minor_compaction(
+            let table =
+                TableContext::current(&self.ctx, &self.db_option, &self.record_schema).await;
+            if let Some(scope) = Self::minor_compaction_with(
                 &self.db_option,
                 recover_wal_ids,
                 batches,
                 &self.record_schema,
                 &self.ctx.manager,
+                &table,
             )
             .await?
             {
                 // Update manifest with new L0 SST
                 let version_ref = self.ctx.manifest.current().await;
                 let mut version_edits = vec![VersionEdit::Add { level: 0, scope }];
+                version_edits.extend(
+                    aggregate::flush_edits(
+                        &aggregate::aggregates::<R>(&self.db_option),
+                        &self.ctx,
+                        &version_ref,
+                        self.record_schema.primary_key_indices(),
+                        batches,
+                    )
+                    .await
+                    .map_err(|err| CompactionError::Manifest(err.into()))?,
+                );
                 version_edits.push(VersionEdit::LatestTimeStamp {
                     ts: version_ref.increase_ts(),
                 });
@@ -285,30 +303,25 @@ where
         if source_scopes.is_empty() {
             return Ok(());
         }
-
-        let source_tier_path = option
-            .level_fs_path(source_tier)
-            .unwrap_or(&option.base_path);
-        let source_tier_fs = ctx.manager.get_fs(source_tier_path);
-        let target_tier_path = option
-            .level_fs_path(target_tier)
-            .unwrap_or(&option.base_path);
-        let target_tier_fs = ctx.manager.get_fs(target_tier_path);
+        if option.paranoid_checks {
+            for scope in source_scopes.iter() {
+                verify_table(option, ctx, instance, scope.gen, source_tier).await?;
+            }
+        }
 
         let mut streams = Vec::with_capacity(source_scopes.len());
 
         if source_tier == 0 {
             for scope in source_scopes.iter() {
-                let file = source_tier_fs
-                    .open_options(
-                        &option.table_path(scope.gen, source_tier),
-                        FileType::Parquet.open_options(true),
-                    )
+                let (fs, path) = ctx.manager.table(option, scope.gen, source_tier);
+                let file = fs
+                    .open_options(&path, FileType::Parquet.open_options(true))
                     .await?;
 
                 streams.push(ScanStream::SsTable {
                     inner: SsTable::open(ctx.parquet_lru.clone(), scope.gen, file)
                         .await?
+                        .schema(version.arrow_schema())
                         .scan(
                             (Bound::Unbounded, Bound::Unbounded),
                             u32::MAX.into(),
@@ -331,7 +344,7 @@ where
                 u32::MAX.into(),
                 None,
                 ProjectionMask::all(),
-                source_tier_fs.clone(),
+                ctx.manager.clone(),
                 ctx.parquet_lru.clone(),
                 None,
                 instance.primary_key_indices(),
@@ -341,13 +354,14 @@ where
             streams.push(ScanStream::Level { inner: tier_scan });
         }
 
-        <TieredCompactor<R> as Compactor<R>>::build_tables(
+        <TieredCompactor<R> as Compactor<R>>::build_tables_with(
             option,
             version_edits,
             target_tier,
             streams,
             instance,
-            target_tier_fs,
+            &ctx.manager,
+            &TableContext::of(version, ctx, option, instance),
         )
         .await?;
 
@@ -408,10 +422,12 @@ pub(crate) mod tests {
         },
         record::{Record, Schema},
         scope::Scope,
+        stream::memory::MemoryBudget,
         tests::Test,
         trigger::{TriggerFactory, TriggerType},
         version::{
-            cleaner::Cleaner, edit::VersionEdit, set::VersionSet, timestamp::Timestamp, Version,
+            cleaner::Cleaner, edit::VersionEdit, hot_range::HotRanges,
+            negative_cache::NegativeCache, set::VersionSet, timestamp::Timestamp, Version,
             MAX_LEVEL,
         },
         wal::log::LogType,
@@ -732,6 +748,9 @@ pub(crate) mod tests {
             gen: table_gen_1,
             wal_ids: None,
             file_size: 100,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
         version.level_slice[0].push(Scope {
             min: "3".to_string(),
@@ -739,6 +758,9 @@ pub(crate) mod tests {
             gen: table_gen_2,
             wal_ids: None,
             file_size: 100,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
         version.level_slice[0].push(Scope {
             min: "5".to_string(),
@@ -746,6 +768,9 @@ pub(crate) mod tests {
             gen: table_gen_3,
             wal_ids: None,
             file_size: 100,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
         version.level_slice[0].push(Scope {
             min: "7".to_string(),
@@ -753,6 +778,9 @@ pub(crate) mod tests {
             gen: table_gen_4,
             wal_ids: None,
             file_size: 100,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
 
         // Test tier compaction
@@ -769,6 +797,9 @@ pub(crate) mod tests {
             Arc::new(NoCache::default()),
             Box::new(manifest),
             TestSchema.arrow_schema().clone(),
+            NegativeCache::new(0),
+            HotRanges::new(0, 0),
+            MemoryBudget::new(None),
         );
 
         TieredCompactor::<Test>::tier_compaction(
@@ -855,6 +886,9 @@ pub(crate) mod tests {
             gen: generate_file_id(),
             wal_ids: None,
             file_size: 100,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
         version.level_slice[0].push(Scope {
             min: "2".to_string(),
@@ -862,6 +896,9 @@ pub(crate) mod tests {
             gen: generate_file_id(),
             wal_ids: None,
             file_size: 100,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
 
         // Tier 0 should not be full yet (at capacity but not exceeding)
@@ -876,6 +913,9 @@ pub(crate) mod tests {
             gen: generate_file_id(),
             wal_ids: None,
             file_size: 100,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
 
         // Now tier 0 should be full (exceeding capacity of 2)
@@ -904,6 +944,9 @@ pub(crate) mod tests {
                 gen: generate_file_id(),
                 wal_ids: None,
                 file_size: 100,
+                rows: 0,
+                tombstones: 0,
+                expires: None,
             });
         }
 
@@ -919,6 +962,9 @@ pub(crate) mod tests {
             gen: generate_file_id(),
             wal_ids: None,
             file_size: 100,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
 
         // Now both tiers should be full
@@ -964,6 +1010,9 @@ pub(crate) mod tests {
             gen: generate_file_id(),
             wal_ids: None,
             file_size: 100,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
         version.level_slice[0].push(Scope {
             min: "3".to_string(),
@@ -971,6 +1020,9 @@ pub(crate) mod tests {
             gen: generate_file_id(),
             wal_ids: None,
             file_size: 100,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
         version.level_slice[0].push(Scope {
             min: "5".to_string(),
@@ -978,6 +1030,9 @@ pub(crate) mod tests {
             gen: generate_file_id(),
             wal_ids: None,
             file_size: 100,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         });
 
         // With max_tiers = 1, tier 0 is still considered full when exceeding capacity
@@ -3,11 +3,18 @@ use std::sync::Arc;
 use arrow::datatypes::Schema;
 
 use crate::{
+    compaction::{adaptive::AdaptiveTuner, subcompaction::SubCompactions},
     fs::manager::StoreManager,
+    idempotency::IdempotencyWindow,
     manifest::{ManifestStorage, ManifestStorageError},
     ondisk::sstable::SsTableID,
     record::Record,
-    version::{edit::VersionEdit, timestamp::Timestamp, VersionRef},
+    soft_delete::PurgeHorizon,
+    stream::memory::MemoryBudget,
+    version::{
+        edit::VersionEdit, hot_range::HotRanges, negative_cache::NegativeCache,
+        timestamp::Timestamp, VersionRef,
+    },
     ParquetLru,
 };
 
@@ -19,6 +26,13 @@ pub struct Context<R: Record> {
     // defined during start-up and should not change during runtime.
     pub(crate) manifest: Box<dyn ManifestStorage<R>>,
     pub(crate) arrow_schema: Arc<Schema>,
+    pub(crate) negative_cache: NegativeCache<R>,
+    pub(crate) hot_ranges: HotRanges<R>,
+    pub(crate) scan_memory: MemoryBudget,
+    pub(crate) tuner: AdaptiveTuner,
+    pub(crate) idempotency_window: IdempotencyWindow,
+    pub(crate) subcompactions: SubCompactions,
+    pub(crate) purge_horizon: PurgeHorizon,
 }
 
 impl<R> Context<R>
@@ -30,12 +44,51 @@ where
         parquet_lru: ParquetLru,
         manifest: Box<dyn ManifestStorage<R>>,
         arrow_schema: Arc<Schema>,
+        negative_cache: NegativeCache<R>,
+        hot_ranges: HotRanges<R>,
+        scan_memory: MemoryBudget,
     ) -> Self {
         Self {
             manager,
             parquet_lru,
             manifest,
             arrow_schema,
+            negative_cache,
+            hot_ranges,
+            scan_memory,
+            tuner: AdaptiveTuner::default(),
+            idempotency_window: IdempotencyWindow::default(),
+            subcompactions: SubCompactions::default(),
+            purge_horizon: PurgeHorizon::default(),
+        }
+    }
+
+    /// Tune the flush and compaction thresholds with `tuner`
+    pub(crate) fn with_tuner(self, tuner: AdaptiveTuner) -> Self {
+        Self { tuner, ..self }
+    }
+
+    /// Remember the tokens of recent idempotent writes in `idempotency_window`
+    pub(crate) fn with_idempotency_window(self, idempotency_window: IdempotencyWindow) -> Self {
+        Self {
+            idempotency_window,
+            ..self
+        }
+    }
+
+    /// Run the sub-compactions of major compactions on `subcompactions`
+    pub(crate) fn with_subcompactions(self, subcompactions: SubCompactions) -> Self {
+        Self {
+            subcompactions,
+            ..self
+        }
+    }
+
+    /// Keep the values that removals replaced until `purge_horizon` passed them
+    pub(crate) fn with_purge_horizon(self, purge_horizon: PurgeHorizon) -> Self {
+        Self {
+            purge_horizon,
+            ..self
         }
     }
 
@@ -51,6 +104,34 @@ where
         &self.parquet_lru
     }
 
+    pub(crate) fn negative_cache(&self) -> &NegativeCache<R> {
+        &self.negative_cache
+    }
+
+    pub(crate) fn hot_ranges(&self) -> &HotRanges<R> {
+        &self.hot_ranges
+    }
+
+    pub(crate) fn scan_memory(&self) -> &MemoryBudget {
+        &self.scan_memory
+    }
+
+    pub(crate) fn tuner(&self) -> &AdaptiveTuner {
+        &self.tuner
+    }
+
+    pub(crate) fn idempotency_window(&self) -> &IdempotencyWindow {
+        &self.idempotency_window
+    }
+
+    pub(crate) fn subcompactions(&self) -> &SubCompactions {
+        &self.subcompactions
+    }
+
+    pub(crate) fn purge_horizon(&self) -> &PurgeHorizon {
+        &self.purge_horizon
+    }
+
     pub(crate) fn arrow_schema(&self) -> &Arc<Schema> {
         &self.arrow_schema
     }
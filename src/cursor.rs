@@ -0,0 +1,225 @@
+//! Cursors that move back and forth over the keys of a [`Snapshot`](crate::snapshot::Snapshot)
+
+use std::{ops::Bound, sync::Arc};
+
+use futures_util::StreamExt;
+use parquet::arrow::ProjectionMask;
+
+use crate::{
+    context::Context,
+    fs::pool::ReaderPool,
+    option::Order,
+    record::{KeyRef, Record, Schema},
+    stream::{merge::MergeStream, Entry},
+    version::{timestamp::Timestamp, Version},
+    DbError, DbStorage, Scan,
+};
+
+// Where the next step of a cursor starts
+enum Position<K> {
+    // Nothing was read yet, so steps start at either end
+    Unset,
+    // Set by `seek`
+    Seek(K),
+    // Set by `seek_for_prev`
+    SeekForPrev(K),
+    // The key of the last returned entry
+    At(K),
+}
+
+/// A cursor over the keys of a [`Snapshot`](crate::snapshot::Snapshot), created with
+/// [`Snapshot::cursor`](crate::snapshot::Snapshot::cursor)
+///
+/// [`Cursor::next`] and [`Cursor::prev`] return the entry after and before the last returned
+/// one, skipping removed keys, and [`Cursor::seek`] and [`Cursor::seek_for_prev`] move the cursor
+/// to a key. Steps in the same direction read on from one merged scan. The scan only starts over
+/// when the cursor seeks or turns around, and then takes its SSTable readers from a pool of the
+/// cursor instead of opening the files again.
+pub struct Cursor<'c, R>
+where
+    R: Record,
+{
+    // The stream borrows `bound`, so it is declared, and dropped, first
+    stream: Option<(Order, MergeStream<'c, R>)>,
+    bound: Bound<Box<<R::Schema as Schema>::Key>>,
+    position: Position<<R::Schema as Schema>::Key>,
+    mem_storage: &'c DbStorage<R>,
+    version: &'c Version<R>,
+    ts: Timestamp,
+    ctx: Arc<Context<R>>,
+    readers: ReaderPool,
+}
+
+impl<'c, R> Cursor<'c, R>
+where
+    R: Record + Send,
+{
+    pub(crate) fn new(
+        mem_storage: &'c DbStorage<R>,
+        version: &'c Version<R>,
+        ts: Timestamp,
+        ctx: Arc<Context<R>>,
+    ) -> Self {
+        // one reader per SSTable of the snapshot
+        let tables = version.level_slice.iter().map(Vec::len).sum();
+
+        Self {
+            stream: None,
+            bound: Bound::Unbounded,
+            position: Position::Unset,
+            mem_storage,
+            version,
+            ts,
+            ctx,
+            readers: ReaderPool::new(tables),
+        }
+    }
+
+    /// Move the cursor so that [`Cursor::next`] returns the first entry at or after `key`
+    pub fn seek(&mut self, key: <R::Schema as Schema>::Key) {
+        self.stream = None;
+        self.position = Position::Seek(key);
+    }
+
+    /// Move the cursor so that [`Cursor::prev`] returns the last entry at or before `key`
+    pub fn seek_for_prev(&mut self, key: <R::Schema as Schema>::Key) {
+        self.stream = None;
+        self.position = Position::SeekForPrev(key);
+    }
+
+    /// The entry after the last returned one, or the first entry of the snapshot if none was
+    /// returned yet
+    pub async fn next(&mut self) -> Result<Option<Entry<'c, R>>, DbError> {
+        self.step(Order::Asc).await
+    }
+
+    /// The entry before the last returned one, or the last entry of the snapshot if none was
+    /// returned yet
+    pub async fn prev(&mut self) -> Result<Option<Entry<'c, R>>, DbError> {
+        self.step(Order::Desc).await
+    }
+
+    async fn step(&mut self, order: Order) -> Result<Option<Entry<'c, R>>, DbError> {
+        if !matches!(&self.stream, Some((current, _)) if *current == order) {
+            let bound = match (&self.position, order) {
+                (Position::Unset, _) => Bound::Unbounded,
+                (Position::Seek(key), Order::Asc) | (Position::SeekForPrev(key), Order::Desc) => {
+                    Bound::Included(key.clone())
+                }
+                (Position::Seek(key) | Position::SeekForPrev(key) | Position::At(key), _) => {
+                    Bound::Excluded(key.clone())
+                }
+            };
+            self.open(order, bound).await?;
+        }
+        let Some((_, stream)) = self.stream.as_mut() else {
+            return Ok(None);
+        };
+
+        while let Some(entry) = stream.next().await {
+            let entry = entry?;
+            if entry.value().is_some() {
+                self.position = Position::At(entry.key().value.to_key());
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
+
+    // Start a scan from `bound` in `order`
+    async fn open(
+        &mut self,
+        order: Order,
+        bound: Bound<<R::Schema as Schema>::Key>,
+    ) -> Result<(), DbError> {
+        self.stream = None;
+        self.bound = bound.map(Box::new);
+        // Safety: the key is boxed, so it stays in place until `bound` is replaced, which only
+        // happens after the stream that borrows it is dropped
+        let bound = match &self.bound {
+            Bound::Included(key) => {
+                Bound::Included(unsafe { &*(key.as_ref() as *const <R::Schema as Schema>::Key) })
+            }
+            Bound::Excluded(key) => {
+                Bound::Excluded(unsafe { &*(key.as_ref() as *const <R::Schema as Schema>::Key) })
+            }
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let range = match order {
+            Order::Asc => (bound, Bound::Unbounded),
+            Order::Desc => (Bound::Unbounded, bound),
+        };
+
+        let mut scan = Scan::new(
+            self.mem_storage,
+            range,
+            self.ts,
+            self.version,
+            Box::new(|_: Option<ProjectionMask>, _: Option<Order>| None),
+            self.ctx.clone(),
+        )
+        .readers(self.readers.clone());
+        if order == Order::Desc {
+            scan = scan.reverse();
+        }
+        self.stream = Some((order, scan.merge_stream().await?));
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        Entry, DB,
+    };
+
+    fn key_of(entry: Option<Entry<'_, Test>>) -> Option<String> {
+        entry.map(|entry| entry.key().value.to_string())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn seek_and_step_both_ways() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        let key = |i: u32| format!("{i:02}");
+
+        db.insert_batch((0..10).map(|i| Test {
+            vstring: key(i),
+            vu32: i,
+            vbool: None,
+        }))
+        .await
+        .unwrap();
+        db.flush().await.unwrap();
+        db.remove(key(5)).await.unwrap();
+
+        let snapshot = db.snapshot().await;
+        let mut cursor = snapshot.cursor();
+
+        assert_eq!(key_of(cursor.next().await.unwrap()), Some(key(0)));
+        assert_eq!(key_of(cursor.prev().await.unwrap()), None);
+
+        cursor.seek(key(4));
+        assert_eq!(key_of(cursor.next().await.unwrap()), Some(key(4)));
+        assert_eq!(key_of(cursor.next().await.unwrap()), Some(key(6)));
+        assert_eq!(key_of(cursor.prev().await.unwrap()), Some(key(4)));
+        assert_eq!(key_of(cursor.prev().await.unwrap()), Some(key(3)));
+
+        cursor.seek_for_prev(key(5));
+        assert_eq!(key_of(cursor.prev().await.unwrap()), Some(key(4)));
+        cursor.seek(key(10));
+        assert_eq!(key_of(cursor.next().await.unwrap()), None);
+        assert_eq!(key_of(cursor.prev().await.unwrap()), Some(key(9)));
+    }
+}
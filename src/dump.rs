@@ -0,0 +1,302 @@
+//! Human-readable dumps of the manifest of a [`DB`](crate::DB) for debugging
+//!
+//! [`DB::dump_manifest`](crate::DB::dump_manifest) describes the SSTables of the current version
+//! as JSON, so it can be attached to a bug report, and
+//! [`DB::load_manifest`](crate::DB::load_manifest) opens a fresh DB directory with the tree of
+//! such a dump, pointing at copies of its SSTables, to reproduce the state:
+//!
+//! ```json
+//! {
+//!   "format_version": 1,
+//!   "ts": 42,
+//!   "tables": [
+//!     { "level": 0, "gen": "01J9...", "min": "\"a\"", "max": "\"k\"", "file_size": 4096, "rows": 120, "tombstones": 3, "expires": null, "wal_ids": null }
+//!   ]
+//! }
+//! ```
+//!
+//! The key range of a table is the `Debug` output of its keys, which is only meant to be read by
+//! people. A loaded table takes its key range from its own rows instead.
+
+use std::{ops::Bound, sync::Arc};
+
+use fusio_log::{error::LogError, Options};
+use futures_util::StreamExt;
+use parquet::{arrow::ProjectionMask, errors::ParquetError};
+use parquet_lru::NoCache;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    error::{fusio_kind, parquet_kind, source_kind, ErrorKind},
+    fs::{manager::StoreManager, FileId, FileType},
+    ondisk::sstable::SsTable,
+    option::DbOption,
+    record::{KeyRef, Record, Schema},
+    scope::Scope,
+    version::{edit::VersionEdit, timestamp::Timestamp, TransactionTs, Version, MAX_LEVEL},
+};
+
+pub(crate) const DUMP_FORMAT_VERSION: u32 = 1;
+
+/// The SSTables of a version of a DB, see the [module docs](self)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestDump {
+    pub format_version: u32,
+    /// Timestamp of the latest commit to the DB
+    pub ts: u32,
+    pub tables: Vec<TableDump>,
+}
+
+/// An SSTable of a [`ManifestDump`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableDump {
+    pub level: usize,
+    pub gen: FileId,
+    /// `Debug` output of the smallest key of the table
+    pub min: String,
+    /// `Debug` output of the largest key of the table
+    pub max: String,
+    pub file_size: u64,
+    /// Number of rows, 0 if the table was written before rows were counted
+    #[serde(default)]
+    pub rows: u64,
+    /// Number of rows that remove their key
+    #[serde(default)]
+    pub tombstones: u64,
+    /// Earliest time a record of the table expires at, see [`Scope::expires`]
+    #[serde(default)]
+    pub expires: Option<u64>,
+    /// WALs of the memtable the table was flushed from, if it was
+    pub wal_ids: Option<Vec<FileId>>,
+}
+
+impl ManifestDump {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a manifest dump should serialize")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, DumpError> {
+        let dump: ManifestDump = serde_json::from_str(json)?;
+        if dump.format_version != DUMP_FORMAT_VERSION {
+            return Err(DumpError::UnsupportedVersion(dump.format_version));
+        }
+        Ok(dump)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DumpError {
+    #[error("manifest dump json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("manifest dump format version {0} is not supported")]
+    UnsupportedVersion(u32),
+    #[error("manifest dump fusio error: {0}")]
+    Fusio(#[from] fusio::Error),
+    #[error("manifest dump parquet error: {0}")]
+    Parquet(#[from] ParquetError),
+    #[error("manifest dump log error: {0}")]
+    Logger(#[from] LogError),
+    #[error("level {0} of the manifest dump exceeds the max level {MAX_LEVEL}")]
+    InvalidLevel(usize),
+    #[error("table {gen} at level {level} of the manifest dump is missing")]
+    MissingTable { level: usize, gen: FileId },
+    #[error("table {gen} at level {level} of the manifest dump has no rows")]
+    EmptyTable { level: usize, gen: FileId },
+    #[error("load target already contains a database")]
+    TargetNotEmpty,
+}
+
+impl DumpError {
+    pub(crate) fn kind(&self) -> ErrorKind {
+        match self {
+            DumpError::Fusio(err) => fusio_kind(err),
+            DumpError::Parquet(err) => parquet_kind(err),
+            DumpError::Logger(err) => source_kind(err),
+            DumpError::EmptyTable { .. } => ErrorKind::Corruption,
+            DumpError::Json(_)
+            | DumpError::UnsupportedVersion(_)
+            | DumpError::InvalidLevel(_)
+            | DumpError::MissingTable { .. }
+            | DumpError::TargetNotEmpty => ErrorKind::InvalidArgument,
+        }
+    }
+}
+
+/// Describe the SSTables of `version`
+pub(crate) fn dump<R: Record>(version: &Version<R>) -> ManifestDump {
+    let tables = version
+        .level_slice
+        .iter()
+        .enumerate()
+        .flat_map(|(level, scopes)| {
+            scopes.iter().map(move |scope| TableDump {
+                level,
+                gen: scope.gen,
+                min: format!("{:?}", scope.min),
+                max: format!("{:?}", scope.max),
+                file_size: scope.file_size,
+                rows: scope.rows,
+                tombstones: scope.tombstones,
+                expires: scope.expires,
+                wal_ids: scope.wal_ids.clone(),
+            })
+        })
+        .collect();
+
+    ManifestDump {
+        format_version: DUMP_FORMAT_VERSION,
+        ts: version.load_ts().into(),
+        tables,
+    }
+}
+
+/// Write a version log with the tables of `dump` into the empty DB location of `option`.
+///
+/// The tables are expected at the paths configured for their levels in `option`.
+pub(crate) async fn load<R: Record>(
+    option: &DbOption,
+    manager: &StoreManager,
+    schema: &R::Schema,
+    dump: &ManifestDump,
+) -> Result<(), DumpError> {
+    let base_fs = manager.base_fs();
+    let version_dir = option.version_log_dir_path();
+    base_fs.create_dir_all(&version_dir).await?;
+    if base_fs.list(&version_dir).await?.next().await.is_some() {
+        return Err(DumpError::TargetNotEmpty);
+    }
+
+    let mut edits = Vec::with_capacity(dump.tables.len() + 2);
+    for table in &dump.tables {
+        let (level, gen) = (table.level, table.gen);
+        if level >= MAX_LEVEL {
+            return Err(DumpError::InvalidLevel(level));
+        }
+        let (fs, path) = manager.table(option, gen, level);
+        let file = fs
+            .open_options(&path, FileType::Parquet.open_options(true))
+            .await
+            .map_err(|_| DumpError::MissingTable { level, gen })?;
+
+        // tables are sorted by key, so the first and the last row hold the key range
+        let mut rows = SsTable::<R>::open(Arc::new(NoCache::default()), gen, file)
+            .await?
+            .scan(
+                (Bound::Unbounded, Bound::Unbounded),
+                Timestamp::from(u32::MAX),
+                None,
+                ProjectionMask::all(),
+                None,
+                schema.primary_key_indices(),
+            )
+            .await?;
+        let mut range: Option<(_, _)> = None;
+        while let Some(row) = rows.next().await {
+            let key = row?.key().to_key();
+            range = Some(match range {
+                Some((min, _)) => (min, key),
+                None => (key.clone(), key),
+            });
+        }
+        let (min, max) = range.ok_or(DumpError::EmptyTable { level, gen })?;
+
+        edits.push(VersionEdit::Add {
+            level: level as u8,
+            scope: Scope {
+                min,
+                max,
+                gen,
+                wal_ids: table.wal_ids.clone(),
+                file_size: table.file_size,
+                rows: table.rows,
+                tombstones: table.tombstones,
+                expires: table.expires,
+            },
+        });
+    }
+    edits.push(VersionEdit::LatestTimeStamp {
+        ts: Timestamp::from(dump.ts),
+    });
+    edits.push(VersionEdit::NewLogLength { len: 0 });
+
+    let mut log = Options::new(option.version_log_path(option.generate_file_id()))
+        .build_with_fs::<VersionEdit<<R::Schema as Schema>::Key>>(base_fs.clone())
+        .await?;
+    log.write_batch(edits.iter()).await?;
+    log.close().await?;
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::ManifestDump;
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dump_and_load() {
+        let source_dir = TempDir::new().unwrap();
+        let target_dir = TempDir::new().unwrap();
+        let key = |i: u32| format!("{i:03}");
+
+        let source: DB<Test, TokioExecutor> = DB::new(
+            DbOption::new(
+                Path::from_filesystem_path(source_dir.path()).unwrap(),
+                &TestSchema,
+            ),
+            TokioExecutor::default(),
+            TestSchema,
+        )
+        .await
+        .unwrap();
+        for batch in 0..2 {
+            source
+                .insert_batch((batch * 10..batch * 10 + 10).map(|i| Test {
+                    vstring: key(i),
+                    vu32: i,
+                    vbool: None,
+                }))
+                .await
+                .unwrap();
+            source.flush().await.unwrap();
+        }
+
+        let json = source.dump_manifest().await.to_json();
+        let dump = ManifestDump::from_json(&json).unwrap();
+        assert!(!dump.tables.is_empty());
+        assert!(dump
+            .tables
+            .iter()
+            .any(|table| table.min == format!("{:?}", key(0))));
+
+        // the SSTables are copied as they would be from a bug report
+        for table in &dump.tables {
+            let name = format!("{}.parquet", table.gen);
+            std::fs::copy(source_dir.path().join(&name), target_dir.path().join(&name)).unwrap();
+        }
+        let target: DB<Test, TokioExecutor> = DB::load_manifest(
+            DbOption::new(
+                Path::from_filesystem_path(target_dir.path()).unwrap(),
+                &TestSchema,
+            ),
+            TokioExecutor::default(),
+            TestSchema,
+            &dump,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(target.dump_manifest().await, dump);
+        for i in 0..20 {
+            let vu32 = target.get(&key(i), |entry| entry.get().vu32).await.unwrap();
+            assert_eq!(vu32, Some(i));
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Type-erased handle of a [`DB`] of [`DynRecord`]s
+//!
+//! [`DynDB`] is object safe, so DBs of different schemas can be kept side by side, e.g. as
+//! `HashMap<String, Arc<dyn DynDB>>`, without carrying the executor type around.
+
+use std::{
+    ops::Bound,
+    pin::{pin, Pin},
+    sync::Arc,
+};
+
+use arrow::datatypes::Schema as ArrowSchema;
+use async_stream::stream;
+use async_trait::async_trait;
+use fusio::{MaybeSend, MaybeSync};
+use futures_core::Stream;
+use futures_util::StreamExt;
+
+use crate::{
+    executor::Executor,
+    record::{DynRecord, Value},
+    transaction::{CommitError, TransactionEntry},
+    DB,
+};
+
+/// Records of a [`DynDB::scan`], in key order
+#[cfg(not(target_arch = "wasm32"))]
+pub type DynRecordStream<'scan> =
+    Pin<Box<dyn Stream<Item = Result<DynRecord, CommitError<DynRecord>>> + Send + 'scan>>;
+/// Records of a [`DynDB::scan`], in key order
+#[cfg(target_arch = "wasm32")]
+pub type DynRecordStream<'scan> =
+    Pin<Box<dyn Stream<Item = Result<DynRecord, CommitError<DynRecord>>> + 'scan>>;
+
+/// Object safe subset of the [`DB`] API over [`DynRecord`]s
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait DynDB: MaybeSend + MaybeSync {
+    /// Arrow schema of the records, including the internal `_null` and `_ts` columns
+    fn arrow_schema(&self) -> &Arc<ArrowSchema>;
+
+    /// See [`DB::insert`]
+    async fn insert(&self, record: DynRecord) -> Result<(), CommitError<DynRecord>>;
+
+    /// See [`DB::insert_batch`]
+    async fn insert_batch(&self, records: Vec<DynRecord>) -> Result<(), CommitError<DynRecord>>;
+
+    /// See [`DB::remove`]
+    async fn remove(&self, key: Value) -> Result<(), CommitError<DynRecord>>;
+
+    /// Get the record with `key` as the primary key, see [`DB::get`]
+    async fn get(&self, key: &Value) -> Result<Option<DynRecord>, CommitError<DynRecord>>;
+
+    /// Scan the records with primary keys in `range`, see [`DB::scan`]
+    fn scan<'scan>(&'scan self, range: (Bound<Value>, Bound<Value>)) -> DynRecordStream<'scan>;
+
+    /// See [`DB::flush`]
+    async fn flush(&self) -> Result<(), CommitError<DynRecord>>;
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<E> DynDB for DB<DynRecord, E>
+where
+    E: Executor + Send + Sync + 'static,
+{
+    fn arrow_schema(&self) -> &Arc<ArrowSchema> {
+        self.ctx.arrow_schema()
+    }
+
+    async fn insert(&self, record: DynRecord) -> Result<(), CommitError<DynRecord>> {
+        DB::insert(self, record).await
+    }
+
+    async fn insert_batch(&self, records: Vec<DynRecord>) -> Result<(), CommitError<DynRecord>> {
+        DB::insert_batch(self, records.into_iter()).await
+    }
+
+    async fn remove(&self, key: Value) -> Result<(), CommitError<DynRecord>> {
+        DB::remove(self, key).await.map(|_| ())
+    }
+
+    async fn get(&self, key: &Value) -> Result<Option<DynRecord>, CommitError<DynRecord>> {
+        DB::get(self, key, |entry| Some(entry.get().to_record())).await
+    }
+
+    fn scan<'scan>(&'scan self, range: (Bound<Value>, Bound<Value>)) -> DynRecordStream<'scan> {
+        Box::pin(stream! {
+            let records = DB::scan(
+                self,
+                (range.0.as_ref(), range.1.as_ref()),
+                |entry| match entry {
+                    TransactionEntry::Stream(entry) => entry.value().map(|record| record.to_record()),
+                    TransactionEntry::Local(record) => Some(record.to_record()),
+                },
+            )
+            .await;
+            let mut records = pin!(records);
+
+            while let Some(record) = records.next().await {
+                match record {
+                    Ok(Some(record)) => yield Ok(record),
+                    // removed
+                    Ok(None) => (),
+                    Err(err) => yield Err(err),
+                }
+            }
+        })
+    }
+
+    async fn flush(&self) -> Result<(), CommitError<DynRecord>> {
+        DB::flush(self).await
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{collections::HashMap, ops::Bound, sync::Arc};
+
+    use fusio::path::Path;
+    use futures_util::StreamExt;
+    use tempfile::TempDir;
+
+    use super::DynDB;
+    use crate::{
+        dyn_schema,
+        executor::tokio::TokioExecutor,
+        record::{
+            dynamic::test::{test_dyn_item_schema, test_dyn_items},
+            DynRecord, Record, Value, ValueRef,
+        },
+        DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn heterogeneous_tables() {
+        let temp_dir = TempDir::new().unwrap();
+        let items_dir = temp_dir.path().join("items");
+        let names_dir = temp_dir.path().join("names");
+        std::fs::create_dir_all(&items_dir).unwrap();
+        std::fs::create_dir_all(&names_dir).unwrap();
+
+        let items_schema = test_dyn_item_schema();
+        let items: DB<DynRecord, TokioExecutor> = DB::new(
+            DbOption::new(
+                Path::from_filesystem_path(&items_dir).unwrap(),
+                &items_schema,
+            ),
+            TokioExecutor::default(),
+            items_schema,
+        )
+        .await
+        .unwrap();
+        let names_schema = dyn_schema!(("name", Utf8, false), ("id", UInt32, true), 0);
+        let names: DB<DynRecord, TokioExecutor> = DB::new(
+            DbOption::new(
+                Path::from_filesystem_path(&names_dir).unwrap(),
+                &names_schema,
+            ),
+            TokioExecutor::default(),
+            names_schema,
+        )
+        .await
+        .unwrap();
+
+        let mut tables: HashMap<String, Arc<dyn DynDB>> = HashMap::new();
+        tables.insert("items".into(), Arc::new(items));
+        tables.insert("names".into(), Arc::new(names));
+
+        tables["items"]
+            .insert_batch(test_dyn_items().into_iter().take(10).collect())
+            .await
+            .unwrap();
+        tables["items"].remove(Value::Int64(3)).await.unwrap();
+        tables["names"]
+            .insert(DynRecord::new(
+                vec![Value::String("tonbo".into()), Value::UInt32(1)],
+                0,
+            ))
+            .await
+            .unwrap();
+
+        let record = tables["names"]
+            .get(&Value::String("tonbo".into()))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.as_record_ref().columns[1], ValueRef::UInt32(1));
+        assert!(tables["items"]
+            .get(&Value::Int64(3))
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(tables["names"].arrow_schema().fields().len(), 4);
+
+        let keys = tables["items"]
+            .scan((
+                Bound::Included(Value::Int64(2)),
+                Bound::Excluded(Value::Int64(6)),
+            ))
+            .map(|record| record.unwrap().as_record_ref().columns[0].to_owned())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            keys,
+            vec![Value::Int64(2), Value::Int64(4), Value::Int64(5)]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Categories of the errors of a [`DB`](crate::DB)
+//!
+//! [`DbError`](crate::DbError), [`CommitError`](crate::transaction::CommitError) and
+//! [`CompactionError`](crate::compaction::error::CompactionError) have a
+//! [`kind`](crate::DbError::kind), so applications can react to an error, e.g. retry it, without
+//! matching its variants or its message.
+
+use std::{error::Error, io};
+
+use arrow::error::ArrowError;
+use parquet::errors::ParquetError;
+
+use crate::stream::memory::ScanMemoryExceeded;
+
+/// Category of an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// Stored data, e.g. an SSTable, a WAL or the manifest, could not be decoded
+    Corruption,
+    /// The storage failed and trying again is not expected to help
+    Io,
+    /// The storage failed in a way that may pass, e.g. a timeout or a dropped connection
+    StorageTransient,
+    /// The request or the input it carries is invalid
+    InvalidArgument,
+    /// The DB could not take the request right now
+    Busy,
+    /// A transaction conflicted with another one that committed first
+    Conflict,
+}
+
+impl ErrorKind {
+    /// Whether the failed operation may succeed if it is tried again. A conflicting transaction
+    /// has to be started again, as it read outdated data.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ErrorKind::StorageTransient | ErrorKind::Busy | ErrorKind::Conflict
+        )
+    }
+}
+
+pub(crate) fn io_kind(err: &io::Error) -> ErrorKind {
+    match err.kind() {
+        io::ErrorKind::TimedOut
+        | io::ErrorKind::Interrupted
+        | io::ErrorKind::WouldBlock
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::ConnectionRefused
+        | io::ErrorKind::NotConnected
+        | io::ErrorKind::BrokenPipe => ErrorKind::StorageTransient,
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => ErrorKind::Corruption,
+        io::ErrorKind::InvalidInput => ErrorKind::InvalidArgument,
+        _ => ErrorKind::Io,
+    }
+}
+
+pub(crate) fn fusio_kind(err: &fusio::Error) -> ErrorKind {
+    match err {
+        fusio::Error::Io(err) => io_kind(err),
+        err => source_kind(err),
+    }
+}
+
+pub(crate) fn parquet_kind(err: &ParquetError) -> ErrorKind {
+    match err {
+        ParquetError::External(err) => source_kind(err.as_ref()),
+        ParquetError::NYI(_) => ErrorKind::InvalidArgument,
+        // the file could not be decoded
+        _ => ErrorKind::Corruption,
+    }
+}
+
+pub(crate) fn arrow_kind(err: &ArrowError) -> ErrorKind {
+    match err {
+        ArrowError::IoError(_, err) => io_kind(err),
+        ArrowError::ExternalError(err) => source_kind(err.as_ref()),
+        ArrowError::ParquetError(_) => ErrorKind::Corruption,
+        _ => ErrorKind::InvalidArgument,
+    }
+}
+
+/// Kind of the first error in the chain of `err` that tells its kind, or [`ErrorKind::Io`]
+pub(crate) fn source_kind(err: &(dyn Error + 'static)) -> ErrorKind {
+    let mut source = Some(err);
+
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            return io_kind(err);
+        }
+        if let Some(fusio::Error::Io(err)) = err.downcast_ref::<fusio::Error>() {
+            return io_kind(err);
+        }
+        if let Some(err) = err.downcast_ref::<ParquetError>() {
+            return parquet_kind(err);
+        }
+        if err.is::<ScanMemoryExceeded>() {
+            return ErrorKind::Busy;
+        }
+        source = err.source();
+    }
+    ErrorKind::Io
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use parquet::errors::ParquetError;
+
+    use super::{parquet_kind, ErrorKind};
+
+    #[test]
+    fn kind_of_wrapped_errors() {
+        let timeout = io::Error::new(io::ErrorKind::TimedOut, "timeout");
+        let err = ParquetError::External(Box::new(fusio::Error::Io(timeout)));
+        assert_eq!(parquet_kind(&err), ErrorKind::StorageTransient);
+        assert!(parquet_kind(&err).is_retryable());
+
+        let err = ParquetError::EOF("footer".into());
+        assert_eq!(parquet_kind(&err), ErrorKind::Corruption);
+        assert!(!parquet_kind(&err).is_retryable());
+    }
+}
@@ -0,0 +1,400 @@
+//! Index of the keys of a [`DB`](crate::DB) by the time their records expire, see
+//! [`DbOption::expires_at`](crate::DbOption::expires_at)
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    mem,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{
+    fs::FileId,
+    record::{Record, Schema},
+    scope::Scope,
+    version::Version,
+    DbOption,
+};
+
+/// Milliseconds since the UNIX epoch at which a record expires, if it does
+pub(crate) type ExpiresAt<R> =
+    Arc<dyn for<'r> Fn(<R as Record>::Ref<'r>) -> Option<u64> + Send + Sync>;
+
+type Key<R> = <<R as Record>::Schema as Schema>::Key;
+
+/// Records that expired as of a point in time, see
+/// [`DbOption::drop_expired`](crate::DbOption::drop_expired)
+pub(crate) struct Expired<R>
+where
+    R: Record,
+{
+    expires_at: ExpiresAt<R>,
+    now: u64,
+}
+
+impl<R> Expired<R>
+where
+    R: Record,
+{
+    pub(crate) fn new(expires_at: ExpiresAt<R>, now: u64) -> Self {
+        Self { expires_at, now }
+    }
+
+    /// Whether `record` expired at or before the time of `self`
+    pub(crate) fn contains(&self, record: R::Ref<'_>) -> bool {
+        (self.expires_at)(record).is_some_and(|at| at <= self.now)
+    }
+}
+
+impl<R> Clone for Expired<R>
+where
+    R: Record,
+{
+    fn clone(&self) -> Self {
+        Self {
+            expires_at: self.expires_at.clone(),
+            now: self.now,
+        }
+    }
+}
+
+/// Earliest time the records written to a table expire at, see [`Scope::expires`]
+pub(crate) struct TableExpiry<R>
+where
+    R: Record,
+{
+    expires_at: Option<ExpiresAt<R>>,
+    earliest: u64,
+}
+
+impl<R> TableExpiry<R>
+where
+    R: Record,
+{
+    pub(crate) fn new(option: &DbOption) -> Self {
+        Self {
+            expires_at: option
+                .expiry
+                .as_ref()
+                .and_then(|expiry| expiry.expires_at::<R>().ok()),
+            earliest: u64::MAX,
+        }
+    }
+
+    pub(crate) fn update(&mut self, record: Option<R::Ref<'_>>) {
+        if let (Some(expires_at), Some(record)) = (&self.expires_at, record) {
+            if let Some(at) = expires_at(record) {
+                self.earliest = self.earliest.min(at);
+            }
+        }
+    }
+
+    /// The earliest time of the records since the last call, `None` without
+    /// [`DbOption::expires_at`]
+    pub(crate) fn take(&mut self) -> Option<u64> {
+        self.expires_at
+            .as_ref()
+            .map(|_| mem::replace(&mut self.earliest, u64::MAX))
+    }
+}
+
+/// The keys of the memtables whose latest record expires, ordered by when it does, and the
+/// SSTables that may hold expired records
+///
+/// Every write and removal of the memtables updates the index, so a key is indexed at the time
+/// its latest record expires and an overwritten record never makes a key expire early. A flushed
+/// key leaves the index, and the [`Scope::expires`] of the table it was flushed to stands in for
+/// it, so the index only holds the keys of the memtables and nothing is scanned when the DB is
+/// opened.
+pub(crate) struct ExpiryIndex<R>
+where
+    R: Record,
+{
+    expires_at: ExpiresAt<R>,
+    entries: Mutex<Entries<Key<R>>>,
+}
+
+struct Entries<K> {
+    by_time: BTreeSet<(u64, K)>,
+    by_key: HashMap<K, u64>,
+    // earliest time a live record of each table expires at as of its last check, in place of the
+    // `Scope::expires` of the table, which also counts the records that were removed since
+    tables: HashMap<FileId, u64>,
+}
+
+impl<R> ExpiryIndex<R>
+where
+    R: Record,
+{
+    pub(crate) fn new(expires_at: ExpiresAt<R>) -> Self {
+        Self {
+            expires_at,
+            entries: Mutex::new(Entries {
+                by_time: BTreeSet::new(),
+                by_key: HashMap::new(),
+                tables: HashMap::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn expires_at(&self) -> &ExpiresAt<R> {
+        &self.expires_at
+    }
+
+    /// Index `key` at the expiry of `record`, or remove it from the index if the key was removed
+    /// or its record does not expire
+    pub(crate) fn update(&self, key: &Key<R>, record: Option<R::Ref<'_>>) {
+        let at = record.and_then(|record| (self.expires_at)(record));
+        let mut entries = self.lock();
+
+        let previous = match at {
+            Some(at) => entries.by_key.insert(key.clone(), at),
+            None => entries.by_key.remove(key),
+        };
+        if let Some(previous) = previous {
+            entries.by_time.remove(&(previous, key.clone()));
+        }
+        if let Some(at) = at {
+            entries.by_time.insert((at, key.clone()));
+        }
+    }
+
+    /// Remove `key` from the index, unless it was indexed at another time since
+    pub(crate) fn forget(&self, key: &Key<R>, at: u64) {
+        let mut entries = self.lock();
+
+        if entries.by_key.get(key) == Some(&at) {
+            entries.by_key.remove(key);
+            entries.by_time.remove(&(at, key.clone()));
+        }
+    }
+
+    /// Remove `key` from the index once the memtables do not hold it anymore
+    pub(crate) fn flushed(&self, key: &Key<R>) {
+        let mut entries = self.lock();
+
+        if let Some(at) = entries.by_key.remove(key) {
+            entries.by_time.remove(&(at, key.clone()));
+        }
+    }
+
+    /// Record that the live records of the table `gen` expire at `at` or later, see
+    /// [`ExpiryIndex::expired_tables`]
+    pub(crate) fn checked(&self, gen: FileId, at: u64) {
+        self.lock().tables.insert(gen, at);
+    }
+
+    /// The tables of `version` that may hold records that expire at or before `now`
+    ///
+    /// A table written without [`DbOption::expires_at`] may hold any, until it was checked.
+    pub(crate) fn expired_tables(&self, version: &Version<R>, now: u64) -> Vec<Scope<Key<R>>> {
+        let mut entries = self.lock();
+        let scopes = version.level_slice.iter().flatten();
+
+        // the checks of compacted tables are of no use anymore
+        let gens = scopes
+            .clone()
+            .map(|scope| scope.gen)
+            .collect::<HashSet<_>>();
+        entries.tables.retain(|gen, _| gens.contains(gen));
+        scopes
+            .filter(|scope| Self::table_expires(&entries, scope) <= now)
+            .cloned()
+            .collect()
+    }
+
+    /// The keys that expire at or before `now`, with the time they expire at
+    pub(crate) fn expired(&self, now: u64) -> Vec<(u64, Key<R>)> {
+        self.lock()
+            .by_time
+            .iter()
+            .take_while(|(at, _)| *at <= now)
+            .cloned()
+            .collect()
+    }
+
+    /// The earliest time a key of the memtables or a record of the tables of `version` expires
+    /// at
+    pub(crate) fn next(&self, version: &Version<R>) -> Option<u64> {
+        let entries = self.lock();
+
+        version
+            .level_slice
+            .iter()
+            .flatten()
+            .map(|scope| Self::table_expires(&entries, scope))
+            .chain(entries.by_time.first().map(|(at, _)| *at))
+            .min()
+    }
+
+    fn table_expires(entries: &Entries<Key<R>>, scope: &Scope<Key<R>>) -> u64 {
+        entries
+            .tables
+            .get(&scope.gen)
+            .copied()
+            .or(scope.expires)
+            .unwrap_or(0)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries<Key<R>>> {
+        self.entries
+            .lock()
+            .expect("expiry index lock should not fail")
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{ops::Bound, time::Duration};
+
+    use fusio::path::Path;
+    use futures_util::StreamExt;
+    use tempfile::TempDir;
+
+    use crate::{
+        clock::ManualClock,
+        executor::tokio::TokioExecutor,
+        inmem::immutable::tests::TestSchema,
+        tests::{Test, TestRef},
+        DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn expired_records_are_removed() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        // records with an even `vu32` have expired, the others never do
+        .expires_at::<Test, _>(|record: TestRef<'_>| {
+            record
+                .vu32
+                .map(|vu32| if vu32 % 2 == 0 { 0 } else { u64::MAX })
+        });
+        let key = |i: u32| format!("{i:02}");
+        {
+            let db: DB<Test, TokioExecutor> =
+                DB::new(option.clone(), TokioExecutor::default(), TestSchema)
+                    .await
+                    .unwrap();
+
+            db.insert_batch((0..10).map(|i| Test {
+                vstring: key(i),
+                vu32: i,
+                vbool: None,
+            }))
+            .await
+            .unwrap();
+            db.flush().await.unwrap();
+            // an overwritten record expires as the new one does
+            db.insert(Test {
+                vstring: key(0),
+                vu32: 1,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+            assert_eq!(db.next_expiry().await, Some(0));
+            db.flush_wal().await.unwrap();
+        }
+
+        // the table stores when its earliest record expires, the write ahead log is indexed again
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        assert_eq!(
+            db.current_manifest().await.level_slice[0][0].expires,
+            Some(0)
+        );
+        assert_eq!(db.next_expiry().await, Some(0));
+        assert_eq!(db.remove_expired().await.unwrap(), 4);
+        assert_eq!(db.next_expiry().await, Some(u64::MAX));
+
+        for i in 0..10 {
+            let exists = db.get(&key(i), |_| Some(())).await.unwrap().is_some();
+            assert_eq!(exists, i == 0 || i % 2 == 1, "key {i}");
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn expired_records_are_hidden() {
+        let temp_dir = TempDir::new().unwrap();
+        let clock = ManualClock::new(0);
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .time_source(clock.clone())
+        // `vu32` is the time the record expires at
+        .expires_at::<Test, _>(|record: TestRef<'_>| record.vu32.map(u64::from))
+        .drop_expired(true);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+
+        for i in 1..=4u32 {
+            db.insert(Test {
+                vstring: i.to_string(),
+                vu32: i * 1_000,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        db.flush().await.unwrap();
+        // a record in the memtable expires like the flushed ones
+        db.insert(Test {
+            vstring: "5".to_string(),
+            vu32: 1_000,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+
+        clock.advance(Duration::from_millis(2_000));
+        for key in ["1", "2", "5"] {
+            assert!(db
+                .get(&key.to_string(), |_| Some(()))
+                .await
+                .unwrap()
+                .is_none());
+        }
+        assert!(db
+            .get(&"3".to_string(), |_| Some(()))
+            .await
+            .unwrap()
+            .is_some());
+        let keys = db
+            .scan((Bound::Unbounded, Bound::Unbounded), |entry| {
+                entry.get().vstring.to_string()
+            })
+            .await
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(keys, vec!["3".to_string(), "4".to_string()]);
+        // the projection leaves out the column the records expire by
+        let txn = db.transaction().await;
+        let entries = txn
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .projection(&["vbool"])
+            .take()
+            .await
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (entry.key().value.to_string(), entry.value().unwrap().vu32)
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            entries,
+            vec![("3".to_string(), None), ("4".to_string(), None)]
+        );
+        drop(txn);
+
+        // hidden keys are forgotten instead of removed again
+        assert_eq!(db.remove_expired().await.unwrap(), 0);
+        assert_eq!(db.next_expiry().await, Some(3_000));
+    }
+}
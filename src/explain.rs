@@ -0,0 +1,139 @@
+//! Plans of scans, as reported by [`Scan::explain`](crate::Scan::explain)
+
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    ops::Bound,
+};
+
+use crate::{
+    fs::FileId,
+    option::{Order, ReadHint},
+    version::timestamp::Timestamp,
+};
+
+/// What a scan would read, worked out from the in-memory metadata without reading any data
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanPlan<K> {
+    /// Key range of the scan
+    pub range: (Bound<K>, Bound<K>),
+    /// Timestamp of the snapshot the scan reads
+    pub ts: Timestamp,
+    pub order: Order,
+    pub limit: Option<usize>,
+    /// Number of entries skipped before the first one is returned
+    pub offset: usize,
+    pub read_hint: Option<ReadHint>,
+    /// Names of the columns that are read, including the primary key columns
+    pub projection: Vec<String>,
+    /// Predicates that filter the rows of every SSTable that is read
+    pub row_filters: Vec<String>,
+    /// Predicate on the merged records, see [`Scan::filter`](crate::Scan::filter)
+    pub filter: Option<String>,
+    /// Immutable memtables, newest first. The mutable memtable is always scanned.
+    pub immutables: Vec<ImmutablePlan>,
+    /// SSTables of every level that holds any
+    pub levels: Vec<LevelPlan<K>>,
+}
+
+/// An immutable memtable and whether a scan reads it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImmutablePlan {
+    /// WAL the memtable was written to, if any
+    pub wal_id: Option<FileId>,
+    /// Number of entries in the memtable
+    pub len: usize,
+    /// Whether any key of the memtable is in the range of the scan
+    pub overlaps: bool,
+}
+
+/// SSTables of a level
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelPlan<K> {
+    pub level: usize,
+    pub tables: Vec<TablePlan<K>>,
+}
+
+/// An SSTable and whether a scan reads it
+#[derive(Debug, Clone, PartialEq)]
+pub struct TablePlan<K> {
+    pub gen: FileId,
+    pub min: K,
+    pub max: K,
+    /// Approximate file size in bytes
+    pub file_size: u64,
+    /// Whether the key range of the table misses the range of the scan, so it is not opened
+    pub pruned: bool,
+}
+
+impl<K> ScanPlan<K> {
+    /// SSTables the scan opens
+    pub fn tables_read(&self) -> usize {
+        self.levels
+            .iter()
+            .flat_map(|level| &level.tables)
+            .filter(|table| !table.pruned)
+            .count()
+    }
+
+    /// SSTables the scan skips by their key range
+    pub fn tables_pruned(&self) -> usize {
+        self.levels
+            .iter()
+            .flat_map(|level| &level.tables)
+            .filter(|table| table.pruned)
+            .count()
+    }
+}
+
+impl<K: Debug> Display for ScanPlan<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Scan range={:?}..{:?} ts={:?} order={:?} limit={:?} offset={} read_hint={:?}",
+            self.range.0,
+            self.range.1,
+            self.ts,
+            self.order,
+            self.limit,
+            self.offset,
+            self.read_hint
+        )?;
+        writeln!(f, "  projection: {}", self.projection.join(", "))?;
+        writeln!(f, "  row filters: {}", self.row_filters.join(" AND "))?;
+        if let Some(filter) = &self.filter {
+            writeln!(f, "  filter: {filter}")?;
+        }
+        writeln!(f, "  mutable memtable")?;
+        for immutable in &self.immutables {
+            writeln!(
+                f,
+                "  immutable memtable wal={:?} entries={}{}",
+                immutable.wal_id,
+                immutable.len,
+                if immutable.overlaps { "" } else { " (pruned)" }
+            )?;
+        }
+        for level in &self.levels {
+            let read = level.tables.iter().filter(|table| !table.pruned).count();
+            writeln!(
+                f,
+                "  level {}: {} of {} tables",
+                level.level,
+                read,
+                level.tables.len()
+            )?;
+            for table in &level.tables {
+                writeln!(
+                    f,
+                    "    {} [{:?}, {:?}] {} bytes{}",
+                    table.gen,
+                    table.min,
+                    table.max,
+                    table.file_size,
+                    if table.pruned { " (pruned)" } else { "" }
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
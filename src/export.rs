@@ -0,0 +1,266 @@
+//! Exports of a snapshot of a [`DB`](crate::DB) as a plain Parquet dataset
+//!
+//! [`DB::export_snapshot`](crate::DB::export_snapshot) writes the latest record of every key as
+//! of a snapshot, without removed keys and without the internal `_null` and `_ts` columns, to
+//! Parquet files that engines like Spark or DuckDB read as a single dataset:
+//!
+//! ```text
+//! path/part-00000.parquet
+//! path/part-00001.parquet
+//! path/_manifest.json
+//! ```
+//!
+//! The files hold consecutive key ranges in key order, each of about
+//! [`DbOption::max_sst_file_size`](crate::DbOption::max_sst_file_size) bytes. The manifest is
+//! written after all of them, so an export without one is incomplete:
+//!
+//! ```json
+//! {
+//!   "format_version": 1,
+//!   "ts": 42,
+//!   "rows": 1000,
+//!   "files": [
+//!     { "path": "part-00000.parquet", "rows": 1000, "min": "a", "max": "k" }
+//!   ]
+//! }
+//! ```
+//!
+//! The key range of a file holds the primary key columns of its first and its last row as Arrow
+//! displays them, separated by `, `.
+
+use std::{pin::pin, sync::Arc};
+
+use arrow::{array::RecordBatch, error::ArrowError, util::display::array_value_to_string};
+use fusio::{fs::OpenOptions, path::Path, DynFs, Write};
+use fusio_parquet::writer::AsyncWriter;
+use futures_util::StreamExt;
+use parquet::{arrow::AsyncArrowWriter, basic::Compression, file::properties::WriterProperties};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fs::FileType, magic::USER_COLUMN_OFFSET, record::Record, version::timestamp::Timestamp,
+    DbError, Scan,
+};
+
+pub(crate) const EXPORT_FORMAT_VERSION: u32 = 1;
+/// Name of the manifest of an export in its directory
+pub const EXPORT_MANIFEST: &str = "_manifest.json";
+// Rows of the record batches the files are written in
+const EXPORT_BATCH_SIZE: usize = 8192;
+
+/// The files of an export, see the [module docs](self)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportManifest {
+    pub format_version: u32,
+    /// Timestamp of the snapshot the export was taken from
+    pub ts: u32,
+    /// Number of rows of all files
+    pub rows: u64,
+    pub files: Vec<ExportedFile>,
+}
+
+/// A Parquet file of an [`ExportManifest`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedFile {
+    /// Path of the file, relative to the directory of the export
+    pub path: String,
+    pub rows: u64,
+    /// Primary key of the first row of the file
+    pub min: String,
+    /// Primary key of the last row of the file
+    pub max: String,
+}
+
+impl ExportManifest {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("an export manifest should serialize")
+    }
+}
+
+/// Write the records of `scan`, a full scan of a snapshot at `ts`, into files of about
+/// `max_file_size` bytes at `path` on `fs`
+pub(crate) async fn export<R: Record + Send>(
+    scan: Scan<'_, '_, R>,
+    ts: Timestamp,
+    primary_key_indices: &[usize],
+    fs: &dyn DynFs,
+    path: &Path,
+    max_file_size: usize,
+) -> Result<ExportManifest, DbError> {
+    let (schema, user_indices) = scan.user_projection()?;
+    let schema = Arc::new(schema);
+    let key_indices = primary_key_indices
+        .iter()
+        .map(|index| index - USER_COLUMN_OFFSET)
+        .collect::<Vec<_>>();
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    fs.create_dir_all(path).await?;
+
+    let mut batches = pin!(scan.package(EXPORT_BATCH_SIZE).await?);
+    let mut files = Vec::new();
+    let mut writer = None;
+    while let Some(columns) = batches.next().await {
+        let batch = columns?.as_record_batch().project(&user_indices)?;
+        if batch.num_rows() == 0 {
+            continue;
+        }
+        let (file_writer, file) = match &mut writer {
+            Some(writer) => writer,
+            None => {
+                let name = format!("part-{:05}.parquet", files.len());
+                let file_writer = AsyncArrowWriter::try_new(
+                    AsyncWriter::new(
+                        fs.open_options(
+                            &path.child(name.as_str()),
+                            FileType::Parquet.open_options(false),
+                        )
+                        .await?,
+                    ),
+                    schema.clone(),
+                    Some(properties.clone()),
+                )?;
+                let file = ExportedFile {
+                    path: name,
+                    rows: 0,
+                    min: key_string(&batch, &key_indices, 0)?,
+                    max: String::new(),
+                };
+                writer.insert((file_writer, file))
+            }
+        };
+        file_writer.write(&batch).await?;
+        file.rows += batch.num_rows() as u64;
+        file.max = key_string(&batch, &key_indices, batch.num_rows() - 1)?;
+
+        if file_writer.bytes_written() + file_writer.in_progress_size() >= max_file_size {
+            let (file_writer, file) = writer.take().expect("the file was just written");
+            file_writer.close().await?;
+            files.push(file);
+        }
+    }
+    if let Some((file_writer, file)) = writer {
+        file_writer.close().await?;
+        files.push(file);
+    }
+
+    let manifest = ExportManifest {
+        format_version: EXPORT_FORMAT_VERSION,
+        ts: ts.into(),
+        rows: files.iter().map(|file| file.rows).sum(),
+        files,
+    };
+    let mut file = fs
+        .open_options(
+            &path.child(EXPORT_MANIFEST),
+            OpenOptions::default()
+                .create(true)
+                .write(true)
+                .truncate(true),
+        )
+        .await?;
+    let (result, _) = file.write_all(manifest.to_json().into_bytes()).await;
+    result?;
+    file.close().await?;
+
+    Ok(manifest)
+}
+
+// The primary key columns of the row at `row`, as Arrow displays them
+fn key_string(
+    batch: &RecordBatch,
+    key_indices: &[usize],
+    row: usize,
+) -> Result<String, ArrowError> {
+    Ok(key_indices
+        .iter()
+        .map(|index| array_value_to_string(batch.column(*index), row))
+        .collect::<Result<Vec<_>, _>>()?
+        .join(", "))
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{fs::File, sync::Arc};
+
+    use fusio::{disk::TokioFs, path::Path, DynFs};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use tempfile::TempDir;
+
+    use super::{ExportManifest, EXPORT_MANIFEST};
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema,
+        record::test::test_items, tests::Test, DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn export_snapshot() {
+        let temp_dir = TempDir::new().unwrap();
+        let db: DB<Test, TokioExecutor> = DB::new(
+            DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &TestSchema,
+            ),
+            TokioExecutor::default(),
+            TestSchema,
+        )
+        .await
+        .unwrap();
+        for item in test_items(0u32..10) {
+            db.insert(item).await.unwrap();
+        }
+        db.flush().await.unwrap();
+        // the memtable holds the latest writes of some keys
+        db.remove("5".to_string()).await.unwrap();
+        db.insert(Test {
+            vstring: "3".to_string(),
+            vu32: 33,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+
+        let export_dir = TempDir::new().unwrap();
+        let fs: Arc<dyn DynFs> = Arc::new(TokioFs);
+        let manifest = db
+            .export_snapshot(fs, &Path::from_filesystem_path(export_dir.path()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(manifest.rows, 9);
+        assert_eq!(manifest.files.len(), 1);
+        assert_eq!(manifest.files[0].min, "0");
+        assert_eq!(manifest.files[0].max, "9");
+
+        let json = std::fs::read_to_string(export_dir.path().join(EXPORT_MANIFEST)).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ExportManifest>(&json).unwrap(),
+            manifest
+        );
+
+        let file = File::open(export_dir.path().join(&manifest.files[0].path)).unwrap();
+        let batches = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let schema = batches[0].schema();
+        let names = schema
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["vstring", "vu32", "vbool"]);
+
+        let rows = batches.iter().map(|batch| batch.num_rows()).sum::<usize>();
+        assert_eq!(rows, 9);
+        let vu32 = batches[0]
+            .column(1)
+            .as_any()
+            .downcast_ref::<arrow::array::UInt32Array>()
+            .unwrap();
+        // "3" is the fourth key and holds its latest write
+        assert_eq!(vu32.value(3), 33);
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    future::Future,
+    hash::Hash,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use flume::Sender;
+use futures_core::future::BoxFuture;
+use parquet::{
+    arrow::{arrow_reader::ArrowReaderOptions, async_reader::AsyncFileReader},
+    errors::{ParquetError, Result as ParquetResult},
+    file::metadata::ParquetMetaData,
+};
+use parquet_lru::BoxedFileReader;
+
+use crate::fs::FileId;
+
+/// Shares the result of a read of an SSTable with all identical reads that run at the same time
+///
+/// A read that misses the [`ParquetLru`](crate::ParquetLru) goes to storage. When many reads of
+/// the same footer or page range miss at once, only the first one is sent to storage and the
+/// others wait for its result. If the first read is cancelled, the waiting reads are sent to
+/// storage themselves.
+#[derive(Clone, Default)]
+pub(crate) struct ReadCoalescer {
+    inner: Arc<CoalescerInner>,
+}
+
+#[derive(Default)]
+struct CoalescerInner {
+    bytes: Inflight<(FileId, Range<u64>), Bytes>,
+    byte_ranges: Inflight<(FileId, Vec<Range<u64>>), Vec<Bytes>>,
+    // All readers of a DB load the metadata with the same options
+    metadata: Inflight<FileId, Arc<ParquetMetaData>>,
+}
+
+impl ReadCoalescer {
+    /// Wrap `reader` of the SSTable `gen`, so its reads are shared with the other readers of the
+    /// table
+    pub(crate) fn reader(&self, gen: FileId, reader: BoxedFileReader) -> BoxedFileReader {
+        BoxedFileReader::new(CoalescedReader {
+            gen,
+            reader,
+            coalescer: self.clone(),
+        })
+    }
+}
+
+// Reads that are sent to storage, with the readers waiting for each of them
+type Waiters<T> = Vec<Sender<Result<T, Arc<ParquetError>>>>;
+
+struct Inflight<K, T> {
+    waiters: Mutex<HashMap<K, Waiters<T>>>,
+}
+
+impl<K, T> Default for Inflight<K, T> {
+    fn default() -> Self {
+        Self {
+            waiters: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K, T> Inflight<K, T>
+where
+    K: Hash + Eq + Clone,
+    T: Clone,
+{
+    /// Run `read` for `key`, unless the same read is already running, then wait for its result
+    async fn coalesce<F>(&self, key: K, read: F) -> ParquetResult<T>
+    where
+        F: Future<Output = ParquetResult<T>>,
+    {
+        let waiting = {
+            let mut waiters = self.lock();
+            match waiters.get_mut(&key) {
+                Some(senders) => {
+                    let (sender, receiver) = flume::bounded(1);
+                    senders.push(sender);
+                    Some(receiver)
+                }
+                None => {
+                    waiters.insert(key.clone(), Vec::new());
+                    None
+                }
+            }
+        };
+        if let Some(receiver) = waiting {
+            return match receiver.recv_async().await {
+                Ok(result) => {
+                    result.map_err(|err| ParquetError::External(Box::new(SharedError(err))))
+                }
+                // the leading read was cancelled
+                Err(_) => read.await,
+            };
+        }
+
+        let leader = Leader {
+            inflight: self,
+            key: Some(key),
+        };
+        let result = read.await.map_err(Arc::new);
+        for sender in leader.finish() {
+            let _ = sender.send(result.clone());
+        }
+        result.map_err(|err| {
+            Arc::try_unwrap(err)
+                .unwrap_or_else(|err| ParquetError::External(Box::new(SharedError(err))))
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<K, Waiters<T>>> {
+        self.waiters
+            .lock()
+            .expect("read coalescer lock should not fail")
+    }
+}
+
+// Removes the read from the inflight reads once it is done or cancelled
+struct Leader<'a, K, T>
+where
+    K: Hash + Eq + Clone,
+    T: Clone,
+{
+    inflight: &'a Inflight<K, T>,
+    key: Option<K>,
+}
+
+impl<K, T> Leader<'_, K, T>
+where
+    K: Hash + Eq + Clone,
+    T: Clone,
+{
+    fn finish(mut self) -> Waiters<T> {
+        self.key
+            .take()
+            .and_then(|key| self.inflight.lock().remove(&key))
+            .unwrap_or_default()
+    }
+}
+
+impl<K, T> Drop for Leader<'_, K, T>
+where
+    K: Hash + Eq + Clone,
+    T: Clone,
+{
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            // dropping the senders wakes the waiting reads
+            self.inflight.lock().remove(&key);
+        }
+    }
+}
+
+/// Error of a read that was shared with the reads waiting for it
+#[derive(Debug)]
+struct SharedError(Arc<ParquetError>);
+
+impl Display for SharedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl Error for SharedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.0.as_ref())
+    }
+}
+
+struct CoalescedReader {
+    gen: FileId,
+    reader: BoxedFileReader,
+    coalescer: ReadCoalescer,
+}
+
+impl AsyncFileReader for CoalescedReader {
+    fn get_bytes(&mut self, range: Range<u64>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+        let key = (self.gen, range.clone());
+
+        Box::pin(async move {
+            self.coalescer
+                .inner
+                .bytes
+                .coalesce(key, self.reader.get_bytes(range))
+                .await
+        })
+    }
+
+    fn get_metadata<'s>(
+        &'s mut self,
+        options: Option<&'s ArrowReaderOptions>,
+    ) -> BoxFuture<'s, ParquetResult<Arc<ParquetMetaData>>> {
+        Box::pin(async move {
+            self.coalescer
+                .inner
+                .metadata
+                .coalesce(self.gen, self.reader.get_metadata(options))
+                .await
+        })
+    }
+
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<u64>>,
+    ) -> BoxFuture<'_, ParquetResult<Vec<Bytes>>> {
+        let key = (self.gen, ranges.clone());
+
+        Box::pin(async move {
+            self.coalescer
+                .inner
+                .byte_ranges
+                .coalesce(key, self.reader.get_byte_ranges(ranges))
+                .await
+        })
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{
+        ops::Range,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use bytes::Bytes;
+    use futures_core::future::BoxFuture;
+    use parquet::{
+        arrow::{arrow_reader::ArrowReaderOptions, async_reader::AsyncFileReader},
+        errors::{ParquetError, Result as ParquetResult},
+        file::metadata::ParquetMetaData,
+    };
+    use parquet_lru::BoxedFileReader;
+
+    use super::ReadCoalescer;
+    use crate::fs::generate_file_id;
+
+    struct SlowReader {
+        reads: Arc<AtomicUsize>,
+    }
+
+    impl AsyncFileReader for SlowReader {
+        fn get_bytes(&mut self, range: Range<u64>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+            Box::pin(async move {
+                self.reads.fetch_add(1, Ordering::Relaxed);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok(Bytes::from(vec![
+                    range.start as u8;
+                    (range.end - range.start) as usize
+                ]))
+            })
+        }
+
+        fn get_metadata<'s>(
+            &'s mut self,
+            _: Option<&'s ArrowReaderOptions>,
+        ) -> BoxFuture<'s, ParquetResult<Arc<ParquetMetaData>>> {
+            Box::pin(async move {
+                self.reads.fetch_add(1, Ordering::Relaxed);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Err(ParquetError::General("no metadata".into()))
+            })
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_reads_are_coalesced() {
+        let coalescer = ReadCoalescer::default();
+        let reads = Arc::new(AtomicUsize::new(0));
+        let gen = generate_file_id();
+        let reader = || {
+            coalescer.reader(
+                gen,
+                BoxedFileReader::new(SlowReader {
+                    reads: reads.clone(),
+                }),
+            )
+        };
+        let (mut reader_1, mut reader_2, mut reader_3) = (reader(), reader(), reader());
+
+        let (bytes_1, bytes_2, bytes_3) = futures_util::join!(
+            reader_1.get_bytes(1..4),
+            reader_2.get_bytes(1..4),
+            reader_3.get_bytes(2..4),
+        );
+        assert_eq!(bytes_1.unwrap(), Bytes::from(vec![1; 3]));
+        assert_eq!(bytes_2.unwrap(), Bytes::from(vec![1; 3]));
+        assert_eq!(bytes_3.unwrap(), Bytes::from(vec![2; 2]));
+        assert_eq!(reads.load(Ordering::Relaxed), 2);
+
+        // reads that don't overlap in time are not shared
+        reader_1.get_bytes(1..4).await.unwrap();
+        assert_eq!(reads.load(Ordering::Relaxed), 3);
+
+        // errors are shared as well
+        let (metadata_1, metadata_2) =
+            futures_util::join!(reader_1.get_metadata(None), reader_2.get_metadata(None));
+        assert_eq!(reads.load(Ordering::Relaxed), 4);
+        for metadata in [metadata_1, metadata_2] {
+            assert!(metadata.unwrap_err().to_string().contains("no metadata"));
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    collections::HashMap,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
+
+use bytes::Bytes;
+use fusio::{fs::OpenOptions, path::Path, DynFs, Error, Read, Write};
+use futures_core::future::BoxFuture;
+use parquet::{
+    arrow::{arrow_reader::ArrowReaderOptions, async_reader::AsyncFileReader},
+    errors::Result as ParquetResult,
+    file::metadata::{ParquetMetaData, ParquetMetaDataReader, ParquetMetaDataWriter},
+};
+use parquet_lru::BoxedFileReader;
+use tracing::error;
+
+use crate::fs::FileId;
+
+/// Footers of SSTables that are kept in a local file across restarts, see
+/// [`DbOption::footer_cache`](crate::DbOption::footer_cache)
+///
+/// The file holds the footer and the page index of every table that was read, each one after
+/// its [`FileId`] and length. SSTables are never rewritten, so a footer stays valid until its
+/// table is removed, which drops it from the cache. A file that cannot be decoded is ignored,
+/// as the footers can always be read from the tables again.
+#[derive(Clone, Default)]
+pub(crate) struct FooterCache {
+    inner: Arc<FooterCacheInner>,
+}
+
+#[derive(Default)]
+struct FooterCacheInner {
+    // File of the cache, footers are only cached once it is loaded
+    path: OnceLock<Path>,
+    footers: Mutex<HashMap<FileId, Arc<ParquetMetaData>>>,
+    // Whether the footers changed since they were last persisted
+    dirty: AtomicBool,
+    // Held while the file is written
+    persisting: async_lock::Mutex<()>,
+}
+
+impl FooterCache {
+    /// Load the footers persisted at `path` on the local file system `fs` and keep caching
+    /// footers there
+    pub(crate) async fn load(&self, fs: &Arc<dyn DynFs>, path: Path) {
+        let footers = match read_footers(fs, &path).await {
+            Ok(footers) => footers,
+            Err(err) => {
+                error!("[Footer Cache Error]: ignoring {}: {}", path, err);
+                HashMap::new()
+            }
+        };
+        self.inner.lock().extend(footers);
+        let _ = self.inner.path.set(path);
+    }
+
+    /// Number of cached footers
+    pub(crate) fn len(&self) -> usize {
+        self.inner.lock().len()
+    }
+
+    /// Wrap `reader` of the SSTable `gen`, so its footer is taken from the cache
+    pub(crate) fn reader(&self, gen: FileId, reader: BoxedFileReader) -> BoxedFileReader {
+        if self.inner.path.get().is_none() {
+            return reader;
+        }
+        BoxedFileReader::new(FooterCachedReader {
+            gen,
+            reader,
+            cache: self.inner.clone(),
+        })
+    }
+
+    /// Drop the footer of the SSTable `gen`, which is about to be removed
+    pub(crate) fn evict(&self, gen: FileId) {
+        if self.inner.lock().remove(&gen).is_some() {
+            self.inner.dirty.store(true, Ordering::Release);
+        }
+    }
+
+    /// Write the footers to the file of the cache, if they changed since they were last written
+    pub(crate) async fn persist(&self, fs: &Arc<dyn DynFs>) -> Result<(), Error> {
+        let inner = &self.inner;
+        let Some(path) = inner.path.get() else {
+            return Ok(());
+        };
+        let _persisting = inner.persisting.lock().await;
+        if !inner.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+        let footers = inner
+            .lock()
+            .iter()
+            .map(|(gen, metadata)| (*gen, metadata.clone()))
+            .collect::<Vec<_>>();
+
+        let mut buf = Vec::new();
+        for (gen, metadata) in footers {
+            let mut encoded = Vec::new();
+            ParquetMetaDataWriter::new(&mut encoded, &metadata)
+                .finish()
+                .map_err(|err| Error::Other(Box::new(err)))?;
+            buf.extend_from_slice(&gen.to_bytes());
+            buf.extend_from_slice(&(encoded.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&encoded);
+        }
+
+        let result = async {
+            let mut file = fs
+                .open_options(
+                    path,
+                    OpenOptions::default()
+                        .create(true)
+                        .write(true)
+                        .truncate(true),
+                )
+                .await?;
+            let (result, _) = file.write_all(buf).await;
+            result?;
+            file.close().await
+        }
+        .await;
+        if result.is_err() {
+            // written again next time
+            inner.dirty.store(true, Ordering::Release);
+        }
+        result
+    }
+}
+
+impl FooterCacheInner {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<FileId, Arc<ParquetMetaData>>> {
+        self.footers
+            .lock()
+            .expect("footer cache lock should not fail")
+    }
+}
+
+async fn read_footers(
+    fs: &Arc<dyn DynFs>,
+    path: &Path,
+) -> Result<HashMap<FileId, Arc<ParquetMetaData>>, Error> {
+    let mut footers = HashMap::new();
+    let mut file = match fs
+        .open_options(path, OpenOptions::default().read(true))
+        .await
+    {
+        Ok(file) => file,
+        Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => return Ok(footers),
+        Err(err) => return Err(err),
+    };
+    let size = file.size().await?;
+    let (result, buf) = file.read_exact_at(vec![0u8; size as usize], 0).await;
+    result?;
+    let buf = Bytes::from(buf);
+
+    let corrupted = || Error::Other("footer cache is corrupted".into());
+    let mut pos = 0;
+    while pos < buf.len() {
+        let header = buf.get(pos..pos + 20).ok_or_else(corrupted)?;
+        let gen = FileId::from_bytes(header[..16].try_into().unwrap());
+        let len = u32::from_le_bytes(header[16..].try_into().unwrap()) as usize;
+        pos += 20;
+        if buf.len() < pos + len {
+            return Err(corrupted());
+        }
+        let metadata = ParquetMetaDataReader::new()
+            .with_page_indexes(true)
+            .parse_and_finish(&buf.slice(pos..pos + len))
+            .map_err(|err| Error::Other(Box::new(err)))?;
+        footers.insert(gen, Arc::new(metadata));
+        pos += len;
+    }
+
+    Ok(footers)
+}
+
+/// Reader that takes the footer of its SSTable from a [`FooterCache`]
+struct FooterCachedReader {
+    gen: FileId,
+    reader: BoxedFileReader,
+    cache: Arc<FooterCacheInner>,
+}
+
+impl AsyncFileReader for FooterCachedReader {
+    fn get_bytes(&mut self, range: Range<u64>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+        self.reader.get_bytes(range)
+    }
+
+    fn get_metadata<'s>(
+        &'s mut self,
+        options: Option<&'s ArrowReaderOptions>,
+    ) -> BoxFuture<'s, ParquetResult<Arc<ParquetMetaData>>> {
+        Box::pin(async move {
+            let cached = self.cache.lock().get(&self.gen).cloned();
+            if let Some(metadata) = cached {
+                return Ok(metadata);
+            }
+            let metadata = self.reader.get_metadata(options).await?;
+            // only complete footers are cached, so they serve reads with and without page index
+            if metadata.column_index().is_some() && metadata.offset_index().is_some() {
+                self.cache.lock().insert(self.gen, metadata.clone());
+                self.cache.dirty.store(true, Ordering::Release);
+            }
+            Ok(metadata)
+        })
+    }
+
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<u64>>,
+    ) -> BoxFuture<'_, ParquetResult<Vec<Bytes>>> {
+        self.reader.get_byte_ranges(ranges)
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::Arc;
+
+    use fusio::{disk::LocalFs, path::Path, DynFs};
+    use tempfile::TempDir;
+
+    use super::FooterCache;
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema,
+        record::test::test_items, DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn footers_survive_restart() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_path = Path::from_filesystem_path(temp_dir.path())
+            .unwrap()
+            .child("footers");
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .footer_cache(cache_path.clone());
+
+        {
+            let db: DB<_, TokioExecutor> =
+                DB::new(option.clone(), TokioExecutor::default(), TestSchema)
+                    .await
+                    .unwrap();
+            for item in test_items(0u32..16) {
+                db.insert(item).await.unwrap();
+            }
+            db.flush().await.unwrap();
+            let vu32 = db
+                .get(&"3".to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap();
+            assert_eq!(vu32, Some(3));
+            db.persist_footer_cache().await.unwrap();
+        }
+
+        let fs: Arc<dyn DynFs> = Arc::new(LocalFs {});
+        let cache = FooterCache::default();
+        cache.load(&fs, cache_path).await;
+        assert_eq!(cache.len(), 1);
+
+        let db: DB<_, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        assert_eq!(db.ctx.manager.readers().footers().len(), 1);
+        let vu32 = db
+            .get(&"7".to_string(), |entry| entry.get().vu32)
+            .await
+            .unwrap();
+        assert_eq!(vu32, Some(7));
+    }
+}
@@ -1,12 +1,29 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use fusio::{disk::LocalFs, dynamic::DynFs, path::Path, Error};
 use fusio_dispatch::FsOptions;
 
+use crate::{
+    fs::{pin::PinnedTables, pool::ReaderPool, FileId},
+    stream::memory::MemoryBudget,
+    DbOption,
+};
+
 pub struct StoreManager {
     base_fs: Arc<dyn DynFs>,
     local_fs: Arc<dyn DynFs>,
     fs_map: HashMap<Path, Arc<dyn DynFs>>,
+    readers: ReaderPool,
+    pinned: PinnedTables,
+    compaction_memory: MemoryBudget,
+    // Rows written to SSTables by flushes and compactions
+    rows_written: AtomicU64,
 }
 
 impl StoreManager {
@@ -25,9 +42,21 @@ impl StoreManager {
             base_fs,
             fs_map,
             local_fs: Arc::new(LocalFs {}),
+            readers: ReaderPool::default(),
+            pinned: PinnedTables::default(),
+            compaction_memory: MemoryBudget::new(None),
+            rows_written: AtomicU64::new(0),
         })
     }
 
+    /// Keep up to `max_open_files` SSTable readers open between reads, 0 opens a reader per read
+    pub fn max_open_files(self, max_open_files: usize) -> Self {
+        StoreManager {
+            readers: ReaderPool::new(max_open_files),
+            ..self
+        }
+    }
+
     pub fn base_fs(&self) -> &Arc<dyn DynFs> {
         &self.base_fs
     }
@@ -39,6 +68,65 @@ impl StoreManager {
     pub fn get_fs(&self, path: &Path) -> &Arc<dyn DynFs> {
         self.fs_map.get(path).unwrap_or(&self.base_fs)
     }
+
+    pub(crate) fn readers(&self) -> &ReaderPool {
+        &self.readers
+    }
+
+    pub(crate) fn pinned(&self) -> &PinnedTables {
+        &self.pinned
+    }
+
+    /// Bytes of the columns that compactions buffer for the SSTables they write
+    pub(crate) fn compaction_memory(&self) -> &MemoryBudget {
+        &self.compaction_memory
+    }
+
+    pub(crate) fn count_rows_written(&self, rows: usize) {
+        self.rows_written.fetch_add(rows as u64, Ordering::Relaxed);
+    }
+
+    /// Rows written to SSTables by flushes and compactions since the DB was opened
+    pub(crate) fn rows_written(&self) -> u64 {
+        self.rows_written.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn level_fs(&self, option: &DbOption, level: usize) -> &Arc<dyn DynFs> {
+        option
+            .level_fs_path(level)
+            .map(|path| self.get_fs(path))
+            .unwrap_or(&self.base_fs)
+    }
+
+    /// File system and path of the SSTable `gen` at `level`
+    pub(crate) fn table(
+        &self,
+        option: &DbOption,
+        gen: FileId,
+        level: usize,
+    ) -> (&Arc<dyn DynFs>, Path) {
+        match self.pinned.path(gen) {
+            Some(path) => (&self.local_fs, path),
+            None => (self.level_fs(option, level), option.table_path(gen, level)),
+        }
+    }
+
+    /// File system and path to write the new SSTable `gen` of `level` to, which is pinned to the
+    /// local file system if [`DbOption::pin_recent_tables`] is set
+    pub(crate) fn new_table(
+        &self,
+        option: &DbOption,
+        gen: FileId,
+        level: usize,
+    ) -> (&Arc<dyn DynFs>, Path) {
+        match option.pinned_table_path(gen) {
+            Some(path) => {
+                self.pinned.pin(gen, path.clone());
+                (&self.local_fs, path)
+            }
+            None => (self.level_fs(option, level), option.table_path(gen, level)),
+        }
+    }
 }
 
 // TODO: TestCases
@@ -1,16 +1,28 @@
+pub(crate) mod coalesce;
+pub(crate) mod footer;
 pub(crate) mod manager;
+pub(crate) mod pin;
+pub(crate) mod pool;
 
 use std::{
-    fmt::{Display, Formatter},
+    fmt::{Debug, Display, Formatter},
     str::FromStr,
+    sync::Mutex,
+    time::{Duration, UNIX_EPOCH},
 };
 
-use fusio::{fs::OpenOptions, path::Path};
+use fusio::{fs::OpenOptions, path::Path, DynFs, Read, Write};
+use futures_util::StreamExt;
 use once_cell::sync::OnceCell;
 use ulid::{DecodeError, Ulid};
 
+use crate::{fs::manager::StoreManager, option::DbOption, version::MAX_LEVEL};
+
 pub type FileId = Ulid;
 
+// Size of the buffer used by `copy`
+const COPY_CHUNK_SIZE: usize = 1024 * 1024;
+
 static GENERATOR: OnceCell<std::sync::Mutex<ulid::Generator>> = OnceCell::new();
 
 #[inline]
@@ -24,6 +36,166 @@ pub fn generate_file_id() -> FileId {
     guard.generate().expect("generator should not fail")
 }
 
+/// Source of the ids of the files a [`DB`](crate::DB) creates, set with
+/// [`DbOption::file_id_generator`](crate::DbOption::file_id_generator)
+///
+/// Ids have to increase with every call, as the WALs and version logs are ordered by their ids.
+/// The time part of an id is taken as the creation time of the file, e.g. for
+/// [`WalRetention::KeepFor`](crate::option::WalRetention::KeepFor).
+pub trait FileIdGenerator: Debug + Send + Sync {
+    /// The next id, `now_ms` is the time of the [`TimeSource`](crate::clock::TimeSource) of the
+    /// DB
+    fn generate(&self, now_ms: u64) -> FileId;
+
+    /// Only generate ids above `id`, the largest id of the files of the DB when it is opened.
+    /// Generators that start from a fixed state would otherwise reuse the ids of the files of an
+    /// earlier run and overwrite them.
+    fn advance_past(&self, id: FileId) {
+        let _ = id;
+    }
+}
+
+/// ULIDs of the current time. An id generated while the clock stands still or goes back has the
+/// time of the previous one, so ids still increase.
+pub struct UlidFileIds {
+    // The generator and the latest id it generated or was advanced past
+    generator: Mutex<(ulid::Generator, FileId)>,
+}
+
+impl Default for UlidFileIds {
+    fn default() -> Self {
+        Self {
+            generator: Mutex::new((ulid::Generator::new(), Ulid::nil())),
+        }
+    }
+}
+
+impl Debug for UlidFileIds {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("UlidFileIds")
+    }
+}
+
+impl FileIdGenerator for UlidFileIds {
+    fn generate(&self, now_ms: u64) -> FileId {
+        let mut guard = self
+            .generator
+            .lock()
+            .expect("file id generator lock should not fail");
+        let (generator, latest) = &mut *guard;
+        let id = generator
+            .generate_from_datetime(UNIX_EPOCH + Duration::from_millis(now_ms))
+            .expect("generator should not fail");
+        // the files of an earlier run may have ids of a later time
+        *latest = if id > *latest {
+            id
+        } else {
+            latest.increment().expect("file ids should not overflow")
+        };
+
+        *latest
+    }
+
+    fn advance_past(&self, id: FileId) {
+        let mut guard = self
+            .generator
+            .lock()
+            .expect("file id generator lock should not fail");
+        guard.1 = guard.1.max(id);
+    }
+}
+
+/// Ids `1, 2, 3, ...` with `timestamp_ms` as their time, so every run creates the same files.
+/// Opening a DB that holds files already continues after the largest of their ids.
+#[derive(Debug)]
+pub struct SequentialFileIds {
+    // The latest id generated or advanced past
+    latest: Mutex<FileId>,
+}
+
+impl SequentialFileIds {
+    pub fn new(timestamp_ms: u64) -> Self {
+        Self {
+            latest: Mutex::new(Ulid::from_parts(timestamp_ms, 0)),
+        }
+    }
+}
+
+impl FileIdGenerator for SequentialFileIds {
+    fn generate(&self, _: u64) -> FileId {
+        let mut latest = self
+            .latest
+            .lock()
+            .expect("sequential file id generator lock should not fail");
+        *latest = latest.increment().expect("file ids should not overflow");
+
+        *latest
+    }
+
+    fn advance_past(&self, id: FileId) {
+        let mut latest = self
+            .latest
+            .lock()
+            .expect("sequential file id generator lock should not fail");
+        *latest = (*latest).max(id);
+    }
+}
+
+/// ULIDs with `timestamp_ms` as their time and random bits drawn from `seed`, so every run with
+/// the same seed creates the same files. The random bits of each id exceed the ones of the
+/// previous id, as they do for ULIDs generated within the same millisecond. Opening a DB that
+/// holds files already continues after the largest of their ids.
+#[derive(Debug)]
+pub struct SeededFileIds {
+    // The state of the random bits and the latest id generated or advanced past
+    state: Mutex<(u64, FileId)>,
+}
+
+impl SeededFileIds {
+    pub fn new(seed: u64, timestamp_ms: u64) -> Self {
+        let mut seed = seed;
+        // the first id starts anywhere in the lower half of the 80 random bits
+        let random =
+            ((splitmix64(&mut seed) as u128) << 15) | (splitmix64(&mut seed) as u128 >> 49);
+
+        Self {
+            state: Mutex::new((seed, Ulid::from_parts(timestamp_ms, random))),
+        }
+    }
+}
+
+impl FileIdGenerator for SeededFileIds {
+    fn generate(&self, _: u64) -> FileId {
+        let mut guard = self
+            .state
+            .lock()
+            .expect("seeded file id generator lock should not fail");
+        let (seed, latest) = &mut *guard;
+        *latest = Ulid::from_parts(
+            latest.timestamp_ms(),
+            latest.random() + 1 + (splitmix64(seed) >> 32) as u128,
+        );
+
+        *latest
+    }
+
+    fn advance_past(&self, id: FileId) {
+        let mut guard = self
+            .state
+            .lock()
+            .expect("seeded file id generator lock should not fail");
+        guard.1 = guard.1.max(id);
+    }
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
 pub enum FileType {
     Wal,
     Parquet,
@@ -68,3 +240,203 @@ pub(crate) fn parse_file_id(path: &Path, suffix: FileType) -> Result<Option<File
         })
         .transpose()
 }
+
+/// Largest id of the WALs, SSTables and version logs of the DB of `option`, `None` if it has no
+/// files yet
+pub(crate) async fn latest_file_id(
+    option: &DbOption,
+    manager: &StoreManager,
+) -> Result<Option<FileId>, fusio::Error> {
+    let base_fs = manager.base_fs();
+    let mut dirs = vec![
+        (base_fs, option.wal_dir_path()),
+        (base_fs, option.version_log_dir_path()),
+    ];
+    for level in 0..MAX_LEVEL {
+        let path = option.level_fs_path(level).unwrap_or(&option.base_path);
+        dirs.push((manager.get_fs(path), path.clone()));
+    }
+    if let Some(path) = option.retained_wal_dir_path() {
+        dirs.push((base_fs, path));
+    }
+    if let Some((path, _)) = &option.pinned_tables {
+        dirs.push((manager.local_fs(), path.clone()));
+    }
+
+    let mut latest = None;
+    for (fs, dir) in dirs {
+        // the directories of levels are created by users and may not exist yet
+        let Ok(mut stream) = fs.list(&dir).await else {
+            continue;
+        };
+        while let Some(meta) = stream.next().await {
+            let meta = meta?;
+            let id = meta
+                .path
+                .filename()
+                .and_then(|name| name.split('.').next())
+                .and_then(|id| FileId::from_str(id).ok());
+            latest = latest.max(id);
+        }
+    }
+
+    Ok(latest)
+}
+
+/// Copy the file at `from` to `to`, which may be on another file system
+pub(crate) async fn copy(
+    from_fs: &dyn DynFs,
+    from: &Path,
+    to_fs: &dyn DynFs,
+    to: &Path,
+) -> Result<(), fusio::Error> {
+    let mut source = from_fs
+        .open_options(from, FileType::Parquet.open_options(true))
+        .await?;
+    let mut target = to_fs
+        .open_options(to, FileType::Parquet.open_options(false))
+        .await?;
+
+    let size = source.size().await?;
+    let mut buf = vec![0u8; COPY_CHUNK_SIZE];
+    let mut pos = 0;
+    while pos < size {
+        let len = (size - pos).min(buf.len() as u64) as usize;
+        let (result, _) = source.read_exact_at(&mut buf[..len], pos).await;
+        result?;
+        let (result, _) = target.write_all(&buf[..len]).await;
+        result?;
+        pos += len as u64;
+    }
+
+    target.close().await
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::path::Path as StdPath;
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::{FileIdGenerator, SeededFileIds, SequentialFileIds};
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    /// Paths and sizes of the files under `dir`
+    fn layout(dir: &StdPath) -> Vec<(String, u64)> {
+        let mut files = Vec::new();
+        let mut dirs = vec![dir.to_path_buf()];
+        while let Some(next) = dirs.pop() {
+            for entry in std::fs::read_dir(next).unwrap() {
+                let entry = entry.unwrap();
+                if entry.file_type().unwrap().is_dir() {
+                    dirs.push(entry.path());
+                } else {
+                    let path = entry
+                        .path()
+                        .strip_prefix(dir)
+                        .unwrap()
+                        .display()
+                        .to_string();
+                    files.push((path, entry.metadata().unwrap().len()));
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn seeded_ids_increase() {
+        let ids = SeededFileIds::new(7, 1_000);
+        let again = SeededFileIds::new(7, 1_000);
+        let mut previous = ids.generate(0);
+        assert_eq!(previous, again.generate(0));
+
+        for _ in 0..1000 {
+            let id = ids.generate(0);
+            assert!(id > previous);
+            assert_eq!(id.timestamp_ms(), 1_000);
+            previous = id;
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn same_layout_across_runs() {
+        let mut layouts = Vec::new();
+        for _ in 0..2 {
+            let temp_dir = TempDir::new().unwrap();
+            let option = DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &TestSchema,
+            )
+            .file_id_generator(SequentialFileIds::new(0));
+            let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+                .await
+                .unwrap();
+
+            for i in 0..10 {
+                db.insert(Test {
+                    vstring: i.to_string(),
+                    vu32: i,
+                    vbool: None,
+                })
+                .await
+                .unwrap();
+            }
+            db.flush().await.unwrap();
+            db.flush_wal().await.unwrap();
+            drop(db);
+
+            layouts.push(layout(temp_dir.path()));
+        }
+
+        assert!(layouts[0]
+            .iter()
+            .any(|(path, _)| path.ends_with(".parquet")));
+        assert_eq!(layouts[0], layouts[1]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reopen_continues_after_existing_ids() {
+        let temp_dir = TempDir::new().unwrap();
+        let open = || async {
+            let option = DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &TestSchema,
+            )
+            .file_id_generator(SequentialFileIds::new(0));
+            DB::<Test, TokioExecutor>::new(option, TokioExecutor::default(), TestSchema)
+                .await
+                .unwrap()
+        };
+
+        for run in 0..2u32 {
+            let db = open().await;
+            for i in run * 10..run * 10 + 10 {
+                db.insert(Test {
+                    vstring: i.to_string(),
+                    vu32: i,
+                    vbool: None,
+                })
+                .await
+                .unwrap();
+            }
+            db.flush().await.unwrap();
+            db.flush_wal().await.unwrap();
+        }
+
+        // the tables of the first run were not overwritten by the second one
+        let db = open().await;
+        for i in 0..20u32 {
+            let vu32 = db
+                .get(&i.to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap();
+            assert_eq!(vu32, Some(i));
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! SSTables kept on the local file system while they are recent
+//!
+//! With [`DbOption::pin_recent_tables`] flushes and compactions write their SSTables into a local
+//! directory instead of the file system of their level. [`PinnedTables`] remembers which tables
+//! are there, so reads find them, and [`migrate`] moves them to their level once they are old
+//! enough.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use fusio::path::Path;
+use futures_util::StreamExt;
+
+use crate::{
+    fs::{copy, manager::StoreManager, parse_file_id, FileId, FileType},
+    record::Record,
+    version::Version,
+    DbOption,
+};
+
+/// Local paths of the SSTables that have not been moved to their level yet
+///
+/// File ids are unique across [`DB`](crate::DB)s, so the tables of all DBs sharing a
+/// [`StoreManager`] are kept in one map.
+#[derive(Default)]
+pub(crate) struct PinnedTables {
+    paths: Mutex<HashMap<FileId, Path>>,
+}
+
+impl PinnedTables {
+    pub(crate) fn pin(&self, gen: FileId, path: Path) {
+        self.lock().insert(gen, path);
+    }
+
+    pub(crate) fn unpin(&self, gen: FileId) -> Option<Path> {
+        self.lock().remove(&gen)
+    }
+
+    pub(crate) fn path(&self, gen: FileId) -> Option<Path> {
+        self.lock().get(&gen).cloned()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<FileId, Path>> {
+        self.paths
+            .lock()
+            .expect("pinned tables lock should not fail")
+    }
+}
+
+/// Pin the tables of `version` found in the pinned directory of `option` after a restart.
+///
+/// Tables of the directory that are not part of `version` were left behind by an interrupted
+/// flush or compaction and are removed.
+pub(crate) async fn recover<R: Record>(
+    option: &DbOption,
+    manager: &StoreManager,
+    version: &Version<R>,
+) -> Result<(), fusio::Error> {
+    let Some((dir, _)) = &option.pinned_tables else {
+        return Ok(());
+    };
+    let local_fs = manager.local_fs();
+    let live = version
+        .level_slice
+        .iter()
+        .flatten()
+        .map(|scope| scope.gen)
+        .collect::<HashSet<_>>();
+    let mut orphans = Vec::new();
+    let mut stream = local_fs.list(dir).await?;
+    while let Some(meta) = stream.next().await {
+        let meta = meta?;
+        if !meta.path.as_ref().ends_with(&FileType::Parquet.to_string()) {
+            continue;
+        }
+        match parse_file_id(&meta.path, FileType::Parquet) {
+            Ok(Some(gen)) if live.contains(&gen) => manager.pinned().pin(gen, meta.path),
+            _ => orphans.push(meta.path),
+        }
+    }
+    drop(stream);
+    for path in orphans {
+        local_fs.remove(&path).await?;
+    }
+
+    Ok(())
+}
+
+/// Move the pinned tables of `version` that are older than the pin age of its [`DbOption`] to
+/// the file system of their level.
+///
+/// A table is copied before it is unpinned, so reads find it in one place or the other, and its
+/// local copy is only removed after the pooled readers of the local file are evicted.
+pub(crate) async fn migrate<R: Record>(
+    version: &Version<R>,
+    manager: &StoreManager,
+) -> Result<(), fusio::Error> {
+    let option = version.option();
+    let Some((_, age)) = &option.pinned_tables else {
+        return Ok(());
+    };
+    // the clock the ids, and therefore the ages, of new tables are taken from
+    let now = option.now_ms();
+
+    for (level, scopes) in version.level_slice.iter().enumerate() {
+        for scope in scopes {
+            if now.saturating_sub(scope.gen.timestamp_ms()) < age.as_millis() as u64 {
+                continue;
+            }
+            let Some(local_path) = manager.pinned().path(scope.gen) else {
+                continue;
+            };
+            copy(
+                manager.local_fs(),
+                &local_path,
+                manager.level_fs(option, level),
+                &option.table_path(scope.gen, level),
+            )
+            .await?;
+
+            manager.pinned().unpin(scope.gen);
+            manager.readers().evict(scope.gen);
+            manager.local_fs().remove(&local_path).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::time::Duration;
+
+    use fusio::path::Path;
+    use fusio_dispatch::FsOptions;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor,
+        fs::{FileId, FileType},
+        inmem::immutable::tests::TestSchema,
+        tests::Test,
+        DbOption, DB,
+    };
+
+    fn option(temp_dir: &TempDir, age: Duration) -> DbOption {
+        let level_0 = temp_dir.path().join("level_0");
+        let pinned = temp_dir.path().join("pinned");
+        std::fs::create_dir_all(&level_0).unwrap();
+
+        DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .level_path(
+            0,
+            Path::from_filesystem_path(&level_0).unwrap(),
+            FsOptions::Local,
+        )
+        .unwrap()
+        .pin_recent_tables(Path::from_filesystem_path(&pinned).unwrap(), age)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn recent_tables_stay_local() {
+        let temp_dir = TempDir::new().unwrap();
+        let table = |dir: &str, gen: FileId| {
+            temp_dir
+                .path()
+                .join(dir)
+                .join(format!("{}.{}", gen, FileType::Parquet))
+        };
+
+        let db: DB<Test, TokioExecutor> = DB::new(
+            option(&temp_dir, Duration::from_secs(3600)),
+            TokioExecutor::default(),
+            TestSchema,
+        )
+        .await
+        .unwrap();
+        db.insert(Test {
+            vstring: "tonbo".to_string(),
+            vu32: 1,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        db.flush().await.unwrap();
+
+        let gen = db.current_manifest().await.level_slice[0][0].gen;
+        assert!(table("pinned", gen).exists());
+        assert!(!table("level_0", gen).exists());
+        let value = db
+            .get(&"tonbo".to_string(), |entry| entry.get().vu32)
+            .await
+            .unwrap();
+        assert_eq!(value, Some(1));
+        drop(db);
+
+        // the table has aged by the time the DB is opened again
+        let db: DB<Test, TokioExecutor> = DB::new(
+            option(&temp_dir, Duration::ZERO),
+            TokioExecutor::default(),
+            TestSchema,
+        )
+        .await
+        .unwrap();
+        assert!(!table("pinned", gen).exists());
+        assert!(table("level_0", gen).exists());
+        let value = db
+            .get(&"tonbo".to_string(), |entry| entry.get().vu32)
+            .await
+            .unwrap();
+        assert_eq!(value, Some(1));
+    }
+}
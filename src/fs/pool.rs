@@ -0,0 +1,293 @@
+use std::{
+    collections::VecDeque,
+    mem,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use fusio::{path::Path, DynFs, DynRead, Error};
+use fusio_parquet::reader::AsyncReader;
+use futures_core::future::BoxFuture;
+use parquet::{
+    arrow::{arrow_reader::ArrowReaderOptions, async_reader::AsyncFileReader},
+    errors::Result as ParquetResult,
+    file::metadata::ParquetMetaData,
+};
+use parquet_lru::{BoxedFileReader, Bytes};
+
+use crate::fs::{coalesce::ReadCoalescer, footer::FooterCache, FileId, FileType};
+
+/// Pool of open SSTable readers shared by all reads of a DB
+///
+/// Readers are handed out for the duration of a scan or lookup and go back to the pool when the
+/// read is done, so later reads of the same table skip opening the file. `capacity` bounds the
+/// readers that are kept open while idle: opening a reader closes the least recently used idle
+/// one once `capacity` readers are open, and a released reader is closed rather than kept while
+/// more than `capacity` are open. A capacity of 0 disables pooling and every read opens its own
+/// reader.
+///
+/// [`ReaderPool::open`] does not wait for a reader to be released once `capacity` readers are
+/// in use. A scan holds a reader of every table it merges until it is done, so a scan over more
+/// tables than `capacity`, or scans that each hold some of the readers, would wait on each other
+/// forever. Readers in use beyond `capacity` are closed as soon as they are released.
+///
+/// Concurrent identical reads of the readers are coalesced by a [`ReadCoalescer`], and their
+/// footers are taken from a [`FooterCache`] once it is loaded.
+#[derive(Clone, Default)]
+pub(crate) struct ReaderPool {
+    inner: Arc<ReaderPoolInner>,
+}
+
+#[derive(Default)]
+struct ReaderPoolInner {
+    capacity: usize,
+    state: Mutex<PoolState>,
+    coalescer: ReadCoalescer,
+    footers: FooterCache,
+}
+
+#[derive(Default)]
+struct PoolState {
+    // Readers that are open, whether idle or in use
+    open: usize,
+    // Idle readers, least recently used first
+    idle: VecDeque<(FileId, BoxedFileReader)>,
+}
+
+impl ReaderPool {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(ReaderPoolInner {
+                capacity,
+                state: Mutex::default(),
+                coalescer: ReadCoalescer::default(),
+                footers: FooterCache::default(),
+            }),
+        }
+    }
+
+    /// Reader of the SSTable `gen` at `path` on `fs`, reusing an idle reader of the table if
+    /// there is one
+    pub(crate) async fn open(
+        &self,
+        fs: &Arc<dyn DynFs>,
+        path: &Path,
+        gen: FileId,
+    ) -> Result<BoxedFileReader, Error> {
+        if self.inner.capacity == 0 {
+            let reader = self.open_reader(fs, path, gen).await?;
+            return Ok(self.inner.coalescer.reader(gen, reader));
+        }
+        let idle = {
+            let mut state = self.lock();
+            state
+                .idle
+                .iter()
+                .rposition(|(id, _)| *id == gen)
+                .and_then(|index| state.idle.remove(index))
+                .map(|(_, reader)| reader)
+        };
+        let reader = match idle {
+            Some(reader) => reader,
+            None => {
+                let reader = self.open_reader(fs, path, gen).await?;
+                let closed = {
+                    let mut state = self.lock();
+                    state.open += 1;
+                    self.close_idle(&mut state)
+                };
+                drop(closed);
+                reader
+            }
+        };
+
+        let reader = BoxedFileReader::new(PooledReader {
+            gen,
+            reader: Some(reader),
+            pool: self.clone(),
+        });
+        Ok(self.inner.coalescer.reader(gen, reader))
+    }
+
+    pub(crate) fn footers(&self) -> &FooterCache {
+        &self.inner.footers
+    }
+
+    /// Close the idle readers of the SSTable `gen`, which is about to be removed
+    pub(crate) fn evict(&self, gen: FileId) {
+        let closed = {
+            let mut state = self.lock();
+            let (closed, kept) = mem::take(&mut state.idle)
+                .into_iter()
+                .partition::<VecDeque<_>, _>(|(id, _)| *id == gen);
+            state.idle = kept;
+            state.open -= closed.len();
+            closed
+        };
+        drop(closed);
+    }
+
+    async fn open_reader(
+        &self,
+        fs: &Arc<dyn DynFs>,
+        path: &Path,
+        gen: FileId,
+    ) -> Result<BoxedFileReader, Error> {
+        let reader = BoxedFileReader::new(open_reader(fs, path).await?);
+        Ok(self.inner.footers.reader(gen, reader))
+    }
+
+    fn release(&self, gen: FileId, reader: BoxedFileReader) {
+        let closed = {
+            let mut state = self.lock();
+            if state.open > self.inner.capacity {
+                state.open -= 1;
+                Some(reader)
+            } else {
+                state.idle.push_back((gen, reader));
+                None
+            }
+        };
+        // readers are closed outside of the lock
+        drop(closed);
+    }
+
+    // Takes idle readers out of the pool until it is back within its capacity
+    fn close_idle(&self, state: &mut PoolState) -> Vec<BoxedFileReader> {
+        let mut closed = Vec::new();
+        while state.open > self.inner.capacity {
+            match state.idle.pop_front() {
+                Some((_, reader)) => {
+                    state.open -= 1;
+                    closed.push(reader);
+                }
+                None => break,
+            }
+        }
+        closed
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, PoolState> {
+        self.inner
+            .state
+            .lock()
+            .expect("reader pool lock should not fail")
+    }
+}
+
+async fn open_reader(fs: &Arc<dyn DynFs>, path: &Path) -> Result<AsyncReader, Error> {
+    let file = fs
+        .open_options(path, FileType::Parquet.open_options(true))
+        .await?;
+    let size = file.size().await?;
+
+    AsyncReader::new(file, size).await
+}
+
+/// Reader that goes back to its [`ReaderPool`] once it is dropped
+struct PooledReader {
+    gen: FileId,
+    reader: Option<BoxedFileReader>,
+    pool: ReaderPool,
+}
+
+impl PooledReader {
+    fn reader(&mut self) -> &mut BoxedFileReader {
+        self.reader
+            .as_mut()
+            .expect("pooled reader is only taken on drop")
+    }
+}
+
+impl AsyncFileReader for PooledReader {
+    fn get_bytes(&mut self, range: Range<u64>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+        self.reader().get_bytes(range)
+    }
+
+    fn get_metadata<'s>(
+        &'s mut self,
+        options: Option<&'s ArrowReaderOptions>,
+    ) -> BoxFuture<'s, ParquetResult<Arc<ParquetMetaData>>> {
+        self.reader().get_metadata(options)
+    }
+
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<u64>>,
+    ) -> BoxFuture<'_, ParquetResult<Vec<Bytes>>> {
+        self.reader().get_byte_ranges(ranges)
+    }
+}
+
+impl Drop for PooledReader {
+    fn drop(&mut self) {
+        if let Some(reader) = self.reader.take() {
+            self.pool.release(self.gen, reader);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::Arc;
+
+    use fusio::{disk::LocalFs, path::Path, DynFs};
+    use tempfile::TempDir;
+
+    use super::ReaderPool;
+    use crate::fs::{generate_file_id, FileId, FileType};
+
+    async fn table(fs: &Arc<dyn DynFs>, dir: &TempDir) -> (Path, FileId) {
+        let gen = generate_file_id();
+        let path = Path::from_filesystem_path(dir.path())
+            .unwrap()
+            .child(format!("{gen}.{}", FileType::Parquet));
+        fs.open_options(&path, FileType::Parquet.open_options(false))
+            .await
+            .unwrap();
+        (path, gen)
+    }
+
+    fn counts(pool: &ReaderPool) -> (usize, usize) {
+        let state = pool.lock();
+        (state.open, state.idle.len())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reuse_and_limit_readers() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs: Arc<dyn DynFs> = Arc::new(LocalFs {});
+        let (path_1, gen_1) = table(&fs, &temp_dir).await;
+        let (path_2, gen_2) = table(&fs, &temp_dir).await;
+        let pool = ReaderPool::new(1);
+
+        let reader_1 = pool.open(&fs, &path_1, gen_1).await.unwrap();
+        let reader_2 = pool.open(&fs, &path_2, gen_2).await.unwrap();
+        // in use readers may exceed the capacity
+        assert_eq!(counts(&pool), (2, 0));
+
+        // closed on release, as the pool is over its capacity
+        drop(reader_1);
+        assert_eq!(counts(&pool), (1, 0));
+        drop(reader_2);
+        assert_eq!(counts(&pool), (1, 1));
+
+        // the idle reader is reused
+        let reader_2 = pool.open(&fs, &path_2, gen_2).await.unwrap();
+        assert_eq!(counts(&pool), (1, 0));
+        drop(reader_2);
+
+        // the idle reader of another table is closed to make room
+        let reader_1 = pool.open(&fs, &path_1, gen_1).await.unwrap();
+        assert_eq!(counts(&pool), (1, 0));
+        drop(reader_1);
+        assert_eq!(counts(&pool), (1, 1));
+
+        pool.evict(gen_1);
+        assert_eq!(counts(&pool), (0, 0));
+
+        let disabled = ReaderPool::default();
+        drop(disabled.open(&fs, &path_1, gen_1).await.unwrap());
+        assert_eq!(counts(&disabled), (0, 0));
+    }
+}
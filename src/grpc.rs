@@ -0,0 +1,606 @@
+//! gRPC service sharing a [`DB`] of [`DynRecord`]s with other processes
+//!
+//! [`TonboServer`] is a [tonic] service with the `tonbo.Tonbo` methods `Get`, `Insert`, `Remove`
+//! and `Scan`, so several small processes on a host can use one tonbo instance instead of each
+//! embedding the engine:
+//!
+//! ```ignore
+//! tonic::transport::Server::builder()
+//!     .add_service(TonboServer::new(Arc::new(db)))
+//!     .serve("[::1]:50051".parse()?)
+//!     .await?;
+//! ```
+//!
+//! Records travel as Arrow IPC streams of the user columns: `Insert` takes one (its columns are
+//! matched to the fields of the schema by name and cast to their types), and `Get` and `Scan`
+//! return them, so clients only need an Arrow implementation to read them. Keys are scalars that
+//! are cast to the type of the primary key. The messages are declared with [`prost`] below and
+//! described by `proto/tonbo.proto` in the repository, which clients in other languages generate
+//! their stubs from. A change to a message here needs the same change there.
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    io::Cursor,
+    ops::Bound,
+    pin::pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use arrow::{
+    array::{
+        new_null_array, Array, ArrayRef, BinaryArray, BooleanArray, Int64Array, StringArray,
+        UInt64Array,
+    },
+    compute::cast,
+    ipc::reader::StreamReader,
+};
+use async_stream::stream;
+use futures_util::StreamExt;
+use tonic::{
+    codec::ProstCodec,
+    codegen::{empty_body, http, Body, BoxFuture, BoxStream, Service, StdError},
+    server::{Grpc, NamedService, ServerStreamingService, UnaryService},
+    Code, Request, Response, Status,
+};
+
+use crate::{
+    error::ErrorKind,
+    executor::Executor,
+    magic::USER_COLUMN_OFFSET,
+    record::{DynRecord, Schema, Value, ValueRef},
+    DB,
+};
+
+const DEFAULT_SCAN_BATCH_SIZE: u32 = 1024;
+
+/// A primary key, cast to the type of the primary key of the schema
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Key {
+    #[prost(oneof = "key::Value", tags = "1, 2, 3, 4, 5")]
+    pub value: Option<key::Value>,
+}
+
+pub mod key {
+    /// The scalar of a [`Key`](super::Key)
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Value {
+        #[prost(bool, tag = "1")]
+        Boolean(bool),
+        #[prost(int64, tag = "2")]
+        Int(i64),
+        #[prost(uint64, tag = "3")]
+        UInt(u64),
+        #[prost(string, tag = "4")]
+        String(String),
+        #[prost(bytes = "vec", tag = "5")]
+        Binary(Vec<u8>),
+    }
+}
+
+/// A bound of the range of a [`ScanRequest`]
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct KeyBound {
+    #[prost(message, optional, tag = "1")]
+    pub key: Option<Key>,
+    #[prost(bool, tag = "2")]
+    pub inclusive: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetRequest {
+    #[prost(message, optional, tag = "1")]
+    pub key: Option<Key>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetResponse {
+    /// Arrow IPC stream with the record as its only row, or no rows if the key does not exist
+    #[prost(bytes = "vec", tag = "1")]
+    pub ipc: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct InsertRequest {
+    /// Arrow IPC stream of the records to insert
+    #[prost(bytes = "vec", tag = "1")]
+    pub ipc: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct InsertResponse {
+    /// Number of inserted records
+    #[prost(uint64, tag = "1")]
+    pub inserted: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RemoveRequest {
+    #[prost(message, optional, tag = "1")]
+    pub key: Option<Key>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RemoveResponse {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScanRequest {
+    /// Unbounded if not set
+    #[prost(message, optional, tag = "1")]
+    pub lower: Option<KeyBound>,
+    /// Unbounded if not set
+    #[prost(message, optional, tag = "2")]
+    pub upper: Option<KeyBound>,
+    #[prost(uint64, optional, tag = "3")]
+    pub limit: Option<u64>,
+    /// Names of the columns to return, all of them if empty. The primary key is always returned.
+    #[prost(string, repeated, tag = "4")]
+    pub projection: Vec<String>,
+    /// Rows per record batch, 1024 if not set
+    #[prost(uint32, optional, tag = "5")]
+    pub batch_size: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScanResponse {
+    /// The next part of the Arrow IPC stream of the scanned records, the responses of a scan
+    /// concatenated form the complete stream
+    #[prost(bytes = "vec", tag = "1")]
+    pub ipc: Vec<u8>,
+}
+
+/// The `tonbo.Tonbo` gRPC service over a [`DB`]
+///
+/// A `Scan` reads a snapshot of the DB that is held until its response stream is finished or
+/// dropped, as it is for [`DB::scan`].
+pub struct TonboServer<E>
+where
+    E: Executor + Send + Sync + 'static,
+{
+    db: Arc<DB<DynRecord, E>>,
+}
+
+impl<E> Clone for TonboServer<E>
+where
+    E: Executor + Send + Sync + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            db: self.db.clone(),
+        }
+    }
+}
+
+impl<E> TonboServer<E>
+where
+    E: Executor + Send + Sync + 'static,
+{
+    pub fn new(db: Arc<DB<DynRecord, E>>) -> Self {
+        Self { db }
+    }
+
+    async fn get(self, request: GetRequest) -> Result<GetResponse, Status> {
+        let key = self.key(request.key).await?;
+        let snapshot = self.db.snapshot().await;
+        let scan = snapshot.scan((Bound::Included(&key), Bound::Included(&key)));
+        let mut parts = pin!(scan.into_ipc_stream(1).await.map_err(db_status)?);
+
+        let mut ipc = Vec::new();
+        while let Some(part) = parts.next().await {
+            ipc.extend(part.map_err(db_status)?);
+        }
+        Ok(GetResponse { ipc })
+    }
+
+    async fn insert(self, request: InsertRequest) -> Result<InsertResponse, Status> {
+        let schema = self.db.ctx.arrow_schema().clone();
+        let fields = &schema.fields()[USER_COLUMN_OFFSET..];
+        let primary_index = self.primary_index().await;
+        let reader = StreamReader::try_new(Cursor::new(request.ipc), None)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        let mut records = Vec::new();
+        for batch in reader {
+            let batch = batch.map_err(|err| Status::invalid_argument(err.to_string()))?;
+            let mut columns = Vec::with_capacity(fields.len());
+            for field in fields {
+                let column = match batch.column_by_name(field.name()) {
+                    Some(column) => cast(column, field.data_type())
+                        .map_err(|err| Status::invalid_argument(err.to_string()))?,
+                    None if field.is_nullable() => {
+                        new_null_array(field.data_type(), batch.num_rows())
+                    }
+                    None => {
+                        return Err(Status::invalid_argument(format!(
+                            "no column for the non-nullable field `{}`",
+                            field.name()
+                        )))
+                    }
+                };
+                columns.push(column);
+            }
+            for row in 0..batch.num_rows() {
+                let mut values = Vec::with_capacity(fields.len());
+                for (idx, (field, column)) in fields.iter().zip(&columns).enumerate() {
+                    if column.is_null(row) && (idx == primary_index || !field.is_nullable()) {
+                        return Err(Status::invalid_argument(format!(
+                            "column `{}` can not be null in row {row}",
+                            field.name()
+                        )));
+                    }
+                    values.push(value(column, row)?);
+                }
+                records.push(DynRecord::new(values, primary_index));
+            }
+        }
+
+        let inserted = records.len() as u64;
+        self.db
+            .insert_batch(records.into_iter())
+            .await
+            .map_err(|err| status(err.kind(), err))?;
+        Ok(InsertResponse { inserted })
+    }
+
+    async fn remove(self, request: RemoveRequest) -> Result<RemoveResponse, Status> {
+        let key = self.key(request.key).await?;
+        self.db
+            .remove(key)
+            .await
+            .map_err(|err| status(err.kind(), err))?;
+        Ok(RemoveResponse {})
+    }
+
+    async fn scan(self, request: ScanRequest) -> Result<BoxStream<ScanResponse>, Status> {
+        let lower = self.bound(request.lower).await?;
+        let upper = self.bound(request.upper).await?;
+        let schema = self.db.ctx.arrow_schema();
+        if let Some(name) = request
+            .projection
+            .iter()
+            .find(|name| schema.index_of(name).is_err())
+        {
+            return Err(Status::invalid_argument(format!("unknown column `{name}`")));
+        }
+        let batch_size = request.batch_size.unwrap_or(DEFAULT_SCAN_BATCH_SIZE).max(1) as usize;
+        let db = self.db.clone();
+
+        Ok(Box::pin(stream! {
+            let snapshot = db.snapshot().await;
+            let mut scan = snapshot.scan((lower.as_ref(), upper.as_ref()));
+            if !request.projection.is_empty() {
+                let projection = request.projection.iter().map(String::as_str).collect::<Vec<_>>();
+                scan = scan.projection(&projection);
+            }
+            if let Some(limit) = request.limit {
+                scan = scan.limit(limit as usize);
+            }
+            match scan.into_ipc_stream(batch_size).await {
+                Ok(parts) => {
+                    let mut parts = pin!(parts);
+                    while let Some(part) = parts.next().await {
+                        yield part.map(|ipc| ScanResponse { ipc }).map_err(db_status);
+                    }
+                }
+                Err(err) => yield Err(db_status(err)),
+            }
+        }))
+    }
+
+    /// Index of the primary key among the user columns
+    async fn primary_index(&self) -> usize {
+        self.db
+            .mem_storage
+            .read()
+            .await
+            .record_schema
+            .primary_key_indices()[0]
+            - USER_COLUMN_OFFSET
+    }
+
+    async fn key(&self, key: Option<Key>) -> Result<Value, Status> {
+        let key = key
+            .and_then(|key| key.value)
+            .ok_or_else(|| Status::invalid_argument("missing key"))?;
+        let array: ArrayRef = match key {
+            key::Value::Boolean(key) => Arc::new(BooleanArray::from(vec![key])),
+            key::Value::Int(key) => Arc::new(Int64Array::from(vec![key])),
+            key::Value::UInt(key) => Arc::new(UInt64Array::from(vec![key])),
+            key::Value::String(key) => Arc::new(StringArray::from(vec![key])),
+            key::Value::Binary(key) => Arc::new(BinaryArray::from_vec(vec![key.as_slice()])),
+        };
+        let schema = self.db.ctx.arrow_schema();
+        let field = schema.field(self.primary_index().await + USER_COLUMN_OFFSET);
+        let key = cast(&array, field.data_type())
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        if key.is_null(0) {
+            return Err(Status::invalid_argument(format!(
+                "key can not be cast to {}",
+                field.data_type()
+            )));
+        }
+        value(&key, 0)
+    }
+
+    async fn bound(&self, bound: Option<KeyBound>) -> Result<Bound<Value>, Status> {
+        Ok(match bound {
+            None => Bound::Unbounded,
+            Some(KeyBound { key, inclusive }) => {
+                let key = self.key(key).await?;
+                if inclusive {
+                    Bound::Included(key)
+                } else {
+                    Bound::Excluded(key)
+                }
+            }
+        })
+    }
+}
+
+fn value(column: &ArrayRef, row: usize) -> Result<Value, Status> {
+    ValueRef::from_array_ref(column, row)
+        .map(|value| value.to_owned())
+        .map_err(|err| Status::invalid_argument(err.to_string()))
+}
+
+fn status(kind: ErrorKind, err: impl ToString) -> Status {
+    let code = match kind {
+        ErrorKind::Corruption => Code::DataLoss,
+        ErrorKind::Io => Code::Internal,
+        ErrorKind::StorageTransient => Code::Unavailable,
+        ErrorKind::InvalidArgument => Code::InvalidArgument,
+        ErrorKind::Busy => Code::ResourceExhausted,
+        ErrorKind::Conflict => Code::Aborted,
+    };
+    Status::new(code, err.to_string())
+}
+
+fn db_status(err: crate::DbError) -> Status {
+    status(err.kind(), err)
+}
+
+impl<E> NamedService for TonboServer<E>
+where
+    E: Executor + Send + Sync + 'static,
+{
+    const NAME: &'static str = "tonbo.Tonbo";
+}
+
+impl<E, B> Service<http::Request<B>> for TonboServer<E>
+where
+    E: Executor + Send + Sync + 'static,
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let server = self.clone();
+        let mut grpc = Grpc::new(ProstCodec::default());
+
+        match req.uri().path() {
+            "/tonbo.Tonbo/Get" => {
+                Box::pin(async move { Ok(grpc.unary(Method(server, TonboServer::get), req).await) })
+            }
+            "/tonbo.Tonbo/Insert" => {
+                Box::pin(
+                    async move { Ok(grpc.unary(Method(server, TonboServer::insert), req).await) },
+                )
+            }
+            "/tonbo.Tonbo/Remove" => {
+                Box::pin(
+                    async move { Ok(grpc.unary(Method(server, TonboServer::remove), req).await) },
+                )
+            }
+            "/tonbo.Tonbo/Scan" => Box::pin(async move {
+                Ok(grpc
+                    .server_streaming(Method(server, TonboServer::scan), req)
+                    .await)
+            }),
+            _ => Box::pin(async move {
+                let mut response = http::Response::new(empty_body());
+                let headers = response.headers_mut();
+                headers.insert("grpc-status", (Code::Unimplemented as i32).into());
+                headers.insert(
+                    http::header::CONTENT_TYPE,
+                    http::HeaderValue::from_static("application/grpc"),
+                );
+                Ok(response)
+            }),
+        }
+    }
+}
+
+/// A method of the service, as the unary or server streaming service tonic calls it through
+struct Method<E, F>(TonboServer<E>, F)
+where
+    E: Executor + Send + Sync + 'static;
+
+impl<E, F, Req, Res, Fut> UnaryService<Req> for Method<E, F>
+where
+    E: Executor + Send + Sync + 'static,
+    F: Fn(TonboServer<E>, Req) -> Fut + Clone + Send + 'static,
+    Req: Send + 'static,
+    Fut: Future<Output = Result<Res, Status>> + Send + 'static,
+{
+    type Response = Res;
+    type Future = BoxFuture<Response<Res>, Status>;
+
+    fn call(&mut self, request: Request<Req>) -> Self::Future {
+        let (server, method) = (self.0.clone(), self.1.clone());
+        Box::pin(async move {
+            method(server, request.into_inner())
+                .await
+                .map(Response::new)
+        })
+    }
+}
+
+impl<E, F, Fut> ServerStreamingService<ScanRequest> for Method<E, F>
+where
+    E: Executor + Send + Sync + 'static,
+    F: Fn(TonboServer<E>, ScanRequest) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Result<BoxStream<ScanResponse>, Status>> + Send + 'static,
+{
+    type Response = ScanResponse;
+    type ResponseStream = BoxStream<ScanResponse>;
+    type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, request: Request<ScanRequest>) -> Self::Future {
+        let (server, method) = (self.0.clone(), self.1.clone());
+        Box::pin(async move {
+            method(server, request.into_inner())
+                .await
+                .map(Response::new)
+        })
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{io::Cursor, sync::Arc};
+
+    use arrow::{
+        array::{AsArray, Int64Array, StringArray},
+        datatypes::{DataType, Field, Int32Type, Schema as ArrowSchema},
+        ipc::{reader::StreamReader, writer::StreamWriter},
+        record_batch::RecordBatch,
+    };
+    use fusio::path::Path;
+    use futures_util::StreamExt;
+    use tempfile::TempDir;
+    use tonic::Code;
+
+    use super::{
+        key, GetRequest, InsertRequest, Key, KeyBound, RemoveRequest, ScanRequest, TonboServer,
+    };
+    use crate::{dyn_schema, executor::tokio::TokioExecutor, record::DynRecord, DbOption, DB};
+
+    fn int_key(key: i64) -> Option<Key> {
+        Some(Key {
+            value: Some(key::Value::Int(key)),
+        })
+    }
+
+    fn read_ipc(ipc: Vec<u8>) -> Vec<RecordBatch> {
+        StreamReader::try_new(Cursor::new(ipc), None)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serve_records() {
+        let temp_dir = TempDir::new().unwrap();
+        let schema = dyn_schema!(("id", Int32, false), ("name", Utf8, true), 0);
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &schema,
+        );
+        let db: DB<DynRecord, TokioExecutor> = DB::new(option, TokioExecutor::default(), schema)
+            .await
+            .unwrap();
+        let server = TonboServer::new(Arc::new(db));
+
+        // ids are cast from Int64 to the Int32 of the schema
+        let input = RecordBatch::try_new(
+            Arc::new(ArrowSchema::new(vec![
+                Field::new("id", DataType::Int64, false),
+                Field::new("name", DataType::Utf8, true),
+            ])),
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2, 3, 4])),
+                Arc::new(StringArray::from(vec![
+                    Some("a"),
+                    None,
+                    Some("c"),
+                    Some("d"),
+                ])),
+            ],
+        )
+        .unwrap();
+        let mut writer = StreamWriter::try_new(Vec::new(), &input.schema()).unwrap();
+        writer.write(&input).unwrap();
+        let response = server
+            .clone()
+            .insert(InsertRequest {
+                ipc: writer.into_inner().unwrap(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(response.inserted, 4);
+        server
+            .clone()
+            .remove(RemoveRequest { key: int_key(2) })
+            .await
+            .unwrap();
+
+        let record = read_ipc(
+            server
+                .clone()
+                .get(GetRequest { key: int_key(3) })
+                .await
+                .unwrap()
+                .ipc,
+        );
+        assert_eq!(record.iter().map(RecordBatch::num_rows).sum::<usize>(), 1);
+        assert_eq!(record[0].column(1).as_string::<i32>().value(0), "c");
+        let missing = read_ipc(
+            server
+                .clone()
+                .get(GetRequest { key: int_key(2) })
+                .await
+                .unwrap()
+                .ipc,
+        );
+        assert_eq!(missing.iter().map(RecordBatch::num_rows).sum::<usize>(), 0);
+
+        let mut parts = server
+            .clone()
+            .scan(ScanRequest {
+                lower: Some(KeyBound {
+                    key: int_key(1),
+                    inclusive: false,
+                }),
+                upper: None,
+                limit: None,
+                projection: vec!["id".into()],
+                batch_size: Some(1),
+            })
+            .await
+            .unwrap();
+        let mut ipc = Vec::new();
+        while let Some(part) = parts.next().await {
+            ipc.extend(part.unwrap().ipc);
+        }
+        let ids = read_ipc(ipc)
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(0)
+                    .as_primitive::<Int32Type>()
+                    .values()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![3, 4]);
+
+        let err = server
+            .clone()
+            .get(GetRequest {
+                key: Some(Key {
+                    value: Some(key::Value::String("one".into())),
+                }),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,190 @@
+//! Approximate distribution of the keys of a [`DB`](crate::DB), as reported by
+//! [`DB::key_histogram`](crate::DB::key_histogram)
+
+/// Number of keys sampled from each memtable
+pub(crate) const MEMTABLE_SAMPLES: usize = 1024;
+
+/// A range of the keyspace with the approximate number of entries and bytes in it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBucket<K> {
+    /// Smallest known key of the bucket
+    pub lower: K,
+    /// Largest known key of the bucket
+    pub upper: K,
+    /// Approximate number of entries, counting every version and removal of a key
+    pub keys: u64,
+    /// Approximate size in bytes on storage
+    pub bytes: u64,
+}
+
+/// Entries and bytes known to lie between `lower` and `upper`, e.g. those of an SSTable
+pub(crate) struct Span<K> {
+    pub(crate) lower: K,
+    pub(crate) upper: K,
+    pub(crate) keys: u64,
+    pub(crate) bytes: u64,
+}
+
+/// Spans of the keys sampled from a memtable of `len` entries, which takes the step between
+/// samples and returns the keys in order. Every sample stands for the entries up to the next one,
+/// at `entry_bytes` each.
+pub(crate) fn memtable_spans<K, I>(
+    len: usize,
+    sample_keys: impl FnOnce(usize) -> I,
+    entry_bytes: u64,
+) -> impl Iterator<Item = Span<K>>
+where
+    K: Clone,
+    I: Iterator<Item = K>,
+{
+    let step = len.div_ceil(MEMTABLE_SAMPLES).max(1);
+
+    sample_keys(step).enumerate().map(move |(i, key)| {
+        let keys = step.min(len - i * step) as u64;
+        Span {
+            lower: key.clone(),
+            upper: key,
+            keys,
+            bytes: keys * entry_bytes,
+        }
+    })
+}
+
+/// Split the keyspace covered by `spans` into at most `buckets` buckets of about the same number
+/// of entries
+///
+/// Nothing is known about the keys between the bounds of the spans, so the entries of a span are
+/// spread evenly over the bounds of all spans that fall into it.
+pub(crate) fn equi_depth<K: Ord + Clone>(spans: Vec<Span<K>>, buckets: usize) -> Vec<KeyBucket<K>> {
+    if spans.is_empty() || buckets == 0 {
+        return Vec::new();
+    }
+    let mut points = spans
+        .iter()
+        .flat_map(|span| [span.lower.clone(), span.upper.clone()])
+        .collect::<Vec<_>>();
+    points.sort();
+    points.dedup();
+
+    // differences of the entries and bytes per point between neighbouring points
+    let mut diffs = vec![(0.0, 0.0); points.len() + 1];
+    for span in spans.iter() {
+        let first = points.partition_point(|point| *point < span.lower);
+        let last = points.partition_point(|point| *point <= span.upper);
+        let count = (last - first) as f64;
+        let (keys, bytes) = (span.keys as f64 / count, span.bytes as f64 / count);
+        diffs[first].0 += keys;
+        diffs[first].1 += bytes;
+        diffs[last].0 -= keys;
+        diffs[last].1 -= bytes;
+    }
+    let total = spans.iter().map(|span| span.keys as f64).sum::<f64>();
+    let depth = total / buckets as f64;
+
+    let mut histogram = Vec::with_capacity(buckets);
+    let (mut point_keys, mut point_bytes) = (0.0, 0.0);
+    let (mut seen, mut keys, mut bytes) = (0.0, 0.0, 0.0);
+    let mut bounds: Option<(K, K)> = None;
+    for (point, (diff_keys, diff_bytes)) in points.into_iter().zip(diffs) {
+        point_keys += diff_keys;
+        point_bytes += diff_bytes;
+        seen += point_keys;
+        keys += point_keys;
+        bytes += point_bytes;
+        match &mut bounds {
+            Some((_, upper)) => *upper = point,
+            None => bounds = Some((point.clone(), point)),
+        }
+
+        if histogram.len() + 1 < buckets && seen >= depth * (histogram.len() + 1) as f64 {
+            if let Some((lower, upper)) = bounds.take() {
+                histogram.push(KeyBucket {
+                    lower,
+                    upper,
+                    keys: keys.round() as u64,
+                    bytes: bytes.round() as u64,
+                });
+            }
+            (keys, bytes) = (0.0, 0.0);
+        }
+    }
+    if let Some((lower, upper)) = bounds {
+        histogram.push(KeyBucket {
+            lower,
+            upper,
+            keys: keys.round() as u64,
+            bytes: bytes.round() as u64,
+        });
+    }
+
+    histogram
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::{equi_depth, Span};
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    #[test]
+    fn overlapping_spans() {
+        let span = |lower, upper, keys| Span {
+            lower,
+            upper,
+            keys,
+            bytes: keys * 10,
+        };
+        // the 90 entries of 1..=9 are spread over 1, 5 and 9
+        let histogram = equi_depth(vec![span(1, 9, 90), span(5, 5, 30)], 2);
+
+        let buckets = histogram
+            .iter()
+            .map(|bucket| (bucket.lower, bucket.upper, bucket.keys, bucket.bytes))
+            .collect::<Vec<_>>();
+        assert_eq!(buckets, vec![(1, 5, 90, 900), (9, 9, 30, 300)]);
+        assert!(equi_depth(Vec::<Span<u32>>::new(), 2).is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn histogram_of_tables_and_memtables() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        let test = |i: u32| Test {
+            vstring: format!("{i:03}"),
+            vu32: i,
+            vbool: None,
+        };
+
+        db.insert_batch((0..100).map(test)).await.unwrap();
+        db.flush().await.unwrap();
+        db.insert_batch((100..200).map(test)).await.unwrap();
+
+        let histogram = db.key_histogram(4).await.unwrap();
+        let buckets = histogram
+            .iter()
+            .map(|bucket| (bucket.lower.as_str(), bucket.upper.as_str(), bucket.keys))
+            .collect::<Vec<_>>();
+        // only the bounds of the table are known, each holding half of its entries
+        assert_eq!(
+            buckets,
+            vec![
+                ("000", "000", 50),
+                ("099", "099", 50),
+                ("100", "149", 50),
+                ("150", "199", 50),
+            ]
+        );
+        assert!(histogram.iter().all(|bucket| bucket.bytes > 0));
+    }
+}
@@ -0,0 +1,172 @@
+//! Tokens of recent writes that must not be applied twice, see
+//! [`DbOption::idempotency_window`](crate::DbOption::idempotency_window)
+
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Mutex,
+};
+
+/// Idempotency tokens of the latest writes of
+/// [`DB::insert_with_token`](crate::DB::insert_with_token)
+///
+/// The window is bounded: once it holds `capacity` tokens, the oldest one is forgotten and a
+/// write that repeats it is applied again. Tokens are only kept in memory, so a reopened DB starts
+/// with an empty window.
+pub(crate) struct IdempotencyWindow {
+    capacity: usize,
+    inner: Mutex<IdempotencyWindowInner>,
+}
+
+struct IdempotencyWindowInner {
+    tokens: HashSet<Box<[u8]>>,
+    // Insertion order for evicting the oldest token
+    order: VecDeque<Box<[u8]>>,
+}
+
+impl IdempotencyWindow {
+    /// Create a window of up to `capacity` tokens, a capacity of 0 remembers no token
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::new(IdempotencyWindowInner {
+                tokens: HashSet::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Whether the window remembers no token, as its capacity is 0
+    pub(crate) fn is_empty(&self) -> bool {
+        self.capacity == 0
+    }
+
+    /// Remember `token`, which returns false if it is already in the window
+    pub(crate) fn reserve(&self, token: &[u8]) -> bool {
+        if self.capacity == 0 {
+            return true;
+        }
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("idempotency window lock should not fail");
+        if inner.tokens.contains(token) {
+            return false;
+        }
+        if inner.order.len() >= self.capacity {
+            if let Some(oldest) = inner.order.pop_front() {
+                inner.tokens.remove(&oldest);
+            }
+        }
+        inner.tokens.insert(token.into());
+        inner.order.push_back(token.into());
+
+        true
+    }
+
+    /// Forget `token` of a write that failed, so a retry of it is applied
+    pub(crate) fn release(&self, token: &[u8]) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("idempotency window lock should not fail");
+        if inner.tokens.remove(token) {
+            inner.order.retain(|reserved| reserved.as_ref() != token);
+        }
+    }
+}
+
+impl Default for IdempotencyWindow {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::IdempotencyWindow;
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema,
+        record::test::test_items, tests::Test, transaction::CommitError, DbError, DbOption, DB,
+    };
+
+    #[test]
+    fn evict_oldest_token() {
+        let window = IdempotencyWindow::new(2);
+        assert!(window.reserve(b"a"));
+        assert!(window.reserve(b"b"));
+        assert!(!window.reserve(b"a"));
+
+        assert!(window.reserve(b"c"));
+        // "a" was the oldest token
+        assert!(window.reserve(b"a"));
+        assert!(!window.reserve(b"c"));
+
+        window.release(b"c");
+        assert!(window.reserve(b"c"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn insert_with_token() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .idempotency_window(16);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+
+        let mut items = test_items(0u32..2);
+        let first = items.next().unwrap();
+        let mut redelivered = first.clone();
+        redelivered.vu32 = 100;
+        assert!(db.insert_with_token(first, "offset-0").await.unwrap());
+        assert!(!db.insert_with_token(redelivered, "offset-0").await.unwrap());
+        assert!(db
+            .insert_with_token(items.next().unwrap(), "offset-1")
+            .await
+            .unwrap());
+
+        let vu32 = db
+            .get(&"0".to_string(), |entry| entry.get().vu32)
+            .await
+            .unwrap();
+        assert_eq!(vu32, Some(0));
+        assert!(db
+            .get(&"1".to_string(), |_| Some(()))
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn insert_with_token_without_window() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .idempotency_window(0);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+
+        let record = test_items(0u32..1).next().unwrap();
+        assert!(matches!(
+            db.insert_with_token(record, "offset-0").await,
+            Err(CommitError::Database(DbError::NoIdempotencyWindow))
+        ));
+        assert!(db
+            .get(&"0".to_string(), |_| Some(()))
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
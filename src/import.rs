@@ -0,0 +1,345 @@
+//! Bulk loading of CSV and newline-delimited JSON into a [`DB`] of [`DynRecord`]s
+//!
+//! Input columns are matched to the fields of the [`DynSchema`](crate::record::DynSchema) of the
+//! DB by name: CSV columns by their header (or by position if there is none) and JSON objects by
+//! their keys. Every value is read as a string first and then coerced to the type of its field
+//! with Arrow's [`cast`] rules, so a value that can't be coerced only rejects its own row.
+//! Rejected rows are collected in the [`ImportReport`] instead of failing the whole import.
+
+use std::{
+    io::{self, BufRead, BufReader, Cursor, Read},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{new_null_array, Array, ArrayRef, AsArray},
+    compute::cast,
+    csv,
+    datatypes::{DataType, Field, Schema as ArrowSchema},
+    error::ArrowError,
+    json,
+    record_batch::RecordBatch,
+};
+use thiserror::Error;
+
+use crate::{
+    executor::Executor,
+    magic::USER_COLUMN_OFFSET,
+    record::{DynRecord, Schema, Value, ValueError, ValueRef},
+    transaction::CommitError,
+    DB,
+};
+
+const DEFAULT_IMPORT_BATCH_SIZE: usize = 1024;
+
+/// Input format of an import
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportFormat {
+    Csv { has_header: bool, delimiter: u8 },
+    NdJson,
+}
+
+/// Options of [`DB::import`]
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    format: ImportFormat,
+    batch_size: usize,
+}
+
+impl ImportOptions {
+    /// Import comma separated values with a header line
+    pub fn csv() -> Self {
+        Self {
+            format: ImportFormat::Csv {
+                has_header: true,
+                delimiter: b',',
+            },
+            batch_size: DEFAULT_IMPORT_BATCH_SIZE,
+        }
+    }
+
+    /// Import one JSON object per line
+    pub fn ndjson() -> Self {
+        Self {
+            format: ImportFormat::NdJson,
+            batch_size: DEFAULT_IMPORT_BATCH_SIZE,
+        }
+    }
+
+    /// Whether the first CSV line names the columns. Without a header, the columns must be in the
+    /// order of the fields of the schema.
+    pub fn has_header(mut self, has_header: bool) -> Self {
+        if let ImportFormat::Csv {
+            has_header: header, ..
+        } = &mut self.format
+        {
+            *header = has_header;
+        }
+        self
+    }
+
+    /// Column delimiter of CSV input
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        if let ImportFormat::Csv {
+            delimiter: value, ..
+        } = &mut self.format
+        {
+            *value = delimiter;
+        }
+        self
+    }
+
+    /// Number of rows that are read and inserted at once
+    pub fn batch_size(self, batch_size: usize) -> Self {
+        Self {
+            batch_size: batch_size.max(1),
+            ..self
+        }
+    }
+}
+
+/// A row that was rejected by an import
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowError {
+    /// Index of the row in the input, not counting the CSV header
+    pub row: usize,
+    pub message: String,
+}
+
+/// Outcome of [`DB::import`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Number of inserted rows
+    pub inserted: usize,
+    /// Rows that were rejected
+    pub errors: Vec<RowError>,
+}
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("import io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("import arrow error: {0}")]
+    Arrow(#[from] ArrowError),
+    #[error("import value error: {0}")]
+    Value(#[from] ValueError),
+    #[error("import commit error: {0}")]
+    Commit(#[from] CommitError<DynRecord>),
+    #[error("import input has no column for the non-nullable field `{0}`")]
+    MissingColumn(String),
+}
+
+impl<E> DB<DynRecord, E>
+where
+    E: Executor + Send + Sync + 'static,
+{
+    /// Read CSV or newline-delimited JSON from `reader` and insert its rows.
+    ///
+    /// Rows with values that can't be coerced to their field, or with nulls in non-nullable
+    /// fields, are skipped and reported. Malformed input that can't be split into rows aborts the
+    /// import; rows of earlier batches stay inserted in that case.
+    pub async fn import<I: Read + Send>(
+        &self,
+        reader: I,
+        options: ImportOptions,
+    ) -> Result<ImportReport, ImportError> {
+        let schema = self.ctx.arrow_schema().clone();
+        let fields = &schema.fields()[USER_COLUMN_OFFSET..];
+        let primary_index = self
+            .mem_storage
+            .read()
+            .await
+            .record_schema
+            .primary_key_indices()[0]
+            - USER_COLUMN_OFFSET;
+
+        let mut reader = BufReader::new(reader);
+        let batches: Box<dyn Iterator<Item = Result<RecordBatch, ArrowError>> + Send> =
+            match options.format {
+                ImportFormat::Csv {
+                    has_header,
+                    delimiter,
+                } => {
+                    let names = if has_header {
+                        let mut header = Vec::new();
+                        reader.read_until(b'\n', &mut header)?;
+                        let (header_schema, _) = csv::reader::Format::default()
+                            .with_header(true)
+                            .with_delimiter(delimiter)
+                            .infer_schema(Cursor::new(header), None)?;
+                        header_schema
+                            .fields()
+                            .iter()
+                            .map(|field| field.name().clone())
+                            .collect()
+                    } else {
+                        fields.iter().map(|field| field.name().clone()).collect()
+                    };
+                    Box::new(
+                        csv::ReaderBuilder::new(string_schema(names))
+                            .with_header(false)
+                            .with_delimiter(delimiter)
+                            .with_batch_size(options.batch_size)
+                            .build(reader)?,
+                    )
+                }
+                ImportFormat::NdJson => Box::new(
+                    json::ReaderBuilder::new(string_schema(
+                        fields.iter().map(|field| field.name().clone()).collect(),
+                    ))
+                    .with_coerce_primitive(true)
+                    .with_batch_size(options.batch_size)
+                    .build(reader)?,
+                ),
+            };
+
+        let mut report = ImportReport::default();
+        let mut offset = 0;
+        for batch in batches {
+            let batch = batch?;
+            let mut columns = Vec::with_capacity(fields.len());
+            for field in fields {
+                let column = match batch.column_by_name(field.name()) {
+                    Some(source) => {
+                        let target = cast(source, field.data_type())?;
+                        (Some(source.clone()), target)
+                    }
+                    None if field.is_nullable() => {
+                        (None, new_null_array(field.data_type(), batch.num_rows()))
+                    }
+                    None => return Err(ImportError::MissingColumn(field.name().clone())),
+                };
+                columns.push(column);
+            }
+
+            let mut records = Vec::with_capacity(batch.num_rows());
+            for row in 0..batch.num_rows() {
+                match coerce_row(fields, &columns, row, primary_index)? {
+                    Ok(values) => records.push(DynRecord::new(values, primary_index)),
+                    Err(message) => report.errors.push(RowError {
+                        row: offset + row,
+                        message,
+                    }),
+                }
+            }
+            offset += batch.num_rows();
+
+            if !records.is_empty() {
+                report.inserted += records.len();
+                self.insert_batch(records.into_iter()).await?;
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Schema that reads every column in `names` as nullable string
+fn string_schema(names: Vec<String>) -> Arc<ArrowSchema> {
+    Arc::new(ArrowSchema::new(
+        names
+            .into_iter()
+            .map(|name| Field::new(name, DataType::Utf8, true))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Collect the values of `row`, or the reason it is rejected
+fn coerce_row(
+    fields: &[Arc<Field>],
+    columns: &[(Option<ArrayRef>, ArrayRef)],
+    row: usize,
+    primary_index: usize,
+) -> Result<Result<Vec<Value>, String>, ValueError> {
+    let mut values = Vec::with_capacity(fields.len());
+
+    for (idx, (field, (source, target))) in fields.iter().zip(columns).enumerate() {
+        if target.is_null(row) {
+            if let Some(source) = source.as_ref().filter(|source| !source.is_null(row)) {
+                return Ok(Err(format!(
+                    "can not coerce `{}` to {} in column `{}`",
+                    source.as_string::<i32>().value(row),
+                    field.data_type(),
+                    field.name()
+                )));
+            }
+            if idx == primary_index || !field.is_nullable() {
+                return Ok(Err(format!("column `{}` can not be null", field.name())));
+            }
+        }
+        values.push(ValueRef::from_array_ref(target, row)?.to_owned());
+    }
+
+    Ok(Ok(values))
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::io::Cursor;
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::{ImportOptions, RowError};
+    use crate::{
+        dyn_schema,
+        executor::tokio::TokioExecutor,
+        record::{DynRecord, Value},
+        DbOption, DB,
+    };
+
+    async fn score(db: &DB<DynRecord, TokioExecutor>, id: i64) -> Option<Value> {
+        db.get(&Value::Int64(id), |entry| {
+            Some(entry.get().columns[2].to_owned())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn import_csv_and_ndjson() {
+        let temp_dir = TempDir::new().unwrap();
+        let schema = dyn_schema!(
+            ("id", Int64, false),
+            ("name", Utf8, true),
+            ("score", Float64, true),
+            0
+        );
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &schema,
+        );
+        let db: DB<DynRecord, TokioExecutor> = DB::new(option, TokioExecutor::default(), schema)
+            .await
+            .unwrap();
+
+        let csv = "score,id,name\n1.5,1,alice\n2.0,two,bob\n,3,carol\nabc,4,dave\n";
+        let report = db
+            .import(Cursor::new(csv), ImportOptions::csv().batch_size(2))
+            .await
+            .unwrap();
+        assert_eq!(report.inserted, 2);
+        assert_eq!(
+            report.errors.iter().map(|err| err.row).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert_eq!(score(&db, 1).await, Some(Value::Float64(1.5)));
+        assert_eq!(score(&db, 3).await, Some(Value::Null));
+        assert_eq!(score(&db, 4).await, None);
+
+        let ndjson = "{\"id\": 5, \"name\": \"eve\", \"score\": 3}\n{\"name\": \"frank\"}\n";
+        let report = db
+            .import(Cursor::new(ndjson), ImportOptions::ndjson())
+            .await
+            .unwrap();
+        assert_eq!(report.inserted, 1);
+        assert_eq!(
+            report.errors,
+            vec![RowError {
+                row: 1,
+                message: "column `id` can not be null".into(),
+            }]
+        );
+        assert_eq!(score(&db, 5).await, Some(Value::Float64(3.0)));
+    }
+}
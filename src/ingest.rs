@@ -0,0 +1,284 @@
+//! Ingestion of SSTables that were written outside of a [`DB`](crate::DB)
+//!
+//! [`DB::ingest_parquet`](crate::DB::ingest_parquet) adds Parquet files with the columns of the
+//! schema of the DB, `_null` and `_ts` included, as SSTables of the DB without writing their
+//! records to the WAL and the memtables, e.g. to bulk load data that was prepared elsewhere:
+//!
+//! ```ignore
+//! let report = db.ingest_parquet(&[path_a, path_b], MAX_LEVEL - 1).await?;
+//! ```
+//!
+//! The rows of a file have to be sorted by key and, for the same key, by descending timestamp,
+//! and no timestamp may be later than the latest commit of the DB. The keys of a file must not
+//! be in any other ingested file, SSTable or memtable of the DB, so ingested records neither
+//! shadow other writes of their keys nor are shadowed by them.
+
+use std::{ops::Bound, sync::Arc};
+
+use arrow::datatypes::Schema as ArrowSchema;
+use fusio::{path::Path, DynFs};
+use futures_util::StreamExt;
+use parquet::{
+    arrow::{parquet_to_arrow_schema, ProjectionMask},
+    errors::ParquetError,
+};
+use parquet_lru::NoCache;
+use thiserror::Error;
+
+use crate::{
+    error::{fusio_kind, parquet_kind, ErrorKind},
+    fs::{FileId, FileType},
+    manifest::ManifestStorageError,
+    ondisk::sstable::SsTable,
+    record::{KeyRef, Record, Schema},
+    scope::Scope,
+    version::{schema::field_id, timestamp::Timestamp, MAX_LEVEL},
+};
+
+/// Outcome of [`DB::ingest_parquet`](crate::DB::ingest_parquet)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IngestReport {
+    /// Level the tables were added to
+    pub level: usize,
+    /// Ids of the new SSTables, sorted by their keys
+    pub tables: Vec<FileId>,
+    /// Number of rows of all ingested tables
+    pub rows: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum IngestError {
+    #[error("ingest fusio error: {0}")]
+    Fusio(#[from] fusio::Error),
+    #[error("ingest parquet error: {0}")]
+    Parquet(#[from] ParquetError),
+    #[error("ingest manifest error: {0}")]
+    Manifest(#[from] ManifestStorageError),
+    #[error("level {0} exceeds the max level {MAX_LEVEL}")]
+    InvalidLevel(usize),
+    #[error("columns of {0} are not the columns of the schema")]
+    Schema(Path),
+    #[error("{0} has no rows")]
+    EmptyTable(Path),
+    #[error("rows of {0} are not sorted by key and descending timestamp")]
+    Unsorted(Path),
+    #[error("{path} holds a write at {ts:?}, after the latest commit of the DB")]
+    TooNew { path: Path, ts: Timestamp },
+    #[error("keys of {0} overlap the keys of another ingested file or of the DB")]
+    Overlap(Path),
+}
+
+impl IngestError {
+    pub(crate) fn kind(&self) -> ErrorKind {
+        match self {
+            IngestError::Fusio(err) => fusio_kind(err),
+            IngestError::Parquet(err) => parquet_kind(err),
+            IngestError::Manifest(err) => err.kind(),
+            IngestError::InvalidLevel(_)
+            | IngestError::Schema(_)
+            | IngestError::EmptyTable(_)
+            | IngestError::Unsorted(_)
+            | IngestError::TooNew { .. }
+            | IngestError::Overlap(_) => ErrorKind::InvalidArgument,
+        }
+    }
+}
+
+/// Scope of the file at `path` as a table of a DB of `schema`, with the field ids of
+/// `table_schema`, whose latest commit is at `ts`, with the id `gen`
+pub(crate) async fn inspect<R: Record>(
+    fs: &Arc<dyn DynFs>,
+    path: &Path,
+    gen: FileId,
+    schema: &R::Schema,
+    table_schema: &ArrowSchema,
+    ts: Timestamp,
+) -> Result<Scope<<R::Schema as Schema>::Key>, IngestError> {
+    let file_size = fs
+        .open_options(path, FileType::Parquet.open_options(true))
+        .await?
+        .size()
+        .await?;
+    let metadata = open::<R>(fs, path, gen).await?.metadata().await?;
+    let file_metadata = metadata.file_metadata();
+    let file_schema = parquet_to_arrow_schema(
+        file_metadata.schema_descr(),
+        file_metadata.key_value_metadata(),
+    )?;
+    let expected = schema.arrow_schema().fields();
+    if file_schema.fields().len() != expected.len()
+        || file_schema
+            .fields()
+            .iter()
+            .zip(expected.iter())
+            .any(|(field, expected)| {
+                field.name() != expected.name() || field.data_type() != expected.data_type()
+            })
+    {
+        return Err(IngestError::Schema(path.clone()));
+    }
+    // columns without field ids have the ids of their positions, which are not those of the
+    // columns of the DB once a column was dropped
+    let ids = |schema: &ArrowSchema| {
+        schema
+            .fields()
+            .iter()
+            .enumerate()
+            .map(|(i, field)| field_id(field).unwrap_or(i as u32))
+            .collect::<Vec<_>>()
+    };
+    if ids(&file_schema) != ids(table_schema) {
+        return Err(IngestError::Schema(path.clone()));
+    }
+
+    let mut rows = open::<R>(fs, path, gen)
+        .await?
+        .scan(
+            (Bound::Unbounded, Bound::Unbounded),
+            Timestamp::from(u32::MAX),
+            None,
+            ProjectionMask::all(),
+            None,
+            schema.primary_key_indices(),
+        )
+        .await?;
+    let mut range: Option<(_, _)> = None;
+    let (mut count, mut tombstones, mut last_ts) = (0, 0, None);
+    while let Some(row) = rows.next().await {
+        let row = row?;
+        let (key, row_ts) = (row.key().to_key(), row.ts());
+        if row_ts > ts {
+            return Err(IngestError::TooNew {
+                path: path.clone(),
+                ts: row_ts,
+            });
+        }
+        range = Some(match range {
+            Some((min, max)) => {
+                // the same key again has to come with an earlier write
+                let sorted = max < key || (max == key && last_ts.is_some_and(|ts| row_ts < ts));
+                if !sorted {
+                    return Err(IngestError::Unsorted(path.clone()));
+                }
+                (min, key)
+            }
+            None => (key.clone(), key),
+        });
+        last_ts = Some(row_ts);
+        count += 1;
+        if row.get().is_none() {
+            tombstones += 1;
+        }
+    }
+    let (min, max) = range.ok_or_else(|| IngestError::EmptyTable(path.clone()))?;
+
+    Ok(Scope {
+        min,
+        max,
+        gen,
+        wal_ids: None,
+        file_size,
+        rows: count,
+        tombstones,
+        // the records of ingested tables are checked when expired records are removed next
+        expires: None,
+    })
+}
+
+// Every read consumes the table, so each of them opens the file
+async fn open<R: Record>(
+    fs: &Arc<dyn DynFs>,
+    path: &Path,
+    gen: FileId,
+) -> Result<SsTable<R>, IngestError> {
+    let file = fs
+        .open_options(path, FileType::Parquet.open_options(true))
+        .await?;
+    Ok(SsTable::open(Arc::new(NoCache::default()), gen, file).await?)
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::IngestError;
+    use crate::{
+        error::ErrorKind, executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema,
+        record::test::test_items, tests::Test, version::MAX_LEVEL, DbError, DbOption, DB,
+    };
+
+    async fn open(dir: &TempDir) -> DB<Test, TokioExecutor> {
+        DB::new(
+            DbOption::new(Path::from_filesystem_path(dir.path()).unwrap(), &TestSchema),
+            TokioExecutor::default(),
+            TestSchema,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ingest_parquet() {
+        let source_dir = TempDir::new().unwrap();
+        {
+            let source = open(&source_dir).await;
+            for item in test_items(0u32..10) {
+                source.insert(item).await.unwrap();
+            }
+            source.remove("5".to_string()).await.unwrap();
+            source.flush().await.unwrap();
+        }
+        let table = std::fs::read_dir(source_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().is_some_and(|ext| ext == "parquet"))
+            .unwrap();
+        let table = Path::from_filesystem_path(table).unwrap();
+
+        // the writes of the table are later than the latest commit of a new DB
+        let empty_dir = TempDir::new().unwrap();
+        let err = open(&empty_dir)
+            .await
+            .ingest_parquet(&[table.clone()], 0)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DbError::Ingest(IngestError::TooNew { .. })));
+
+        let temp_dir = TempDir::new().unwrap();
+        let db = open(&temp_dir).await;
+        for i in 0..12 {
+            db.insert(Test {
+                vstring: format!("x{i}"),
+                vu32: i,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        let report = db
+            .ingest_parquet(&[table.clone()], MAX_LEVEL - 1)
+            .await
+            .unwrap();
+        assert_eq!(report.level, MAX_LEVEL - 1);
+        assert_eq!(report.rows, 10);
+        let version = db.ctx.manifest().current().await;
+        assert_eq!(version.level_slice[MAX_LEVEL - 1].len(), 1);
+        assert_eq!(version.level_slice[MAX_LEVEL - 1][0].gen, report.tables[0]);
+        assert_eq!(version.level_slice[MAX_LEVEL - 1][0].tombstones, 1);
+        drop(version);
+
+        for (key, expected) in [("3", Some(3)), ("5", None), ("x1", Some(1))] {
+            let vu32 = db
+                .get(&key.to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap();
+            assert_eq!(vu32, expected, "key {key}");
+        }
+
+        // the keys of the table are in the DB now
+        let err = db.ingest_parquet(&[table], 0).await.unwrap_err();
+        assert!(matches!(err, DbError::Ingest(IngestError::Overlap(_))));
+        assert_eq!(err.kind(), ErrorKind::InvalidArgument);
+    }
+}
@@ -3,6 +3,7 @@ use std::{mem, sync::Arc};
 use fusio::DynFs;
 
 use crate::{
+    context::Context,
     fs::FileId,
     inmem::{immutable::ImmutableMemTable, mutable::MutableMemTable},
     record::{Record, Schema as RecordSchema},
@@ -12,12 +13,14 @@ use crate::{
 /// Flush mutable memtable to immutable and return owned batches ready for compaction.
 ///
 /// This function performs only the minimal critical section work while holding
-/// the `DbStorage` write lock: it converts the current mutable into an
-/// immutable batch if needed, determines how many immutables to flush, then
-/// drains those immutables from storage and returns ownership to the caller.
-/// Heavy I/O and merging should happen after releasing the lock.
+/// the `DbStorage` write lock: it folds the merge operands of the mutable into
+/// records and converts it into an immutable batch if needed, determines how
+/// many immutables to flush, then drains those immutables from storage and
+/// returns ownership to the caller. Heavy I/O and merging should happen after
+/// releasing the lock.
 pub(crate) async fn minor_flush<R>(
     db_storage: &mut crate::DbStorage<R>,
+    ctx: &Context<R>,
     base_fs: Arc<dyn DynFs>,
     immutable_chunk_num: usize,
     immutable_chunk_max_num: usize,
@@ -41,6 +44,7 @@ where
 
     // Add the mutable memtable into the immutable memtable
     if !db_storage.mutable.is_empty() {
+        db_storage.fold_operands(ctx).await?;
         let trigger_clone = db_storage.trigger.clone();
 
         // Replace mutable memtable with new memtable
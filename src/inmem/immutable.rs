@@ -1,7 +1,13 @@
-use std::{collections::BTreeMap, mem::transmute, ops::Bound, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    mem::{size_of, transmute},
+    ops::Bound,
+    sync::Arc,
+};
 
 use arrow::{array::RecordBatch, datatypes::Schema as ArrowSchema};
 use crossbeam_skiplist::SkipMap;
+use fusio_log::Encode;
 use parquet::arrow::ProjectionMask;
 
 use crate::{
@@ -19,6 +25,8 @@ where
 {
     data: A,
     index: BTreeMap<Ts<<<A::Record as Record>::Schema as Schema>::Key>, u32>,
+    // Approximate bytes of the arrays and the index
+    bytes: usize,
 }
 
 impl<A> ImmutableMemTable<A>
@@ -32,18 +40,22 @@ where
     ) -> Self {
         let mut index = BTreeMap::new();
         let mut builder = A::builder(schema, mutable.len());
+        let mut bytes = 0;
 
         for (offset, (key, value)) in mutable.into_iter().enumerate() {
             builder.push(
                 Ts::new(key.value.as_key_ref(), key.ts),
                 value.as_ref().map(Record::as_record_ref),
             );
+            bytes += size_of::<(Ts<<<A::Record as Record>::Schema as Schema>::Key>, u32)>()
+                + key.value.size();
             index.insert(key, offset as u32);
         }
 
         let data = builder.finish(None);
+        bytes += data.as_record_batch().get_array_memory_size();
 
-        Self { data, index }
+        Self { data, index, bytes }
     }
 }
 
@@ -56,6 +68,11 @@ where
         self.data.as_record_batch()
     }
 
+    /// Approximate bytes of the arrays and the key index of the memtable
+    pub(crate) fn memory_size(&self) -> usize {
+        self.bytes
+    }
+
     pub(crate) fn scan<'scan>(
         &'scan self,
         range: (
@@ -119,6 +136,89 @@ where
             .next()
             .is_some()
     }
+
+    /// Number of entries, counting every version of a key
+    pub(crate) fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Every `step`th key in key order, counting every version of a key
+    pub(crate) fn sample_keys(
+        &self,
+        step: usize,
+    ) -> impl Iterator<Item = <<A::Record as Record>::Schema as Schema>::Key> + '_ {
+        self.index.keys().step_by(step).map(|key| key.value.clone())
+    }
+
+    /// First key in `range` in `order`, whatever the timestamps and values of its versions
+    pub(crate) fn edge_key(
+        &self,
+        range: (
+            Bound<&<<A::Record as Record>::Schema as Schema>::Key>,
+            Bound<&<<A::Record as Record>::Schema as Schema>::Key>,
+        ),
+        order: Option<Order>,
+    ) -> Option<<<A::Record as Record>::Schema as Schema>::Key> {
+        if !self.meets_range(range) {
+            return None;
+        }
+        let lower = match range.0 {
+            Bound::Included(key) => Bound::Included(TsRef::new(key, u32::MAX.into())),
+            Bound::Excluded(key) => Bound::Excluded(TsRef::new(key, EPOCH)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let upper = match range.1 {
+            Bound::Included(key) => Bound::Included(TsRef::new(key, EPOCH)),
+            Bound::Excluded(key) => Bound::Excluded(TsRef::new(key, u32::MAX.into())),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        let mut keys = self
+            .index
+            .range::<TsRef<<<A::Record as Record>::Schema as Schema>::Key>, _>((lower, upper))
+            .map(|(key, _)| key);
+        let key = if order == Some(Order::Desc) {
+            keys.next_back()
+        } else {
+            keys.next()
+        };
+        key.map(|key| key.value.clone())
+    }
+
+    /// Whether any version of a key in `range` is in the memtable
+    pub(crate) fn meets_range(
+        &self,
+        range: (
+            Bound<&<<A::Record as Record>::Schema as Schema>::Key>,
+            Bound<&<<A::Record as Record>::Schema as Schema>::Key>,
+        ),
+    ) -> bool {
+        if let (
+            Bound::Included(start) | Bound::Excluded(start),
+            Bound::Included(end) | Bound::Excluded(end),
+        ) = range
+        {
+            let inclusive = matches!(range, (Bound::Included(_), Bound::Included(_)));
+            if start > end || (start == end && !inclusive) {
+                return false;
+            }
+        }
+        let lower = match range.0 {
+            Bound::Included(key) => Bound::Included(TsRef::new(key, u32::MAX.into())),
+            Bound::Excluded(key) => Bound::Excluded(TsRef::new(key, EPOCH)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let upper = match range.1 {
+            Bound::Included(key) => Bound::Included(TsRef::new(key, EPOCH)),
+            Bound::Excluded(key) => Bound::Excluded(TsRef::new(key, u32::MAX.into())),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        self.index
+            .range::<TsRef<<<A::Record as Record>::Schema as Schema>::Key>, _>((lower, upper))
+            .next()
+            .is_some()
+    }
 }
 
 pub(crate) struct ImmutableScan<'iter, R>
@@ -191,7 +291,11 @@ pub(crate) mod tests {
         datatypes::{ArrowPrimitiveType, DataType, Field, Schema as ArrowSchema, UInt32Type},
     };
     use once_cell::sync::Lazy;
-    use parquet::{arrow::ProjectionMask, format::SortingColumn, schema::types::ColumnPath};
+    use parquet::{
+        arrow::{ArrowSchemaConverter, ProjectionMask},
+        format::SortingColumn,
+        schema::types::{ColumnPath, SchemaDescriptor},
+    };
 
     use crate::{
         magic,
@@ -252,6 +356,16 @@ pub(crate) mod tests {
             });
             (&PATHS[..], &SORTING[..])
         }
+
+        fn parquet_schema(&self) -> &SchemaDescriptor {
+            static SCHEMA: Lazy<SchemaDescriptor> = Lazy::new(|| {
+                ArrowSchemaConverter::new()
+                    .convert(TestSchema.arrow_schema())
+                    .unwrap()
+            });
+
+            &SCHEMA
+        }
     }
 
     #[derive(Debug)]
@@ -1,11 +1,19 @@
-use std::{ops::Bound, sync::Arc};
+use std::{
+    mem::size_of,
+    ops::Bound,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 use async_lock::Mutex;
 use crossbeam_skiplist::{map::Entry, SkipMap};
 use fusio::DynFs;
+use fusio_log::Encode;
 
 use crate::{
-    fs::{generate_file_id, FileId},
+    fs::FileId,
     inmem::immutable::ImmutableMemTable,
     option::Order,
     record::{KeyRef, Record, Schema},
@@ -78,11 +86,34 @@ where
     }
 }
 
+/// Merge operands of a [`MutableMemTable`] in a key range, see [`MutableMemTable::scan_operands`]
+pub(crate) struct OperandScan<'scan, R>
+where
+    R: Record,
+{
+    iter: Box<dyn Iterator<Item = Entry<'scan, Ts<<R::Schema as Schema>::Key>, R>> + Send + 'scan>,
+}
+
+impl<'scan, R> Iterator for OperandScan<'scan, R>
+where
+    R: Record,
+{
+    type Item = Entry<'scan, Ts<<R::Schema as Schema>::Key>, R>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
 pub(crate) struct MutableMemTable<R>
 where
     R: Record,
 {
     data: SkipMap<Ts<<R::Schema as Schema>::Key>, Option<R>>,
+    // Operands of `DB::merge` that are not folded into records yet
+    operands: SkipMap<Ts<<R::Schema as Schema>::Key>, R>,
+    // Approximate bytes of the entries in `data` and `operands`
+    bytes: AtomicUsize,
     wal: Option<Mutex<WalFile<R>>>,
     trigger: Arc<dyn FreezeTrigger<R>>,
     schema: Arc<R::Schema>,
@@ -100,7 +131,7 @@ where
     ) -> Result<Self, fusio::Error> {
         let mut wal = None;
         if option.use_wal {
-            let file_id = generate_file_id();
+            let file_id = option.generate_file_id();
 
             wal = Some(Mutex::new(
                 WalFile::<R>::new(
@@ -115,6 +146,8 @@ where
 
         Ok(Self {
             data: Default::default(),
+            operands: Default::default(),
+            bytes: AtomicUsize::new(0),
             wal,
             trigger,
             schema,
@@ -170,20 +203,132 @@ where
                 .map_err(|e| DbError::WalWrite(Box::new(e)))?;
         }
 
+        Ok(self.insert_entry(record_entry))
+    }
+
+    /// Append the entries of a commit at `ts`, logging them to the WAL with a single write of
+    /// `First`, `Middle` and `Last` entries, or a `Full` entry if there is only one
+    pub(crate) async fn append_commit(
+        &self,
+        entries: Vec<(<R::Schema as Schema>::Key, Option<R>)>,
+        ts: Timestamp,
+    ) -> Result<WriteResult, DbError> {
+        let last = entries.len().saturating_sub(1);
+        let record_entries = entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, (key, value))| {
+                let log_ty = match i {
+                    _ if last == 0 => LogType::Full,
+                    0 => LogType::First,
+                    _ if i == last => LogType::Last,
+                    _ => LogType::Middle,
+                };
+                Log::new(Ts::new(key, ts), value, Some(log_ty))
+            })
+            .collect::<Vec<_>>();
+        if let Some(wal) = &self.wal {
+            wal.lock()
+                .await
+                .write_batch(&record_entries)
+                .await
+                .map_err(|e| DbError::WalWrite(Box::new(e)))?;
+        }
+
+        let mut result = WriteResult::Continue;
+        for record_entry in record_entries {
+            if self.insert_entry(record_entry).needs_compaction() {
+                result = WriteResult::NeedCompaction;
+            }
+        }
+        Ok(result)
+    }
+
+    fn insert_entry(&self, record_entry: Log<R>) -> WriteResult {
+        let bytes = size_of::<(Ts<<R::Schema as Schema>::Key>, Option<R>)>()
+            + record_entry.key.value.size()
+            + record_entry.value.as_ref().map_or(0, Record::size);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
         let entry = self.data.insert(record_entry.key, record_entry.value);
 
-        Ok(
-            if entry
-                .value()
-                .as_ref()
-                .map(|v| self.trigger.check_if_exceed(v))
-                .unwrap_or(false)
-            {
-                WriteResult::NeedCompaction
-            } else {
-                WriteResult::Continue
-            },
-        )
+        if entry
+            .value()
+            .as_ref()
+            .map(|v| self.trigger.check_if_exceed(v))
+            .unwrap_or(false)
+        {
+            WriteResult::NeedCompaction
+        } else {
+            WriteResult::Continue
+        }
+    }
+
+    /// Append `operand` of [`DB::merge`](crate::DB::merge) at `ts`, logged to the WAL as a commit
+    /// of its own
+    pub(crate) async fn merge(&self, operand: R, ts: Timestamp) -> Result<WriteResult, DbError> {
+        let record_entry = Log::new(
+            Ts::new(operand.key().to_key(), ts),
+            Some(operand),
+            Some(LogType::Merge),
+        );
+        if let Some(wal) = &self.wal {
+            wal.lock()
+                .await
+                .write(&record_entry)
+                .await
+                .map_err(|e| DbError::WalWrite(Box::new(e)))?;
+        }
+
+        let Log { key, value, .. } = record_entry;
+        Ok(value.map_or(WriteResult::Continue, |operand| {
+            self.insert_operand(key, operand)
+        }))
+    }
+
+    /// Append `operand` at `ts` without logging it, e.g. when it is recovered from the WAL
+    pub(crate) fn recover_operand(&self, operand: R, ts: Timestamp) -> WriteResult {
+        self.insert_operand(Ts::new(operand.key().to_key(), ts), operand)
+    }
+
+    fn insert_operand(&self, key: Ts<<R::Schema as Schema>::Key>, operand: R) -> WriteResult {
+        let bytes =
+            size_of::<(Ts<<R::Schema as Schema>::Key>, R)>() + key.value.size() + operand.size();
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        let entry = self.operands.insert(key, operand);
+
+        if self.trigger.check_if_exceed(entry.value()) {
+            WriteResult::NeedCompaction
+        } else {
+            WriteResult::Continue
+        }
+    }
+
+    /// The merge operands of `key` at or before `ts`, newest first
+    pub(crate) fn operands(
+        &self,
+        key: &<R::Schema as Schema>::Key,
+        ts: Timestamp,
+    ) -> Vec<Entry<'_, Ts<<R::Schema as Schema>::Key>, R>> {
+        if self.operands.is_empty() {
+            return Vec::new();
+        }
+        self.operands
+            .range::<TsRef<<R::Schema as Schema>::Key>, _>((
+                Bound::Included(TsRef::new(key, ts)),
+                Bound::Included(TsRef::new(key, EPOCH)),
+            ))
+            .collect()
+    }
+
+    /// Every merge operand, with the operands of each key oldest first
+    pub(crate) fn operands_oldest_first(
+        &self,
+    ) -> impl Iterator<Item = Entry<'_, Ts<<R::Schema as Schema>::Key>, R>> + '_ {
+        self.operands.iter().rev()
+    }
+
+    pub(crate) fn has_operands(&self) -> bool {
+        !self.operands.is_empty()
     }
 
     pub(crate) fn get(
@@ -208,18 +353,7 @@ where
         ts: Timestamp,
         order: Option<Order>,
     ) -> MutableScan<'scan, R> {
-        let lower = match range.0 {
-            Bound::Included(key) => Bound::Included(TsRef::new(key, ts)),
-            Bound::Excluded(key) => Bound::Excluded(TsRef::new(key, EPOCH)),
-            Bound::Unbounded => Bound::Unbounded,
-        };
-        let upper = match range.1 {
-            Bound::Included(key) => Bound::Included(TsRef::new(key, EPOCH)),
-            Bound::Excluded(key) => Bound::Excluded(TsRef::new(key, ts)),
-            Bound::Unbounded => Bound::Unbounded,
-        };
-
-        let range_iter: MutableRange<'scan, R> = self.data.range((lower, upper));
+        let range_iter: MutableRange<'scan, R> = self.data.range(scan_bounds(range, ts));
 
         let boxed_iter: Box<dyn Iterator<Item = _> + Send + 'scan> = if order == Some(Order::Desc) {
             Box::new(range_iter.rev())
@@ -230,18 +364,47 @@ where
         MutableScan::new(boxed_iter)
     }
 
+    /// The merge operands in `range` at or before `ts`, in the order of a [`Self::scan`]
+    pub(crate) fn scan_operands<'scan>(
+        &'scan self,
+        range: (
+            Bound<&'scan <R::Schema as Schema>::Key>,
+            Bound<&'scan <R::Schema as Schema>::Key>,
+        ),
+        ts: Timestamp,
+        order: Option<Order>,
+    ) -> OperandScan<'scan, R> {
+        let range_iter = self
+            .operands
+            .range::<TsRef<<R::Schema as Schema>::Key>, _>(scan_bounds(range, ts));
+
+        OperandScan {
+            iter: if order == Some(Order::Desc) {
+                Box::new(range_iter.rev())
+            } else {
+                Box::new(range_iter)
+            },
+        }
+    }
+
     pub(crate) fn is_empty(&self) -> bool {
-        self.data.is_empty()
+        self.data.is_empty() && self.operands.is_empty()
     }
 
     pub(crate) fn check_conflict(&self, key: &<R::Schema as Schema>::Key, ts: Timestamp) -> bool {
+        let range = (
+            Bound::Excluded(TsRef::new(key, u32::MAX.into())),
+            Bound::Excluded(TsRef::new(key, ts)),
+        );
         self.data
-            .range::<TsRef<<R::Schema as Schema>::Key>, _>((
-                Bound::Excluded(TsRef::new(key, u32::MAX.into())),
-                Bound::Excluded(TsRef::new(key, ts)),
-            ))
+            .range::<TsRef<<R::Schema as Schema>::Key>, _>(range)
             .next()
             .is_some()
+            || self
+                .operands
+                .range::<TsRef<<R::Schema as Schema>::Key>, _>(range)
+                .next()
+                .is_some()
     }
 
     pub(crate) async fn into_immutable(
@@ -253,6 +416,10 @@ where
         ),
         fusio_log::error::LogError,
     > {
+        debug_assert!(
+            self.operands.is_empty(),
+            "merge operands must be folded before freezing"
+        );
         let mut file_id = None;
 
         if let Some(wal) = self.wal {
@@ -280,30 +447,174 @@ impl<R> MutableMemTable<R>
 where
     R: Record,
 {
-    #[allow(unused)]
     pub(crate) fn len(&self) -> usize {
         self.data.len()
     }
+
+    /// Approximate bytes of the keys and records in the memtable
+    pub(crate) fn memory_size(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Every `step`th key in key order, counting every version of a key
+    pub(crate) fn sample_keys(
+        &self,
+        step: usize,
+    ) -> impl Iterator<Item = <R::Schema as Schema>::Key> + '_ {
+        self.data
+            .iter()
+            .step_by(step)
+            .map(|entry| entry.key().value.clone())
+    }
+
+    /// First key in `range` in `order`, whatever the timestamps and values of its versions
+    pub(crate) fn edge_key(
+        &self,
+        range: (
+            Bound<&<R::Schema as Schema>::Key>,
+            Bound<&<R::Schema as Schema>::Key>,
+        ),
+        order: Option<Order>,
+    ) -> Option<<R::Schema as Schema>::Key> {
+        let lower = match range.0 {
+            Bound::Included(key) => Bound::Included(TsRef::new(key, u32::MAX.into())),
+            Bound::Excluded(key) => Bound::Excluded(TsRef::new(key, EPOCH)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let upper = match range.1 {
+            Bound::Included(key) => Bound::Included(TsRef::new(key, EPOCH)),
+            Bound::Excluded(key) => Bound::Excluded(TsRef::new(key, u32::MAX.into())),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        let mut entries = self
+            .data
+            .range::<TsRef<<R::Schema as Schema>::Key>, _>((lower, upper));
+        let mut operands = self
+            .operands
+            .range::<TsRef<<R::Schema as Schema>::Key>, _>((lower, upper));
+        if order == Some(Order::Desc) {
+            let entry = entries.next_back().map(|entry| entry.key().value.clone());
+            let operand = operands.next_back().map(|entry| entry.key().value.clone());
+            entry.max(operand)
+        } else {
+            let entry = entries.next().map(|entry| entry.key().value.clone());
+            let operand = operands.next().map(|entry| entry.key().value.clone());
+            match (entry, operand) {
+                (Some(entry), Some(operand)) => Some(entry.min(operand)),
+                (entry, operand) => entry.or(operand),
+            }
+        }
+    }
+}
+
+// Bounds of the versions at or before `ts` of the keys in `range`
+fn scan_bounds<'scan, K>(
+    range: (Bound<&'scan K>, Bound<&'scan K>),
+    ts: Timestamp,
+) -> (Bound<&'scan TsRef<K>>, Bound<&'scan TsRef<K>>) {
+    let lower = match range.0 {
+        Bound::Included(key) => Bound::Included(TsRef::new(key, ts)),
+        Bound::Excluded(key) => Bound::Excluded(TsRef::new(key, EPOCH)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    let upper = match range.1 {
+        Bound::Included(key) => Bound::Included(TsRef::new(key, EPOCH)),
+        Bound::Excluded(key) => Bound::Excluded(TsRef::new(key, ts)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    (lower, upper)
 }
 
 #[cfg(all(test, feature = "tokio"))]
 mod tests {
-    use std::{ops::Bound, sync::Arc};
+    use std::{ops::Bound, pin::pin, sync::Arc};
 
+    #[cfg(feature = "dyn-record")]
     use arrow::datatypes::DataType as ArrayDataType;
     use fusio::{disk::TokioFs, path::Path, DynFs};
+    use fusio_log::FsOptions;
+    use futures_util::StreamExt;
 
     use super::MutableMemTable;
+    #[cfg(feature = "dyn-record")]
+    use crate::record::{DynRecord, DynSchema, DynamicField, Value};
     use crate::{
         inmem::immutable::tests::TestSchema,
-        record::{test::StringSchema, DynRecord, DynSchema, DynamicField, Record, Value},
+        record::{test::StringSchema, Record},
         tests::{Test, TestRef},
         trigger::TriggerFactory,
         version::timestamp::Ts,
-        wal::log::LogType,
+        wal::{log::LogType, Recovered, WalFile},
         DbOption,
     };
 
+    #[tokio::test]
+    async fn append_commit_writes_wal_once() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fs = Arc::new(TokioFs) as Arc<dyn DynFs>;
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &StringSchema,
+        );
+        fs.create_dir_all(&option.wal_dir_path()).await.unwrap();
+
+        let trigger = TriggerFactory::create(option.trigger_type);
+        let mem_table =
+            MutableMemTable::<String>::new(&option, trigger, fs.clone(), Arc::new(StringSchema))
+                .await
+                .unwrap();
+        mem_table
+            .append_commit(
+                vec![
+                    ("a".to_string(), Some("a".to_string())),
+                    ("b".to_string(), None),
+                    ("c".to_string(), Some("c".to_string())),
+                ],
+                1_u32.into(),
+            )
+            .await
+            .unwrap();
+        mem_table
+            .append_commit(vec![("d".to_string(), Some("d".to_string()))], 2_u32.into())
+            .await
+            .unwrap();
+        assert_eq!(mem_table.len(), 4);
+        assert!(mem_table.get(&"b".to_string(), 1_u32.into()).is_some());
+
+        let (file_id, _) = mem_table.into_immutable().await.unwrap();
+        let path = option.wal_path(file_id.unwrap());
+
+        // every commit is a single batch of the log
+        let mut batches = pin!(WalFile::<String>::recover(FsOptions::Local, path.clone()).await);
+        let mut lens = Vec::new();
+        while let Some(batch) = batches.next().await {
+            lens.push(batch.unwrap().len());
+        }
+        assert_eq!(lens, vec![3, 1]);
+
+        let commits = WalFile::<String>::recover_commits(FsOptions::Local, path)
+            .await
+            .unwrap();
+        let commits = commits
+            .into_iter()
+            .map(|(ts, commit)| {
+                let Recovered::Commit(entries) = commit else {
+                    unreachable!("no merge operand was written")
+                };
+                let keys = entries.into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+                (u32::from(ts), keys)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            commits,
+            vec![
+                (1, vec!["a".to_string(), "b".to_string(), "c".to_string()]),
+                (2, vec!["d".to_string()]),
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn insert_and_get() {
         let key_1 = "key_1".to_owned();
@@ -452,6 +763,7 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "dyn-record")]
     #[tokio::test]
     async fn test_dyn_read() {
         let temp_dir = tempfile::tempdir().unwrap();
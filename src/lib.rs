@@ -106,49 +106,97 @@
 //!     }
 //! }
 //! ```
+pub mod aggregate;
+pub mod background;
+pub mod backup;
+pub mod batch;
+#[cfg(feature = "workload")]
+pub mod bench;
+pub mod clock;
 pub mod compaction;
 pub mod context;
+pub mod cursor;
+#[cfg(feature = "dump")]
+pub mod dump;
+#[cfg(feature = "dyn-record")]
+pub mod dyn_db;
+pub mod error;
 pub mod executor;
+mod expiry;
+pub mod explain;
+#[cfg(feature = "dump")]
+pub mod export;
 pub mod fs;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod histogram;
+mod idempotency;
+#[cfg(feature = "import")]
+pub mod import;
+pub mod ingest;
 pub mod inmem;
 pub(crate) mod magic;
 mod manifest;
+pub mod merge;
 mod ondisk;
 pub mod option;
 pub mod record;
+pub mod resume;
+pub mod runtime;
 pub mod scope;
-pub(crate) mod snapshot;
+pub mod shard;
+pub mod snapshot;
+mod soft_delete;
+pub mod sst;
+pub mod stats;
 pub mod stream;
 pub mod transaction;
 mod trigger;
+pub mod union;
+pub mod verify;
 pub mod version;
 mod wal;
+pub mod watch;
 
 use std::{
-    collections::HashMap,
     future::Future,
-    io,
+    io, iter,
     marker::PhantomData,
     mem,
-    ops::Bound,
+    ops::{Bound, RangeBounds},
     pin::pin,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
+    time::Duration,
 };
 
+use aggregate::{Aggregate, Aggregates};
 pub use arrow;
-use async_stream::stream;
+use arrow::{
+    array::RecordBatch, datatypes::Schema as ArrowSchema, error::ArrowError,
+    ipc::writer::StreamWriter,
+};
+use async_stream::{stream, try_stream};
+use background::{BackgroundError, BackgroundTask, BackgroundTasks};
+use backup::{BackupError, BackupReport, BackupSource};
+use batch::BatchError;
 use context::Context;
+use error::{arrow_kind, fusio_kind, io_kind, parquet_kind, source_kind, ErrorKind};
+use explain::{ImmutablePlan, LevelPlan, ScanPlan, TablePlan};
+#[cfg(feature = "dump")]
+use export::ExportManifest;
 use flume::{bounded, Sender};
 use fs::FileId;
-use fusio::{MaybeSend, MaybeSync};
+use fusio::{DynFs, MaybeSend, MaybeSync};
 pub use fusio::{SeqRead, Write};
 pub use fusio_log::{Decode, Encode};
 use futures::channel::oneshot;
 use futures_core::Stream;
-use futures_util::StreamExt;
+use futures_util::{future::Either, stream, StreamExt};
+use histogram::KeyBucket;
+use ingest::{IngestError, IngestReport};
 use inmem::{
     immutable::ImmutableMemTable,
     mutable::{MutableMemTable, WriteResult},
@@ -158,19 +206,20 @@ use magic::USER_COLUMN_OFFSET;
 use manifest::ManifestStorageError;
 pub use once_cell;
 pub use parquet;
-use parquet::{
-    arrow::{ArrowSchemaConverter, ProjectionMask},
-    errors::ParquetError,
-};
+use parquet::{arrow::ProjectionMask, errors::ParquetError};
 use parquet_lru::{DynLruCache, NoCache};
 use record::Record;
+#[cfg(feature = "dyn-record")]
+use record::{DynRecord, DynRecordRef, Expr, ExprError};
+use resume::{Resumable, ResumeToken};
+use stats::{DbStats, MemoryUsage, RangeStats};
 use thiserror::Error;
 pub use tonbo_macros::{KeyAttributes, Record};
-use tracing::error;
-use transaction::{CommitError, Transaction, TransactionEntry};
+use tracing::{error, info_span, Instrument};
+use transaction::{CommitError, IsolationLevel, Transaction, TransactionEntry};
 use trigger::FreezeTrigger;
+use verify::IntegrityReport;
 use version::timestamp::{Timestamp, TsRef};
-use wal::log::Log;
 
 #[doc(hidden)]
 pub use crate::magic::TS;
@@ -181,23 +230,52 @@ pub use crate::record::{ArrowArrays, ArrowArraysBuilder};
 pub use crate::version::timestamp::Ts;
 use crate::{
     compaction::{
-        error::CompactionError, leveled::LeveledCompactor, tiered::TieredCompactor, CompactTask,
-        Compactor,
+        adaptive::{AdaptiveTuner, TunedThresholds},
+        error::CompactionError,
+        leveled::LeveledCompactor,
+        subcompaction::SubCompactions,
+        tiered::TieredCompactor,
+        CompactTask, CompactionReport, Compactor,
     },
     executor::{Executor, RwLock as ExecutorRwLock},
-    fs::{manager::StoreManager, parse_file_id, FileType},
+    expiry::{Expired, ExpiryIndex},
+    fs::{manager::StoreManager, parse_file_id, pin, pool::ReaderPool, FileType},
+    idempotency::IdempotencyWindow,
     inmem::flush::minor_flush,
     manifest::ManifestStorage,
-    record::Schema,
+    merge::DynMergeOperator,
+    ondisk::sstable::SsTable,
+    record::{KeyRef, PrefixKey, Schema},
+    runtime::Scheduler,
     snapshot::Snapshot,
+    soft_delete::PurgeHorizon,
     stream::{
-        mem_projection::MemProjectionStream, merge::MergeStream, package::PackageStream, ScanStream,
+        mem_projection::MemProjectionStream,
+        memory::MemoryBudget,
+        merge::{MergeStream, RecordFilter},
+        package::PackageStream,
+        record_batch::RecordBatchEntry,
+        ScanStream,
     },
     trigger::TriggerFactory,
-    version::{cleaner::Cleaner, error::VersionError, set::VersionSet, Version, VersionRef},
-    wal::{log::LogType, RecoverError, WalFile},
+    version::{
+        cleaner::Cleaner,
+        edit::VersionEdit,
+        error::VersionError,
+        hot_range::{HotRanges, HOT_RANGE_KEY_CAPACITY},
+        negative_cache::NegativeCache,
+        range_tombstone::RangeTombstone,
+        schema::{SchemaMismatch, SchemaVersion},
+        set::VersionSet,
+        Version, VersionRef,
+    },
+    wal::{log::LogType, RecoverError, Recovered, WalFile},
+    watch::{ChangeOp, Watch, WatchKeys, Watchers},
+};
+pub use crate::{
+    option::*,
+    stream::{reader::ScanBatchReader, Entry},
 };
-pub use crate::{option::*, stream::Entry};
 
 pub trait CompactionExecutor<R: Record>: MaybeSend + MaybeSync {
     fn check_then_compaction<'a>(
@@ -211,6 +289,19 @@ pub trait CompactionExecutor<R: Record>: MaybeSend + MaybeSync {
         recover_wal_ids: Option<Vec<FileId>>,
         is_manual: bool,
     ) -> impl Future<Output = Result<(), CompactionError<R>>> + MaybeSend + 'a;
+
+    /// Compact the tables that hold keys in `range`, see [`DB::compact_range`], which runs a
+    /// manual compaction of all tables unless it is overridden
+    fn compact_range<'a>(
+        &'a self,
+        range: (
+            Bound<<R::Schema as Schema>::Key>,
+            Bound<<R::Schema as Schema>::Key>,
+        ),
+    ) -> impl Future<Output = Result<(), CompactionError<R>>> + MaybeSend + 'a {
+        let _ = range;
+        self.check_then_compaction(None, None, true)
+    }
 }
 
 // Implementation for custom compactors (Box<dyn Compactor<R>>)
@@ -229,6 +320,16 @@ impl<R: Record> CompactionExecutor<R> for Box<dyn Compactor<R>> {
         self.as_ref()
             .check_then_compaction(batches, recover_wal_ids, is_manual)
     }
+
+    fn compact_range<'a>(
+        &'a self,
+        range: (
+            Bound<<R::Schema as Schema>::Key>,
+            Bound<<R::Schema as Schema>::Key>,
+        ),
+    ) -> impl Future<Output = Result<(), CompactionError<R>>> + MaybeSend + 'a {
+        self.as_ref().compact_range(range)
+    }
 }
 
 /// Wrapper of [`DbStorage`] for handling concurrent operations
@@ -241,6 +342,7 @@ where
     mem_storage: Arc<E::RwLock<DbStorage<R>>>,
     ctx: Arc<Context<R>>,
     lock_map: LockMap<<R::Schema as Schema>::Key>,
+    background: Arc<BackgroundTasks>,
     _p: PhantomData<E>,
 }
 
@@ -254,17 +356,63 @@ where
     /// path specified in [`DbOption`] (if it does not exist before) and run it
     /// according to the configuration of [`DbOption`].
     ///
+    /// Opening loads the key ranges of the SSTables from the manifest and replays the WALs, but
+    /// does not open any SSTable, so a DB on remote storage starts without fetching footers and
+    /// each SSTable is only opened when a read or a compaction first reaches it. The exceptions
+    /// are an aggregate of [`DbOption::aggregate`] that is new to the manifest, whose groups are
+    /// counted by a scan of the SSTables once, and the aged tables of
+    /// [`DbOption::pin_recent_tables`], which are moved to their levels.
+    ///
     /// For more configurable options, please refer to [`DbOption`].
     pub async fn new(option: DbOption, executor: E, schema: R::Schema) -> Result<Self, DbError> {
+        let manager = Self::store_manager(&option)?;
         Self::build(
             Arc::new(option),
-            executor,
+            &executor,
             schema,
+            manager,
             Arc::new(NoCache::default()),
+            None,
         )
         .await
     }
 
+    /// Restore a backup from `source` into the location of `option` and open it.
+    ///
+    /// The location must not contain a DB yet. Every table referenced by the manifest of the
+    /// backup has to be present; tables are placed at the paths configured for their levels in
+    /// `option`, so a backup can be restored with a different storage layout than it was taken
+    /// from.
+    pub async fn restore(
+        option: DbOption,
+        executor: E,
+        schema: R::Schema,
+        source: BackupSource,
+    ) -> Result<Self, DbError> {
+        let manager = StoreManager::new(option.base_fs.clone(), option.level_paths.clone())?;
+        backup::restore::<R>(&option, &manager, source).await?;
+
+        Self::new(option, executor, schema).await
+    }
+
+    /// Open a DB with the tree of a [`ManifestDump`](dump::ManifestDump) in the empty location of
+    /// `option`, e.g. to reproduce the state of a bug report.
+    ///
+    /// The SSTables of the dump have to be present at the paths configured for their levels in
+    /// `option`; they are referenced, not copied.
+    #[cfg(feature = "dump")]
+    pub async fn load_manifest(
+        option: DbOption,
+        executor: E,
+        schema: R::Schema,
+        dump: &dump::ManifestDump,
+    ) -> Result<Self, DbError> {
+        let manager = StoreManager::new(option.base_fs.clone(), option.level_paths.clone())?;
+        dump::load::<R>(&option, &manager, &schema, dump).await?;
+
+        Self::new(option, executor, schema).await
+    }
+
     /// Open [`DB`] with a custom compactor factory. This provides completely static dispatch.
     pub async fn new_with_compactor_factory<C, F>(
         option: DbOption,
@@ -276,24 +424,38 @@ where
         C: CompactionExecutor<R> + Send + Sync + 'static,
         F: FnOnce(Arc<DbOption>, Arc<R::Schema>, Arc<Context<R>>) -> C,
     {
+        let manager = Self::store_manager(&option)?;
         Self::build_with_compactor_factory(
             Arc::new(option),
-            executor,
+            &executor,
             schema,
+            manager,
             Arc::new(NoCache::default()),
             factory,
         )
         .await
     }
 
-    async fn build(
+    fn store_manager(option: &DbOption) -> Result<Arc<StoreManager>, DbError> {
+        Ok(Arc::new(
+            StoreManager::new(option.base_fs.clone(), option.level_paths.clone())?
+                .max_open_files(option.max_open_files),
+        ))
+    }
+
+    /// Open the DB on `manager`. Its background tasks are run by `scheduler` if there is one,
+    /// otherwise each of them is spawned on `executor`.
+    pub(crate) async fn build(
         option: Arc<DbOption>,
-        executor: E,
+        executor: &E,
         schema: R::Schema,
+        manager: Arc<StoreManager>,
         lru_cache: ParquetLru,
+        scheduler: Option<&Scheduler>,
     ) -> Result<Self, DbError> {
-        let (record_schema, _manager, cleaner, task_rx, mem_storage, ctx) =
-            Self::build_common_setup::<E>(option.clone(), schema, lru_cache).await?;
+        let (record_schema, cleaner, task_rx, mem_storage, ctx) =
+            Self::build_common_setup::<E>(option.clone(), executor, schema, manager, lru_cache)
+                .await?;
 
         match &option.compaction_option {
             CompactionOption::Leveled(opt) => {
@@ -303,7 +465,16 @@ where
                     option.clone(),
                     ctx.clone(),
                 );
-                Self::finish_build(executor, mem_storage, ctx, compactor, cleaner, task_rx).await
+                Self::finish_build(
+                    executor,
+                    mem_storage,
+                    ctx,
+                    compactor,
+                    cleaner,
+                    task_rx,
+                    scheduler,
+                )
+                .await
             }
             CompactionOption::Tiered(opt) => {
                 let compactor = TieredCompactor::<R>::new(
@@ -312,15 +483,25 @@ where
                     option.clone(),
                     ctx.clone(),
                 );
-                Self::finish_build(executor, mem_storage, ctx, compactor, cleaner, task_rx).await
+                Self::finish_build(
+                    executor,
+                    mem_storage,
+                    ctx,
+                    compactor,
+                    cleaner,
+                    task_rx,
+                    scheduler,
+                )
+                .await
             }
         }
     }
 
     async fn build_with_compactor_factory<C, F>(
         option: Arc<DbOption>,
-        executor: E,
+        executor: &E,
         schema: R::Schema,
+        manager: Arc<StoreManager>,
         lru_cache: ParquetLru,
         factory: F,
     ) -> Result<Self, DbError>
@@ -328,21 +509,32 @@ where
         C: CompactionExecutor<R> + Send + Sync + 'static,
         F: FnOnce(Arc<DbOption>, Arc<R::Schema>, Arc<Context<R>>) -> C,
     {
-        let (record_schema, _, cleaner, task_rx, mem_storage, ctx) =
-            Self::build_common_setup::<E>(option.clone(), schema, lru_cache).await?;
+        let (record_schema, cleaner, task_rx, mem_storage, ctx) =
+            Self::build_common_setup::<E>(option.clone(), executor, schema, manager, lru_cache)
+                .await?;
 
         let compactor = factory(option, record_schema, ctx.clone());
-        Self::finish_build(executor, mem_storage, ctx, compactor, cleaner, task_rx).await
+        Self::finish_build(
+            executor,
+            mem_storage,
+            ctx,
+            compactor,
+            cleaner,
+            task_rx,
+            None,
+        )
+        .await
     }
 
     async fn build_common_setup<Ex>(
         option: Arc<DbOption>,
+        executor: &Ex,
         schema: R::Schema,
+        manager: Arc<StoreManager>,
         lru_cache: ParquetLru,
     ) -> Result<
         (
             Arc<R::Schema>,
-            Arc<StoreManager>,
             Cleaner,
             flume::Receiver<CompactTask>,
             Arc<Ex::RwLock<DbStorage<R>>>,
@@ -353,11 +545,43 @@ where
     where
         Ex: Executor + Send + Sync,
     {
+        if let Some(key_type) = option
+            .table_boundary
+            .as_ref()
+            .and_then(|boundary| boundary.mismatch::<<R::Schema as Schema>::Key>())
+        {
+            return Err(DbError::TableBoundaryKey(key_type));
+        }
+        if let Some(record_type) = option
+            .expiry
+            .as_ref()
+            .and_then(|expiry| expiry.expires_at::<R>().err())
+        {
+            return Err(DbError::ExpiryRecord(record_type));
+        }
+        if let Some(record_type) = option
+            .compaction_filter
+            .as_ref()
+            .and_then(|filter| filter.filter::<R>().err())
+        {
+            return Err(DbError::CompactionFilterRecord(record_type));
+        }
+        if let Some(record_type) = option
+            .merge_operator
+            .as_ref()
+            .and_then(|operator| operator.operator::<R>().err())
+        {
+            return Err(DbError::MergeOperatorRecord(record_type));
+        }
+        for aggregate in &option.aggregates {
+            if let Err(record_type) = aggregate.fns::<R>() {
+                return Err(DbError::AggregateRecord(
+                    aggregate.name.clone(),
+                    record_type,
+                ));
+            }
+        }
         let record_schema = Arc::new(schema);
-        let manager = Arc::new(StoreManager::new(
-            option.base_fs.clone(),
-            option.level_paths.clone(),
-        )?);
         {
             // Ensure both the WAL and version-log paths exist on the local file system
             // and base (default) file system
@@ -381,6 +605,24 @@ where
                 .create_dir_all(&option.version_log_dir_path())
                 .await
                 .map_err(DbError::Fusio)?;
+            if let Some((pinned_path, _)) = &option.pinned_tables {
+                manager
+                    .local_fs()
+                    .create_dir_all(pinned_path)
+                    .await
+                    .map_err(DbError::Fusio)?;
+            }
+            if let Some(retained_wal_path) = option.retained_wal_dir_path() {
+                manager
+                    .base_fs()
+                    .create_dir_all(&retained_wal_path)
+                    .await
+                    .map_err(DbError::Fusio)?;
+            }
+        }
+
+        if let Some(latest) = fs::latest_file_id(&option, &manager).await? {
+            option.file_ids.advance_past(latest);
         }
 
         let (task_tx, task_rx) = bounded(1);
@@ -391,184 +633,402 @@ where
                 .await
                 .map_err(ManifestStorageError::Version)?,
         );
-        let mem_storage = Arc::new(Ex::rw_lock(
-            DbStorage::new(
-                option.clone(),
-                task_tx,
-                manifest.as_ref(),
-                record_schema.clone(),
-                &manager,
+        // the tables are read as the stored schema, which the schema of the DB may only append
+        // nullable columns to, after the renames and drops of the options, and whose primary key
+        // it keeps
+        let stored = manifest.current().await.schema().cloned();
+        let (arrow_schema, primary_key) = (
+            record_schema.arrow_schema(),
+            record_schema.primary_key_indices(),
+        );
+        let schema = match &stored {
+            Some(stored) => stored.evolve(arrow_schema, primary_key, &option.column_changes)?,
+            None => Some(SchemaVersion::new(0, arrow_schema.clone(), primary_key)?),
+        };
+        let mem_storage = DbStorage::new(
+            option.clone(),
+            task_tx,
+            manifest.as_ref(),
+            record_schema.clone(),
+            &manager,
+            executor,
+        )
+        .await?;
+        if let Some(schema) = schema {
+            // the records of the WAL hold the values of their columns by position, which a drop
+            // would shift
+            let dropped = stored.as_ref().and_then(|stored| stored.dropped(&schema));
+            if let Some(name) = dropped {
+                if !mem_storage.mutable.is_empty() || !mem_storage.immutables.is_empty() {
+                    return Err(SchemaMismatch::UnflushedDrop(name.to_string()).into());
+                }
+            }
+            manifest
+                .update(vec![VersionEdit::NewSchema { schema }], None)
+                .await?;
+        }
+        let mem_storage = Arc::new(Ex::rw_lock(mem_storage));
+
+        let ctx = Arc::new(
+            Context::new(
+                manager.clone(),
+                lru_cache.clone(),
+                manifest,
+                record_schema.arrow_schema().clone(),
+                NegativeCache::new(option.negative_cache_capacity),
+                HotRanges::new(HOT_RANGE_KEY_CAPACITY, option.hot_range_tables),
+                MemoryBudget::new(option.scan_memory_limit),
             )
-            .await?,
-        ));
-
-        let ctx = Arc::new(Context::new(
-            manager.clone(),
-            lru_cache.clone(),
-            manifest,
-            record_schema.arrow_schema().clone(),
-        ));
+            .with_idempotency_window(IdempotencyWindow::new(option.idempotency_window))
+            .with_purge_horizon(match option.soft_delete {
+                Some(purge_after) => PurgeHorizon::new(purge_after),
+                None => PurgeHorizon::default(),
+            })
+            .with_subcompactions(match option.max_subcompactions {
+                1 => SubCompactions::default(),
+                max => SubCompactions::spawn(executor, max),
+            })
+            .with_tuner(match &option.adaptive_tuning {
+                Some(tuning) => AdaptiveTuner::new(
+                    tuning.clone(),
+                    option.time_source.clone(),
+                    option.immutable_chunk_max_num,
+                    match &option.compaction_option {
+                        CompactionOption::Leveled(options) => Some(options),
+                        CompactionOption::Tiered(_) => None,
+                    },
+                ),
+                None => AdaptiveTuner::default(),
+            }),
+        );
+        if let Some(path) = &option.footer_cache {
+            manager
+                .readers()
+                .footers()
+                .load(manager.local_fs(), path.clone())
+                .await;
+        }
+        {
+            let version = ctx.current_manifest().await;
+            pin::recover(&option, &manager, &version)
+                .await
+                .map_err(DbError::Fusio)?;
+            pin::migrate(&version, &manager)
+                .await
+                .map_err(DbError::Fusio)?;
+        }
+        {
+            // counted before the compactor starts, so no flush changes the tables meanwhile
+            let version = ctx.current_manifest().await;
+            let edits = aggregate::register_edits(
+                &aggregate::aggregates::<R>(&option),
+                &ctx,
+                &version,
+                record_schema.primary_key_indices(),
+            )
+            .await?;
+            if !edits.is_empty() {
+                ctx.manifest().update(edits, None).await?;
+            }
+        }
 
-        Ok((record_schema, manager, cleaner, task_rx, mem_storage, ctx))
+        Ok((record_schema, cleaner, task_rx, mem_storage, ctx))
     }
 
     async fn finish_build<C>(
-        executor: E,
+        executor: &E,
         mem_storage: Arc<E::RwLock<DbStorage<R>>>,
         ctx: Arc<Context<R>>,
         compactor: C,
         mut cleaner: Cleaner,
         task_rx: flume::Receiver<CompactTask>,
+        scheduler: Option<&Scheduler>,
     ) -> Result<Self, DbError>
     where
         C: CompactionExecutor<R> + MaybeSend + MaybeSync + 'static,
         E: Executor + Send + Sync + 'static,
     {
-        executor.spawn(async move {
+        let background = Arc::new(BackgroundTasks::default());
+        let cleaner_task = async move {
             if let Err(err) = cleaner.listen().await {
                 error!("[Cleaner Error]: {}", err)
             }
-        });
+        };
 
         let mem_storage_task = mem_storage.clone();
         let ctx_task = ctx.clone();
-        executor.spawn(async move {
+        let permits = scheduler.and_then(Scheduler::permits);
+        let compactor_task = async move {
+            let (record_schema, time_source) = {
+                let storage = mem_storage_task.read().await;
+                (
+                    storage.record_schema.clone(),
+                    storage.option.time_source.clone(),
+                )
+            };
             // Waits to receive compaction task. `CompactTask::Freeze` will perform automatic
             // compaction and `Compact::Flush` will perform manual compaction
             while let Ok(task) = task_rx.recv_async().await {
-                if let Err(err) = match task {
-                    CompactTask::Freeze => {
-                        // Handle minor flush; drain owned immutables under short lock
-                        let mut guard = mem_storage_task.write().await;
-
-                        let immutable_chunk_num = guard.option.immutable_chunk_num;
-                        let immutable_chunk_max_num = guard.option.immutable_chunk_max_num;
-                        let base_fs = ctx_task.manager.base_fs().clone();
-
-                        let batches_and_wal_ids = minor_flush(
-                            &mut *guard,
-                            base_fs,
-                            immutable_chunk_num,
-                            immutable_chunk_max_num,
-                            false,
-                        )
-                        .await;
-
-                        match batches_and_wal_ids {
-                            Ok(Some((mut batches, recover_wal_ids))) => {
-                                // Mark compaction window before releasing lock
-                                guard.compaction_in_progress.store(true, Ordering::Release);
-                                // Release lock before heavy work
-                                drop(guard);
-                                // Keep a copy for potential rollback
-                                let rollback_wal_ids = recover_wal_ids.clone();
-
-                                let compaction_result = compactor
-                                    .check_then_compaction(
-                                        Some(&batches[..]),
-                                        recover_wal_ids,
-                                        false,
-                                    )
-                                    .await;
-
-                                // Finalize: clear window and possibly rollback
-                                let mut g = mem_storage_task.write().await;
-                                if compaction_result.is_err() {
-                                    for item in batches.drain(..).rev() {
-                                        g.immutables.insert(0, item);
-                                    }
-                                    if let Some(ids) = rollback_wal_ids {
-                                        if let Some(existing) = &mut g.recover_wal_ids {
-                                            existing.extend(ids);
-                                        } else {
-                                            g.recover_wal_ids = Some(ids);
+                let _permit = match &permits {
+                    Some(permits) => Some(permits.acquire_arc().await),
+                    None => None,
+                };
+                let previous = ctx_task.manifest().current().await;
+                let (started_ms, rows_before) =
+                    (time_source.now_ms(), ctx_task.manager.rows_written());
+                let mut flush_tx = None;
+                let span = info_span!(
+                    "flush",
+                    manual = matches!(task, CompactTask::Flush(_) | CompactTask::CompactRange(..))
+                );
+                if let Err(err) = async {
+                    match task {
+                        CompactTask::Compact(tx) => {
+                            flush_tx = Some(tx);
+                            compactor.check_then_compaction(None, None, false).await
+                        }
+                        CompactTask::CompactRange(range, tx) => {
+                            flush_tx = Some(tx);
+                            let range = range
+                                .downcast::<(
+                                    Bound<<R::Schema as Schema>::Key>,
+                                    Bound<<R::Schema as Schema>::Key>,
+                                )>()
+                                .expect("compacted range should hold keys of the schema");
+                            compactor.compact_range(*range).await
+                        }
+                        CompactTask::DeleteRange(range, tx) => {
+                            let range = range
+                                .downcast::<(
+                                    Bound<<R::Schema as Schema>::Key>,
+                                    Bound<<R::Schema as Schema>::Key>,
+                                )>()
+                                .expect("deleted range should hold keys of the schema");
+                            // no write goes in between the tombstone and the removals
+                            let storage = mem_storage_task.write().await;
+                            let result = storage.delete_range(&ctx_task, *range).await;
+                            if result.as_ref().is_ok_and(WriteResult::needs_compaction) {
+                                let _ = storage.compaction_tx.try_send(CompactTask::Freeze);
+                            }
+                            drop(storage);
+                            let _ = tx.send(result.map(|_| ()));
+                            Ok(())
+                        }
+                        CompactTask::Freeze => {
+                            // Handle minor flush; drain owned immutables under short lock
+                            let mut guard = mem_storage_task.write().await;
+
+                            let immutable_chunk_num = guard.option.immutable_chunk_num;
+                            let immutable_chunk_max_num = ctx_task
+                                .tuner()
+                                .immutable_chunk_max_num(guard.option.immutable_chunk_max_num);
+                            let base_fs = ctx_task.manager.base_fs().clone();
+
+                            let batches_and_wal_ids = minor_flush(
+                                &mut *guard,
+                                &ctx_task,
+                                base_fs,
+                                immutable_chunk_num,
+                                immutable_chunk_max_num,
+                                false,
+                            )
+                            .await;
+
+                            match batches_and_wal_ids {
+                                Ok(Some((mut batches, recover_wal_ids))) => {
+                                    // Mark compaction window before releasing lock
+                                    guard.compaction_in_progress.store(true, Ordering::Release);
+                                    // Release lock before heavy work
+                                    drop(guard);
+                                    // Keep a copy for potential rollback
+                                    let rollback_wal_ids = recover_wal_ids.clone();
+
+                                    let compaction_result = compactor
+                                        .check_then_compaction(
+                                            Some(&batches[..]),
+                                            recover_wal_ids,
+                                            false,
+                                        )
+                                        .await;
+
+                                    // Finalize: clear window and possibly rollback
+                                    let mut g = mem_storage_task.write().await;
+                                    if compaction_result.is_err() {
+                                        for item in batches.drain(..).rev() {
+                                            g.immutables.insert(0, item);
                                         }
+                                        if let Some(ids) = rollback_wal_ids {
+                                            if let Some(existing) = &mut g.recover_wal_ids {
+                                                existing.extend(ids);
+                                            } else {
+                                                g.recover_wal_ids = Some(ids);
+                                            }
+                                        }
+                                    } else {
+                                        g.flushed(&batches);
                                     }
+                                    g.compaction_in_progress.store(false, Ordering::Release);
+                                    drop(g);
+                                    compaction_result
+                                }
+                                Ok(None) => {
+                                    compactor.check_then_compaction(None, None, false).await
+                                }
+                                Err(e) => {
+                                    error!("[Minor Flush Error]: {}", e);
+                                    Ok(())
                                 }
-                                g.compaction_in_progress.store(false, Ordering::Release);
-                                drop(g);
-                                compaction_result
-                            }
-                            Ok(None) => compactor.check_then_compaction(None, None, false).await,
-                            Err(e) => {
-                                error!("[Minor Flush Error]: {}", e);
-                                Ok(())
                             }
                         }
-                    }
-                    CompactTask::Flush(option_tx) => {
-                        // Handle manual flush; drain owned immutables under short lock
-                        let mut guard = mem_storage_task.write().await;
-
-                        let immutable_chunk_num = guard.option.immutable_chunk_num;
-                        let immutable_chunk_max_num = guard.option.immutable_chunk_max_num;
-                        let base_fs = ctx_task.manager.base_fs().clone();
-
-                        let batches_and_wal_ids = minor_flush(
-                            &mut *guard,
-                            base_fs,
-                            immutable_chunk_num,
-                            immutable_chunk_max_num,
-                            true,
-                        )
-                        .await;
-
-                        let res = match batches_and_wal_ids {
-                            Ok(Some((mut batches, recover_wal_ids))) => {
-                                // Mark compaction window before releasing lock
-                                guard.compaction_in_progress.store(true, Ordering::Release);
-                                // Release lock before heavy work
-                                drop(guard);
-                                let rollback_wal_ids = recover_wal_ids.clone();
-                                let compaction_result = compactor
-                                    .check_then_compaction(
-                                        Some(&batches[..]),
-                                        recover_wal_ids,
-                                        true,
-                                    )
-                                    .await;
-                                let mut g = mem_storage_task.write().await;
-                                if compaction_result.is_err() {
-                                    for item in batches.drain(..).rev() {
-                                        g.immutables.insert(0, item);
-                                    }
-                                    if let Some(ids) = rollback_wal_ids {
-                                        if let Some(existing) = &mut g.recover_wal_ids {
-                                            existing.extend(ids);
-                                        } else {
-                                            g.recover_wal_ids = Some(ids);
+                        CompactTask::Flush(option_tx) => {
+                            // Handle manual flush; drain owned immutables under short lock
+                            let mut guard = mem_storage_task.write().await;
+
+                            let immutable_chunk_num = guard.option.immutable_chunk_num;
+                            let immutable_chunk_max_num = ctx_task
+                                .tuner()
+                                .immutable_chunk_max_num(guard.option.immutable_chunk_max_num);
+                            let base_fs = ctx_task.manager.base_fs().clone();
+
+                            let batches_and_wal_ids = minor_flush(
+                                &mut *guard,
+                                &ctx_task,
+                                base_fs,
+                                immutable_chunk_num,
+                                immutable_chunk_max_num,
+                                true,
+                            )
+                            .await;
+
+                            let res = match batches_and_wal_ids {
+                                Ok(Some((mut batches, recover_wal_ids))) => {
+                                    // Mark compaction window before releasing lock
+                                    guard.compaction_in_progress.store(true, Ordering::Release);
+                                    // Release lock before heavy work
+                                    drop(guard);
+                                    let rollback_wal_ids = recover_wal_ids.clone();
+                                    let compaction_result = compactor
+                                        .check_then_compaction(
+                                            Some(&batches[..]),
+                                            recover_wal_ids,
+                                            true,
+                                        )
+                                        .await;
+                                    let mut g = mem_storage_task.write().await;
+                                    if compaction_result.is_err() {
+                                        for item in batches.drain(..).rev() {
+                                            g.immutables.insert(0, item);
+                                        }
+                                        if let Some(ids) = rollback_wal_ids {
+                                            if let Some(existing) = &mut g.recover_wal_ids {
+                                                existing.extend(ids);
+                                            } else {
+                                                g.recover_wal_ids = Some(ids);
+                                            }
                                         }
+                                    } else {
+                                        g.flushed(&batches);
                                     }
+                                    g.compaction_in_progress.store(false, Ordering::Release);
+                                    drop(g);
+                                    compaction_result
                                 }
-                                g.compaction_in_progress.store(false, Ordering::Release);
-                                drop(g);
-                                compaction_result
-                            }
-                            Ok(None) => compactor.check_then_compaction(None, None, true).await,
-                            Err(e) => {
-                                error!("[Minor Flush Error]: {}", e);
-                                Ok(())
-                            }
-                        };
+                                Ok(None) => compactor.check_then_compaction(None, None, true).await,
+                                Err(e) => {
+                                    error!("[Minor Flush Error]: {}", e);
+                                    Ok(())
+                                }
+                            };
 
-                        if let Some(tx) = option_tx {
-                            // Always notify the caller to avoid hanging flush() even on error
-                            let _ = tx.send(());
+                            // notified once the report is taken, even on error, to avoid hanging
+                            // flush()
+                            flush_tx = option_tx;
+                            res
                         }
-                        res
                     }
-                } {
+                }
+                .instrument(span)
+                .await
+                {
                     error!("[Compaction Error]: {}", err);
                 }
+
+                let version = ctx_task.manifest().current().await;
+                let waiting_immutables = mem_storage_task.read().await.immutables.len();
+                ctx_task.tuner().observe(
+                    !Arc::ptr_eq(&previous, &version),
+                    waiting_immutables,
+                    &version,
+                );
+                if let Some(tx) = flush_tx {
+                    let _ = tx.send(CompactionReport::between(
+                        &previous,
+                        &version,
+                        ctx_task.manager.rows_written() - rows_before,
+                        Duration::from_millis(time_source.now_ms().saturating_sub(started_ms)),
+                    ));
+                }
+                if !Arc::ptr_eq(&previous, &version) {
+                    if let Err(err) = ctx_task
+                        .hot_ranges()
+                        .warm(
+                            &ctx_task,
+                            &version,
+                            &previous,
+                            record_schema.primary_key_indices(),
+                        )
+                        .await
+                    {
+                        error!("[Hot Range Warm Error]: {}", err);
+                    }
+                }
+                if let Err(err) = pin::migrate(&version, &ctx_task.manager).await {
+                    error!("[Pinned Table Migration Error]: {}", err);
+                }
+                let manager = &ctx_task.manager;
+                if let Err(err) = manager
+                    .readers()
+                    .footers()
+                    .persist(manager.local_fs())
+                    .await
+                {
+                    error!("[Footer Cache Error]: {}", err);
+                }
             }
-        });
+        };
+        match scheduler {
+            Some(scheduler) => {
+                scheduler.run(background.supervise(BackgroundTask::Cleaner, cleaner_task));
+                scheduler.run(background.supervise(BackgroundTask::Compactor, compactor_task));
+            }
+            None => {
+                background.spawn(executor, BackgroundTask::Cleaner, cleaner_task);
+                background.spawn(executor, BackgroundTask::Compactor, compactor_task);
+            }
+        }
 
-        Ok(Self {
+        let db = Self {
             mem_storage,
             lock_map: Arc::new(Default::default()),
             ctx,
+            background,
             _p: Default::default(),
-        })
+        };
+
+        Ok(db)
+    }
+
+    /// Returns the first panic of a background task, if any.
+    ///
+    /// Nothing is flushed or compacted anymore after the compactor panicked, so callers should
+    /// stop writing and reopen the [`DB`] once this returns an error.
+    pub fn background_error(&self) -> Option<BackgroundError> {
+        self.background.error()
+    }
+
+    /// Whether the background `task` of the [`DB`] is still running
+    pub fn is_background_task_alive(&self, task: BackgroundTask) -> bool {
+        self.background.is_alive(task)
     }
 
     /// Returns the current manifest version
@@ -604,6 +1064,16 @@ where
     /// txn.commit().await.unwrap();
     /// ```
     pub async fn transaction(&self) -> Transaction<'_, R, E> {
+        self.transaction_with(IsolationLevel::Snapshot).await
+    }
+
+    /// Open an optimistic ACID transaction whose reads see the commits of other transactions as
+    /// `isolation` says, see [`IsolationLevel`]
+    ///
+    /// [`IsolationLevel::ReadCommitted`] transactions read the latest commits on every
+    /// [`Transaction::get`] and [`Transaction::scan`] and skip the conflict check on commit,
+    /// which suits read-mostly work and blind writes that do not depend on what they read.
+    pub async fn transaction_with(&self, isolation: IsolationLevel) -> Transaction<'_, R, E> {
         // Wait out any compaction window where immutables were drained
         loop {
             let guard = self.mem_storage.read().await;
@@ -614,11 +1084,11 @@ where
             }
             let version_ref = self.ctx.manifest().current().await;
             let snapshot: Snapshot<'_, R, E> = Snapshot::new(guard, version_ref, self.ctx.clone());
-            break Transaction::new(snapshot, self.lock_map.clone());
+            break Transaction::new(snapshot, self.lock_map.clone(), isolation);
         }
     }
 
-    /// Returns a snapshot of the database
+    /// Returns a read-only snapshot of the database as of the latest commit, see [`Snapshot`]
     pub async fn snapshot(&self) -> Snapshot<'_, R, E> {
         // Avoid building a snapshot while immutables are drained for compaction
         loop {
@@ -636,6 +1106,39 @@ where
         Ok(self.write(record, self.ctx.increase_ts()).await?)
     }
 
+    /// Insert a single tonbo record unless an insert with the same idempotency `token` was
+    /// applied recently, which returns whether the record was inserted
+    ///
+    /// An upstream that delivers records at least once, e.g. a consumer replaying a log from its
+    /// last committed offset, can pass the offset or a message id as the token, so a record that
+    /// is delivered again does not overwrite a newer write of its key. Only the latest
+    /// [`DbOption::idempotency_window`] tokens are remembered, and only in memory: a record that
+    /// is delivered again after the DB was reopened is inserted again. A failed insert does not
+    /// keep its token, so it can be retried.
+    ///
+    /// Returns [`DbError::NoIdempotencyWindow`] if the window is 0, as the token would not be
+    /// remembered.
+    pub async fn insert_with_token(
+        &self,
+        record: R,
+        token: impl AsRef<[u8]>,
+    ) -> Result<bool, CommitError<R>> {
+        let token = token.as_ref();
+        let window = self.ctx.idempotency_window();
+        if window.is_empty() {
+            return Err(DbError::NoIdempotencyWindow.into());
+        }
+        if !window.reserve(token) {
+            return Ok(false);
+        }
+        if let Err(err) = self.write(record, self.ctx.increase_ts()).await {
+            window.release(token);
+            return Err(err.into());
+        }
+
+        Ok(true)
+    }
+
     /// Insert a sequence of data as a single batch
     pub async fn insert_batch(
         &self,
@@ -644,6 +1147,16 @@ where
         Ok(self.write_batch(records, self.ctx.increase_ts()).await?)
     }
 
+    /// Insert the rows of `batch`, which has the columns of the schema without `_null` and
+    /// `_ts`, as a single batch, see [`batch`]
+    pub async fn insert_record_batch(&self, batch: &RecordBatch) -> Result<(), CommitError<R>> {
+        let schema = self.mem_storage.read().await.record_schema.clone();
+        let records = batch::records::<R>(&schema, batch).map_err(DbError::from)?;
+        Ok(self
+            .write_batch(records.into_iter(), self.ctx.increase_ts())
+            .await?)
+    }
+
     /// Delete the record with the primary key as the `key`
     pub async fn remove(
         &self,
@@ -657,1199 +1170,4388 @@ where
             .await?)
     }
 
-    /// Trigger compaction manually. This will flush the WAL and trigger compaction
-    pub async fn flush(&self) -> Result<(), CommitError<R>> {
-        let (tx, rx) = oneshot::channel();
-        let compaction_tx = { self.mem_storage.read().await.compaction_tx.clone() };
-        compaction_tx
-            .send_async(CompactTask::Flush(Some(tx)))
-            .await?;
-
-        rx.await.map_err(|_| CommitError::ChannelClose)?;
+    /// Write `operand` for the latest record of its key, which the [`DbOption::merge_operator`]
+    /// applies to it, see the [`merge`] module
+    ///
+    /// The operand is stored as an entry of its own and nothing is read. Reads fold the operands
+    /// of a key into its record, and they are folded into records for good when the memtable is
+    /// frozen. The expiry index and the aggregates see the folded records then.
+    pub async fn merge(&self, operand: R) -> Result<(), CommitError<R>> {
+        let storage = self.mem_storage.read().await;
+        if storage.option.merge_operator.is_none() {
+            return Err(DbError::NoMergeOperator.into());
+        }
 
+        let result = storage.merge(operand, self.ctx.increase_ts()).await?;
+        if result.needs_compaction() {
+            let _ = storage.compaction_tx.try_send(CompactTask::Freeze);
+        }
         Ok(())
     }
 
-    /// Get the record with `key` as the primary key and process it using closure `f`
-    pub async fn get<T>(
-        &self,
-        key: &<R::Schema as Schema>::Key,
-        mut f: impl FnMut(TransactionEntry<'_, R>) -> Option<T>,
-    ) -> Result<Option<T>, CommitError<R>> {
-        loop {
-            let guard = self.mem_storage.read().await;
-            if guard.compaction_in_progress.load(Ordering::Acquire) {
-                drop(guard);
-                continue;
+    /// Write back the value that the latest removal of `key` replaced, which returns whether
+    /// there was one, see [`DbOption::soft_delete`]
+    ///
+    /// Nothing is written if `key` was not removed or its value was purged already. The value is
+    /// written by a transaction, so its commit fails with [`CommitError::WriteConflict`] if `key`
+    /// is written meanwhile.
+    pub async fn undelete(&self, key: &<R::Schema as Schema>::Key) -> Result<bool, CommitError<R>> {
+        let mut txn = self.transaction().await;
+        let record = {
+            let versions = txn.get_versions(key).await?;
+            let removed = versions
+                .iter()
+                .position(|version| version.value().is_some());
+            match removed {
+                Some(removed) if removed > 0 => versions[removed]
+                    .to_record()
+                    .await
+                    .map_err(DbError::Fusio)?,
+                _ => None,
             }
-            break Ok(guard
-                .get(
-                    &self.ctx,
-                    &*self.ctx.manifest().current().await,
-                    key,
-                    self.ctx.load_ts(),
-                    Projection::All,
-                )
-                .await?
-                .and_then(|entry| {
-                    if entry.value().is_none() {
-                        None
-                    } else {
-                        f(TransactionEntry::Stream(entry))
-                    }
-                }));
-        }
+        };
+        let Some(record) = record else {
+            return Ok(false);
+        };
+        txn.insert(record);
+        txn.commit().await?;
+
+        Ok(true)
     }
 
-    /// Scan records with primary keys in the `range` and process them using closure `f`
-    pub async fn scan<'scan, T: 'scan>(
-        &'scan self,
-        range: (
-            Bound<&'scan <R::Schema as Schema>::Key>,
-            Bound<&'scan <R::Schema as Schema>::Key>,
-        ),
-        mut f: impl FnMut(TransactionEntry<'_, R>) -> T + 'scan,
-    ) -> impl Stream<Item = Result<T, CommitError<R>>> + 'scan {
-        stream! {
-            // Delay stream construction while compaction window is active
-            let schema = loop {
-                let guard = self.mem_storage.read().await;
-                if guard.compaction_in_progress.load(Ordering::Acquire) {
-                    drop(guard);
-                    continue;
+    /// Remove the records that expired by now, see [`DbOption::expires_at`], and return how many
+    /// were removed
+    ///
+    /// Each expired key is removed by its own transaction that checks that its record still
+    /// expired, so records written concurrently are never removed. The keys of the memtables are
+    /// indexed by the time they expire, while the key ranges of the SSTables whose earliest
+    /// record expired are scanned. A table keeps the expiry of its removed records until a
+    /// compaction rewrites it, so its range is scanned again once after the DB is reopened. Call
+    /// this periodically or when [`DB::next_expiry`] passed, e.g. from a background task:
+    ///
+    /// ```ignore
+    /// loop {
+    ///     db.remove_expired().await?;
+    ///     tokio::time::sleep(Duration::from_secs(1)).await;
+    /// }
+    /// ```
+    pub async fn remove_expired(&self) -> Result<usize, CommitError<R>> {
+        let (now, expires_at, mut expired, tables) = {
+            let storage = self.mem_storage.read().await;
+            let now = storage.option.now_ms();
+            let version = self.ctx.manifest().current().await;
+            match &storage.expiry {
+                Some(expiry) => (
+                    now,
+                    expiry.expires_at().clone(),
+                    expiry.expired(now),
+                    expiry.expired_tables(&version, now),
+                ),
+                None => return Ok(0),
+            }
+        };
+        // the tables that may hold expired records are checked by a scan of their key ranges,
+        // which also finds when their live records expire next
+        for scope in tables {
+            let snapshot = self.snapshot().await;
+            let mut scan = pin!(
+                snapshot
+                    .scan((Bound::Included(&scope.min), Bound::Included(&scope.max)))
+                    .take()
+                    .await?
+            );
+            let mut next = u64::MAX;
+            while let Some(entry) = scan.next().await.transpose()? {
+                match entry.value().and_then(|record| expires_at(record)) {
+                    Some(at) if at <= now => expired.push((at, entry.key().value.to_key())),
+                    Some(at) => next = next.min(at),
+                    None => {}
                 }
-                break guard;
+            }
+            if let Some(expiry) = &snapshot.mem_storage().expiry {
+                expiry.checked(scope.gen, next);
+            }
+        }
+        expired.sort_by(|(_, a), (_, b)| a.cmp(b));
+        expired.dedup_by(|(_, a), (_, b)| a == b);
+
+        let mut removed = 0;
+        for (at, key) in expired {
+            let mut txn = self.transaction().await;
+            let exists = match txn.get(&key, Projection::All).await? {
+                Some(entry) => Some(expires_at(entry.get()).is_some_and(|expires| expires <= now)),
+                None => None,
             };
-            let current = self.ctx.manifest().current().await;
-            let mut scan = Scan::new(
-                &schema,
-                range,
-                self.ctx.load_ts(),
-                &*current,
-                Box::new(|_, _| None),
-                self.ctx.clone(),
-            ).take().await?;
-
-            while let Some(record) = scan.next().await {
-                yield Ok(f(TransactionEntry::Stream(record?)))
+            match exists {
+                Some(true) => {
+                    txn.remove(key);
+                    match txn.commit().await {
+                        Ok(()) => removed += 1,
+                        // the key was written since, which indexed it again
+                        Err(CommitError::WriteConflict(_)) => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+                // the key was written since, which indexed it again
+                Some(false) => {}
+                None => {
+                    drop(txn);
+                    if let Some(expiry) = &self.mem_storage.read().await.expiry {
+                        expiry.forget(&key, at);
+                    }
+                }
             }
         }
+        Ok(removed)
     }
 
-    pub(crate) async fn write(&self, record: R, ts: Timestamp) -> Result<(), DbError> {
-        let mem_storage = self.mem_storage.read().await;
+    /// Notify the returned [`Watch`] of every write and removal of `keys`, buffering at most
+    /// `capacity` changes that were not consumed yet
+    ///
+    /// A change is sent once the write reached the memtable, so a read of the key after
+    /// receiving it sees the change. Caches and push notifications can therefore invalidate or
+    /// refresh keys without polling the DB.
+    pub async fn watch(
+        &self,
+        keys: WatchKeys<<R::Schema as Schema>::Key>,
+        capacity: usize,
+    ) -> Watch<<R::Schema as Schema>::Key> {
+        self.mem_storage.read().await.watchers.watch(keys, capacity)
+    }
 
-        let write_result = mem_storage.write(LogType::Full, record, ts).await?;
-        if write_result.needs_compaction() {
-            let _ = mem_storage.compaction_tx.try_send(CompactTask::Freeze);
-        };
-        Ok(())
+    /// The count, sum, minimum and maximum of the records of `group` in the aggregate `name`, see
+    /// [`DbOption::aggregate`]
+    ///
+    /// `None` if the group has no records or no aggregate is called `name`. The groups stored in
+    /// the manifest count the records of the SSTables, so the keys of the group that are still
+    /// in the memtables are looked up in the SSTables to replace their records.
+    pub async fn aggregate(
+        &self,
+        name: &str,
+        group: &<R::Schema as Schema>::Key,
+    ) -> Result<Option<Aggregate>, DbError> {
+        loop {
+            let guard = self.mem_storage.read().await;
+            if guard.compaction_in_progress.load(Ordering::Acquire) {
+                drop(guard);
+                continue;
+            }
+            let version = self.ctx.manifest().current().await;
+            break guard.aggregate(&self.ctx, &version, name, group).await;
+        }
     }
 
-    // Write a batch of records
-    pub(crate) async fn write_batch(
-        &self,
-        mut records: impl ExactSizeIterator<Item = R>,
-        ts: Timestamp,
-    ) -> Result<(), DbError> {
-        let mem_storage = self.mem_storage.read().await;
+    /// Milliseconds since the UNIX epoch at which the next record expires, see
+    /// [`DbOption::expires_at`]
+    ///
+    /// A passed time for a table written without [`DbOption::expires_at`], whose records are
+    /// only known once [`DB::remove_expired`] checked them.
+    pub async fn next_expiry(&self) -> Option<u64> {
+        let version = self.ctx.manifest().current().await;
+        self.mem_storage
+            .read()
+            .await
+            .expiry
+            .as_ref()
+            .and_then(|expiry| expiry.next(&version))
+    }
 
-        if let Some(first) = records.next() {
-            let is_excess = if let Some(record) = records.next() {
-                mem_storage.write(LogType::First, first, ts).await?;
+    /// Trigger compaction manually. This will flush the WAL and trigger compaction
+    ///
+    /// Returns the tables the flush and the compactions it triggered wrote and removed. A flush
+    /// or compaction that fails is logged and leaves the tables as they were.
+    pub async fn flush(&self) -> Result<CompactionReport, CommitError<R>> {
+        let (tx, rx) = oneshot::channel();
+        let compaction_tx = { self.mem_storage.read().await.compaction_tx.clone() };
+        compaction_tx
+            .send_async(CompactTask::Flush(Some(tx)))
+            .await?;
 
-                let mut last_buf = record;
+        rx.await.map_err(|_| CommitError::ChannelClose)
+    }
 
-                for record in records {
-                    mem_storage
-                        .write(LogType::Middle, mem::replace(&mut last_buf, record), ts)
-                        .await?;
-                }
-                mem_storage.write(LogType::Last, last_buf, ts).await?
-            } else {
-                mem_storage.write(LogType::Full, first, ts).await?
-            };
-            if is_excess.needs_compaction() {
-                let _ = mem_storage.compaction_tx.try_send(CompactTask::Freeze);
-            };
-        };
+    /// Wait until no flush or compaction is running or queued, and run the major compactions that
+    /// are due
+    ///
+    /// Once this returns, every level is within the thresholds of its compaction options, e.g.
+    /// level 0 holds fewer tables than its `level0_file_num_compaction_trigger`, unless writes
+    /// triggered new flushes in the meantime. Memtables are not flushed, see [`DB::flush`] for
+    /// that.
+    pub async fn wait_for_compaction(&self) -> Result<(), CommitError<R>> {
+        let compaction_tx = { self.mem_storage.read().await.compaction_tx.clone() };
+        loop {
+            let (tx, rx) = oneshot::channel();
+            compaction_tx.send_async(CompactTask::Compact(tx)).await?;
+            rx.await.map_err(|_| CommitError::ChannelClose)?;
 
-        Ok(())
+            // tasks are run in order, so the ones queued before are done
+            if compaction_tx.is_empty() {
+                return Ok(());
+            }
+        }
     }
 
-    /// Flush WAL to the stable storage. If WAL is disabled, this method will do nothing.
+    /// Compact the tables that hold keys in `range` down the levels, and only those, e.g. to
+    /// reclaim the space of overwritten and removed records of a hot key range without rewriting
+    /// the whole tree
     ///
-    /// There is no guarantee that the data will be flushed to WAL because of the buffer. So it is
-    /// necessary to call this method before exiting if data loss is not acceptable. See also
-    /// [`DbOption::disable_wal`] and [`DbOption::wal_buffer_size`].
-    pub async fn flush_wal(&self) -> Result<(), DbError> {
-        self.mem_storage.write().await.flush_wal().await?;
-        Ok(())
+    /// With the leveled compaction strategy, the tables of each level that hold keys in `range`
+    /// are merged with the tables of the next level they overlap, down to the bottom most level
+    /// that holds tables. Other compactors run a manual compaction of all tables instead.
+    /// Memtables are not flushed, see [`DB::flush`] for that.
+    pub async fn compact_range(
+        &self,
+        range: (
+            Bound<<R::Schema as Schema>::Key>,
+            Bound<<R::Schema as Schema>::Key>,
+        ),
+    ) -> Result<CompactionReport, CommitError<R>> {
+        let (tx, rx) = oneshot::channel();
+        let compaction_tx = { self.mem_storage.read().await.compaction_tx.clone() };
+        compaction_tx
+            .send_async(CompactTask::CompactRange(Box::new(range), tx))
+            .await?;
+
+        rx.await.map_err(|_| CommitError::ChannelClose)
     }
 
-    /// Destroy [`DB`].
+    /// Remove the records of all keys in `range`
     ///
-    /// **Note:** This will remove all wal and manifest file in the directory.
-    pub async fn destroy(self) -> Result<(), DbError> {
-        self.mem_storage
-            .write()
-            .await
-            .destroy(&self.ctx.manager)
+    /// Memtables get a removal of each of their keys in the range. The SSTables are not
+    /// rewritten, a range tombstone in the manifest hides the records they hold in the range
+    /// instead, until compactions dropped all of them. Writes after the call are not affected.
+    pub async fn delete_range(
+        &self,
+        range: (
+            Bound<<R::Schema as Schema>::Key>,
+            Bound<<R::Schema as Schema>::Key>,
+        ),
+    ) -> Result<(), CommitError<R>> {
+        let (tx, rx) = oneshot::channel();
+        let compaction_tx = { self.mem_storage.read().await.compaction_tx.clone() };
+        compaction_tx
+            .send_async(CompactTask::DeleteRange(Box::new(range), tx))
             .await?;
-        if let Some(mut ctx) = Arc::into_inner(self.ctx) {
-            ctx.manifest.destroy().await?;
-        }
 
-        Ok(())
+        Ok(rx.await.map_err(|_| CommitError::ChannelClose)??)
     }
-}
-
-/// DbStorage state for coordinating in-memory + on-disk operations
-pub struct DbStorage<R>
-where
-    R: Record,
-{
-    #[allow(private_interfaces)]
-    pub mutable: MutableMemTable<R>,
-    pub immutables: Vec<(
-        Option<FileId>,
-        ImmutableMemTable<<R::Schema as Schema>::Columns>,
-    )>,
-    compaction_tx: Sender<CompactTask>,
-    recover_wal_ids: Option<Vec<FileId>>,
-    trigger: Arc<dyn FreezeTrigger<R>>,
-    record_schema: Arc<R::Schema>,
-    option: Arc<DbOption>,
-    // Indicates a compaction window where immutables are drained and not yet visible in manifest
-    compaction_in_progress: AtomicBool,
-}
 
-impl<R> DbStorage<R>
-where
-    R: Record + Send,
-{
-    /// Creates a new instane of 'DbStorage'. If there are write ahead logs in the directory this
-    /// function will reconstruct the record batches and recovery the version before crash.
-    async fn new(
-        option: Arc<DbOption>,
-        compaction_tx: Sender<CompactTask>,
-        manifest: &dyn ManifestStorage<R>,
-        record_schema: Arc<R::Schema>,
-        manager: &StoreManager,
-    ) -> Result<Self, DbError> {
-        let base_fs = manager.base_fs();
-        let wal_dir_path = option.wal_dir_path();
-        let mut transaction_map = HashMap::new();
+    /// Write the footers cached since they were last written to the file of
+    /// [`DbOption::footer_cache`], which otherwise happens after flushes and compactions
+    ///
+    /// Does nothing without a footer cache. Useful for a DB that only serves reads, e.g. before
+    /// it is shut down.
+    pub async fn persist_footer_cache(&self) -> Result<(), DbError> {
+        let manager = &self.ctx.manager;
+        manager
+            .readers()
+            .footers()
+            .persist(manager.local_fs())
+            .await
+            .map_err(DbError::Fusio)
+    }
 
-        // Collect all write ahead logs in the WAL directory
-        let wal_metas = {
-            let mut wal_metas = Vec::new();
-            let mut wal_stream = base_fs.list(&wal_dir_path).await?;
+    /// Scan `range` through a blocking [`ScanBatchReader`] for synchronous Arrow consumers
+    ///
+    /// The scan runs on `executor` against a snapshot of the DB and sends batches of up to
+    /// `batch_size` rows to the reader, one batch ahead of the consumer. `configure` can set the
+    /// projection, limit or order of the scan. The internal `_null` and `_ts` columns are not
+    /// part of the batches.
+    ///
+    /// This blocks until the scan has started, so it must not be called on a thread that drives
+    /// async tasks.
+    pub fn batch_reader<F>(
+        self: &Arc<Self>,
+        executor: &E,
+        range: (
+            Bound<<R::Schema as Schema>::Key>,
+            Bound<<R::Schema as Schema>::Key>,
+        ),
+        batch_size: usize,
+        configure: F,
+    ) -> Result<ScanBatchReader, DbError>
+    where
+        F: for<'scan, 'range> FnOnce(Scan<'scan, 'range, R>) -> Scan<'scan, 'range, R>
+            + MaybeSend
+            + 'static,
+    {
+        let db = self.clone();
+        let (schema_tx, schema_rx) = bounded(1);
+        let (batch_tx, batch_rx) = bounded(1);
 
-            while let Some(file_meta) = wal_stream.next().await {
-                let file_meta = file_meta?;
-                if file_meta.path.as_ref().ends_with("wal") {
-                    wal_metas.push(file_meta);
+        executor.spawn(async move {
+            let snapshot = db.snapshot().await;
+            let scan = configure(snapshot.scan((range.0.as_ref(), range.1.as_ref())));
+            let (schema, indices) = match scan.user_projection() {
+                Ok(projection) => projection,
+                Err(err) => {
+                    let _ = schema_tx.send(Err(DbError::from(err)));
+                    return;
+                }
+            };
+            let batches = match scan.package(batch_size).await {
+                Ok(batches) => batches,
+                Err(err) => {
+                    let _ = schema_tx.send(Err(err));
+                    return;
                 }
+            };
+            if schema_tx.send(Ok(Arc::new(schema))).is_err() {
+                return;
             }
-            wal_metas.sort_by(|meta_a, meta_b| meta_a.path.cmp(&meta_b.path));
-            wal_metas
-        };
-
-        let trigger = TriggerFactory::create(option.trigger_type);
-        let mut mem_storage = DbStorage {
-            mutable: MutableMemTable::new(
-                &option,
-                trigger.clone(),
-                manager.base_fs().clone(),
-                record_schema.clone(),
-            )
-            .await?,
-            immutables: Default::default(),
-            compaction_tx,
-            recover_wal_ids: None,
-            trigger,
-            record_schema,
-            option: option.clone(),
-            compaction_in_progress: AtomicBool::new(false),
-        };
-
-        let mut wal_ids = Vec::new();
-
-        // Iterates through all the write ahead logs and reconstructs each record for every batch.
-        for wal_meta in wal_metas {
-            let wal_path = wal_meta.path;
-
-            // SAFETY: wal_stream return only file name
-            let wal_id = parse_file_id(&wal_path, FileType::Wal)?.unwrap();
-            wal_ids.push(wal_id);
-
-            let mut recover_stream =
-                pin!(WalFile::<R>::recover(option.base_fs.clone(), wal_path).await);
-            while let Some(record) = recover_stream.next().await {
-                let record_batch = record?;
-
-                for entry in record_batch {
-                    let Log {
-                        key,
-                        value,
-                        log_type,
-                    } = entry;
-                    let ts = key.ts;
-                    let key = key.value;
-
-                    let is_excess = match log_type.unwrap() {
-                        LogType::Full => {
-                            mem_storage
-                                .recover_append(key, manifest.increase_ts(), value)
-                                .await?
-                        }
-                        LogType::First => {
-                            transaction_map.insert(ts, vec![(key, value)]);
-                            WriteResult::Continue
-                        }
-                        LogType::Middle => {
-                            transaction_map.get_mut(&ts).unwrap().push((key, value));
-                            WriteResult::Continue
-                        }
-                        LogType::Last => {
-                            let mut is_excess = WriteResult::Continue;
-                            let mut records = transaction_map.remove(&ts).unwrap();
-                            records.push((key, value));
-
-                            // Increase timestamp for each multipart record.
-                            let ts = manifest.increase_ts();
-                            for (key, value_option) in records {
-                                is_excess =
-                                    mem_storage.recover_append(key, ts, value_option).await?;
-                            }
-                            is_excess
-                        }
-                    };
 
-                    // Compact during recovery if exceeded memory threshold
-                    if is_excess.needs_compaction() {
-                        let _ = mem_storage.compaction_tx.try_send(CompactTask::Freeze);
-                    };
+            let mut batches = pin!(batches);
+            while let Some(columns) = batches.next().await {
+                let batch = columns
+                    .map_err(|err| ArrowError::ExternalError(Box::new(err)))
+                    .and_then(|columns| columns.as_record_batch().project(&indices));
+                let is_err = batch.is_err();
+                // the reader was dropped or the scan failed
+                if batch_tx.send_async(batch).await.is_err() || is_err {
+                    break;
                 }
             }
-        }
-        mem_storage.recover_wal_ids = Some(wal_ids);
+        });
+        let schema = schema_rx.recv().map_err(|_| DbError::Canceled)??;
 
-        Ok(mem_storage)
+        Ok(ScanBatchReader::new(schema, batch_rx))
     }
 
-    // Write individual record to mutable memtable
-    async fn write(
+    /// Get the record with `key` as the primary key and process it using closure `f`
+    pub async fn get<T>(
         &self,
-        log_ty: LogType,
-        record: R,
-        ts: Timestamp,
-    ) -> Result<WriteResult, DbError> {
-        self.mutable.insert(log_ty, record, ts).await
+        key: &<R::Schema as Schema>::Key,
+        mut f: impl FnMut(TransactionEntry<'_, R>) -> Option<T>,
+    ) -> Result<Option<T>, CommitError<R>> {
+        Ok(self
+            .get_at_latest(key, Projection::All, |entry| {
+                f(TransactionEntry::Stream(entry))
+            })
+            .await?
+            .flatten())
     }
 
-    // Remove individual record from mutable memtable
-    async fn remove(
+    /// Get the latest committed record with `key` as the primary key and process it using
+    /// closure `f`
+    ///
+    /// Only the read timestamp and the current version are taken, no transaction state is set
+    /// up and no reads are tracked, which suits read-only services.
+    pub async fn get_at_latest<T>(
         &self,
-        log_ty: LogType,
-        key: <R::Schema as Schema>::Key,
-        ts: Timestamp,
-    ) -> Result<WriteResult, DbError> {
-        self.mutable.remove(log_ty, key, ts).await
+        key: &<R::Schema as Schema>::Key,
+        projection: Projection<'_>,
+        f: impl FnOnce(Entry<'_, R>) -> T,
+    ) -> Result<Option<T>, DbError> {
+        loop {
+            let guard = self.mem_storage.read().await;
+            if guard.compaction_in_progress.load(Ordering::Acquire) {
+                drop(guard);
+                continue;
+            }
+            break Ok(guard
+                .get(
+                    &self.ctx,
+                    &self.ctx.manifest().current().await,
+                    key,
+                    self.ctx.load_ts(),
+                    projection,
+                )
+                .await?
+                .filter(|entry| entry.value().is_some())
+                .map(f));
+        }
     }
 
-    // Make a recovery append
-    async fn recover_append(
+    /// Get the latest committed records of `keys` and process each of them using closure `f`,
+    /// with the outputs in the order of `keys` and `None` for the keys without a record
+    ///
+    /// The SSTable reads of all keys are batched, so on an object store this takes about as
+    /// many round trips as a single get instead of one get per key.
+    pub async fn get_many<T>(
         &self,
-        key: <R::Schema as Schema>::Key,
-        ts: Timestamp,
-        value: Option<R>,
-    ) -> Result<WriteResult, DbError> {
-        // Passes in None as we do not need it to be durably logged
-        self.mutable.append(None, key, ts, value).await
-    }
-
-    // Retrieve record using primary key
-    async fn get<'get>(
-        &'get self,
-        ctx: &Context<R>,
-        version: &'get Version<R>,
-        key: &'get <R::Schema as Schema>::Key,
-        ts: Timestamp,
-        projection: Projection<'get>,
-    ) -> Result<Option<Entry<'get, R>>, DbError> {
-        let pk_indices = self.record_schema.primary_key_indices();
-        let schema = ctx.arrow_schema();
-
-        let projection = match projection {
-            Projection::All => ProjectionMask::all(),
-            Projection::Parts(projection) => {
-                let mut fixed_projection: Vec<usize> =
-                    Vec::with_capacity(2 + pk_indices.len() + projection.len());
-                fixed_projection.push(0);
-                fixed_projection.push(1);
-                fixed_projection.extend_from_slice(pk_indices);
-                fixed_projection.extend(projection.into_iter().map(|name| {
-                    schema
-                        .index_of(name)
-                        .unwrap_or_else(|_| panic!("unexpected field {name}"))
-                }));
-                fixed_projection.dedup();
-
-                ProjectionMask::roots(
-                    &ArrowSchemaConverter::new().convert(schema).unwrap(),
-                    fixed_projection,
-                )
+        keys: &[<R::Schema as Schema>::Key],
+        projection: Projection<'_>,
+        mut f: impl FnMut(Entry<'_, R>) -> T,
+    ) -> Result<Vec<Option<T>>, DbError> {
+        let keys = keys.iter().collect::<Vec<_>>();
+        loop {
+            let guard = self.mem_storage.read().await;
+            if guard.compaction_in_progress.load(Ordering::Acquire) {
+                drop(guard);
+                continue;
             }
-        };
-
-        if let Some(entry) = self.mutable.get(key, ts) {
-            return Ok(Some(Entry::Projection((
-                Box::new(Entry::Mutable(entry)),
-                Arc::new(projection),
-            ))));
+            break Ok(guard
+                .get_many(
+                    &self.ctx,
+                    &self.ctx.manifest().current().await,
+                    &keys,
+                    self.ctx.load_ts(),
+                    projection,
+                )
+                .await?
+                .into_iter()
+                .map(|entry| entry.filter(|entry| entry.value().is_some()).map(&mut f))
+                .collect());
         }
+    }
 
-        for (_, immutable) in self.immutables.iter().rev() {
-            if let Some(entry) = immutable.get(key, ts, projection.clone()) {
-                return Ok(Some(Entry::RecordBatch(entry)));
+    /// Whether `key` may have a record, which only checks the memtables, the key ranges of the
+    /// SSTables and the keys that point lookups found absent, so no SSTable is read
+    ///
+    /// [`Existence::No`] is certain, e.g. for a dedup pipeline to skip the get of a key it did not
+    /// see yet, while a key within the range of an SSTable is [`Existence::Maybe`] whether or not
+    /// the table holds it.
+    pub async fn may_exist(&self, key: &<R::Schema as Schema>::Key) -> Existence {
+        loop {
+            let guard = self.mem_storage.read().await;
+            if guard.compaction_in_progress.load(Ordering::Acquire) {
+                drop(guard);
+                continue;
             }
+            break guard.may_exist(
+                &self.ctx,
+                &self.ctx.manifest().current().await,
+                key,
+                self.ctx.load_ts(),
+            );
         }
-
-        // Returns a table query with a projection
-        Ok(version
-            .query(
-                ctx.storage_manager(),
-                TsRef::new(key, ts),
-                projection,
-                ctx.cache().clone(),
-                self.record_schema.primary_key_indices(),
-            )
-            .await?
-            .map(|entry| Entry::RecordBatch(entry)))
     }
 
-    // Performs a concurrency check to make sure a write hasn't already happend before the current
-    // one
-    fn check_conflict(&self, key: &<R::Schema as Schema>::Key, ts: Timestamp) -> bool {
-        self.mutable.check_conflict(key, ts)
-            || self
-                .immutables
-                .iter()
-                .rev()
-                .any(|(_, immutable)| immutable.check_conflict(key, ts))
+    /// Scan records with primary keys in the `range` and process them using closure `f`
+    pub async fn scan<'scan, T: 'scan>(
+        &'scan self,
+        range: (
+            Bound<&'scan <R::Schema as Schema>::Key>,
+            Bound<&'scan <R::Schema as Schema>::Key>,
+        ),
+        f: impl FnMut(TransactionEntry<'_, R>) -> T + 'scan,
+    ) -> impl Stream<Item = Result<T, CommitError<R>>> + 'scan {
+        self.scan_with(range, |scan| scan, f).await
     }
 
-    // Flush the write ahead log to disk
-    async fn flush_wal(&self) -> Result<(), DbError> {
-        self.mutable.flush_wal().await?;
-        Ok(())
+    /// [`DB::scan`] that `configure`s the [`Scan`] before it starts, with the same builder
+    /// [`Transaction::scan`] and [`Snapshot::scan`] return, so limits, projections, orders and
+    /// the other options of a scan need no transaction
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let names = db
+    ///     .scan_with(
+    ///         (Bound::Unbounded, Bound::Unbounded),
+    ///         |scan| scan.projection(&["name"]).reverse().limit(10),
+    ///         |entry| entry.get().name.to_string(),
+    ///     )
+    ///     .await;
+    /// ```
+    pub async fn scan_with<'scan, T: 'scan, F>(
+        &'scan self,
+        range: (
+            Bound<&'scan <R::Schema as Schema>::Key>,
+            Bound<&'scan <R::Schema as Schema>::Key>,
+        ),
+        configure: F,
+        mut f: impl FnMut(TransactionEntry<'_, R>) -> T + 'scan,
+    ) -> impl Stream<Item = Result<T, CommitError<R>>> + 'scan
+    where
+        F: for<'s, 'range> FnOnce(Scan<'s, 'range, R>) -> Scan<'s, 'range, R> + 'scan,
+    {
+        self.scan_snapshot(range, configure, move |entry| {
+            f(TransactionEntry::Stream(entry))
+        })
+        .await
+        .map(|result| result.map_err(CommitError::from))
     }
 
-    // Remove all WALs from the filesystem
-    async fn destroy(&mut self, manager: &StoreManager) -> Result<(), DbError> {
-        self.mutable.destroy().await?;
-
-        let base_fs = manager.base_fs();
-        let wal_dir_path = self.option.wal_dir_path();
-        let mut wal_stream = base_fs.list(&wal_dir_path).await?;
-        let fs = manager.base_fs();
+    /// Scan records whose primary key starts with `prefix` and process them using closure `f`
+    ///
+    /// The scan reads from `prefix` up to the smallest key after every key with the prefix, e.g.
+    /// up to `"user:1;"` for `"user:1:"`. With [`DbOption::prefix_bloom_filter`], the SSTables in
+    /// that range whose filter rules out the prefix are not read.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let orders = db
+    ///     .scan_prefix(&"user:1:".to_string(), |entry| entry.get().amount)
+    ///     .await;
+    /// ```
+    pub async fn scan_prefix<'scan, T: 'scan>(
+        &'scan self,
+        prefix: &'scan <R::Schema as Schema>::Key,
+        f: impl FnMut(TransactionEntry<'_, R>) -> T + 'scan,
+    ) -> impl Stream<Item = Result<T, CommitError<R>>> + 'scan
+    where
+        <R::Schema as Schema>::Key: PrefixKey,
+    {
+        stream! {
+            let upper = prefix.prefix_successor();
+            let range = (
+                Bound::Included(prefix),
+                upper.as_ref().map_or(Bound::Unbounded, Bound::Excluded),
+            );
+            let mut records = pin!(
+                self.scan_with(range, |scan| scan.prefix(prefix.prefix_bytes()), f)
+                    .await
+            );
 
-        while let Some(file_meta) = wal_stream.next().await {
-            fs.remove(&file_meta?.path).await?;
+            while let Some(record) = records.next().await {
+                yield record;
+            }
         }
-        Ok(())
     }
-}
-
-/// Scan configuration intermediate structure. Calling `take` will execute a scan on
-/// the memtable, immutable memtables, and SSTables on disk in that order.
-///
-/// To initialize you can call `DB.transaction().scan()` using your `DB` instance.
-/// For more information on how to use refer to the doc in [`DB::transaction()`]
-pub struct Scan<'scan, 'range, R>
-where
-    R: Record,
-    'range: 'scan,
-{
-    // Storage to scan
-    mem_storage: &'scan DbStorage<R>,
-    // Lower and upper bound for the scan
-    lower: Bound<&'range <R::Schema as Schema>::Key>,
-    upper: Bound<&'range <R::Schema as Schema>::Key>,
-    // Current `Snapshot`'s timestamp
-    ts: Timestamp,
-    // DB Version that is being scanned
-    version: &'scan Version<R>,
-    fn_pre_stream: Box<
-        dyn FnOnce(Option<ProjectionMask>, Option<Order>) -> Option<ScanStream<'scan, R>>
-            + Send
-            + 'scan,
-    >,
-    // Row limit for query
-    limit: Option<usize>,
-    // Specifies the direction a scan will take
-    order: Option<Order>,
-    projection_indices: Option<Vec<usize>>,
-    projection: ProjectionMask,
-    ctx: Arc<Context<R>>,
-}
 
-impl<'scan, 'range, R> Scan<'scan, 'range, R>
-where
-    R: Record + Send,
-{
-    fn new(
-        mem_storage: &'scan DbStorage<R>,
-        (lower, upper): (
-            Bound<&'range <R::Schema as Schema>::Key>,
-            Bound<&'range <R::Schema as Schema>::Key>,
+    /// Scan the latest committed records with primary keys in the `range`, `configure`d like
+    /// [`DB::scan_with`], and process them using closure `f`
+    ///
+    /// Like [`DB::get_at_latest`], the scan only takes the read timestamp and the current
+    /// version when it starts.
+    pub async fn scan_snapshot<'scan, T: 'scan, F>(
+        &'scan self,
+        range: (
+            Bound<&'scan <R::Schema as Schema>::Key>,
+            Bound<&'scan <R::Schema as Schema>::Key>,
         ),
-        ts: Timestamp,
-        version: &'scan Version<R>,
-        fn_pre_stream: Box<
-            dyn FnOnce(Option<ProjectionMask>, Option<Order>) -> Option<ScanStream<'scan, R>>
-                + Send
-                + 'scan,
-        >,
-        ctx: Arc<Context<R>>,
-    ) -> Self {
-        Self {
-            mem_storage,
-            lower,
-            upper,
-            ts,
-            version,
-            fn_pre_stream,
-            limit: None,
-            order: None,
-            projection_indices: None,
-            projection: ProjectionMask::all(),
-            ctx,
-        }
-    }
+        configure: F,
+        mut f: impl FnMut(Entry<'_, R>) -> T + 'scan,
+    ) -> impl Stream<Item = Result<T, DbError>> + 'scan
+    where
+        F: for<'s, 'range> FnOnce(Scan<'s, 'range, R>) -> Scan<'s, 'range, R> + 'scan,
+    {
+        stream! {
+            // Delay stream construction while compaction window is active
+            let schema = loop {
+                let guard = self.mem_storage.read().await;
+                if guard.compaction_in_progress.load(Ordering::Acquire) {
+                    drop(guard);
+                    continue;
+                }
+                break guard;
+            };
+            let current = self.ctx.manifest().current().await;
+            let scan = Scan::new(
+                &schema,
+                range,
+                self.ctx.load_ts(),
+                &*current,
+                Box::new(|_, _, _| None),
+                self.ctx.clone(),
+            );
+            let mut scan = configure(scan).take().await?;
 
-    /// Limit for the scan (number of rows returned)
-    pub fn limit(self, limit: usize) -> Self {
-        Self {
-            limit: Some(limit),
-            ..self
+            while let Some(record) = scan.next().await {
+                yield Ok(f(record?))
+            }
         }
     }
 
-    /// Configures the scan to return results in descending order (reverse order).
+    /// Scan the latest committed records with primary keys in the `range` as Arrow record
+    /// batches of at most `batch_size` rows, see [`Scan::into_record_batches`]
     ///
-    /// By default, scans return results in ascending order. Use this method to scan
-    /// from highest to lowest key values.
+    /// The batches hold the primary key and the columns of `projection`, or all columns without
+    /// one, but not the internal `_null` and `_ts` columns, so they can be handed to any Arrow
+    /// consumer.
     ///
     /// # Example
     ///
     /// ```ignore
-    /// // Scan users in reverse order (newest first)
-    /// let upper = "Blob".into();
-    /// let mut scan = txn
-    ///     .scan((Bound::Included(&name), Bound::Excluded(&upper)))
-    ///     .reverse() // scan in descending order
-    ///     .limit(10) // get last 10 records
-    ///     .take()
-    ///     .await
-    ///     .unwrap();
-    ///
-    /// while let Some(entry) = scan.next().await.transpose().unwrap() {
-    ///     println!("User in reverse order: {:?}", entry.value());
-    /// }
+    /// let batches = db
+    ///     .scan_arrow((Bound::Unbounded, Bound::Unbounded), Some(&["name"][..]), 8192)
+    ///     .await;
     /// ```
-    pub fn reverse(self) -> Self {
-        Self {
-            order: Some(Order::Desc),
-            ..self
+    pub async fn scan_arrow<'scan>(
+        &'scan self,
+        range: (
+            Bound<&'scan <R::Schema as Schema>::Key>,
+            Bound<&'scan <R::Schema as Schema>::Key>,
+        ),
+        projection: Option<&'scan [&'scan str]>,
+        batch_size: usize,
+    ) -> impl Stream<Item = Result<RecordBatch, DbError>> + 'scan {
+        try_stream! {
+            // Delay stream construction while compaction window is active
+            let schema = loop {
+                let guard = self.mem_storage.read().await;
+                if guard.compaction_in_progress.load(Ordering::Acquire) {
+                    drop(guard);
+                    continue;
+                }
+                break guard;
+            };
+            let current = self.ctx.manifest().current().await;
+            let mut scan = Scan::new(
+                &schema,
+                range,
+                self.ctx.load_ts(),
+                &*current,
+                Box::new(|_, _, _| None),
+                self.ctx.clone(),
+            );
+            if let Some(projection) = projection {
+                scan = scan.projection(projection);
+            }
+            let mut batches = pin!(scan.into_record_batches(batch_size).await?);
+
+            while let Some(batch) = batches.next().await {
+                yield batch?;
+            }
         }
     }
 
-    /// fields in projection Record by field indices
-    pub fn projection(self, projection: &[&str]) -> Self {
-        let schema = self.mem_storage.record_schema.arrow_schema();
-        let mut projection = projection
-            .iter()
-            .map(|name| {
-                schema.index_of(name).unwrap_or_else(|_| {
-                    panic!("Field in projection does not exist in schema: {name}")
-                })
-            })
-            .collect::<Vec<usize>>();
+    pub(crate) async fn write(&self, record: R, ts: Timestamp) -> Result<(), DbError> {
+        let mem_storage = self.mem_storage.read().await;
 
-        let pk_indices = self.mem_storage.record_schema.primary_key_indices();
+        let write_result = mem_storage.write(LogType::Full, record, ts).await?;
+        if write_result.needs_compaction() {
+            let _ = mem_storage.compaction_tx.try_send(CompactTask::Freeze);
+        };
+        Ok(())
+    }
 
-        // The scan uses a fixed projection of 0: `_null` field, 1: `_ts` field, and all primary key
-        // columns
-        let mut fixed_projection = vec![0, 1];
-        fixed_projection.extend_from_slice(pk_indices);
-        fixed_projection.append(&mut projection);
-        fixed_projection.dedup();
+    // Write a batch of records
+    pub(crate) async fn write_batch(
+        &self,
+        records: impl ExactSizeIterator<Item = R>,
+        ts: Timestamp,
+    ) -> Result<(), DbError> {
+        let mem_storage = self.mem_storage.read().await;
 
-        let mask = ProjectionMask::roots(
-            &ArrowSchemaConverter::new().convert(schema).unwrap(),
-            fixed_projection.clone(),
-        );
+        let entries = records
+            .map(|record| (record.key().to_key(), Some(record)))
+            .collect::<Vec<_>>();
+        if !entries.is_empty() {
+            let is_excess = mem_storage.write_commit(entries, ts).await?;
+            if is_excess.needs_compaction() {
+                let _ = mem_storage.compaction_tx.try_send(CompactTask::Freeze);
+            };
+        };
 
-        Self {
-            projection: mask,
-            projection_indices: Some(fixed_projection),
-            ..self
-        }
+        Ok(())
     }
 
-    /// Fields in projection Record by field indices
-    pub fn projection_with_index(self, mut projection: Vec<usize>) -> Self {
-        // skip two columns: _null and _ts
-        for p in &mut projection {
-            *p += USER_COLUMN_OFFSET;
-        }
+    /// Bytes of decoded SSTable record batches that the scans of the [`DB`] currently hold, see
+    /// [`DbOption::scan_memory_limit`]
+    pub fn scan_memory_used(&self) -> usize {
+        self.ctx.scan_memory().used()
+    }
 
-        let pk_indices = self.mem_storage.record_schema.primary_key_indices();
+    /// Thresholds that are currently used by the [`DbOption::adaptive_tuning`] of the [`DB`],
+    /// `None` without tuning
+    pub fn tuned_thresholds(&self) -> Option<TunedThresholds> {
+        self.ctx.tuner().thresholds()
+    }
 
-        // The scan uses a fixed projection of 0: `_null` field, 1: `_ts` field, and all primary key
-        // columns
-        let mut fixed_projection = vec![0, 1];
-        fixed_projection.extend_from_slice(pk_indices);
-        fixed_projection.append(&mut projection);
-        fixed_projection.dedup();
+    /// Memory held by the components of the [`DB`] and the number of its memtables and SSTables
+    ///
+    /// The memory is tracked as it is allocated and released, so this only sums up counters and
+    /// can be polled, e.g. to keep the total memtable size of several DBs within a budget.
+    pub async fn stats(&self) -> DbStats {
+        let version = self.ctx.current_manifest().await;
+        let storage = self.mem_storage.read().await;
+
+        DbStats {
+            memory: MemoryUsage {
+                mutable: storage.mutable.memory_size(),
+                immutables: storage
+                    .immutables
+                    .iter()
+                    .map(|(_, immutable)| immutable.memory_size())
+                    .sum(),
+                negative_cache: self.ctx.negative_cache().memory_size(),
+                scans: self.ctx.scan_memory().used(),
+                compactions: self.ctx.manager.compaction_memory().used(),
+            },
+            immutable_memtables: storage.immutables.len(),
+            tables_per_level: version.level_slice.iter().map(Vec::len).collect(),
+        }
+    }
 
-        let mask = ProjectionMask::roots(
-            &ArrowSchemaConverter::new()
-                .convert(self.mem_storage.record_schema.arrow_schema())
-                .unwrap(),
-            fixed_projection.clone(),
-        );
+    /// Approximate distribution of the keys and bytes of the [`DB`] over at most `buckets`
+    /// ranges of about the same number of entries, in key order
+    ///
+    /// The distribution is estimated from the key ranges of the SSTables, the row counts in their
+    /// parquet footers and a sample of the keys of the memtables, so no data pages are read. The
+    /// entries of an SSTable are assumed to be spread evenly over the keys known to fall into its
+    /// range. Every version and removal of a key counts as an entry, and entries of the
+    /// memtables are counted at the average entry size of the SSTables. The bounds of the
+    /// buckets make good split keys for [`Sharding::Range`](crate::shard::Sharding::Range).
+    pub async fn key_histogram(
+        &self,
+        buckets: usize,
+    ) -> Result<Vec<KeyBucket<<R::Schema as Schema>::Key>>, DbError> {
+        self.snapshot().await.key_histogram(buckets).await
+    }
 
-        Self {
-            projection: mask,
-            projection_indices: Some(fixed_projection),
-            ..self
-        }
+    /// Smallest key of the [`DB`] that is not removed
+    pub async fn first_key(&self) -> Result<Option<<R::Schema as Schema>::Key>, DbError> {
+        self.first_key_in((Bound::Unbounded, Bound::Unbounded))
+            .await
     }
 
-    /// Get a Stream that returns single row of Record
-    pub async fn take(
-        self,
-    ) -> Result<impl Stream<Item = Result<Entry<'scan, R>, ParquetError>>, DbError> {
-        let mut streams = Vec::new();
-        let is_projection = self.projection_indices.is_some();
+    /// Largest key of the [`DB`] that is not removed
+    pub async fn last_key(&self) -> Result<Option<<R::Schema as Schema>::Key>, DbError> {
+        self.last_key_in((Bound::Unbounded, Bound::Unbounded)).await
+    }
 
-        if let Some(pre_stream) =
-            (self.fn_pre_stream)(is_projection.then(|| self.projection.clone()), self.order)
-        {
-            streams.push(pre_stream);
-        }
+    /// Smallest key in `range` that is not removed
+    ///
+    /// Unlike a scan with a limit of one, this does not open every SSTable that overlaps the
+    /// range. The first keys of the memtables and the key ranges of the SSTables give the
+    /// candidates, and only the earliest one is looked up, moving on to the next if it is
+    /// removed. An SSTable is only scanned if the range starts within it.
+    pub async fn first_key_in(
+        &self,
+        range: (
+            Bound<&<R::Schema as Schema>::Key>,
+            Bound<&<R::Schema as Schema>::Key>,
+        ),
+    ) -> Result<Option<<R::Schema as Schema>::Key>, DbError> {
+        self.snapshot().await.edge_key(range, Order::Asc).await
+    }
 
-        // In-memory memtable scan
-        {
-            let mut mutable_scan = self
-                .mem_storage
-                .mutable
-                .scan((self.lower, self.upper), self.ts, self.order)
-                .into();
-            if is_projection {
-                mutable_scan =
-                    MemProjectionStream::new(mutable_scan, self.projection.clone()).into();
-            }
-            streams.push(mutable_scan);
-        }
+    /// Largest key in `range` that is not removed, see [`DB::first_key_in`]
+    pub async fn last_key_in(
+        &self,
+        range: (
+            Bound<&<R::Schema as Schema>::Key>,
+            Bound<&<R::Schema as Schema>::Key>,
+        ),
+    ) -> Result<Option<<R::Schema as Schema>::Key>, DbError> {
+        self.snapshot().await.edge_key(range, Order::Desc).await
+    }
 
-        // Iterates through immutable memtables
-        for (_, immutable) in self.mem_storage.immutables.iter().rev() {
-            streams.push(
-                immutable
-                    .scan(
-                        (self.lower, self.upper),
-                        self.ts,
-                        self.projection.clone(),
-                        self.order,
-                    )
-                    .into(),
-            );
-        }
+    /// Estimated bytes and rows of the SSTables of each level in `range`, see [`RangeStats`]
+    ///
+    /// Only the scopes of the tables in the version and the footers of the tables that overlap
+    /// the range without lying within it are read, so this suits planning queries and
+    /// monitoring the size of key ranges. Every version and removal of a key counts as a row,
+    /// see [`DB::count`] for the number of records.
+    pub async fn approximate_stats(
+        &self,
+        range: (
+            Bound<&<R::Schema as Schema>::Key>,
+            Bound<&<R::Schema as Schema>::Key>,
+        ),
+    ) -> Result<RangeStats, DbError> {
+        self.snapshot().await.approximate_stats(range).await
+    }
 
-        // Scans all SSTables in the coreresponding version
-        self.version
-            .streams(
-                &self.ctx,
-                &mut streams,
-                (self.lower, self.upper),
-                self.ts,
-                self.limit,
-                self.projection,
-                self.order,
-                self.mem_storage.record_schema.primary_key_indices(),
-            )
-            .await?;
+    /// Number of records with primary keys in `range` at the latest commit, see [`Scan::count`]
+    pub async fn count(
+        &self,
+        range: (
+            Bound<&<R::Schema as Schema>::Key>,
+            Bound<&<R::Schema as Schema>::Key>,
+        ),
+    ) -> Result<u64, DbError> {
+        self.snapshot().await.scan(range).count().await
+    }
 
-        let mut merge_stream = MergeStream::from_vec(streams, self.ts, self.order).await?;
-        if let Some(limit) = self.limit {
-            merge_stream = merge_stream.limit(limit);
-        }
-        Ok(merge_stream)
+    /// Flush WAL to the stable storage. If WAL is disabled, this method will do nothing.
+    ///
+    /// There is no guarantee that the data will be flushed to WAL because of the buffer. So it is
+    /// necessary to call this method before exiting if data loss is not acceptable. See also
+    /// [`DbOption::disable_wal`] and [`DbOption::wal_buffer_size`].
+    pub async fn flush_wal(&self) -> Result<(), DbError> {
+        self.mem_storage.write().await.flush_wal().await?;
+        Ok(())
     }
 
-    /// Get a Stream that returns RecordBatch consisting of a `batch_size` number of records
-    pub async fn package(
-        self,
-        batch_size: usize,
-    ) -> Result<
-        impl Stream<Item = Result<<R::Schema as Schema>::Columns, ParquetError>> + 'scan,
-        DbError,
-    > {
-        let mut streams = Vec::new();
-        let is_projection = self.projection_indices.is_some();
+    /// Stream a consistent backup of the [`DB`] into `writer`.
+    ///
+    /// The in-memory data is flushed first, then the manifest and every SSTable it references are
+    /// written as a single archive (see [`backup`] for the format). Tables stay pinned for the
+    /// duration of the backup, so it can run concurrently with compaction.
+    pub async fn backup_to<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<BackupReport, CommitError<R>> {
+        self.flush().await?;
 
-        if let Some(pre_stream) =
-            (self.fn_pre_stream)(is_projection.then(|| self.projection.clone()), self.order)
+        let version = self.ctx.manifest().current().await;
+        let report = backup::write_backup(writer, &version, self.ctx.storage_manager())
+            .await
+            .map_err(DbError::Backup)?;
+
+        Ok(report)
+    }
+
+    /// Describe the SSTables of the current version, see [`dump`]
+    #[cfg(feature = "dump")]
+    pub async fn dump_manifest(&self) -> dump::ManifestDump {
+        dump::dump(&self.ctx.manifest().current().await)
+    }
+
+    /// Add the Parquet files at `paths` on the base file system as SSTables of `level`, see
+    /// [`ingest`]
+    ///
+    /// The files are checked and copied to the path of `level` under new ids, and are only added
+    /// once all of them passed, so a failed ingestion leaves the DB as it was. Ingesting into a
+    /// deep level, e.g. the bottom level, saves compactions from rewriting the tables on their
+    /// way down.
+    pub async fn ingest_parquet(
+        &self,
+        paths: &[fusio::path::Path],
+        level: usize,
+    ) -> Result<IngestReport, DbError> {
+        if level >= version::MAX_LEVEL {
+            return Err(IngestError::InvalidLevel(level).into());
+        }
+        let (option, schema) = {
+            let storage = self.mem_storage.read().await;
+            (storage.option.clone(), storage.record_schema.clone())
+        };
+        let manager = &self.ctx.manager;
+        let ts = self.ctx.load_ts();
+        let table_schema = self.ctx.manifest().current().await.table_schema(&schema);
+
+        let mut scopes = Vec::with_capacity(paths.len());
+        for path in paths {
+            let gen = option.generate_file_id();
+            let scope =
+                ingest::inspect::<R>(manager.base_fs(), path, gen, &schema, &table_schema, ts)
+                    .await?;
+            scopes.push((path, scope));
+        }
+        scopes.sort_by(|(_, a), (_, b)| a.min.cmp(&b.min));
+        if let Some(pair) = scopes
+            .windows(2)
+            .find(|pair| pair[1].1.min <= pair[0].1.max)
         {
-            streams.push(pre_stream);
+            return Err(IngestError::Overlap(pair[1].0.clone()).into());
         }
 
-        {
-            let mut mutable_scan = self
-                .mem_storage
-                .mutable
-                .scan((self.lower, self.upper), self.ts, self.order)
-                .into();
-            if is_projection {
-                mutable_scan =
-                    MemProjectionStream::new(mutable_scan, self.projection.clone()).into();
+        let mut copied = Vec::with_capacity(scopes.len());
+        let result = async {
+            for (path, scope) in scopes.iter() {
+                let (table_fs, target) = manager.new_table(&option, scope.gen, level);
+                copied.push((table_fs.clone(), target.clone()));
+                fs::copy(&**manager.base_fs(), path, &**table_fs, &target)
+                    .await
+                    .map_err(IngestError::Fusio)?;
             }
-            streams.push(mutable_scan);
-        }
 
-        for (_, immutable) in self.mem_storage.immutables.iter().rev() {
-            streams.push(
-                immutable
-                    .scan(
-                        (self.lower, self.upper),
-                        self.ts,
-                        self.projection.clone(),
-                        self.order,
-                    )
-                    .into(),
-            );
+            // flushes wait for the check, so no memtable moves into the SSTables in between
+            let storage = self.mem_storage.write().await;
+            let version = self.ctx.manifest().current().await;
+            for (path, scope) in scopes.iter() {
+                let range = (Bound::Included(&scope.min), Bound::Included(&scope.max));
+                if storage.mutable.edge_key(range, None).is_some()
+                    || storage
+                        .immutables
+                        .iter()
+                        .any(|(_, immutable)| immutable.meets_range(range))
+                    || version
+                        .level_slice
+                        .iter()
+                        .flatten()
+                        .any(|table| table.meets_range(range))
+                {
+                    return Err(IngestError::Overlap((*path).clone()));
+                }
+            }
+            let edits = scopes
+                .iter()
+                .map(|(_, scope)| VersionEdit::Add {
+                    level: level as u8,
+                    scope: scope.clone(),
+                })
+                .collect();
+            self.ctx.manifest().update(edits, None).await?;
+            drop(storage);
+            Ok(())
         }
-        self.version
-            .streams(
-                &self.ctx,
-                &mut streams,
-                (self.lower, self.upper),
-                self.ts,
-                self.limit,
-                self.projection,
-                self.order,
-                self.mem_storage.record_schema.primary_key_indices(),
-            )
+        .await;
+        if let Err(err) = result {
+            for (table_fs, target) in copied {
+                let _ = table_fs.remove(&target).await;
+            }
+            return Err(err.into());
+        }
+
+        Ok(IngestReport {
+            level,
+            rows: scopes.iter().map(|(_, scope)| scope.rows).sum(),
+            tables: scopes.into_iter().map(|(_, scope)| scope.gen).collect(),
+        })
+    }
+
+    /// Write the records of a snapshot of the [`DB`] as Parquet files and a manifest into the
+    /// directory `path` on `fs`, see [`export`]
+    ///
+    /// The snapshot keeps its version and memtables pinned until the export is written, so
+    /// writes go on meanwhile but memtables are not flushed.
+    #[cfg(feature = "dump")]
+    pub async fn export_snapshot(
+        &self,
+        fs: Arc<dyn DynFs>,
+        path: &fusio::path::Path,
+    ) -> Result<ExportManifest, DbError> {
+        let (option, schema) = {
+            let storage = self.mem_storage.read().await;
+            (storage.option.clone(), storage.record_schema.clone())
+        };
+        let snapshot = self.snapshot().await;
+
+        export::export(
+            snapshot.scan((Bound::Unbounded, Bound::Unbounded)),
+            snapshot.ts(),
+            schema.primary_key_indices(),
+            &*fs,
+            path,
+            option.max_sst_file_size,
+        )
+        .await
+    }
+
+    /// Read every SSTable of the current version and report where it does not match the
+    /// manifest, see [`verify`]
+    ///
+    /// The tables stay pinned while they are read, so the check can run concurrently with
+    /// compaction. Every table is read in full, which costs as much as a full scan.
+    pub async fn verify_integrity(&self) -> IntegrityReport {
+        let (option, schema) = {
+            let storage = self.mem_storage.read().await;
+            (storage.option.clone(), storage.record_schema.clone())
+        };
+        let version = self.ctx.manifest().current().await;
+
+        verify::verify(&version, &option, &self.ctx, &schema).await
+    }
+
+    /// Destroy [`DB`].
+    ///
+    /// **Note:** This will remove all wal and manifest file in the directory.
+    pub async fn destroy(self) -> Result<(), DbError> {
+        self.mem_storage
+            .write()
+            .await
+            .destroy(&self.ctx.manager)
             .await?;
-        let merge_stream = MergeStream::from_vec(streams, self.ts, self.order).await?;
+        if let Some(mut ctx) = Arc::into_inner(self.ctx) {
+            ctx.manifest.destroy().await?;
+        }
 
-        Ok(PackageStream::new(
-            batch_size,
-            merge_stream,
-            self.projection_indices,
-            self.ctx.arrow_schema().clone(),
-        ))
+        Ok(())
     }
 }
 
-#[derive(Debug, Error)]
-pub enum DbError {
-    #[error("write io error: {0}")]
-    Io(#[from] io::Error),
-    #[error("write version error: {0}")]
-    Version(#[from] VersionError),
-    #[error("write manifest storage error: {0}")]
-    Manifest(#[from] ManifestStorageError),
-    #[error("write parquet error: {0}")]
-    Parquet(#[from] ParquetError),
-    #[error("write ulid decode error: {0}")]
-    UlidDecode(#[from] ulid::DecodeError),
-    #[error("write fusio error: {0}")]
-    Fusio(#[from] fusio::Error),
-    // #[error("write encode error: {0}")]
-    // Encode(<<R as Record>::Ref as Encode>::Error),
-    #[error("write recover error: {0}")]
-    Recover(#[from] RecoverError<fusio::Error>),
-    #[error("wal write error: {0}")]
-    WalWrite(Box<dyn std::error::Error + Send + Sync + 'static>),
-    #[error("exceeds the maximum level(0-6)")]
-    ExceedsMaxLevel,
-    #[error("write log error: {0}")]
-    Logger(#[from] fusio_log::error::LogError),
+/// DbStorage state for coordinating in-memory + on-disk operations
+pub struct DbStorage<R>
+where
+    R: Record,
+{
+    #[allow(private_interfaces)]
+    pub mutable: MutableMemTable<R>,
+    pub immutables: Vec<(
+        Option<FileId>,
+        ImmutableMemTable<<R::Schema as Schema>::Columns>,
+    )>,
+    compaction_tx: Sender<CompactTask>,
+    recover_wal_ids: Option<Vec<FileId>>,
+    trigger: Arc<dyn FreezeTrigger<R>>,
+    record_schema: Arc<R::Schema>,
+    option: Arc<DbOption>,
+    // Indicates a compaction window where immutables are drained and not yet visible in manifest
+    compaction_in_progress: AtomicBool,
+    // Keys by the time their records expire, if `DbOption::expires_at` is set
+    expiry: Option<ExpiryIndex<R>>,
+    // Aggregates of `DbOption::aggregate` by name
+    aggregates: Aggregates<R>,
+    watchers: Watchers<<R::Schema as Schema>::Key>,
 }
 
-type LockMap<K> = Arc<LockableHashMap<K, ()>>;
+impl<R> DbStorage<R>
+where
+    R: Record + Send,
+{
+    /// Creates a new instane of 'DbStorage'. If there are write ahead logs in the directory this
+    /// function will reconstruct the record batches and recovery the version before crash.
+    ///
+    /// Write ahead logs are decoded concurrently on `executor`, at most
+    /// [`DbOption::wal_recover_parallelism`] at a time.
+    async fn new<E>(
+        option: Arc<DbOption>,
+        compaction_tx: Sender<CompactTask>,
+        manifest: &dyn ManifestStorage<R>,
+        record_schema: Arc<R::Schema>,
+        manager: &StoreManager,
+        executor: &E,
+    ) -> Result<Self, DbError>
+    where
+        E: Executor,
+    {
+        let base_fs = manager.base_fs();
+        let wal_dir_path = option.wal_dir_path();
 
-pub enum Projection<'r> {
-    All,
-    Parts(Vec<&'r str>),
-}
+        // Collect all write ahead logs in the WAL directory
+        let wal_metas = {
+            let mut wal_metas = Vec::new();
+            let mut wal_stream = base_fs.list(&wal_dir_path).await?;
 
-pub type ParquetLru = Arc<dyn DynLruCache<FileId> + Send + Sync>;
+            while let Some(file_meta) = wal_stream.next().await {
+                let file_meta = file_meta?;
+                if file_meta.path.as_ref().ends_with("wal") {
+                    wal_metas.push(file_meta);
+                }
+            }
+            wal_metas.sort_by(|meta_a, meta_b| meta_a.path.cmp(&meta_b.path));
+            wal_metas
+        };
 
-#[cfg(all(test, feature = "tokio"))]
+        let trigger = TriggerFactory::create(option.trigger_type);
+        let mut mem_storage = DbStorage {
+            mutable: MutableMemTable::new(
+                &option,
+                trigger.clone(),
+                manager.base_fs().clone(),
+                record_schema.clone(),
+            )
+            .await?,
+            immutables: Default::default(),
+            compaction_tx,
+            recover_wal_ids: None,
+            trigger,
+            record_schema,
+            option: option.clone(),
+            compaction_in_progress: AtomicBool::new(false),
+            expiry: option
+                .expiry
+                .as_ref()
+                .and_then(|expiry| expiry.expires_at::<R>().ok())
+                .map(ExpiryIndex::new),
+            aggregates: aggregate::aggregates::<R>(&option),
+            watchers: Watchers::default(),
+        };
+
+        let wal_ids = wal_metas
+            .iter()
+            // SAFETY: wal_stream return only file name
+            .map(|wal_meta| Ok(parse_file_id(&wal_meta.path, FileType::Wal)?.unwrap()))
+            .collect::<Result<Vec<_>, DbError>>()?;
+        // Point-in-time recovery cuts the WALs it was first applied to only, the commits written
+        // after it are replayed in full on later opens
+        let recover_until = match option.recover_until {
+            Some(until) => match manifest.current().await.recovered_until {
+                Some((ts, wal)) if ts == until => Some((until, wal, false)),
+                _ => wal_ids.last().map(|wal| (until, *wal, true)),
+            },
+            None => None,
+        };
+        let mut replayed_wals = wal_ids.iter();
+
+        // Decode the write ahead logs concurrently on the executor. `buffered` yields the decoded
+        // files in WAL order, so timestamps are still assigned in the order the commits happened.
+        let mut recovered_wals = stream::iter(wal_metas.into_iter().map(|wal_meta| {
+            let (tx, rx) = oneshot::channel();
+            let fs_option = option.base_fs.clone();
+
+            executor.spawn(async move {
+                let _ = tx.send(WalFile::<R>::recover_commits(fs_option, wal_meta.path).await);
+            });
+            rx
+        }))
+        .buffered(option.wal_recover_parallelism);
+
+        // Reconstructs each record for every commit of every write ahead log.
+        while let Some(commits) = recovered_wals.next().await {
+            let commits = commits.map_err(|_| RecoverError::<fusio::Error>::Canceled)??;
+            let wal_id = replayed_wals.next();
+
+            for (commit_ts, commit) in commits {
+                // Point-in-time recovery skips everything committed after the target timestamp
+                if recover_until.is_some_and(|(until, last_wal, _)| {
+                    wal_id.is_some_and(|wal_id| *wal_id <= last_wal) && commit_ts > until
+                }) {
+                    continue;
+                }
+                // Increase timestamp for each commit, multipart records share the same one.
+                let ts = manifest.increase_ts();
+                let mut is_excess = WriteResult::Continue;
+
+                match commit {
+                    Recovered::Commit(records) => {
+                        for (key, mut value) in records {
+                            if let Some(value) = &mut value {
+                                value.evolve(&mem_storage.record_schema);
+                            }
+                            is_excess = mem_storage.recover_append(key, ts, value).await?;
+                        }
+                    }
+                    Recovered::Merge(mut operand) => {
+                        operand.evolve(&mem_storage.record_schema);
+                        is_excess = mem_storage.mutable.recover_operand(operand, ts);
+                    }
+                }
+
+                // Compact during recovery if exceeded memory threshold
+                if is_excess.needs_compaction() {
+                    let _ = mem_storage.compaction_tx.try_send(CompactTask::Freeze);
+                };
+            }
+        }
+        if let Some((ts, wal, true)) = recover_until {
+            manifest
+                .update(vec![VersionEdit::RecoveredUntil { ts, wal }], None)
+                .await?;
+        }
+        mem_storage.recover_wal_ids = Some(wal_ids);
+
+        Ok(mem_storage)
+    }
+
+    // Write individual record to mutable memtable
+    async fn write(
+        &self,
+        log_ty: LogType,
+        record: R,
+        ts: Timestamp,
+    ) -> Result<WriteResult, DbError> {
+        let key = record.key().to_key();
+        self.index(&key, Some(record.as_record_ref()));
+        let result = self.mutable.insert(log_ty, record, ts).await?;
+        self.watchers.notify(&key, ChangeOp::Insert, ts);
+
+        Ok(result)
+    }
+
+    // Remove individual record from mutable memtable
+    async fn remove(
+        &self,
+        log_ty: LogType,
+        key: <R::Schema as Schema>::Key,
+        ts: Timestamp,
+    ) -> Result<WriteResult, DbError> {
+        self.index(&key, None);
+        let result = self.mutable.remove(log_ty, key.clone(), ts).await?;
+        self.watchers.notify(&key, ChangeOp::Remove, ts);
+
+        Ok(result)
+    }
+
+    // Write the records and removals of a commit to mutable memtable, with a single WAL write
+    async fn write_commit(
+        &self,
+        entries: Vec<(<R::Schema as Schema>::Key, Option<R>)>,
+        ts: Timestamp,
+    ) -> Result<WriteResult, DbError> {
+        let mut changes = Vec::with_capacity(entries.len());
+        for (key, record) in &entries {
+            self.index(key, record.as_ref().map(Record::as_record_ref));
+            let op = match record {
+                Some(_) => ChangeOp::Insert,
+                None => ChangeOp::Remove,
+            };
+            changes.push((key.clone(), op));
+        }
+        let result = self.mutable.append_commit(entries, ts).await?;
+        for (key, op) in changes {
+            self.watchers.notify(&key, op, ts);
+        }
+
+        Ok(result)
+    }
+
+    // Write an operand of `DB::merge` to mutable memtable, which is folded into the record of its
+    // key by the reads and when the memtable is frozen
+    async fn merge(&self, operand: R, ts: Timestamp) -> Result<WriteResult, DbError> {
+        let key = operand.key().to_key();
+        let result = self.mutable.merge(operand, ts).await?;
+        self.watchers.notify(&key, ChangeOp::Insert, ts);
+
+        Ok(result)
+    }
+
+    // Write a tombstone of `range` for the SSTables and a removal of each key of the memtables in
+    // it, which must not be written meanwhile
+    async fn delete_range(
+        &self,
+        ctx: &Context<R>,
+        range: (
+            Bound<<R::Schema as Schema>::Key>,
+            Bound<<R::Schema as Schema>::Key>,
+        ),
+    ) -> Result<WriteResult, DbError> {
+        let ts = ctx.increase_ts();
+        let bounds = (range.0.as_ref(), range.1.as_ref());
+
+        let mut keys = self
+            .mutable
+            .scan(bounds, ts, None)
+            .map(|entry| entry.key().value.clone())
+            .collect::<Vec<_>>();
+        keys.extend(
+            self.mutable
+                .scan_operands(bounds, ts, None)
+                .map(|entry| entry.key().value.clone()),
+        );
+        for (_, immutable) in self.immutables.iter() {
+            keys.extend(
+                immutable
+                    .scan(bounds, ts, ProjectionMask::all(), None)
+                    .map(|entry| entry.key().to_key()),
+            );
+        }
+        keys.sort();
+        keys.dedup();
+
+        let version = ctx.manifest().current().await;
+        let tables = version
+            .level_slice
+            .iter()
+            .flatten()
+            .filter(|scope| scope.meets_range(bounds))
+            .map(|scope| scope.gen)
+            .collect::<Vec<_>>();
+        if !tables.is_empty() {
+            let mut edits = aggregate::range_edits(
+                &self.aggregates,
+                ctx,
+                &version,
+                bounds,
+                self.record_schema.primary_key_indices(),
+            )
+            .await?;
+            edits.push(VersionEdit::DeleteRange {
+                tombstone: RangeTombstone {
+                    lower: range.0.clone(),
+                    upper: range.1.clone(),
+                    ts,
+                    tables,
+                },
+            });
+            ctx.manifest().update(edits, None).await?;
+        }
+        if keys.is_empty() {
+            return Ok(WriteResult::Continue);
+        }
+
+        self.write_commit(keys.into_iter().map(|key| (key, None)).collect(), ts)
+            .await
+    }
+
+    // Leave the keys of the flushed `batches` that the memtables do not hold anymore to the
+    // tables they were flushed to in the expiry index
+    fn flushed(
+        &self,
+        batches: &[(
+            Option<FileId>,
+            ImmutableMemTable<<R::Schema as Schema>::Columns>,
+        )],
+    ) {
+        let Some(expiry) = &self.expiry else {
+            return;
+        };
+        for (_, batch) in batches {
+            for key in batch.sample_keys(1) {
+                let held = self.mutable.get(&key, u32::MAX.into()).is_some()
+                    || self.immutables.iter().any(|(_, immutable)| {
+                        immutable
+                            .get(&key, u32::MAX.into(), ProjectionMask::all())
+                            .is_some()
+                    });
+                if !held {
+                    expiry.flushed(&key);
+                }
+            }
+        }
+    }
+
+    // Update the expiry index with the latest record of `key`, `None` if it was removed
+    fn index(&self, key: &<R::Schema as Schema>::Key, record: Option<R::Ref<'_>>) {
+        if let Some(expiry) = &self.expiry {
+            expiry.update(key, record);
+        }
+    }
+
+    // The aggregate `name` of `group`: the groups stored in `version` count the records of the
+    // SSTables, which the newest entries of the memtables replace for the keys they hold
+    async fn aggregate(
+        &self,
+        ctx: &Context<R>,
+        version: &VersionRef<R>,
+        name: &str,
+        group: &<R::Schema as Schema>::Key,
+    ) -> Result<Option<Aggregate>, DbError> {
+        let (Some(aggregate), Some(groups)) =
+            (self.aggregates.get(name), version.aggregates.get(name))
+        else {
+            return Ok(None);
+        };
+        let mut state = groups.get(group).cloned().unwrap_or_default();
+
+        let ts = ctx.load_ts();
+        let mut keys = self
+            .mutable
+            .sample_keys(1)
+            .chain(
+                self.immutables
+                    .iter()
+                    .flat_map(|(_, immutable)| immutable.sample_keys(1)),
+            )
+            .filter(|key| aggregate.group_of(key).as_ref() == Some(group))
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys.dedup();
+
+        let mut replaced = Vec::new();
+        for key in &keys {
+            let value = match self.mutable.get(key, ts) {
+                Some(entry) => {
+                    Some(aggregate.value_of(entry.value().as_ref().map(Record::as_record_ref)))
+                }
+                None => self
+                    .immutables
+                    .iter()
+                    .rev()
+                    .find_map(|(_, immutable)| immutable.get(key, ts, ProjectionMask::all()))
+                    .map(|entry| aggregate.value_of(entry.get())),
+            };
+            if let Some(value) = value {
+                replaced.push((key, value));
+            }
+        }
+        let stored = aggregate::table_records(
+            ctx,
+            version,
+            &replaced.iter().map(|(key, _)| *key).collect::<Vec<_>>(),
+            self.record_schema.primary_key_indices(),
+        )
+        .await?;
+        for ((_, value), stored) in replaced.into_iter().zip(stored) {
+            state.replace(
+                aggregate.value_of(stored.as_ref().and_then(RecordBatchEntry::get)),
+                value,
+            );
+        }
+
+        Ok((!state.is_empty()).then(|| state.aggregate()))
+    }
+
+    // Make a recovery append
+    async fn recover_append(
+        &self,
+        key: <R::Schema as Schema>::Key,
+        ts: Timestamp,
+        value: Option<R>,
+    ) -> Result<WriteResult, DbError> {
+        self.index(&key, value.as_ref().map(Record::as_record_ref));
+        // Passes in None as we do not need it to be durably logged
+        self.mutable.append(None, key, ts, value).await
+    }
+
+    // Retrieve record using primary key, with the merge operands of the key folded into it
+    async fn get<'get>(
+        &'get self,
+        ctx: &Context<R>,
+        version: &'get VersionRef<R>,
+        key: &'get <R::Schema as Schema>::Key,
+        ts: Timestamp,
+        projection: Projection<'get>,
+    ) -> Result<Option<Entry<'get, R>>, DbError> {
+        let projection = match projection {
+            Projection::All => ProjectionMask::all(),
+            Projection::Parts(projection) => self.record_schema.projection(projection),
+        };
+
+        let expired = self.expired();
+        let operands = self.mutable.operands(key, ts);
+        if operands.is_empty() {
+            let read = match expired {
+                Some(_) => ProjectionMask::all(),
+                None => projection.clone(),
+            };
+            let entry = self.latest(ctx, version, key, ts, read).await?;
+            return Ok(entry
+                .filter(|entry| self.is_live(expired.as_ref(), version, key, ts, entry))
+                .map(|entry| self.project_live(expired.as_ref(), entry, &Arc::new(projection))));
+        }
+
+        let merge_operator = self.merge_operator()?.ok_or(DbError::NoMergeOperator)?;
+        let entry = self
+            .latest(ctx, version, key, ts, ProjectionMask::all())
+            .await?;
+        // a record or removal replaces the operands older than it
+        let base_ts = entry.as_ref().map(|entry| entry.key().ts);
+        let operands = operands
+            .iter()
+            .rev()
+            .filter(|operand| base_ts.map_or(true, |base_ts| operand.key().ts > base_ts))
+            .collect::<Vec<_>>();
+        let Some(newest) = operands.last() else {
+            return Ok(
+                entry.filter(|entry| self.is_live(expired.as_ref(), version, key, ts, entry))
+            );
+        };
+        let newest_ts = newest.key().ts;
+
+        let record = {
+            let existing = entry
+                .as_ref()
+                .filter(|entry| self.is_live(expired.as_ref(), version, key, ts, entry))
+                .and_then(Entry::value);
+            merge::fold(
+                merge_operator.as_ref(),
+                key,
+                existing,
+                operands
+                    .iter()
+                    .map(|operand| operand.value().as_record_ref()),
+            )?
+        };
+        let entry = Entry::Merged((Ts::new(key.clone(), newest_ts), record));
+        Ok(Some(entry)
+            .filter(|entry| self.is_live(expired.as_ref(), version, key, ts, entry))
+            .map(|entry| {
+                Entry::Projection((
+                    Box::new(entry),
+                    Arc::new(projection),
+                    self.record_schema.arrow_schema().clone(),
+                ))
+            }))
+    }
+
+    // The latest record or removal of `key` as of `ts` of the memtables and the SSTables, without
+    // the merge operands of the key
+    async fn latest<'get>(
+        &'get self,
+        ctx: &Context<R>,
+        version: &'get VersionRef<R>,
+        key: &'get <R::Schema as Schema>::Key,
+        ts: Timestamp,
+        projection: ProjectionMask,
+    ) -> Result<Option<Entry<'get, R>>, DbError> {
+        if let Some(entry) = self.mutable.get(key, ts) {
+            return Ok(Some(Entry::Projection((
+                Box::new(Entry::Mutable(entry)),
+                Arc::new(projection),
+                self.record_schema.arrow_schema().clone(),
+            ))));
+        }
+
+        for (_, immutable) in self.immutables.iter().rev() {
+            if let Some(entry) = immutable.get(key, ts, projection.clone()) {
+                return Ok(Some(Entry::RecordBatch(entry)));
+            }
+        }
+
+        if ctx.negative_cache().is_absent(version, key, ts) {
+            return Ok(None);
+        }
+        ctx.hot_ranges().record(key);
+        // Returns a table query with a projection
+        let entry = version
+            .query(
+                ctx.storage_manager(),
+                TsRef::new(key, ts),
+                projection,
+                ctx.cache().clone(),
+                self.record_schema.primary_key_indices(),
+            )
+            .await?;
+        if entry.is_none() {
+            ctx.negative_cache().insert(version, key, ts);
+        }
+
+        Ok(entry.map(Entry::RecordBatch))
+    }
+
+    // Whether the record of `entry` is visible to a read of `key` at `ts`, i.e. it is neither
+    // expired nor removed by a range tombstone
+    fn is_live(
+        &self,
+        expired: Option<&Expired<R>>,
+        version: &VersionRef<R>,
+        key: &<R::Schema as Schema>::Key,
+        ts: Timestamp,
+        entry: &Entry<'_, R>,
+    ) -> bool {
+        let is_expired = match (expired, entry.value()) {
+            (Some(expired), Some(record)) => expired.contains(record),
+            _ => false,
+        };
+        let is_range_removed = entry.value().is_some()
+            && version
+                .range_tombstones
+                .iter()
+                .any(|tombstone| tombstone.hides(key, entry.key().ts, ts));
+        !is_expired && !is_range_removed
+    }
+
+    // Projects `entry` with `projection` if it was read with every column to tell whether it
+    // expired, as any of them may tell when a record expires, see `DbOption::drop_expired`
+    fn project_live<'get>(
+        &self,
+        expired: Option<&Expired<R>>,
+        entry: Entry<'get, R>,
+        projection: &Arc<ProjectionMask>,
+    ) -> Entry<'get, R> {
+        match expired {
+            Some(_) => Entry::Projection((
+                Box::new(entry),
+                projection.clone(),
+                self.record_schema.arrow_schema().clone(),
+            )),
+            None => entry,
+        }
+    }
+
+    // The merge operator that folds the operands of the mutable memtable, `None` if it holds none
+    fn merge_operator(&self) -> Result<Option<DynMergeOperator<R>>, DbError> {
+        if !self.mutable.has_operands() {
+            return Ok(None);
+        }
+        self.option
+            .merge_operator
+            .as_ref()
+            .ok_or(DbError::NoMergeOperator)?
+            .operator::<R>()
+            .map(Some)
+            .map_err(DbError::MergeOperatorRecord)
+    }
+
+    // Fold the merge operands of the mutable memtable into records, so that it can be frozen
+    // into arrays, which only hold records and removals
+    async fn fold_operands(&self, ctx: &Context<R>) -> Result<(), DbError> {
+        let Some(merge_operator) = self.merge_operator()? else {
+            return Ok(());
+        };
+        let version = ctx.manifest().current().await;
+        let expired = self.expired();
+
+        // the operands of a key are folded oldest first, each into the result of the ones before
+        for operand in self.mutable.operands_oldest_first() {
+            let (key, ts) = (&operand.key().value, operand.key().ts);
+            let record = {
+                let entry = self
+                    .latest(ctx, &version, key, ts, ProjectionMask::all())
+                    .await?;
+                let existing = entry
+                    .as_ref()
+                    .filter(|entry| self.is_live(expired.as_ref(), &version, key, ts, entry))
+                    .and_then(Entry::value);
+                merge::fold(
+                    merge_operator.as_ref(),
+                    key,
+                    existing,
+                    iter::once(operand.value().as_record_ref()),
+                )?
+            };
+            let is_latest = self
+                .mutable
+                .get(key, u32::MAX.into())
+                .map_or(true, |entry| entry.key().ts < ts);
+            if is_latest {
+                self.index(key, record.as_ref().map(Record::as_record_ref));
+            }
+            self.mutable.append(None, key.clone(), ts, record).await?;
+            // a fold that fails later on does not fold this operand twice
+            operand.remove();
+        }
+
+        Ok(())
+    }
+
+    // Retrieve the records of `keys`, with the SSTable reads of all of them batched
+    async fn get_many<'get>(
+        &'get self,
+        ctx: &Context<R>,
+        version: &'get VersionRef<R>,
+        keys: &[&'get <R::Schema as Schema>::Key],
+        ts: Timestamp,
+        projection: Projection<'get>,
+    ) -> Result<Vec<Option<Entry<'get, R>>>, DbError> {
+        let parts = match &projection {
+            Projection::All => None,
+            Projection::Parts(projection) => Some(projection.clone()),
+        };
+        let projection = match projection {
+            Projection::All => ProjectionMask::all(),
+            Projection::Parts(projection) => self.record_schema.projection(projection),
+        };
+        let expired = self.expired();
+        let returned_projection = Arc::new(projection.clone());
+        let projection = match expired {
+            Some(_) => ProjectionMask::all(),
+            None => projection,
+        };
+        let mutable_projection = Arc::new(projection.clone());
+
+        let live = |key: &<R::Schema as Schema>::Key, entry: Entry<'get, R>| {
+            self.is_live(expired.as_ref(), version, key, ts, &entry)
+                .then(|| self.project_live(expired.as_ref(), entry, &returned_projection))
+        };
+
+        let mut entries = Vec::with_capacity(keys.len());
+        // keys that are left to the SSTables
+        let mut misses = Vec::new();
+        for (index, &key) in keys.iter().enumerate() {
+            // the merge operands of a key are folded by a read of its own
+            if !self.mutable.operands(key, ts).is_empty() {
+                let projection = match &parts {
+                    Some(names) => Projection::Parts(names.clone()),
+                    None => Projection::All,
+                };
+                entries.push(self.get(ctx, version, key, ts, projection).await?);
+                continue;
+            }
+            if let Some(entry) = self.mutable.get(key, ts) {
+                entries.push(live(
+                    key,
+                    Entry::Projection((
+                        Box::new(Entry::Mutable(entry)),
+                        mutable_projection.clone(),
+                        self.record_schema.arrow_schema().clone(),
+                    )),
+                ));
+                continue;
+            }
+            let immutable = self
+                .immutables
+                .iter()
+                .rev()
+                .find_map(|(_, immutable)| immutable.get(key, ts, projection.clone()));
+            if let Some(entry) = immutable {
+                entries.push(live(key, Entry::RecordBatch(entry)));
+                continue;
+            }
+            entries.push(None);
+            if !ctx.negative_cache().is_absent(version, key, ts) {
+                ctx.hot_ranges().record(key);
+                misses.push(index);
+            }
+        }
+        if misses.is_empty() {
+            return Ok(entries);
+        }
+
+        let found = version
+            .query_many(
+                ctx.storage_manager(),
+                &misses.iter().map(|&index| keys[index]).collect::<Vec<_>>(),
+                ts,
+                projection,
+                ctx.cache().clone(),
+                self.record_schema.primary_key_indices(),
+            )
+            .await?;
+        for (index, entry) in misses.into_iter().zip(found) {
+            match entry {
+                Some(entry) => entries[index] = live(keys[index], Entry::RecordBatch(entry)),
+                None => ctx.negative_cache().insert(version, keys[index], ts),
+            }
+        }
+
+        Ok(entries)
+    }
+
+    // The records that expired by now if they are hidden, see `DbOption::drop_expired`
+    fn expired(&self) -> Option<Expired<R>> {
+        if !self.hides_expired() {
+            return None;
+        }
+        self.expiry
+            .as_ref()
+            .map(|expiry| Expired::new(expiry.expires_at().clone(), self.option.now_ms()))
+    }
+
+    // Whether reads hide expired records, which they read with every column
+    fn hides_expired(&self) -> bool {
+        self.option.drop_expired && self.expiry.is_some()
+    }
+
+    // Whether `key` may have a record as of `ts`, without reading the SSTables
+    fn may_exist(
+        &self,
+        ctx: &Context<R>,
+        version: &VersionRef<R>,
+        key: &<R::Schema as Schema>::Key,
+        ts: Timestamp,
+    ) -> Existence {
+        let exists = |exists: bool| {
+            if exists {
+                Existence::Maybe
+            } else {
+                Existence::No
+            }
+        };
+        if !self.mutable.operands(key, ts).is_empty() {
+            return Existence::Maybe;
+        }
+        if let Some(entry) = self.mutable.get(key, ts) {
+            return exists(entry.value().is_some());
+        }
+        for (_, immutable) in self.immutables.iter().rev() {
+            if let Some(entry) = immutable.get(key, ts, self.record_schema.projection([])) {
+                return exists(entry.get().is_some());
+            }
+        }
+
+        exists(!ctx.negative_cache().is_absent(version, key, ts) && version.may_contain(key))
+    }
+
+    // Performs a concurrency check to make sure a write hasn't already happend before the current
+    // one
+    fn check_conflict(&self, key: &<R::Schema as Schema>::Key, ts: Timestamp) -> bool {
+        self.mutable.check_conflict(key, ts)
+            || self
+                .immutables
+                .iter()
+                .rev()
+                .any(|(_, immutable)| immutable.check_conflict(key, ts))
+    }
+
+    // Flush the write ahead log to disk
+    async fn flush_wal(&self) -> Result<(), DbError> {
+        self.mutable.flush_wal().await?;
+        Ok(())
+    }
+
+    // Remove all WALs from the filesystem
+    async fn destroy(&mut self, manager: &StoreManager) -> Result<(), DbError> {
+        self.mutable.destroy().await?;
+
+        let base_fs = manager.base_fs();
+        let wal_dir_path = self.option.wal_dir_path();
+        let mut wal_stream = base_fs.list(&wal_dir_path).await?;
+        let fs = manager.base_fs();
+
+        while let Some(file_meta) = wal_stream.next().await {
+            fs.remove(&file_meta?.path).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes that are merged over the DB in a scan, e.g. the local writes of a transaction, as a
+/// stream of every key range of the scan
+pub(crate) type FnPreStream<'scan, 'range, R> = Box<
+    dyn Fn(
+            (
+                Bound<&'range <<R as Record>::Schema as Schema>::Key>,
+                Bound<&'range <<R as Record>::Schema as Schema>::Key>,
+            ),
+            Option<ProjectionMask>,
+            Option<Order>,
+        ) -> Option<ScanStream<'scan, R>>
+        + Send
+        + Sync
+        + 'scan,
+>;
+
+/// Scan configuration intermediate structure. Calling `take` will execute a scan on
+/// the memtable, immutable memtables, and SSTables on disk in that order.
+///
+/// To initialize you can call `DB.transaction().scan()` using your `DB` instance.
+/// For more information on how to use refer to the doc in [`DB::transaction()`]
+pub struct Scan<'scan, 'range, R>
+where
+    R: Record,
+    'range: 'scan,
+{
+    // Storage to scan
+    mem_storage: &'scan DbStorage<R>,
+    // Lower and upper bound for the scan
+    lower: Bound<&'range <R::Schema as Schema>::Key>,
+    upper: Bound<&'range <R::Schema as Schema>::Key>,
+    // Further disjoint ranges, see `Scan::ranges`
+    ranges: Vec<(
+        Bound<&'range <R::Schema as Schema>::Key>,
+        Bound<&'range <R::Schema as Schema>::Key>,
+    )>,
+    // Current `Snapshot`'s timestamp
+    ts: Timestamp,
+    // DB Version that is being scanned
+    version: &'scan Version<R>,
+    fn_pre_stream: FnPreStream<'scan, 'range, R>,
+    // Row limit for query
+    limit: Option<usize>,
+    // Number of rows skipped before the first one is returned
+    offset: usize,
+    // Specifies the direction a scan will take
+    order: Option<Order>,
+    projection_indices: Option<Vec<usize>>,
+    projection: ProjectionMask,
+    // Expected access pattern of the SSTables
+    read_hint: Option<ReadHint>,
+    // Pool the SSTable readers are taken from instead of the one of the DB
+    readers: Option<ReaderPool>,
+    // Predicate on the merged records, with how it reads for `explain`
+    filter: Option<(RecordFilter<R>, String)>,
+    // Whether removed keys return the value the removal replaced
+    include_removed: bool,
+    // Bytes every key in the range starts with, see `Scan::prefix`
+    prefix: Option<Arc<[u8]>>,
+    // SSTables left out of the scan, see `Scan::count`
+    skip_tables: Vec<FileId>,
+    ctx: Arc<Context<R>>,
+}
+
+#[cfg(feature = "dyn-record")]
+impl<'scan, 'range> Scan<'scan, 'range, DynRecord> {
+    /// Only return the records that match `filter`, e.g. to push the `WHERE` clause of a query
+    /// down to the DB
+    ///
+    /// The predicate is evaluated on the records that the scan returns, after the versions of
+    /// every key were merged and the projection was applied, so columns left out of the
+    /// projection read as null. It is not pushed into the row filters of the SSTables: a row
+    /// that does not match may still hide an older version of its key in another table that
+    /// does. Records that do not match count towards neither [`Scan::offset`] nor
+    /// [`Scan::limit`].
+    pub fn filter(self, filter: &Expr) -> Result<Self, ExprError> {
+        let predicate = filter.bind(self.ctx.arrow_schema())?;
+        let matches: RecordFilter<DynRecord> =
+            Arc::new(move |record: &DynRecordRef<'_>| predicate.matches(record));
+
+        Ok(Self {
+            filter: Some((matches, filter.to_string())),
+            ..self
+        })
+    }
+}
+
+impl<'scan, 'range, R> Scan<'scan, 'range, R>
+where
+    R: Record + Send,
+{
+    fn new(
+        mem_storage: &'scan DbStorage<R>,
+        (lower, upper): (
+            Bound<&'range <R::Schema as Schema>::Key>,
+            Bound<&'range <R::Schema as Schema>::Key>,
+        ),
+        ts: Timestamp,
+        version: &'scan Version<R>,
+        fn_pre_stream: FnPreStream<'scan, 'range, R>,
+        ctx: Arc<Context<R>>,
+    ) -> Self {
+        Self {
+            mem_storage,
+            lower,
+            upper,
+            ranges: Vec::new(),
+            ts,
+            version,
+            fn_pre_stream,
+            limit: None,
+            offset: 0,
+            order: None,
+            projection_indices: None,
+            projection: ProjectionMask::all(),
+            read_hint: None,
+            readers: None,
+            filter: None,
+            include_removed: false,
+            prefix: None,
+            skip_tables: Vec::new(),
+            ctx,
+        }
+    }
+
+    /// Also scan the key ranges `ranges`, besides the range the scan was created with
+    ///
+    /// The ranges must not overlap, e.g. to read the keys of several partitions. Their records
+    /// come in one stream in the order of the scan, with [`Scan::offset`] and [`Scan::limit`]
+    /// counted across all of them. The ranges are merged one after the other from the same
+    /// [`Version`], and the readers of the SSTables read for a range are reused by the next ones.
+    ///
+    /// # Panics
+    ///
+    /// [`Scan::take`] and [`Scan::package`] panic if two ranges of the scan overlap.
+    pub fn ranges(
+        mut self,
+        ranges: impl IntoIterator<
+            Item = (
+                Bound<&'range <R::Schema as Schema>::Key>,
+                Bound<&'range <R::Schema as Schema>::Key>,
+            ),
+        >,
+    ) -> Self {
+        self.ranges.extend(ranges);
+        self
+    }
+
+    /// Limit for the scan (number of rows returned)
+    pub fn limit(self, limit: usize) -> Self {
+        Self {
+            limit: Some(limit),
+            ..self
+        }
+    }
+
+    /// Skip the first `offset` rows of the scan, e.g. for OFFSET/LIMIT pagination
+    ///
+    /// Removed keys count as rows, as they do for [`Scan::limit`]. The skipped rows are still
+    /// read and merged, as an SSTable cannot tell how many of its rows are newer versions of its
+    /// keys or hidden from the snapshot, but no more of every table is read than the offset and
+    /// limit need.
+    pub fn offset(self, offset: usize) -> Self {
+        Self { offset, ..self }
+    }
+
+    /// Configures the scan to return results in descending order (reverse order).
+    ///
+    /// By default, scans return results in ascending order. Use this method to scan
+    /// from highest to lowest key values.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// // Scan users in reverse order (newest first)
+    /// let upper = "Blob".into();
+    /// let mut scan = txn
+    ///     .scan((Bound::Included(&name), Bound::Excluded(&upper)))
+    ///     .reverse() // scan in descending order
+    ///     .limit(10) // get last 10 records
+    ///     .take()
+    ///     .await
+    ///     .unwrap();
+    ///
+    /// while let Some(entry) = scan.next().await.transpose().unwrap() {
+    ///     println!("User in reverse order: {:?}", entry.value());
+    /// }
+    /// ```
+    pub fn reverse(self) -> Self {
+        self.order(Order::Desc)
+    }
+
+    /// Return the records in `order` of their keys, ascending by default, see [`Scan::reverse`]
+    ///
+    /// The memtables and SSTables are read in `order` and merged as they are read, so a
+    /// descending scan buffers no more than an ascending one.
+    pub fn order(self, order: Order) -> Self {
+        Self {
+            order: Some(order),
+            ..self
+        }
+    }
+
+    /// Only return the keys after the last one of the page that `token` ends, in the order of
+    /// the scan, see [`Scan::take_resumable`]
+    ///
+    /// The scan must have the ranges and order of the one the token comes from.
+    pub fn resume(mut self, token: &'range ResumeToken<<R::Schema as Schema>::Key>) -> Self {
+        let order = self.order.unwrap_or_default();
+        (self.lower, self.upper) = token
+            .after((self.lower, self.upper), order)
+            // an empty range that is still disjoint from the ones after the key
+            .unwrap_or((Bound::Included(token.key()), Bound::Excluded(token.key())));
+        self.ranges = self
+            .ranges
+            .into_iter()
+            .filter_map(|range| token.after(range, order))
+            .collect();
+        self
+    }
+
+    /// Hints how the scan reads its range, so the SSTables are read accordingly.
+    ///
+    /// [`ReadHint::Sequential`] suits scans that read most of a large range, e.g. analytic queries,
+    /// and [`ReadHint::Random`] suits range probes that stop after a few rows. Without a hint,
+    /// SSTables are read with the defaults of the Parquet reader.
+    pub fn read_hint(self, read_hint: ReadHint) -> Self {
+        Self {
+            read_hint: Some(read_hint),
+            ..self
+        }
+    }
+
+    /// Also return the removed keys whose value is still kept, with the value their removal
+    /// replaced, see [`DbOption::soft_delete`]
+    ///
+    /// A key that was removed and written again only returns its latest value.
+    pub fn include_removed(self) -> Self {
+        Self {
+            include_removed: true,
+            ..self
+        }
+    }
+
+    /// Skip the SSTables whose prefix bloom filter rules out `prefix`, which every key in the
+    /// range of the scan must start with, see [`DbOption::prefix_bloom_filter`]
+    pub(crate) fn prefix(self, prefix: &[u8]) -> Self {
+        Self {
+            prefix: Some(prefix.into()),
+            ..self
+        }
+    }
+
+    /// Take the SSTable readers from `readers`, e.g. to keep them open for later scans
+    pub(crate) fn readers(self, readers: ReaderPool) -> Self {
+        Self {
+            readers: Some(readers),
+            ..self
+        }
+    }
+
+    /// fields in projection Record by field indices
+    pub fn projection(self, projection: &[&str]) -> Self {
+        let schema = self.mem_storage.record_schema.arrow_schema();
+        let mut projection = projection
+            .iter()
+            .map(|name| {
+                schema.index_of(name).unwrap_or_else(|_| {
+                    panic!("Field in projection does not exist in schema: {name}")
+                })
+            })
+            .collect::<Vec<usize>>();
+
+        let pk_indices = self.mem_storage.record_schema.primary_key_indices();
+
+        // The scan uses a fixed projection of 0: `_null` field, 1: `_ts` field, and all primary key
+        // columns
+        let mut fixed_projection = vec![0, 1];
+        fixed_projection.extend_from_slice(pk_indices);
+        fixed_projection.append(&mut projection);
+        fixed_projection.dedup();
+
+        let mask = ProjectionMask::roots(
+            self.mem_storage.record_schema.parquet_schema(),
+            fixed_projection.clone(),
+        );
+
+        Self {
+            projection: mask,
+            projection_indices: Some(fixed_projection),
+            ..self
+        }
+    }
+
+    /// Fields in projection Record by field indices
+    pub fn projection_with_index(self, mut projection: Vec<usize>) -> Self {
+        // skip two columns: _null and _ts
+        for p in &mut projection {
+            *p += USER_COLUMN_OFFSET;
+        }
+
+        let pk_indices = self.mem_storage.record_schema.primary_key_indices();
+
+        // The scan uses a fixed projection of 0: `_null` field, 1: `_ts` field, and all primary key
+        // columns
+        let mut fixed_projection = vec![0, 1];
+        fixed_projection.extend_from_slice(pk_indices);
+        fixed_projection.append(&mut projection);
+        fixed_projection.dedup();
+
+        let mask = ProjectionMask::roots(
+            self.mem_storage.record_schema.parquet_schema(),
+            fixed_projection.clone(),
+        );
+
+        Self {
+            projection: mask,
+            projection_indices: Some(fixed_projection),
+            ..self
+        }
+    }
+
+    /// Plan of the scan: which memtables and SSTables it would read and which it skips, with its
+    /// projection and row filters
+    ///
+    /// The plan is worked out from the key ranges of the memtables and the SSTables of the
+    /// scanned [`Version`], so no data is read. Memtables and SSTables are read if they overlap
+    /// any range of [`Scan::ranges`], while the range and row filters of the plan are the ones of
+    /// the range the scan was created with.
+    pub fn explain(&self) -> ScanPlan<<R::Schema as Schema>::Key> {
+        let ranges = iter::once((self.lower, self.upper))
+            .chain(self.ranges.iter().copied())
+            .collect::<Vec<_>>();
+        let arrow_schema = self.ctx.arrow_schema();
+        let projection = self
+            .projection_indices
+            .clone()
+            .unwrap_or_else(|| (0..arrow_schema.fields().len()).collect())
+            .into_iter()
+            .skip(USER_COLUMN_OFFSET)
+            .map(|index| arrow_schema.field(index).name().clone())
+            .collect();
+
+        let pk_names = self
+            .mem_storage
+            .record_schema
+            .primary_key_indices()
+            .iter()
+            .map(|index| arrow_schema.field(*index).name().as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut row_filters = vec![format!("_ts <= {}", u32::from(self.ts))];
+        match self.lower {
+            Bound::Included(key) => row_filters.push(format!("({pk_names}) >= {key:?}")),
+            Bound::Excluded(key) => row_filters.push(format!("({pk_names}) > {key:?}")),
+            Bound::Unbounded => (),
+        }
+        match self.upper {
+            Bound::Included(key) => row_filters.push(format!("({pk_names}) <= {key:?}")),
+            Bound::Excluded(key) => row_filters.push(format!("({pk_names}) < {key:?}")),
+            Bound::Unbounded => (),
+        }
+
+        let immutables = self
+            .mem_storage
+            .immutables
+            .iter()
+            .rev()
+            .map(|(wal_id, immutable)| ImmutablePlan {
+                wal_id: *wal_id,
+                len: immutable.len(),
+                overlaps: ranges.iter().any(|range| immutable.meets_range(*range)),
+            })
+            .collect();
+        let levels = self
+            .version
+            .level_slice
+            .iter()
+            .enumerate()
+            .filter(|(_, scopes)| !scopes.is_empty())
+            .map(|(level, scopes)| LevelPlan {
+                level,
+                tables: scopes
+                    .iter()
+                    .map(|scope| TablePlan {
+                        gen: scope.gen,
+                        min: scope.min.clone(),
+                        max: scope.max.clone(),
+                        file_size: scope.file_size,
+                        pruned: !ranges.iter().any(|range| scope.meets_range(*range)),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        ScanPlan {
+            range: (self.lower.cloned(), self.upper.cloned()),
+            ts: self.ts,
+            order: self.order.unwrap_or_default(),
+            limit: self.limit,
+            offset: self.offset,
+            read_hint: self.read_hint,
+            projection,
+            row_filters,
+            filter: self.filter.as_ref().map(|(_, filter)| filter.clone()),
+            immutables,
+            levels,
+        }
+    }
+
+    /// Number of records the scan returns, removed keys aside, without materializing them
+    ///
+    /// Only the primary key columns of the SSTables are read, and the merged versions of every
+    /// key are counted as [`Scan::take`] returns them, so the count is exact at the timestamp of
+    /// the scan. An SSTable within the range whose first and last key no other table, memtable
+    /// or write of the transaction shares keys between is counted from the statistics of its
+    /// footer instead, if they show no removal and no version newer than the scan. With
+    /// [`Scan::limit`], [`Scan::offset`], [`Scan::ranges`], a filter or
+    /// [`Scan::include_removed`], or with records that expire, every table is read.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let adults = txn
+    ///     .scan((Bound::Included(&18), Bound::Unbounded))
+    ///     .count()
+    ///     .await?;
+    /// ```
+    pub async fn count(mut self) -> Result<u64, DbError> {
+        let mut count = 0;
+        if self.ranges.is_empty()
+            && self.limit.is_none()
+            && self.offset == 0
+            && self.filter.is_none()
+            && !self.include_removed
+            && self.mem_storage.expired().is_none()
+            && !self.has_writes_in_range().await
+        {
+            for (gen, rows) in self.tables_counted_from_footers().await? {
+                self.skip_tables.push(gen);
+                count += rows;
+            }
+        }
+        if self.filter.is_none() {
+            self = self.projection(&[]);
+        }
+
+        let mut records = self.take().await?;
+        while let Some(entry) = records.next().await {
+            if entry?.value().is_some() {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    // Whether the writes of the transaction of the scan hold a key in its range
+    async fn has_writes_in_range(&self) -> bool {
+        match (self.fn_pre_stream)((self.lower, self.upper), None, self.order) {
+            Some(stream) => pin!(stream).next().await.is_some(),
+            None => false,
+        }
+    }
+
+    // SSTables within the range whose keys no other table or memtable shares, and whose footer
+    // shows only records visible to the scan, with their number of rows. Compactions and
+    // flushes write one version of each key, besides the value a kept removal replaced, so each
+    // row of such a table is a record of its own key.
+    async fn tables_counted_from_footers(&self) -> Result<Vec<(FileId, u64)>, DbError> {
+        let range = (self.lower, self.upper);
+        let readers = self.readers.as_ref().unwrap_or(self.ctx.manager.readers());
+        let tables = || {
+            self.version
+                .level_slice
+                .iter()
+                .enumerate()
+                .flat_map(|(level, scopes)| scopes.iter().map(move |scope| (level, scope)))
+        };
+
+        let mut counted = Vec::new();
+        for (level, scope) in tables() {
+            if !range.contains(&scope.min)
+                || !range.contains(&scope.max)
+                || self
+                    .version
+                    .range_tombstones
+                    .iter()
+                    .any(|tombstone| tombstone.tables.contains(&scope.gen))
+            {
+                continue;
+            }
+            let keys = (Bound::Included(&scope.min), Bound::Included(&scope.max));
+            if self.mem_storage.mutable.edge_key(keys, None).is_some()
+                || self
+                    .mem_storage
+                    .immutables
+                    .iter()
+                    .any(|(_, immutable)| immutable.meets_range(keys))
+                || tables().any(|(_, other)| other.gen != scope.gen && other.meets_range(keys))
+            {
+                continue;
+            }
+
+            let (fs, path) = self
+                .ctx
+                .manager
+                .table(self.version.option(), scope.gen, level);
+            let reader = readers.open(fs, &path, scope.gen).await?;
+            let rows = SsTable::<R>::from_reader(self.ctx.parquet_lru.clone(), scope.gen, reader)
+                .await
+                .visible_rows(self.ts)
+                .await?;
+            if let Some(rows) = rows {
+                counted.push((scope.gen, rows));
+            }
+        }
+        Ok(counted)
+    }
+
+    /// Get a Stream that returns single row of Record
+    ///
+    /// The stream reads the [`Version`] of the snapshot it was created from, which keeps every
+    /// SSTable of that version from being deleted until the stream and the snapshot are dropped,
+    /// however many compactions replace those SSTables meanwhile.
+    pub async fn take(
+        self,
+    ) -> Result<impl Stream<Item = Result<Entry<'scan, R>, ParquetError>>, DbError> {
+        if self.ranges.is_empty() {
+            return Ok(Either::Left(self.merge_stream().await?));
+        }
+        Ok(Either::Right(self.ranges_stream()))
+    }
+
+    /// Get a Stream like [`Scan::take`] whose [`Resumable::resume_token`] resumes the scan after
+    /// the last entry it returned, e.g. to page through a table without [`Scan::offset`], see
+    /// [`crate::resume`]
+    pub async fn take_resumable(
+        self,
+    ) -> Result<
+        Resumable<'scan, R, impl Stream<Item = Result<Entry<'scan, R>, ParquetError>>>,
+        DbError,
+    > {
+        let ts = self.ts;
+        Ok(Resumable::new(self.take().await?, ts))
+    }
+
+    pub(crate) async fn merge_stream(self) -> Result<MergeStream<'scan, R>, DbError> {
+        let streams = self
+            .range_streams(
+                (self.lower, self.upper),
+                self.readers.as_ref().unwrap_or(self.ctx.manager.readers()),
+            )
+            .await?;
+
+        let merge_operator = self.mem_storage.merge_operator()?;
+        let merged_projection = self.merged_projection();
+        let mut merge_stream = MergeStream::from_vec(streams, self.ts, self.order)
+            .await?
+            .offset(self.offset)
+            .filter(self.filter.map(|(filter, _)| filter))
+            .expired(self.mem_storage.expired())
+            .range_tombstones(self.version.range_tombstones.clone())
+            .include_removed(self.include_removed)
+            .merge_operator(merge_operator, merged_projection)
+            .project(self.returned_projection());
+        if let Some(limit) = self.limit {
+            merge_stream = merge_stream.limit(limit);
+        }
+        Ok(merge_stream)
+    }
+
+    // Streams of the writes of the transaction, the memtables and the SSTables in `range`
+    async fn range_streams(
+        &self,
+        range: (
+            Bound<&'range <R::Schema as Schema>::Key>,
+            Bound<&'range <R::Schema as Schema>::Key>,
+        ),
+        readers: &ReaderPool,
+    ) -> Result<Vec<ScanStream<'scan, R>>, DbError> {
+        let mut streams = Vec::new();
+        let read_projection = self.read_projection();
+        let is_projection = read_projection.is_some();
+        let projection = read_projection.clone().unwrap_or_else(ProjectionMask::all);
+
+        if let Some(pre_stream) = (self.fn_pre_stream)(range, read_projection, self.order) {
+            streams.push(pre_stream);
+        }
+
+        // In-memory memtable scan
+        {
+            let mut mutable_scan = self
+                .mem_storage
+                .mutable
+                .scan(range, self.ts, self.order)
+                .into();
+            if is_projection {
+                mutable_scan = MemProjectionStream::new(
+                    mutable_scan,
+                    projection.clone(),
+                    self.mem_storage.record_schema.arrow_schema().clone(),
+                )
+                .into();
+            }
+            streams.push(mutable_scan);
+        }
+        // Merge operands, folded into the records of their keys by the merge stream
+        if self.mem_storage.mutable.has_operands() {
+            streams.push(
+                self.mem_storage
+                    .mutable
+                    .scan_operands(range, self.ts, self.order)
+                    .into(),
+            );
+        }
+
+        // Iterates through immutable memtables
+        for (_, immutable) in self.mem_storage.immutables.iter().rev() {
+            streams.push(
+                immutable
+                    .scan(range, self.ts, projection.clone(), self.order)
+                    .into(),
+            );
+        }
+
+        // Scans all SSTables in the coreresponding version
+        self.version
+            .streams(
+                &self.ctx,
+                &mut streams,
+                range,
+                self.ts,
+                self.table_limit(),
+                projection,
+                self.order,
+                self.mem_storage.record_schema.primary_key_indices(),
+                self.read_hint,
+                self.prefix.clone(),
+                &self.skip_tables,
+                readers,
+            )
+            .await?;
+
+        Ok(streams)
+    }
+
+    // Projection the streams read with, `None` for every column. Scans that hide expired records
+    // read every column, as any of them may tell when a record expires, and leave the projected
+    // ones after merging, see `Scan::returned_projection`
+    fn read_projection(&self) -> Option<ProjectionMask> {
+        (self.projection_indices.is_some() && !self.mem_storage.hides_expired())
+            .then(|| self.projection.clone())
+    }
+
+    // Projection of the records the merge stream folds from merge operands, like the one of the
+    // memtable entries
+    fn merged_projection(&self) -> Option<(ProjectionMask, Arc<ArrowSchema>)> {
+        self.read_projection()
+            .map(|mask| (mask, self.mem_storage.record_schema.arrow_schema().clone()))
+    }
+
+    // Projection of the records the merge stream returns if the streams read every column, see
+    // `Scan::read_projection`
+    fn returned_projection(&self) -> Option<(ProjectionMask, Arc<ArrowSchema>)> {
+        (self.projection_indices.is_some() && self.mem_storage.hides_expired()).then(|| {
+            (
+                self.projection.clone(),
+                self.mem_storage.record_schema.arrow_schema().clone(),
+            )
+        })
+    }
+
+    // Every range of the scan in the order it is read, which panics if two of them overlap
+    fn sorted_ranges(
+        &self,
+    ) -> Vec<(
+        Bound<&'range <R::Schema as Schema>::Key>,
+        Bound<&'range <R::Schema as Schema>::Key>,
+    )> {
+        let mut ranges = self.ranges.clone();
+        ranges.push((self.lower, self.upper));
+        ranges.sort_by(|(a, _), (b, _)| cmp_lower(*a, *b));
+        for pair in ranges.windows(2) {
+            let ((_, upper), (lower, _)) = (pair[0], pair[1]);
+            let disjoint = match (upper, lower) {
+                (Bound::Included(upper), Bound::Included(lower)) => upper < lower,
+                (Bound::Included(upper) | Bound::Excluded(upper), Bound::Excluded(lower))
+                | (Bound::Excluded(upper), Bound::Included(lower)) => upper <= lower,
+                _ => false,
+            };
+            assert!(
+                disjoint,
+                "ranges of a scan overlap: {:?} and {:?}",
+                pair[0], pair[1]
+            );
+        }
+        if self.order == Some(Order::Desc) {
+            ranges.reverse();
+        }
+        ranges
+    }
+
+    // Merges the ranges of the scan one after the other, with the offset and limit counted
+    // across all of them
+    fn ranges_stream(self) -> impl Stream<Item = Result<Entry<'scan, R>, ParquetError>> + 'scan {
+        let ranges = self.sorted_ranges();
+        // idle readers of a range are taken by the next ones
+        let readers = self.readers.clone().unwrap_or_else(|| {
+            ReaderPool::new(self.version.level_slice.iter().map(Vec::len).sum())
+        });
+        let filter = self.filter.as_ref().map(|(filter, _)| filter.clone());
+
+        try_stream! {
+            let (mut skipped, mut taken) = (0, 0);
+            for range in ranges {
+                if self.limit.is_some_and(|limit| taken >= limit) {
+                    break;
+                }
+                let streams = self
+                    .range_streams(range, &readers)
+                    .await
+                    .map_err(|err| ParquetError::External(Box::new(err)))?;
+                let merge_operator = self
+                    .mem_storage
+                    .merge_operator()
+                    .map_err(|err| ParquetError::External(Box::new(err)))?;
+                let mut merge_stream = pin!(MergeStream::from_vec(streams, self.ts, self.order)
+                    .await?
+                    .filter(filter.clone())
+                    .expired(self.mem_storage.expired())
+                    .range_tombstones(self.version.range_tombstones.clone())
+                    .include_removed(self.include_removed)
+                    .merge_operator(merge_operator, self.merged_projection())
+                    .project(self.returned_projection()));
+
+                while let Some(entry) = merge_stream.next().await {
+                    let entry = entry?;
+                    if skipped < self.offset {
+                        skipped += 1;
+                        continue;
+                    }
+                    yield entry;
+                    taken += 1;
+                    if self.limit.is_some_and(|limit| taken >= limit) {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    // Rows read from each SSTable at most, unknown if records are filtered, expired or range
+    // removed after merging or removals are followed by the values they replaced
+    fn table_limit(&self) -> Option<usize> {
+        match self.filter {
+            Some(_) => None,
+            None if self.mem_storage.option.soft_delete.is_some() => None,
+            None if self.mem_storage.option.drop_expired => None,
+            None if !self.version.range_tombstones.is_empty() => None,
+            None => self.limit.map(|limit| limit.saturating_add(self.offset)),
+        }
+    }
+
+    /// Get a Stream that returns RecordBatch consisting of a `batch_size` number of records
+    pub async fn package(
+        self,
+        batch_size: usize,
+    ) -> Result<
+        impl Stream<Item = Result<<R::Schema as Schema>::Columns, ParquetError>> + 'scan,
+        DbError,
+    > {
+        let projection_indices = self.projection_indices.clone();
+        let schema = self.ctx.arrow_schema().clone();
+        let entries = if self.ranges.is_empty() {
+            let streams = self
+                .range_streams(
+                    (self.lower, self.upper),
+                    self.readers.as_ref().unwrap_or(self.ctx.manager.readers()),
+                )
+                .await?;
+            let merge_operator = self.mem_storage.merge_operator()?;
+            let merged_projection = self.merged_projection();
+            Either::Left(
+                MergeStream::from_vec(streams, self.ts, self.order)
+                    .await?
+                    .offset(self.offset)
+                    .filter(self.filter.map(|(filter, _)| filter))
+                    .expired(self.mem_storage.expired())
+                    .range_tombstones(self.version.range_tombstones.clone())
+                    .include_removed(self.include_removed)
+                    .merge_operator(merge_operator, merged_projection)
+                    .project(self.returned_projection()),
+            )
+        } else {
+            Either::Right(self.ranges_stream())
+        };
+
+        Ok(PackageStream::new(
+            batch_size,
+            entries,
+            projection_indices,
+            schema,
+        ))
+    }
+
+    /// Schema of the user columns in the packaged record batches and the indices of these columns
+    /// in the batches, which also hold the internal `_null` and `_ts` columns
+    fn user_projection(&self) -> Result<(ArrowSchema, Vec<usize>), ArrowError> {
+        let arrow_schema = self.ctx.arrow_schema();
+        let indices = self
+            .projection_indices
+            .clone()
+            .unwrap_or_else(|| (0..arrow_schema.fields().len()).collect());
+        let schema = arrow_schema.project(&indices[USER_COLUMN_OFFSET..])?;
+
+        Ok((schema, (USER_COLUMN_OFFSET..indices.len()).collect()))
+    }
+
+    /// Get a Stream of the packaged record batches with the projected user columns only, without
+    /// the internal `_null` and `_ts` columns
+    pub async fn into_record_batches(
+        self,
+        batch_size: usize,
+    ) -> Result<impl Stream<Item = Result<RecordBatch, DbError>> + 'scan, DbError> {
+        let (_, user_indices) = self.user_projection()?;
+        let batches = self.package(batch_size).await?;
+
+        Ok(batches.map(move |columns| Ok(columns?.as_record_batch().project(&user_indices)?)))
+    }
+
+    /// Get a Stream of the packaged record batches encoded in the Arrow IPC streaming format
+    ///
+    /// The first item holds the schema message and the last one the end-of-stream marker, so the
+    /// concatenated items form a complete IPC stream that any Arrow implementation can read. The
+    /// internal `_null` and `_ts` columns are not exported.
+    pub async fn into_ipc_stream(
+        self,
+        batch_size: usize,
+    ) -> Result<impl Stream<Item = Result<Vec<u8>, DbError>> + 'scan, DbError> {
+        let (schema, _) = self.user_projection()?;
+        let mut writer = StreamWriter::try_new(Vec::new(), &schema)?;
+        let batches = self.into_record_batches(batch_size).await?;
+
+        Ok(stream! {
+            let mut batches = pin!(batches);
+            yield Ok(mem::take(writer.get_mut()));
+
+            while let Some(batch) = batches.next().await {
+                writer.write(&batch?)?;
+                yield Ok(mem::take(writer.get_mut()));
+            }
+            writer.finish()?;
+            yield Ok(mem::take(writer.get_mut()));
+        })
+    }
+
+    /// Write the packaged record batches into `writer` as an Arrow IPC stream, see
+    /// [`Scan::into_ipc_stream`]
+    pub async fn write_ipc<W: Write>(
+        self,
+        batch_size: usize,
+        writer: &mut W,
+    ) -> Result<(), DbError> {
+        let mut stream = pin!(self.into_ipc_stream(batch_size).await?);
+
+        while let Some(buf) = stream.next().await {
+            let (result, _) = writer.write_all(buf?).await;
+            result?;
+        }
+        writer.flush().await?;
+
+        Ok(())
+    }
+}
+
+// Orders lower bounds of key ranges by the first key they admit
+fn cmp_lower<K: Ord>(a: Bound<&K>, b: Bound<&K>) -> std::cmp::Ordering {
+    match (a, b) {
+        (Bound::Unbounded, Bound::Unbounded) => std::cmp::Ordering::Equal,
+        (Bound::Unbounded, _) => std::cmp::Ordering::Less,
+        (_, Bound::Unbounded) => std::cmp::Ordering::Greater,
+        (Bound::Included(a), Bound::Excluded(b)) if a == b => std::cmp::Ordering::Less,
+        (Bound::Excluded(a), Bound::Included(b)) if a == b => std::cmp::Ordering::Greater,
+        (Bound::Included(a) | Bound::Excluded(a), Bound::Included(b) | Bound::Excluded(b)) => {
+            a.cmp(b)
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DbError {
+    #[error("write io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("write version error: {0}")]
+    Version(#[from] VersionError),
+    #[error("write manifest storage error: {0}")]
+    Manifest(#[from] ManifestStorageError),
+    #[error("write parquet error: {0}")]
+    Parquet(#[from] ParquetError),
+    #[error("write ulid decode error: {0}")]
+    UlidDecode(#[from] ulid::DecodeError),
+    #[error("write fusio error: {0}")]
+    Fusio(#[from] fusio::Error),
+    // #[error("write encode error: {0}")]
+    // Encode(<<R as Record>::Ref as Encode>::Error),
+    #[error("write recover error: {0}")]
+    Recover(#[from] RecoverError<fusio::Error>),
+    #[error("wal write error: {0}")]
+    WalWrite(Box<dyn std::error::Error + Send + Sync + 'static>),
+    #[error("exceeds the maximum level(0-6)")]
+    ExceedsMaxLevel,
+    #[error("write log error: {0}")]
+    Logger(#[from] fusio_log::error::LogError),
+    #[error("backup error: {0}")]
+    Backup(#[from] BackupError),
+    #[cfg(feature = "dump")]
+    #[error("manifest dump error: {0}")]
+    Dump(#[from] dump::DumpError),
+    #[error("ingest error: {0}")]
+    Ingest(#[from] IngestError),
+    #[error("arrow error: {0}")]
+    Arrow(#[from] ArrowError),
+    #[error("background task was canceled")]
+    Canceled,
+    #[error("table boundary compares keys of type {0}, not the keys of the schema")]
+    TableBoundaryKey(&'static str),
+    #[error("expiry reads records of type {0}, not the records of the schema")]
+    ExpiryRecord(&'static str),
+    #[error("compaction filter reads records of type {0}, not the records of the schema")]
+    CompactionFilterRecord(&'static str),
+    #[error("aggregate {0} reads records of type {1}, not the records of the schema")]
+    AggregateRecord(String, &'static str),
+    #[error("merge operator reads records of type {0}, not the records of the schema")]
+    MergeOperatorRecord(&'static str),
+    #[error("no merge operator is set")]
+    NoMergeOperator,
+    #[error("merge operator changed the key of a record")]
+    MergeOperatorKey,
+    #[error("idempotency window is 0, so no token is remembered")]
+    NoIdempotencyWindow,
+    #[error("schema mismatch: {0}")]
+    SchemaMismatch(#[from] SchemaMismatch),
+    #[error("record batch error: {0}")]
+    Batch(#[from] BatchError),
+}
+
+impl DbError {
+    /// Category of the error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            DbError::Io(err) => io_kind(err),
+            DbError::Version(err) => err.kind(),
+            DbError::Manifest(err) => err.kind(),
+            DbError::Parquet(err) => parquet_kind(err),
+            DbError::UlidDecode(_) => ErrorKind::Corruption,
+            DbError::Fusio(err) => fusio_kind(err),
+            DbError::Recover(err) => err.kind(),
+            DbError::WalWrite(err) => source_kind(err.as_ref()),
+            DbError::ExceedsMaxLevel => ErrorKind::InvalidArgument,
+            DbError::Logger(err) => source_kind(err),
+            DbError::Backup(err) => err.kind(),
+            #[cfg(feature = "dump")]
+            DbError::Dump(err) => err.kind(),
+            DbError::Ingest(err) => err.kind(),
+            DbError::Arrow(err) => arrow_kind(err),
+            DbError::Canceled => ErrorKind::Io,
+            DbError::TableBoundaryKey(_)
+            | DbError::ExpiryRecord(_)
+            | DbError::CompactionFilterRecord(_)
+            | DbError::AggregateRecord(..)
+            | DbError::MergeOperatorRecord(_)
+            | DbError::NoMergeOperator
+            | DbError::MergeOperatorKey
+            | DbError::NoIdempotencyWindow => ErrorKind::InvalidArgument,
+            DbError::SchemaMismatch(SchemaMismatch::Arrow(err)) => arrow_kind(err),
+            DbError::SchemaMismatch(_) => ErrorKind::InvalidArgument,
+            DbError::Batch(err) => err.kind(),
+        }
+    }
+
+    /// Whether the failed operation may succeed if it is tried again, see
+    /// [`ErrorKind::is_retryable`]
+    pub fn is_retryable(&self) -> bool {
+        self.kind().is_retryable()
+    }
+}
+
+type LockMap<K> = Arc<LockableHashMap<K, ()>>;
+
+pub enum Projection<'r> {
+    All,
+    Parts(Vec<&'r str>),
+}
+
+/// Whether a key may have a record, see [`DB::may_exist`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Existence {
+    /// The key has no record
+    No,
+    /// The key may have a record, which only a get tells for sure
+    Maybe,
+}
+
+pub type ParquetLru = Arc<dyn DynLruCache<FileId> + Send + Sync>;
+
+#[cfg(all(test, feature = "tokio"))]
 pub(crate) mod tests {
     use std::{
         collections::{BTreeMap, Bound},
+        io::Cursor,
         sync::Arc,
+        time::Duration,
+    };
+
+    #[cfg(feature = "dyn-record")]
+    use arrow::datatypes::DataType as ArrowDataType;
+    use arrow::{array::RecordBatch, ipc::reader::StreamReader, record_batch::RecordBatchReader};
+    use flume::{bounded, Receiver};
+    use fusio::{disk::TokioFs, path::Path, DynFs};
+    use fusio_dispatch::FsOptions;
+    use futures::StreamExt;
+    use parquet_lru::NoCache;
+    use tempfile::TempDir;
+
+    pub use crate::record::test::{Test, TestRef};
+    #[cfg(feature = "dyn-record")]
+    use crate::record::{
+        dynamic::test::{test_dyn_item_schema, test_dyn_items},
+        DynRecord, DynSchema, DynamicField, KeyRef, Value, ValueRef,
+    };
+    use crate::{
+        clock::ManualClock,
+        compaction::{
+            leveled::{LeveledCompactor, LeveledOptions},
+            tiered::TieredCompactor,
+            CompactTask,
+        },
+        context::Context,
+        executor::{tokio::TokioExecutor, Executor},
+        fs::{generate_file_id, manager::StoreManager},
+        inmem::{immutable::tests::TestSchema, mutable::MutableMemTable},
+        manifest::ManifestStorageError,
+        merge::MergeOperator,
+        record::Schema as RecordSchema,
+        stream::memory::MemoryBudget,
+        transaction::CommitError,
+        trigger::{TriggerFactory, TriggerType},
+        version::{
+            cleaner::Cleaner, hot_range::HotRanges, negative_cache::NegativeCache,
+            set::tests::build_version_set, Version,
+        },
+        wal::log::LogType,
+        CompactionOption, DbError, DbOption, Existence, Order, Projection, Record, DB,
     };
 
-    use flume::{bounded, Receiver};
-    use fusio::{disk::TokioFs, path::Path, DynFs};
-    use fusio_dispatch::FsOptions;
-    use futures::StreamExt;
-    use parquet_lru::NoCache;
-    use tempfile::TempDir;
+    pub(crate) async fn build_schema(
+        option: Arc<DbOption>,
+        fs: &Arc<dyn DynFs>,
+    ) -> Result<(crate::DbStorage<Test>, Receiver<CompactTask>), fusio::Error> {
+        let trigger = TriggerFactory::create(option.trigger_type);
+
+        let mutable = MutableMemTable::new(
+            &option,
+            trigger.clone(),
+            fs.clone(),
+            Arc::new(TestSchema {}),
+        )
+        .await?;
+
+        mutable
+            .insert(
+                LogType::Full,
+                Test {
+                    vstring: "alice".to_string(),
+                    vu32: 1,
+                    vbool: Some(true),
+                },
+                1_u32.into(),
+            )
+            .await
+            .unwrap();
+        mutable
+            .insert(
+                LogType::Full,
+                Test {
+                    vstring: "ben".to_string(),
+                    vu32: 2,
+                    vbool: Some(true),
+                },
+                1_u32.into(),
+            )
+            .await
+            .unwrap();
+        mutable
+            .insert(
+                LogType::Full,
+                Test {
+                    vstring: "carl".to_string(),
+                    vu32: 3,
+                    vbool: Some(true),
+                },
+                1_u32.into(),
+            )
+            .await
+            .unwrap();
+
+        let immutables = {
+            let trigger = TriggerFactory::create(option.trigger_type);
+
+            let mutable: MutableMemTable<Test> =
+                MutableMemTable::new(&option, trigger.clone(), fs.clone(), Arc::new(TestSchema))
+                    .await?;
+
+            mutable
+                .insert(
+                    LogType::Full,
+                    Test {
+                        vstring: "dice".to_string(),
+                        vu32: 4,
+                        vbool: Some(true),
+                    },
+                    1_u32.into(),
+                )
+                .await
+                .unwrap();
+            mutable
+                .insert(
+                    LogType::Full,
+                    Test {
+                        vstring: "erika".to_string(),
+                        vu32: 5,
+                        vbool: Some(true),
+                    },
+                    1_u32.into(),
+                )
+                .await
+                .unwrap();
+            mutable
+                .insert(
+                    LogType::Full,
+                    Test {
+                        vstring: "funk".to_string(),
+                        vu32: 6,
+                        vbool: Some(true),
+                    },
+                    1_u32.into(),
+                )
+                .await
+                .unwrap();
+
+            vec![(
+                Some(generate_file_id()),
+                mutable.into_immutable().await.unwrap().1,
+            )]
+        };
+
+        let (compaction_tx, compaction_rx) = bounded(1);
+
+        Ok((
+            crate::DbStorage {
+                mutable,
+                immutables,
+                compaction_tx,
+                recover_wal_ids: None,
+                trigger,
+                record_schema: Arc::new(TestSchema {}),
+                option,
+                compaction_in_progress: std::sync::atomic::AtomicBool::new(false),
+                expiry: None,
+                aggregates: Default::default(),
+                watchers: Default::default(),
+            },
+            compaction_rx,
+        ))
+    }
+
+    use crate::record::test::test_items;
+
+    pub(crate) async fn build_db<R, E>(
+        option: Arc<DbOption>,
+        task_rx: Receiver<CompactTask>,
+        executor: E,
+        mem_storage: crate::DbStorage<R>,
+        record_schema: Arc<R::Schema>,
+        version: Version<R>,
+        manager: Arc<StoreManager>,
+    ) -> Result<DB<R, E>, DbError>
+    where
+        R: Record + Send + Sync,
+        <R::Schema as RecordSchema>::Columns: Send + Sync,
+        E: Executor + Send + Sync + 'static,
+    {
+        {
+            let base_fs = manager.base_fs();
+
+            let _ = base_fs.create_dir_all(&option.wal_dir_path()).await;
+            let _ = base_fs.create_dir_all(&option.version_log_dir_path()).await;
+        }
+
+        let mem_storage = Arc::new(E::rw_lock(mem_storage));
+
+        let (cleaner, clean_sender) = Cleaner::new(option.clone(), manager.clone());
+        let manifest = Box::new(
+            build_version_set::<R, E>(version, clean_sender, option.clone(), manager.clone())
+                .await
+                .map_err(ManifestStorageError::Version)?,
+        );
+        let ctx = Arc::new(Context::new(
+            manager,
+            Arc::new(NoCache::default()),
+            manifest,
+            TestSchema.arrow_schema().clone(),
+            NegativeCache::new(0),
+            HotRanges::new(0, 0),
+            MemoryBudget::new(None),
+        ));
+        // Create built-in compactor for tests
+        match &option.compaction_option {
+            CompactionOption::Leveled(opt) => {
+                let compactor = LeveledCompactor::<R>::new(
+                    opt.clone(),
+                    record_schema.clone(),
+                    option.clone(),
+                    ctx.clone(),
+                );
+                DB::finish_build(
+                    &executor,
+                    mem_storage,
+                    ctx,
+                    compactor,
+                    cleaner,
+                    task_rx,
+                    None,
+                )
+                .await
+            }
+            CompactionOption::Tiered(opt) => {
+                let compactor = TieredCompactor::<R>::new(
+                    opt.clone(),
+                    record_schema.clone(),
+                    option.clone(),
+                    ctx.clone(),
+                );
+                DB::finish_build(
+                    &executor,
+                    mem_storage,
+                    ctx,
+                    compactor,
+                    cleaner,
+                    task_rx,
+                    None,
+                )
+                .await
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn read_from_disk() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_dir_l0 = TempDir::new().unwrap();
+
+        let path = Path::from_filesystem_path(temp_dir.path()).unwrap();
+        let path_l0 = Path::from_filesystem_path(temp_dir_l0.path()).unwrap();
+
+        let mut option = DbOption::new(path, &TestSchema)
+            .level_path(0, path_l0, FsOptions::Local)
+            .unwrap();
+        option = option
+            .immutable_chunk_num(1)
+            .immutable_chunk_max_num(1)
+            .leveled_compaction(
+                LeveledOptions::default()
+                    .major_threshold_with_sst_size(3)
+                    .level_sst_magnification(10)
+                    .major_default_oldest_table_num(1),
+            )
+            .max_sst_file_size(2 * 1024 * 1024);
+        option.trigger_type = TriggerType::Length(/* max_mutable_len */ 5);
+
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+
+        for (i, item) in test_items(0u32..32).enumerate() {
+            db.write(item, 0.into()).await.unwrap();
+            if i % 5 == 0 {
+                db.flush().await.unwrap();
+            }
+        }
+
+        let tx = db.transaction().await;
+        let key = 20.to_string();
+        let option1 = tx.get(&key, Projection::All).await.unwrap().unwrap();
+
+        dbg!(db.ctx.manifest.current().await);
+
+        let version = db.ctx.manifest.current().await;
+        assert!(!version.level_slice[1].is_empty());
+
+        assert_eq!(option1.get().vstring, "20");
+        assert_eq!(option1.get().vu32, Some(20));
+        assert_eq!(option1.get().vbool, Some(true));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn open_without_reading_tables() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_dir_l0 = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .level_path(
+            0,
+            Path::from_filesystem_path(temp_dir_l0.path()).unwrap(),
+            FsOptions::Local,
+        )
+        .unwrap();
+        {
+            let db: DB<Test, TokioExecutor> =
+                DB::new(option.clone(), TokioExecutor::default(), TestSchema)
+                    .await
+                    .unwrap();
+            for item in test_items(0u32..10) {
+                db.insert(item).await.unwrap();
+            }
+            db.flush().await.unwrap();
+        }
+        // the tables of the level are unreachable, e.g. on a remote store that is down
+        for entry in std::fs::read_dir(temp_dir_l0.path()).unwrap() {
+            std::fs::remove_file(entry.unwrap().path()).unwrap();
+        }
+
+        // opening only replays the manifest and the WALs, the tables are opened on first access
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        assert_eq!(db.stats().await.tables_per_level[0], 1);
+        for item in test_items(10u32..20) {
+            db.insert(item).await.unwrap();
+        }
+        let vu32 = db
+            .get(&"15".to_string(), |entry| entry.get().vu32)
+            .await
+            .unwrap();
+        assert_eq!(vu32, Some(15));
+        assert!(db.get(&"5".to_string(), |_| Some(())).await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_flush() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .immutable_chunk_num(1)
+        .immutable_chunk_max_num(1)
+        .leveled_compaction(
+            LeveledOptions::default()
+                .major_threshold_with_sst_size(3)
+                .level_sst_magnification(10)
+                .major_default_oldest_table_num(1),
+        )
+        .max_sst_file_size(2 * 1024 * 1024);
+        option.trigger_type = TriggerType::Length(/* max_mutable_len */ 50);
+
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+
+        for item in test_items(0u32..10) {
+            db.write(item.clone(), 0.into()).await.unwrap();
+        }
+        db.flush().await.unwrap();
+        for item in test_items(10u32..20) {
+            db.write(item.clone(), 0.into()).await.unwrap();
+        }
+
+        dbg!(db.ctx.manifest.current().await);
+        db.flush().await.unwrap();
+        dbg!(db.ctx.manifest.current().await);
+
+        let version = db.ctx.manifest.current().await;
+        assert!(!version.level_slice[0].is_empty());
+    }
+
+    #[ignore = "s3"]
+    #[cfg(all(feature = "aws", feature = "tokio-http"))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_s3_recover() {
+        let temp_dir = TempDir::new().unwrap();
 
-    pub use crate::record::test::{Test, TestRef};
-    use crate::{
-        compaction::{
-            leveled::{LeveledCompactor, LeveledOptions},
-            tiered::TieredCompactor,
-            CompactTask,
-        },
-        context::Context,
-        executor::{tokio::TokioExecutor, Executor},
-        fs::{generate_file_id, manager::StoreManager},
-        inmem::{immutable::tests::TestSchema, mutable::MutableMemTable},
-        manifest::ManifestStorageError,
-        record::{
-            dynamic::test::{test_dyn_item_schema, test_dyn_items},
-            DynRecord, KeyRef, Schema as RecordSchema, Value, ValueRef,
-        },
-        trigger::{TriggerFactory, TriggerType},
-        version::{cleaner::Cleaner, set::tests::build_version_set, Version},
-        wal::log::LogType,
-        CompactionOption, DbError, DbOption, Projection, Record, DB,
-    };
+        if option_env!("AWS_ACCESS_KEY_ID").is_none()
+            || option_env!("AWS_SECRET_ACCESS_KEY").is_none()
+        {
+            eprintln!("can not get `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`");
+            return;
+        }
+        let key_id = std::option_env!("AWS_ACCESS_KEY_ID").unwrap().to_string();
+        let secret_key = std::option_env!("AWS_SECRET_ACCESS_KEY")
+            .unwrap()
+            .to_string();
+        let bucket = std::env::var("BUCKET_NAME").expect("expected s3 bucket not to be empty");
+        let region = Some(std::env::var("AWS_REGION").expect("expected s3 region not to be empty"));
+        let token = Some(std::option_env!("AWS_SESSION_TOKEN").unwrap().to_string());
+
+        let s3_option = FsOptions::S3 {
+            bucket,
+            credential: Some(fusio::remotes::aws::AwsCredential {
+                key_id,
+                secret_key,
+                token,
+            }),
+            endpoint: None,
+            region,
+            sign_payload: None,
+            checksum: None,
+        };
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema {},
+        )
+        .base_fs(s3_option.clone());
+        {
+            let db = DB::new(option.clone(), TokioExecutor::default(), TestSchema)
+                .await
+                .unwrap();
+
+            for item in test_items(0u32..10) {
+                db.insert(item.clone()).await.unwrap();
+            }
+            // flush to s3
+            db.flush().await.unwrap();
+            drop(db);
+        }
+        {
+            // remove files in local disk
+            let wal_path = option.wal_dir_path().to_string();
+            let path = std::path::Path::new(wal_path.as_str());
+            if path.exists() {
+                std::fs::remove_dir_all(path).unwrap();
+            }
+            let version_path = option.version_log_dir_path().to_string();
+            let path = std::path::Path::new(version_path.as_str());
+            if path.exists() {
+                std::fs::remove_dir_all(path).unwrap();
+            }
+        }
+        // test recover from s3
+        {
+            let db: DB<Test, TokioExecutor> =
+                DB::new(option.clone(), TokioExecutor::default(), TestSchema)
+                    .await
+                    .unwrap();
+            let mut sort_items = BTreeMap::new();
+            for item in test_items(0u32..10) {
+                sort_items.insert(item.vstring.clone(), item.clone());
+            }
+            let tx = db.transaction().await;
+            let mut scan = tx
+                .scan((Bound::Unbounded, Bound::Unbounded))
+                .projection(&["vstring", "vu32", "vbool"])
+                .take()
+                .await
+                .unwrap();
+
+            while let Some(actual) = scan.next().await.transpose().unwrap() {
+                let (expected_key, expected) = sort_items.pop_first().unwrap();
+                assert_eq!(actual.key().value, expected_key);
+                assert_eq!(actual.value().as_ref().unwrap().vstring, expected.vstring);
+                assert_eq!(actual.value().as_ref().unwrap().vu32, Some(expected.vu32));
+                assert_eq!(actual.value().as_ref().unwrap().vbool, expected.vbool);
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn schema_recover() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs = Arc::new(TokioFs) as Arc<dyn DynFs>;
+
+        let option = Arc::new(DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        ));
+        fs.create_dir_all(&option.wal_dir_path()).await.unwrap();
+
+        let (task_tx, _task_rx) = bounded(1);
 
-    pub(crate) async fn build_schema(
-        option: Arc<DbOption>,
-        fs: &Arc<dyn DynFs>,
-    ) -> Result<(crate::DbStorage<Test>, Receiver<CompactTask>), fusio::Error> {
         let trigger = TriggerFactory::create(option.trigger_type);
+        let mem_storage: crate::DbStorage<Test> = crate::DbStorage {
+            mutable: MutableMemTable::new(&option, trigger.clone(), fs, Arc::new(TestSchema))
+                .await
+                .unwrap(),
+            immutables: Default::default(),
+            compaction_tx: task_tx.clone(),
+            recover_wal_ids: None,
+            trigger,
+            record_schema: Arc::new(TestSchema),
+            option: option.clone(),
+            compaction_in_progress: std::sync::atomic::AtomicBool::new(false),
+            expiry: None,
+            aggregates: Default::default(),
+            watchers: Default::default(),
+        };
 
-        let mutable = MutableMemTable::new(
-            &option,
-            trigger.clone(),
-            fs.clone(),
-            Arc::new(TestSchema {}),
+        for (i, item) in test_items(0u32..32).enumerate() {
+            mem_storage
+                .write(LogType::Full, item, (i as u32).into())
+                .await
+                .unwrap();
+        }
+        mem_storage.flush_wal().await.unwrap();
+        drop(mem_storage);
+
+        let db: DB<Test, TokioExecutor> = DB::new(
+            option.as_ref().to_owned(),
+            TokioExecutor::default(),
+            TestSchema,
         )
-        .await?;
+        .await
+        .unwrap();
 
-        mutable
-            .insert(
-                LogType::Full,
-                Test {
-                    vstring: "alice".to_string(),
-                    vu32: 1,
-                    vbool: Some(true),
-                },
-                1_u32.into(),
+        let mut sort_items = BTreeMap::new();
+        for item in test_items(0u32..32) {
+            sort_items.insert(item.vstring.clone(), item);
+        }
+        {
+            let tx = db.transaction().await;
+            let mut scan = tx
+                .scan((Bound::Unbounded, Bound::Unbounded))
+                .take()
+                .await
+                .unwrap();
+
+            while let Some(entry) = scan.next().await.transpose().unwrap() {
+                let (_, test) = sort_items.pop_first().unwrap();
+
+                assert_eq!(entry.key().value, test.key());
+                assert_eq!(entry.value().as_ref().unwrap().vstring, test.vstring);
+                assert_eq!(entry.value().as_ref().unwrap().vu32, Some(test.vu32));
+                assert_eq!(entry.value().as_ref().unwrap().vbool, test.vbool);
+            }
+        }
+    }
+
+    // Every round writes the same keys with `vu32 = round` at timestamp `round` into a new WAL
+    // file
+    async fn write_wal_rounds(option: &Arc<DbOption>, rounds: u32) {
+        let fs = Arc::new(TokioFs) as Arc<dyn DynFs>;
+        fs.create_dir_all(&option.wal_dir_path()).await.unwrap();
+
+        for round in 0..rounds {
+            let (task_tx, _task_rx) = bounded(1);
+            let trigger = TriggerFactory::create(option.trigger_type);
+            let mem_storage: crate::DbStorage<Test> = crate::DbStorage {
+                mutable: MutableMemTable::new(
+                    option,
+                    trigger.clone(),
+                    fs.clone(),
+                    Arc::new(TestSchema),
+                )
+                .await
+                .unwrap(),
+                immutables: Default::default(),
+                compaction_tx: task_tx,
+                recover_wal_ids: None,
+                trigger,
+                record_schema: Arc::new(TestSchema),
+                option: option.clone(),
+                compaction_in_progress: std::sync::atomic::AtomicBool::new(false),
+                expiry: None,
+                aggregates: Default::default(),
+                watchers: Default::default(),
+            };
+
+            for mut item in test_items(0u32..16) {
+                item.vu32 = round;
+                mem_storage
+                    .write(LogType::Full, item, round.into())
+                    .await
+                    .unwrap();
+            }
+            mem_storage.flush_wal().await.unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn parallel_wal_recover() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = Arc::new(
+            DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &TestSchema,
+            )
+            .wal_recover_parallelism(4),
+        );
+        write_wal_rounds(&option, 8).await;
+
+        let db: DB<Test, TokioExecutor> = DB::new(
+            option.as_ref().to_owned(),
+            TokioExecutor::default(),
+            TestSchema,
+        )
+        .await
+        .unwrap();
+
+        for item in test_items(0u32..16) {
+            let vu32 = db
+                .get(&item.vstring, |entry| entry.get().vu32)
+                .await
+                .unwrap();
+            assert_eq!(vu32, Some(7));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn point_in_time_recover() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = Arc::new(DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        ));
+        write_wal_rounds(&option, 8).await;
+
+        let open = || {
+            DB::<Test, TokioExecutor>::new(
+                option.as_ref().to_owned().recover_until(3_u32),
+                TokioExecutor::default(),
+                TestSchema,
             )
+        };
+        let db = open().await.unwrap();
+
+        for item in test_items(0u32..16) {
+            let vu32 = db
+                .get(&item.vstring, |entry| entry.get().vu32)
+                .await
+                .unwrap();
+            assert_eq!(vu32, Some(3));
+        }
+
+        // the writes after the recovery are not cut by reopening with the same option
+        db.insert(Test {
+            vstring: "0".to_string(),
+            vu32: 100,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        db.flush_wal().await.unwrap();
+        drop(db);
+
+        let db = open().await.unwrap();
+        let vu32 = db
+            .get(&"0".to_string(), |entry| entry.get().vu32)
+            .await
+            .unwrap();
+        assert_eq!(vu32, Some(100));
+        let vu32 = db
+            .get(&"1".to_string(), |entry| entry.get().vu32)
+            .await
+            .unwrap();
+        assert_eq!(vu32, Some(3));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scan_into_ipc_stream() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
             .await
             .unwrap();
-        mutable
-            .insert(
-                LogType::Full,
-                Test {
-                    vstring: "ben".to_string(),
-                    vu32: 2,
-                    vbool: Some(true),
-                },
-                1_u32.into(),
-            )
+        for item in test_items(0u32..32) {
+            db.insert(item).await.unwrap();
+        }
+
+        let txn = db.transaction().await;
+        let mut bytes = Vec::new();
+        txn.scan((Bound::Unbounded, Bound::Unbounded))
+            .projection(&["vu32"])
+            .write_ipc(8, &mut Cursor::new(&mut bytes))
             .await
             .unwrap();
-        mutable
-            .insert(
-                LogType::Full,
-                Test {
-                    vstring: "carl".to_string(),
-                    vu32: 3,
-                    vbool: Some(true),
-                },
-                1_u32.into(),
-            )
+
+        let reader = StreamReader::try_new(Cursor::new(bytes), None).unwrap();
+        let fields = reader
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(fields, vec!["vstring", "vu32"]);
+        let rows = reader.map(|batch| batch.unwrap().num_rows()).sum::<usize>();
+        assert_eq!(rows, 32);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scan_arrow() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
             .await
             .unwrap();
+        for item in test_items(0u32..20) {
+            db.insert(item).await.unwrap();
+        }
+        db.flush().await.unwrap();
+        for item in test_items(20u32..32) {
+            db.insert(item).await.unwrap();
+        }
+        db.remove("3".to_string()).await.unwrap();
 
-        let immutables = {
-            let trigger = TriggerFactory::create(option.trigger_type);
-
-            let mutable: MutableMemTable<Test> =
-                MutableMemTable::new(&option, trigger.clone(), fs.clone(), Arc::new(TestSchema))
-                    .await?;
+        let batches = db
+            .scan_arrow((Bound::Unbounded, Bound::Unbounded), Some(&["vu32"][..]), 8)
+            .await
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+        assert!(batches.iter().all(|batch| batch.num_rows() <= 8));
+        let fields = batches[0]
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(fields, vec!["vstring", "vu32"]);
+        let rows = batches.iter().map(RecordBatch::num_rows).sum::<usize>();
+        assert_eq!(rows, 31);
+
+        let lower = "1".to_string();
+        let upper = "2".to_string();
+        let batches = db
+            .scan_arrow((Bound::Included(&lower), Bound::Excluded(&upper)), None, 64)
+            .await
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].num_columns(), 3);
+        // "1" and "10" to "19"
+        assert_eq!(batches[0].num_rows(), 11);
+    }
 
-            mutable
-                .insert(
-                    LogType::Full,
-                    Test {
-                        vstring: "dice".to_string(),
-                        vu32: 4,
-                        vbool: Some(true),
-                    },
-                    1_u32.into(),
-                )
-                .await
-                .unwrap();
-            mutable
-                .insert(
-                    LogType::Full,
-                    Test {
-                        vstring: "erika".to_string(),
-                        vu32: 5,
-                        vbool: Some(true),
-                    },
-                    1_u32.into(),
-                )
-                .await
-                .unwrap();
-            mutable
-                .insert(
-                    LogType::Full,
-                    Test {
-                        vstring: "funk".to_string(),
-                        vu32: 6,
-                        vbool: Some(true),
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scan_in_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        for item in test_items(0u32..6) {
+            db.insert(item).await.unwrap();
+        }
+        db.flush().await.unwrap();
+        for item in test_items(4u32..9) {
+            db.insert(item).await.unwrap();
+        }
+        db.remove("2".to_string()).await.unwrap();
+
+        for (order, expected) in [
+            (Order::Asc, ["0", "1", "3", "4", "5", "6", "7", "8"]),
+            (Order::Desc, ["8", "7", "6", "5", "4", "3", "1", "0"]),
+        ] {
+            let keys = db
+                .scan_snapshot(
+                    (Bound::Unbounded, Bound::Unbounded),
+                    |scan| scan.order(order),
+                    |entry| {
+                        entry
+                            .value()
+                            .is_some()
+                            .then(|| entry.key().value.to_string())
                     },
-                    1_u32.into(),
                 )
                 .await
-                .unwrap();
-
-            vec![(
-                Some(generate_file_id()),
-                mutable.into_immutable().await.unwrap().1,
-            )]
-        };
-
-        let (compaction_tx, compaction_rx) = bounded(1);
-
-        Ok((
-            crate::DbStorage {
-                mutable,
-                immutables,
-                compaction_tx,
-                recover_wal_ids: None,
-                trigger,
-                record_schema: Arc::new(TestSchema {}),
-                option,
-                compaction_in_progress: std::sync::atomic::AtomicBool::new(false),
-            },
-            compaction_rx,
-        ))
+                .filter_map(|key| async move { key.unwrap() })
+                .collect::<Vec<_>>()
+                .await;
+            assert_eq!(keys, expected, "{order:?}");
+        }
     }
 
-    use crate::record::test::test_items;
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scan_prefix() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .prefix_bloom_filter(2);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        for item in test_items(0u32..32) {
+            db.insert(item).await.unwrap();
+        }
+        db.flush().await.unwrap();
+        for item in test_items(32u32..36) {
+            db.insert(item).await.unwrap();
+        }
 
-    pub(crate) async fn build_db<R, E>(
-        option: Arc<DbOption>,
-        task_rx: Receiver<CompactTask>,
-        executor: E,
-        mem_storage: crate::DbStorage<R>,
-        record_schema: Arc<R::Schema>,
-        version: Version<R>,
-        manager: Arc<StoreManager>,
-    ) -> Result<DB<R, E>, DbError>
-    where
-        R: Record + Send + Sync,
-        <R::Schema as RecordSchema>::Columns: Send + Sync,
-        E: Executor + Send + Sync + 'static,
-    {
-        {
-            let base_fs = manager.base_fs();
+        let prefix = "3".to_string();
+        let keys = db
+            .scan_prefix(&prefix, |entry| entry.get().vstring.to_string())
+            .await
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(keys, ["3", "30", "31", "32", "33", "34", "35"]);
+        // in the key range of the flushed table, but ruled out by its filter
+        let absent = "2a".to_string();
+        let keys = db
+            .scan_prefix(&absent, |entry| entry.get().vstring.to_string())
+            .await
+            .collect::<Vec<_>>()
+            .await;
+        assert!(keys.is_empty());
+
+        let mut txn = db.transaction().await;
+        txn.insert(Test {
+            vstring: "3a".to_string(),
+            vu32: 0,
+            vbool: None,
+        });
+        txn.remove("30".to_string());
+        let keys = txn
+            .scan_prefix(&prefix, |entry| {
+                entry
+                    .value()
+                    .is_some()
+                    .then(|| entry.key().value.to_string())
+            })
+            .filter_map(|key| async move { key.unwrap() })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(keys, ["3", "31", "32", "33", "34", "35", "3a"]);
+    }
 
-            let _ = base_fs.create_dir_all(&option.wal_dir_path()).await;
-            let _ = base_fs.create_dir_all(&option.version_log_dir_path()).await;
+    #[tokio::test(flavor = "multi_thread")]
+    async fn count() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        for item in test_items(10u32..20) {
+            db.insert(item).await.unwrap();
+        }
+        db.flush().await.unwrap();
+        for item in test_items(20u32..25) {
+            db.insert(item).await.unwrap();
         }
+        let all = (Bound::Unbounded, Bound::Unbounded);
+
+        // the table holds the keys up to "19" and the memtable the ones after it
+        let snapshot = db.snapshot().await;
+        let counted = snapshot.scan(all).tables_counted_from_footers().await;
+        assert_eq!(counted.unwrap().len(), 1);
+        drop(snapshot);
+        assert_eq!(db.count(all).await.unwrap(), 15);
+        let from = "15".to_string();
+        assert_eq!(
+            db.count((Bound::Included(&from), Bound::Unbounded))
+                .await
+                .unwrap(),
+            10
+        );
 
-        let mem_storage = Arc::new(E::rw_lock(mem_storage));
+        db.remove("12".to_string()).await.unwrap();
+        let snapshot = db.snapshot().await;
+        let counted = snapshot.scan(all).tables_counted_from_footers().await;
+        assert!(counted.unwrap().is_empty());
+        drop(snapshot);
+        assert_eq!(db.count(all).await.unwrap(), 14);
+
+        let mut txn = db.transaction().await;
+        txn.insert(Test {
+            vstring: "3".to_string(),
+            vu32: 0,
+            vbool: None,
+        });
+        txn.remove("20".to_string());
+        assert_eq!(txn.count(all).await.unwrap(), 14);
+        let limited = txn
+            .scan((Bound::Included(&from), Bound::Unbounded))
+            .limit(3)
+            .count()
+            .await;
+        assert_eq!(limited.unwrap(), 3);
+    }
 
-        let (cleaner, clean_sender) = Cleaner::new(option.clone(), manager.clone());
-        let manifest = Box::new(
-            build_version_set::<R, E>(version, clean_sender, option.clone(), manager.clone())
-                .await
-                .map_err(ManifestStorageError::Version)?,
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scan_with_builder() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
         );
-        let ctx = Arc::new(Context::new(
-            manager,
-            Arc::new(NoCache::default()),
-            manifest,
-            TestSchema.arrow_schema().clone(),
-        ));
-        // Create built-in compactor for tests
-        match &option.compaction_option {
-            CompactionOption::Leveled(opt) => {
-                let compactor = LeveledCompactor::<R>::new(
-                    opt.clone(),
-                    record_schema.clone(),
-                    option.clone(),
-                    ctx.clone(),
-                );
-                DB::finish_build(executor, mem_storage, ctx, compactor, cleaner, task_rx).await
-            }
-            CompactionOption::Tiered(opt) => {
-                let compactor = TieredCompactor::<R>::new(
-                    opt.clone(),
-                    record_schema.clone(),
-                    option.clone(),
-                    ctx.clone(),
-                );
-                DB::finish_build(executor, mem_storage, ctx, compactor, cleaner, task_rx).await
-            }
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        for item in test_items(0u32..32) {
+            db.insert(item).await.unwrap();
+        }
+
+        let scanned = db
+            .scan_with(
+                (Bound::Unbounded, Bound::Unbounded),
+                |scan| scan.projection(&["vu32"]).reverse().offset(1).limit(3),
+                |entry| {
+                    let record = entry.get();
+                    (record.vstring.to_string(), record.vu32, record.vbool)
+                },
+            )
+            .await
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+
+        // the same builder on a transaction scans the same records
+        let txn = db.transaction().await;
+        let mut stream = txn
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .projection(&["vu32"])
+            .reverse()
+            .offset(1)
+            .limit(3)
+            .take()
+            .await
+            .unwrap();
+        let mut expected = Vec::new();
+        while let Some(entry) = stream.next().await {
+            let entry = entry.unwrap();
+            let record = entry.value().unwrap();
+            expected.push((record.vstring.to_string(), record.vu32, record.vbool));
         }
+        assert_eq!(scanned.len(), 3);
+        assert_eq!(scanned, expected);
+        assert!(scanned
+            .iter()
+            .all(|(_, vu32, vbool)| vu32.is_some() && vbool.is_none()));
     }
 
     #[tokio::test(flavor = "multi_thread")]
-    async fn read_from_disk() {
+    async fn scan_resume() {
         let temp_dir = TempDir::new().unwrap();
-        let temp_dir_l0 = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        for item in test_items(0u32..10) {
+            db.insert(item).await.unwrap();
+        }
 
-        let path = Path::from_filesystem_path(temp_dir.path()).unwrap();
-        let path_l0 = Path::from_filesystem_path(temp_dir_l0.path()).unwrap();
+        for (order, expected) in [
+            (
+                Order::Asc,
+                [
+                    &["0", "1", "2", "3"][..],
+                    &["4", "5", "6", "7"],
+                    &["8", "9"],
+                ],
+            ),
+            (
+                Order::Desc,
+                [
+                    &["9", "8", "7", "6"][..],
+                    &["5", "4", "3", "2"],
+                    &["1", "0"],
+                ],
+            ),
+        ] {
+            let mut pages = Vec::new();
+            let mut bytes: Option<Vec<u8>> = None;
+            loop {
+                let token = match &bytes {
+                    Some(bytes) => Some(ResumeToken::<String>::from_bytes(bytes).await.unwrap()),
+                    None => None,
+                };
+                let txn = db.transaction().await;
+                let mut scan = txn
+                    .scan((Bound::Unbounded, Bound::Unbounded))
+                    .order(order)
+                    .limit(4);
+                if let Some(token) = &token {
+                    scan = scan.resume(token);
+                }
+                let mut page = scan.take_resumable().await.unwrap();
+                let mut keys = Vec::new();
+                while let Some(entry) = page.next().await {
+                    keys.push(entry.unwrap().key().value.to_string());
+                }
+                bytes = match page.resume_token() {
+                    Some(token) => Some(token.to_bytes().await.unwrap()),
+                    None => None,
+                };
+                if keys.is_empty() {
+                    break;
+                }
+                pages.push(keys);
+            }
+            assert_eq!(pages, expected);
+            assert!(bytes.is_none());
+        }
 
-        let mut option = DbOption::new(path, &TestSchema)
-            .level_path(0, path_l0, FsOptions::Local)
-            .unwrap();
-        option = option
-            .immutable_chunk_num(1)
-            .immutable_chunk_max_num(1)
-            .leveled_compaction(
-                LeveledOptions::default()
-                    .major_threshold_with_sst_size(3)
-                    .level_sst_magnification(10)
-                    .major_default_oldest_table_num(1),
-            )
-            .max_sst_file_size(2 * 1024 * 1024);
-        option.trigger_type = TriggerType::Length(/* max_mutable_len */ 5);
+        // a resumed scan keeps the bounds past the key of the token and reads its own snapshot
+        let txn = db.transaction().await;
+        let mut page = txn
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .limit(2)
+            .take_resumable()
+            .await
+            .unwrap();
+        while page.next().await.is_some() {}
+        let token = page.resume_token().unwrap();
+        assert_eq!(token.key(), "1");
+        drop(page);
+        drop(txn);
+
+        db.insert(Test {
+            vstring: "10".to_string(),
+            vu32: 10,
+            vbool: None,
+        })
+        .await
+        .unwrap();
+        assert!(token.ts() < db.snapshot().await.ts());
+        let (lower, upper) = ("10".to_string(), "3".to_string());
+        let txn = db.transaction().await;
+        let keys = txn
+            .scan((Bound::Unbounded, Bound::Excluded(&lower)))
+            .ranges([(Bound::Included(&upper), Bound::Unbounded)])
+            .resume(&token)
+            .limit(3)
+            .take()
+            .await
+            .unwrap()
+            .map(|entry| entry.unwrap().key().value.to_string())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(keys, vec!["3", "4", "5"]);
+    }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scan_ranges() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
         let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
             .await
             .unwrap();
-
-        for (i, item) in test_items(0u32..32).enumerate() {
-            db.write(item, 0.into()).await.unwrap();
-            if i % 5 == 0 {
-                db.flush().await.unwrap();
-            }
+        for item in test_items(0u32..16) {
+            db.insert(item).await.unwrap();
+        }
+        db.flush().await.unwrap();
+        for item in test_items(16u32..32) {
+            db.insert(item).await.unwrap();
         }
 
-        let tx = db.transaction().await;
-        let key = 20.to_string();
-        let option1 = tx.get(&key, Projection::All).await.unwrap().unwrap();
-
-        dbg!(db.ctx.manifest.current().await);
-
-        let version = db.ctx.manifest.current().await;
-        assert!(!version.level_slice[1].is_empty());
+        let mut txn = db.transaction().await;
+        txn.remove("21".to_string());
+        let (lower_1, upper_1) = ("1".to_string(), "12".to_string());
+        let (lower_2, upper_2) = ("2".to_string(), "21".to_string());
+        // ranges come in key order, whatever order they were given in
+        let entries = txn
+            .scan((Bound::Included(&lower_2), Bound::Included(&upper_2)))
+            .ranges([(Bound::Included(&lower_1), Bound::Excluded(&upper_1))])
+            .take()
+            .await
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (entry.key().value.to_string(), entry.value().is_some())
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            entries,
+            vec![
+                ("1".to_string(), true),
+                ("10".to_string(), true),
+                ("11".to_string(), true),
+                ("2".to_string(), true),
+                ("20".to_string(), true),
+                ("21".to_string(), false),
+            ]
+        );
 
-        assert_eq!(option1.get().vstring, "20");
-        assert_eq!(option1.get().vu32, Some(20));
-        assert_eq!(option1.get().vbool, Some(true));
+        // offset and limit count across the ranges
+        let keys = txn
+            .scan((Bound::Included(&lower_2), Bound::Included(&upper_2)))
+            .ranges([(Bound::Included(&lower_1), Bound::Excluded(&upper_1))])
+            .reverse()
+            .offset(1)
+            .limit(3)
+            .take()
+            .await
+            .unwrap()
+            .map(|entry| entry.unwrap().key().value.to_string())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(keys, vec!["20", "2", "11"]);
+
+        // a limit of every row does not overflow with the offset
+        let keys = txn
+            .scan((Bound::Included(&lower_1), Bound::Excluded(&upper_1)))
+            .offset(1)
+            .limit(usize::MAX)
+            .take()
+            .await
+            .unwrap()
+            .map(|entry| entry.unwrap().key().value.to_string())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(keys, vec!["10", "11"]);
     }
 
     #[tokio::test(flavor = "multi_thread")]
-    async fn test_flush() {
+    async fn soft_delete() {
         let temp_dir = TempDir::new().unwrap();
-
-        let mut option = DbOption::new(
+        let clock = ManualClock::new(0);
+        let option = DbOption::new(
             Path::from_filesystem_path(temp_dir.path()).unwrap(),
             &TestSchema,
         )
-        .immutable_chunk_num(1)
-        .immutable_chunk_max_num(1)
-        .leveled_compaction(
-            LeveledOptions::default()
-                .major_threshold_with_sst_size(3)
-                .level_sst_magnification(10)
-                .major_default_oldest_table_num(1),
-        )
-        .max_sst_file_size(2 * 1024 * 1024);
-        option.trigger_type = TriggerType::Length(/* max_mutable_len */ 50);
-
+        .time_source(clock.clone())
+        .soft_delete(Duration::from_secs(60));
         let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
             .await
             .unwrap();
-
-        for item in test_items(0u32..10) {
-            db.write(item.clone(), 0.into()).await.unwrap();
+        for item in test_items(0u32..4) {
+            db.insert(item).await.unwrap();
         }
+        db.remove("2".to_string()).await.unwrap();
         db.flush().await.unwrap();
+        clock.advance(Duration::from_secs(60));
         for item in test_items(10u32..20) {
-            db.write(item.clone(), 0.into()).await.unwrap();
+            db.insert(item).await.unwrap();
         }
-
-        dbg!(db.ctx.manifest.current().await);
+        db.remove("1".to_string()).await.unwrap();
         db.flush().await.unwrap();
-        dbg!(db.ctx.manifest.current().await);
+        db.compact_range((Bound::Unbounded, Bound::Unbounded))
+            .await
+            .unwrap();
 
-        let version = db.ctx.manifest.current().await;
-        assert!(!version.level_slice[0].is_empty());
+        let removed = |key: &'static str| {
+            let db = &db;
+            async move {
+                let key = key.to_string();
+                let txn = db.transaction().await;
+                let entries = txn
+                    .scan((Bound::Included(&key), Bound::Included(&key)))
+                    .include_removed()
+                    .take()
+                    .await
+                    .unwrap()
+                    .map(|entry| entry.unwrap().value().map(|record| record.vu32))
+                    .collect::<Vec<_>>()
+                    .await;
+                entries
+            }
+        };
+        // the removal of "2" is a minute old and was purged by the compaction, the one of "1" is
+        // within the horizon
+        assert_eq!(removed("1").await, vec![Some(Some(1))]);
+        assert_eq!(removed("2").await, vec![None]);
+        assert!(db
+            .get(&"1".to_string(), |_| Some(()))
+            .await
+            .unwrap()
+            .is_none());
+
+        assert!(db.undelete(&"1".to_string()).await.unwrap());
+        assert!(!db.undelete(&"2".to_string()).await.unwrap());
+        assert!(!db.undelete(&"3".to_string()).await.unwrap());
+        let vu32 = db
+            .get(&"1".to_string(), |entry| entry.get().vu32)
+            .await
+            .unwrap();
+        assert_eq!(vu32, Some(1));
     }
 
-    #[ignore = "s3"]
-    #[cfg(all(feature = "aws", feature = "tokio-http"))]
     #[tokio::test(flavor = "multi_thread")]
-    async fn test_s3_recover() {
+    async fn may_exist() {
         let temp_dir = TempDir::new().unwrap();
-
-        if option_env!("AWS_ACCESS_KEY_ID").is_none()
-            || option_env!("AWS_SECRET_ACCESS_KEY").is_none()
-        {
-            eprintln!("can not get `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`");
-            return;
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        for item in test_items(0u32..8) {
+            db.insert(item).await.unwrap();
         }
-        let key_id = std::option_env!("AWS_ACCESS_KEY_ID").unwrap().to_string();
-        let secret_key = std::option_env!("AWS_SECRET_ACCESS_KEY")
+        db.remove("3".to_string()).await.unwrap();
+        assert_eq!(db.may_exist(&"2".to_string()).await, Existence::Maybe);
+        assert_eq!(db.may_exist(&"3".to_string()).await, Existence::No);
+        assert_eq!(db.may_exist(&"35".to_string()).await, Existence::No);
+
+        db.flush().await.unwrap();
+        db.insert(test_items(9u32..10).next().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(db.may_exist(&"9".to_string()).await, Existence::Maybe);
+        // beyond the key range of the SSTable
+        assert_eq!(db.may_exist(&"8".to_string()).await, Existence::No);
+        // within it, until a get found it absent
+        assert_eq!(db.may_exist(&"35".to_string()).await, Existence::Maybe);
+        assert!(db
+            .get(&"35".to_string(), |_| Some(()))
+            .await
             .unwrap()
-            .to_string();
-        let bucket = std::env::var("BUCKET_NAME").expect("expected s3 bucket not to be empty");
-        let region = Some(std::env::var("AWS_REGION").expect("expected s3 region not to be empty"));
-        let token = Some(std::option_env!("AWS_SESSION_TOKEN").unwrap().to_string());
+            .is_none());
+        assert_eq!(db.may_exist(&"35".to_string()).await, Existence::No);
+    }
 
-        let s3_option = FsOptions::S3 {
-            bucket,
-            credential: Some(fusio::remotes::aws::AwsCredential {
-                key_id,
-                secret_key,
-                token,
-            }),
-            endpoint: None,
-            region,
-            sign_payload: None,
-            checksum: None,
-        };
+    #[tokio::test(flavor = "multi_thread")]
+    async fn get_many() {
+        let temp_dir = TempDir::new().unwrap();
         let option = DbOption::new(
             Path::from_filesystem_path(temp_dir.path()).unwrap(),
-            &TestSchema {},
-        )
-        .base_fs(s3_option.clone());
-        {
-            let db = DB::new(option.clone(), TokioExecutor::default(), TestSchema)
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        for item in test_items(0u32..6) {
+            db.insert(item).await.unwrap();
+        }
+        db.flush().await.unwrap();
+        for item in test_items(4u32..9) {
+            db.insert(Test {
+                vu32: item.vu32 * 10,
+                ..item
+            })
+            .await
+            .unwrap();
+        }
+        db.remove("1".to_string()).await.unwrap();
+        db.flush().await.unwrap();
+        db.insert(test_items(9u32..10).next().unwrap())
+            .await
+            .unwrap();
+
+        let keys = ["9", "0", "1", "5", "35", "7"].map(String::from);
+        let vu32 = db
+            .get_many(&keys, Projection::Parts(vec!["vu32"]), |entry| {
+                entry.value().unwrap().vu32
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            vu32,
+            [
+                Some(Some(9)),
+                Some(Some(0)),
+                None,
+                Some(Some(50)),
+                None,
+                Some(Some(70))
+            ]
+        );
+
+        let mut txn = db.transaction().await;
+        txn.insert(Test {
+            vstring: "0".to_string(),
+            vu32: 100,
+            vbool: None,
+        });
+        let keys = keys.iter().collect::<Vec<_>>();
+        let vu32 = txn
+            .get_many(&keys, Projection::All)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.map(|entry| entry.get().vu32))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vu32,
+            [
+                Some(Some(9)),
+                Some(Some(100)),
+                None,
+                Some(Some(50)),
+                None,
+                Some(Some(70))
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn delete_range() {
+        async fn keys(db: &DB<Test, TokioExecutor>) -> Vec<String> {
+            let txn = db.transaction().await;
+            let mut scan = txn
+                .scan((Bound::Unbounded, Bound::Unbounded))
+                .take()
                 .await
                 .unwrap();
-
-            for item in test_items(0u32..10) {
-                db.insert(item.clone()).await.unwrap();
+            let mut keys = Vec::new();
+            while let Some(entry) = scan.next().await.transpose().unwrap() {
+                if entry.value().is_some() {
+                    keys.push(entry.key().value.to_string());
+                }
             }
-            // flush to s3
-            db.flush().await.unwrap();
-            drop(db);
+            keys
         }
-        {
-            // remove files in local disk
-            let wal_path = option.wal_dir_path().to_string();
-            let path = std::path::Path::new(wal_path.as_str());
-            if path.exists() {
-                std::fs::remove_dir_all(path).unwrap();
+
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        for item in test_items(0u32..6) {
+            db.insert(item).await.unwrap();
+        }
+        db.flush().await.unwrap();
+        for item in test_items(4u32..9) {
+            db.insert(item).await.unwrap();
+        }
+        db.delete_range((
+            Bound::Included("2".to_string()),
+            Bound::Excluded("6".to_string()),
+        ))
+        .await
+        .unwrap();
+        db.insert(test_items(3u32..4).next().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(db.ctx.manifest().current().await.range_tombstones.len(), 1);
+
+        assert_eq!(keys(&db).await, ["0", "1", "3", "6", "7", "8"]);
+        for (key, exists) in [("1", true), ("2", false), ("3", true), ("5", false)] {
+            let entry = db.get(&key.to_string(), |_| Some(())).await.unwrap();
+            assert_eq!(entry.is_some(), exists, "{key}");
+        }
+
+        // the table the tombstone lists is merged away, and the records stay removed
+        db.flush().await.unwrap();
+        db.compact_range((Bound::Unbounded, Bound::Unbounded))
+            .await
+            .unwrap();
+        assert!(db
+            .ctx
+            .manifest()
+            .current()
+            .await
+            .range_tombstones
+            .is_empty());
+        assert_eq!(keys(&db).await, ["0", "1", "3", "6", "7", "8"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn merge() {
+        struct AddVu32;
+
+        impl MergeOperator<Test> for AddVu32 {
+            fn merge(&self, existing: Option<TestRef<'_>>, operand: TestRef<'_>) -> Option<Test> {
+                let vu32 = existing.and_then(|test| test.vu32).unwrap_or(0);
+                Some(Test {
+                    vstring: operand.vstring.to_string(),
+                    vu32: vu32 + operand.vu32.unwrap_or(0),
+                    vbool: operand.vbool,
+                })
             }
-            let version_path = option.version_log_dir_path().to_string();
-            let path = std::path::Path::new(version_path.as_str());
-            if path.exists() {
-                std::fs::remove_dir_all(path).unwrap();
+        }
+
+        struct ChangeKey;
+
+        impl MergeOperator<Test> for ChangeKey {
+            fn merge(&self, _: Option<TestRef<'_>>, operand: TestRef<'_>) -> Option<Test> {
+                Some(Test {
+                    vstring: format!("{}!", operand.vstring),
+                    vu32: 0,
+                    vbool: None,
+                })
             }
         }
-        // test recover from s3
-        {
-            let db: DB<Test, TokioExecutor> =
-                DB::new(option.clone(), TokioExecutor::default(), TestSchema)
+
+        async fn records(db: &DB<Test, TokioExecutor>) -> Vec<(String, u32)> {
+            let mut records = Vec::new();
+            for key in ["a", "b"] {
+                let vu32 = db
+                    .get(&key.to_string(), |entry| entry.get().vu32)
                     .await
                     .unwrap();
-            let mut sort_items = BTreeMap::new();
-            for item in test_items(0u32..10) {
-                sort_items.insert(item.vstring.clone(), item.clone());
+                records.extend(vu32.map(|vu32| (key.to_string(), vu32)));
             }
-            let tx = db.transaction().await;
-            let mut scan = tx
+            let txn = db.transaction().await;
+            let mut scan = txn
                 .scan((Bound::Unbounded, Bound::Unbounded))
-                .projection(&["vstring", "vu32", "vbool"])
                 .take()
                 .await
                 .unwrap();
-
-            while let Some(actual) = scan.next().await.transpose().unwrap() {
-                let (expected_key, expected) = sort_items.pop_first().unwrap();
-                assert_eq!(actual.key().value, expected_key);
-                assert_eq!(actual.value().as_ref().unwrap().vstring, expected.vstring);
-                assert_eq!(actual.value().as_ref().unwrap().vu32, Some(expected.vu32));
-                assert_eq!(actual.value().as_ref().unwrap().vbool, expected.vbool);
+            let mut scanned = Vec::new();
+            while let Some(entry) = scan.next().await.transpose().unwrap() {
+                if let Some(test) = entry.value() {
+                    scanned.push((test.vstring.to_string(), test.vu32.unwrap()));
+                }
             }
+            assert_eq!(records, scanned);
+            records
         }
+
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .merge_operator::<Test, _>(AddVu32);
+        let db: DB<Test, TokioExecutor> =
+            DB::new(option.clone(), TokioExecutor::default(), TestSchema)
+                .await
+                .unwrap();
+        let operand = |key: &str, vu32| Test {
+            vstring: key.to_string(),
+            vu32,
+            vbool: None,
+        };
+        db.insert(operand("a", 10)).await.unwrap();
+        db.flush().await.unwrap();
+
+        futures_util::future::join_all((1..=4).map(|vu32| db.merge(operand("a", vu32))))
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        db.merge(operand("b", 5)).await.unwrap();
+        // the operands are entries of their own, which the reads fold
+        let operands = db
+            .mem_storage
+            .read()
+            .await
+            .mutable
+            .operands(&"a".to_string(), u32::MAX.into())
+            .len();
+        assert_eq!(operands, 4);
+        assert_eq!(records(&db).await, [("a".into(), 20), ("b".into(), 5)]);
+
+        // an insert replaces the operands before it
+        db.insert(operand("b", 1)).await.unwrap();
+        db.merge(operand("b", 2)).await.unwrap();
+        assert_eq!(records(&db).await, [("a".into(), 20), ("b".into(), 3)]);
+
+        // the operands are recovered from the WAL
+        db.flush_wal().await.unwrap();
+        drop(db);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        assert_eq!(records(&db).await, [("a".into(), 20), ("b".into(), 3)]);
+
+        // and folded into records when the memtable is frozen
+        db.flush().await.unwrap();
+        assert!(!db.mem_storage.read().await.mutable.has_operands());
+        assert_eq!(records(&db).await, [("a".into(), 20), ("b".into(), 3)]);
+
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .merge_operator::<Test, _>(ChangeKey);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        db.merge(operand("a", 1)).await.unwrap();
+        assert!(matches!(
+            db.get(&"a".to_string(), |entry| entry.get().vu32).await,
+            Err(CommitError::Database(DbError::MergeOperatorKey))
+        ));
+
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        assert!(matches!(
+            db.merge(operand("a", 1)).await,
+            Err(CommitError::Database(DbError::NoMergeOperator))
+        ));
     }
 
     #[tokio::test(flavor = "multi_thread")]
-    async fn schema_recover() {
+    async fn read_latest_without_transaction() {
         let temp_dir = TempDir::new().unwrap();
-        let fs = Arc::new(TokioFs) as Arc<dyn DynFs>;
-
-        let option = Arc::new(DbOption::new(
+        let option = DbOption::new(
             Path::from_filesystem_path(temp_dir.path()).unwrap(),
             &TestSchema,
-        ));
-        fs.create_dir_all(&option.wal_dir_path()).await.unwrap();
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        for item in test_items(0u32..8) {
+            db.insert(item).await.unwrap();
+        }
+        db.remove("3".to_string()).await.unwrap();
 
-        let (task_tx, _task_rx) = bounded(1);
+        let record = db
+            .get_at_latest(&"2".to_string(), Projection::Parts(vec!["vu32"]), |entry| {
+                let record = entry.value().unwrap();
+                (record.vu32, record.vbool)
+            })
+            .await
+            .unwrap();
+        assert_eq!(record, Some((Some(2), None)));
+        let removed = db
+            .get_at_latest(&"3".to_string(), Projection::All, |_| ())
+            .await
+            .unwrap();
+        assert!(removed.is_none());
 
-        let trigger = TriggerFactory::create(option.trigger_type);
-        let mem_storage: crate::DbStorage<Test> = crate::DbStorage {
-            mutable: MutableMemTable::new(&option, trigger.clone(), fs, Arc::new(TestSchema))
+        let keys = db
+            .scan_snapshot(
+                (Bound::Included(&"1".to_string()), Bound::Unbounded),
+                |scan| scan.limit(4),
+                |entry| entry.key().value.to_string(),
+            )
+            .await
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(keys, vec!["1", "2", "3", "4"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scan_batch_reader() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: Arc<DB<Test, TokioExecutor>> = Arc::new(
+            DB::new(option, TokioExecutor::default(), TestSchema)
                 .await
                 .unwrap(),
-            immutables: Default::default(),
-            compaction_tx: task_tx.clone(),
-            recover_wal_ids: None,
-            trigger,
-            record_schema: Arc::new(TestSchema),
-            option: option.clone(),
-            compaction_in_progress: std::sync::atomic::AtomicBool::new(false),
-        };
+        );
+        for item in test_items(0u32..32) {
+            db.insert(item).await.unwrap();
+        }
 
-        for (i, item) in test_items(0u32..32).enumerate() {
-            mem_storage
-                .write(LogType::Full, item, (i as u32).into())
-                .await
+        let rows = tokio::task::spawn_blocking(move || {
+            let reader = db
+                .batch_reader(
+                    &TokioExecutor::default(),
+                    (Bound::Included("10".to_string()), Bound::Unbounded),
+                    4,
+                    |scan| scan.projection(&["vu32"]),
+                )
                 .unwrap();
-        }
-        mem_storage.flush_wal().await.unwrap();
-        drop(mem_storage);
+            assert_eq!(reader.schema().fields().len(), 2);
 
-        let db: DB<Test, TokioExecutor> = DB::new(
-            option.as_ref().to_owned(),
-            TokioExecutor::default(),
-            TestSchema,
-        )
+            reader.map(|batch| batch.unwrap().num_rows()).sum::<usize>()
+        })
         .await
         .unwrap();
+        // string keys from "10" to "9"
+        assert_eq!(rows, 30);
+    }
 
-        let mut sort_items = BTreeMap::new();
-        for item in test_items(0u32..32) {
-            sort_items.insert(item.vstring.clone(), item);
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scan_explain() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .leveled_compaction(LeveledOptions::default().major_threshold_with_sst_size(10));
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        for item in test_items(0u32..10) {
+            db.insert(item).await.unwrap();
         }
-        {
-            let tx = db.transaction().await;
-            let mut scan = tx
-                .scan((Bound::Unbounded, Bound::Unbounded))
-                .take()
-                .await
-                .unwrap();
-
-            while let Some(entry) = scan.next().await.transpose().unwrap() {
-                let (_, test) = sort_items.pop_first().unwrap();
-
-                assert_eq!(entry.key().value, test.key());
-                assert_eq!(entry.value().as_ref().unwrap().vstring, test.vstring);
-                assert_eq!(entry.value().as_ref().unwrap().vu32, Some(test.vu32));
-                assert_eq!(entry.value().as_ref().unwrap().vbool, test.vbool);
-            }
+        db.flush().await.unwrap();
+        for item in test_items(10u32..20) {
+            db.insert(item).await.unwrap();
         }
+        db.flush().await.unwrap();
+
+        let txn = db.transaction().await;
+        let (lower, upper) = ("3".to_string(), "5".to_string());
+        let plan = txn
+            .scan((Bound::Included(&lower), Bound::Included(&upper)))
+            .projection(&["vu32"])
+            .explain();
+
+        // the table of "0".."9" overlaps the range, while the one of "10".."19" doesn't
+        assert_eq!(plan.levels.len(), 1);
+        assert_eq!(plan.levels[0].level, 0);
+        assert_eq!(plan.tables_read(), 1);
+        assert_eq!(plan.tables_pruned(), 1);
+        assert_eq!(plan.projection, vec!["vstring", "vu32"]);
+        assert_eq!(
+            plan.row_filters[1..],
+            [r#"(vstring) >= "3""#, r#"(vstring) <= "5""#]
+        );
+        assert!(plan.to_string().contains("level 0: 1 of 2 tables"));
     }
 
+    #[cfg(feature = "dyn-record")]
     #[tokio::test(flavor = "multi_thread")]
     async fn dyn_schema_recover() {
         let temp_dir = TempDir::new().unwrap();
@@ -1885,6 +5587,9 @@ pub(crate) mod tests {
             record_schema: dyn_schema.clone(),
             option,
             compaction_in_progress: std::sync::atomic::AtomicBool::new(false),
+            expiry: None,
+            aggregates: Default::default(),
+            watchers: Default::default(),
         };
 
         for item in test_dyn_items().into_iter() {
@@ -1973,6 +5678,7 @@ pub(crate) mod tests {
         }
     }
 
+    #[cfg(feature = "dyn-record")]
     #[tokio::test(flavor = "multi_thread")]
     async fn test_read_write_dyn() {
         let temp_dir = TempDir::new().unwrap();
@@ -2117,6 +5823,422 @@ pub(crate) mod tests {
         }
     }
 
+    #[cfg(feature = "dyn-record")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_read_write_dyn_composite_key() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let fields = [
+            DynamicField::new("tenant".into(), ArrowDataType::Utf8, false),
+            DynamicField::new("count".into(), ArrowDataType::Int32, true),
+            DynamicField::new("time".into(), ArrowDataType::Int64, false),
+        ];
+        let dyn_schema = DynSchema::with_primary_indices(&fields, &[0, 2]);
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &dyn_schema,
+        )
+        .immutable_chunk_num(1)
+        .immutable_chunk_max_num(1);
+        option.trigger_type = TriggerType::Length(5);
+
+        let db: DB<DynRecord, TokioExecutor> =
+            DB::new(option, TokioExecutor::default(), dyn_schema)
+                .await
+                .unwrap();
+
+        let key = |tenant: &str, time: i64| {
+            Value::Composite(vec![Value::String(tenant.into()), Value::Int64(time)])
+        };
+        for tenant in ["b", "a"] {
+            // written out of order, and with times whose strings sort differently
+            for time in [10_i64, 9, 2, 1] {
+                let record = DynRecord::try_with_primary_indices(
+                    vec![
+                        Value::String(tenant.into()),
+                        Value::Int32(time as i32),
+                        Value::Int64(time),
+                    ],
+                    vec![0, 2],
+                )
+                .unwrap();
+                db.write(record, 0.into()).await.unwrap();
+            }
+        }
+        db.remove(key("a", 9)).await.unwrap();
+
+        let tx = db.transaction().await;
+        let entry = tx.get(&key("b", 2), Projection::All).await.unwrap();
+        assert_eq!(entry.unwrap().get().columns[1], ValueRef::Int32(2));
+        assert!(tx
+            .get(&key("a", 9), Projection::All)
+            .await
+            .unwrap()
+            .is_none());
+
+        let lower = key("a", 2);
+        let upper = key("b", 2);
+        let mut scan = tx
+            .scan((Bound::Included(&lower), Bound::Included(&upper)))
+            .take()
+            .await
+            .unwrap();
+        let mut keys = vec![];
+        while let Some(entry) = scan.next().await.transpose().unwrap() {
+            if entry.value().is_some() {
+                keys.push(entry.key().value.clone().to_key());
+            }
+        }
+        assert_eq!(
+            keys,
+            vec![key("a", 2), key("a", 10), key("b", 1), key("b", 2)]
+        );
+    }
+
+    #[cfg(feature = "dyn-record")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_read_write_dyn_struct() {
+        use arrow::datatypes::{Field, Fields};
+
+        let temp_dir = TempDir::new().unwrap();
+
+        let address = Fields::from(vec![
+            Field::new("city", ArrowDataType::Utf8, false),
+            Field::new("zip", ArrowDataType::UInt32, true),
+        ]);
+        let fields = [
+            DynamicField::new("id".into(), ArrowDataType::Int64, false),
+            DynamicField::new(
+                "address".into(),
+                ArrowDataType::Struct(address.clone()),
+                true,
+            ),
+            DynamicField::new("name".into(), ArrowDataType::Utf8, true),
+        ];
+        let dyn_schema = DynSchema::new(&fields, 0);
+        let mut option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &dyn_schema,
+        )
+        .immutable_chunk_num(1)
+        .immutable_chunk_max_num(1);
+        option.trigger_type = TriggerType::Length(5);
+
+        let db: DB<DynRecord, TokioExecutor> =
+            DB::new(option, TokioExecutor::default(), dyn_schema)
+                .await
+                .unwrap();
+
+        let record = |id: i64| {
+            let address = if id % 2 == 0 {
+                Value::Struct(
+                    address.clone(),
+                    vec![Value::String(format!("city{id}")), Value::UInt32(id as u32)],
+                )
+            } else {
+                Value::Null
+            };
+            DynRecord::new(
+                vec![
+                    Value::Int64(id),
+                    address,
+                    Value::String(format!("name{id}")),
+                ],
+                0,
+            )
+        };
+        for id in 0..20 {
+            db.write(record(id), 0.into()).await.unwrap();
+        }
+
+        let tx = db.transaction().await;
+        let mut scan = tx
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .take()
+            .await
+            .unwrap();
+        let mut id = 0;
+        while let Some(entry) = scan.next().await.transpose().unwrap() {
+            assert_eq!(
+                entry.value().unwrap().columns,
+                record(id).as_record_ref().columns
+            );
+            id += 1;
+        }
+        assert_eq!(id, 20);
+
+        // the name comes after the leaf columns of both fields of the address
+        let mut scan = tx
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .projection(&["name"])
+            .take()
+            .await
+            .unwrap();
+        let mut id = 0;
+        while let Some(entry) = scan.next().await.transpose().unwrap() {
+            let columns = entry.value().unwrap().columns;
+            assert_eq!(columns[1], ValueRef::Null);
+            assert_eq!(columns[2], ValueRef::String(&format!("name{id}")));
+            id += 1;
+        }
+        assert_eq!(id, 20);
+    }
+
+    #[cfg(feature = "dyn-record")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_dyn_add_nullable_column() {
+        use crate::{error::ErrorKind, version::schema::SchemaMismatch};
+
+        let temp_dir = TempDir::new().unwrap();
+        let schema = |fields: &[(&str, ArrowDataType, bool)]| {
+            let fields = fields
+                .iter()
+                .map(|(name, data_type, nullable)| {
+                    DynamicField::new(name.to_string(), data_type.clone(), *nullable)
+                })
+                .collect::<Vec<_>>();
+            DynSchema::new(&fields, 0)
+        };
+        let stored = [
+            ("id", ArrowDataType::Int64, false),
+            ("name", ArrowDataType::Utf8, true),
+        ];
+        let added = [
+            ("id", ArrowDataType::Int64, false),
+            ("name", ArrowDataType::Utf8, true),
+            ("age", ArrowDataType::UInt8, true),
+        ];
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &schema(&stored),
+        );
+
+        {
+            let db: DB<DynRecord, TokioExecutor> =
+                DB::new(option.clone(), TokioExecutor::default(), schema(&stored))
+                    .await
+                    .unwrap();
+            for id in 0..15 {
+                let record = DynRecord::new(
+                    vec![Value::Int64(id), Value::String(format!("name{id}"))],
+                    0,
+                );
+                db.write(record, 0.into()).await.unwrap();
+                // the last records are only in the WAL
+                if id == 9 {
+                    db.flush().await.unwrap();
+                }
+            }
+            db.flush_wal().await.unwrap();
+        }
+
+        let db: DB<DynRecord, TokioExecutor> =
+            DB::new(option.clone(), TokioExecutor::default(), schema(&added))
+                .await
+                .unwrap();
+        let version = db.ctx.manifest().current().await;
+        assert_eq!(version.schema().unwrap().version(), 1);
+        for id in 15..20 {
+            let record = DynRecord::new(
+                vec![
+                    Value::Int64(id),
+                    Value::String(format!("name{id}")),
+                    Value::UInt8(id as u8),
+                ],
+                0,
+            );
+            db.write(record, 0.into()).await.unwrap();
+        }
+
+        let age = db
+            .get(&Value::Int64(3), |entry| {
+                Some(entry.get().columns[2] == ValueRef::Null)
+            })
+            .await
+            .unwrap();
+        assert_eq!(age, Some(true));
+
+        let tx = db.transaction().await;
+        let mut scan = tx
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .take()
+            .await
+            .unwrap();
+        let mut id = 0;
+        while let Some(entry) = scan.next().await.transpose().unwrap() {
+            let columns = entry.value().unwrap().columns;
+            assert_eq!(columns[0], ValueRef::Int64(id));
+            assert_eq!(columns[1], ValueRef::String(&format!("name{id}")));
+            if id < 15 {
+                assert_eq!(columns[2], ValueRef::Null);
+            } else {
+                assert_eq!(columns[2], ValueRef::UInt8(id as u8));
+            }
+            id += 1;
+        }
+        assert_eq!(id, 20);
+        drop(scan);
+        drop(tx);
+        drop(db);
+
+        // a column of the stored schema can not be removed
+        let err = DB::<DynRecord, TokioExecutor>::new(
+            option.clone(),
+            TokioExecutor::default(),
+            schema(&stored[..1]),
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(
+            err,
+            DbError::SchemaMismatch(SchemaMismatch::MissingColumn(ref name)) if name == "name"
+        ));
+        assert_eq!(err.kind(), ErrorKind::InvalidArgument);
+
+        // nor can the primary key change
+        let fields = added
+            .iter()
+            .map(|(name, data_type, nullable)| {
+                DynamicField::new(name.to_string(), data_type.clone(), *nullable)
+            })
+            .collect::<Vec<_>>();
+        let err = DB::<DynRecord, TokioExecutor>::new(
+            option,
+            TokioExecutor::default(),
+            DynSchema::new(&fields, 2),
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(
+            err,
+            DbError::SchemaMismatch(SchemaMismatch::PrimaryKey { ref stored, ref found })
+                if stored == &["id"] && found == &["age"]
+        ));
+    }
+
+    #[cfg(feature = "dyn-record")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_dyn_rename_and_drop_column() {
+        use crate::version::schema::SchemaMismatch;
+
+        let temp_dir = TempDir::new().unwrap();
+        let schema = |fields: &[(&str, ArrowDataType)]| {
+            let fields = fields
+                .iter()
+                .map(|(name, data_type)| {
+                    DynamicField::new(name.to_string(), data_type.clone(), *name != "id")
+                })
+                .collect::<Vec<_>>();
+            DynSchema::new(&fields, 0)
+        };
+        let stored = [
+            ("id", ArrowDataType::Int64),
+            ("name", ArrowDataType::Utf8),
+            ("age", ArrowDataType::UInt8),
+        ];
+        let changed = [
+            ("id", ArrowDataType::Int64),
+            ("nickname", ArrowDataType::Utf8),
+            ("email", ArrowDataType::Utf8),
+        ];
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &schema(&stored),
+        );
+        let changed_option = option
+            .clone()
+            .rename_column("name", "nickname")
+            .drop_column("age");
+
+        {
+            let db: DB<DynRecord, TokioExecutor> =
+                DB::new(option.clone(), TokioExecutor::default(), schema(&stored))
+                    .await
+                    .unwrap();
+            for id in 0..12 {
+                let record = DynRecord::new(
+                    vec![
+                        Value::Int64(id),
+                        Value::String(format!("name{id}")),
+                        Value::UInt8(id as u8),
+                    ],
+                    0,
+                );
+                db.write(record, 0.into()).await.unwrap();
+                // the last records are only in the WAL
+                if id == 9 {
+                    db.flush().await.unwrap();
+                }
+            }
+            db.flush_wal().await.unwrap();
+        }
+
+        // the records of the WAL still hold `age`
+        let err = DB::<DynRecord, TokioExecutor>::new(
+            changed_option.clone(),
+            TokioExecutor::default(),
+            schema(&changed),
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(
+            err,
+            DbError::SchemaMismatch(SchemaMismatch::UnflushedDrop(ref name)) if name == "age"
+        ));
+
+        {
+            let db: DB<DynRecord, TokioExecutor> =
+                DB::new(option, TokioExecutor::default(), schema(&stored))
+                    .await
+                    .unwrap();
+            db.flush().await.unwrap();
+        }
+
+        let db: DB<DynRecord, TokioExecutor> =
+            DB::new(changed_option, TokioExecutor::default(), schema(&changed))
+                .await
+                .unwrap();
+        let version = db.ctx.manifest().current().await;
+        assert_eq!(version.schema().unwrap().version(), 1);
+        for id in 12..15 {
+            let record = DynRecord::new(
+                vec![
+                    Value::Int64(id),
+                    Value::String(format!("name{id}")),
+                    Value::String(format!("{id}@tonbo.io")),
+                ],
+                0,
+            );
+            db.write(record, 0.into()).await.unwrap();
+        }
+
+        let tx = db.transaction().await;
+        let mut scan = tx
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .take()
+            .await
+            .unwrap();
+        let mut id = 0;
+        while let Some(entry) = scan.next().await.transpose().unwrap() {
+            let columns = entry.value().unwrap().columns;
+            assert_eq!(columns.len(), 3);
+            assert_eq!(columns[0], ValueRef::Int64(id));
+            assert_eq!(columns[1], ValueRef::String(&format!("name{id}")));
+            if id < 12 {
+                // the tables were written before `email` was added, not with `age` in its place
+                assert_eq!(columns[2], ValueRef::Null);
+            } else {
+                assert_eq!(columns[2], ValueRef::String(&format!("{id}@tonbo.io")));
+            }
+            id += 1;
+        }
+        assert_eq!(id, 15);
+    }
+
+    #[cfg(feature = "dyn-record")]
     #[tokio::test(flavor = "multi_thread")]
     async fn test_dyn_multiple_db() {
         let temp_dir1 = TempDir::with_prefix("db1").unwrap();
@@ -9,6 +9,7 @@ use fusio::{MaybeSend, MaybeSync};
 use thiserror::Error;
 
 use crate::{
+    error::ErrorKind,
     ondisk::sstable::SsTableID,
     record::{Record, Schema},
     version::{edit::VersionEdit, error::VersionError, TransactionTs, VersionRef},
@@ -20,6 +21,14 @@ pub enum ManifestStorageError {
     Version(#[from] VersionError),
 }
 
+impl ManifestStorageError {
+    pub(crate) fn kind(&self) -> ErrorKind {
+        match self {
+            ManifestStorageError::Version(err) => err.kind(),
+        }
+    }
+}
+
 /// Trait for storing and managing LSM-tree manifest
 ///
 /// The `ManifestStorage` trait provides an interface for managing LSM-tree manifest
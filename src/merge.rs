@@ -0,0 +1,86 @@
+//! Read-modify-write updates of records, set with
+//! [`DbOption::merge_operator`](crate::DbOption::merge_operator)
+//!
+//! [`DB::merge`](crate::DB::merge) writes an operand for the latest record of its key, e.g. to
+//! add to a counter or to extend a set, without the caller reading the record and committing a
+//! transaction for every update:
+//!
+//! ```ignore
+//! struct AddCount;
+//!
+//! impl MergeOperator<Counter> for AddCount {
+//!     fn merge(&self, existing: Option<CounterRef<'_>>, operand: CounterRef<'_>) -> Option<Counter> {
+//!         let count = existing.and_then(|counter| counter.count).unwrap_or(0);
+//!         Some(Counter {
+//!             name: operand.name.to_string(),
+//!             count: count + operand.count.unwrap_or(0),
+//!         })
+//!     }
+//! }
+//!
+//! db.merge(Counter { name: "visits".into(), count: 1 }).await?;
+//! ```
+//!
+//! The operands are stored as entries of their own in the mutable memtable, next to the records
+//! and removals, and the merge does not read anything. Reads fold the operands of a key into its
+//! latest record older than them, and they are folded into records for good when the memtable is
+//! frozen.
+
+use std::sync::Arc;
+
+use crate::{
+    record::{KeyRef, Record, Schema},
+    DbError,
+};
+
+/// Folds an operand into the latest record of its key, see [`DB::merge`](crate::DB::merge)
+///
+/// The operands of a key are folded one at a time, oldest first, so every operand is folded into
+/// the result of the ones before it. A plain insert or removal of the key replaces the operands
+/// older than it. An operand is folded by every read of its key until its memtable is frozen, so
+/// the operator must return the same record for the same arguments.
+pub trait MergeOperator<R>: Send + Sync + 'static
+where
+    R: Record,
+{
+    /// The record of the key of `operand` after applying `operand` to its latest record
+    /// `existing`, `None` if the key has no record. Returning `None` removes the key.
+    ///
+    /// Reads with a projection pass only the projected columns of `existing`, so the projected
+    /// columns of the result must not depend on the other ones.
+    fn merge(&self, existing: Option<R::Ref<'_>>, operand: R::Ref<'_>) -> Option<R>;
+}
+
+pub(crate) type DynMergeOperator<R> = Arc<dyn MergeOperator<R>>;
+
+/// Folds `operands` of `key`, oldest first, into its record `existing`, failing if `operator`
+/// returns a record of another key
+pub(crate) fn fold<'r, R>(
+    operator: &dyn MergeOperator<R>,
+    key: &<R::Schema as Schema>::Key,
+    existing: Option<R::Ref<'_>>,
+    operands: impl IntoIterator<Item = R::Ref<'r>>,
+) -> Result<Option<R>, DbError>
+where
+    R: Record,
+{
+    let mut operands = operands.into_iter();
+    let mut merged = match operands.next() {
+        Some(operand) => operator.merge(existing, operand),
+        None => return Ok(None),
+    };
+    loop {
+        if merged
+            .as_ref()
+            .is_some_and(|record| record.key().to_key() != *key)
+        {
+            return Err(DbError::MergeOperatorKey);
+        }
+        match operands.next() {
+            Some(operand) => {
+                merged = operator.merge(merged.as_ref().map(R::as_record_ref), operand)
+            }
+            None => return Ok(merged),
+        }
+    }
+}
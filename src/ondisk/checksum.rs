@@ -0,0 +1,250 @@
+use std::{mem, ops::Bound, pin::pin};
+
+use fusio::{IoBuf, Write};
+use fusio_log::Encode;
+use futures_util::StreamExt;
+use parquet::{arrow::ProjectionMask, file::metadata::KeyValue};
+
+use crate::{
+    compaction::error::CompactionError,
+    context::Context,
+    fs::{FileId, FileType},
+    ondisk::sstable::SsTable,
+    record::{Record, Schema},
+    version::timestamp::Ts,
+    DbOption,
+};
+
+/// Key of the record checksum in the key-value metadata of an SSTable
+pub(crate) const RECORD_CHECKSUM_KEY: &str = "tonbo.record_crc32";
+
+/// CRC32 over the records of an SSTable in the order they are written, see
+/// [`DbOption::record_checksums`]
+///
+/// A record is hashed in its WAL encoding: its key and timestamp, then its value, which is
+/// `None` for a removal.
+pub(crate) struct RecordChecksum {
+    // `None` unless checksums are enabled
+    hasher: Option<crc32fast::Hasher>,
+}
+
+impl RecordChecksum {
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self {
+            hasher: enabled.then(crc32fast::Hasher::new),
+        }
+    }
+
+    pub(crate) async fn update<K, V>(
+        &mut self,
+        key: &Ts<K>,
+        value: &Option<V>,
+    ) -> Result<(), fusio::Error>
+    where
+        K: Encode + Sync,
+        V: Encode + Sync,
+    {
+        let Some(hasher) = &mut self.hasher else {
+            return Ok(());
+        };
+        let mut writer = HashWriter(hasher);
+        key.encode(&mut writer).await?;
+        value.encode(&mut writer).await
+    }
+
+    /// Metadata entry of the checksum of the records so far, after which it starts over
+    pub(crate) fn take(&mut self) -> Option<KeyValue> {
+        let crc = mem::take(self.hasher.as_mut()?).finalize();
+
+        Some(KeyValue::new(
+            RECORD_CHECKSUM_KEY.to_string(),
+            crc.to_string(),
+        ))
+    }
+}
+
+/// Check the records of the SSTable `gen` of `level` against the checksum in its footer, which
+/// passes tables without a checksum
+pub(crate) async fn verify_table<R>(
+    option: &DbOption,
+    ctx: &Context<R>,
+    schema: &R::Schema,
+    gen: FileId,
+    level: usize,
+) -> Result<(), CompactionError<R>>
+where
+    R: Record,
+{
+    let (fs, path) = ctx.manager.table(option, gen, level);
+    let metadata = SsTable::<R>::open(
+        ctx.parquet_lru.clone(),
+        gen,
+        fs.open_options(&path, FileType::Parquet.open_options(true))
+            .await?,
+    )
+    .await?
+    .metadata()
+    .await?;
+    let Some(expected) = metadata
+        .file_metadata()
+        .key_value_metadata()
+        .and_then(|metadata| metadata.iter().find(|kv| kv.key == RECORD_CHECKSUM_KEY))
+    else {
+        return Ok(());
+    };
+
+    let mut rows = pin!(
+        SsTable::<R>::open(
+            ctx.parquet_lru.clone(),
+            gen,
+            fs.open_options(&path, FileType::Parquet.open_options(true))
+                .await?,
+        )
+        .await?
+        .scan(
+            (Bound::Unbounded, Bound::Unbounded),
+            u32::MAX.into(),
+            None,
+            ProjectionMask::all(),
+            None,
+            schema.primary_key_indices(),
+        )
+        .await?
+    );
+    let mut checksum = RecordChecksum::new(true);
+    while let Some(row) = rows.next().await {
+        let row = row?;
+        checksum.update(&row.internal_key(), &row.get()).await?;
+    }
+
+    match checksum.take() {
+        Some(actual) if actual.value == expected.value => Ok(()),
+        _ => Err(CompactionError::Checksum { level, gen }),
+    }
+}
+
+/// Writer that only feeds what is written into a hasher
+struct HashWriter<'a>(&'a mut crc32fast::Hasher);
+
+impl Write for HashWriter<'_> {
+    async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), fusio::Error>, B) {
+        self.0.update(buf.as_slice());
+        (Ok(()), buf)
+    }
+
+    async fn flush(&mut self) -> Result<(), fusio::Error> {
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), fusio::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use fusio::path::Path;
+    use fusio_parquet::writer::AsyncWriter;
+    use parquet::{
+        arrow::AsyncArrowWriter,
+        file::{metadata::KeyValue, properties::WriterProperties},
+    };
+    use tempfile::TempDir;
+
+    use super::{verify_table, RECORD_CHECKSUM_KEY};
+    use crate::{
+        compaction::error::CompactionError,
+        executor::tokio::TokioExecutor,
+        fs::FileType,
+        inmem::immutable::tests::TestSchema,
+        ondisk::sstable::SsTable,
+        record::{
+            test::{get_test_record_batch, test_items},
+            Schema,
+        },
+        tests::Test,
+        DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn verify_record_checksums() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .record_checksums(true)
+        .paranoid_checks(true);
+        let db: DB<Test, TokioExecutor> =
+            DB::new(option.clone(), TokioExecutor::default(), TestSchema)
+                .await
+                .unwrap();
+        for item in test_items(0u32..10) {
+            db.insert(item).await.unwrap();
+        }
+        db.remove("3".to_string()).await.unwrap();
+        db.flush().await.unwrap();
+
+        let gen = db.ctx.manifest().current().await.level_slice[0][0].gen;
+        let (fs, path) = db.ctx.manager.table(&option, gen, 0);
+        let metadata = SsTable::<Test>::open(
+            db.ctx.parquet_lru.clone(),
+            gen,
+            fs.open_options(&path, FileType::Parquet.open_options(true))
+                .await
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .metadata()
+        .await
+        .unwrap();
+        assert!(metadata
+            .file_metadata()
+            .key_value_metadata()
+            .unwrap()
+            .iter()
+            .any(|kv| kv.key == RECORD_CHECKSUM_KEY));
+        verify_table(&option, &db.ctx, &TestSchema, gen, 0)
+            .await
+            .unwrap();
+
+        // a table whose records differ from the ones it was written with
+        let other_dir = TempDir::new().unwrap();
+        let batch = get_test_record_batch::<TokioExecutor>(
+            DbOption::new(
+                Path::from_filesystem_path(other_dir.path()).unwrap(),
+                &TestSchema,
+            ),
+            TokioExecutor::default(),
+        )
+        .await;
+        let corrupted = option.generate_file_id();
+        let (fs, path) = db.ctx.manager.table(&option, corrupted, 0);
+        let mut writer = AsyncArrowWriter::try_new(
+            AsyncWriter::new(
+                fs.open_options(&path, FileType::Parquet.open_options(false))
+                    .await
+                    .unwrap(),
+            ),
+            TestSchema.arrow_schema().clone(),
+            Some(
+                WriterProperties::builder()
+                    .set_key_value_metadata(Some(vec![KeyValue::new(
+                        RECORD_CHECKSUM_KEY.to_string(),
+                        "0".to_string(),
+                    )]))
+                    .build(),
+            ),
+        )
+        .unwrap();
+        writer.write(&batch).await.unwrap();
+        writer.close().await.unwrap();
+
+        let result = verify_table(&option, &db.ctx, &TestSchema, corrupted, 0).await;
+        assert!(matches!(
+            result,
+            Err(CompactionError::Checksum { level: 0, gen }) if gen == corrupted
+        ));
+    }
+}
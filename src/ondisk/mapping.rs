@@ -0,0 +1,203 @@
+use std::sync::Arc;
+
+use arrow::{
+    array::{new_null_array, RecordBatch},
+    datatypes::{Schema as ArrowSchema, SchemaRef},
+    error::ArrowError,
+};
+use parquet::{arrow::ProjectionMask, schema::types::SchemaDescriptor};
+
+use crate::{record::leaf_count, version::schema::field_id};
+
+/// Columns of an SSTable matched to the columns of the schema of the DB by their field ids, see
+/// [`SchemaVersion`](crate::version::schema::SchemaVersion)
+///
+/// The columns of a table written without field ids have the ids of their positions. Columns of
+/// the schema that the table does not have are read as nulls, and columns of the table that were
+/// dropped from the schema are not read.
+#[derive(Debug)]
+pub(crate) struct ColumnMapping {
+    // index of the column of the table of each column of the schema
+    columns: Vec<Option<usize>>,
+    // leaves of the table the projection of the schema reads
+    projection_mask: ProjectionMask,
+    // projected columns of the schema, and the column of the read record batches of each
+    schema: SchemaRef,
+    sources: Vec<Option<usize>>,
+}
+
+impl ColumnMapping {
+    /// Mapping of the columns of a table of `table_schema` to `schema`, with the leaf columns of
+    /// `projection_mask` over the leaves of `schema`, `None` if the table has the columns of
+    /// `schema`
+    pub(crate) fn new(
+        table_schema: &ArrowSchema,
+        table_descriptor: &SchemaDescriptor,
+        schema: &ArrowSchema,
+        projection_mask: &ProjectionMask,
+    ) -> Option<Self> {
+        let ids = |schema: &ArrowSchema| {
+            schema
+                .fields()
+                .iter()
+                .enumerate()
+                .map(|(i, field)| field_id(field).unwrap_or(i as u32))
+                .collect::<Vec<_>>()
+        };
+        let (table_ids, schema_ids) = (ids(table_schema), ids(schema));
+        let same_names = table_schema
+            .fields()
+            .iter()
+            .zip(schema.fields())
+            .all(|(table, field)| table.name() == field.name());
+        if table_ids == schema_ids && same_names {
+            return None;
+        }
+
+        let columns = schema_ids
+            .iter()
+            .map(|id| table_ids.iter().position(|table_id| table_id == id))
+            .collect::<Vec<_>>();
+        let leaves = |schema: &ArrowSchema| {
+            let mut leaf = 0;
+            schema
+                .fields()
+                .iter()
+                .map(|field| {
+                    let start = leaf;
+                    leaf += leaf_count(field.data_type());
+                    start..leaf
+                })
+                .collect::<Vec<_>>()
+        };
+        let (table_leaves, schema_leaves) = (leaves(table_schema), leaves(schema));
+        let projected = schema_leaves
+            .into_iter()
+            .map(|mut leaves| leaves.any(|leaf| projection_mask.leaf_included(leaf)))
+            .collect::<Vec<_>>();
+
+        // the record batches hold the projected columns of the table in the order of the table
+        let mut read = columns
+            .iter()
+            .zip(&projected)
+            .filter_map(|(column, projected)| column.filter(|_| *projected))
+            .collect::<Vec<_>>();
+        read.sort_unstable();
+        let (mut fields, mut sources) = (Vec::new(), Vec::new());
+        for ((field, column), projected) in schema.fields().iter().zip(&columns).zip(&projected) {
+            if *projected {
+                fields.push(field.clone());
+                sources.push(column.and_then(|column| read.binary_search(&column).ok()));
+            }
+        }
+
+        Some(Self {
+            columns,
+            projection_mask: ProjectionMask::leaves(
+                table_descriptor,
+                read.iter().flat_map(|column| table_leaves[*column].clone()),
+            ),
+            schema: Arc::new(ArrowSchema::new_with_metadata(
+                fields,
+                schema.metadata().clone(),
+            )),
+            sources,
+        })
+    }
+
+    /// Leaves of the table to read
+    pub(crate) fn projection_mask(&self) -> ProjectionMask {
+        self.projection_mask.clone()
+    }
+
+    /// Indices of the columns of the table of the columns of the schema at `indices`, which the
+    /// table has
+    pub(crate) fn table_indices(&self, indices: &[usize]) -> Vec<usize> {
+        indices
+            .iter()
+            .filter_map(|index| self.columns[*index])
+            .collect()
+    }
+
+    /// The projected columns of the schema of a `record_batch` read from the table
+    pub(crate) fn apply(&self, record_batch: RecordBatch) -> Result<RecordBatch, ArrowError> {
+        let rows = record_batch.num_rows();
+        let columns = self
+            .sources
+            .iter()
+            .zip(self.schema.fields())
+            .map(|(source, field)| match source {
+                Some(source) => record_batch.column(*source).clone(),
+                None => new_null_array(field.data_type(), rows),
+            })
+            .collect::<Vec<_>>();
+
+        RecordBatch::try_new(self.schema.clone(), columns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use arrow::{
+        array::{Array, AsArray, Int64Array, RecordBatch, StringArray},
+        datatypes::{DataType, Field, Int64Type, Schema as ArrowSchema},
+    };
+    use parquet::arrow::{ArrowSchemaConverter, ProjectionMask, PARQUET_FIELD_ID_META_KEY};
+
+    use super::ColumnMapping;
+
+    fn field(name: &str, data_type: DataType, id: u32) -> Field {
+        Field::new(name, data_type, true).with_metadata(HashMap::from([(
+            PARQUET_FIELD_ID_META_KEY.to_string(),
+            id.to_string(),
+        )]))
+    }
+
+    #[test]
+    fn map_by_field_id() {
+        // `a` was dropped, `b` renamed to `c`, and `d` added
+        let table_schema = ArrowSchema::new(vec![
+            field("id", DataType::Int64, 0),
+            field("a", DataType::Int64, 1),
+            field("b", DataType::Utf8, 2),
+        ]);
+        let schema = ArrowSchema::new(vec![
+            field("id", DataType::Int64, 0),
+            field("c", DataType::Utf8, 2),
+            field("d", DataType::Int64, 3),
+        ]);
+        let descriptor = ArrowSchemaConverter::new().convert(&table_schema).unwrap();
+        let mapping =
+            ColumnMapping::new(&table_schema, &descriptor, &schema, &ProjectionMask::all())
+                .unwrap();
+
+        assert_eq!(mapping.table_indices(&[0, 1, 2]), vec![0, 2]);
+        let projection_mask = mapping.projection_mask();
+        assert!(projection_mask.leaf_included(0));
+        assert!(!projection_mask.leaf_included(1));
+        assert!(projection_mask.leaf_included(2));
+
+        let read = RecordBatch::try_new(
+            Arc::new(table_schema.project(&[0, 2]).unwrap()),
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2])),
+                Arc::new(StringArray::from(vec!["x", "y"])),
+            ],
+        )
+        .unwrap();
+        let batch = mapping.apply(read).unwrap();
+        assert_eq!(batch.schema().field(1).name(), "c");
+        assert_eq!(
+            &batch.column(0).as_primitive::<Int64Type>().values()[..],
+            &[1, 2]
+        );
+        assert_eq!(batch.column(1).as_string::<i32>().value(1), "y");
+        assert_eq!(batch.column(2).null_count(), 2);
+
+        assert!(
+            ColumnMapping::new(&schema, &descriptor, &schema, &ProjectionMask::all()).is_none()
+        );
+    }
+}
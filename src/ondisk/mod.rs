@@ -1,3 +1,6 @@
 mod arrows;
+pub(crate) mod checksum;
+pub(crate) mod mapping;
+pub(crate) mod prefix_bloom;
 pub(crate) mod scan;
 pub(crate) mod sstable;
@@ -0,0 +1,150 @@
+use arrow::{
+    array::{Array, AsArray},
+    datatypes::DataType,
+};
+use parquet::file::metadata::KeyValue;
+
+/// Key of the prefix bloom filter in the key-value metadata of an SSTable
+pub(crate) const PREFIX_BLOOM_KEY: &str = "tonbo.prefix_bloom";
+
+// Bits set for each prefix
+const HASHES: u64 = 7;
+// Bits per distinct prefix, which rules out about 99% of the absent prefixes with `HASHES` bits
+const BITS_PER_PREFIX: usize = 10;
+// Initial value of the second hash of a prefix
+const SECOND_HASH_SEED: u32 = 0x9e37_79b9;
+
+/// Bloom filter over the leading bytes of the keys of an SSTable, see
+/// [`DbOption::prefix_bloom_filter`](crate::DbOption::prefix_bloom_filter)
+///
+/// The bytes are those of the first primary key column. Keys shorter than the prefix length are
+/// left out, as only a prefix at least that long can be looked up. A prefix is hashed twice with
+/// CRC32, whose values do not change between releases, unlike those of the hashers of the
+/// standard library, and its bits are derived from both hashes.
+pub(crate) struct PrefixBloom {
+    len: usize,
+    bits: Vec<u64>,
+}
+
+impl PrefixBloom {
+    /// Filter over the prefixes of `len` bytes of the values of the primary key column `column`,
+    /// `None` unless it is a string or binary column
+    pub(crate) fn build(column: &dyn Array, len: usize) -> Option<Self> {
+        let values: Box<dyn Iterator<Item = Option<&[u8]>>> = match column.data_type() {
+            DataType::Utf8 => Box::new(
+                column
+                    .as_string::<i32>()
+                    .iter()
+                    .map(|v| v.map(str::as_bytes)),
+            ),
+            DataType::LargeUtf8 => Box::new(
+                column
+                    .as_string::<i64>()
+                    .iter()
+                    .map(|v| v.map(str::as_bytes)),
+            ),
+            DataType::Binary => Box::new(column.as_binary::<i32>().iter()),
+            DataType::LargeBinary => Box::new(column.as_binary::<i64>().iter()),
+            _ => return None,
+        };
+        let mut prefixes = values
+            .flatten()
+            .filter(|value| value.len() >= len)
+            .map(|value| &value[..len])
+            .collect::<Vec<_>>();
+        // the rows are sorted by key, so equal prefixes are next to each other
+        prefixes.dedup();
+
+        let words = (prefixes.len() * BITS_PER_PREFIX).div_ceil(64).max(1);
+        let mut bloom = PrefixBloom {
+            len,
+            bits: vec![0; words],
+        };
+        for prefix in prefixes {
+            for bit in bloom.bits_of(prefix) {
+                bloom.bits[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        Some(bloom)
+    }
+
+    /// Whether the table may hold a key that starts with `prefix`, which it always may if
+    /// `prefix` is shorter than the prefixes of the filter
+    pub(crate) fn may_contain(&self, prefix: &[u8]) -> bool {
+        if prefix.len() < self.len {
+            return true;
+        }
+        self.bits_of(&prefix[..self.len])
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    fn bits_of(&self, prefix: &[u8]) -> impl Iterator<Item = usize> {
+        let first = crc32fast::hash(prefix) as u64;
+        let mut hasher = crc32fast::Hasher::new_with_initial(SECOND_HASH_SEED);
+        hasher.update(prefix);
+        // an odd step visits distinct bits unless the filter is smaller than `HASHES` bits
+        let second = hasher.finalize() as u64 | 1;
+        let len = self.bits.len() as u64 * 64;
+
+        (0..HASHES).map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % len) as usize)
+    }
+
+    /// Metadata entry of the filter, its prefix length and its bits in hex
+    pub(crate) fn to_key_value(&self) -> KeyValue {
+        let bits = self
+            .bits
+            .iter()
+            .map(|word| format!("{word:016x}"))
+            .collect::<String>();
+
+        KeyValue::new(PREFIX_BLOOM_KEY.to_string(), format!("{}:{bits}", self.len))
+    }
+
+    /// Filter in the key-value metadata of the footer of a table, `None` if the table was
+    /// written without one
+    pub(crate) fn from_metadata(metadata: Option<&Vec<KeyValue>>) -> Option<Self> {
+        let value = metadata?
+            .iter()
+            .find(|kv| kv.key == PREFIX_BLOOM_KEY)?
+            .value
+            .as_deref()?;
+        let (len, bits) = value.split_once(':')?;
+        if bits.is_empty() || bits.len() % 16 != 0 {
+            return None;
+        }
+        let bits = (0..bits.len())
+            .step_by(16)
+            .map(|i| u64::from_str_radix(bits.get(i..i + 16)?, 16).ok())
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(PrefixBloom {
+            len: len.parse().ok()?,
+            bits,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{Int32Array, StringArray};
+
+    use super::PrefixBloom;
+
+    #[test]
+    fn prefix_bloom() {
+        let keys = StringArray::from(vec!["a", "user:1:a", "user:1:b", "user:2:a", "user:3:a"]);
+        let bloom = PrefixBloom::build(&keys, 7).unwrap();
+
+        assert!(bloom.may_contain(b"user:1:"));
+        assert!(bloom.may_contain(b"user:2:a"));
+        assert!(!bloom.may_contain(b"user:4:"));
+        assert!(!bloom.may_contain(b"group:1"));
+        // too short to look up
+        assert!(bloom.may_contain(b"user:"));
+        assert!(PrefixBloom::build(&Int32Array::from(vec![1, 2]), 1).is_none());
+
+        let decoded = PrefixBloom::from_metadata(Some(&vec![bloom.to_key_value()])).unwrap();
+        assert_eq!(decoded.len, bloom.len);
+        assert_eq!(decoded.bits, bloom.bits);
+    }
+}
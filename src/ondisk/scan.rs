@@ -7,16 +7,23 @@ use std::{
 
 use arrow::datatypes::Schema;
 use futures_core::{ready, Stream};
-use parquet::arrow::{
-    async_reader::{AsyncFileReader, ParquetRecordBatchStream},
-    ProjectionMask,
+use parquet::{
+    arrow::{
+        async_reader::{AsyncFileReader, ParquetRecordBatchStream},
+        ProjectionMask,
+    },
+    errors::ParquetError,
 };
 use pin_project_lite::pin_project;
 
+use super::mapping::ColumnMapping;
 use crate::{
     option::Order,
     record::Record,
-    stream::record_batch::{RecordBatchEntry, RecordBatchIterator},
+    stream::{
+        memory::MemoryBudget,
+        record_batch::{RecordBatchEntry, RecordBatchIterator},
+    },
 };
 
 pin_project! {
@@ -28,6 +35,8 @@ pin_project! {
         projection_mask: ProjectionMask,
         full_schema: Arc<Schema>,
         order: Option<Order>,
+        memory_budget: Option<MemoryBudget>,
+        mapping: Option<ColumnMapping>,
         _marker: PhantomData<&'scan ()>
     }
 }
@@ -45,16 +54,31 @@ impl<R> SsTableScan<'_, R> {
             projection_mask,
             full_schema,
             order,
+            memory_budget: None,
+            mapping: None,
             _marker: PhantomData,
         }
     }
+
+    /// Charge the decoded record batches to `memory_budget`
+    pub(crate) fn memory_budget(self, memory_budget: Option<MemoryBudget>) -> Self {
+        Self {
+            memory_budget,
+            ..self
+        }
+    }
+
+    /// Map the columns of the decoded record batches to the schema of the DB with `mapping`
+    pub(crate) fn mapping(self, mapping: Option<ColumnMapping>) -> Self {
+        Self { mapping, ..self }
+    }
 }
 
 impl<R> Stream for SsTableScan<'_, R>
 where
     R: Record,
 {
-    type Item = Result<RecordBatchEntry<R>, parquet::errors::ParquetError>;
+    type Item = Result<RecordBatchEntry<R>, ParquetError>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let mut this = self.project();
@@ -72,12 +96,28 @@ where
                         Some(record_batch) => record_batch,
                         None => return Poll::Ready(None),
                     };
-                    *this.iter = Some(RecordBatchIterator::new(
+                    let record_batch = match this.mapping {
+                        Some(mapping) => mapping.apply(record_batch)?,
+                        None => record_batch,
+                    };
+                    let reservation = match this.memory_budget {
+                        Some(budget) => Some(
+                            budget
+                                .reserve(record_batch.get_array_memory_size())
+                                .map_err(|err| ParquetError::External(Box::new(err)))?,
+                        ),
+                        None => None,
+                    };
+                    let iter = RecordBatchIterator::new(
                         record_batch,
                         this.projection_mask.clone(),
                         this.full_schema.clone(),
                         *this.order,
-                    ));
+                    );
+                    *this.iter = Some(match reservation {
+                        Some(reservation) => iter.reservation(reservation),
+                        None => iter,
+                    });
                 }
             }
         }
@@ -1,25 +1,37 @@
 use std::{marker::PhantomData, ops::Bound, sync::Arc};
 
+use arrow::{
+    array::{Array, ArrayRef, AsArray, BooleanArray, Datum},
+    compute::kernels::cmp::{gt, lt},
+    datatypes::{
+        DataType, Int16Type, Int32Type, Int64Type, Int8Type, Schema as ArrowSchema, UInt16Type,
+        UInt32Type, UInt64Type, UInt8Type,
+    },
+    error::ArrowError,
+};
 use fusio::{dynamic::DynFile, DynRead};
 use fusio_parquet::reader::AsyncReader;
 use futures_util::StreamExt;
 use parquet::{
     arrow::{
-        arrow_reader::{ArrowReaderBuilder, ArrowReaderOptions},
+        arrow_reader::{statistics::StatisticsConverter, ArrowReaderBuilder, ArrowReaderOptions},
         async_reader::{AsyncFileReader, AsyncReader as ParquetAsyncReader},
         ParquetRecordBatchStreamBuilder, ProjectionMask,
     },
     errors::Result as ParquetResult,
+    file::metadata::ParquetMetaData,
 };
 use parquet_lru::{BoxedFileReader, DynLruCache};
 use ulid::Ulid;
 
-use super::{arrows::get_range_filter, scan::SsTableScan};
+use super::{
+    arrows::get_range_filter, mapping::ColumnMapping, prefix_bloom::PrefixBloom, scan::SsTableScan,
+};
 use crate::{
     fs::FileId,
-    option::Order,
-    record::{Record, Schema},
-    stream::record_batch::RecordBatchEntry,
+    option::{Order, ReadHint},
+    record::{Key, Record, Schema},
+    stream::{memory::MemoryBudget, record_batch::RecordBatchEntry},
     version::timestamp::{Timestamp, TsRef},
 };
 
@@ -50,6 +62,11 @@ where
     R: Record,
 {
     reader: BoxedFileReader,
+    read_hint: Option<ReadHint>,
+    memory_budget: Option<MemoryBudget>,
+    bloom_filters: bool,
+    prefix: Option<Arc<[u8]>>,
+    schema: Option<Arc<ArrowSchema>>,
     _marker: PhantomData<R>,
 }
 
@@ -64,21 +81,75 @@ where
     ) -> Result<Self, fusio::Error> {
         let size = file.size().await?;
 
-        Ok(SsTable {
-            reader: lru_cache
-                .get_reader(
-                    id,
-                    BoxedFileReader::new(AsyncReader::new(file, size).await?),
-                )
-                .await,
+        Ok(Self::from_reader(
+            lru_cache,
+            id,
+            BoxedFileReader::new(AsyncReader::new(file, size).await?),
+        )
+        .await)
+    }
+
+    /// Open the table over a `reader` that was already opened, e.g. by the
+    /// [`ReaderPool`](crate::fs::pool::ReaderPool)
+    pub(crate) async fn from_reader(
+        lru_cache: Arc<dyn DynLruCache<Ulid> + Send + Sync>,
+        id: Ulid,
+        reader: BoxedFileReader,
+    ) -> Self {
+        SsTable {
+            reader: lru_cache.get_reader(id, reader).await,
+            read_hint: None,
+            memory_budget: None,
+            bloom_filters: false,
+            prefix: None,
+            schema: None,
             _marker: PhantomData,
-        })
+        }
+    }
+
+    /// Footer of the table
+    pub(crate) async fn metadata(mut self) -> ParquetResult<Arc<ParquetMetaData>> {
+        self.reader.get_metadata(None).await
+    }
+
+    /// Tune the reads of scans over the table for the access pattern of `read_hint`
+    pub(crate) fn read_hint(self, read_hint: Option<ReadHint>) -> Self {
+        Self { read_hint, ..self }
+    }
+
+    /// Charge the record batches decoded by scans over the table to `memory_budget`
+    pub(crate) fn memory_budget(self, memory_budget: Option<MemoryBudget>) -> Self {
+        Self {
+            memory_budget,
+            ..self
+        }
+    }
+
+    /// Skip the rows of gets whose key the bloom filters of the table rule out, see
+    /// [`DbOption::bloom_filters`](crate::DbOption::bloom_filters)
+    pub(crate) fn bloom_filters(self, bloom_filters: bool) -> Self {
+        Self {
+            bloom_filters,
+            ..self
+        }
+    }
+
+    /// Skip the rows of scans whose keys all start with `prefix` if the prefix bloom filter of
+    /// the table rules it out, see
+    /// [`DbOption::prefix_bloom_filter`](crate::DbOption::prefix_bloom_filter)
+    pub(crate) fn prefix(self, prefix: Option<Arc<[u8]>>) -> Self {
+        Self { prefix, ..self }
+    }
+
+    /// Read the table as `schema`, the schema of the DB, whose columns are matched to the
+    /// columns of the table by their field ids, see [`ColumnMapping`]
+    pub(crate) fn schema(self, schema: Option<Arc<ArrowSchema>>) -> Self {
+        Self { schema, ..self }
     }
 
     async fn into_parquet_builder(
         self,
         limit: Option<usize>,
-        projection_mask: ProjectionMask,
     ) -> ParquetResult<ArrowReaderBuilder<ParquetAsyncReader<Box<dyn AsyncFileReader + 'static>>>>
     {
         let mut builder = ParquetRecordBatchStreamBuilder::new_with_options(
@@ -89,7 +160,77 @@ where
         if let Some(limit) = limit {
             builder = builder.with_limit(limit);
         }
-        Ok(builder.with_projection(projection_mask))
+        if let Some(read_hint) = self.read_hint {
+            builder = builder.with_batch_size(read_hint.batch_size());
+        }
+        Ok(builder)
+    }
+
+    // Reads the leaves of `projection_mask` of the schema of the table, or of `schema` if the
+    // table has other columns, along with the indices of the primary key columns in the table
+    fn project(
+        builder: ArrowReaderBuilder<ParquetAsyncReader<Box<dyn AsyncFileReader + 'static>>>,
+        schema: Option<&Arc<ArrowSchema>>,
+        projection_mask: &ProjectionMask,
+        pk_indices: &[usize],
+    ) -> (
+        ArrowReaderBuilder<ParquetAsyncReader<Box<dyn AsyncFileReader + 'static>>>,
+        Option<ColumnMapping>,
+        Vec<usize>,
+    ) {
+        let mapping = schema.and_then(|schema| {
+            ColumnMapping::new(
+                builder.schema(),
+                builder.metadata().file_metadata().schema_descr(),
+                schema,
+                projection_mask,
+            )
+        });
+        match mapping {
+            Some(mapping) => {
+                let pk_indices = mapping.table_indices(pk_indices);
+                (
+                    builder.with_projection(mapping.projection_mask()),
+                    Some(mapping),
+                    pk_indices,
+                )
+            }
+            None => (
+                builder.with_projection(projection_mask.clone()),
+                None,
+                pk_indices.to_vec(),
+            ),
+        }
+    }
+
+    /// Number of rows of the table if the statistics of its footer show that each of them is a
+    /// record, not a removal, written at or before `ts`, `None` otherwise
+    pub(crate) async fn visible_rows(self, ts: Timestamp) -> ParquetResult<Option<u64>> {
+        let builder = self.into_parquet_builder(None).await?;
+        let (metadata, arrow_schema) = (builder.metadata(), builder.schema());
+        let row_groups = metadata.row_groups();
+        // the first columns are `_null`, which marks the removals, and `_ts`
+        let maxes = |column: usize| {
+            StatisticsConverter::try_new(
+                arrow_schema.field(column).name(),
+                arrow_schema,
+                metadata.file_metadata().schema_descr(),
+            )
+            .and_then(|converter| converter.row_group_maxes(row_groups))
+            .ok()
+        };
+        let (Some(removals), Some(timestamps)) = (maxes(0), maxes(1)) else {
+            return Ok(None);
+        };
+        let no_removals = removals.null_count() == 0 && removals.as_boolean().true_count() == 0;
+        let visible = timestamps.null_count() == 0
+            && timestamps
+                .as_primitive::<UInt32Type>()
+                .values()
+                .iter()
+                .all(|written| *written <= u32::from(ts));
+
+        Ok((no_removals && visible).then(|| metadata.file_metadata().num_rows() as u64))
     }
 
     pub(crate) async fn get(
@@ -98,15 +239,29 @@ where
         projection_mask: ProjectionMask,
         pk_indices: &[usize],
     ) -> ParquetResult<Option<RecordBatchEntry<R>>> {
-        self.scan(
+        let memory_budget = self.memory_budget.clone();
+        let bloom_filters = self.bloom_filters;
+        let schema = self.schema.clone();
+        let builder = self.into_parquet_builder(Some(1)).await?;
+        let (mut builder, mapping, pk_indices) =
+            Self::project(builder, schema.as_ref(), &projection_mask, pk_indices);
+        if bloom_filters
+            && excluded_by_bloom_filters(&mut builder, key.value(), &pk_indices).await?
+        {
+            return Ok(None);
+        }
+
+        Self::build_scan(
+            builder,
+            memory_budget,
+            mapping.zip(schema),
             (Bound::Included(key.value()), Bound::Included(key.value())),
             key.ts(),
-            Some(1),
             projection_mask,
             None, // Order doesn't matter for single-key get
-            pk_indices,
-        )
-        .await?
+            &pk_indices,
+            false,
+        )?
         .next()
         .await
         .transpose()
@@ -124,12 +279,62 @@ where
         order: Option<Order>,
         pk_indices: &[usize],
     ) -> Result<SsTableScan<'scan, R>, parquet::errors::ParquetError> {
-        let builder = self
-            .into_parquet_builder(limit, projection_mask.clone())
-            .await?;
+        let memory_budget = self.memory_budget.clone();
+        let prefix = self.prefix.clone();
+        let schema = self.schema.clone();
+        let builder = self.into_parquet_builder(limit).await?;
+        let (builder, mapping, pk_indices) =
+            Self::project(builder, schema.as_ref(), &projection_mask, pk_indices);
+        let excluded = prefix.is_some_and(|prefix| {
+            PrefixBloom::from_metadata(builder.metadata().file_metadata().key_value_metadata())
+                .is_some_and(|bloom| !bloom.may_contain(&prefix))
+        });
+
+        Self::build_scan(
+            builder,
+            memory_budget,
+            mapping.zip(schema),
+            range,
+            ts,
+            projection_mask,
+            order,
+            &pk_indices,
+            excluded,
+        )
+    }
 
+    #[allow(clippy::too_many_arguments)]
+    fn build_scan<'scan>(
+        builder: ArrowReaderBuilder<ParquetAsyncReader<Box<dyn AsyncFileReader + 'static>>>,
+        memory_budget: Option<MemoryBudget>,
+        // how the columns of the table map to the schema of the DB, which the table was not
+        // written with
+        mapping: Option<(ColumnMapping, Arc<ArrowSchema>)>,
+        range: (
+            Bound<&'scan <R::Schema as Schema>::Key>,
+            Bound<&'scan <R::Schema as Schema>::Key>,
+        ),
+        ts: Timestamp,
+        projection_mask: ProjectionMask,
+        order: Option<Order>,
+        pk_indices: &[usize],
+        // whether the prefix bloom filter of the table rules out every row of the scan
+        excluded: bool,
+    ) -> Result<SsTableScan<'scan, R>, parquet::errors::ParquetError> {
+        let mut builder = builder;
+        let row_groups = if excluded {
+            Some(Vec::new())
+        } else {
+            row_groups_in_range(builder.metadata(), builder.schema(), range, pk_indices)
+        };
+        if let Some(row_groups) = row_groups {
+            builder = builder.with_row_groups(row_groups);
+        }
         let schema_descriptor = builder.metadata().file_metadata().schema_descr();
-        let full_schema = builder.schema().clone();
+        let (full_schema, mapping) = match mapping {
+            Some((mapping, schema)) => (schema, Some(mapping)),
+            None => (builder.schema().clone(), None),
+        };
 
         // Build a row filter for ts and primary key range
         let filter = get_range_filter::<R>(schema_descriptor, range, ts, pk_indices);
@@ -139,10 +344,189 @@ where
             projection_mask,
             full_schema,
             order,
-        ))
+        )
+        .memory_budget(memory_budget)
+        .mapping(mapping))
     }
 }
 
+// Row groups whose statistics of the first primary key column may hold keys in `range`, or
+// `None` if all of them may. Only the first column decides, as the rows of a row group may hold
+// any value of the other columns of a composite key between its bounds.
+fn row_groups_in_range<K: Key>(
+    metadata: &ParquetMetaData,
+    arrow_schema: &ArrowSchema,
+    range: (Bound<&K>, Bound<&K>),
+    pk_indices: &[usize],
+) -> Option<Vec<usize>> {
+    let column = arrow_schema.field(*pk_indices.first()?).name();
+    let converter = StatisticsConverter::try_new(
+        column,
+        arrow_schema,
+        metadata.file_metadata().schema_descr(),
+    )
+    .ok()?;
+    let row_groups = metadata.row_groups();
+    let below = match bound_key(range.0) {
+        Some(lower) => Some(compare_first(
+            &converter.row_group_maxes(row_groups).ok()?,
+            lower,
+            lt,
+        )?),
+        None => None,
+    };
+    let above = match bound_key(range.1) {
+        Some(upper) => Some(compare_first(
+            &converter.row_group_mins(row_groups).ok()?,
+            upper,
+            gt,
+        )?),
+        None => None,
+    };
+    // a missing statistic compares as null, which skips no row group
+    let skipped = |stats: &Option<BooleanArray>, i: usize| {
+        stats
+            .as_ref()
+            .is_some_and(|stats| stats.is_valid(i) && stats.value(i))
+    };
+
+    let selected = (0..row_groups.len())
+        .filter(|i| !skipped(&below, *i) && !skipped(&above, *i))
+        .collect::<Vec<_>>();
+    (selected.len() < row_groups.len()).then_some(selected)
+}
+
+/// Rows and compressed bytes of the row groups of a table whose statistics of the first primary
+/// key column may hold keys in `range`
+pub(crate) fn size_in_range<K: Key>(
+    metadata: &ParquetMetaData,
+    arrow_schema: &ArrowSchema,
+    range: (Bound<&K>, Bound<&K>),
+    pk_indices: &[usize],
+) -> (u64, u64) {
+    let row_groups = metadata.row_groups();
+    let selected = row_groups_in_range(metadata, arrow_schema, range, pk_indices)
+        .unwrap_or_else(|| (0..row_groups.len()).collect());
+
+    selected
+        .into_iter()
+        .map(|i| &row_groups[i])
+        .fold((0, 0), |(rows, bytes), row_group| {
+            (
+                rows + row_group.num_rows() as u64,
+                bytes + row_group.compressed_size() as u64,
+            )
+        })
+}
+
+// Compare each value of `stats` to the first column of `key`
+fn compare_first<K: Key>(
+    stats: &ArrayRef,
+    key: &K,
+    cmp: fn(&dyn Datum, &dyn Datum) -> Result<BooleanArray, ArrowError>,
+) -> Option<BooleanArray> {
+    let datum = key.to_arrow_datums().swap_remove(0);
+    cmp(stats, &*datum).ok()
+}
+
+fn bound_key<K>(bound: Bound<&K>) -> Option<&K> {
+    match bound {
+        Bound::Included(key) | Bound::Excluded(key) => Some(key),
+        Bound::Unbounded => None,
+    }
+}
+
+// Whether the bloom filter of a primary key column rules out `key` in every row group. Row
+// groups without bloom filters, and values without a plain parquet representation, may hold the
+// key.
+async fn excluded_by_bloom_filters<K: Key>(
+    builder: &mut ArrowReaderBuilder<ParquetAsyncReader<Box<dyn AsyncFileReader + 'static>>>,
+    key: &K,
+    pk_indices: &[usize],
+) -> ParquetResult<bool> {
+    let metadata = builder.metadata().clone();
+    let schema_descr = metadata.file_metadata().schema_descr();
+    let mut values = Vec::with_capacity(pk_indices.len());
+    for (datum, root) in key.to_arrow_datums().iter().zip(pk_indices) {
+        // primary key columns are not nested, so the column of the root is its only leaf
+        let mut leaves = (0..schema_descr.num_columns())
+            .filter(|i| schema_descr.get_column_root_idx(*i) == *root);
+        let (Some(leaf), None) = (leaves.next(), leaves.next()) else {
+            continue;
+        };
+        if let Some(value) = bloom_filter_value(datum.get().0) {
+            values.push((leaf, value));
+        }
+    }
+    if values.is_empty() {
+        return Ok(false);
+    }
+
+    for row_group in 0..metadata.num_row_groups() {
+        let mut excluded = false;
+        for (leaf, value) in values.iter() {
+            let filter = builder
+                .get_row_group_column_bloom_filter(row_group, *leaf)
+                .await?;
+            if filter.is_some_and(|filter| !filter.check(&value[..])) {
+                excluded = true;
+                break;
+            }
+        }
+        if !excluded {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// Bytes the parquet writer hashes into a bloom filter for the only value of `array`, which are
+// those of the physical type the arrow type is written as
+fn bloom_filter_value(array: &dyn Array) -> Option<Vec<u8>> {
+    if array.is_empty() || array.is_null(0) {
+        return None;
+    }
+    let value = match array.data_type() {
+        DataType::Boolean => vec![array.as_boolean().value(0) as u8],
+        DataType::Int8 => (array.as_primitive::<Int8Type>().value(0) as i32)
+            .to_le_bytes()
+            .to_vec(),
+        DataType::Int16 => (array.as_primitive::<Int16Type>().value(0) as i32)
+            .to_le_bytes()
+            .to_vec(),
+        DataType::Int32 => array
+            .as_primitive::<Int32Type>()
+            .value(0)
+            .to_le_bytes()
+            .to_vec(),
+        DataType::Int64 => array
+            .as_primitive::<Int64Type>()
+            .value(0)
+            .to_le_bytes()
+            .to_vec(),
+        DataType::UInt8 => (array.as_primitive::<UInt8Type>().value(0) as i32)
+            .to_le_bytes()
+            .to_vec(),
+        DataType::UInt16 => (array.as_primitive::<UInt16Type>().value(0) as i32)
+            .to_le_bytes()
+            .to_vec(),
+        // unsigned integers keep their bits in the signed physical type
+        DataType::UInt32 => (array.as_primitive::<UInt32Type>().value(0) as i32)
+            .to_le_bytes()
+            .to_vec(),
+        DataType::UInt64 => (array.as_primitive::<UInt64Type>().value(0) as i64)
+            .to_le_bytes()
+            .to_vec(),
+        DataType::Utf8 => array.as_string::<i32>().value(0).as_bytes().to_vec(),
+        DataType::LargeUtf8 => array.as_string::<i64>().value(0).as_bytes().to_vec(),
+        DataType::Binary => array.as_binary::<i32>().value(0).to_vec(),
+        DataType::LargeBinary => array.as_binary::<i64>().value(0).to_vec(),
+        DataType::FixedSizeBinary(_) => array.as_fixed_size_binary().value(0).to_vec(),
+        _ => return None,
+    };
+    Some(value)
+}
+
 #[cfg(all(test, feature = "tokio"))]
 pub(crate) mod tests {
     use std::{borrow::Borrow, fs::File, ops::Bound, sync::Arc};
@@ -162,7 +546,7 @@ pub(crate) mod tests {
     };
     use parquet_lru::NoCache;
 
-    use super::SsTable;
+    use super::{excluded_by_bloom_filters, row_groups_in_range, SsTable};
     use crate::{
         executor::tokio::TokioExecutor,
         fs::{manager::StoreManager, FileType},
@@ -305,6 +689,158 @@ pub(crate) mod tests {
         }
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn bloom_filter_get() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let manager = StoreManager::new(FsOptions::Local, vec![]).unwrap();
+        let base_fs = manager.base_fs();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let record_batch =
+            get_test_record_batch::<TokioExecutor>(option.clone(), TokioExecutor::default()).await;
+        let table_path = temp_dir.path().join("bloom_filter_get_test.parquet");
+        let _ = File::create(&table_path).unwrap();
+        let table_path = Path::from_filesystem_path(table_path).unwrap();
+
+        // the default writer properties write bloom filters for the primary key
+        let file = base_fs
+            .open_options(&table_path, FileType::Parquet.open_options(false))
+            .await
+            .unwrap();
+        let mut writer = AsyncArrowWriter::try_new(
+            AsyncWriter::new(file),
+            TestSchema {}.arrow_schema().clone(),
+            Some(option.write_parquet_properties.clone()),
+        )
+        .unwrap();
+        writer.write(&record_batch).await.unwrap();
+        writer.close().await.unwrap();
+
+        let pk_indices = TestSchema {}.primary_key_indices();
+        let mut builder = open_sstable::<Test>(base_fs, &table_path)
+            .await
+            .into_parquet_builder(None, ProjectionMask::all())
+            .await
+            .unwrap();
+        assert!(
+            !excluded_by_bloom_filters(&mut builder, &"hello".to_owned(), pk_indices)
+                .await
+                .unwrap()
+        );
+        // bloom filters have false positives, but only few
+        let mut excluded = 0;
+        for i in 0..20 {
+            if excluded_by_bloom_filters(&mut builder, &format!("missing {i}"), pk_indices)
+                .await
+                .unwrap()
+            {
+                excluded += 1;
+            }
+        }
+        assert!(excluded > 10, "only {excluded} missing keys were excluded");
+
+        let key = Ts::new("hello".to_owned(), 1.into());
+        let entry = open_sstable::<Test>(base_fs, &table_path)
+            .await
+            .bloom_filters(true)
+            .get(key.borrow(), ProjectionMask::all(), pk_indices)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.get().unwrap().vstring, "hello");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn row_group_pruning() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let manager = StoreManager::new(FsOptions::Local, vec![]).unwrap();
+        let base_fs = manager.base_fs();
+        let record_batch = get_test_record_batch::<TokioExecutor>(
+            DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &TestSchema,
+            ),
+            TokioExecutor::default(),
+        )
+        .await;
+        let table_path = temp_dir.path().join("row_group_pruning_test.parquet");
+        let _ = File::create(&table_path).unwrap();
+        let table_path = Path::from_filesystem_path(table_path).unwrap();
+
+        // "hello" and "world" go into a row group each
+        let file = base_fs
+            .open_options(&table_path, FileType::Parquet.open_options(false))
+            .await
+            .unwrap();
+        let mut writer = AsyncArrowWriter::try_new(
+            AsyncWriter::new(file),
+            TestSchema {}.arrow_schema().clone(),
+            Some(
+                WriterProperties::builder()
+                    .set_max_row_group_size(1)
+                    .build(),
+            ),
+        )
+        .unwrap();
+        writer.write(&record_batch).await.unwrap();
+        writer.close().await.unwrap();
+
+        let metadata = open_sstable::<Test>(base_fs, &table_path)
+            .await
+            .metadata()
+            .await
+            .unwrap();
+        assert_eq!(metadata.num_row_groups(), 2);
+        let arrow_schema = TestSchema {}.arrow_schema().clone();
+        let pk_indices = TestSchema {}.primary_key_indices();
+        let (hello, i) = ("hello".to_owned(), "i".to_owned());
+        assert_eq!(
+            row_groups_in_range(
+                &metadata,
+                &arrow_schema,
+                (Bound::Included(&hello), Bound::Included(&hello)),
+                pk_indices,
+            ),
+            Some(vec![0])
+        );
+        assert_eq!(
+            row_groups_in_range(
+                &metadata,
+                &arrow_schema,
+                (Bound::Included(&i), Bound::Unbounded),
+                pk_indices,
+            ),
+            Some(vec![1])
+        );
+        assert_eq!(
+            row_groups_in_range::<String>(
+                &metadata,
+                &arrow_schema,
+                (Bound::Unbounded, Bound::Unbounded),
+                pk_indices,
+            ),
+            None
+        );
+
+        let mut scan = open_sstable::<Test>(base_fs, &table_path)
+            .await
+            .scan(
+                (Bound::Included(&i), Bound::Unbounded),
+                1_u32.into(),
+                None,
+                ProjectionMask::all(),
+                None,
+                pk_indices,
+            )
+            .await
+            .unwrap();
+        let entry = scan.next().await.unwrap().unwrap();
+        assert_eq!(entry.get().unwrap().vstring, "world");
+        assert!(scan.next().await.is_none());
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn projection_scan() {
         let temp_dir = tempfile::tempdir().unwrap();
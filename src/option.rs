@@ -1,4 +1,10 @@
-use std::fmt::{Debug, Formatter};
+use std::{
+    any::{type_name, Any, TypeId},
+    fmt::{Debug, Formatter},
+    hash::{Hash, Hasher},
+    sync::Arc,
+    time::Duration,
+};
 
 pub use fusio::path::Path;
 #[cfg(feature = "aws")]
@@ -6,19 +12,31 @@ pub use fusio::remotes::aws::AwsCredential;
 pub use fusio_dispatch::FsOptions;
 use parquet::{
     basic::Compression,
-    file::properties::{EnabledStatistics, WriterProperties},
+    file::properties::{EnabledStatistics, WriterProperties, WriterPropertiesBuilder},
 };
 use thiserror::Error;
 
 use crate::{
-    compaction::{leveled::LeveledOptions, tiered::TieredOptions},
-    fs::{FileId, FileType},
-    record::Schema,
+    aggregate::{GroupOf, ValueOf},
+    clock::{SystemClock, TimeSource},
+    compaction::{
+        adaptive::AdaptiveTuning,
+        filter::{CompactionFilter, DynCompactionFilter},
+        leveled::LeveledOptions,
+        tiered::TieredOptions,
+    },
+    expiry::ExpiresAt,
+    fs::{FileId, FileIdGenerator, FileType, UlidFileIds},
+    merge::{DynMergeOperator, MergeOperator},
+    record::{Key, Record, Schema},
     trigger::TriggerType,
-    version::MAX_LEVEL,
+    version::{schema::ColumnChange, timestamp::Timestamp, MAX_LEVEL},
 };
 
 const DEFAULT_WAL_BUFFER_SIZE: usize = 4 * 1024;
+const DEFAULT_WAL_RECOVER_PARALLELISM: usize = 4;
+const DEFAULT_NEGATIVE_CACHE_CAPACITY: usize = 4096;
+const DEFAULT_IDEMPOTENCY_WINDOW: usize = 16 * 1024;
 
 /// Specifies the ordering direction for scans and other operations
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
@@ -30,6 +48,45 @@ pub enum Order {
     Desc,
 }
 
+/// Expected access pattern of a scan, which tunes how its SSTables are read
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReadHint {
+    /// Long scans that read most of their range, e.g. analytic queries. Decodes large batches and
+    /// opens the next SSTable of a level while the current one is still read.
+    Sequential,
+    /// Short range probes that only read a few rows. Decodes small batches and opens each
+    /// SSTable only once the scan reaches it.
+    Random,
+}
+
+impl ReadHint {
+    /// Number of rows decoded from an SSTable at once
+    pub(crate) fn batch_size(&self) -> usize {
+        match self {
+            ReadHint::Sequential => 8192,
+            ReadHint::Random => 256,
+        }
+    }
+}
+
+/// What happens to the write-ahead log of a memtable once the memtable is flushed to an SSTable
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum WalRetention {
+    /// Remove obsolete WALs the next time the version log is rewritten, see
+    /// [`DbOption::version_log_snapshot_threshold`]
+    #[default]
+    Deferred,
+    /// Remove a WAL as soon as its memtable is flushed
+    Immediate,
+    /// Keep the given number of obsolete WALs, the most recently created ones
+    KeepFiles(usize),
+    /// Keep obsolete WALs until they are older than the given duration, counted from the
+    /// creation of the WAL
+    KeepFor(Duration),
+    /// Move obsolete WALs into the given directory of the base file system and never remove them
+    Archive(Path),
+}
+
 pub enum CompactionOption {
     Leveled(LeveledOptions),
     Tiered(TieredOptions),
@@ -71,6 +128,9 @@ pub struct DbOption {
     /// Maximum allowed size (in bytes) for a single SST file
     pub(crate) max_sst_file_size: usize,
 
+    /// Number of key ranges a major compaction is split into and merged in parallel
+    pub(crate) max_subcompactions: usize,
+
     /// Version count after which a snapshot is taken of the version log
     pub(crate) version_log_snapshot_threshold: u32,
 
@@ -83,9 +143,24 @@ pub struct DbOption {
     /// Buffer size (in bytes) for the write-ahead log
     pub(crate) wal_buffer_size: usize,
 
+    /// Maximum number of write-ahead logs decoded concurrently during recovery
+    pub(crate) wal_recover_parallelism: usize,
+
+    /// Only replay write-ahead log commits up to this timestamp during recovery
+    pub(crate) recover_until: Option<Timestamp>,
+
     /// Parquet writer properties for on-disk SST files
     pub(crate) write_parquet_properties: WriterProperties,
 
+    /// Parquet writer properties of the SSTables of a level, instead of `write_parquet_properties`
+    pub(crate) level_write_properties: Vec<Option<WriterProperties>>,
+
+    /// Whether gets check the bloom filters of the primary key columns of an SSTable
+    pub(crate) bloom_filters: bool,
+
+    /// Length in bytes of the key prefixes in the prefix bloom filter of an SSTable
+    pub(crate) prefix_bloom_len: Option<usize>,
+
     /// Detailed options governing compaction behavior
     pub(crate) compaction_option: CompactionOption,
 
@@ -94,40 +169,187 @@ pub struct DbOption {
 
     /// Maximum number of immutable chunks
     pub(crate) immutable_chunk_max_num: usize,
+
+    /// Maximum number of keys remembered as absent from the SSTables
+    pub(crate) negative_cache_capacity: usize,
+
+    /// Maximum number of idempotency tokens remembered for `DB::insert_with_token`
+    pub(crate) idempotency_window: usize,
+
+    /// Number of rewritten SSTables that are warmed with their hot keys after a version change
+    pub(crate) hot_range_tables: usize,
+
+    /// Bounds of the thresholds that are tuned while the DB runs
+    pub(crate) adaptive_tuning: Option<AdaptiveTuning>,
+
+    /// Maximum number of SSTable readers kept open between reads
+    pub(crate) max_open_files: usize,
+
+    /// Maximum bytes of SSTable record batches held by all scans at once
+    pub(crate) scan_memory_limit: Option<usize>,
+
+    /// Local directory of the recent SSTables and the age until which they are kept there
+    pub(crate) pinned_tables: Option<(Path, Duration)>,
+
+    /// Local file of the cached SSTable footers
+    pub(crate) footer_cache: Option<Path>,
+
+    /// Whether new SSTables carry a checksum of their records
+    pub(crate) record_checksums: bool,
+
+    /// Whether compactions verify the record checksums of the SSTables they read
+    pub(crate) paranoid_checks: bool,
+
+    /// Adjacent keys of a compaction output that must not share an SSTable
+    pub(crate) table_boundary: Option<TableBoundary>,
+
+    /// Maximum number of writes and bytes buffered by a single transaction
+    pub(crate) max_transaction_entries: usize,
+    pub(crate) max_transaction_bytes: usize,
+
+    /// Keys that start a new SSTable of a compaction's output, sampled per level
+    pub(crate) table_guards: Option<TableGuards>,
+
+    /// What happens to the write-ahead logs of flushed memtables
+    pub(crate) wal_retention: WalRetention,
+
+    /// When records expire, to index the keys that `DB::remove_expired` removes
+    pub(crate) expiry: Option<Expiry>,
+
+    /// Whether reads hide expired records and major compactions remove them
+    pub(crate) drop_expired: bool,
+
+    /// How long a removal keeps the value it replaced
+    pub(crate) soft_delete: Option<Duration>,
+
+    /// What major compactions write for the records they rewrite
+    pub(crate) compaction_filter: Option<CompactionFilterOption>,
+
+    /// Aggregates of the records that the write path keeps up to date
+    pub(crate) aggregates: Vec<AggregateOption>,
+
+    /// How `DB::merge` applies operands to records
+    pub(crate) merge_operator: Option<MergeOperatorOption>,
+
+    /// Renames and drops of columns of the stored schema, applied when the DB is opened
+    pub(crate) column_changes: Vec<ColumnChange>,
+
+    /// Source of the ids of new WALs, SSTables and version logs
+    pub(crate) file_ids: Arc<dyn FileIdGenerator>,
+
+    /// Source of the current time
+    pub(crate) time_source: Arc<dyn TimeSource>,
 }
 
 impl DbOption {
     /// build the default configured [`DbOption`] with base path and primary key
     pub fn new<S: Schema>(base_path: Path, schema: &S) -> Self {
-        let (column_paths, sorting_columns) = schema.primary_key_paths_and_sorting();
-
-        // Configure Parquet writer properties for all PK columns and sorting order.
-        let mut writer_builder = WriterProperties::builder()
-            .set_compression(Compression::LZ4)
-            .set_sorting_columns(Some(sorting_columns.to_vec()))
-            .set_created_by(concat!("tonbo version ", env!("CARGO_PKG_VERSION")).to_owned());
-
-        for path in column_paths.iter().cloned() {
-            writer_builder = writer_builder
-                .set_column_statistics_enabled(path.clone(), EnabledStatistics::Page)
-                .set_column_bloom_filter_enabled(path, true);
-        }
-
         DbOption {
             immutable_chunk_num: 3,
             immutable_chunk_max_num: 5,
             max_sst_file_size: 256 * 1024 * 1024,
+            max_subcompactions: 1,
             clean_channel_buffer: 10,
             base_path,
-            write_parquet_properties: writer_builder.build(),
+            write_parquet_properties: writer_properties(schema).build(),
+            level_write_properties: vec![None; MAX_LEVEL],
+            bloom_filters: true,
+            prefix_bloom_len: None,
 
             use_wal: true,
             wal_buffer_size: DEFAULT_WAL_BUFFER_SIZE,
+            wal_recover_parallelism: DEFAULT_WAL_RECOVER_PARALLELISM,
+            recover_until: None,
             trigger_type: TriggerType::SizeOfMem(64 * 1024 * 1024),
             version_log_snapshot_threshold: 200,
             level_paths: vec![None; MAX_LEVEL],
             base_fs: FsOptions::Local,
             compaction_option: CompactionOption::Leveled(LeveledOptions::default()),
+            negative_cache_capacity: DEFAULT_NEGATIVE_CACHE_CAPACITY,
+            idempotency_window: DEFAULT_IDEMPOTENCY_WINDOW,
+            hot_range_tables: 0,
+            adaptive_tuning: None,
+            max_open_files: 0,
+            scan_memory_limit: None,
+            pinned_tables: None,
+            footer_cache: None,
+            record_checksums: false,
+            paranoid_checks: false,
+            table_boundary: None,
+            table_guards: None,
+            max_transaction_entries: usize::MAX,
+            max_transaction_bytes: usize::MAX,
+            wal_retention: WalRetention::Deferred,
+            expiry: None,
+            drop_expired: false,
+            soft_delete: None,
+            compaction_filter: None,
+            aggregates: Vec::new(),
+            merge_operator: None,
+            column_changes: Vec::new(),
+            file_ids: Arc::new(UlidFileIds::default()),
+            time_source: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl DbOption {
+    /// [`DbOption::new`] tuned for write heavy workloads, e.g. ingestion or event logs
+    ///
+    /// Memtables are larger and more of them are buffered before writes stall, and levels hold
+    /// twice the data before they are compacted, so fewer flushes and compactions rewrite the
+    /// data at the cost of more SSTables to read.
+    pub fn write_optimized<S: Schema>(base_path: Path, schema: &S) -> Self {
+        DbOption {
+            trigger_type: TriggerType::SizeOfMem(128 * 1024 * 1024),
+            immutable_chunk_num: 4,
+            immutable_chunk_max_num: 8,
+            wal_buffer_size: 64 * 1024,
+            ..DbOption::new(base_path, schema)
+        }
+        .leveled_compaction(LeveledOptions::default().major_threshold_with_sst_size(8))
+    }
+
+    /// [`DbOption::new`] tuned for read heavy workloads, e.g. point lookups by key
+    ///
+    /// Smaller memtables and SSTables are compacted early, so a read checks few tables, and
+    /// more of the tables and absent keys are cached. Smaller Parquet pages let the page index
+    /// skip more of a table for a lookup.
+    pub fn read_optimized<S: Schema>(base_path: Path, schema: &S) -> Self {
+        DbOption {
+            trigger_type: TriggerType::SizeOfMem(32 * 1024 * 1024),
+            immutable_chunk_num: 2,
+            immutable_chunk_max_num: 4,
+            max_sst_file_size: 64 * 1024 * 1024,
+            negative_cache_capacity: 64 * 1024,
+            max_open_files: 512,
+            hot_range_tables: 8,
+            write_parquet_properties: writer_properties(schema)
+                .set_data_page_size_limit(64 * 1024)
+                .build(),
+            ..DbOption::new(base_path, schema)
+        }
+        .leveled_compaction(LeveledOptions::default().major_threshold_with_sst_size(2))
+    }
+
+    /// [`DbOption::new`] with `base_fs` as the file system, tuned for object stores such as S3
+    ///
+    /// Requests to an object store are slow and billed, so SSTables are larger and fewer, the
+    /// WAL is written in larger parts, and the readers of more SSTables are kept open instead of
+    /// fetching their footers again.
+    pub fn object_store_optimized<S: Schema>(
+        base_path: Path,
+        schema: &S,
+        base_fs: FsOptions,
+    ) -> Self {
+        DbOption {
+            base_fs,
+            trigger_type: TriggerType::SizeOfMem(128 * 1024 * 1024),
+            max_sst_file_size: 512 * 1024 * 1024,
+            wal_buffer_size: 1024 * 1024,
+            negative_cache_capacity: 64 * 1024,
+            max_open_files: 256,
+            ..DbOption::new(base_path, schema)
         }
     }
 }
@@ -147,6 +369,12 @@ impl DbOption {
         self
     }
 
+    /// Configure tiered compaction with custom options instead of leveled compaction
+    ///
+    /// Every flush adds a table to tier 0, and once a tier holds more tables than its capacity,
+    /// all of them are merged into the next tier at once. Records are rewritten once per tier
+    /// rather than each time a level overlaps the next one, which suits write heavy ingestion at
+    /// the cost of lookups that check more tables.
     pub fn tiered_compaction(mut self, options: TieredOptions) -> Self {
         self.compaction_option = CompactionOption::Tiered(options);
         self
@@ -158,6 +386,19 @@ impl DbOption {
         self
     }
 
+    /// Split each major compaction of the leveled compaction strategy into up to `max` disjoint
+    /// key ranges, which are merged in parallel on as many tasks of the executor
+    ///
+    /// The ranges are cut at the smallest keys of the input tables, so a compaction of few tables
+    /// is split into fewer ranges. Each range writes tables of its own, which therefore end at the
+    /// cuts. The default of 1 merges every compaction on the compaction task.
+    pub fn max_subcompactions(self, max: usize) -> Self {
+        DbOption {
+            max_subcompactions: max.max(1),
+            ..self
+        }
+    }
+
     /// Set immutable chunk number
     pub fn immutable_chunk_num(mut self, value: usize) -> Self {
         self.immutable_chunk_num = value;
@@ -186,6 +427,35 @@ impl DbOption {
         }
     }
 
+    /// Check the bloom filters of the primary key columns of an SSTable before a get reads its
+    /// rows, enabled by default
+    ///
+    /// The default writer properties write a bloom filter for every primary key column, so a get
+    /// of a key that is in the key range of a table but not in the table only reads the filters
+    /// instead of the pages that would hold the key. Tables written without bloom filters, e.g.
+    /// with [`DbOption::write_parquet_option`], are read as if the check was disabled.
+    pub fn bloom_filters(self, bloom_filters: bool) -> Self {
+        DbOption {
+            bloom_filters,
+            ..self
+        }
+    }
+
+    /// Write a bloom filter of the first `prefix_len` bytes of the keys into every SSTable, so
+    /// [`DB::scan_prefix`](crate::DB::scan_prefix) skips the tables without keys of its prefix
+    /// instead of reading their pages, disabled by default
+    ///
+    /// The filter covers the first primary key column, which must be a string or binary column,
+    /// e.g. a `tenant:user:` part of length `prefix_len` in front of every key. Prefix scans for
+    /// shorter prefixes read every table in their key range, as do tables written before the
+    /// filter was enabled.
+    pub fn prefix_bloom_filter(self, prefix_len: usize) -> Self {
+        DbOption {
+            prefix_bloom_len: Some(prefix_len),
+            ..self
+        }
+    }
+
     /// disable WAL
     ///
     /// tips: risk of data loss during downtime
@@ -206,6 +476,440 @@ impl DbOption {
         }
     }
 
+    /// Maximum number of WAL files decoded concurrently on the executor during recovery, default
+    /// value is 4
+    ///
+    /// Replay into the memtable still happens in WAL order, so per-key timestamp ordering is
+    /// preserved regardless of this value. Set to 1 to recover WAL files one by one.
+    pub fn wal_recover_parallelism(self, wal_recover_parallelism: usize) -> Self {
+        DbOption {
+            wal_recover_parallelism: wal_recover_parallelism.max(1),
+            ..self
+        }
+    }
+
+    /// What happens to the WAL of a memtable once the memtable is flushed, default value is
+    /// [`WalRetention::Deferred`]
+    ///
+    /// WALs that are kept are moved out of the WAL directory, into `wal_retained` under the base
+    /// path or into the directory of [`WalRetention::Archive`], so they are not replayed when the
+    /// [`DB`](crate::DB) is opened. Kept WALs are only pruned after flushes, so they may outlive
+    /// their limit while nothing is written.
+    pub fn wal_retention(self, wal_retention: WalRetention) -> Self {
+        DbOption {
+            wal_retention,
+            ..self
+        }
+    }
+
+    /// Point-in-time recovery: only replay WAL commits whose timestamp is less than or equal to
+    /// `ts` when the [`DB`](crate::DB) is opened.
+    ///
+    /// Place an older checkpoint (version log and SSTables) together with the archived WAL files
+    /// under the base path and open the [`DB`](crate::DB) with this option to get the state "as of"
+    /// `ts`. Commits after `ts` are skipped and their WAL files are removed once the recovered data
+    /// has been flushed, so keep a copy of the archive if it is needed again.
+    ///
+    /// The recovery is applied once: the manifest remembers the WALs it cut, and later opens with
+    /// the same `ts` replay the commits written since in full.
+    pub fn recover_until(self, ts: impl Into<Timestamp>) -> Self {
+        DbOption {
+            recover_until: Some(ts.into()),
+            ..self
+        }
+    }
+
+    /// Maximum number of keys whose point lookups are remembered as misses in the SSTables,
+    /// default value is 4096
+    ///
+    /// Repeated lookups of absent keys, e.g. for dedup checks, skip the SSTables while the set of
+    /// SSTables is unchanged. Set to 0 to disable the cache.
+    pub fn negative_cache_capacity(self, negative_cache_capacity: usize) -> Self {
+        DbOption {
+            negative_cache_capacity,
+            ..self
+        }
+    }
+
+    /// Maximum number of idempotency tokens of
+    /// [`DB::insert_with_token`](crate::DB::insert_with_token) that are remembered, default
+    /// value is 16384
+    ///
+    /// Once the window is full, the oldest token is forgotten and an insert repeating it is
+    /// applied again. Size the window to cover how far an upstream may replay its writes. Tokens
+    /// are only kept in memory, so a reopened DB applies the inserts it had seen before. Set to 0
+    /// to remember no token, which makes `insert_with_token` return
+    /// [`DbError::NoIdempotencyWindow`](crate::DbError::NoIdempotencyWindow).
+    pub fn idempotency_window(self, idempotency_window: usize) -> Self {
+        DbOption {
+            idempotency_window,
+            ..self
+        }
+    }
+
+    /// Number of SSTables that are warmed after a flush or compaction, default value is 0
+    ///
+    /// Point lookups that reach the SSTables are counted per key. Once a compaction replaced the
+    /// tables holding the hottest keys, the new tables with the most reads repeat the lookups of
+    /// their hot keys, which loads their pages into the [`ParquetLru`](crate::ParquetLru) passed
+    /// to [`DB::with_lru_cache`](crate::DB::with_lru_cache). Set to 0 to disable the tracking.
+    pub fn hot_range_tables(self, hot_range_tables: usize) -> Self {
+        DbOption {
+            hot_range_tables,
+            ..self
+        }
+    }
+
+    /// Tune [`DbOption::immutable_chunk_max_num`] and the major compaction threshold of
+    /// [`LeveledOptions`] within the bounds of `tuning` while the DB runs, see
+    /// [`compaction::adaptive`](crate::compaction::adaptive)
+    ///
+    /// The configured values are the starting point, clamped into the bounds.
+    pub fn adaptive_tuning(self, tuning: AdaptiveTuning) -> Self {
+        DbOption {
+            adaptive_tuning: Some(tuning),
+            ..self
+        }
+    }
+
+    /// Maximum number of SSTable readers that are kept open between reads, default value is 0
+    ///
+    /// Scans and point lookups share a pool of open readers, so reading a table again skips
+    /// opening its file, and idle readers beyond this limit are closed, least recently used
+    /// first. The limit bounds idle readers, not reads: a read never waits for a reader, as a
+    /// scan holds a reader of every table it merges, so reads running at the same time may open
+    /// more readers, which are closed once the reads are done. Set to 0 to open a reader for
+    /// every read.
+    pub fn max_open_files(self, max_open_files: usize) -> Self {
+        DbOption {
+            max_open_files,
+            ..self
+        }
+    }
+
+    /// Maximum bytes of decoded SSTable record batches that all scans of the [`DB`](crate::DB)
+    /// hold at once, unlimited by default
+    ///
+    /// A merged scan holds a batch of every SSTable and level it reads, so a burst of wide scans
+    /// can hold many batches at the same time. Once they would exceed this limit, the scan that
+    /// decodes the next batch fails with a
+    /// [`ScanMemoryExceeded`](crate::stream::memory::ScanMemoryExceeded) error of kind
+    /// [`ErrorKind::Busy`](crate::error::ErrorKind::Busy), which can be retried after other scans
+    /// are done. Memtables and compactions are not limited. Smaller batches of
+    /// [`ReadHint::Random`] let more scans run within the limit.
+    pub fn scan_memory_limit(self, bytes: usize) -> Self {
+        DbOption {
+            scan_memory_limit: Some(bytes),
+            ..self
+        }
+    }
+
+    /// Keep the SSTables younger than `age` in the local directory `path`, whatever their level
+    ///
+    /// Flushes and compactions write their SSTables to `path`, so the most recent data is read
+    /// from local storage even if the [`DbOption::level_path`] of its level is remote. Tables
+    /// older than `age` are moved to their level after the next flush or compaction, or when the
+    /// [`DB`](crate::DB) is opened. Every DB needs a directory of its own.
+    pub fn pin_recent_tables(self, path: Path, age: Duration) -> Self {
+        DbOption {
+            pinned_tables: Some((path, age)),
+            ..self
+        }
+    }
+
+    /// Keep the footers and page indexes of the SSTables that were read in the local file `path`,
+    /// so they are not fetched again after a restart
+    ///
+    /// The file is loaded when the [`DB`](crate::DB) is opened and written after flushes and
+    /// compactions, or by [`DB::persist_footer_cache`](crate::DB::persist_footer_cache). A
+    /// restarted node then serves its first reads of remote levels without fetching the footer
+    /// of every table first. The directory of `path` has to exist, and every DB needs a file of
+    /// its own.
+    pub fn footer_cache(self, path: Path) -> Self {
+        DbOption {
+            footer_cache: Some(path),
+            ..self
+        }
+    }
+
+    /// Store a checksum of the records of every new SSTable in its footer, disabled by default
+    ///
+    /// The checksum is a CRC32 over the encoding of each record in the WAL format, so it covers
+    /// the values as the DB wrote them rather than the Parquet pages, which have checksums of
+    /// their own. It is verified with [`DbOption::paranoid_checks`].
+    pub fn record_checksums(self, record_checksums: bool) -> Self {
+        DbOption {
+            record_checksums,
+            ..self
+        }
+    }
+
+    /// Verify the record checksums of the SSTables a compaction reads before it merges them,
+    /// disabled by default
+    ///
+    /// Every input table is read once more for the check, and a table whose records do not match
+    /// its checksum fails the compaction instead of spreading into the tables it writes. Tables
+    /// written without [`DbOption::record_checksums`] are not checked.
+    pub fn paranoid_checks(self, paranoid_checks: bool) -> Self {
+        DbOption {
+            paranoid_checks,
+            ..self
+        }
+    }
+
+    /// Start a new SSTable between two adjacent keys of a major compaction's output whenever
+    /// `is_boundary` returns true for them, not only once a table reaches
+    /// [`DbOption::max_sst_file_size`]
+    ///
+    /// With e.g. a tenant prefix in the keys, every table below level 0 then holds the keys of a
+    /// single tenant, so its tables can be deleted as a whole and scans of one tenant skip the
+    /// tables of all others. Flushes to level 0 still write a single table. `K` is the key type
+    /// of the schema, which is checked when the [`DB`](crate::DB) is opened.
+    pub fn table_boundary<K, F>(self, is_boundary: F) -> Self
+    where
+        K: Key,
+        F: Fn(&K, &K) -> bool + Send + Sync + 'static,
+    {
+        DbOption {
+            table_boundary: Some(TableBoundary {
+                key_type: TypeId::of::<K>(),
+                key_type_name: type_name::<K>(),
+                is_boundary: Arc::new(move |previous, key| {
+                    match (previous.downcast_ref::<K>(), key.downcast_ref::<K>()) {
+                        (Some(previous), Some(key)) => is_boundary(previous, key),
+                        _ => false,
+                    }
+                }),
+            }),
+            ..self
+        }
+    }
+
+    /// Maximum number of keys a [`Transaction`](crate::transaction::Transaction) writes, see
+    /// [`BufferFull`](crate::transaction::BufferFull)
+    pub fn max_transaction_entries(self, max_transaction_entries: usize) -> Self {
+        DbOption {
+            max_transaction_entries,
+            ..self
+        }
+    }
+
+    /// Maximum size in bytes of the records and removed keys a
+    /// [`Transaction`](crate::transaction::Transaction) buffers until it commits, see
+    /// [`BufferFull`](crate::transaction::BufferFull)
+    pub fn max_transaction_bytes(self, max_transaction_bytes: usize) -> Self {
+        DbOption {
+            max_transaction_bytes,
+            ..self
+        }
+    }
+
+    /// Start a new SSTable of a major compaction's output at every guard key of the output's level
+    ///
+    /// Without guards, tables are only cut once they reach [`DbOption::max_sst_file_size`], so
+    /// their boundaries drift from one compaction to the next and every table of a level comes to
+    /// overlap more tables of the next one. Guards are the keys whose hash ends in at least `bits`
+    /// zero bits on level 1, one bit less on every deeper level, so about one in `2^bits` keys
+    /// starts a table of level 1, twice as many start a table of level 2, and the guards of a
+    /// level are guards of all deeper levels. They only depend on the keys, so compactions keep
+    /// cutting at the same keys. `bits` has to be at least [`MAX_LEVEL`].
+    pub fn table_guards(self, bits: u32) -> Self {
+        assert!(
+            bits >= MAX_LEVEL as u32,
+            "table guard bits {bits} are less than the max level {MAX_LEVEL}"
+        );
+        DbOption {
+            table_guards: Some(TableGuards { bits }),
+            ..self
+        }
+    }
+
+    /// Keep the value a removal replaced for `purge_after` after the removal, instead of dropping
+    /// it with the next flush or compaction
+    ///
+    /// Removed keys stay hidden from reads, but
+    /// [`Scan::include_removed`](crate::Scan::include_removed) returns their last values and
+    /// [`DB::undelete`](crate::DB::undelete) writes it back. The age of a removal is read from
+    /// [`DbOption::time_source`] when flushes and compactions start, which only learn when the
+    /// removals they rewrite were committed up to the time between them. Once `purge_after`
+    /// passed, the next compaction of the removal drops the value like any overwritten one. The
+    /// removals of before a reopen are kept for `purge_after` after the first flush.
+    pub fn soft_delete(self, purge_after: Duration) -> Self {
+        DbOption {
+            soft_delete: Some(purge_after),
+            ..self
+        }
+    }
+
+    /// Pass every record that a major compaction rewrites through `filter`, which may remove or
+    /// rewrite it, see the [`filter`](crate::compaction::filter) module
+    ///
+    /// `R` is the record type of the schema, which is checked when the [`DB`](crate::DB) is
+    /// opened.
+    pub fn compaction_filter<R, F>(self, filter: F) -> Self
+    where
+        R: Record,
+        F: CompactionFilter<R>,
+    {
+        let filter: DynCompactionFilter<R> = Arc::new(filter);
+
+        DbOption {
+            compaction_filter: Some(CompactionFilterOption {
+                record_type_name: type_name::<R>(),
+                filter: Arc::new(filter),
+            }),
+            ..self
+        }
+    }
+
+    /// Apply the operands of [`DB::merge`](crate::DB::merge) to records with `operator`, see the
+    /// [`merge`](crate::merge) module
+    ///
+    /// `R` is the record type of the schema, which is checked when the [`DB`](crate::DB) is
+    /// opened.
+    pub fn merge_operator<R, M>(self, operator: M) -> Self
+    where
+        R: Record,
+        M: MergeOperator<R>,
+    {
+        let operator: DynMergeOperator<R> = Arc::new(operator);
+
+        DbOption {
+            merge_operator: Some(MergeOperatorOption {
+                record_type_name: type_name::<R>(),
+                operator: Arc::new(operator),
+            }),
+            ..self
+        }
+    }
+
+    /// Index the keys of the [`DB`](crate::DB) by the time their records expire, as milliseconds
+    /// since the UNIX epoch read from the record by `expires_at`, e.g. from an expiry column
+    ///
+    /// [`DB::remove_expired`](crate::DB::remove_expired) then removes exactly the records that
+    /// expired, instead of them staying visible until they are overwritten or removed. Only the
+    /// keys of the memtables are indexed in memory, and each SSTable stores the time its earliest
+    /// record expires at in the manifest, so nothing is scanned when the DB is opened. `R` is the
+    /// record type of the schema, which is checked when the DB is opened.
+    pub fn expires_at<R, F>(self, expires_at: F) -> Self
+    where
+        R: Record,
+        F: for<'r> Fn(R::Ref<'r>) -> Option<u64> + Send + Sync + 'static,
+    {
+        let expires_at: ExpiresAt<R> = Arc::new(expires_at);
+
+        DbOption {
+            expiry: Some(Expiry {
+                record_type_name: type_name::<R>(),
+                expires_at: Arc::new(expires_at),
+            }),
+            ..self
+        }
+    }
+
+    /// Hide the records that expired as of [`DbOption::expires_at`] from gets and scans, and
+    /// remove them in major compactions, without waiting for
+    /// [`DB::remove_expired`](crate::DB::remove_expired)
+    ///
+    /// A record counts as expired from the time it expires at, read from the clock of
+    /// [`DbOption::time_source`] when a read or a compaction starts. `remove_expired` then only
+    /// forgets the hidden keys, which stay on disk until a compaction rewrites them. Reads with a
+    /// projection read every column to tell whether a record expired, and only return the
+    /// projected ones.
+    pub fn drop_expired(self, drop_expired: bool) -> Self {
+        DbOption {
+            drop_expired,
+            ..self
+        }
+    }
+
+    /// Keep the count, sum, minimum and maximum of the records of each group of keys up to date,
+    /// queryable by `name` with [`DB::aggregate`](crate::DB::aggregate)
+    ///
+    /// `group_of` maps a key to its group, e.g. to the prefix shared by the keys of a tenant, and
+    /// `value_of` reads the value that is summed up from a record. Keys without a group are not
+    /// counted and records without a value only count. The groups are stored in the manifest and
+    /// updated by every flush, and a query replaces the records of the keys of its group that
+    /// are still in the memtables, so it always matches the latest writes without scanning the
+    /// records of the group. Only the groups take memory, not the keys, and the SSTables are
+    /// scanned once when the DB is first opened with a new `name`. `R` is the record type of the
+    /// schema, which is checked when the DB is opened.
+    ///
+    /// The stored groups are kept for `name` as long as the DB is opened with it, so functions
+    /// that group or value the records differently need a new name. Records that compactions
+    /// drop, e.g. by [`DbOption::drop_expired`] or a compaction filter, are still counted.
+    pub fn aggregate<R, G, V>(mut self, name: impl Into<String>, group_of: G, value_of: V) -> Self
+    where
+        R: Record,
+        G: Fn(&<R::Schema as Schema>::Key) -> Option<<R::Schema as Schema>::Key>
+            + Send
+            + Sync
+            + 'static,
+        V: for<'r> Fn(R::Ref<'r>) -> Option<i64> + Send + Sync + 'static,
+    {
+        let fns: (GroupOf<R>, ValueOf<R>) = (Arc::new(group_of), Arc::new(value_of));
+
+        self.aggregates.push(AggregateOption {
+            name: name.into(),
+            record_type_name: type_name::<R>(),
+            fns: Arc::new(fns),
+        });
+        self
+    }
+
+    /// Rename the column `from` of the schema the DB was written with to `to`, the name the
+    /// schema the DB is opened with gives it
+    ///
+    /// The rename is recorded in the version log when the DB is opened, without rewriting the
+    /// SSTables, which keep the field id of the column. It only applies while the stored schema
+    /// has a column `from` and the schema has none, so it may be given each time the DB is
+    /// opened.
+    pub fn rename_column(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.column_changes.push(ColumnChange::Rename {
+            from: from.into(),
+            to: to.into(),
+        });
+        self
+    }
+
+    /// Drop the column `name` of the schema the DB was written with, which the schema the DB is
+    /// opened with leaves out
+    ///
+    /// Without it, opening the DB with a schema that leaves out a column fails with
+    /// [`SchemaMismatch::MissingColumn`](crate::version::schema::SchemaMismatch::MissingColumn).
+    /// The drop is recorded in the version log and the column is no longer read, while the
+    /// SSTables keep its values until compactions rewrite them. It only applies while the stored
+    /// schema has a column `name` and the schema has none.
+    ///
+    /// The WAL must not hold records when the drop applies, so the DB has to be flushed with
+    /// [`DB::flush`](crate::DB::flush) before it is closed, or opening it fails with
+    /// [`SchemaMismatch::UnflushedDrop`](crate::version::schema::SchemaMismatch::UnflushedDrop).
+    pub fn drop_column(mut self, name: impl Into<String>) -> Self {
+        self.column_changes.push(ColumnChange::Drop(name.into()));
+        self
+    }
+
+    /// Take the ids of new WALs, SSTables and version logs from `generator` instead of the ULIDs
+    /// of the current time, e.g. a [`SequentialFileIds`](crate::fs::SequentialFileIds) or
+    /// [`SeededFileIds`](crate::fs::SeededFileIds) so tests and simulations create the same
+    /// directory layout and version logs on every run
+    pub fn file_id_generator(self, generator: impl FileIdGenerator + 'static) -> Self {
+        DbOption {
+            file_ids: Arc::new(generator),
+            ..self
+        }
+    }
+
+    /// Read the current time from `time_source` instead of the system clock, e.g. from a
+    /// [`ManualClock`](crate::clock::ManualClock) to run tests in virtual time, see
+    /// [`clock`](crate::clock)
+    pub fn time_source(self, time_source: impl TimeSource + 'static) -> Self {
+        DbOption {
+            time_source: Arc::new(time_source),
+            ..self
+        }
+    }
+
     /// VersionLog will use version_log_snapshot_threshold as the cycle to SnapShot to reduce the
     /// size.
     pub fn version_log_snapshot_threshold(self, version_log_snapshot_threshold: u32) -> Self {
@@ -228,6 +932,26 @@ impl DbOption {
         Ok(self)
     }
 
+    /// Write the SSTables of `level` with `properties` instead of the ones of
+    /// [`DbOption::write_parquet_option`]
+    ///
+    /// Flushes write level 0 and compactions write the level they compact into, so e.g. level 0
+    /// can use fast LZ4 while the bottom levels, which hold most of the data and are rewritten
+    /// least often, use ZSTD and larger row groups. `properties` replace the default ones
+    /// entirely, including the statistics and bloom filters of the primary key columns and the
+    /// sorting columns.
+    pub fn level_write_properties(
+        mut self,
+        level: usize,
+        properties: WriterProperties,
+    ) -> Result<Self, ExceedsMaxLevel> {
+        if level >= MAX_LEVEL {
+            return Err(ExceedsMaxLevel);
+        }
+        self.level_write_properties[level] = Some(properties);
+        Ok(self)
+    }
+
     /// set the base path option.
     ///
     /// This will be the default option for all wal, manifest and SSTables. Use
@@ -238,11 +962,223 @@ impl DbOption {
     }
 }
 
+/// Parquet writer properties of the SSTables of `schema`: the primary key columns have page
+/// statistics and bloom filters, and rows are sorted by them
+fn writer_properties<S: Schema>(schema: &S) -> WriterPropertiesBuilder {
+    let (column_paths, sorting_columns) = schema.primary_key_paths_and_sorting();
+
+    let mut writer_builder = WriterProperties::builder()
+        .set_compression(Compression::LZ4)
+        .set_sorting_columns(Some(sorting_columns.to_vec()))
+        .set_created_by(concat!("tonbo version ", env!("CARGO_PKG_VERSION")).to_owned());
+
+    for path in column_paths.iter().cloned() {
+        writer_builder = writer_builder
+            .set_column_statistics_enabled(path.clone(), EnabledStatistics::Page)
+            .set_column_bloom_filter_enabled(path, true);
+    }
+    writer_builder
+}
+
 #[derive(Debug, Error)]
 #[error("exceeds max level, max level is {}", MAX_LEVEL)]
 pub struct ExceedsMaxLevel;
 
+type IsBoundary = dyn Fn(&dyn Any, &dyn Any) -> bool + Send + Sync;
+
+/// Type erased predicate of [`DbOption::table_boundary`]
+#[derive(Clone)]
+pub(crate) struct TableBoundary {
+    key_type: TypeId,
+    key_type_name: &'static str,
+    is_boundary: Arc<IsBoundary>,
+}
+
+impl TableBoundary {
+    /// Name of the key type of the predicate, unless it is `K`
+    pub(crate) fn mismatch<K: Key>(&self) -> Option<&'static str> {
+        (self.key_type != TypeId::of::<K>()).then_some(self.key_type_name)
+    }
+
+    pub(crate) fn is_boundary<K: Key>(&self, previous: &K, key: &K) -> bool {
+        (self.is_boundary)(previous, key)
+    }
+}
+
+impl Debug for TableBoundary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TableBoundary")
+            .field("key_type", &self.key_type_name)
+            .finish()
+    }
+}
+
+/// Guard keys of [`DbOption::table_guards`]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TableGuards {
+    bits: u32,
+}
+
+impl TableGuards {
+    pub(crate) fn is_guard<K: Key>(&self, level: usize, key: &K) -> bool {
+        let mut hasher = GuardHasher::default();
+        key.hash(&mut hasher);
+        let bits = self.bits.saturating_sub(level.saturating_sub(1) as u32);
+
+        hasher.finish().trailing_zeros() >= bits
+    }
+}
+
+/// FNV-1a, whose output, unlike the one of `DefaultHasher`, doesn't change between releases, so
+/// the guards of the tables of a DB stay the same
+struct GuardHasher(u64);
+
+impl Default for GuardHasher {
+    fn default() -> Self {
+        GuardHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for GuardHasher {
+    fn finish(&self) -> u64 {
+        // FNV spreads the input poorly into the low bits, which decide the guards
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// Type erased [`ExpiresAt`] of [`DbOption::expires_at`]
+#[derive(Clone)]
+pub(crate) struct Expiry {
+    record_type_name: &'static str,
+    expires_at: Arc<dyn Any + Send + Sync>,
+}
+
+impl Expiry {
+    /// When records of type `R` expire, or the name of the record type of the function if it is
+    /// not `R`
+    pub(crate) fn expires_at<R: Record>(&self) -> Result<ExpiresAt<R>, &'static str> {
+        self.expires_at
+            .downcast_ref::<ExpiresAt<R>>()
+            .cloned()
+            .ok_or(self.record_type_name)
+    }
+}
+
+impl Debug for Expiry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Expiry")
+            .field("record_type", &self.record_type_name)
+            .finish()
+    }
+}
+
+/// Type erased filter of [`DbOption::compaction_filter`]
+#[derive(Clone)]
+pub(crate) struct CompactionFilterOption {
+    record_type_name: &'static str,
+    filter: Arc<dyn Any + Send + Sync>,
+}
+
+impl CompactionFilterOption {
+    /// The filter of records of type `R`, or the name of the record type of the filter if it is
+    /// not `R`
+    pub(crate) fn filter<R: Record>(&self) -> Result<DynCompactionFilter<R>, &'static str> {
+        self.filter
+            .downcast_ref::<DynCompactionFilter<R>>()
+            .cloned()
+            .ok_or(self.record_type_name)
+    }
+}
+
+impl Debug for CompactionFilterOption {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompactionFilterOption")
+            .field("record_type", &self.record_type_name)
+            .finish()
+    }
+}
+
+/// Type erased operator of [`DbOption::merge_operator`]
+#[derive(Clone)]
+pub(crate) struct MergeOperatorOption {
+    record_type_name: &'static str,
+    operator: Arc<dyn Any + Send + Sync>,
+}
+
+impl MergeOperatorOption {
+    /// The operator of records of type `R`, or the name of the record type of the operator if it
+    /// is not `R`
+    pub(crate) fn operator<R: Record>(&self) -> Result<DynMergeOperator<R>, &'static str> {
+        self.operator
+            .downcast_ref::<DynMergeOperator<R>>()
+            .cloned()
+            .ok_or(self.record_type_name)
+    }
+}
+
+impl Debug for MergeOperatorOption {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MergeOperatorOption")
+            .field("record_type", &self.record_type_name)
+            .finish()
+    }
+}
+
+/// Type erased functions of [`DbOption::aggregate`]
+#[derive(Clone)]
+pub(crate) struct AggregateOption {
+    pub(crate) name: String,
+    record_type_name: &'static str,
+    fns: Arc<dyn Any + Send + Sync>,
+}
+
+impl AggregateOption {
+    /// The group and value functions for records of type `R`, or the name of the record type of
+    /// the functions if it is not `R`
+    pub(crate) fn fns<R: Record>(&self) -> Result<(GroupOf<R>, ValueOf<R>), &'static str> {
+        self.fns
+            .downcast_ref::<(GroupOf<R>, ValueOf<R>)>()
+            .cloned()
+            .ok_or(self.record_type_name)
+    }
+}
+
+impl Debug for AggregateOption {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AggregateOption")
+            .field("name", &self.name)
+            .field("record_type", &self.record_type_name)
+            .finish()
+    }
+}
+
 impl DbOption {
+    /// Milliseconds since the UNIX epoch, read from the time source
+    pub(crate) fn now_ms(&self) -> u64 {
+        self.time_source.now_ms()
+    }
+
+    /// Id of a new file, see [`DbOption::file_id_generator`]
+    pub(crate) fn generate_file_id(&self) -> FileId {
+        self.file_ids.generate(self.now_ms())
+    }
+
+    /// Parquet writer properties of the SSTables of `level`
+    pub(crate) fn write_properties(&self, level: usize) -> &WriterProperties {
+        self.level_write_properties[level]
+            .as_ref()
+            .unwrap_or(&self.write_parquet_properties)
+    }
+
     pub(crate) fn table_path(&self, gen: FileId, level: usize) -> Path {
         self.level_paths[level]
             .as_ref()
@@ -260,6 +1196,17 @@ impl DbOption {
             .child(format!("{}.{}", gen, FileType::Wal))
     }
 
+    /// Directory the obsolete WALs are moved to, if any are kept
+    pub(crate) fn retained_wal_dir_path(&self) -> Option<Path> {
+        match &self.wal_retention {
+            WalRetention::Deferred | WalRetention::Immediate => None,
+            WalRetention::KeepFiles(_) | WalRetention::KeepFor(_) => {
+                Some(self.base_path.child("wal_retained"))
+            }
+            WalRetention::Archive(path) => Some(path.clone()),
+        }
+    }
+
     pub(crate) fn version_log_dir_path(&self) -> Path {
         self.base_path.child("version")
     }
@@ -272,6 +1219,12 @@ impl DbOption {
     pub(crate) fn level_fs_path(&self, level: usize) -> Option<&Path> {
         self.level_paths[level].as_ref().map(|(path, _)| path)
     }
+
+    pub(crate) fn pinned_table_path(&self, gen: FileId) -> Option<Path> {
+        self.pinned_tables
+            .as_ref()
+            .map(|(path, _)| path.child(format!("{}.{}", gen, FileType::Parquet)))
+    }
 }
 
 impl Debug for DbOption {
@@ -288,9 +1241,87 @@ impl Debug for DbOption {
             .field("trigger_type", &self.trigger_type)
             .field("use_wal", &self.use_wal)
             .field("max_sst_file_size", &self.max_sst_file_size)
+            .field("max_subcompactions", &self.max_subcompactions)
             .field("wal_buffer_size", &self.wal_buffer_size)
+            .field("wal_recover_parallelism", &self.wal_recover_parallelism)
+            .field("recover_until", &self.recover_until)
             .field("write_parquet_properties", &self.write_parquet_properties)
+            .field("level_write_properties", &self.level_write_properties)
+            .field("bloom_filters", &self.bloom_filters)
+            .field("prefix_bloom_len", &self.prefix_bloom_len)
             .field("compaction_option", &self.compaction_option)
+            .field("negative_cache_capacity", &self.negative_cache_capacity)
+            .field("idempotency_window", &self.idempotency_window)
+            .field("hot_range_tables", &self.hot_range_tables)
+            .field("adaptive_tuning", &self.adaptive_tuning)
+            .field("max_open_files", &self.max_open_files)
+            .field("scan_memory_limit", &self.scan_memory_limit)
+            .field("pinned_tables", &self.pinned_tables)
+            .field("footer_cache", &self.footer_cache)
+            .field("record_checksums", &self.record_checksums)
+            .field("paranoid_checks", &self.paranoid_checks)
+            .field("table_boundary", &self.table_boundary)
+            .field("table_guards", &self.table_guards)
+            .field("max_transaction_entries", &self.max_transaction_entries)
+            .field("max_transaction_bytes", &self.max_transaction_bytes)
+            .field("wal_retention", &self.wal_retention)
+            .field("expiry", &self.expiry)
+            .field("drop_expired", &self.drop_expired)
+            .field("soft_delete", &self.soft_delete)
+            .field("compaction_filter", &self.compaction_filter)
+            .field("aggregates", &self.aggregates)
+            .field("merge_operator", &self.merge_operator)
+            .field("column_changes", &self.column_changes)
+            .field("file_ids", &self.file_ids)
+            .field("time_source", &self.time_source)
             .finish()
     }
 }
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use fusio::path::Path;
+    use fusio_dispatch::FsOptions;
+    use tempfile::TempDir;
+
+    use super::DbOption;
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn presets_open() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = |name: &str| {
+            let dir = temp_dir.path().join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            Path::from_filesystem_path(dir).unwrap()
+        };
+        let presets = [
+            DbOption::write_optimized(path("write"), &TestSchema),
+            DbOption::read_optimized(path("read"), &TestSchema),
+            DbOption::object_store_optimized(path("object_store"), &TestSchema, FsOptions::Local),
+        ];
+        assert!(presets[0].immutable_chunk_max_num > presets[1].immutable_chunk_max_num);
+        assert!(presets[2].max_sst_file_size > presets[1].max_sst_file_size);
+
+        for option in presets {
+            let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+                .await
+                .unwrap();
+            db.insert(Test {
+                vstring: "key".to_string(),
+                vu32: 1,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+            db.flush().await.unwrap();
+            assert!(db
+                .get(&"key".to_string(), |_| Some(()))
+                .await
+                .unwrap()
+                .is_some());
+        }
+    }
+}
@@ -3,9 +3,9 @@ use std::{mem, sync::Arc};
 use arrow::{
     array::{
         Array, ArrayBuilder, ArrayRef, BooleanArray, BooleanBufferBuilder, BooleanBuilder,
-        Date32Builder, Date64Builder, FixedSizeBinaryBuilder, Float32Builder, Float64Builder,
-        GenericBinaryBuilder, Int16Builder, Int32Builder, Int64Builder, Int8Builder,
-        LargeStringBuilder, PrimitiveBuilder, StringBuilder, Time32MillisecondBuilder,
+        Date32Builder, Date64Builder, Decimal128Builder, FixedSizeBinaryBuilder, Float32Builder,
+        Float64Builder, GenericBinaryBuilder, Int16Builder, Int32Builder, Int64Builder,
+        Int8Builder, LargeStringBuilder, PrimitiveBuilder, StringBuilder, Time32MillisecondBuilder,
         Time32SecondBuilder, Time64MicrosecondBuilder, Time64NanosecondBuilder,
         TimestampMicrosecondBuilder, TimestampMillisecondBuilder, TimestampNanosecondBuilder,
         TimestampSecondBuilder, UInt16Builder, UInt32Builder, UInt64Builder, UInt8Builder,
@@ -16,7 +16,12 @@ use arrow::{
     },
 };
 
-use super::{record::DynRecord, record_ref::DynRecordRef, AsValue, DataType};
+use super::{
+    record::DynRecord,
+    record_ref::DynRecordRef,
+    schema::{primary_key_indices, projected_columns},
+    AsValue, DataType,
+};
 use crate::{
     magic::USER_COLUMN_OFFSET,
     record::{
@@ -87,7 +92,10 @@ macro_rules! implement_arrow_array {
                             }
                         )*
                         DataType::FixedSizeBinary(w) => builders.push(Box::new(FixedSizeBinaryBuilder::with_capacity(capacity, *w))),
-                        DataType::List(_field) => builders.push(Box::new(NestedBuilder::with_capacity(field.clone(), capacity))),
+                        DataType::Decimal128 { .. } => builders.push(Box::new(
+                            Decimal128Builder::with_capacity(capacity).with_data_type(field.data_type().clone()),
+                        )),
+                        DataType::List(_) | DataType::Struct(_) => builders.push(Box::new(NestedBuilder::with_capacity(field.clone(), capacity))),
                         DataType::Time32(_) | DataType::Time64(_) => unreachable!(),
                     }
                     datatypes.push(datatype);
@@ -117,21 +125,21 @@ macro_rules! implement_arrow_array {
 
                 let schema = self.record_batch.schema();
                 let metadata = schema.metadata();
-                let primary_key_index = metadata
-                    .get("primary_key_index")
-                    .unwrap()
-                    .parse::<usize>()
-                    .unwrap();
+                let primary_indices = primary_key_indices(metadata);
+                let projected = projected_columns(
+                    projection_mask,
+                    self.arrays.iter().map(|array| array.data_type()),
+                );
                 let mut columns = vec![];
-                for (idx, array) in self.arrays.iter().enumerate() {
-                    if projection_mask.leaf_included(idx + USER_COLUMN_OFFSET) {
+                for (array, projected) in self.arrays.iter().zip(projected) {
+                    if projected {
                         let value_ref = ValueRef::from_array_ref(array, offset).unwrap();
                         columns.push(value_ref);
                     } else {
                         columns.push(ValueRef::Null);
                     }
                 }
-                Some(Some(DynRecordRef::new(columns, primary_key_index)))
+                Some(Some(DynRecordRef::with_primary_indices(columns, primary_indices)))
             }
 
             fn as_record_batch(&self) -> &arrow::array::RecordBatch {
@@ -215,10 +223,22 @@ macro_rules! implement_builder_array {
                                         None => bd.append_value(vec![0; w as usize]).unwrap(),
                                     }
                                 }
-                                DataType::List(_field) =>{
+                                DataType::Decimal128 { .. } => {
+                                    let bd = Self::as_builder_mut::<Decimal128Builder>(builder.as_mut());
+                                    match col.as_i128_opt() {
+                                        Some(value) => bd.append_value(*value),
+                                        None if is_nullable => bd.append_null(),
+                                        None => bd.append_value(Default::default()),
+                                    }
+                                }
+                                DataType::List(_) | DataType::Struct(_) => {
                                     let bd = Self::as_builder_mut::<NestedBuilder>(builder.as_mut());
-                                    // TODO: remove this clone
-                                    bd.append_value(col.clone())
+                                    if col.is_null() && !is_nullable {
+                                        bd.append_default();
+                                    } else {
+                                        // TODO: remove this clone
+                                        bd.append_value(col.clone())
+                                    }
                                 }
                                 DataType::Time32(_) | DataType::Time64(_) => unreachable!(),
                             }
@@ -227,13 +247,7 @@ macro_rules! implement_builder_array {
                     None => {
                         // For tombstones (row == None), ensure the primary key column is still
                         // populated from the provided key, so ordering and lookups remain correct.
-                        let primary_key_index = self
-                            .schema
-                            .metadata()
-                            .get("primary_key_index")
-                            .expect("primary key index must exist in schema metadata")
-                            .parse::<usize>()
-                            .expect("primary key index must be a valid usize");
+                        let primary_indices = primary_key_indices(self.schema.metadata());
 
                         for (idx, (builder, datatype)) in self
                             .builders
@@ -244,12 +258,18 @@ macro_rules! implement_builder_array {
                             let field = self.schema.field(idx + USER_COLUMN_OFFSET);
                             let is_nullable = field.is_nullable();
 
-                            if idx == primary_key_index {
+                            let key_position = primary_indices.iter().position(|index| *index == idx);
+                            if let Some(position) = key_position {
+                                // a composite key holds the value of each of its columns in order
+                                let key_value = match &key.value {
+                                    ValueRef::Composite(values) => &values[position],
+                                    value => value,
+                                };
                                 match datatype {
                                     $(
                                         $primitive_pat => {
                                             let bd = Self::as_builder_mut::<PrimitiveBuilder<$arrow_ty>>(builder.as_mut());
-                                            match key.value.$as_primitive_value() {
+                                            match key_value.$as_primitive_value() {
                                                 Some(value) => bd.append_value(*value),
                                                 None => bd.append_value(Default::default()),
                                             }
@@ -257,7 +277,7 @@ macro_rules! implement_builder_array {
                                     )*
                                     DataType::Boolean => {
                                         let bd = Self::as_builder_mut::<BooleanBuilder>(builder.as_mut());
-                                        match key.value.as_bool_opt() {
+                                        match key_value.as_bool_opt() {
                                             Some(value) => bd.append_value(*value),
                                             None => bd.append_value(Default::default()),
                                         }
@@ -265,7 +285,7 @@ macro_rules! implement_builder_array {
                                     $(
                                         $alt_variant => {
                                             let bd = Self::as_builder_mut::<$builder_ty>(builder.as_mut());
-                                            match key.value.$as_alt_value() {
+                                            match key_value.$as_alt_value() {
                                                 Some(value) => bd.append_value(value),
                                                 None => bd.append_value(<$alt_ty>::default()),
                                             }
@@ -274,7 +294,7 @@ macro_rules! implement_builder_array {
                                     $(
                                         $alt_variant2 => {
                                             let bd = Self::as_builder_mut::<$builder_ty2>(builder.as_mut());
-                                            match key.value.$as_alt_value2() {
+                                            match key_value.$as_alt_value2() {
                                                 Some(value) => bd.append_value(*value),
                                                 None => bd.append_value(Default::default()),
                                             }
@@ -282,13 +302,20 @@ macro_rules! implement_builder_array {
                                     )*
                                     DataType::FixedSizeBinary(w) => {
                                         let bd = Self::as_builder_mut::<FixedSizeBinaryBuilder>(builder.as_mut());
-                                        match key.value.as_bytes_opt() {
+                                        match key_value.as_bytes_opt() {
                                             Some(value) => bd.append_value(value).unwrap(),
                                             None => bd.append_value(vec![0; *w as usize]).unwrap(),
                                         }
                                     }
-                                    DataType::List(_field) => {
-                                        // Lists are not allowed as primary keys; append default
+                                    DataType::Decimal128 { .. } => {
+                                        let bd = Self::as_builder_mut::<Decimal128Builder>(builder.as_mut());
+                                        match key_value.as_i128_opt() {
+                                            Some(value) => bd.append_value(*value),
+                                            None => bd.append_value(Default::default()),
+                                        }
+                                    }
+                                    DataType::List(_) | DataType::Struct(_) => {
+                                        // Lists and structs are not allowed as primary keys; append default
                                         let bd = Self::as_builder_mut::<NestedBuilder>(builder.as_mut());
                                         bd.append_default();
                                     }
@@ -322,7 +349,11 @@ macro_rules! implement_builder_array {
                                         Self::as_builder_mut::<FixedSizeBinaryBuilder>(builder.as_mut())
                                             .append_value(vec![0; *w as usize]).unwrap();
                                     }
-                                    DataType::List(_field) =>{
+                                    DataType::Decimal128 { .. } => {
+                                        Self::as_builder_mut::<Decimal128Builder>(builder.as_mut())
+                                            .append_value(i128::default());
+                                    }
+                                    DataType::List(_) | DataType::Struct(_) => {
                                         let bd = Self::as_builder_mut::<NestedBuilder>(builder.as_mut());
                                         if is_nullable {
                                             bd.append_null();
@@ -370,7 +401,10 @@ macro_rules! implement_builder_array {
                             DataType::FixedSizeBinary(_) => mem::size_of_val(
                                 Self::as_builder::<FixedSizeBinaryBuilder>(builder.as_ref()).values_slice()
                             ),
-                            DataType::List(_) => {
+                            DataType::Decimal128 { .. } => mem::size_of_val(
+                                Self::as_builder::<Decimal128Builder>(builder.as_ref()).values_slice()
+                            ),
+                            DataType::List(_) | DataType::Struct(_) => {
                                 Self::as_builder::<NestedBuilder>(builder.as_ref()).bytes_written()
                             },
                             DataType::Time32(_) | DataType::Time64(_) => unreachable!(),
@@ -427,7 +461,14 @@ macro_rules! implement_builder_array {
                             );
                             array_refs.push(array.clone());
                         }
-                        DataType::List(_) => {
+                        DataType::Decimal128 { .. } => {
+                            let array = Arc::new(
+                                Self::as_builder_mut::<Decimal128Builder>(builder.as_mut())
+                                    .finish(),
+                            );
+                            array_refs.push(array.clone());
+                        }
+                        DataType::List(_) | DataType::Struct(_) => {
                             let array = Arc::new(
                                 Self::as_builder_mut::<NestedBuilder>(builder.as_mut())
                                     .finish(),
@@ -749,6 +790,69 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_build_decimal128() {
+        use arrow::array::AsArray;
+
+        let schema = DynSchema::new(
+            &vec![
+                DynamicField::new("price".into(), DataType::Decimal128(10, 2), false),
+                DynamicField::new("discount".into(), DataType::Decimal128(5, 3), true),
+            ][..],
+            0,
+        );
+
+        let record1 = DynRecord::new(
+            vec![
+                Value::Decimal128(12345, 10, 2),
+                Value::Decimal128(-50, 5, 3),
+            ],
+            0,
+        );
+        let record2 = DynRecord::new(vec![Value::Decimal128(99, 10, 2), Value::Null], 0);
+
+        let mut builder = DynRecordImmutableArrays::builder(schema.arrow_schema().clone(), 5);
+        let key1 = crate::version::timestamp::Ts {
+            ts: 0.into(),
+            value: record1.key(),
+        };
+        let key2 = crate::version::timestamp::Ts {
+            ts: 0.into(),
+            value: record2.key(),
+        };
+        builder.push(key1.clone(), Some(record1.as_record_ref()));
+        builder.push(key1, None);
+        builder.push(key2, Some(record2.as_record_ref()));
+        let arrays = builder.finish(None);
+
+        let record_batch = arrays.as_record_batch();
+        assert_eq!(
+            record_batch.column(2).data_type(),
+            &DataType::Decimal128(10, 2)
+        );
+        assert_eq!(
+            record_batch.column(3).data_type(),
+            &DataType::Decimal128(5, 3)
+        );
+        // the tombstone keeps its key
+        assert_eq!(
+            record_batch
+                .column(2)
+                .as_primitive::<arrow::datatypes::Decimal128Type>()
+                .value(1),
+            12345
+        );
+        for (offset, record) in [(0, &record1), (2, &record2)] {
+            let cols = arrays
+                .get(offset, &ProjectionMask::all())
+                .unwrap()
+                .unwrap()
+                .columns;
+            assert_eq!(cols, record.as_record_ref().columns);
+        }
+        assert!(arrays.get(1, &ProjectionMask::all()).unwrap().is_none());
+    }
+
     #[tokio::test]
     async fn test_build_list() {
         let ty1 = DataType::List(Arc::new(Field::new("code", DataType::UInt16, true)));
@@ -859,4 +963,97 @@ mod tests {
             }
         }
     }
+
+    #[tokio::test]
+    async fn test_build_struct() {
+        use arrow::datatypes::Fields;
+
+        use crate::record::ValueRef;
+
+        let fields = Fields::from(vec![
+            Field::new("city", DataType::Utf8, false),
+            Field::new("zip", DataType::UInt32, true),
+        ]);
+        let schema = DynSchema::new(
+            &[
+                DynamicField::new("id".into(), DataType::UInt64, false),
+                DynamicField::new("address".into(), DataType::Struct(fields.clone()), true),
+                DynamicField::new("name".into(), DataType::Utf8, true),
+            ],
+            0,
+        );
+
+        let record1 = DynRecord::new(
+            vec![
+                Value::UInt64(1),
+                Value::Struct(
+                    fields.clone(),
+                    vec![Value::String("Berlin".into()), Value::UInt32(10115)],
+                ),
+                Value::String("tonbo".into()),
+            ],
+            0,
+        );
+        let record2 = DynRecord::new(
+            vec![
+                Value::UInt64(2),
+                Value::Struct(fields.clone(), vec![Value::Null, Value::Null]),
+                Value::Null,
+            ],
+            0,
+        );
+        let record3 = DynRecord::new(
+            vec![Value::UInt64(3), Value::Null, Value::String("tonbo".into())],
+            0,
+        );
+
+        let mut builder = DynRecordImmutableArrays::builder(schema.arrow_schema().clone(), 3);
+        for record in [&record1, &record2, &record3] {
+            let key = crate::version::timestamp::Ts {
+                ts: 0.into(),
+                value: record.key(),
+            };
+            builder.push(key, Some(record.as_record_ref()));
+        }
+        let arrays = builder.finish(None);
+
+        let cols = arrays
+            .get(0, &ProjectionMask::all())
+            .unwrap()
+            .unwrap()
+            .columns;
+        assert_eq!(cols, record1.as_record_ref().columns);
+        // the city is not nullable, so it is the default of a string
+        let cols = arrays
+            .get(1, &ProjectionMask::all())
+            .unwrap()
+            .unwrap()
+            .columns;
+        assert_eq!(
+            cols[1],
+            ValueRef::Struct(&fields, vec![ValueRef::String(""), ValueRef::Null])
+        );
+        let cols = arrays
+            .get(2, &ProjectionMask::all())
+            .unwrap()
+            .unwrap()
+            .columns;
+        assert_eq!(cols, record3.as_record_ref().columns);
+
+        // the struct takes the leaf columns of both of its fields
+        let mask = schema.projection(["name"]);
+        let cols = arrays.get(0, &mask).unwrap().unwrap().columns;
+        assert_eq!(
+            cols,
+            vec![
+                ValueRef::UInt64(1),
+                ValueRef::Null,
+                ValueRef::String("tonbo")
+            ]
+        );
+        let mask = schema.projection(["address"]);
+        let cols = arrays.get(0, &mask).unwrap().unwrap().columns;
+        assert_eq!(cols[1], record1.as_record_ref().columns[1]);
+        assert_eq!(cols[2], ValueRef::Null);
+    }
 }
@@ -2,15 +2,16 @@ use std::sync::Arc;
 
 use arrow::{
     array::{
-        make_builder, ArrayBuilder, ArrayRef, BinaryBuilder, BooleanBuilder, Date32Builder,
-        Date64Builder, FixedSizeBinaryBuilder, Float32Builder, Float64Builder, Int16Builder,
-        Int32Builder, Int64Builder, Int8Builder, LargeBinaryBuilder, LargeStringBuilder,
-        ListBuilder, StringBuilder, Time32MillisecondBuilder, Time32SecondBuilder,
-        Time64MicrosecondBuilder, Time64NanosecondBuilder, TimestampMicrosecondBuilder,
-        TimestampMillisecondBuilder, TimestampNanosecondBuilder, TimestampSecondBuilder,
-        UInt16Builder, UInt32Builder, UInt64Builder, UInt8Builder,
+        make_builder, ArrayBuilder, ArrayRef, BinaryBuilder, BooleanBufferBuilder, BooleanBuilder,
+        Date32Builder, Date64Builder, Decimal128Builder, FixedSizeBinaryBuilder, Float32Builder,
+        Float64Builder, Int16Builder, Int32Builder, Int64Builder, Int8Builder, LargeBinaryBuilder,
+        LargeStringBuilder, ListBuilder, StringBuilder, StructArray, Time32MillisecondBuilder,
+        Time32SecondBuilder, Time64MicrosecondBuilder, Time64NanosecondBuilder,
+        TimestampMicrosecondBuilder, TimestampMillisecondBuilder, TimestampNanosecondBuilder,
+        TimestampSecondBuilder, UInt16Builder, UInt32Builder, UInt64Builder, UInt8Builder,
     },
-    datatypes::{DataType, Field, FieldRef, TimeUnit},
+    buffer::NullBuffer,
+    datatypes::{DataType, Field, FieldRef, Fields, TimeUnit},
 };
 use fusio_log::Encode;
 
@@ -27,7 +28,7 @@ pub struct NestedBuilder {
 impl NestedBuilder {
     /// Create a new [`NestedBuilder``] with the specified field and capacity
     pub fn with_capacity(field: FieldRef, capacity: usize) -> Self {
-        let builder = make_builder(field.data_type(), capacity);
+        let builder = make_nested_builder(field.data_type(), capacity);
         Self {
             builder,
             field,
@@ -150,7 +151,14 @@ impl NestedBuilder {
             }
             DataType::FixedSizeBinary(_) => {
                 let bd = Self::as_builder_mut::<FixedSizeBinaryBuilder>(builder);
-                bd.append_value(value.as_bytes()).unwrap();
+                match value.as_bytes_opt() {
+                    Some(value) => bd.append_value(value).unwrap(),
+                    None => bd.append_null(),
+                }
+            }
+            DataType::Decimal128(_, _) => {
+                let bd = Self::as_builder_mut::<Decimal128Builder>(builder);
+                bd.append_option(value.as_i128_opt().copied());
             }
             DataType::LargeBinary => {
                 let bd = Self::as_builder_mut::<LargeBinaryBuilder>(builder);
@@ -178,28 +186,40 @@ impl NestedBuilder {
                     _ => unreachable!(),
                 }
             }
-            DataType::Struct(_) => todo!(),
+            DataType::Struct(fields) => {
+                let bd = Self::as_builder_mut::<StructValueBuilder>(builder);
+
+                match value {
+                    ValueRef::Null => {
+                        for (child, field) in bd.builders.iter_mut().zip(fields.iter()) {
+                            Self::append_value_inner(child, field.data_type(), ValueRef::Null);
+                        }
+                        bd.validity.append(false);
+                    }
+                    ValueRef::Struct(_, values) => {
+                        for ((child, field), value) in
+                            bd.builders.iter_mut().zip(fields.iter()).zip(values)
+                        {
+                            let value = if value.is_null() && !field.is_nullable() {
+                                Self::default_value(field.data_type())
+                            } else {
+                                value
+                            };
+                            Self::append_value_inner(child, field.data_type(), value);
+                        }
+                        bd.validity.append(true);
+                    }
+                    _ => unreachable!(),
+                }
+            }
             _ => unimplemented!(),
         }
     }
 
-    /// Append a value to this [`NestedBuilder``]
-    /// For performance, this method does not check the validity of the value. But the value must
-    /// match the field's data type.
-    pub fn append_value(&mut self, value: ValueRef) {
-        self.bytes_written += value.size();
-        Self::append_value_inner(&mut self.builder, self.field.data_type(), value);
-    }
-
-    /// Append a null value
-    pub fn append_null(&mut self) {
-        self.append_value(ValueRef::Null);
-    }
-
-    /// Append a default value to this [`NestedBuilder`]
-    pub fn append_default(&mut self) {
-        let data_type = self.field.data_type().clone();
-        let value = match &data_type {
+    /// Default value of `data_type`, a struct of the default values of its fields that are not
+    /// nullable and nulls for the others
+    fn default_value(data_type: &DataType) -> ValueRef<'_> {
+        match data_type {
             DataType::Null => ValueRef::Null,
             DataType::Boolean => ValueRef::Boolean(bool::default()),
             DataType::Int8 => ValueRef::Int8(i8::default()),
@@ -221,11 +241,45 @@ impl NestedBuilder {
             DataType::Time64(time_unit) => ValueRef::Time64(i64::default(), (*time_unit).into()),
             DataType::Binary => ValueRef::Binary(&[]),
             DataType::FixedSizeBinary(w) => ValueRef::FixedSizeBinary(&[], *w as u32),
+            DataType::Decimal128(precision, scale) => {
+                ValueRef::Decimal128(i128::default(), *precision, *scale)
+            }
             DataType::Utf8 => ValueRef::String(""),
             DataType::List(field) => ValueRef::List(field.data_type(), vec![]),
+            DataType::Struct(fields) => ValueRef::Struct(
+                fields,
+                fields
+                    .iter()
+                    .map(|field| {
+                        if field.is_nullable() {
+                            ValueRef::Null
+                        } else {
+                            Self::default_value(field.data_type())
+                        }
+                    })
+                    .collect(),
+            ),
             _ => unreachable!(),
-        };
-        self.append_value(value);
+        }
+    }
+
+    /// Append a value to this [`NestedBuilder``]
+    /// For performance, this method does not check the validity of the value. But the value must
+    /// match the field's data type.
+    pub fn append_value(&mut self, value: ValueRef) {
+        self.bytes_written += value.size();
+        Self::append_value_inner(&mut self.builder, self.field.data_type(), value);
+    }
+
+    /// Append a null value
+    pub fn append_null(&mut self) {
+        self.append_value(ValueRef::Null);
+    }
+
+    /// Append a default value to this [`NestedBuilder`]
+    pub fn append_default(&mut self) {
+        let data_type = self.field.data_type().clone();
+        self.append_value(Self::default_value(&data_type));
     }
 
     #[allow(unused)]
@@ -266,6 +320,81 @@ impl ArrayBuilder for NestedBuilder {
     }
 }
 
+/// Builder of arrays of `data_type` that [`NestedBuilder`] can append values to, which unlike
+/// [`make_builder`] builds structs, also those in lists, with a [`StructValueBuilder`], as the
+/// children of an arrow `StructBuilder` can not be downcast without knowing their types
+fn make_nested_builder(data_type: &DataType, capacity: usize) -> Box<dyn ArrayBuilder> {
+    match data_type {
+        DataType::List(field) => Box::new(
+            ListBuilder::with_capacity(make_nested_builder(field.data_type(), capacity), capacity)
+                .with_field(field.clone()),
+        ),
+        DataType::Struct(fields) => {
+            Box::new(StructValueBuilder::with_capacity(fields.clone(), capacity))
+        }
+        _ => make_builder(data_type, capacity),
+    }
+}
+
+/// Array builder for structs, with a builder made by [`make_nested_builder`] for every field
+struct StructValueBuilder {
+    fields: Fields,
+    builders: Vec<Box<dyn ArrayBuilder>>,
+    validity: BooleanBufferBuilder,
+}
+
+impl StructValueBuilder {
+    fn with_capacity(fields: Fields, capacity: usize) -> Self {
+        let builders = fields
+            .iter()
+            .map(|field| make_nested_builder(field.data_type(), capacity))
+            .collect();
+        Self {
+            fields,
+            builders,
+            validity: BooleanBufferBuilder::new(capacity),
+        }
+    }
+}
+
+impl ArrayBuilder for StructValueBuilder {
+    fn len(&self) -> usize {
+        self.validity.len()
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        let arrays = self
+            .builders
+            .iter_mut()
+            .map(|builder| builder.finish())
+            .collect();
+        let nulls = NullBuffer::new(self.validity.finish());
+        Arc::new(StructArray::new(self.fields.clone(), arrays, Some(nulls)))
+    }
+
+    fn finish_cloned(&self) -> ArrayRef {
+        let arrays = self
+            .builders
+            .iter()
+            .map(|builder| builder.finish_cloned())
+            .collect();
+        let nulls = NullBuffer::new(self.validity.finish_cloned());
+        Arc::new(StructArray::new(self.fields.clone(), arrays, Some(nulls)))
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn into_box_any(self: Box<Self>) -> Box<dyn std::any::Any> {
+        self
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use arrow::{
@@ -0,0 +1,368 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    mem,
+};
+
+use arrow::datatypes::{DataType as ArrowDataType, Schema as ArrowSchema};
+use thiserror::Error;
+
+use super::{DynRecordRef, Value, ValueRef};
+use crate::magic::USER_COLUMN_OFFSET;
+
+/// Comparison of two operands of an [`Expr`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+impl Display for CmpOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CmpOp::Eq => "=",
+            CmpOp::NotEq => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::LtEq => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::GtEq => ">=",
+        })
+    }
+}
+
+/// Predicate on the columns of [`DynRecord`](super::DynRecord)s, see
+/// [`Scan::filter`](crate::Scan::filter)
+///
+/// Comparisons with a null operand are unknown, and so are `and` and `or` of unknown operands
+/// unless the other operand decides them, as in SQL. Records match if the predicate is true.
+///
+/// ```ignore
+/// let filter = Expr::col("age")
+///     .gt_eq(Expr::lit(Value::UInt8(18)))
+///     .and(Expr::col("name").not_eq(Expr::lit(Value::String("root".into()))));
+/// ```
+#[derive(Debug, Clone)]
+pub enum Expr {
+    /// Value of the column with the name
+    Column(String),
+    /// A constant
+    Literal(Value),
+    /// Comparison of two columns or literals
+    Compare(Box<Expr>, CmpOp, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// An [`Expr`] that does not fit the schema of the records it filters
+#[derive(Debug, Error)]
+pub enum ExprError {
+    #[error("expression refers to unknown column {0}")]
+    UnknownColumn(String),
+    #[error("expression compares {left} with {right}")]
+    Type {
+        left: ArrowDataType,
+        right: ArrowDataType,
+    },
+    #[error("expression {0} is not a predicate")]
+    NotPredicate(String),
+    #[error("expression {0} is not a column or literal")]
+    NotOperand(String),
+}
+
+impl Expr {
+    /// The column called `name`
+    pub fn col(name: impl Into<String>) -> Self {
+        Expr::Column(name.into())
+    }
+
+    /// The constant `value`
+    pub fn lit(value: Value) -> Self {
+        Expr::Literal(value)
+    }
+
+    fn compare(self, op: CmpOp, other: Expr) -> Self {
+        Expr::Compare(Box::new(self), op, Box::new(other))
+    }
+
+    pub fn eq(self, other: Expr) -> Self {
+        self.compare(CmpOp::Eq, other)
+    }
+
+    pub fn not_eq(self, other: Expr) -> Self {
+        self.compare(CmpOp::NotEq, other)
+    }
+
+    pub fn lt(self, other: Expr) -> Self {
+        self.compare(CmpOp::Lt, other)
+    }
+
+    pub fn lt_eq(self, other: Expr) -> Self {
+        self.compare(CmpOp::LtEq, other)
+    }
+
+    pub fn gt(self, other: Expr) -> Self {
+        self.compare(CmpOp::Gt, other)
+    }
+
+    pub fn gt_eq(self, other: Expr) -> Self {
+        self.compare(CmpOp::GtEq, other)
+    }
+
+    pub fn and(self, other: Expr) -> Self {
+        Expr::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: Expr) -> Self {
+        Expr::Or(Box::new(self), Box::new(other))
+    }
+
+    /// Resolve the columns of the predicate in `schema`, the Arrow schema of the records including
+    /// the internal `_null` and `_ts` columns
+    pub(crate) fn bind(&self, schema: &ArrowSchema) -> Result<Predicate, ExprError> {
+        match self {
+            Expr::Compare(left, op, right) => {
+                let (left, left_type) = left.bind_operand(schema)?;
+                let (right, right_type) = right.bind_operand(schema)?;
+                if let (Some(left), Some(right)) = (left_type, right_type) {
+                    if !comparable(&left, &right) {
+                        return Err(ExprError::Type { left, right });
+                    }
+                }
+                Ok(Predicate::Compare(left, *op, right))
+            }
+            Expr::And(left, right) => Ok(Predicate::And(
+                Box::new(left.bind(schema)?),
+                Box::new(right.bind(schema)?),
+            )),
+            Expr::Or(left, right) => Ok(Predicate::Or(
+                Box::new(left.bind(schema)?),
+                Box::new(right.bind(schema)?),
+            )),
+            Expr::Column(_) | Expr::Literal(_) => Err(ExprError::NotPredicate(self.to_string())),
+        }
+    }
+
+    // The operand and its type, unless it is a null literal
+    fn bind_operand(
+        &self,
+        schema: &ArrowSchema,
+    ) -> Result<(Operand, Option<ArrowDataType>), ExprError> {
+        match self {
+            Expr::Column(name) => {
+                let index = schema
+                    .fields()
+                    .iter()
+                    .skip(USER_COLUMN_OFFSET)
+                    .position(|field| field.name() == name)
+                    .ok_or_else(|| ExprError::UnknownColumn(name.clone()))?;
+                let data_type = schema.field(index + USER_COLUMN_OFFSET).data_type().clone();
+
+                Ok((Operand::Column(index), Some(data_type)))
+            }
+            Expr::Literal(Value::Null) => Ok((Operand::Literal(Value::Null), None)),
+            Expr::Literal(value) => Ok((Operand::Literal(value.clone()), Some(value.data_type()))),
+            _ => Err(ExprError::NotOperand(self.to_string())),
+        }
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Column(name) => f.write_str(name),
+            Expr::Literal(value) => write!(f, "{value:?}"),
+            Expr::Compare(left, op, right) => write!(f, "{left} {op} {right}"),
+            Expr::And(left, right) => write!(f, "({left} AND {right})"),
+            Expr::Or(left, right) => write!(f, "({left} OR {right})"),
+        }
+    }
+}
+
+// Whether values of the types can be compared, e.g. strings of any offset size and times of any
+// unit
+fn comparable(left: &ArrowDataType, right: &ArrowDataType) -> bool {
+    use ArrowDataType::*;
+
+    match (left, right) {
+        (Utf8 | LargeUtf8, Utf8 | LargeUtf8) | (Binary | LargeBinary, Binary | LargeBinary) => true,
+        (Timestamp(..), Timestamp(..)) | (Time32(_), Time32(_)) | (Time64(_), Time64(_)) => true,
+        _ => left == right,
+    }
+}
+
+pub(crate) enum Operand {
+    // Index of the column among the user columns
+    Column(usize),
+    Literal(Value),
+}
+
+impl Operand {
+    fn value<'a>(&'a self, record: &DynRecordRef<'a>) -> ValueRef<'a> {
+        match self {
+            Operand::Column(index) => record.columns[*index].clone(),
+            Operand::Literal(value) => ValueRef::from(value),
+        }
+    }
+}
+
+/// An [`Expr`] bound to the columns of a schema
+pub(crate) enum Predicate {
+    Compare(Operand, CmpOp, Operand),
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+}
+
+impl Predicate {
+    /// Whether `record` matches the predicate
+    pub(crate) fn matches(&self, record: &DynRecordRef<'_>) -> bool {
+        self.evaluate(record) == Some(true)
+    }
+
+    // `None` if the outcome is unknown
+    fn evaluate(&self, record: &DynRecordRef<'_>) -> Option<bool> {
+        match self {
+            Predicate::Compare(left, op, right) => {
+                let (left, right) = (left.value(record), right.value(record));
+                // the types were checked when the expression was bound, but columns of a
+                // projection that leaves them out read as null
+                if left.is_null()
+                    || right.is_null()
+                    || mem::discriminant(&left) != mem::discriminant(&right)
+                {
+                    return None;
+                }
+                let ordering = left.cmp(&right);
+                Some(match op {
+                    CmpOp::Eq => ordering.is_eq(),
+                    CmpOp::NotEq => ordering.is_ne(),
+                    CmpOp::Lt => ordering.is_lt(),
+                    CmpOp::LtEq => ordering.is_le(),
+                    CmpOp::Gt => ordering.is_gt(),
+                    CmpOp::GtEq => ordering.is_ge(),
+                })
+            }
+            Predicate::And(left, right) => match (left.evaluate(record), right.evaluate(record)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            Predicate::Or(left, right) => match (left.evaluate(record), right.evaluate(record)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::datatypes::{DataType, Field, Schema};
+
+    use super::{Expr, ExprError};
+    use crate::record::{DynRecord, Record, Value};
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn filter_scan() {
+        use std::ops::Bound;
+
+        use fusio::path::Path;
+        use futures_util::StreamExt;
+        use tempfile::TempDir;
+
+        use crate::{
+            executor::tokio::TokioExecutor,
+            record::dynamic::test::{test_dyn_item_schema, test_dyn_items},
+            DbOption, DB,
+        };
+
+        let temp_dir = TempDir::new().unwrap();
+        let schema = test_dyn_item_schema();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &schema,
+        );
+        let db: DB<DynRecord, TokioExecutor> = DB::new(option, TokioExecutor::default(), schema)
+            .await
+            .unwrap();
+
+        let mut items = test_dyn_items();
+        db.insert_batch(items.clone().into_iter()).await.unwrap();
+        db.flush().await.unwrap();
+        // the newer version of 40 does not match, so the flushed one must not show up either
+        items[40].values[6] = Value::Boolean(false);
+        db.insert(items[40].clone()).await.unwrap();
+
+        let filter = Expr::col("age")
+            .gt_eq(Expr::lit(Value::Int8(40)))
+            .and(Expr::col("enabled").eq(Expr::lit(Value::Boolean(true))));
+        let snapshot = db.snapshot().await;
+        let scan = snapshot
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .filter(&filter)
+            .unwrap()
+            .limit(3);
+        assert!(scan
+            .explain()
+            .to_string()
+            .contains("filter: (age >= Int8(40)"));
+
+        let ids = scan
+            .take()
+            .await
+            .unwrap()
+            .map(|entry| entry.unwrap().value().unwrap().columns[0].to_owned())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            ids,
+            vec![Value::Int64(42), Value::Int64(44), Value::Int64(46)]
+        );
+    }
+
+    #[test]
+    fn evaluate() {
+        let schema = Schema::new(vec![
+            Field::new("_null", DataType::Boolean, false),
+            Field::new("_ts", DataType::UInt32, false),
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+        ]);
+        let record = DynRecord::new(vec![Value::Int64(3), Value::Null], 0);
+        let record = record.as_record_ref();
+        let matches = |expr: Expr| expr.bind(&schema).unwrap().matches(&record);
+
+        assert!(matches(Expr::col("id").gt(Expr::lit(Value::Int64(2)))));
+        assert!(!matches(Expr::col("id").lt(Expr::lit(Value::Int64(2)))));
+        // comparisons with null are unknown, unless `and` or `or` are decided otherwise
+        let unknown = || Expr::col("name").eq(Expr::lit(Value::String("a".into())));
+        assert!(!matches(unknown()));
+        assert!(!matches(
+            unknown().and(Expr::col("id").eq(Expr::lit(Value::Int64(3))))
+        ));
+        assert!(matches(
+            unknown().or(Expr::col("id").eq(Expr::lit(Value::Int64(3))))
+        ));
+
+        assert!(matches!(
+            Expr::col("age")
+                .eq(Expr::lit(Value::Int64(1)))
+                .bind(&schema),
+            Err(ExprError::UnknownColumn(_))
+        ));
+        assert!(matches!(
+            Expr::col("id")
+                .eq(Expr::lit(Value::String("1".into())))
+                .bind(&schema),
+            Err(ExprError::Type { .. })
+        ));
+        assert!(matches!(
+            Expr::col("id").bind(&schema),
+            Err(ExprError::NotPredicate(_))
+        ));
+    }
+}
@@ -1,5 +1,6 @@
 pub(crate) mod array;
 pub(crate) mod builder;
+mod expr;
 mod record;
 mod record_ref;
 mod schema;
@@ -9,6 +10,7 @@ use std::sync::Arc;
 
 pub use array::*;
 use arrow::datatypes::DataType as ArrowDataType;
+pub use expr::*;
 pub use record::*;
 pub use record_ref::*;
 pub use schema::*;
@@ -51,7 +53,18 @@ pub enum DataType {
     ///
     /// See [`arrow::datatypes::DataType::Date64`] for more details.
     Date64,
+    /// An exact decimal number of `precision` digits, `scale` of them after the decimal point.
+    ///
+    /// See [`arrow::datatypes::DataType::Decimal128`] for more details.
+    Decimal128 {
+        precision: u8,
+        scale: i8,
+    },
     List(Arc<DynamicField>),
+    /// A nested object of the fields, stored without flattening them into columns.
+    ///
+    /// See [`arrow::datatypes::DataType::Struct`] for more details.
+    Struct(Vec<DynamicField>),
 }
 
 impl From<&ArrowDataType> for DataType {
@@ -81,9 +94,19 @@ impl From<&ArrowDataType> for DataType {
             ArrowDataType::LargeBinary => DataType::LargeBinary,
             ArrowDataType::LargeUtf8 => DataType::LargeString,
             ArrowDataType::FixedSizeBinary(w) => DataType::FixedSizeBinary(*w),
+            ArrowDataType::Decimal128(precision, scale) => DataType::Decimal128 {
+                precision: *precision,
+                scale: *scale,
+            },
             ArrowDataType::List(field) => {
                 DataType::List(Arc::new(DynamicField::from(field.as_ref())))
             }
+            ArrowDataType::Struct(fields) => DataType::Struct(
+                fields
+                    .iter()
+                    .map(|field| DynamicField::from(field.as_ref()))
+                    .collect(),
+            ),
             _ => todo!("datatype: {datatype:?}"),
         }
     }
@@ -1,22 +1,36 @@
+use arrow::datatypes::DataType;
 use fusio::SeqRead;
 use fusio_log::{Decode, Encode};
 
 use super::{schema::DynSchema, DynRecordRef, Value, ValueError};
-use crate::record::{error::RecordError, Key, Record};
+use crate::{
+    magic::USER_COLUMN_OFFSET,
+    record::{error::RecordError, Key, Record, Schema},
+};
+
+// Written in place of the primary key index of a record whose key has several columns, followed
+// by their number and indices
+pub(crate) const COMPOSITE_KEY_MARKER: u32 = u32::MAX;
 
 #[derive(Debug)]
 pub struct DynRecord {
     values: Vec<Value>,
-    primary_index: usize,
+    primary_indices: Vec<usize>,
 }
 
 #[allow(unused)]
 impl DynRecord {
     /// Create a new DynRecord without validation.
     pub fn new(values: Vec<Value>, primary_index: usize) -> Self {
+        Self::with_primary_indices(values, vec![primary_index])
+    }
+
+    /// Create a new DynRecord whose primary key is made of the values at `primary_indices`, see
+    /// [`DynSchema::with_primary_indices`], without validation.
+    pub fn with_primary_indices(values: Vec<Value>, primary_indices: Vec<usize>) -> Self {
         Self {
             values,
-            primary_index,
+            primary_indices,
         }
     }
 
@@ -26,16 +40,36 @@ impl DynRecord {
     ///
     /// Returns an error if the validation failed.
     pub fn try_new(values: Vec<Value>, primary_index: usize) -> Result<Self, RecordError> {
-        if primary_index >= values.len() {
-            return Err(RecordError::InvalidArgumentError(format!(
-                "primary key index {} can not great or equal than value length {}",
-                primary_index,
-                values.len()
-            )));
+        Self::try_with_primary_indices(values, vec![primary_index])
+    }
+
+    /// Create a new DynRecord whose primary key is made of the values at `primary_indices` with
+    /// validation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the validation failed.
+    pub fn try_with_primary_indices(
+        values: Vec<Value>,
+        primary_indices: Vec<usize>,
+    ) -> Result<Self, RecordError> {
+        if primary_indices.is_empty() {
+            return Err(RecordError::InvalidArgumentError(
+                "primary key must have at least one column".into(),
+            ));
+        }
+        for primary_index in primary_indices.iter() {
+            if *primary_index >= values.len() {
+                return Err(RecordError::InvalidArgumentError(format!(
+                    "primary key index {} can not great or equal than value length {}",
+                    primary_index,
+                    values.len()
+                )));
+            }
         }
         for (idx, value) in values.iter().enumerate() {
             match value {
-                Value::Null if idx == primary_index => {
+                Value::Null if primary_indices.contains(&idx) => {
                     return Err(RecordError::NullNotAllowed(
                         "Primary can not be null".into(),
                     ))
@@ -54,9 +88,132 @@ impl DynRecord {
 
         Ok(Self {
             values,
-            primary_index,
+            primary_indices,
         })
     }
+
+    /// Builder of a record of `schema` that sets its values by column name, see
+    /// [`DynRecordBuilder`]
+    ///
+    /// ```ignore
+    /// let record = DynRecord::builder(&schema)
+    ///     .set("id", 1i64)?
+    ///     .set("name", "tonbo")?
+    ///     .build()?;
+    /// ```
+    pub fn builder(schema: &DynSchema) -> DynRecordBuilder<'_> {
+        let columns = schema.arrow_schema().fields().len() - USER_COLUMN_OFFSET;
+        DynRecordBuilder {
+            schema,
+            values: vec![None; columns],
+        }
+    }
+
+    /// Values of the columns of the record, in the order of its schema
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// Value of the column `name` of `schema`, the schema of the record, whose typed contents
+    /// the [`AsValue`](crate::record::AsValue) accessors return
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RecordError::UnknownColumn`] if `schema` has no column `name` or the record has
+    /// no value for it.
+    pub fn get(&self, schema: &DynSchema, name: &str) -> Result<&Value, RecordError> {
+        schema
+            .column_index(name)
+            .and_then(|index| self.values.get(index))
+            .ok_or_else(|| RecordError::UnknownColumn(name.to_string()))
+    }
+}
+
+/// Builder of a [`DynRecord`] that checks each value against the column of the schema it is set
+/// for, created by [`DynRecord::builder`]
+#[derive(Debug)]
+pub struct DynRecordBuilder<'s> {
+    schema: &'s DynSchema,
+    values: Vec<Option<Value>>,
+}
+
+impl DynRecordBuilder<'_> {
+    /// Set the value of the column `name`, [`Value::Null`] or `None` for a null
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the schema has no column `name`, the value is not of the type of the
+    /// column, or it is null and the column is not nullable.
+    pub fn set(mut self, name: &str, value: impl Into<Value>) -> Result<Self, RecordError> {
+        let index = self
+            .schema
+            .column_index(name)
+            .ok_or_else(|| RecordError::UnknownColumn(name.to_string()))?;
+        let field = self.schema.arrow_schema().field(index + USER_COLUMN_OFFSET);
+        let value = value.into();
+        if value.is_null() {
+            if !field.is_nullable() {
+                return Err(RecordError::NullNotAllowed(name.to_string()));
+            }
+        } else if !is_of_type(&value, field.data_type()) {
+            return Err(RecordError::ValueError(ValueError::TypeMismatch {
+                expected: field.data_type().to_string(),
+                actual: value.data_type().to_string(),
+            }));
+        }
+        self.values[index] = Some(value);
+
+        Ok(self)
+    }
+
+    /// The record, whose columns that were not set are null
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a column that is not nullable, which the columns of the primary key
+    /// are, was not set.
+    pub fn build(self) -> Result<DynRecord, RecordError> {
+        let primary_indices = self
+            .schema
+            .primary_key_indices()
+            .iter()
+            .map(|index| index - USER_COLUMN_OFFSET)
+            .collect::<Vec<_>>();
+        let fields = &self.schema.arrow_schema().fields()[USER_COLUMN_OFFSET..];
+        let values = self
+            .values
+            .into_iter()
+            .zip(fields)
+            .map(|(value, field)| match value {
+                Some(value) => Ok(value),
+                None if field.is_nullable() => Ok(Value::Null),
+                None => Err(RecordError::NullNotAllowed(field.name().clone())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(index) = primary_indices
+            .iter()
+            .find(|index| values[**index].is_null())
+        {
+            return Err(RecordError::NullNotAllowed(fields[*index].name().clone()));
+        }
+
+        Ok(DynRecord {
+            values,
+            primary_indices,
+        })
+    }
+}
+
+// Whether `value` may be stored in a column of `data_type`, which has the time zone of its
+// timestamps and the fields of its lists that values do not have
+fn is_of_type(value: &Value, data_type: &DataType) -> bool {
+    match (value, data_type) {
+        (Value::Timestamp(..), DataType::Timestamp(unit, _)) => {
+            matches!(value.data_type(), DataType::Timestamp(value_unit, _) if value_unit == *unit)
+        }
+        (Value::List(item, _), DataType::List(field)) => field.data_type() == item,
+        _ => value.data_type() == *data_type,
+    }
 }
 
 impl Decode for DynRecord {
@@ -65,7 +222,17 @@ impl Decode for DynRecord {
         R: SeqRead,
     {
         let len = u32::decode(reader).await? as usize;
-        let primary_index = u32::decode(reader).await? as usize;
+        let primary_indices = match u32::decode(reader).await? {
+            COMPOSITE_KEY_MARKER => {
+                let key_len = u32::decode(reader).await? as usize;
+                let mut primary_indices = Vec::with_capacity(key_len);
+                for _ in 0..key_len {
+                    primary_indices.push(u32::decode(reader).await? as usize);
+                }
+                primary_indices
+            }
+            primary_index => vec![primary_index as usize],
+        };
         let mut values = Vec::with_capacity(len);
         for _ in 0..len {
             let col = Value::decode(reader).await?;
@@ -74,7 +241,7 @@ impl Decode for DynRecord {
 
         Ok(DynRecord {
             values,
-            primary_index,
+            primary_indices,
         })
     }
 }
@@ -89,12 +256,19 @@ impl Record for DynRecord {
         for col in self.values.iter() {
             columns.push(col.as_key_ref());
         }
-        DynRecordRef::new(columns, self.primary_index)
+        DynRecordRef::with_primary_indices(columns, self.primary_indices.clone())
     }
 
     fn size(&self) -> usize {
         self.values.iter().fold(0, |acc, col| acc + col.size())
     }
+
+    fn evolve(&mut self, schema: &Self::Schema) {
+        let columns = schema.arrow_schema().fields().len() - USER_COLUMN_OFFSET;
+        if self.values.len() < columns {
+            self.values.resize(columns, Value::Null);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -108,7 +282,10 @@ pub(crate) mod test {
     use super::{DynRecord, DynSchema, Record};
     use crate::{
         make_dyn_schema,
-        record::{DynRecordRef, TimeUnit, Value, ValueRef},
+        record::{
+            error::RecordError, AsValue, DynRecordRef, RecordRef, TimeUnit, Value, ValueError,
+            ValueRef,
+        },
     };
 
     #[allow(unused)]
@@ -182,7 +359,7 @@ pub(crate) mod test {
     fn test_as_record_ref() {
         let record = test_dyn_record();
         let record_ref = record.as_record_ref();
-        let expected = DynRecordRef::new(
+        let expected = DynRecordRef::with_primary_indices(
             vec![
                 ValueRef::Int64(10i64),
                 ValueRef::Int8(10i8),
@@ -196,7 +373,7 @@ pub(crate) mod test {
                 ValueRef::Float64(1.01),
                 ValueRef::Timestamp(1717507203412, TimeUnit::Millisecond),
             ],
-            0,
+            vec![0],
         );
 
         for (actual, expected) in record_ref.columns.iter().zip(expected.columns) {
@@ -222,6 +399,37 @@ pub(crate) mod test {
         );
     }
 
+    #[tokio::test]
+    async fn test_encode_decode_composite_key() {
+        let record = DynRecord::try_with_primary_indices(
+            vec![Value::String("tonbo".into()), Value::Null, Value::Int64(42)],
+            vec![0, 2],
+        )
+        .unwrap();
+        let record_ref = record.as_record_ref();
+        assert_eq!(
+            record_ref.clone().key(),
+            ValueRef::Composite(vec![ValueRef::String("tonbo"), ValueRef::Int64(42)])
+        );
+
+        let mut bytes = Vec::new();
+        let mut buf = Cursor::new(&mut bytes);
+        record_ref.encode(&mut buf).await.unwrap();
+        assert_eq!(bytes.len(), record_ref.size());
+
+        let mut buf = Cursor::new(&mut bytes);
+        let actual = DynRecord::decode(&mut buf).await.unwrap();
+        assert_eq!(actual.primary_indices, vec![0, 2]);
+        assert_eq!(record.values, actual.values);
+
+        // no column of the key may be null
+        assert!(DynRecord::try_with_primary_indices(
+            vec![Value::String("tonbo".into()), Value::Null],
+            vec![0, 1],
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_create_record() {
         let res = DynRecord::try_new(
@@ -256,4 +464,87 @@ pub(crate) mod test {
         let res = DynRecord::try_new(vec![Value::Null], 1);
         assert!(res.is_err());
     }
+
+    #[test]
+    fn test_builder() {
+        let schema = test_dyn_item_schema();
+        let builder = DynRecord::builder(&schema)
+            .set("id", 10i64)
+            .unwrap()
+            .set("weight", 56i32)
+            .unwrap()
+            .set("name", "tonbo")
+            .unwrap()
+            .set("enabled", true)
+            .unwrap()
+            .set("grade", 1.5f32)
+            .unwrap()
+            .set("email", None::<String>)
+            .unwrap()
+            .set("timestamp", Value::Timestamp(1, TimeUnit::Millisecond))
+            .unwrap();
+        let record = builder.build().unwrap();
+        assert_eq!(record.values().len(), 11);
+        assert_eq!(record.values()[0], Value::Int64(10));
+        assert_eq!(record.values()[4], Value::String("tonbo".into()));
+        // columns that were not set are null
+        assert_eq!(record.values()[1], Value::Null);
+        assert_eq!(record.values()[5], Value::Null);
+        assert_eq!(record.as_record_ref().key(), ValueRef::Int64(10));
+
+        assert!(matches!(
+            DynRecord::builder(&schema).set("id", 1i32),
+            Err(RecordError::ValueError(ValueError::TypeMismatch { expected, actual }))
+                if expected == "Int64" && actual == "Int32"
+        ));
+        assert!(matches!(
+            DynRecord::builder(&schema).set("name", Value::Null),
+            Err(RecordError::NullNotAllowed(name)) if name == "name"
+        ));
+        assert!(matches!(
+            DynRecord::builder(&schema).set("missing", 1i64),
+            Err(RecordError::UnknownColumn(name)) if name == "missing"
+        ));
+        // the primary key has to be set
+        assert!(matches!(
+            DynRecord::builder(&schema)
+                .set("weight", 56i32)
+                .unwrap()
+                .set("name", "tonbo")
+                .unwrap()
+                .set("enabled", true)
+                .unwrap()
+                .set("grade", 1.5f32)
+                .unwrap()
+                .build(),
+            Err(RecordError::NullNotAllowed(name)) if name == "id"
+        ));
+    }
+
+    #[test]
+    fn test_get_by_name() {
+        let schema = test_dyn_item_schema();
+        let record = test_dyn_record();
+
+        let name = record.get(&schema, "name").unwrap();
+        assert_eq!(name.as_string_opt(), Some("tonbo"));
+        assert_eq!(name.as_i64_opt(), None);
+        assert_eq!(
+            record.get(&schema, "height").unwrap().as_i16_opt(),
+            Some(&183)
+        );
+        assert!(matches!(
+            record.get(&schema, "missing"),
+            Err(RecordError::UnknownColumn(name)) if name == "missing"
+        ));
+        // `_ts` is not a column of the record
+        assert!(record.get(&schema, "_ts").is_err());
+
+        let record_ref = record.as_record_ref();
+        assert_eq!(
+            record_ref.get(&schema, "email").unwrap(),
+            &ValueRef::String("contact@tonbo.io")
+        );
+        assert!(record_ref.get(&schema, "missing").is_err());
+    }
 }
@@ -7,27 +7,59 @@ use arrow::{
 use fusio::Write;
 use fusio_log::Encode;
 
+use super::{
+    record::COMPOSITE_KEY_MARKER,
+    schema::{primary_key_indices, projected_columns, DynSchema},
+};
 use crate::{
     magic::USER_COLUMN_OFFSET,
-    record::{option::OptionRecordRef, DynRecord, Key, Record, RecordRef, Schema, ValueRef},
+    record::{
+        error::RecordError, option::OptionRecordRef, DynRecord, Key, Record, RecordRef, Schema,
+        ValueRef,
+    },
 };
 
 #[derive(Clone)]
 pub struct DynRecordRef<'r> {
     pub columns: Vec<ValueRef<'r>>,
     // XXX: log encode should keep the same behavior
-    pub primary_index: usize,
+    /// Columns of the primary key, several for a composite key
+    pub primary_indices: Vec<usize>,
     _marker: PhantomData<&'r ()>,
 }
 
 impl<'r> DynRecordRef<'r> {
-    pub(crate) fn new(columns: Vec<ValueRef<'r>>, primary_index: usize) -> Self {
+    pub(crate) fn with_primary_indices(
+        columns: Vec<ValueRef<'r>>,
+        primary_indices: Vec<usize>,
+    ) -> Self {
         Self {
             columns,
-            primary_index,
+            primary_indices,
             _marker: PhantomData,
         }
     }
+
+    /// Copy the values into an owned [`DynRecord`]
+    pub fn to_record(&self) -> DynRecord {
+        DynRecord::with_primary_indices(
+            self.columns.iter().map(ValueRef::to_owned).collect(),
+            self.primary_indices.clone(),
+        )
+    }
+
+    /// Value of the column `name` of `schema`, the schema of the record
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RecordError::UnknownColumn`] if `schema` has no column `name` or the record has
+    /// no value for it, e.g. as it was not projected.
+    pub fn get(&self, schema: &DynSchema, name: &str) -> Result<&ValueRef<'r>, RecordError> {
+        schema
+            .column_index(name)
+            .and_then(|index| self.columns.get(index))
+            .ok_or_else(|| RecordError::UnknownColumn(name.to_string()))
+    }
 }
 
 impl Encode for DynRecordRef<'_> {
@@ -36,7 +68,16 @@ impl Encode for DynRecordRef<'_> {
         W: Write,
     {
         (self.columns.len() as u32).encode(writer).await?;
-        (self.primary_index as u32).encode(writer).await?;
+        match &self.primary_indices[..] {
+            [primary_index] => (*primary_index as u32).encode(writer).await?,
+            primary_indices => {
+                COMPOSITE_KEY_MARKER.encode(writer).await?;
+                (primary_indices.len() as u32).encode(writer).await?;
+                for primary_index in primary_indices {
+                    (*primary_index as u32).encode(writer).await?;
+                }
+            }
+        }
         for col in self.columns.iter() {
             col.encode(writer).await?;
         }
@@ -45,6 +86,9 @@ impl Encode for DynRecordRef<'_> {
 
     fn size(&self) -> usize {
         let mut size = 2 * mem::size_of::<u32>();
+        if self.primary_indices.len() > 1 {
+            size += (1 + self.primary_indices.len()) * mem::size_of::<u32>();
+        }
         for col in self.columns.iter() {
             size += col.size();
         }
@@ -56,10 +100,20 @@ impl<'r> RecordRef<'r> for DynRecordRef<'r> {
     type Record = DynRecord;
 
     fn key(self) -> <<<Self::Record as Record>::Schema as Schema>::Key as Key>::Ref<'r> {
-        self.columns
-            .get(self.primary_index)
-            .cloned()
-            .expect("The primary key must exist")
+        let column = |index: &usize| {
+            self.columns
+                .get(*index)
+                .cloned()
+                .expect("The primary key must exist")
+        };
+        match &self.primary_indices[..] {
+            [primary_index] => column(primary_index),
+            primary_indices => ValueRef::Composite(primary_indices.iter().map(column).collect()),
+        }
+    }
+
+    fn to_owned_record(&self) -> Option<Self::Record> {
+        Some(self.to_record())
     }
 
     fn from_record_batch(
@@ -71,11 +125,7 @@ impl<'r> RecordRef<'r> for DynRecordRef<'r> {
         let null = record_batch.column(0).as_boolean().value(offset);
         let metadata = full_schema.metadata();
 
-        let primary_index = metadata
-            .get("primary_key_index")
-            .unwrap()
-            .parse::<usize>()
-            .unwrap();
+        let primary_indices = primary_key_indices(metadata);
         let ts = record_batch
             .column(1)
             .as_primitive::<arrow::datatypes::UInt32Type>()
@@ -86,8 +136,13 @@ impl<'r> RecordRef<'r> for DynRecordRef<'r> {
 
         let schema = record_batch.schema();
         let flattened_fields = schema.fields();
+        let user_fields = &full_schema.fields()[USER_COLUMN_OFFSET..];
+        let projected = projected_columns(
+            projection_mask,
+            user_fields.iter().map(|field| field.data_type()),
+        );
 
-        for (idx, field) in full_schema.fields().iter().enumerate().skip(2) {
+        for (field, projected) in user_fields.iter().zip(projected) {
             let batch_field = flattened_fields
                 .iter()
                 .enumerate()
@@ -97,7 +152,7 @@ impl<'r> RecordRef<'r> for DynRecordRef<'r> {
                 continue;
             }
             let array = record_batch.column(batch_field.unwrap().0);
-            if array.is_null(offset) || !projection_mask.leaf_included(idx) {
+            if array.is_null(offset) || !projected {
                 columns.push(ValueRef::Null);
             } else {
                 // TODO: handle error
@@ -107,20 +162,40 @@ impl<'r> RecordRef<'r> for DynRecordRef<'r> {
 
         let record = DynRecordRef {
             columns,
-            primary_index,
+            primary_indices,
             _marker: PhantomData,
         };
         OptionRecordRef::new(ts, record, null)
     }
 
     fn projection(&mut self, projection_mask: &parquet::arrow::ProjectionMask) {
+        // without the schema, every column is taken as a single leaf column
         for (idx, col) in self.columns.iter_mut().enumerate() {
-            if idx != self.primary_index && !projection_mask.leaf_included(idx + USER_COLUMN_OFFSET)
+            if !self.primary_indices.contains(&idx)
+                && !projection_mask.leaf_included(idx + USER_COLUMN_OFFSET)
             {
                 *col = ValueRef::Null;
             }
         }
     }
+
+    fn projection_with_schema(
+        &mut self,
+        projection_mask: &parquet::arrow::ProjectionMask,
+        full_schema: &Arc<ArrowSchema>,
+    ) {
+        let projected = projected_columns(
+            projection_mask,
+            full_schema.fields()[USER_COLUMN_OFFSET..]
+                .iter()
+                .map(|field| field.data_type()),
+        );
+        for (idx, (col, projected)) in self.columns.iter_mut().zip(projected).enumerate() {
+            if !self.primary_indices.contains(&idx) && !projected {
+                *col = ValueRef::Null;
+            }
+        }
+    }
 }
 
 #[cfg(test)]
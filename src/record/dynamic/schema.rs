@@ -4,11 +4,18 @@ use arrow::{
     datatypes::{DataType, Field, Schema as ArrowSchema},
     error::ArrowError,
 };
-use parquet::{format::SortingColumn, schema::types::ColumnPath};
+use parquet::{
+    arrow::{ArrowSchemaConverter, ProjectionMask},
+    format::SortingColumn,
+    schema::types::{ColumnPath, SchemaDescriptor},
+};
 use thiserror::Error;
 
 use super::{array::DynRecordImmutableArrays, DynRecord, Value};
-use crate::{magic, record::Schema};
+use crate::{
+    magic::{self, USER_COLUMN_OFFSET},
+    record::Schema,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct DynamicField {
@@ -49,12 +56,76 @@ impl From<&Field> for DynamicField {
     }
 }
 
+/// Key of the columns of the primary key in the metadata of the Arrow schema of a [`DynSchema`],
+/// the comma separated indices of its fields
+pub(crate) const PRIMARY_KEY_INDEX: &str = "primary_key_index";
+
+/// Indices of the fields of the primary key of a [`DynSchema`] in the metadata of its Arrow
+/// schema
+pub(crate) fn primary_key_indices(metadata: &HashMap<String, String>) -> Vec<usize> {
+    metadata
+        .get(PRIMARY_KEY_INDEX)
+        .expect("primary key index must exist in schema metadata")
+        .split(',')
+        .map(|index| {
+            index
+                .parse::<usize>()
+                .expect("primary key index must be a valid usize")
+        })
+        .collect()
+}
+
+/// Number of Parquet leaf columns of a column of `data_type`, as a nested column, e.g. a struct,
+/// is stored in a leaf column for every primitive value inside it
+pub(crate) fn leaf_count(data_type: &DataType) -> usize {
+    match data_type {
+        DataType::Struct(fields) => fields
+            .iter()
+            .map(|field| leaf_count(field.data_type()))
+            .sum(),
+        DataType::List(field)
+        | DataType::LargeList(field)
+        | DataType::FixedSizeList(field, _)
+        | DataType::Map(field, _) => leaf_count(field.data_type()),
+        _ => 1,
+    }
+}
+
+/// Index of the first Parquet leaf column of the user column at `index` of the columns of the
+/// data types `data_types`
+fn first_leaf<'a>(data_types: impl IntoIterator<Item = &'a DataType>, index: usize) -> usize {
+    USER_COLUMN_OFFSET
+        + data_types
+            .into_iter()
+            .take(index)
+            .map(leaf_count)
+            .sum::<usize>()
+}
+
+/// Whether `projection_mask` reads each of the user columns of the data types `data_types`,
+/// which it does if it reads any of the leaf columns of a column, see [`leaf_count`]
+pub(crate) fn projected_columns<'a>(
+    projection_mask: &ProjectionMask,
+    data_types: impl IntoIterator<Item = &'a DataType>,
+) -> Vec<bool> {
+    let mut leaf = USER_COLUMN_OFFSET;
+    data_types
+        .into_iter()
+        .map(|data_type| {
+            let start = leaf;
+            leaf += leaf_count(data_type);
+            (start..leaf).any(|leaf| projection_mask.leaf_included(leaf))
+        })
+        .collect()
+}
+
 #[derive(Debug)]
 pub struct DynSchema {
-    primary_index_arrow: usize,
+    primary_indices_arrow: Vec<usize>,
     pk_paths: Vec<ColumnPath>,
     sorting: Vec<SortingColumn>,
     arrow_schema: Arc<ArrowSchema>,
+    parquet_schema: SchemaDescriptor,
 }
 
 #[derive(Debug, Error)]
@@ -62,12 +133,46 @@ pub struct DynSchema {
 pub enum SchemaError {
     #[error("write io error: {0}")]
     Arrow(#[from] ArrowError),
+    #[error("field {0} is reserved or given more than once")]
+    DuplicateField(String),
+    #[error("invalid primary key: {0}")]
+    InvalidPrimaryKey(String),
 }
 
 impl DynSchema {
+    /// Builder of a schema that checks its fields and primary key, see [`DynSchemaBuilder`]
+    pub fn builder() -> DynSchemaBuilder {
+        DynSchemaBuilder::default()
+    }
+
     pub fn new(schema: &[DynamicField], primary_index: usize) -> Self {
+        Self::with_primary_indices(schema, &[primary_index])
+    }
+
+    /// Schema whose primary key is made of the fields at `primary_indices`, a composite key if
+    /// there are several, whose records are ordered by the first of them, then by the next one
+    /// and so on
+    ///
+    /// The key of a record is a [`Value::Composite`] with the values of the key fields in the
+    /// order of `primary_indices`.
+    ///
+    /// # Panics
+    ///
+    /// If `primary_indices` is empty.
+    pub fn with_primary_indices(schema: &[DynamicField], primary_indices: &[usize]) -> Self {
+        assert!(
+            !primary_indices.is_empty(),
+            "a primary key has at least one field"
+        );
         let mut metadata = HashMap::new();
-        metadata.insert("primary_key_index".to_string(), primary_index.to_string());
+        metadata.insert(
+            PRIMARY_KEY_INDEX.to_string(),
+            primary_indices
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+        );
         let arrow_schema = Arc::new(ArrowSchema::new_with_metadata(
             [
                 Field::new("_null", DataType::Boolean, false),
@@ -78,19 +183,23 @@ impl DynSchema {
             .collect::<Vec<_>>(),
             metadata,
         ));
-        let pk_paths = vec![ColumnPath::new(vec![
-            magic::TS.to_string(),
-            schema[primary_index].name.clone(),
-        ])];
-        let sorting = vec![
-            SortingColumn::new(1_i32, true, true),
-            SortingColumn::new((primary_index + 2) as i32, false, true),
-        ];
+        let pk_paths = primary_indices
+            .iter()
+            .map(|index| ColumnPath::new(vec![magic::TS.to_string(), schema[*index].name.clone()]))
+            .collect();
+        let sorting = [SortingColumn::new(1_i32, true, true)]
+            .into_iter()
+            .chain(primary_indices.iter().map(|index| {
+                let leaf = first_leaf(schema.iter().map(|field| &field.data_type), *index);
+                SortingColumn::new(leaf as i32, false, true)
+            }))
+            .collect();
 
         Self {
-            primary_index_arrow: primary_index + 2,
+            primary_indices_arrow: primary_indices.iter().map(|index| index + 2).collect(),
             pk_paths,
             sorting,
+            parquet_schema: parquet_schema(&arrow_schema),
             arrow_schema,
         }
     }
@@ -101,7 +210,7 @@ impl DynSchema {
         primary_index: usize,
     ) -> Result<Self, SchemaError> {
         let mut metadata = HashMap::new();
-        metadata.insert("primary_key_index".to_string(), primary_index.to_string());
+        metadata.insert(PRIMARY_KEY_INDEX.to_string(), primary_index.to_string());
 
         let arrow_schema = ArrowSchema::try_merge(vec![
             ArrowSchema::new_with_metadata(
@@ -127,18 +236,119 @@ impl DynSchema {
             magic::TS.to_string(),
             fields_vec[primary_index].name.clone(),
         ])];
+        let leaf = first_leaf(
+            arrow_schema
+                .fields()
+                .iter()
+                .skip(USER_COLUMN_OFFSET)
+                .map(|field| field.data_type()),
+            primary_index,
+        );
         let sorting = vec![
             SortingColumn::new(1_i32, true, true),
-            SortingColumn::new((primary_index + 2) as i32, false, true),
+            SortingColumn::new(leaf as i32, false, true),
         ];
 
         Ok(Self {
-            primary_index_arrow: primary_index + 2,
+            primary_indices_arrow: vec![primary_index + 2],
             pk_paths,
             sorting,
+            parquet_schema: parquet_schema(&arrow_schema),
             arrow_schema: Arc::new(arrow_schema),
         })
     }
+
+    /// Index of the column `name` in the values of a [`DynRecord`] of this schema
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.arrow_schema
+            .index_of(name)
+            .ok()
+            .filter(|index| *index >= USER_COLUMN_OFFSET)
+            .map(|index| index - USER_COLUMN_OFFSET)
+    }
+}
+
+/// Builder of a [`DynSchema`], created by [`DynSchema::builder`]
+///
+/// ```ignore
+/// let schema = DynSchema::builder()
+///     .field("id", DataType::Int64, false)
+///     .field("name", DataType::Utf8, true)
+///     .primary_key("id")
+///     .build()?;
+/// ```
+#[derive(Debug, Default)]
+pub struct DynSchemaBuilder {
+    fields: Vec<DynamicField>,
+    primary_key: Vec<String>,
+}
+
+impl DynSchemaBuilder {
+    /// Append the field `name`
+    pub fn field(
+        mut self,
+        name: impl Into<String>,
+        data_type: DataType,
+        is_nullable: bool,
+    ) -> Self {
+        self.fields
+            .push(DynamicField::new(name.into(), data_type, is_nullable));
+        self
+    }
+
+    /// Append the field `name` to the primary key, which is a composite key if it has several
+    pub fn primary_key(mut self, name: impl Into<String>) -> Self {
+        self.primary_key.push(name.into());
+        self
+    }
+
+    /// The schema of the fields and primary key
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a field is named `_null` or `_ts` or given twice, or the primary key
+    /// is empty, has a field twice, or has a field that is not one of the schema or is nullable.
+    pub fn build(self) -> Result<DynSchema, SchemaError> {
+        for (i, field) in self.fields.iter().enumerate() {
+            if field.name == "_null"
+                || field.name == magic::TS
+                || self.fields[..i]
+                    .iter()
+                    .any(|other| other.name == field.name)
+            {
+                return Err(SchemaError::DuplicateField(field.name.clone()));
+            }
+        }
+        if self.primary_key.is_empty() {
+            return Err(SchemaError::InvalidPrimaryKey(
+                "a primary key has at least one field".into(),
+            ));
+        }
+        let mut primary_indices = Vec::with_capacity(self.primary_key.len());
+        for name in &self.primary_key {
+            let index = self
+                .fields
+                .iter()
+                .position(|field| &field.name == name)
+                .ok_or_else(|| SchemaError::InvalidPrimaryKey(format!("no field {name}")))?;
+            if self.fields[index].is_nullable {
+                return Err(SchemaError::InvalidPrimaryKey(format!(
+                    "field {name} is nullable"
+                )));
+            }
+            if primary_indices.contains(&index) {
+                return Err(SchemaError::InvalidPrimaryKey(format!(
+                    "field {name} is given more than once"
+                )));
+            }
+            primary_indices.push(index);
+        }
+
+        Ok(DynSchema::with_primary_indices(
+            &self.fields,
+            &primary_indices,
+        ))
+    }
 }
 
 impl Schema for DynSchema {
@@ -153,12 +363,22 @@ impl Schema for DynSchema {
     }
 
     fn primary_key_indices(&self) -> &[usize] {
-        std::slice::from_ref(&self.primary_index_arrow)
+        &self.primary_indices_arrow
     }
 
     fn primary_key_paths_and_sorting(&self) -> (&[ColumnPath], &[SortingColumn]) {
         (&self.pk_paths, &self.sorting)
     }
+
+    fn parquet_schema(&self) -> &SchemaDescriptor {
+        &self.parquet_schema
+    }
+}
+
+fn parquet_schema(arrow_schema: &ArrowSchema) -> SchemaDescriptor {
+    ArrowSchemaConverter::new()
+        .convert(arrow_schema)
+        .expect("columns of a dynamic schema convert to Parquet")
 }
 
 /// Creates a [`DynSchema`] from literal slice of values and primary key index, suitable for rapid
@@ -214,7 +434,8 @@ macro_rules! make_dyn_schema {
 mod tests {
     use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
 
-    use super::DynSchema;
+    use super::{DynSchema, SchemaError};
+    use crate::{dyn_schema, record::Schema as _};
 
     #[test]
     fn test_from_arrow_schema() {
@@ -247,4 +468,60 @@ mod tests {
         let primary_key_index = metadata.get("primary_key_index");
         assert_eq!(primary_key_index, Some(&"0".into()));
     }
+
+    #[test]
+    fn test_projection() {
+        let dyn_schema = dyn_schema!(
+            ("id", UInt64, false),
+            ("name", Utf8, true),
+            ("grade", Float32, true),
+            0
+        );
+        assert_eq!(dyn_schema.parquet_schema().num_columns(), 5);
+
+        // `_null`, `_ts` and the primary key are always read
+        let mask = dyn_schema.projection(["grade"]);
+        let included = (0..5)
+            .filter(|leaf| mask.leaf_included(*leaf))
+            .collect::<Vec<_>>();
+        assert_eq!(included, vec![0, 1, 2, 4]);
+    }
+
+    #[test]
+    fn test_builder() {
+        let schema = DynSchema::builder()
+            .field("id", DataType::UInt64, false)
+            .field("name", DataType::Utf8, true)
+            .primary_key("id")
+            .build()
+            .unwrap();
+        assert_eq!(schema.primary_key_indices(), &[2]);
+        assert_eq!(schema.column_index("name"), Some(1));
+
+        let builder = || {
+            DynSchema::builder()
+                .field("id", DataType::UInt64, false)
+                .field("name", DataType::Utf8, true)
+        };
+        assert!(matches!(
+            builder().field("name", DataType::Utf8, true).primary_key("id").build(),
+            Err(SchemaError::DuplicateField(name)) if name == "name"
+        ));
+        assert!(matches!(
+            builder().field("_ts", DataType::UInt32, false).primary_key("id").build(),
+            Err(SchemaError::DuplicateField(name)) if name == "_ts"
+        ));
+        assert!(matches!(
+            builder().build(),
+            Err(SchemaError::InvalidPrimaryKey(_))
+        ));
+        assert!(matches!(
+            builder().primary_key("name").build(),
+            Err(SchemaError::InvalidPrimaryKey(_))
+        ));
+        assert!(matches!(
+            builder().primary_key("missing").build(),
+            Err(SchemaError::InvalidPrimaryKey(_))
+        ));
+    }
 }
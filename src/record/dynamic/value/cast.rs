@@ -76,6 +76,13 @@ pub trait AsValue: seal::Sealed {
         self.as_f64_opt().expect("f64")
     }
 
+    /// Return the unscaled value of a decimal
+    fn as_i128_opt(&self) -> Option<&i128>;
+
+    fn as_i128(&self) -> &i128 {
+        self.as_i128_opt().expect("i128")
+    }
+
     fn as_bytes_opt(&self) -> Option<&[u8]>;
 
     fn as_bytes(&self) -> &[u8] {
@@ -167,6 +174,13 @@ impl AsValue for Value {
         }
     }
 
+    fn as_i128_opt(&self) -> Option<&i128> {
+        match self {
+            Value::Decimal128(v, _, _) => Some(v),
+            _ => None,
+        }
+    }
+
     fn as_bytes_opt(&self) -> Option<&[u8]> {
         match self {
             Value::Binary(v) => Some(v),
@@ -272,6 +286,13 @@ impl AsValue for ValueRef<'_> {
         }
     }
 
+    fn as_i128_opt(&self) -> Option<&i128> {
+        match self {
+            ValueRef::Decimal128(v, _, _) => Some(v),
+            _ => None,
+        }
+    }
+
     fn as_bytes_opt(&self) -> Option<&[u8]> {
         match self {
             ValueRef::Binary(v) => Some(v),
@@ -10,7 +10,10 @@ use futures_util::future::LocalBoxFuture;
 use futures_util::FutureExt;
 
 use super::{TimeUnit, Value};
-use crate::record::{decode_arrow_datatype, encode_arrow_datatype, Key, ValueError, ValueRef};
+use crate::record::{
+    arrow_datatype_size, decode_arrow_datatype, encode_arrow_datatype, is_composite, Key,
+    ValueError, ValueRef,
+};
 
 #[cfg(not(target_arch = "wasm32"))]
 type BoxedFuture<'a, T> = BoxFuture<'a, T>;
@@ -43,6 +46,15 @@ impl Value {
                     Vec::<u8>::decode(reader).await?,
                     *w as u32,
                 )),
+                DataType::Decimal128(precision, scale) => {
+                    let low = u64::decode(reader).await?;
+                    let high = i64::decode(reader).await?;
+                    Ok(Value::Decimal128(
+                        ((high as i128) << 64) | low as i128,
+                        *precision,
+                        *scale,
+                    ))
+                }
                 DataType::Date32 => Ok(Value::Date32(i32::decode(reader).await?)),
                 DataType::Date64 => Ok(Value::Date64(i64::decode(reader).await?)),
                 DataType::Timestamp(time_unit, _) => Ok(Value::Timestamp(
@@ -77,6 +89,17 @@ impl Value {
                     }
                     Ok(Value::List(field.data_type().clone(), list))
                 }
+                DataType::Struct(fields) => {
+                    let mut values = Vec::with_capacity(fields.len());
+                    for _ in 0..fields.len() {
+                        values.push(Value::decode_inner(reader).await?);
+                    }
+                    if is_composite(fields) {
+                        Ok(Value::Composite(values))
+                    } else {
+                        Ok(Value::Struct(fields.clone(), values))
+                    }
+                }
                 _ => Err(fusio::Error::Other(Box::new(ValueError::InvalidDataType(
                     data_type.to_string(),
                 )))),
@@ -219,6 +242,11 @@ impl ValueRef<'_> {
                 ValueRef::FixedSizeBinary(v, _) => {
                     v.encode(writer).await?;
                 }
+                ValueRef::Decimal128(v, _, _) => {
+                    // the low and the high 64 bits, as there is no encoding of `i128`
+                    (*v as u64).encode(writer).await?;
+                    ((*v >> 64) as i64).encode(writer).await?;
+                }
                 ValueRef::List(_, vec) => {
                     let len = vec.len() as u32;
                     len.encode(writer).await?;
@@ -226,6 +254,11 @@ impl ValueRef<'_> {
                         v.as_key_ref().encode_inner(writer).await?;
                     }
                 }
+                ValueRef::Struct(_, values) | ValueRef::Composite(values) => {
+                    for v in values.iter() {
+                        v.encode_inner(writer).await?;
+                    }
+                }
             }
             Ok(())
         };
@@ -270,6 +303,7 @@ impl Encode for ValueRef<'_> {
             ValueRef::Timestamp(v, time_unit) => 1 + v.size() + time_unit.size(),
             ValueRef::Time32(v, time_unit) => 1 + v.size() + time_unit.size(),
             ValueRef::Time64(v, time_unit) => 1 + v.size() + time_unit.size(),
+            ValueRef::Decimal128(_, _, _) => 3 + 2 * std::mem::size_of::<u64>(),
             ValueRef::List(data_type, vec) => {
                 vec.iter().map(|v| v.size()).sum::<usize>()
                     + match data_type {
@@ -279,6 +313,10 @@ impl Encode for ValueRef<'_> {
                         _ => 1,
                     }
             }
+            ValueRef::Struct(_, values) | ValueRef::Composite(values) => {
+                arrow_datatype_size(&self.data_type())
+                    + values.iter().map(|v| v.size()).sum::<usize>()
+            }
         }
     }
 }
@@ -320,6 +358,21 @@ mod tests {
         assert_eq!(value, decoded);
     }
 
+    #[tokio::test]
+    async fn test_value_decimal_encode_decode() {
+        for v in [0, 12345, -12345, i128::MAX, i128::MIN, 1 << 64, -(1 << 64)] {
+            let value = Value::Decimal128(v, 38, 4);
+            let mut buf = Vec::new();
+            let mut cursor = Cursor::new(&mut buf);
+            value.encode(&mut cursor).await.unwrap();
+            assert_eq!(buf.len(), value.size());
+
+            let mut cursor = Cursor::new(&mut buf);
+            let decoded = Value::decode(&mut cursor).await.unwrap();
+            assert!(matches!(decoded, Value::Decimal128(d, 38, 4) if d == v));
+        }
+    }
+
     #[tokio::test]
     async fn test_value_timstamp_encode_decode() {
         let value = Value::Timestamp(1732838400, TimeUnit::Nanosecond);
@@ -431,6 +484,26 @@ mod tests {
         assert_eq!(value, decoded.as_key_ref());
     }
 
+    #[tokio::test]
+    async fn test_struct_value_encode_decode() {
+        let fields = arrow::datatypes::Fields::from(vec![
+            Field::new("city", DataType::Utf8, false),
+            Field::new("zip", DataType::UInt32, true),
+        ]);
+        let value = Value::Struct(
+            fields,
+            vec![Value::String("Berlin".to_string()), Value::Null],
+        );
+        let mut buf = Vec::new();
+        let mut cursor = Cursor::new(&mut buf);
+        value.encode(&mut cursor).await.unwrap();
+        assert_eq!(buf.len(), value.size());
+
+        let mut cursor = Cursor::new(&mut buf);
+        let decoded = Value::decode(&mut cursor).await.unwrap();
+        assert_eq!(value, decoded);
+    }
+
     #[tokio::test]
     async fn test_fixed_size_binary_value_encode_decode() {
         let value = Value::FixedSizeBinary(b"hello".to_vec(), 5);
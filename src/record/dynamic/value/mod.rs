@@ -9,7 +9,7 @@ use std::{
     sync::Arc,
 };
 
-use arrow::datatypes::{DataType, Field};
+use arrow::datatypes::{DataType, Decimal128Type, DecimalType, Field, Fields};
 pub use cast::*;
 use thiserror::Error;
 pub(crate) use util::*;
@@ -56,8 +56,22 @@ pub enum Value {
     Time32(i32, TimeUnit),
     Time64(i64, TimeUnit),
     Timestamp(i64, TimeUnit),
+    /// An exact decimal number.
+    /// The first parameter specifies the unscaled value, the second the precision, i.e. the
+    /// number of digits, and the third the scale, i.e. the number of digits after the decimal
+    /// point, so `Decimal128(12345, 10, 2)` is `123.45`
+    Decimal128(i128, u8, i8),
     /// List of values that are of the same type.
     List(DataType, Vec<Arc<Value>>),
+    /// A nested object.
+    /// The first parameter specifies the fields of the struct and the second parameter the value
+    /// of every field in the order of the fields
+    Struct(Fields, Vec<Value>),
+    /// Key of a record whose primary key is made of several columns, see
+    /// [`DynSchema::with_primary_indices`](crate::record::DynSchema::with_primary_indices). It
+    /// holds the value of every key column in the order of the columns, and is ordered by them in
+    /// that order.
+    Composite(Vec<Value>),
 }
 
 impl Value {
@@ -106,11 +120,14 @@ impl Value {
                 };
                 DataType::Time64(arrow_unit)
             }
+            Value::Decimal128(_, precision, scale) => DataType::Decimal128(*precision, *scale),
             Value::List(data_type, _) => arrow::datatypes::DataType::List(Arc::new(Field::new(
                 "item",
                 data_type.clone(),
                 false,
             ))),
+            Value::Struct(fields, _) => DataType::Struct(fields.clone()),
+            Value::Composite(values) => composite_data_type(values.iter().map(Value::data_type)),
         }
     }
 
@@ -120,6 +137,52 @@ impl Value {
     }
 }
 
+macro_rules! impl_from_primitive {
+    ($($ty:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$ty> for Value {
+                fn from(value: $ty) -> Self {
+                    Value::$variant(value)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_primitive!(
+    bool => Boolean,
+    i8 => Int8,
+    i16 => Int16,
+    i32 => Int32,
+    i64 => Int64,
+    u8 => UInt8,
+    u16 => UInt16,
+    u32 => UInt32,
+    u64 => UInt64,
+    f32 => Float32,
+    f64 => Float64,
+    String => String,
+    Vec<u8> => Binary,
+);
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(value: &[u8]) -> Self {
+        Value::Binary(value.to_vec())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
 impl Key for Value {
     type Ref<'r> = ValueRef<'r>;
 
@@ -176,9 +239,20 @@ impl Key for Value {
                 }
                 _ => unreachable!("Time64 only supports microsecond and nanosecond"),
             },
+            Value::Decimal128(v, precision, scale) => Arc::new(arrow::array::Scalar::new(
+                arrow::array::Decimal128Array::from(vec![*v])
+                    .with_precision_and_scale(*precision, *scale)
+                    .expect("precision and scale of a decimal must be valid"),
+            )),
             Value::List(_, _) => {
                 unreachable!("List value cannot be used as primary key.")
             }
+            Value::Struct(_, _) => {
+                unreachable!("Struct value cannot be used as primary key.")
+            }
+            Value::Composite(values) => {
+                return values.iter().flat_map(Key::to_arrow_datums).collect();
+            }
         };
         vec![datum]
     }
@@ -232,7 +306,14 @@ impl PartialEq for Value {
                 let (o_sec, o_nsec) = split_second_ns(*b, *unit2);
                 s_sec == o_sec && s_nsec == o_nsec
             }
+            (Value::Decimal128(a, _, scale1), Value::Decimal128(b, _, scale2)) => {
+                cmp_decimal128(*a, *scale1, *b, *scale2).is_eq()
+            }
             (Value::List(ty1, a), Value::List(ty2, b)) => ty1.eq(ty2) && a.eq(b),
+            (Value::Struct(fields1, a), Value::Struct(fields2, b)) => {
+                fields1.eq(fields2) && a.eq(b)
+            }
+            (Value::Composite(a), Value::Composite(b)) => a.eq(b),
             _ => false,
         }
     }
@@ -278,12 +359,22 @@ impl Ord for Value {
                     Ordering::Equal => s_nsec.cmp(&o_nsec),
                 }
             }
+            (Value::Decimal128(a, _, scale1), Value::Decimal128(b, _, scale2)) => {
+                cmp_decimal128(*a, *scale1, *b, *scale2)
+            }
             (Value::List(ty1, a), Value::List(ty2, b)) => {
                 if ty1 != ty2 {
                     panic!("cannot compare different list types: {self:?} and {other:?}")
                 }
                 a.cmp(b)
             }
+            (Value::Struct(fields1, a), Value::Struct(fields2, b)) => {
+                if fields1 != fields2 {
+                    panic!("cannot compare different struct types: {self:?} and {other:?}")
+                }
+                a.cmp(b)
+            }
+            (Value::Composite(a), Value::Composite(b)) => a.cmp(b),
             _ => {
                 panic!("cannot compare different types: {self:?} and {other:?}")
             }
@@ -329,10 +420,16 @@ impl Hash for Value {
                 v.hash(state);
                 time_unit.hash(state);
             }
+            Value::Decimal128(v, _, scale) => normalize_decimal128(*v, *scale).hash(state),
             Value::List(ty, vec) => {
                 ty.hash(state);
                 vec.hash(state);
             }
+            Value::Struct(fields, values) => {
+                fields.hash(state);
+                values.hash(state);
+            }
+            Value::Composite(values) => values.hash(state),
         }
     }
 }
@@ -360,6 +457,11 @@ impl fmt::Display for Value {
             Value::Timestamp(v, unit) => write!(f, "Timestamp({v}, {unit:?})"),
             Value::Time32(v, unit) => write!(f, "Time32({v}, {unit:?})"),
             Value::Time64(v, unit) => write!(f, "Time64({v}, {unit:?})"),
+            Value::Decimal128(v, precision, scale) => write!(
+                f,
+                "{}",
+                Decimal128Type::format_decimal(*v, *precision, *scale)
+            ),
             Value::List(ty, vec) => write!(
                 f,
                 "List({ty:?}, [{values}])",
@@ -369,13 +471,35 @@ impl fmt::Display for Value {
                     .collect::<Vec<_>>()
                     .join(", ")
             ),
+            Value::Struct(fields, values) => write!(
+                f,
+                "{{{values}}}",
+                values = fields
+                    .iter()
+                    .zip(values)
+                    .map(|(field, v)| format!("{}: {v}", field.name()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Value::Composite(values) => write!(
+                f,
+                "({values})",
+                values = values
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::{
+        hash::{Hash, Hasher},
+        sync::Arc,
+    };
 
     use arrow::datatypes::{DataType, Field};
 
@@ -492,6 +616,30 @@ mod tests {
         assert!(t1 > t4);
     }
 
+    #[test]
+    fn test_decimal_value_cmp() {
+        let d1 = Value::Decimal128(12345, 10, 2);
+        let d2 = Value::Decimal128(123450, 10, 3);
+        let d3 = Value::Decimal128(-12345, 10, 2);
+        let d4 = Value::Decimal128(124, 10, 0);
+        assert!(d1 == d2);
+        assert!(d3 < d1);
+        assert!(d1 < d4);
+        assert!(Value::Decimal128(i128::MAX, 38, 0) > Value::Decimal128(1, 38, 10));
+        assert!(Value::Decimal128(i128::MIN, 38, 0) < Value::Decimal128(-1, 38, 10));
+
+        let hash = |value: &Value| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&d1), hash(&d2));
+
+        assert_eq!(d1.to_string(), "123.45");
+        assert_eq!(d1.data_type(), DataType::Decimal128(10, 2));
+        assert_eq!(*d1.as_i128(), 12345);
+    }
+
     #[test]
     fn test_list_value_cmp() {
         {
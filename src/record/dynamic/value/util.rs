@@ -1,6 +1,6 @@
-use std::sync::Arc;
+use std::{cmp::Ordering, collections::HashMap, sync::Arc};
 
-use arrow::datatypes::Field;
+use arrow::datatypes::{DataType, Field, Fields};
 use fusio_log::{Decode, Encode};
 #[cfg(not(target_arch = "wasm32"))]
 use futures_util::future::BoxFuture;
@@ -15,6 +15,9 @@ type BoxedFuture<'a, T> = BoxFuture<'a, T>;
 #[cfg(target_arch = "wasm32")]
 type BoxedFuture<'a, T> = LocalBoxFuture<'a, T>;
 
+/// Key of the field metadata that tells the fields of a composite key from those of a struct
+const COMPOSITE_FIELD_KEY: &str = "tonbo.composite";
+
 /// Split a timestamp value into seconds and nanoseconds
 pub(crate) fn split_second_ns(v: i64, unit: TimeUnit) -> (i64, u32) {
     let base = TimeUnit::Second.factor() / unit.factor();
@@ -23,6 +26,83 @@ pub(crate) fn split_second_ns(v: i64, unit: TimeUnit) -> (i64, u32) {
     (sec, nsec as u32)
 }
 
+/// Compare the decimals `a` and `b` of the scales `a_scale` and `b_scale` by their numeric values
+pub(crate) fn cmp_decimal128(a: i128, a_scale: i8, b: i128, b_scale: i8) -> Ordering {
+    match a_scale.cmp(&b_scale) {
+        Ordering::Equal => a.cmp(&b),
+        Ordering::Less => {
+            let factor = 10_i128.checked_pow((b_scale as i32 - a_scale as i32) as u32);
+            match factor.and_then(|factor| a.checked_mul(factor)) {
+                Some(a) => a.cmp(&b),
+                // `a` is too large in magnitude to be rescaled, so it is beyond any `b`
+                None if a < 0 => Ordering::Less,
+                None => Ordering::Greater,
+            }
+        }
+        Ordering::Greater => cmp_decimal128(b, b_scale, a, a_scale).reverse(),
+    }
+}
+
+/// Decimal equal to `value` of the scale `scale` without trailing zeros, so equal decimals of
+/// different scales hash the same
+pub(crate) fn normalize_decimal128(mut value: i128, mut scale: i8) -> (i128, i8) {
+    if value == 0 {
+        return (0, 0);
+    }
+    while value % 10 == 0 && scale > i8::MIN {
+        value /= 10;
+        scale -= 1;
+    }
+    (value, scale)
+}
+
+/// Data type of a composite key, a struct with a field named by its position for every column of
+/// the key
+pub(crate) fn composite_data_type(data_types: impl Iterator<Item = DataType>) -> DataType {
+    DataType::Struct(
+        data_types
+            .enumerate()
+            .map(|(i, data_type)| {
+                Field::new(i.to_string(), data_type, false).with_metadata(HashMap::from([(
+                    COMPOSITE_FIELD_KEY.to_string(),
+                    String::new(),
+                )]))
+            })
+            .collect(),
+    )
+}
+
+/// Whether `fields` are those of the data type of a composite key, see [`composite_data_type`],
+/// rather than those of a struct value
+pub(crate) fn is_composite(fields: &Fields) -> bool {
+    fields
+        .iter()
+        .all(|field| field.metadata().contains_key(COMPOSITE_FIELD_KEY))
+}
+
+/// Number of bytes [`encode_arrow_datatype`] writes for `data_type`
+pub(crate) fn arrow_datatype_size(data_type: &DataType) -> usize {
+    match data_type {
+        DataType::Timestamp(_, _) | DataType::Time32(_) | DataType::Time64(_) => 2,
+        DataType::Decimal128(_, _) => 3,
+        DataType::FixedSizeBinary(_) => 5,
+        DataType::List(field) => 2 + field.name().size() + arrow_datatype_size(field.data_type()),
+        DataType::Struct(fields) if is_composite(fields) => {
+            5 + fields
+                .iter()
+                .map(|field| arrow_datatype_size(field.data_type()))
+                .sum::<usize>()
+        }
+        DataType::Struct(fields) => {
+            5 + fields
+                .iter()
+                .map(|field| field.name().size() + arrow_datatype_size(field.data_type()) + 1)
+                .sum::<usize>()
+        }
+        _ => 1,
+    }
+}
+
 pub(crate) async fn encode_arrow_timeunit<W>(
     time_unit: &arrow::datatypes::TimeUnit,
     writer: &mut W,
@@ -99,12 +179,33 @@ where
                 21u8.encode(writer).await?;
                 w.encode(writer).await?;
             }
+            arrow::datatypes::DataType::Decimal128(precision, scale) => {
+                22u8.encode(writer).await?;
+                precision.encode(writer).await?;
+                scale.encode(writer).await?;
+            }
             arrow::datatypes::DataType::List(field) => {
                 30u8.encode(writer).await?;
                 field.name().encode(writer).await?;
                 encode_arrow_datatype(field.data_type(), writer).await?;
                 field.is_nullable().encode(writer).await?;
             }
+            arrow::datatypes::DataType::Struct(fields) if is_composite(fields) => {
+                31u8.encode(writer).await?;
+                (fields.len() as u32).encode(writer).await?;
+                for field in fields.iter() {
+                    encode_arrow_datatype(field.data_type(), writer).await?;
+                }
+            }
+            arrow::datatypes::DataType::Struct(fields) => {
+                32u8.encode(writer).await?;
+                (fields.len() as u32).encode(writer).await?;
+                for field in fields.iter() {
+                    field.name().encode(writer).await?;
+                    encode_arrow_datatype(field.data_type(), writer).await?;
+                    field.is_nullable().encode(writer).await?;
+                }
+            }
             _ => unreachable!(),
         };
         Ok(())
@@ -162,6 +263,11 @@ where
                 let bytes_width = i32::decode(reader).await?;
                 Ok(arrow::datatypes::DataType::FixedSizeBinary(bytes_width))
             }
+            22 => {
+                let precision = u8::decode(reader).await?;
+                let scale = i8::decode(reader).await?;
+                Ok(arrow::datatypes::DataType::Decimal128(precision, scale))
+            }
             30 => {
                 let name = String::decode(reader).await?;
                 let data_type = decode_arrow_datatype(reader).await?;
@@ -172,6 +278,25 @@ where
                     is_nullable,
                 ))))
             }
+            31 => {
+                let len = u32::decode(reader).await?;
+                let mut data_types = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    data_types.push(decode_arrow_datatype(reader).await?);
+                }
+                Ok(composite_data_type(data_types.into_iter()))
+            }
+            32 => {
+                let len = u32::decode(reader).await?;
+                let mut fields = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    let name = String::decode(reader).await?;
+                    let data_type = decode_arrow_datatype(reader).await?;
+                    let is_nullable = bool::decode(reader).await?;
+                    fields.push(Field::new(name, data_type, is_nullable));
+                }
+                Ok(arrow::datatypes::DataType::Struct(Fields::from(fields)))
+            }
 
             _ => unreachable!(),
         }
@@ -276,6 +401,19 @@ mod tests {
             let decoded = decode_arrow_datatype(&mut cursor).await.unwrap();
             assert_eq!(data_type, decoded);
         }
+        {
+            let data_type = DataType::Decimal128(20, -3);
+            let mut buf = Vec::new();
+            let mut cursor = Cursor::new(&mut buf);
+            encode_arrow_datatype(&data_type, &mut cursor)
+                .await
+                .unwrap();
+            assert_eq!(buf.len(), arrow_datatype_size(&data_type));
+
+            let mut cursor = Cursor::new(&mut buf);
+            let decoded = decode_arrow_datatype(&mut cursor).await.unwrap();
+            assert_eq!(data_type, decoded);
+        }
         {
             let mut buf = Vec::new();
             let mut cursor = Cursor::new(&mut buf);
@@ -288,5 +426,25 @@ mod tests {
             let decoded = decode_arrow_datatype(&mut cursor).await.unwrap();
             assert_eq!(data_type, decoded);
         }
+        {
+            let data_type = DataType::Struct(Fields::from(vec![
+                Field::new("name", DataType::Utf8, false),
+                Field::new(
+                    "tags",
+                    DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+                    true,
+                ),
+            ]));
+            let mut buf = Vec::new();
+            let mut cursor = Cursor::new(&mut buf);
+            encode_arrow_datatype(&data_type, &mut cursor)
+                .await
+                .unwrap();
+            assert_eq!(buf.len(), arrow_datatype_size(&data_type));
+
+            let mut cursor = Cursor::new(&mut buf);
+            let decoded = decode_arrow_datatype(&mut cursor).await.unwrap();
+            assert_eq!(data_type, decoded);
+        }
     }
 }
@@ -8,12 +8,15 @@ use arrow::{
         TimestampSecondArray,
     },
     datatypes::{
-        DataType, Field, Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type,
-        TimeUnit as ArrowTimeUnit, UInt16Type, UInt32Type, UInt64Type, UInt8Type,
+        DataType, Decimal128Type, Field, Fields, Float32Type, Float64Type, Int16Type, Int32Type,
+        Int64Type, Int8Type, TimeUnit as ArrowTimeUnit, UInt16Type, UInt32Type, UInt64Type,
+        UInt8Type,
     },
 };
 
-use crate::record::{split_second_ns, KeyRef, TimeUnit, Value, ValueError};
+use crate::record::{
+    cmp_decimal128, composite_data_type, split_second_ns, KeyRef, TimeUnit, Value, ValueError,
+};
 
 /// A reference type for Value that avoids cloning
 #[derive(Debug)]
@@ -38,7 +41,13 @@ pub enum ValueRef<'a> {
     Timestamp(i64, TimeUnit),
     Time32(i32, TimeUnit),
     Time64(i64, TimeUnit),
+    /// Reference to a [`Value::Decimal128`]
+    Decimal128(i128, u8, i8),
     List(&'a DataType, Vec<Arc<Value>>),
+    /// Reference to a [`Value::Struct`]
+    Struct(&'a Fields, Vec<ValueRef<'a>>),
+    /// Reference to a [`Value::Composite`]
+    Composite(Vec<ValueRef<'a>>),
 }
 
 impl Clone for ValueRef<'_> {
@@ -64,7 +73,10 @@ impl Clone for ValueRef<'_> {
             ValueRef::Timestamp(v, u) => ValueRef::Timestamp(*v, *u),
             ValueRef::Time32(v, u) => ValueRef::Time32(*v, *u),
             ValueRef::Time64(v, u) => ValueRef::Time64(*v, *u),
+            ValueRef::Decimal128(v, p, s) => ValueRef::Decimal128(*v, *p, *s),
             ValueRef::List(data_type, v) => ValueRef::List(data_type, v.clone()),
+            ValueRef::Struct(fields, values) => ValueRef::Struct(fields, values.clone()),
+            ValueRef::Composite(values) => ValueRef::Composite(values.clone()),
         }
     }
 }
@@ -264,6 +276,12 @@ impl<'a> ValueRef<'a> {
                 }
                 _ => unreachable!("Time64 only supports microsecond and nanosecond"),
             },
+            DataType::Decimal128(precision, scale) => {
+                let arr = array.as_primitive_opt::<Decimal128Type>().ok_or_else(|| {
+                    ValueError::InvalidConversion("Decimal128 cast failed".into())
+                })?;
+                Ok(ValueRef::Decimal128(arr.value(index), *precision, *scale))
+            }
             DataType::List(field) => {
                 let arr = array
                     .as_any()
@@ -283,6 +301,19 @@ impl<'a> ValueRef<'a> {
 
                 Ok(ValueRef::List(field.data_type(), values))
             }
+            DataType::Struct(fields) => {
+                let arr = array
+                    .as_struct_opt()
+                    .ok_or_else(|| ValueError::InvalidConversion("Struct cast failed".into()))?;
+
+                let values = arr
+                    .columns()
+                    .iter()
+                    .map(|column| ValueRef::from_array_ref(column, index))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(ValueRef::Struct(fields, values))
+            }
             _ => Err(ValueError::InvalidConversion(format!(
                 "Unsupported data type: {:?}",
                 array.data_type()
@@ -335,11 +366,16 @@ impl<'a> ValueRef<'a> {
                 };
                 DataType::Time64(arrow_unit)
             }
+            ValueRef::Decimal128(_, precision, scale) => DataType::Decimal128(*precision, *scale),
             ValueRef::List(data_type, _) => arrow::datatypes::DataType::List(Arc::new(Field::new(
                 "item",
                 (*data_type).clone(),
                 false,
             ))),
+            ValueRef::Struct(fields, _) => DataType::Struct((*fields).clone()),
+            ValueRef::Composite(values) => {
+                composite_data_type(values.iter().map(ValueRef::data_type))
+            }
         }
     }
 
@@ -373,10 +409,18 @@ impl ValueRef<'_> {
             ValueRef::Timestamp(v, unit) => Value::Timestamp(*v, *unit),
             ValueRef::Time32(v, unit) => Value::Time32(*v, *unit),
             ValueRef::Time64(v, unit) => Value::Time64(*v, *unit),
+            ValueRef::Decimal128(v, precision, scale) => Value::Decimal128(*v, *precision, *scale),
             ValueRef::List(data_type, values) => Value::List(
                 DataType::List(Arc::new(Field::new("item", (*data_type).clone(), false))),
                 values.clone(),
             ),
+            ValueRef::Struct(fields, values) => Value::Struct(
+                (*fields).clone(),
+                values.iter().map(ValueRef::to_owned).collect(),
+            ),
+            ValueRef::Composite(values) => {
+                Value::Composite(values.iter().map(ValueRef::to_owned).collect())
+            }
         }
     }
 }
@@ -404,7 +448,14 @@ impl<'a> From<&'a Value> for ValueRef<'a> {
             Value::Timestamp(v, unit) => ValueRef::Timestamp(*v, *unit),
             Value::Time32(v, unit) => ValueRef::Time32(*v, *unit),
             Value::Time64(v, unit) => ValueRef::Time64(*v, *unit),
+            Value::Decimal128(v, precision, scale) => ValueRef::Decimal128(*v, *precision, *scale),
             Value::List(data_type, v) => ValueRef::List(data_type, v.clone()),
+            Value::Struct(fields, values) => {
+                ValueRef::Struct(fields, values.iter().map(From::from).collect())
+            }
+            Value::Composite(values) => {
+                ValueRef::Composite(values.iter().map(From::from).collect())
+            }
         }
     }
 }
@@ -457,7 +508,14 @@ impl PartialEq for ValueRef<'_> {
                 let (o_sec, o_nsec) = split_second_ns(*b, *unit2);
                 s_sec == o_sec && s_nsec == o_nsec
             }
+            (ValueRef::Decimal128(a, _, scale1), ValueRef::Decimal128(b, _, scale2)) => {
+                cmp_decimal128(*a, *scale1, *b, *scale2).is_eq()
+            }
             (ValueRef::List(ty1, a), ValueRef::List(ty2, b)) => ty1.eq(ty2) && a.eq(b),
+            (ValueRef::Struct(fields1, a), ValueRef::Struct(fields2, b)) => {
+                fields1.eq(fields2) && a.eq(b)
+            }
+            (ValueRef::Composite(a), ValueRef::Composite(b)) => a.eq(b),
             _ => false,
         }
     }
@@ -503,12 +561,22 @@ impl Ord for ValueRef<'_> {
                     Ordering::Equal => s_nsec.cmp(&o_nsec),
                 }
             }
+            (ValueRef::Decimal128(a, _, scale1), ValueRef::Decimal128(b, _, scale2)) => {
+                cmp_decimal128(*a, *scale1, *b, *scale2)
+            }
             (ValueRef::List(ty1, a), ValueRef::List(ty2, b)) => {
                 if ty1 != ty2 {
                     panic!("can not compare different list types: {self:?} and {other:?}")
                 }
                 a.cmp(b)
             }
+            (ValueRef::Struct(fields1, a), ValueRef::Struct(fields2, b)) => {
+                if fields1 != fields2 {
+                    panic!("can not compare different struct types: {self:?} and {other:?}")
+                }
+                a.cmp(b)
+            }
+            (ValueRef::Composite(a), ValueRef::Composite(b)) => a.cmp(b),
             _ => {
                 panic!("can not compare different types: {self:?} and {other:?}")
             }
@@ -540,10 +608,18 @@ impl<'a> KeyRef<'a> for ValueRef<'a> {
             ValueRef::Timestamp(v, time_unit) => Value::Timestamp(v, time_unit),
             ValueRef::Time32(v, time_unit) => Value::Time32(v, time_unit),
             ValueRef::Time64(v, time_unit) => Value::Time64(v, time_unit),
+            ValueRef::Decimal128(v, precision, scale) => Value::Decimal128(v, precision, scale),
             ValueRef::List(data_type, v) => Value::List(
                 DataType::List(Arc::new(Field::new("item", data_type.clone(), false))),
                 v.clone(),
             ),
+            ValueRef::Struct(fields, values) => Value::Struct(
+                fields.clone(),
+                values.into_iter().map(KeyRef::to_key).collect(),
+            ),
+            ValueRef::Composite(values) => {
+                Value::Composite(values.into_iter().map(KeyRef::to_key).collect())
+            }
         }
     }
 }
@@ -1,13 +1,17 @@
 use thiserror::Error;
 
+#[cfg(feature = "dyn-record")]
 use crate::record::ValueError;
 
 #[derive(Debug, Error)]
 pub enum RecordError {
+    #[cfg(feature = "dyn-record")]
     #[error("value error: {0}")]
     ValueError(#[source] ValueError),
     #[error("Null value not allowed: {0}")]
     NullNotAllowed(String),
     #[error("Invalid argument : {0}")]
     InvalidArgumentError(String),
+    #[error("Unknown column: {0}")]
+    UnknownColumn(String),
 }
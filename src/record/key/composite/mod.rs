@@ -1,96 +1,122 @@
-#[cfg(test)]
-mod tests {
-    use std::hash::Hash;
+use std::{marker::PhantomData, sync::Arc};
 
-    use arrow::array::Datum;
-    use fusio::{SeqRead, Write};
-    use fusio_log::{Decode, Encode};
+use arrow::array::Datum;
+use fusio::{SeqRead, Write};
+use fusio_log::{Decode, Encode};
 
-    use crate::record::{Key, KeyRef};
+use crate::record::{Key, KeyRef};
 
-    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-    pub struct Key2<K1: Key, K2: Key>(pub K1, pub K2);
-
-    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-    pub struct Key2Ref<'r, R1: KeyRef<'r>, R2: KeyRef<'r>>(
-        pub R1,
-        pub R2,
-        std::marker::PhantomData<&'r ()>,
-    );
-
-    impl<K1: Key, K2: Key> Key for Key2<K1, K2> {
-        type Ref<'r> = Key2Ref<'r, <K1 as Key>::Ref<'r>, <K2 as Key>::Ref<'r>>;
-
-        fn as_key_ref(&self) -> Self::Ref<'_> {
-            Key2Ref(
-                self.0.as_key_ref(),
-                self.1.as_key_ref(),
-                std::marker::PhantomData,
-            )
-        }
+macro_rules! implement_composite_key {
+    ($key:ident, $key_ref:ident, $(($k:ident, $r:ident, $v:ident, $idx:tt)),+) => {
+        /// Primary key made of several columns, ordered by its first column, then by the next
+        /// one and so on, so the keys of one value of the first column form a range
+        #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $key<$($k: Key),+>($(pub $k),+);
+
+        /// Borrowed form of a composite key, ordered like it
+        #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+        pub struct $key_ref<'r, $($r: KeyRef<'r>),+>($(pub $r,)+ PhantomData<&'r ()>);
 
-        fn to_arrow_datums(&self) -> Vec<std::sync::Arc<dyn Datum>> {
-            let mut out = Vec::new();
-            out.extend(self.0.to_arrow_datums());
-            out.extend(self.1.to_arrow_datums());
-            out
+        impl<'r, $($r),+> $key_ref<'r, $($r),+>
+        where
+            $($r: KeyRef<'r>,)+
+        {
+            pub fn new($($v: $r),+) -> Self {
+                $key_ref($($v,)+ PhantomData)
+            }
         }
-    }
 
-    impl<'r, R1, R2> KeyRef<'r> for Key2Ref<'r, R1, R2>
-    where
-        R1: KeyRef<'r>,
-        R2: KeyRef<'r>,
-    {
-        type Key = Key2<R1::Key, R2::Key>;
+        impl<$($k: Key),+> Key for $key<$($k),+> {
+            type Ref<'r> = $key_ref<'r, $(<$k as Key>::Ref<'r>),+>;
 
-        fn to_key(self) -> Self::Key {
-            Key2(self.0.to_key(), self.1.to_key())
+            fn as_key_ref(&self) -> Self::Ref<'_> {
+                $key_ref($(self.$idx.as_key_ref(),)+ PhantomData)
+            }
+
+            fn to_arrow_datums(&self) -> Vec<Arc<dyn Datum>> {
+                let mut datums = Vec::new();
+                $(datums.extend(self.$idx.to_arrow_datums());)+
+                datums
+            }
         }
-    }
 
-    impl<K1: Key, K2: Key> Encode for Key2<K1, K2> {
-        async fn encode<W>(&self, writer: &mut W) -> Result<(), fusio::Error>
+        impl<'r, $($r),+> KeyRef<'r> for $key_ref<'r, $($r),+>
         where
-            W: Write,
+            $($r: KeyRef<'r>,)+
         {
-            self.0.encode(writer).await?;
-            self.1.encode(writer).await
+            type Key = $key<$($r::Key),+>;
+
+            fn to_key(self) -> Self::Key {
+                $key($(self.$idx.to_key()),+)
+            }
         }
 
-        fn size(&self) -> usize {
-            self.0.size() + self.1.size()
+        impl<$($k: Key),+> Encode for $key<$($k),+> {
+            async fn encode<W>(&self, writer: &mut W) -> Result<(), fusio::Error>
+            where
+                W: Write,
+            {
+                $(self.$idx.encode(writer).await?;)+
+                Ok(())
+            }
+
+            fn size(&self) -> usize {
+                0 $(+ self.$idx.size())+
+            }
         }
-    }
 
-    impl<'r, R1, R2> Encode for Key2Ref<'r, R1, R2>
-    where
-        R1: KeyRef<'r>,
-        R2: KeyRef<'r>,
-    {
-        async fn encode<W>(&self, writer: &mut W) -> Result<(), fusio::Error>
+        impl<'r, $($r),+> Encode for $key_ref<'r, $($r),+>
         where
-            W: Write,
+            $($r: KeyRef<'r>,)+
         {
-            self.0.encode(writer).await?;
-            self.1.encode(writer).await
+            async fn encode<W>(&self, writer: &mut W) -> Result<(), fusio::Error>
+            where
+                W: Write,
+            {
+                $(self.$idx.encode(writer).await?;)+
+                Ok(())
+            }
+
+            fn size(&self) -> usize {
+                0 $(+ self.$idx.size())+
+            }
         }
 
-        fn size(&self) -> usize {
-            self.0.size() + self.1.size()
+        impl<$($k: Key),+> Decode for $key<$($k),+> {
+            async fn decode<R>(reader: &mut R) -> Result<Self, fusio::Error>
+            where
+                R: SeqRead,
+            {
+                $(let $v = $k::decode(reader).await?;)+
+                Ok($key($($v),+))
+            }
         }
-    }
+    };
+}
 
-    impl<K1: Key, K2: Key> Decode for Key2<K1, K2> {
-        async fn decode<R>(reader: &mut R) -> Result<Self, fusio::Error>
-        where
-            R: SeqRead,
-        {
-            let k1 = K1::decode(reader).await?;
-            let k2 = K2::decode(reader).await?;
-            Ok(Key2(k1, k2))
-        }
-    }
+implement_composite_key!(Key2, Key2Ref, (K1, R1, k1, 0), (K2, R2, k2, 1));
+implement_composite_key!(
+    Key3,
+    Key3Ref,
+    (K1, R1, k1, 0),
+    (K2, R2, k2, 1),
+    (K3, R3, k3, 2)
+);
+implement_composite_key!(
+    Key4,
+    Key4Ref,
+    (K1, R1, k1, 0),
+    (K2, R2, k2, 1),
+    (K3, R3, k3, 2),
+    (K4, R4, k4, 3)
+);
+
+#[cfg(test)]
+mod tests {
+    use fusio_log::{Decode, Encode};
+
+    use super::{Key2, Key2Ref, Key3};
+    use crate::record::{Key, KeyRef};
 
     #[tokio::test]
     async fn key2_roundtrip_and_order() {
@@ -115,4 +141,16 @@ mod tests {
         let owned: Key2<String, u32> = r.to_key();
         assert_eq!(owned, Key2("zz".to_string(), 5));
     }
+
+    #[test]
+    fn key3_order_and_datums() {
+        let key = Key3(1u32, "b".to_string(), -1i64);
+        assert!(key < Key3(1, "b".to_string(), 0));
+        assert!(key > Key3(1, "a".to_string(), 5));
+        assert!(key < Key3(2, "a".to_string(), -5));
+        assert!(key.as_key_ref() < Key3(1u32, "c".to_string(), -2i64).as_key_ref());
+        assert_eq!(key.as_key_ref().to_key(), key);
+        // one datum per column, in the order of the columns
+        assert_eq!(key.to_arrow_datums().len(), 3);
+    }
 }
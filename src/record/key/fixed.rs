@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use arrow::array::{Datum, FixedSizeBinaryArray};
+use fusio::{SeqRead, Write};
+use fusio_log::{Decode, Encode};
+
+use super::{Key, KeyRef};
+
+/// Binary value of exactly `N` bytes, ordered by its bytes
+///
+/// Its column is an arrow `FixedSizeBinary(N)` column, which parquet stores as a
+/// `FIXED_LEN_BYTE_ARRAY` without the length of each value.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FixedSizeBinary<const N: usize>(pub [u8; N]);
+
+/// The 16 bytes of a UUID, ordered as the bytes of its canonical form
+pub type Uuid = FixedSizeBinary<16>;
+
+impl<const N: usize> FixedSizeBinary<N> {
+    pub fn as_bytes(&self) -> &[u8; N] {
+        &self.0
+    }
+}
+
+impl<const N: usize> Default for FixedSizeBinary<N> {
+    fn default() -> Self {
+        Self([0; N])
+    }
+}
+
+impl<const N: usize> From<[u8; N]> for FixedSizeBinary<N> {
+    fn from(value: [u8; N]) -> Self {
+        Self(value)
+    }
+}
+
+impl<const N: usize> From<&[u8]> for FixedSizeBinary<N> {
+    /// # Panics
+    ///
+    /// If `value` is not `N` bytes long
+    fn from(value: &[u8]) -> Self {
+        Self(value.try_into().unwrap_or_else(|_| {
+            panic!("expected {N} bytes, got {}", value.len());
+        }))
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for FixedSizeBinary<N> {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<const N: usize> Encode for FixedSizeBinary<N> {
+    async fn encode<W: Write>(&self, writer: &mut W) -> Result<(), fusio::Error> {
+        let (result, _) = writer.write_all(&self.0[..]).await;
+        result?;
+
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        N
+    }
+}
+
+impl<const N: usize> Decode for FixedSizeBinary<N> {
+    async fn decode<R: SeqRead>(reader: &mut R) -> Result<Self, fusio::Error> {
+        let mut bytes = [0u8; N];
+        let (result, _) = reader.read_exact(&mut bytes[..]).await;
+        result?;
+
+        Ok(Self(bytes))
+    }
+}
+
+impl<const N: usize> Key for FixedSizeBinary<N> {
+    type Ref<'r> = FixedSizeBinary<N>;
+
+    fn as_key_ref(&self) -> Self::Ref<'_> {
+        *self
+    }
+
+    fn to_arrow_datums(&self) -> Vec<Arc<dyn Datum>> {
+        vec![Arc::new(FixedSizeBinaryArray::new_scalar(self.0)) as Arc<dyn Datum>]
+    }
+}
+
+impl<const N: usize> KeyRef<'_> for FixedSizeBinary<N> {
+    type Key = FixedSizeBinary<N>;
+
+    fn to_key(self) -> Self::Key {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fusio_log::{Decode, Encode};
+
+    use super::{FixedSizeBinary, Uuid};
+    use crate::transaction::Buffer;
+
+    #[tokio::test]
+    async fn test_encode_decode() {
+        let key = Uuid::from([7; 16]);
+        let mut buf = Buffer::default();
+        key.encode(&mut buf).await.unwrap();
+        assert_eq!(key.size(), 16);
+
+        let bytes = buf.into_bytes();
+        assert_eq!(bytes.len(), 16);
+        let decoded = Uuid::decode(&mut Buffer::new(bytes)).await.unwrap();
+        assert_eq!(decoded, key);
+    }
+
+    #[test]
+    fn test_order() {
+        let a = FixedSizeBinary::from([0, 255]);
+        let b = FixedSizeBinary::from([1, 0]);
+        assert!(a < b);
+        assert_eq!(FixedSizeBinary::<2>::from(&[1, 0][..]), b);
+    }
+}
@@ -1,5 +1,6 @@
 mod composite;
 mod datetime;
+mod fixed;
 mod list;
 mod num;
 mod str;
@@ -8,7 +9,9 @@ mod timestamp;
 use std::{hash::Hash, sync::Arc};
 
 use arrow::array::Datum;
+pub use composite::*;
 pub use datetime::*;
+pub use fixed::*;
 use fusio_log::{Decode, Encode};
 pub use list::*;
 pub use num::*;
@@ -34,3 +37,13 @@ pub trait KeyRef<'r>: Clone + Encode + Send + Sync + Ord + std::fmt::Debug {
 
     fn to_key(self) -> Self::Key;
 }
+
+/// Keys that are ordered by their bytes, so the keys that start with a prefix form a range, see
+/// [`DB::scan_prefix`](crate::DB::scan_prefix)
+pub trait PrefixKey: Key {
+    /// Bytes of the key in the order of the keys
+    fn prefix_bytes(&self) -> &[u8];
+
+    /// Smallest key after every key that starts with this one, `None` if there is none
+    fn prefix_successor(&self) -> Option<Self>;
+}
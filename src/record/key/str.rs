@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use arrow::array::{Datum, StringArray};
 
-use super::{Key, KeyRef};
+use super::{Key, KeyRef, PrefixKey};
 
 pub type LargeString = String;
 
@@ -18,6 +18,28 @@ impl Key for String {
     }
 }
 
+impl PrefixKey for String {
+    fn prefix_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+
+    fn prefix_successor(&self) -> Option<Self> {
+        let mut successor = self.clone();
+        // trailing `char::MAX`s have no next char, so the one before them is advanced
+        while let Some(last) = successor.pop() {
+            let next = match last {
+                '\u{d7ff}' => Some('\u{e000}'),
+                last => char::from_u32(last as u32 + 1),
+            };
+            if let Some(next) = next {
+                successor.push(next);
+                return Some(successor);
+            }
+        }
+        None
+    }
+}
+
 impl<'r> KeyRef<'r> for &'r str {
     type Key = String;
 
@@ -25,3 +47,26 @@ impl<'r> KeyRef<'r> for &'r str {
         self.to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::record::PrefixKey;
+
+    #[test]
+    fn prefix_successor() {
+        assert_eq!(
+            "user:1:".to_string().prefix_successor(),
+            Some("user:1;".into())
+        );
+        assert_eq!(
+            "ab\u{d7ff}".to_string().prefix_successor(),
+            Some("ab\u{e000}".into())
+        );
+        assert_eq!(
+            "a\u{10ffff}\u{10ffff}".to_string().prefix_successor(),
+            Some("b".into())
+        );
+        assert_eq!("\u{10ffff}".to_string().prefix_successor(), None);
+        assert_eq!(String::new().prefix_successor(), None);
+    }
+}
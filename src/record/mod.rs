@@ -1,3 +1,4 @@
+#[cfg(feature = "dyn-record")]
 pub mod dynamic;
 pub mod error;
 pub mod key;
@@ -8,11 +9,16 @@ pub(crate) mod test;
 use std::{fmt::Debug, sync::Arc};
 
 use arrow::{array::RecordBatch, datatypes::Schema as ArrowSchema};
+#[cfg(feature = "dyn-record")]
 pub use dynamic::*;
 use fusio_log::{Decode, Encode};
 pub use key::*;
 use option::OptionRecordRef;
-use parquet::{arrow::ProjectionMask, format::SortingColumn, schema::types::ColumnPath};
+use parquet::{
+    arrow::ProjectionMask,
+    format::SortingColumn,
+    schema::types::{ColumnPath, SchemaDescriptor},
+};
 
 use crate::version::timestamp::Ts;
 
@@ -72,6 +78,37 @@ pub trait Schema: Debug + Send + Sync {
     /// index that would have been returned by the legacy single-index API.
     fn primary_key_indices(&self) -> &[usize];
 
+    /// Returns the Parquet [`SchemaDescriptor`] of [`Schema::arrow_schema`], which projection
+    /// masks are built from.
+    ///
+    /// The conversion with [`ArrowSchemaConverter`](parquet::arrow::ArrowSchemaConverter) is not
+    /// cheap, so it should be done once and the descriptor kept next to the Arrow schema.
+    fn parquet_schema(&self) -> &SchemaDescriptor;
+
+    /// Returns the projection of the columns `names` that reads the `_null`, `_ts` and primary
+    /// key columns along with them, e.g. `schema.projection(["a", "b"])`.
+    ///
+    /// The mask can be kept and reused by every read of these columns.
+    ///
+    /// # Panics
+    ///
+    /// If a name is not a column of the schema.
+    fn projection<'n>(&self, names: impl IntoIterator<Item = &'n str>) -> ProjectionMask
+    where
+        Self: Sized,
+    {
+        let arrow_schema = self.arrow_schema();
+        let mut indices = vec![0, 1];
+        indices.extend_from_slice(self.primary_key_indices());
+        indices.extend(names.into_iter().map(|name| {
+            arrow_schema
+                .index_of(name)
+                .unwrap_or_else(|_| panic!("unexpected field {name}"))
+        }));
+
+        ProjectionMask::roots(self.parquet_schema(), indices)
+    }
+
     // Note: Implementations should ensure parity with `primary_key_indices()` for included
     // columns, and include `_ts` in sorting columns.
 }
@@ -94,6 +131,10 @@ pub trait Record: 'static + Sized + Decode + Debug + Send + Sync {
 
     /// Returns the size of the record in bytes.
     fn size(&self) -> usize;
+
+    /// Adapt a record decoded from a write ahead log to `schema`, which may append nullable
+    /// columns to the schema it was written with.
+    fn evolve(&mut self, _schema: &Self::Schema) {}
 }
 
 pub trait RecordRef<'r>: Clone + Sized + Encode + Send + Sync {
@@ -108,6 +149,23 @@ pub trait RecordRef<'r>: Clone + Sized + Encode + Send + Sync {
     /// Note: Primary key column(s) are always kept.
     fn projection(&mut self, projection_mask: &ProjectionMask);
 
+    /// [`projection`](RecordRef::projection) with `full_schema`, the combination of `_null`,
+    /// `_ts` and all fields defined in the [`Schema`], which the leaf columns of the mask are
+    /// those of. Records whose columns may span several leaf columns, e.g. struct columns, need
+    /// it to tell the leaves of each column.
+    fn projection_with_schema(
+        &mut self,
+        projection_mask: &ProjectionMask,
+        full_schema: &Arc<ArrowSchema>,
+    ) {
+        let _ = full_schema;
+        self.projection(projection_mask)
+    }
+
+    /// Returns an owned copy of the record, `None` if a column that is not nullable was left out
+    /// by a [`projection`](RecordRef::projection). Nullable columns that were left out are null.
+    fn to_owned_record(&self) -> Option<Self::Record>;
+
     /// Get the [`RecordRef`] from the [`RecordBatch`] at the given offset.
     ///
     /// `full_schema` is the combination of `_null`, `_ts` and all fields defined in the [`Schema`].
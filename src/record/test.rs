@@ -8,7 +8,11 @@ use arrow::{
     datatypes::{DataType, Field, Schema as ArrowSchema, UInt32Type},
 };
 use once_cell::sync::Lazy;
-use parquet::{arrow::ProjectionMask, format::SortingColumn, schema::types::ColumnPath};
+use parquet::{
+    arrow::{ArrowSchemaConverter, ProjectionMask},
+    format::SortingColumn,
+    schema::types::{ColumnPath, SchemaDescriptor},
+};
 
 use super::{
     option::OptionRecordRef, ArrowArrays, ArrowArraysBuilder, Key, Record, RecordRef, Schema,
@@ -62,6 +66,16 @@ impl Schema for StringSchema {
         });
         (&PATHS[..], &SORTING[..])
     }
+
+    fn parquet_schema(&self) -> &SchemaDescriptor {
+        static SCHEMA: Lazy<SchemaDescriptor> = Lazy::new(|| {
+            ArrowSchemaConverter::new()
+                .convert(StringSchema.arrow_schema())
+                .unwrap()
+        });
+
+        &SCHEMA
+    }
 }
 
 impl Record for String {
@@ -94,6 +108,10 @@ impl<'r> RecordRef<'r> for &'r str {
 
     fn projection(&mut self, _: &ProjectionMask) {}
 
+    fn to_owned_record(&self) -> Option<Self::Record> {
+        Some(self.to_string())
+    }
+
     fn from_record_batch(
         record_batch: &'r RecordBatch,
         offset: usize,
@@ -301,6 +319,14 @@ impl<'r> RecordRef<'r> for TestRef<'r> {
         }
     }
 
+    fn to_owned_record(&self) -> Option<Self::Record> {
+        Some(Test {
+            vstring: self.vstring.to_string(),
+            vu32: self.vu32?,
+            vbool: self.vbool,
+        })
+    }
+
     fn from_record_batch(
         record_batch: &'r RecordBatch,
         offset: usize,
@@ -0,0 +1,209 @@
+//! Tokens that resume a paged scan after the last key of a page, see
+//! [`Scan::take_resumable`](crate::Scan::take_resumable)
+//!
+//! A stateless service hands the token of a page to its client, e.g. as an HTTP query
+//! parameter, and resumes the scan from it when the client asks for the next page, instead of
+//! skipping the pages before it with [`Scan::offset`](crate::Scan::offset):
+//!
+//! ```ignore
+//! let token = match request.token {
+//!     Some(bytes) => Some(ResumeToken::from_bytes(&bytes).await?),
+//!     None => None,
+//! };
+//! let mut scan = txn.scan((Bound::Unbounded, Bound::Unbounded)).limit(50);
+//! if let Some(token) = &token {
+//!     scan = scan.resume(token);
+//! }
+//! let mut page = scan.take_resumable().await?;
+//! while let Some(entry) = page.next().await.transpose()? {
+//!     // ...
+//! }
+//! let next = match page.resume_token() {
+//!     Some(token) => Some(token.to_bytes().await?),
+//!     None => None,
+//! };
+//! ```
+
+use std::{
+    marker::PhantomData,
+    ops::Bound,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use fusio::{SeqRead, Write};
+use fusio_log::{Decode, Encode};
+use futures_core::Stream;
+use parquet::errors::ParquetError;
+
+use crate::{
+    option::Order,
+    record::{Key, KeyRef, Record, Schema},
+    stream::Entry,
+    transaction::Buffer,
+    version::timestamp::Timestamp,
+};
+
+/// Where a page of a scan ended: its last key and the timestamp it was read at
+///
+/// A scan [resumed](crate::Scan::resume) from the token returns the keys after the last one in
+/// the order of the scan, so no key is returned twice. The next page is read at the timestamp
+/// of its own snapshot, as the versions the page was read at are not kept, so comparing
+/// [`ResumeToken::ts`] with it tells whether commits happened in between.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResumeToken<K>
+where
+    K: Key,
+{
+    key: K,
+    ts: Timestamp,
+}
+
+impl<K> ResumeToken<K>
+where
+    K: Key,
+{
+    /// Last key of the page, removed or not
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Timestamp the page was read at
+    pub fn ts(&self) -> Timestamp {
+        self.ts
+    }
+
+    /// Bytes of the token, which [`ResumeToken::from_bytes`] reads back
+    pub async fn to_bytes(&self) -> Result<Vec<u8>, fusio::Error> {
+        let mut buf = Buffer::default();
+        self.encode(&mut buf).await?;
+        Ok(buf.into_bytes())
+    }
+
+    /// Token written by [`ResumeToken::to_bytes`]
+    pub async fn from_bytes(bytes: &[u8]) -> Result<Self, fusio::Error> {
+        Self::decode(&mut Buffer::new(bytes.to_vec())).await
+    }
+
+    /// The part of the range `(lower, upper)` that comes after the key of the token in `order`,
+    /// `None` if none of it does
+    pub(crate) fn after<'range>(
+        &'range self,
+        (lower, upper): (Bound<&'range K>, Bound<&'range K>),
+        order: Order,
+    ) -> Option<(Bound<&'range K>, Bound<&'range K>)> {
+        let key = &self.key;
+        let range = match order {
+            Order::Asc => {
+                let lower = match lower {
+                    Bound::Included(bound) if bound > key => lower,
+                    Bound::Excluded(bound) if bound >= key => lower,
+                    _ => Bound::Excluded(key),
+                };
+                (lower, upper)
+            }
+            Order::Desc => {
+                let upper = match upper {
+                    Bound::Included(bound) if bound < key => upper,
+                    Bound::Excluded(bound) if bound <= key => upper,
+                    _ => Bound::Excluded(key),
+                };
+                (lower, upper)
+            }
+        };
+        let empty = match range {
+            (Bound::Included(lower), Bound::Included(upper)) => lower > upper,
+            (
+                Bound::Included(lower) | Bound::Excluded(lower),
+                Bound::Included(upper) | Bound::Excluded(upper),
+            ) => lower >= upper,
+            _ => false,
+        };
+        (!empty).then_some(range)
+    }
+}
+
+impl<K> Encode for ResumeToken<K>
+where
+    K: Key,
+{
+    async fn encode<W>(&self, writer: &mut W) -> Result<(), fusio::Error>
+    where
+        W: Write,
+    {
+        self.key.encode(writer).await?;
+        self.ts.encode(writer).await
+    }
+
+    fn size(&self) -> usize {
+        self.key.size() + self.ts.size()
+    }
+}
+
+impl<K> Decode for ResumeToken<K>
+where
+    K: Key,
+{
+    async fn decode<R>(reader: &mut R) -> Result<Self, fusio::Error>
+    where
+        R: SeqRead,
+    {
+        let key = K::decode(reader).await?;
+        let ts = Timestamp::decode(reader).await?;
+        Ok(ResumeToken { key, ts })
+    }
+}
+
+/// Stream of a page of a scan that keeps its last key, see
+/// [`Scan::take_resumable`](crate::Scan::take_resumable)
+pub struct Resumable<'scan, R, S>
+where
+    R: Record,
+{
+    stream: Pin<Box<S>>,
+    ts: Timestamp,
+    last: Option<<R::Schema as Schema>::Key>,
+    _marker: PhantomData<Entry<'scan, R>>,
+}
+
+impl<'scan, R, S> Resumable<'scan, R, S>
+where
+    R: Record,
+    S: Stream<Item = Result<Entry<'scan, R>, ParquetError>>,
+{
+    pub(crate) fn new(stream: S, ts: Timestamp) -> Self {
+        Self {
+            stream: Box::pin(stream),
+            ts,
+            last: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Token that resumes the scan after the last entry returned so far, `None` if none was
+    pub fn resume_token(&self) -> Option<ResumeToken<<R::Schema as Schema>::Key>> {
+        self.last
+            .clone()
+            .map(|key| ResumeToken { key, ts: self.ts })
+    }
+}
+
+// the stream is boxed, so no field is ever pinned
+impl<R, S> Unpin for Resumable<'_, R, S> where R: Record {}
+
+impl<'scan, R, S> Stream for Resumable<'scan, R, S>
+where
+    R: Record,
+    S: Stream<Item = Result<Entry<'scan, R>, ParquetError>>,
+{
+    type Item = Result<Entry<'scan, R>, ParquetError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let poll = this.stream.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(Ok(entry))) = &poll {
+            this.last = Some(entry.key().value.to_key());
+        }
+        poll
+    }
+}
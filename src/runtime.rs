@@ -0,0 +1,193 @@
+//! Resources shared by many [`DB`]s
+//!
+//! Every [`DB`] opened with [`DB::new`] has its own [`StoreManager`], its own parquet cache and
+//! its own background tasks. A [`TonboRuntime`] owns one of each and opens any number of DBs
+//! against them, so many small tables share one pool of open files, one cache and one task that
+//! flushes and compacts all of them.
+
+use std::{pin::Pin, sync::Arc};
+
+use async_lock::Semaphore;
+use flume::{Receiver, Sender};
+use fusio::dynamic::MaybeSendFuture;
+use futures_util::{select, stream::FuturesUnordered, StreamExt};
+use parquet_lru::NoCache;
+
+use crate::{
+    executor::Executor,
+    fs::manager::StoreManager,
+    record::{Record, Schema},
+    DbError, DbOption, ParquetLru, DB,
+};
+
+type Task = Pin<Box<dyn MaybeSendFuture<Output = ()> + 'static>>;
+
+/// Executor, storage, cache and background task shared by the [`DB`]s it opens
+///
+/// The file systems of `base_fs` and `level_paths` and the `max_open_files` of the [`DbOption`]
+/// of a DB opened by the runtime are ignored in favor of its [`StoreManager`], see
+/// [`StoreManager::max_open_files`]. The level paths are still used to place the SSTables, on the
+/// file system the [`StoreManager`] has for them.
+pub struct TonboRuntime<E: Executor> {
+    executor: E,
+    manager: Arc<StoreManager>,
+    cache: ParquetLru,
+    scheduler: Scheduler,
+}
+
+impl<E> TonboRuntime<E>
+where
+    E: Executor + Send + Sync + 'static,
+{
+    /// Start the background task of the runtime on `executor`
+    pub fn new(executor: E, manager: StoreManager) -> Self {
+        let (tasks, task_rx) = flume::unbounded();
+        executor.spawn(run_tasks(task_rx));
+
+        Self {
+            executor,
+            manager: Arc::new(manager),
+            cache: Arc::new(NoCache::default()),
+            scheduler: Scheduler {
+                tasks,
+                permits: None,
+            },
+        }
+    }
+
+    /// Cache of the SSTables of all DBs the runtime opens from now on
+    pub fn cache(self, cache: ParquetLru) -> Self {
+        TonboRuntime { cache, ..self }
+    }
+
+    /// Run up to `max` flushes and compactions of the DBs the runtime opens from now on at the
+    /// same time, 0 does not limit them. The default is 0.
+    pub fn max_concurrent_compactions(self, max: usize) -> Self {
+        TonboRuntime {
+            scheduler: Scheduler {
+                permits: (max > 0).then(|| Arc::new(Semaphore::new(max))),
+                ..self.scheduler
+            },
+            ..self
+        }
+    }
+
+    pub fn store_manager(&self) -> &Arc<StoreManager> {
+        &self.manager
+    }
+
+    /// Open a [`DB`] with `option`, see [`DB::new`]
+    pub async fn open<R>(&self, option: DbOption, schema: R::Schema) -> Result<DB<R, E>, DbError>
+    where
+        R: Record + Send + Sync,
+        <R::Schema as Schema>::Columns: Send + Sync,
+    {
+        DB::build(
+            Arc::new(option),
+            &self.executor,
+            schema,
+            self.manager.clone(),
+            self.cache.clone(),
+            Some(&self.scheduler),
+        )
+        .await
+    }
+}
+
+/// Runs the background tasks of the DBs of a [`TonboRuntime`]
+pub(crate) struct Scheduler {
+    tasks: Sender<Task>,
+    permits: Option<Arc<Semaphore>>,
+}
+
+impl Scheduler {
+    pub(crate) fn run<F>(&self, task: F)
+    where
+        F: MaybeSendFuture<Output = ()> + 'static,
+    {
+        // the task is dropped along with the runtime
+        let _ = self.tasks.send(Box::pin(task));
+    }
+
+    /// Permits of a flush or compaction, if their number is limited
+    pub(crate) fn permits(&self) -> Option<Arc<Semaphore>> {
+        self.permits.clone()
+    }
+}
+
+async fn run_tasks(task_rx: Receiver<Task>) {
+    let mut incoming = task_rx.into_stream();
+    let mut running = FuturesUnordered::new();
+
+    loop {
+        select! {
+            task = incoming.next() => match task {
+                Some(task) => running.push(task),
+                None => break,
+            },
+            () = running.select_next_some() => (),
+        }
+    }
+    while running.next().await.is_some() {}
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::Arc;
+
+    use fusio::path::Path;
+    use fusio_dispatch::FsOptions;
+    use tempfile::TempDir;
+
+    use super::TonboRuntime;
+    use crate::{
+        background::BackgroundTask, executor::tokio::TokioExecutor, fs::manager::StoreManager,
+        inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn databases_share_runtime() {
+        let temp_dir = TempDir::new().unwrap();
+        let runtime = TonboRuntime::new(
+            TokioExecutor::default(),
+            StoreManager::new(FsOptions::Local, vec![]).unwrap(),
+        )
+        .max_concurrent_compactions(1);
+
+        let mut dbs = Vec::new();
+        for name in ["a", "b"] {
+            let path = temp_dir.path().join(name);
+            std::fs::create_dir_all(&path).unwrap();
+            let db = runtime
+                .open::<Test>(
+                    DbOption::new(Path::from_filesystem_path(&path).unwrap(), &TestSchema),
+                    TestSchema,
+                )
+                .await
+                .unwrap();
+            dbs.push(db);
+        }
+
+        for (i, db) in dbs.iter().enumerate() {
+            db.insert(Test {
+                vstring: i.to_string(),
+                vu32: i as u32,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+            db.flush().await.unwrap();
+            assert!(db.is_background_task_alive(BackgroundTask::Compactor));
+            assert!(Arc::ptr_eq(&db.ctx.manager, runtime.store_manager()));
+        }
+        for (i, db) in dbs.iter().enumerate() {
+            let version = db.current_manifest().await;
+            assert_eq!(version.level_slice[0].len(), 1);
+            let value = db
+                .get(&i.to_string(), |entry| entry.get().vu32)
+                .await
+                .unwrap();
+            assert_eq!(value, Some(i as u32));
+        }
+    }
+}
@@ -5,6 +5,12 @@ use fusio_log::{Decode, Encode};
 
 use crate::{fs::FileId, record::Key};
 
+// Flags of an encoded scope, whose version logs written before the row counts only know
+// `WAL_IDS`
+const WAL_IDS: u8 = 1;
+const ROW_COUNTS: u8 = 2;
+const EXPIRES: u8 = 4;
+
 #[derive(Debug, Eq, PartialEq)]
 pub struct Scope<K: Key> {
     pub min: K,
@@ -13,6 +19,14 @@ pub struct Scope<K: Key> {
     pub wal_ids: Option<Vec<FileId>>,
     /// Approximate file size in bytes
     pub file_size: u64,
+    /// Number of rows, 0 if the table was written before rows were counted
+    pub rows: u64,
+    /// Number of rows that remove their key
+    pub tombstones: u64,
+    /// Earliest time in milliseconds since the UNIX epoch a record of the table expires at,
+    /// `u64::MAX` if none does, `None` if the table was written without
+    /// [`DbOption::expires_at`](crate::DbOption::expires_at)
+    pub expires: Option<u64>,
 }
 
 impl<K> Clone for Scope<K>
@@ -26,6 +40,9 @@ where
             gen: self.gen,
             wal_ids: self.wal_ids.clone(),
             file_size: self.file_size,
+            rows: self.rows,
+            tombstones: self.tombstones,
+            expires: self.expires,
         }
     }
 }
@@ -38,6 +55,11 @@ where
         &self.min <= key && key <= &self.max
     }
 
+    /// Share of the rows that remove their key, `None` if the rows were not counted
+    pub fn tombstone_ratio(&self) -> Option<f64> {
+        (self.rows > 0).then(|| self.tombstones as f64 / self.rows as f64)
+    }
+
     #[allow(unused)]
     pub fn meets(&self, target: &Self) -> bool {
         self.contains(&target.min) || self.contains(&target.max)
@@ -94,12 +116,13 @@ where
 
         self.file_size.encode(writer).await?;
 
+        let expires = if self.expires.is_some() { EXPIRES } else { 0 };
         match &self.wal_ids {
             None => {
-                0u8.encode(writer).await?;
+                (ROW_COUNTS | expires).encode(writer).await?;
             }
             Some(ids) => {
-                1u8.encode(writer).await?;
+                (WAL_IDS | ROW_COUNTS | expires).encode(writer).await?;
                 (ids.len() as u32).encode(writer).await?;
                 for id in ids {
                     let (result, _) = writer.write_all(&id.to_bytes()[..]).await;
@@ -107,6 +130,11 @@ where
                 }
             }
         }
+        self.rows.encode(writer).await?;
+        self.tombstones.encode(writer).await?;
+        if let Some(expires) = self.expires {
+            expires.encode(writer).await?;
+        }
         Ok(())
     }
 
@@ -132,9 +160,10 @@ where
         };
         let size = u64::decode(reader).await?;
 
-        let wal_ids = match u8::decode(reader).await? {
+        let flags = u8::decode(reader).await?;
+        let wal_ids = match flags & WAL_IDS {
             0 => None,
-            1 => {
+            _ => {
                 let len = u32::decode(reader).await? as usize;
                 let mut ids = Vec::with_capacity(len);
 
@@ -145,7 +174,14 @@ where
                 }
                 Some(ids)
             }
-            _ => unreachable!(),
+        };
+        let (rows, tombstones) = match flags & ROW_COUNTS {
+            0 => (0, 0),
+            _ => (u64::decode(reader).await?, u64::decode(reader).await?),
+        };
+        let expires = match flags & EXPIRES {
+            0 => None,
+            _ => Some(u64::decode(reader).await?),
         };
 
         Ok(Scope {
@@ -154,6 +190,9 @@ where
             gen,
             wal_ids,
             file_size: size,
+            rows,
+            tombstones,
+            expires,
         })
     }
 }
@@ -179,6 +218,9 @@ mod test {
             gen: generate_file_id(),
             wal_ids: None,
             file_size: 8,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
         };
 
         // test out of range
@@ -242,6 +284,9 @@ mod test {
             gen: generate_file_id(),
             wal_ids: None,
             file_size: 8,
+            rows: 3,
+            tombstones: 1,
+            expires: Some(1_000),
         };
 
         let mut bytes = Vec::new();
@@ -0,0 +1,321 @@
+//! Partitioning of the keys of a table over several [`DB`]s
+//!
+//! Every [`DB`] compacts on its own, so a [`ShardedDB`] spreads the write and compaction load of
+//! a table over its shards. Each key lives in exactly one shard, picked by the [`Sharding`] of
+//! the table.
+
+use std::{
+    hash::{Hash, Hasher},
+    ops::Bound,
+};
+
+use async_stream::stream;
+use futures_core::Stream;
+use futures_util::StreamExt;
+
+use crate::{
+    executor::Executor,
+    record::{KeyRef, Record, Schema},
+    stream::merge::MergeStream,
+    transaction::{CommitError, TransactionEntry},
+    version::timestamp::Timestamp,
+    DB,
+};
+
+/// How the keys of a [`ShardedDB`] are assigned to its shards
+///
+/// The assignment has to stay the same for the lifetime of the data, so the number of shards or
+/// the split keys must not change once records were written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sharding<K> {
+    /// By the hash of the key, which spreads any key distribution evenly
+    Hash,
+    /// By key range. Shard `i` holds the keys from split key `i - 1` up to, but excluding, split
+    /// key `i`, so there is one shard more than split keys. Scans only read the shards that
+    /// overlap their range.
+    Range(Vec<K>),
+}
+
+/// A table whose keys are partitioned over several [`DB`]s
+///
+/// Every shard is opened on its own paths, e.g. with [`DB::new`] or
+/// [`TonboRuntime::open`](crate::runtime::TonboRuntime::open). Gets and writes go to the shard of
+/// their key, scans read all shards that may hold keys of their range and merge them in key
+/// order. Writes to different shards are not atomic with each other.
+pub struct ShardedDB<R, E>
+where
+    R: Record,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor,
+{
+    shards: Vec<DB<R, E>>,
+    sharding: Sharding<<R::Schema as Schema>::Key>,
+}
+
+impl<R, E> ShardedDB<R, E>
+where
+    R: Record + Send + Sync,
+    <R::Schema as Schema>::Columns: Send + Sync,
+    E: Executor + Send + Sync + 'static,
+{
+    /// Partition the keys over `shards` by `sharding`
+    ///
+    /// # Panics
+    ///
+    /// If there are no shards, or if range sharding does not have one split key less than there
+    /// are shards, in ascending order.
+    pub fn new(shards: Vec<DB<R, E>>, sharding: Sharding<<R::Schema as Schema>::Key>) -> Self {
+        assert!(!shards.is_empty(), "a sharded DB needs at least one shard");
+        if let Sharding::Range(splits) = &sharding {
+            assert_eq!(
+                splits.len() + 1,
+                shards.len(),
+                "range sharding needs one split key less than there are shards"
+            );
+            assert!(
+                splits.windows(2).all(|pair| pair[0] < pair[1]),
+                "split keys must be in ascending order"
+            );
+        }
+        Self { shards, sharding }
+    }
+
+    pub fn shards(&self) -> &[DB<R, E>] {
+        &self.shards
+    }
+
+    /// Index of the shard that holds `key`
+    pub fn shard_of(&self, key: &<R::Schema as Schema>::Key) -> usize {
+        match &self.sharding {
+            Sharding::Hash => {
+                let mut hasher = Fnv1a::default();
+                key.hash(&mut hasher);
+                (hasher.finish() % self.shards.len() as u64) as usize
+            }
+            Sharding::Range(splits) => splits.partition_point(|split| split <= key),
+        }
+    }
+
+    /// Insert a single record into the shard of its key
+    pub async fn insert(&self, record: R) -> Result<(), CommitError<R>> {
+        let shard = self.shard_of(&record.key().to_key());
+        self.shards[shard].insert(record).await
+    }
+
+    /// Insert `records`, as a single batch per shard
+    pub async fn insert_batch(
+        &self,
+        records: impl IntoIterator<Item = R>,
+    ) -> Result<(), CommitError<R>> {
+        let mut batches = (0..self.shards.len())
+            .map(|_| Vec::new())
+            .collect::<Vec<_>>();
+        for record in records {
+            batches[self.shard_of(&record.key().to_key())].push(record);
+        }
+        for (shard, batch) in self.shards.iter().zip(batches) {
+            if !batch.is_empty() {
+                shard.insert_batch(batch.into_iter()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete the record with the primary key as the `key`
+    pub async fn remove(&self, key: <R::Schema as Schema>::Key) -> Result<(), CommitError<R>> {
+        let shard = self.shard_of(&key);
+        self.shards[shard].remove(key).await.map(|_| ())
+    }
+
+    /// Get the record with `key` as the primary key from its shard, see [`DB::get`]
+    pub async fn get<T>(
+        &self,
+        key: &<R::Schema as Schema>::Key,
+        f: impl FnMut(TransactionEntry<'_, R>) -> Option<T>,
+    ) -> Result<Option<T>, CommitError<R>> {
+        self.shards[self.shard_of(key)].get(key, f).await
+    }
+
+    /// Scan records with primary keys in the `range` of all shards in key order and process
+    /// them using closure `f`, see [`DB::scan`]
+    pub async fn scan<'scan, T: 'scan>(
+        &'scan self,
+        range: (
+            Bound<&'scan <R::Schema as Schema>::Key>,
+            Bound<&'scan <R::Schema as Schema>::Key>,
+        ),
+        mut f: impl FnMut(TransactionEntry<'_, R>) -> T + 'scan,
+    ) -> impl Stream<Item = Result<T, CommitError<R>>> + 'scan {
+        stream! {
+            let mut snapshots = Vec::new();
+            for shard in self.shards_in(range) {
+                snapshots.push(self.shards[shard].snapshot().await);
+            }
+            let mut streams = Vec::with_capacity(snapshots.len());
+            for snapshot in snapshots.iter() {
+                streams.push(snapshot.scan(range).merge_stream().await?.into());
+            }
+            // every shard already hides the versions newer than its snapshot
+            let mut scan = MergeStream::from_vec(streams, Timestamp::from(u32::MAX), None).await?;
+
+            while let Some(record) = scan.next().await {
+                yield Ok(f(TransactionEntry::Stream(record?)))
+            }
+        }
+    }
+
+    /// Flush the WAL and trigger compaction of every shard, see [`DB::flush`]
+    pub async fn flush(&self) -> Result<(), CommitError<R>> {
+        for shard in self.shards.iter() {
+            shard.flush().await?;
+        }
+        Ok(())
+    }
+
+    // Shards that may hold keys in `range`
+    fn shards_in(
+        &self,
+        range: (
+            Bound<&<R::Schema as Schema>::Key>,
+            Bound<&<R::Schema as Schema>::Key>,
+        ),
+    ) -> std::ops::Range<usize> {
+        match range {
+            (Bound::Unbounded, Bound::Unbounded) => 0..self.shards.len(),
+            _ if matches!(self.sharding, Sharding::Hash) => 0..self.shards.len(),
+            (lower, upper) => {
+                let first = match lower {
+                    Bound::Included(key) | Bound::Excluded(key) => self.shard_of(key),
+                    Bound::Unbounded => 0,
+                };
+                let last = match upper {
+                    Bound::Included(key) | Bound::Excluded(key) => self.shard_of(key),
+                    Bound::Unbounded => self.shards.len() - 1,
+                };
+                first..last.max(first) + 1
+            }
+        }
+    }
+}
+
+/// FNV-1a, which unlike the std hashers is neither seeded nor subject to change between releases
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::ops::Bound;
+
+    use fusio::path::Path;
+    use futures_util::StreamExt;
+    use tempfile::TempDir;
+
+    use super::{ShardedDB, Sharding};
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    async fn shards(temp_dir: &TempDir, count: usize) -> Vec<DB<Test, TokioExecutor>> {
+        let mut shards = Vec::new();
+        for shard in 0..count {
+            let path = temp_dir.path().join(shard.to_string());
+            std::fs::create_dir_all(&path).unwrap();
+            let option = DbOption::new(Path::from_filesystem_path(&path).unwrap(), &TestSchema);
+            shards.push(
+                DB::new(option, TokioExecutor::default(), TestSchema)
+                    .await
+                    .unwrap(),
+            );
+        }
+        shards
+    }
+
+    fn test(i: u32) -> Test {
+        Test {
+            vstring: format!("{i:02}"),
+            vu32: i,
+            vbool: None,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn route_and_merge_shards() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = ShardedDB::new(shards(&temp_dir, 3).await, Sharding::Hash);
+
+        db.insert_batch((0..20).map(test)).await.unwrap();
+        db.insert(test(20)).await.unwrap();
+        db.remove("05".to_string()).await.unwrap();
+        db.shards()[1].flush().await.unwrap();
+
+        for i in (0..=20).filter(|i| *i != 5) {
+            let key = format!("{i:02}");
+            let value = db.get(&key, |entry| entry.get().vu32).await.unwrap();
+            assert_eq!(value, Some(i));
+        }
+
+        let lower = "03".to_string();
+        let upper = "08".to_string();
+        let scan = db
+            .scan(
+                (Bound::Included(&lower), Bound::Excluded(&upper)),
+                |entry| entry.value().map(|value| value.vu32.unwrap()),
+            )
+            .await;
+        let values = scan
+            .map(|value| value.unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![3, 4, 6, 7]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn range_shards() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = ShardedDB::new(
+            shards(&temp_dir, 3).await,
+            Sharding::Range(vec!["10".to_string(), "20".to_string()]),
+        );
+        assert_eq!(db.shard_of(&"09".to_string()), 0);
+        assert_eq!(db.shard_of(&"10".to_string()), 1);
+        assert_eq!(db.shard_of(&"25".to_string()), 2);
+
+        db.insert_batch((0..30).map(test)).await.unwrap();
+        let lower = "12".to_string();
+        assert_eq!(
+            db.shards_in((Bound::Included(&lower), Bound::Unbounded)),
+            1..3
+        );
+        let values = db
+            .scan((Bound::Included(&lower), Bound::Unbounded), |entry| {
+                entry.get().vu32.unwrap()
+            })
+            .await
+            .map(|value| value.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(values, (12..30).collect::<Vec<_>>());
+    }
+}
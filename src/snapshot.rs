@@ -1,17 +1,30 @@
-use std::{collections::Bound, sync::Arc};
+use std::{collections::Bound, ops::RangeBounds, pin::pin, sync::Arc};
 
+use futures_util::StreamExt;
 use parquet::arrow::ProjectionMask;
 
 use crate::{
     context::Context,
+    cursor::Cursor,
     executor::{Executor, RwLock},
+    histogram::{equi_depth, memtable_spans, KeyBucket, Span},
+    ondisk::sstable::{size_in_range, SsTable},
     option::Order,
-    record::{Record, Schema as RecordSchema},
+    record::{KeyRef, Record, Schema as RecordSchema},
+    stats::{LevelRangeStats, RangeStats},
     stream::{self, ScanStream},
+    transaction::KeyVersion,
     version::{timestamp::Timestamp, TransactionTs, VersionRef},
-    DbError, DbStorage, Projection, Scan,
+    DbError, DbStorage, FnPreStream, Projection, Scan,
 };
 
+/// Read-only view of a [`DB`](crate::DB) as of the latest commit when it was taken, see
+/// [`DB::snapshot`](crate::DB::snapshot)
+///
+/// All reads of a snapshot see the same records, whatever is written meanwhile, without the
+/// write set and the commit of a [`Transaction`](crate::transaction::Transaction). The snapshot
+/// holds its version of the SSTables and a read lock on the memtables, so memtables are not
+/// flushed until it is dropped and it should not be kept longer than its reads need.
 pub struct Snapshot<'s, R, E>
 where
     R: Record,
@@ -32,14 +45,26 @@ where
     E: Executor,
     E::RwLock<DbStorage<R>>: 's,
 {
+    /// Get the record with `key` as the primary key as of the snapshot
     pub async fn get<'get>(
         &'get self,
         key: &'get <R::Schema as RecordSchema>::Key,
         projection: Projection<'get>,
+    ) -> Result<Option<stream::Entry<'get, R>>, DbError> {
+        self.get_at(key, projection, self.ts).await
+    }
+
+    /// Like [`Snapshot::get`], but as of `ts`, which may be later than the snapshot as long as
+    /// no memtable was flushed since it was taken, which its read lock on the memtables ensures
+    pub(crate) async fn get_at<'get>(
+        &'get self,
+        key: &'get <R::Schema as RecordSchema>::Key,
+        projection: Projection<'get>,
+        ts: Timestamp,
     ) -> Result<Option<stream::Entry<'get, R>>, DbError> {
         Ok(self
             .share
-            .get(&self.ctx, &self.version, key, self.ts, projection)
+            .get(&self.ctx, &self.version, key, ts, projection)
             .await?
             .and_then(|entry| {
                 if entry.value().is_none() {
@@ -50,6 +75,33 @@ where
             }))
     }
 
+    /// Get the records of `keys`, in the order of `keys`, with the SSTable reads of all of them
+    /// batched, see [`DB::get_many`](crate::DB::get_many)
+    pub async fn get_many<'get>(
+        &'get self,
+        keys: &[&'get <R::Schema as RecordSchema>::Key],
+        projection: Projection<'get>,
+    ) -> Result<Vec<Option<stream::Entry<'get, R>>>, DbError> {
+        self.get_many_at(keys, projection, self.ts).await
+    }
+
+    /// Like [`Snapshot::get_many`], but as of `ts`, see [`Snapshot::get_at`]
+    pub(crate) async fn get_many_at<'get>(
+        &'get self,
+        keys: &[&'get <R::Schema as RecordSchema>::Key],
+        projection: Projection<'get>,
+        ts: Timestamp,
+    ) -> Result<Vec<Option<stream::Entry<'get, R>>>, DbError> {
+        Ok(self
+            .share
+            .get_many(&self.ctx, &self.version, keys, ts, projection)
+            .await?
+            .into_iter()
+            .map(|entry| entry.filter(|entry| entry.value().is_some()))
+            .collect())
+    }
+
+    /// Scan the records with primary keys in `range` as of the snapshot
     pub fn scan<'scan, 'range>(
         &'scan self,
         range: (
@@ -62,11 +114,16 @@ where
             range,
             self.ts,
             &self.version,
-            Box::new(move |_: Option<ProjectionMask>, _: Option<Order>| None),
+            Box::new(|_, _, _| None),
             self.ctx.clone(),
         )
     }
 
+    /// Cursor over the entries of the snapshot that can seek and step both ways
+    pub fn cursor(&self) -> Cursor<'_, R> {
+        Cursor::new(&self.share, &self.version, self.ts, self.ctx.clone())
+    }
+
     pub(crate) fn new(
         share: <E::RwLock<DbStorage<R>> as RwLock<DbStorage<R>>>::ReadGuard<'s>,
         version: VersionRef<R>,
@@ -80,10 +137,16 @@ where
         }
     }
 
-    pub(crate) fn ts(&self) -> Timestamp {
+    /// Timestamp of the commits the snapshot reads
+    pub fn ts(&self) -> Timestamp {
         self.ts
     }
 
+    /// Timestamp of the latest commit to the DB
+    pub(crate) fn load_ts(&self) -> Timestamp {
+        self.version.load_ts()
+    }
+
     pub(crate) fn increase_ts(&self) -> Timestamp {
         self.version.increase_ts()
     }
@@ -92,22 +155,255 @@ where
         &self.share
     }
 
+    /// Every version of `key` committed at or before `ts`, newest first, see
+    /// [`Transaction::get_versions`](crate::transaction::Transaction::get_versions)
+    pub(crate) async fn versions_at<'get>(
+        &'get self,
+        key: &'get <R::Schema as RecordSchema>::Key,
+        ts: Timestamp,
+    ) -> Result<Vec<KeyVersion<'get, R>>, DbError> {
+        let range = (Bound::Included(key), Bound::Included(key));
+        let mut streams: Vec<ScanStream<'get, R>> =
+            vec![self.share.mutable.scan(range, ts, None).into()];
+        for (_, immutable) in self.share.immutables.iter().rev() {
+            streams.push(
+                immutable
+                    .scan(range, ts, ProjectionMask::all(), None)
+                    .into(),
+            );
+        }
+        self.version
+            .streams(
+                &self.ctx,
+                &mut streams,
+                range,
+                ts,
+                None,
+                ProjectionMask::all(),
+                None,
+                self.share.record_schema.primary_key_indices(),
+                None,
+                None,
+                &[],
+                self.ctx.manager.readers(),
+            )
+            .await?;
+
+        // every source holds all of its versions of the key, unlike a merged scan
+        let mut versions = Vec::new();
+        for stream in streams {
+            let mut stream = pin!(stream);
+            while let Some(entry) = stream.next().await {
+                let entry = entry?;
+                versions.push(KeyVersion::new(entry.key().ts, entry));
+            }
+        }
+        versions.sort_by(|a, b| b.ts.cmp(&a.ts));
+
+        Ok(versions)
+    }
+
+    /// See [`DB::key_histogram`](crate::DB::key_histogram)
+    pub(crate) async fn key_histogram(
+        &self,
+        buckets: usize,
+    ) -> Result<Vec<KeyBucket<<R::Schema as RecordSchema>::Key>>, DbError> {
+        let mut spans = Vec::new();
+        let (mut table_keys, mut table_bytes) = (0, 0);
+        for (level, scopes) in self.version.level_slice.iter().enumerate() {
+            for scope in scopes {
+                let manager = &self.ctx.manager;
+                let (fs, path) = manager.table(self.version.option(), scope.gen, level);
+                let reader = manager.readers().open(fs, &path, scope.gen).await?;
+                let metadata =
+                    SsTable::<R>::from_reader(self.ctx.cache().clone(), scope.gen, reader)
+                        .await
+                        .metadata()
+                        .await?;
+                let keys = metadata.file_metadata().num_rows() as u64;
+
+                table_keys += keys;
+                table_bytes += scope.file_size;
+                spans.push(Span {
+                    lower: scope.min.clone(),
+                    upper: scope.max.clone(),
+                    keys,
+                    bytes: scope.file_size,
+                });
+            }
+        }
+
+        let entry_bytes = table_bytes.checked_div(table_keys).unwrap_or(0);
+        let mem_storage = self.mem_storage();
+        spans.extend(memtable_spans(
+            mem_storage.mutable.len(),
+            |step| mem_storage.mutable.sample_keys(step),
+            entry_bytes,
+        ));
+        for (_, immutable) in mem_storage.immutables.iter() {
+            spans.extend(memtable_spans(
+                immutable.len(),
+                |step| immutable.sample_keys(step),
+                entry_bytes,
+            ));
+        }
+
+        Ok(equi_depth(spans, buckets))
+    }
+
+    /// See [`DB::approximate_stats`](crate::DB::approximate_stats)
+    pub(crate) async fn approximate_stats(
+        &self,
+        range: (
+            Bound<&<R::Schema as RecordSchema>::Key>,
+            Bound<&<R::Schema as RecordSchema>::Key>,
+        ),
+    ) -> Result<RangeStats, DbError> {
+        let schema = &self.share.record_schema;
+        let mut levels = Vec::with_capacity(self.version.level_slice.len());
+        for (level, scopes) in self.version.level_slice.iter().enumerate() {
+            let mut stats = LevelRangeStats::default();
+            for scope in scopes.iter().filter(|scope| scope.meets_range(range)) {
+                stats.tables += 1;
+                // tables written before rows were counted have no rows in their scope
+                if range.contains(&scope.min) && range.contains(&scope.max) && scope.rows > 0 {
+                    stats.rows += scope.rows;
+                    stats.bytes += scope.file_size;
+                    continue;
+                }
+
+                let manager = &self.ctx.manager;
+                let (fs, path) = manager.table(self.version.option(), scope.gen, level);
+                let reader = manager.readers().open(fs, &path, scope.gen).await?;
+                let metadata =
+                    SsTable::<R>::from_reader(self.ctx.cache().clone(), scope.gen, reader)
+                        .await
+                        .metadata()
+                        .await?;
+                let (rows, bytes) = size_in_range(
+                    &metadata,
+                    schema.arrow_schema(),
+                    range,
+                    schema.primary_key_indices(),
+                );
+                stats.rows += rows;
+                stats.bytes += bytes;
+            }
+            levels.push(stats);
+        }
+
+        Ok(RangeStats { levels })
+    }
+
+    /// See [`DB::first_key_in`](crate::DB::first_key_in) and
+    /// [`DB::last_key_in`](crate::DB::last_key_in)
+    ///
+    /// The memtables know their first key in `range`, and so does every SSTable whose first key
+    /// lies in it. The earliest of those keys is checked with a point lookup, and the search moves
+    /// past it if it is removed. Only when the range starts within an SSTable is the range up to
+    /// the earliest known key scanned.
+    pub(crate) async fn edge_key(
+        &self,
+        range: (
+            Bound<&<R::Schema as RecordSchema>::Key>,
+            Bound<&<R::Schema as RecordSchema>::Key>,
+        ),
+        order: Order,
+    ) -> Result<Option<<R::Schema as RecordSchema>::Key>, DbError> {
+        let before = |key: &<R::Schema as RecordSchema>::Key,
+                      other: &<R::Schema as RecordSchema>::Key| match order {
+            Order::Asc => key < other,
+            Order::Desc => key > other,
+        };
+        let earliest =
+            |key: Option<<R::Schema as RecordSchema>::Key>,
+             other: Option<<R::Schema as RecordSchema>::Key>| match (key, other) {
+                (Some(key), Some(other)) if before(&other, &key) => Some(other),
+                (key, other) => key.or(other),
+            };
+        // the search moves from `start` towards `end`
+        let (mut start, end) = match order {
+            Order::Asc => (range.0.cloned(), range.1),
+            Order::Desc => (range.1.cloned(), range.0),
+        };
+
+        loop {
+            let range = match order {
+                Order::Asc => (start.as_ref(), end),
+                Order::Desc => (end, start.as_ref()),
+            };
+            let mem_storage = self.mem_storage();
+            let mut known = mem_storage.mutable.edge_key(range, Some(order));
+            for (_, immutable) in mem_storage.immutables.iter() {
+                known = earliest(known, immutable.edge_key(range, Some(order)));
+            }
+            let mut within_table = false;
+            for scope in self.version.level_slice.iter().flatten() {
+                if !scope.meets_range(range) {
+                    continue;
+                }
+                let edge = match order {
+                    Order::Asc => &scope.min,
+                    Order::Desc => &scope.max,
+                };
+                let edge_in_range = match start.as_ref() {
+                    Bound::Included(key) => !before(edge, key),
+                    Bound::Excluded(key) => before(key, edge),
+                    Bound::Unbounded => true,
+                };
+                if edge_in_range {
+                    known = earliest(known, Some(edge.clone()));
+                } else {
+                    within_table = true;
+                }
+            }
+
+            if within_table {
+                let stop = known.as_ref().map_or(end, Bound::Included);
+                let range = match order {
+                    Order::Asc => (start.as_ref(), stop),
+                    Order::Desc => (stop, start.as_ref()),
+                };
+                let mut scan = self.scan(range).projection(&[]);
+                if order == Order::Desc {
+                    scan = scan.reverse();
+                }
+                let mut stream = pin!(scan.take().await?);
+                while let Some(entry) = stream.next().await {
+                    let entry = entry?;
+                    if entry.value().is_some() {
+                        return Ok(Some(entry.key().value.to_key()));
+                    }
+                }
+            } else if let Some(key) = &known {
+                if self
+                    .get(key, Projection::Parts(Vec::new()))
+                    .await?
+                    .is_some()
+                {
+                    return Ok(known);
+                }
+            }
+            match known {
+                Some(key) => start = Bound::Excluded(key),
+                None => return Ok(None),
+            }
+        }
+    }
+
     pub(crate) fn _scan<'scan, 'range>(
         &'scan self,
         range: (
             Bound<&'range <R::Schema as RecordSchema>::Key>,
             Bound<&'range <R::Schema as RecordSchema>::Key>,
         ),
-        fn_pre_stream: Box<
-            dyn FnOnce(Option<ProjectionMask>, Option<Order>) -> Option<ScanStream<'scan, R>>
-                + Send
-                + 'scan,
-        >,
+        ts: Timestamp,
+        fn_pre_stream: FnPreStream<'scan, 'range, R>,
     ) -> Scan<'scan, 'range, R> {
         Scan::new(
             &self.share,
             range,
-            self.ts,
+            ts,
             &self.version,
             fn_pre_stream,
             self.ctx.clone(),
@@ -129,8 +425,8 @@ mod tests {
         executor::tokio::TokioExecutor,
         fs::manager::StoreManager,
         inmem::immutable::tests::TestSchema,
-        tests::{build_db, build_schema},
-        DbOption,
+        tests::{build_db, build_schema, Test},
+        DbOption, Projection, DB,
     };
 
     #[tokio::test(flavor = "multi_thread")]
@@ -222,4 +518,87 @@ mod tests {
         let entry_14 = stream.next().await.unwrap().unwrap();
         assert_eq!(entry_14.key().value, "funk");
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn repeatable_reads() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        let test = |key: &str, vu32| Test {
+            vstring: key.to_string(),
+            vu32,
+            vbool: None,
+        };
+        db.insert(test("a", 1)).await.unwrap();
+        db.insert(test("b", 2)).await.unwrap();
+
+        let snapshot = db.snapshot().await;
+        db.insert(test("a", 10)).await.unwrap();
+        db.remove("b".to_string()).await.unwrap();
+        db.insert(test("c", 3)).await.unwrap();
+
+        for (key, vu32) in [("a", Some(1)), ("b", Some(2)), ("c", None)] {
+            let key = key.to_string();
+            let entry = snapshot.get(&key, Projection::All).await.unwrap();
+            assert_eq!(entry.and_then(|entry| entry.value().unwrap().vu32), vu32);
+        }
+        let mut scan = snapshot
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .take()
+            .await
+            .unwrap();
+        let mut keys = Vec::new();
+        while let Some(entry) = scan.next().await.transpose().unwrap() {
+            keys.push(entry.key().value.to_string());
+        }
+        assert_eq!(keys, ["a", "b"]);
+        assert!(snapshot.ts() < db.ctx.load_ts());
+    }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn first_and_last_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        let key = |i: u32| format!("{i:02}");
+        assert_eq!(db.first_key().await.unwrap(), None);
+
+        db.insert_batch((0..10).map(|i| Test {
+            vstring: key(i),
+            vu32: i,
+            vbool: None,
+        }))
+        .await
+        .unwrap();
+        db.flush().await.unwrap();
+        for i in [0, 4, 9] {
+            db.remove(key(i)).await.unwrap();
+        }
+
+        assert_eq!(db.first_key().await.unwrap(), Some(key(1)));
+        assert_eq!(db.last_key().await.unwrap(), Some(key(8)));
+        // the range starts within the table
+        let (lower, upper) = (key(3), key(1));
+        assert_eq!(
+            db.first_key_in((Bound::Excluded(&lower), Bound::Unbounded))
+                .await
+                .unwrap(),
+            Some(key(5))
+        );
+        assert_eq!(
+            db.last_key_in((Bound::Unbounded, Bound::Excluded(&upper)))
+                .await
+                .unwrap(),
+            None
+        );
+    }
 }
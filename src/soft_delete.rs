@@ -0,0 +1,61 @@
+//! Horizon of the removals that keep the value they replaced, see
+//! [`DbOption::soft_delete`](crate::DbOption::soft_delete)
+
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+use crate::version::timestamp::Timestamp;
+
+/// Marks of the latest timestamp at a wall clock time, to tell which removals are older than the
+/// purge delay of [`DbOption::soft_delete`](crate::DbOption::soft_delete)
+///
+/// Timestamps count commits, not time, so every flush and compaction that asks for the horizon
+/// marks the latest timestamp at the current time. A removal is purged once a mark of a timestamp
+/// at or after it is older than the delay. Marks are only kept in memory, so the removals of
+/// before a reopen are kept until the delay passed since the first flush after it.
+pub(crate) struct PurgeHorizon {
+    purge_after: Option<Duration>,
+    // Milliseconds since the UNIX epoch and the latest timestamp at that time, oldest first
+    marks: Mutex<VecDeque<(u64, Timestamp)>>,
+}
+
+impl PurgeHorizon {
+    /// Create a horizon that purges removals `purge_after` they were committed
+    pub(crate) fn new(purge_after: Duration) -> Self {
+        Self {
+            purge_after: Some(purge_after),
+            marks: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Oldest timestamp of a removal that keeps the value it replaced at `now_ms`, when `ts` is
+    /// the latest timestamp, `None` if removals do not keep values
+    pub(crate) fn keep_removed_since(&self, now_ms: u64, ts: Timestamp) -> Option<Timestamp> {
+        let purge_after = self.purge_after?;
+        let mut marks = self
+            .marks
+            .lock()
+            .expect("purge horizon lock should not fail");
+        marks.push_back((now_ms, ts));
+        let Some(horizon) = now_ms.checked_sub(purge_after.as_millis() as u64) else {
+            return Some(Timestamp::from(0));
+        };
+        // only the newest mark at or before the horizon is needed of the ones before it
+        while marks.get(1).is_some_and(|(at, _)| *at <= horizon) {
+            marks.pop_front();
+        }
+
+        Some(match marks.front() {
+            Some((at, ts)) if *at <= horizon => Timestamp::from(u32::from(*ts).saturating_add(1)),
+            _ => Timestamp::from(0),
+        })
+    }
+}
+
+impl Default for PurgeHorizon {
+    fn default() -> Self {
+        Self {
+            purge_after: None,
+            marks: Mutex::new(VecDeque::new()),
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! Reads of single SSTables without a [`DB`](crate::DB)
+//!
+//! An SSTable is a Parquet file whose rows are sorted by key, with the `_null` column marking
+//! removals and the `_ts` column holding the timestamp of each write. [`SstReader`] reads such a
+//! file on its own, e.g. from a backup or a data lake, with the schema of the DB that wrote it:
+//!
+//! ```ignore
+//! let reader = SstReader::open(fs, path, schema).await?;
+//! if let Some(entry) = reader.get(&key).await? {
+//!     println!("{:?} written at {:?}", entry.get(), entry.ts());
+//! }
+//! let mut rows = pin!(reader.scan((Bound::Unbounded, Bound::Unbounded)).await?);
+//! while let Some(entry) = rows.next().await {
+//!     // ...
+//! }
+//! ```
+//!
+//! A table only holds part of the data of a DB: newer writes of its keys may be in other tables
+//! or memtables.
+
+use std::{ops::Bound, sync::Arc};
+
+use fusio::{path::Path, DynFs};
+use futures_core::Stream;
+use futures_util::StreamExt;
+use parquet::{arrow::ProjectionMask, file::metadata::ParquetMetaData};
+use parquet_lru::NoCache;
+
+pub use crate::stream::record_batch::RecordBatchEntry;
+use crate::{
+    fs::{generate_file_id, FileType},
+    ondisk::sstable::SsTable,
+    record::{Record, Schema},
+    version::timestamp::Timestamp,
+    DbError,
+};
+
+/// Reader of a single SSTable, see the [module docs](self)
+pub struct SstReader<R>
+where
+    R: Record,
+{
+    fs: Arc<dyn DynFs>,
+    path: Path,
+    schema: Arc<R::Schema>,
+    ts: Timestamp,
+}
+
+impl<R> SstReader<R>
+where
+    R: Record,
+{
+    /// Reader of the SSTable at `path` on `fs`, written with `schema`
+    ///
+    /// Fails if the file is not a Parquet file or its columns are not the columns of `schema`.
+    pub async fn open(fs: Arc<dyn DynFs>, path: Path, schema: R::Schema) -> Result<Self, DbError> {
+        let reader = SstReader {
+            fs,
+            path,
+            schema: Arc::new(schema),
+            ts: Timestamp::from(u32::MAX),
+        };
+        let metadata = reader.metadata().await?;
+        let columns = metadata.file_metadata().schema_descr().columns();
+        let fields = reader.schema.arrow_schema().fields();
+        if columns.len() != fields.len()
+            || columns
+                .iter()
+                .zip(fields.iter())
+                .any(|(column, field)| column.name() != field.name())
+        {
+            return Err(DbError::Parquet(parquet::errors::ParquetError::General(
+                format!(
+                    "columns of {} are not the columns of the schema",
+                    reader.path
+                ),
+            )));
+        }
+
+        Ok(reader)
+    }
+
+    /// Read the table as of the timestamp `ts`, ignoring later writes, which reads every write by
+    /// default
+    pub fn at(self, ts: u32) -> Self {
+        SstReader {
+            ts: Timestamp::from(ts),
+            ..self
+        }
+    }
+
+    /// Footer of the table, with its row groups and their statistics
+    pub async fn metadata(&self) -> Result<Arc<ParquetMetaData>, DbError> {
+        Ok(self.table().await?.metadata().await?)
+    }
+
+    /// The latest write of `key`, which is a removal if [`RecordBatchEntry::get`] returns `None`
+    pub async fn get(
+        &self,
+        key: &<R::Schema as Schema>::Key,
+    ) -> Result<Option<RecordBatchEntry<R>>, DbError> {
+        let mut rows = std::pin::pin!(
+            self.scan((Bound::Included(key), Bound::Included(key)))
+                .await?
+        );
+        rows.next().await.transpose()
+    }
+
+    /// Rows of the keys in `range`, in key order and the latest write of a key first
+    pub async fn scan<'scan>(
+        &self,
+        range: (
+            Bound<&'scan <R::Schema as Schema>::Key>,
+            Bound<&'scan <R::Schema as Schema>::Key>,
+        ),
+    ) -> Result<impl Stream<Item = Result<RecordBatchEntry<R>, DbError>> + 'scan, DbError> {
+        let rows = self
+            .table()
+            .await?
+            .scan(
+                range,
+                self.ts,
+                None,
+                ProjectionMask::all(),
+                None,
+                self.schema.primary_key_indices(),
+            )
+            .await?;
+
+        Ok(rows.map(|row| row.map_err(DbError::from)))
+    }
+
+    // Every read opens the file, as a table is consumed by its read
+    async fn table(&self) -> Result<SsTable<R>, DbError> {
+        let file = self
+            .fs
+            .open_options(&self.path, FileType::Parquet.open_options(true))
+            .await?;
+
+        Ok(SsTable::open(Arc::new(NoCache::default()), generate_file_id(), file).await?)
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{ops::Bound, pin::pin, sync::Arc};
+
+    use fusio::{disk::LocalFs, path::Path, DynFs};
+    use futures_util::StreamExt;
+    use tempfile::TempDir;
+
+    use super::SstReader;
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema,
+        record::test::test_items, tests::Test, DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn read_table_without_db() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let db: DB<Test, TokioExecutor> = DB::new(
+                DbOption::new(
+                    Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                    &TestSchema,
+                ),
+                TokioExecutor::default(),
+                TestSchema,
+            )
+            .await
+            .unwrap();
+            for item in test_items(0u32..10) {
+                db.insert(item).await.unwrap();
+            }
+            db.remove("5".to_string()).await.unwrap();
+            db.flush().await.unwrap();
+        }
+        let table = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().is_some_and(|ext| ext == "parquet"))
+            .unwrap();
+
+        let fs: Arc<dyn DynFs> = Arc::new(LocalFs {});
+        let path = Path::from_filesystem_path(table).unwrap();
+        let reader = SstReader::<Test>::open(fs.clone(), path.clone(), TestSchema)
+            .await
+            .unwrap();
+        assert_eq!(
+            reader.metadata().await.unwrap().file_metadata().num_rows(),
+            10
+        );
+
+        let entry = reader.get(&"3".to_string()).await.unwrap().unwrap();
+        assert_eq!(entry.get().unwrap().vu32, Some(3));
+        // removals are kept as rows without a value
+        let entry = reader.get(&"5".to_string()).await.unwrap().unwrap();
+        assert!(entry.get().is_none());
+
+        let lower = "2".to_string();
+        let upper = "4".to_string();
+        let rows = pin!(reader
+            .scan((Bound::Included(&lower), Bound::Excluded(&upper)))
+            .await
+            .unwrap());
+        let keys = rows
+            .map(|row| row.unwrap().key().to_string())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(keys, vec!["2", "3"]);
+
+        // writes after the timestamp are not read
+        let entry = reader.get(&"3".to_string()).await.unwrap().unwrap();
+        let before = SstReader::<Test>::open(fs, path, TestSchema)
+            .await
+            .unwrap()
+            .at(u32::from(entry.ts()) - 1);
+        assert!(before.get(&"3".to_string()).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,170 @@
+//! Statistics of a [`DB`](crate::DB), as reported by [`DB::stats`](crate::DB::stats)
+
+/// Approximate bytes of memory held by the components of a [`DB`](crate::DB)
+///
+/// Memtables count the encoded size of their keys and records plus the size of their entries,
+/// immutable memtables the memory of their Arrow arrays plus their key index. The pages cached
+/// by the [`ParquetLru`](crate::ParquetLru) of the DB are accounted by the cache itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Keys and records of the mutable memtable
+    pub mutable: usize,
+    /// Arrow arrays and key indexes of the immutable memtables that are not flushed yet
+    pub immutables: usize,
+    /// Keys remembered by the negative lookup cache, see
+    /// [`DbOption::negative_cache_capacity`](crate::DbOption::negative_cache_capacity)
+    pub negative_cache: usize,
+    /// Decoded SSTable record batches held by scans, see
+    /// [`DbOption::scan_memory_limit`](crate::DbOption::scan_memory_limit)
+    pub scans: usize,
+    /// Columns that running compactions buffer for the SSTables they write
+    pub compactions: usize,
+}
+
+impl MemoryUsage {
+    /// Bytes of the mutable and immutable memtables, i.e. of the writes that are not flushed yet
+    pub fn memtables(&self) -> usize {
+        self.mutable + self.immutables
+    }
+
+    /// Bytes of all components
+    pub fn total(&self) -> usize {
+        self.memtables() + self.negative_cache + self.scans + self.compactions
+    }
+}
+
+/// Statistics of a [`DB`](crate::DB) at one point in time
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DbStats {
+    /// Memory held by the components of the DB
+    pub memory: MemoryUsage,
+    /// Number of immutable memtables waiting to be flushed
+    pub immutable_memtables: usize,
+    /// Number of SSTables of each level
+    pub tables_per_level: Vec<usize>,
+}
+
+/// Estimated size of the SSTables of a level in a key range, see [`RangeStats`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LevelRangeStats {
+    /// Number of SSTables of the level whose key range meets the range
+    pub tables: usize,
+    /// Estimated bytes on storage of their rows in the range
+    pub bytes: u64,
+    /// Estimated number of their rows in the range, counting every version and removal of a key
+    pub rows: u64,
+}
+
+/// Estimated size of the SSTables in a key range, as reported by
+/// [`DB::approximate_stats`](crate::DB::approximate_stats)
+///
+/// A table within the range counts with the size and rows of its [`Scope`](crate::scope::Scope),
+/// and a table that only overlaps the range with the row groups of its footer whose statistics of
+/// the first primary key column meet the range. Writes that are not flushed yet are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RangeStats {
+    /// Estimates of each level
+    pub levels: Vec<LevelRangeStats>,
+}
+
+impl RangeStats {
+    /// Estimated bytes on storage of all levels
+    pub fn bytes(&self) -> u64 {
+        self.levels.iter().map(|level| level.bytes).sum()
+    }
+
+    /// Estimated number of rows of all levels
+    pub fn rows(&self) -> u64 {
+        self.levels.iter().map(|level| level.rows).sum()
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::ops::Bound;
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn approximate_range_stats() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+
+        db.insert_batch((0..100).map(|i| Test {
+            vstring: format!("{i:03}"),
+            vu32: i,
+            vbool: None,
+        }))
+        .await
+        .unwrap();
+        let all = (Bound::Unbounded, Bound::Unbounded);
+        assert_eq!(db.approximate_stats(all).await.unwrap().rows(), 0);
+
+        db.flush().await.unwrap();
+        let stats = db.approximate_stats(all).await.unwrap();
+        assert_eq!(stats.levels[0].tables, 1);
+        assert_eq!(stats.levels[0].rows, 100);
+        assert!(stats.levels[0].bytes > 0);
+        assert_eq!(stats.rows(), 100);
+
+        // the only row group of the table meets the range
+        let (lower, upper) = ("050".to_string(), "060".to_string());
+        let stats = db
+            .approximate_stats((Bound::Included(&lower), Bound::Excluded(&upper)))
+            .await
+            .unwrap();
+        assert_eq!(stats.levels[0].tables, 1);
+        assert_eq!(stats.rows(), 100);
+
+        let after = "100".to_string();
+        let stats = db
+            .approximate_stats((Bound::Included(&after), Bound::Unbounded))
+            .await
+            .unwrap();
+        assert_eq!(stats.levels[0].tables, 0);
+        assert_eq!(stats.bytes(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn memory_moves_from_memtables_to_tables() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+
+        db.insert_batch((0..100).map(|i| Test {
+            vstring: format!("{i:03}"),
+            vu32: i,
+            vbool: None,
+        }))
+        .await
+        .unwrap();
+        let stats = db.stats().await;
+        assert!(stats.memory.mutable > 100 * 7);
+        assert_eq!(stats.memory.immutables, 0);
+        assert_eq!(stats.immutable_memtables, 0);
+        assert_eq!(stats.tables_per_level[0], 0);
+
+        db.flush().await.unwrap();
+        let stats = db.stats().await;
+        assert_eq!(stats.memory.memtables(), 0);
+        assert_eq!(stats.memory.compactions, 0);
+        assert_eq!(stats.tables_per_level[0], 1);
+    }
+}
@@ -6,23 +6,20 @@ use std::{
     task::{Context, Poll},
 };
 
-use fusio::{
-    dynamic::{DynFile, MaybeSendFuture},
-    path::Path,
-    DynFs, Error,
-};
+use arrow::datatypes::Schema as ArrowSchema;
+use fusio::dynamic::MaybeSendFuture;
 use futures_core::Stream;
 use parquet::{arrow::ProjectionMask, errors::ParquetError};
 use parquet_lru::DynLruCache;
 use ulid::Ulid;
 
 use crate::{
-    fs::{FileId, FileType},
+    fs::{manager::StoreManager, pool::ReaderPool, FileId},
     ondisk::{scan::SsTableScan, sstable::SsTable},
-    option::Order,
+    option::{Order, ReadHint},
     record::{Record, Schema},
     scope::Scope,
-    stream::record_batch::RecordBatchEntry,
+    stream::{memory::MemoryBudget, record_batch::RecordBatchEntry},
     version::{timestamp::Timestamp, Version},
     DbOption,
 };
@@ -33,14 +30,19 @@ where
 {
     Init(FileId),
     Ready(Box<SsTableScan<'level, R>>),
-    OpenFile(
-        Ulid,
-        Pin<Box<dyn MaybeSendFuture<Output = Result<Box<dyn DynFile>, Error>> + 'level>>,
-    ),
-    OpenSst(Pin<Box<dyn MaybeSendFuture<Output = Result<SsTable<R>, Error>> + 'level>>),
-    LoadStream(
-        Pin<Box<dyn Future<Output = Result<SsTableScan<'level, R>, ParquetError>> + Send + 'level>>,
-    ),
+    LoadNext(NextScan<'level, R>),
+}
+
+type NextScan<'level, R> =
+    Pin<Box<dyn MaybeSendFuture<Output = Result<SsTableScan<'level, R>, ParquetError>> + 'level>>;
+
+// The next SSTable of the level, opened while the current one is still read
+enum Prefetch<'level, R>
+where
+    R: Record,
+{
+    Loading(NextScan<'level, R>),
+    Loaded(Result<SsTableScan<'level, R>, ParquetError>),
 }
 
 pub(crate) struct LevelStream<'level, R>
@@ -56,11 +58,16 @@ where
     limit: Option<usize>,
     projection_mask: ProjectionMask,
     status: FutureStatus<'level, R>,
-    fs: Arc<dyn DynFs>,
-    path: Option<Path>,
+    manager: Arc<StoreManager>,
+    readers: ReaderPool,
     parquet_lru: Arc<dyn DynLruCache<Ulid> + Send + Sync>,
     order: Option<Order>,
     pk_indices: &'level [usize],
+    read_hint: Option<ReadHint>,
+    memory_budget: Option<MemoryBudget>,
+    prefix: Option<Arc<[u8]>>,
+    schema: Option<Arc<ArrowSchema>>,
+    prefetch: Option<Prefetch<'level, R>>,
 }
 
 impl<'level, R> LevelStream<'level, R>
@@ -81,7 +88,7 @@ where
         limit: Option<usize>,
         projection_mask: ProjectionMask,
         // TODO: Refactor some top level components to a context structure.
-        fs: Arc<dyn DynFs>,
+        manager: Arc<StoreManager>,
         parquet_lru: Arc<dyn DynLruCache<Ulid> + Send + Sync>,
         order: Option<Order>,
         pk_indices: &'level [usize],
@@ -110,13 +117,91 @@ where
             limit,
             projection_mask,
             status,
-            fs,
-            path: None,
+            manager,
+            readers: ReaderPool::default(),
             parquet_lru,
             order,
             pk_indices,
+            read_hint: None,
+            memory_budget: None,
+            prefix: None,
+            schema: version.arrow_schema(),
+            prefetch: None,
         })
     }
+
+    /// Tune the reads of the level for the access pattern of `read_hint`. With
+    /// [`ReadHint::Sequential`] the next SSTable is opened while the current one is read.
+    pub(crate) fn read_hint(self, read_hint: Option<ReadHint>) -> Self {
+        Self { read_hint, ..self }
+    }
+
+    /// Take the readers of the level's SSTables from `readers`
+    pub(crate) fn readers(self, readers: ReaderPool) -> Self {
+        Self { readers, ..self }
+    }
+
+    /// Charge the record batches decoded from the level's SSTables to `memory_budget`
+    pub(crate) fn memory_budget(self, memory_budget: Option<MemoryBudget>) -> Self {
+        Self {
+            memory_budget,
+            ..self
+        }
+    }
+
+    /// Skip the rows of the level's SSTables whose prefix bloom filter rules out `prefix`, which
+    /// every key of the range starts with
+    pub(crate) fn prefix(self, prefix: Option<Arc<[u8]>>) -> Self {
+        Self { prefix, ..self }
+    }
+
+    // Starts opening the next SSTable if the scan is sequential
+    fn prefetch_next(&mut self) {
+        if self.read_hint != Some(ReadHint::Sequential) || self.prefetch.is_some() {
+            return;
+        }
+        if let Some(gen) = self.gens.pop_front() {
+            self.prefetch = Some(Prefetch::Loading(self.open_scan(gen)));
+        }
+    }
+
+    fn open_scan(&self, gen: FileId) -> NextScan<'level, R> {
+        let (manager, readers) = (self.manager.clone(), self.readers.clone());
+        let option = self.option.clone();
+        let level = self.level;
+        let parquet_lru = self.parquet_lru.clone();
+        let range = (self.lower, self.upper);
+        let (ts, limit, order, read_hint) = (self.ts, self.limit, self.order, self.read_hint);
+        let projection_mask = self.projection_mask.clone();
+        let memory_budget = self.memory_budget.clone();
+        let prefix = self.prefix.clone();
+        let schema = self.schema.clone();
+        let pk_indices = self.pk_indices;
+
+        Box::pin(async move {
+            let (fs, path) = manager.table(&option, gen, level);
+            let reader = readers
+                .open(fs, &path, gen)
+                .await
+                .map_err(|err| ParquetError::External(Box::new(err)))?;
+            SsTable::from_reader(parquet_lru, gen, reader)
+                .await
+                .read_hint(read_hint)
+                .memory_budget(memory_budget)
+                .prefix(prefix)
+                .schema(schema)
+                .scan(range, ts, limit, projection_mask, order, pk_indices)
+                .await
+        })
+    }
+
+    fn poll_prefetch(&mut self, cx: &mut Context<'_>) {
+        if let Some(Prefetch::Loading(scan)) = &mut self.prefetch {
+            if let Poll::Ready(result) = scan.as_mut().poll(cx) {
+                self.prefetch = Some(Prefetch::Loaded(result));
+            }
+        }
+    }
 }
 
 impl<R> Stream for LevelStream<'_, R>
@@ -127,56 +212,33 @@ where
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         loop {
+            self.poll_prefetch(cx);
+
             return match &mut self.status {
                 FutureStatus::Init(gen) => {
                     let gen = *gen;
-                    self.path = Some(self.option.table_path(gen, self.level));
-
-                    let reader = self.fs.open_options(
-                        self.path.as_ref().unwrap(),
-                        FileType::Parquet.open_options(true),
-                    );
-                    #[allow(clippy::missing_transmute_annotations)]
-                    let reader = unsafe {
-                        std::mem::transmute::<
-                            _,
-                            Pin<
-                                Box<
-                                    dyn MaybeSendFuture<Output = Result<Box<dyn DynFile>, Error>>
-                                        + 'static,
-                                >,
-                            >,
-                        >(reader)
-                    };
-                    self.status = FutureStatus::OpenFile(gen, reader);
+                    self.status = FutureStatus::LoadNext(self.open_scan(gen));
                     continue;
                 }
                 FutureStatus::Ready(stream) => match Pin::new(stream.as_mut()).poll_next(cx) {
-                    Poll::Ready(None) => match self.gens.pop_front() {
-                        None => Poll::Ready(None),
-                        Some(gen) => {
-                            self.path = Some(self.option.table_path(gen, self.level));
-
-                            let reader = self.fs.open_options(
-                                self.path.as_ref().unwrap(),
-                                FileType::Parquet.open_options(true),
-                            );
-                            #[allow(clippy::missing_transmute_annotations)]
-                            let reader = unsafe {
-                                std::mem::transmute::<
-                                    _,
-                                    Pin<
-                                        Box<
-                                            dyn MaybeSendFuture<
-                                                    Output = Result<Box<dyn DynFile>, Error>,
-                                                > + 'static,
-                                        >,
-                                    >,
-                                >(reader)
-                            };
-                            self.status = FutureStatus::OpenFile(gen, reader);
+                    Poll::Ready(None) => match self.prefetch.take() {
+                        Some(Prefetch::Loaded(Ok(scan))) => {
+                            self.status = FutureStatus::Ready(Box::new(scan));
+                            self.prefetch_next();
+                            continue;
+                        }
+                        Some(Prefetch::Loaded(Err(err))) => Poll::Ready(Some(Err(err))),
+                        Some(Prefetch::Loading(scan)) => {
+                            self.status = FutureStatus::LoadNext(scan);
                             continue;
                         }
+                        None => match self.gens.pop_front() {
+                            None => Poll::Ready(None),
+                            Some(gen) => {
+                                self.status = FutureStatus::LoadNext(self.open_scan(gen));
+                                continue;
+                            }
+                        },
                     },
                     Poll::Ready(Some(result)) => {
                         if let Some(limit) = &mut self.limit {
@@ -186,41 +248,10 @@ where
                     }
                     Poll::Pending => Poll::Pending,
                 },
-                FutureStatus::OpenFile(id, file_future) => match Pin::new(file_future).poll(cx) {
-                    Poll::Ready(Ok(file)) => {
-                        let id = *id;
-                        self.status = FutureStatus::OpenSst(Box::pin(SsTable::open(
-                            self.parquet_lru.clone(),
-                            id,
-                            file,
-                        )));
-                        continue;
-                    }
-                    Poll::Ready(Err(err)) => {
-                        Poll::Ready(Some(Err(ParquetError::External(Box::new(err)))))
-                    }
-                    Poll::Pending => Poll::Pending,
-                },
-                FutureStatus::OpenSst(sst_future) => match Pin::new(sst_future).poll(cx) {
-                    Poll::Ready(Ok(sst)) => {
-                        self.status = FutureStatus::LoadStream(Box::pin(sst.scan(
-                            (self.lower, self.upper),
-                            self.ts,
-                            self.limit,
-                            self.projection_mask.clone(),
-                            self.order,
-                            self.pk_indices,
-                        )));
-                        continue;
-                    }
-                    Poll::Ready(Err(err)) => {
-                        Poll::Ready(Some(Err(ParquetError::External(Box::new(err)))))
-                    }
-                    Poll::Pending => Poll::Pending,
-                },
-                FutureStatus::LoadStream(stream_future) => match Pin::new(stream_future).poll(cx) {
+                FutureStatus::LoadNext(scan_future) => match scan_future.as_mut().poll(cx) {
                     Poll::Ready(Ok(scan)) => {
                         self.status = FutureStatus::Ready(Box::new(scan));
+                        self.prefetch_next();
                         continue;
                     }
                     Poll::Ready(Err(err)) => Poll::Ready(Some(Err(err))),
@@ -245,13 +276,13 @@ mod tests {
     use crate::{
         compaction::tests::build_version, fs::manager::StoreManager,
         inmem::immutable::tests::TestSchema, record::Schema, stream::level::LevelStream, DbOption,
-        Order,
+        Order, ReadHint,
     };
 
     #[tokio::test(flavor = "multi_thread")]
     async fn projection_scan() {
         let temp_dir = TempDir::new().unwrap();
-        let manager = StoreManager::new(FsOptions::Local, vec![]).unwrap();
+        let manager = Arc::new(StoreManager::new(FsOptions::Local, vec![]).unwrap());
         let option = Arc::new(DbOption::new(
             Path::from_filesystem_path(temp_dir.path()).unwrap(),
             &TestSchema {},
@@ -285,7 +316,7 @@ mod tests {
                         .unwrap(),
                     [0, 1, 2, 3],
                 ),
-                manager.base_fs().clone(),
+                manager.clone(),
                 Arc::new(NoCache::default()),
                 None, // Default order for test
                 TestSchema {}.primary_key_indices(),
@@ -326,7 +357,7 @@ mod tests {
                         .unwrap(),
                     [0, 1, 2, 4],
                 ),
-                manager.base_fs().clone(),
+                manager.clone(),
                 Arc::new(NoCache::default()),
                 None, // Default order for test
                 TestSchema {}.primary_key_indices(),
@@ -367,7 +398,7 @@ mod tests {
                         .unwrap(),
                     [0, 1, 2],
                 ),
-                manager.base_fs().clone(),
+                manager.clone(),
                 Arc::new(NoCache::default()),
                 None, // Default order for test
                 TestSchema {}.primary_key_indices(),
@@ -398,7 +429,7 @@ mod tests {
     #[tokio::test(flavor = "multi_thread")]
     async fn test_projection_scan_rev() {
         let temp_dir = TempDir::new().unwrap();
-        let manager = StoreManager::new(FsOptions::Local, vec![]).unwrap();
+        let manager = Arc::new(StoreManager::new(FsOptions::Local, vec![]).unwrap());
         let option = Arc::new(DbOption::new(
             Path::from_filesystem_path(temp_dir.path()).unwrap(),
             &TestSchema {},
@@ -432,7 +463,7 @@ mod tests {
                         .unwrap(),
                     [0, 1, 2, 3],
                 ),
-                manager.base_fs().clone(),
+                manager.clone(),
                 Arc::new(NoCache::default()),
                 Some(Order::Desc),
                 TestSchema {}.primary_key_indices(),
@@ -446,4 +477,67 @@ mod tests {
             assert_eq!(expected, actual.as_slice())
         }
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn read_hint_scan() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(StoreManager::new(FsOptions::Local, vec![]).unwrap());
+        let option = Arc::new(DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema {},
+        ));
+
+        manager
+            .base_fs()
+            .create_dir_all(&option.version_log_dir_path())
+            .await
+            .unwrap();
+        manager
+            .base_fs()
+            .create_dir_all(&option.wal_dir_path())
+            .await
+            .unwrap();
+
+        let (_, version) = build_version(&option, &manager, &Arc::new(TestSchema)).await;
+
+        let mut scans = Vec::new();
+        for read_hint in [None, Some(ReadHint::Sequential), Some(ReadHint::Random)] {
+            for order in [None, Some(Order::Desc)] {
+                let mut level_stream = LevelStream::new(
+                    &version,
+                    1,
+                    0,
+                    2,
+                    (Bound::Unbounded, Bound::Unbounded),
+                    u32::MAX.into(),
+                    None,
+                    ProjectionMask::all(),
+                    manager.clone(),
+                    Arc::new(NoCache::default()),
+                    order,
+                    TestSchema {}.primary_key_indices(),
+                )
+                .unwrap()
+                .read_hint(read_hint);
+
+                let mut keys = vec![];
+                while let Some(entry) = level_stream.next().await.transpose().unwrap() {
+                    keys.push(entry.key().to_string());
+                }
+                scans.push(keys);
+            }
+        }
+
+        let expected = (1..=9).map(|key| key.to_string()).collect::<Vec<_>>();
+        for (i, keys) in scans.iter().enumerate() {
+            if i % 2 == 0 {
+                assert_eq!(keys, &expected);
+            } else {
+                assert_eq!(
+                    keys.iter().rev().collect::<Vec<_>>(),
+                    expected.iter().collect::<Vec<_>>()
+                );
+            }
+        }
+    }
 }
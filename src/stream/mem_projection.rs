@@ -4,6 +4,7 @@ use std::{
     task::{Context, Poll},
 };
 
+use arrow::datatypes::Schema as ArrowSchema;
 use futures_core::Stream;
 use parquet::{arrow::ProjectionMask, errors::ParquetError};
 use pin_project_lite::pin_project;
@@ -20,6 +21,7 @@ pin_project! {
     {
         stream: Box<ScanStream<'projection, R>>,
         projection_mask: Arc<ProjectionMask>,
+        full_schema: Arc<ArrowSchema>,
     }
 }
 
@@ -27,10 +29,15 @@ impl<'projection, R> MemProjectionStream<'projection, R>
 where
     R: Record,
 {
-    pub(crate) fn new(stream: ScanStream<'projection, R>, projection_mask: ProjectionMask) -> Self {
+    pub(crate) fn new(
+        stream: ScanStream<'projection, R>,
+        projection_mask: ProjectionMask,
+        full_schema: Arc<ArrowSchema>,
+    ) -> Self {
         Self {
             stream: Box::new(stream),
             projection_mask: Arc::new(projection_mask),
+            full_schema,
         }
     }
 }
@@ -48,6 +55,7 @@ where
             Poll::Ready(Some(Ok(entry))) => Poll::Ready(Some(Ok(Entry::Projection((
                 Box::new(entry),
                 project.projection_mask.clone(),
+                project.full_schema.clone(),
             ))))),
             poll => poll,
         };
@@ -139,6 +147,7 @@ mod tests {
                 .scan((Bound::Unbounded, Bound::Unbounded), 6.into(), None)
                 .into(),
             mask,
+            TestSchema.arrow_schema().clone(),
         );
 
         let entry_0 = stream.next().await.unwrap().unwrap();
@@ -0,0 +1,166 @@
+//! Limit on the memory of the record batches that the scans of a [`DB`](crate::DB) hold at once,
+//! see [`DbOption::scan_memory_limit`](crate::DbOption::scan_memory_limit)
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use thiserror::Error;
+
+/// A scan would have held more decoded record batches than the
+/// [`DbOption::scan_memory_limit`](crate::DbOption::scan_memory_limit) allows
+#[derive(Debug, Error)]
+#[error("scan memory limit of {limit} bytes exceeded: {used} bytes in use, {requested} requested")]
+pub struct ScanMemoryExceeded {
+    /// The configured limit
+    pub limit: usize,
+    /// Bytes held by all scans when the batch was decoded
+    pub used: usize,
+    /// Size of the batch that did not fit
+    pub requested: usize,
+}
+
+/// Bytes held by a component of a DB, e.g. the SSTable record batches of its scans, up to an
+/// optional limit
+#[derive(Debug, Clone)]
+pub(crate) struct MemoryBudget {
+    limit: usize,
+    used: Arc<AtomicUsize>,
+}
+
+impl MemoryBudget {
+    pub(crate) fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit: limit.unwrap_or(usize::MAX),
+            used: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Hold `bytes` of the budget until the returned [`Reservation`] is dropped
+    pub(crate) fn reserve(&self, bytes: usize) -> Result<Reservation, ScanMemoryExceeded> {
+        let mut used = self.used.load(Ordering::Acquire);
+        loop {
+            let total = used.saturating_add(bytes);
+            if total > self.limit {
+                return Err(ScanMemoryExceeded {
+                    limit: self.limit,
+                    used,
+                    requested: bytes,
+                });
+            }
+            match self
+                .used
+                .compare_exchange_weak(used, total, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => {
+                    return Ok(Reservation {
+                        used: self.used.clone(),
+                        bytes,
+                    })
+                }
+                Err(current) => used = current,
+            }
+        }
+    }
+
+    /// Hold no bytes for now, but as many as the returned [`Reservation`] is resized to, whatever
+    /// the limit. Used to account memory that can not be refused, like the buffers of
+    /// compactions.
+    pub(crate) fn track(&self) -> Reservation {
+        Reservation {
+            used: self.used.clone(),
+            bytes: 0,
+        }
+    }
+
+    /// Bytes currently held by all reservations
+    pub(crate) fn used(&self) -> usize {
+        self.used.load(Ordering::Acquire)
+    }
+}
+
+/// Bytes of a [`MemoryBudget`] held by a record batch and the entries read from it
+#[derive(Debug)]
+pub(crate) struct Reservation {
+    used: Arc<AtomicUsize>,
+    bytes: usize,
+}
+
+impl Reservation {
+    /// Hold `bytes` instead of the bytes held so far
+    pub(crate) fn resize(&mut self, bytes: usize) {
+        if bytes > self.bytes {
+            self.used.fetch_add(bytes - self.bytes, Ordering::AcqRel);
+        } else {
+            self.used.fetch_sub(self.bytes - bytes, Ordering::AcqRel);
+        }
+        self.bytes = bytes;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.used.fetch_sub(self.bytes, Ordering::AcqRel);
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::ops::Bound;
+
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::MemoryBudget;
+    use crate::{
+        error::ErrorKind, executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema,
+        tests::Test, DbOption, DB,
+    };
+
+    #[test]
+    fn reservations_are_released() {
+        let budget = MemoryBudget::new(Some(100));
+
+        let first = budget.reserve(60).unwrap();
+        let err = budget.reserve(50).unwrap_err();
+        assert_eq!((err.used, err.requested), (60, 50));
+
+        drop(first);
+        let _second = budget.reserve(100).unwrap();
+        assert_eq!(budget.used(), 100);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scans_beyond_the_limit_fail() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .scan_memory_limit(1);
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+
+        db.insert_batch((0..10).map(|i| Test {
+            vstring: format!("{i:02}"),
+            vu32: i,
+            vbool: None,
+        }))
+        .await
+        .unwrap();
+        db.flush().await.unwrap();
+
+        let snapshot = db.snapshot().await;
+        // the scan reads the first batch of the table when it starts
+        let err = snapshot
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .take()
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::Busy);
+        assert_eq!(db.scan_memory_used(), 0);
+    }
+}
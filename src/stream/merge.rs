@@ -1,16 +1,32 @@
 use std::{
     cmp::Ordering,
     collections::BinaryHeap,
+    future::poll_fn,
+    iter, mem,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
 };
 
+use arrow::datatypes::Schema as ArrowSchema;
 use futures_core::{ready, Stream};
 use futures_util::stream::StreamExt;
+use parquet::{arrow::ProjectionMask, errors::ParquetError};
 use pin_project_lite::pin_project;
 
 use super::{Entry, ScanStream};
-use crate::{option::Order, record::Record, version::timestamp::Timestamp};
+use crate::{
+    expiry::Expired,
+    merge::{self, DynMergeOperator},
+    option::Order,
+    record::{KeyRef, Record, Schema},
+    version::{range_tombstone::RangeTombstone, timestamp::Timestamp},
+    DbError,
+};
+
+/// Predicate the merged records of a scan must match, see [`Scan::filter`](crate::Scan::filter)
+pub(crate) type RecordFilter<R> =
+    Arc<dyn for<'r> Fn(&<R as Record>::Ref<'r>) -> bool + Send + Sync>;
 
 pin_project! {
     pub struct MergeStream<'merge, R>
@@ -18,11 +34,29 @@ pin_project! {
         R: Record,
     {
         streams: Vec<ScanStream<'merge, R>>,
+        // Versions of the current key of each stream of a descending merge, see `NewestFirst`
+        newest_first: Option<Vec<NewestFirst<'merge, R>>>,
         peeked: BinaryHeap<CmpEntry<'merge, R>>,
         buf: Option<Entry<'merge, R>>,
         ts: Timestamp,
         limit: Option<usize>,
+        offset: usize,
         order: Option<Order>,
+        by_stream: bool,
+        filter: Option<RecordFilter<R>>,
+        expired: Option<Expired<R>>,
+        range_tombstones: Vec<RangeTombstone<<R::Schema as Schema>::Key>>,
+        keep_removed: Option<Timestamp>,
+        include_removed: bool,
+        // Whether the value a removal of the buffered key replaced was taken already
+        restored: bool,
+        merge_operator: Option<DynMergeOperator<R>>,
+        // Projection of the records folded from merge operands
+        projection: Option<(Arc<ProjectionMask>, Arc<ArrowSchema>)>,
+        // Projection of the returned records, applied once expired records were hidden
+        returned_projection: Option<(Arc<ProjectionMask>, Arc<ArrowSchema>)>,
+        // Older merge operands of the buffered key if it is an operand, newest first
+        operands: Vec<Entry<'merge, R>>,
     }
 }
 
@@ -36,20 +70,43 @@ where
         order: Option<Order>,
     ) -> Result<Self, parquet::errors::ParquetError> {
         let mut peeked = BinaryHeap::with_capacity(streams.len());
+        let mut newest_first = (order == Some(Order::Desc)).then(|| {
+            streams
+                .iter()
+                .map(|_| NewestFirst::default())
+                .collect::<Vec<_>>()
+        });
 
         for (offset, stream) in streams.iter_mut().enumerate() {
-            if let Some(entry) = stream.next().await {
-                peeked.push(CmpEntry::new(offset, entry?, order));
+            let entry = match &mut newest_first {
+                Some(runs) => poll_fn(|cx| runs[offset].poll_next(stream, cx)).await,
+                None => stream.next().await,
+            };
+            if let Some(entry) = entry {
+                peeked.push(CmpEntry::new(offset, entry?, order, false));
             }
         }
 
         let mut merge_stream = Self {
             streams,
+            newest_first,
             peeked,
             buf: None,
             ts,
             limit: None,
+            offset: 0,
             order,
+            by_stream: false,
+            filter: None,
+            expired: None,
+            range_tombstones: Vec::new(),
+            keep_removed: None,
+            include_removed: false,
+            restored: false,
+            merge_operator: None,
+            projection: None,
+            returned_projection: None,
+            operands: Vec::new(),
         };
         merge_stream.next().await;
 
@@ -63,6 +120,118 @@ where
             ..self
         }
     }
+
+    /// Skip the first `offset` entries of the stream, which do not count towards the limit
+    pub(crate) fn offset(self, offset: usize) -> Self {
+        Self { offset, ..self }
+    }
+
+    /// Only return the records that match `filter`, which hides removed keys. Entries that do not
+    /// match count towards neither the offset nor the limit.
+    pub(crate) fn filter(self, filter: Option<RecordFilter<R>>) -> Self {
+        Self { filter, ..self }
+    }
+
+    /// Hide the records in `expired` like removed keys, see
+    /// [`DbOption::drop_expired`](crate::DbOption::drop_expired)
+    pub(crate) fn expired(self, expired: Option<Expired<R>>) -> Self {
+        Self { expired, ..self }
+    }
+
+    /// Hide the records that `range_tombstones` removed, see
+    /// [`DB::delete_range`](crate::DB::delete_range)
+    pub(crate) fn range_tombstones(
+        self,
+        range_tombstones: Vec<RangeTombstone<<R::Schema as Schema>::Key>>,
+    ) -> Self {
+        Self {
+            range_tombstones,
+            ..self
+        }
+    }
+
+    /// Also return the value a removal at or after `since` replaced, right after the removal, see
+    /// [`DbOption::soft_delete`](crate::DbOption::soft_delete)
+    pub(crate) fn keep_removed(self, since: Option<Timestamp>) -> Self {
+        Self {
+            keep_removed: since,
+            ..self
+        }
+    }
+
+    /// Return the value a removal replaced instead of the removal, if there is one
+    pub(crate) fn include_removed(self, include_removed: bool) -> Self {
+        Self {
+            include_removed,
+            ..self
+        }
+    }
+
+    /// Fold the merge operands of the streams into the records of their keys with
+    /// `merge_operator`, and project the folded records with `projection`
+    pub(crate) fn merge_operator(
+        self,
+        merge_operator: Option<DynMergeOperator<R>>,
+        projection: Option<(ProjectionMask, Arc<ArrowSchema>)>,
+    ) -> Self {
+        Self {
+            merge_operator,
+            projection: projection.map(|(mask, schema)| (Arc::new(mask), schema)),
+            ..self
+        }
+    }
+
+    /// Project the returned records with `projection` after the expired ones were hidden, for
+    /// streams that read every column as any of them may tell when a record expires, see
+    /// [`MergeStream::expired`]
+    pub(crate) fn project(self, projection: Option<(ProjectionMask, Arc<ArrowSchema>)>) -> Self {
+        Self {
+            returned_projection: projection.map(|(mask, schema)| (Arc::new(mask), schema)),
+            ..self
+        }
+    }
+
+    /// Keep the entry of the first stream of those that hold the same key, whatever its
+    /// timestamp. The streams must not hold several versions of a key each, like the merged scans
+    /// of different DBs, whose timestamps are not comparable.
+    pub(crate) async fn from_vec_by_stream(
+        mut streams: Vec<ScanStream<'merge, R>>,
+        order: Option<Order>,
+    ) -> Result<Self, parquet::errors::ParquetError> {
+        let mut peeked = BinaryHeap::with_capacity(streams.len());
+
+        for (offset, stream) in streams.iter_mut().enumerate() {
+            if let Some(entry) = stream.next().await {
+                peeked.push(CmpEntry::new(offset, entry?, order, true));
+            }
+        }
+
+        let mut merge_stream = Self {
+            streams,
+            newest_first: None,
+            peeked,
+            buf: None,
+            // every stream already hides the versions newer than its own snapshot
+            ts: Timestamp::from(u32::MAX),
+            limit: None,
+            offset: 0,
+            order,
+            by_stream: true,
+            filter: None,
+            expired: None,
+            range_tombstones: Vec::new(),
+            keep_removed: None,
+            include_removed: false,
+            restored: false,
+            merge_operator: None,
+            projection: None,
+            returned_projection: None,
+            operands: Vec::new(),
+        };
+        merge_stream.next().await;
+
+        Ok(merge_stream)
+    }
 }
 
 impl<'merge, R> Stream for MergeStream<'merge, R>
@@ -80,29 +249,278 @@ where
             }
         }
         while let Some(offset) = this.peeked.peek().map(|entry| entry.offset) {
-            let next = ready!(Pin::new(&mut this.streams[offset]).poll_next(cx)).transpose()?;
+            let stream = &mut this.streams[offset];
+            let next = match this.newest_first {
+                Some(runs) => ready!(runs[offset].poll_next(stream, cx)),
+                None => ready!(Pin::new(stream).poll_next(cx)),
+            }
+            .transpose()?;
             let peeked = match this.peeked.pop() {
                 Some(peeked) => peeked,
                 None => return Poll::Ready(None),
             };
             if let Some(next) = next {
-                this.peeked.push(CmpEntry::new(offset, next, *this.order));
+                this.peeked
+                    .push(CmpEntry::new(offset, next, *this.order, *this.by_stream));
             }
             if peeked.entry.key().ts > *ts {
                 continue;
             }
+            let is_buffered_key = this
+                .buf
+                .as_ref()
+                .is_some_and(|buf| buf.key().value == peeked.entry.key().value);
+            if is_buffered_key && this.buf.as_ref().is_some_and(is_operand) {
+                if is_operand(&peeked.entry) {
+                    this.operands.push(peeked.entry);
+                } else if let Some(newest) = this.buf.take() {
+                    let base = Some(peeked.entry).filter(|base| {
+                        !is_expired(this.expired, base)
+                            && !is_range_removed(this.range_tombstones, *ts, base)
+                    });
+                    *this.buf = Some(fold(
+                        this.merge_operator,
+                        this.projection,
+                        newest,
+                        this.operands,
+                        base,
+                    )?);
+                    // the folded record replaced every older version of its key
+                    *this.restored = true;
+                }
+                continue;
+            }
             if let Some(buf) = this.buf {
                 if buf.key().value == peeked.entry.key().value {
-                    continue;
+                    let restore = (*this.include_removed
+                        || this.keep_removed.is_some_and(|since| buf.key().ts >= since))
+                        && !*this.restored
+                        && buf.value().is_none()
+                        && !is_operand(&peeked.entry)
+                        && peeked.entry.value().is_some();
+                    if !restore {
+                        continue;
+                    }
+                    *this.restored = true;
+                    if *this.include_removed {
+                        *buf = peeked.entry;
+                        continue;
+                    }
+                } else {
+                    *this.restored = false;
                 }
             }
+            let entry = match this.buf.replace(peeked.entry) {
+                Some(entry) if is_operand(&entry) => Some(fold(
+                    this.merge_operator,
+                    this.projection,
+                    entry,
+                    this.operands,
+                    None,
+                )?),
+                entry => entry,
+            };
+            if entry.as_ref().is_some_and(|entry| {
+                is_expired(this.expired, entry)
+                    || is_range_removed(this.range_tombstones, *ts, entry)
+            }) {
+                continue;
+            }
+            let entry = entry.map(|entry| project(this.returned_projection, entry));
+            if entry
+                .as_ref()
+                .is_some_and(|entry| !matches(this.filter, entry))
+            {
+                continue;
+            }
+            if entry.is_some() && *this.offset > 0 {
+                *this.offset -= 1;
+                continue;
+            }
             if let Some(limit) = this.limit.as_ref() {
                 this.limit.replace(*limit - 1);
             }
 
-            return Poll::Ready(this.buf.replace(peeked.entry).map(Ok));
+            return Poll::Ready(entry.map(Ok));
+        }
+        if *this.offset > 0 {
+            return Poll::Ready(None);
+        }
+        let entry = match this.buf.take() {
+            Some(entry) if is_operand(&entry) => Some(fold(
+                this.merge_operator,
+                this.projection,
+                entry,
+                this.operands,
+                None,
+            )?),
+            entry => entry,
+        };
+        Poll::Ready(
+            entry
+                .filter(|entry| {
+                    !is_expired(this.expired, entry)
+                        && !is_range_removed(this.range_tombstones, *ts, entry)
+                })
+                .map(|entry| project(this.returned_projection, entry))
+                .filter(|entry| matches(this.filter, entry))
+                .map(Ok),
+        )
+    }
+}
+
+fn is_operand<R>(entry: &Entry<'_, R>) -> bool
+where
+    R: Record,
+{
+    matches!(entry, Entry::Operand(_))
+}
+
+// Folds the merge operand `newest` and the older `operands` of its key into the record `base`
+fn fold<'merge, R>(
+    merge_operator: &Option<DynMergeOperator<R>>,
+    projection: &Option<(Arc<ProjectionMask>, Arc<ArrowSchema>)>,
+    newest: Entry<'merge, R>,
+    operands: &mut Vec<Entry<'merge, R>>,
+    base: Option<Entry<'merge, R>>,
+) -> Result<Entry<'merge, R>, ParquetError>
+where
+    R: Record,
+{
+    let merge_operator = merge_operator
+        .as_ref()
+        .ok_or_else(|| ParquetError::External(Box::new(DbError::NoMergeOperator)))?;
+    let key = newest.key().map(|key| key.clone().to_key());
+    let record = merge::fold(
+        merge_operator.as_ref(),
+        key.value(),
+        base.as_ref().and_then(Entry::value),
+        operands
+            .iter()
+            .rev()
+            .chain(iter::once(&newest))
+            .filter_map(Entry::value),
+    )
+    .map_err(|err| ParquetError::External(Box::new(err)))?;
+    operands.clear();
+
+    Ok(project(projection, Entry::Merged((key, record))))
+}
+
+fn project<'merge, R>(
+    projection: &Option<(Arc<ProjectionMask>, Arc<ArrowSchema>)>,
+    entry: Entry<'merge, R>,
+) -> Entry<'merge, R>
+where
+    R: Record,
+{
+    match projection {
+        Some((mask, schema)) => Entry::Projection((Box::new(entry), mask.clone(), schema.clone())),
+        None => entry,
+    }
+}
+
+fn matches<R>(filter: &Option<RecordFilter<R>>, entry: &Entry<'_, R>) -> bool
+where
+    R: Record,
+{
+    match filter {
+        Some(filter) => entry.value().is_some_and(|record| filter(&record)),
+        None => true,
+    }
+}
+
+fn is_expired<R>(expired: &Option<Expired<R>>, entry: &Entry<'_, R>) -> bool
+where
+    R: Record,
+{
+    match (expired, entry.value()) {
+        (Some(expired), Some(record)) => expired.contains(record),
+        _ => false,
+    }
+}
+
+// Whether a tombstone of `range_tombstones` hides the record of `entry` from a read at `ts`
+fn is_range_removed<R>(
+    range_tombstones: &[RangeTombstone<<R::Schema as Schema>::Key>],
+    ts: Timestamp,
+    entry: &Entry<'_, R>,
+) -> bool
+where
+    R: Record,
+{
+    if range_tombstones.is_empty() || entry.value().is_none() {
+        return false;
+    }
+    let key = entry.key();
+    let value = key.value.to_key();
+    range_tombstones
+        .iter()
+        .any(|tombstone| tombstone.hides(&value, key.ts, ts))
+}
+
+// A stream that is read in descending order returns the versions of each key oldest first, as
+// they are stored newest first. This collects the versions of the key the stream is at to return
+// them newest first, like an ascending stream does, so only one key is buffered per stream.
+struct NewestFirst<'stream, R>
+where
+    R: Record,
+{
+    // versions of the key being read, oldest first
+    collecting: Vec<Entry<'stream, R>>,
+    // versions of the key before, taken from the back
+    ready: Vec<Entry<'stream, R>>,
+    done: bool,
+}
+
+impl<R> Default for NewestFirst<'_, R>
+where
+    R: Record,
+{
+    fn default() -> Self {
+        Self {
+            collecting: Vec::new(),
+            ready: Vec::new(),
+            done: false,
+        }
+    }
+}
+
+impl<'stream, R> NewestFirst<'stream, R>
+where
+    R: Record,
+{
+    fn poll_next(
+        &mut self,
+        stream: &mut ScanStream<'stream, R>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Entry<'stream, R>, parquet::errors::ParquetError>>> {
+        loop {
+            if let Some(entry) = self.ready.pop() {
+                return Poll::Ready(Some(Ok(entry)));
+            }
+            if self.done {
+                return Poll::Ready(None);
+            }
+            match ready!(Pin::new(&mut *stream).poll_next(cx)) {
+                Some(Ok(entry)) => {
+                    let is_next_key = self
+                        .collecting
+                        .last()
+                        .is_some_and(|last| last.key().value != entry.key().value);
+                    if is_next_key {
+                        self.ready = mem::replace(&mut self.collecting, vec![entry]);
+                    } else {
+                        self.collecting.push(entry);
+                    }
+                }
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => {
+                    self.done = true;
+                    self.ready = mem::take(&mut self.collecting);
+                }
+            }
         }
-        Poll::Ready(this.buf.take().map(Ok))
     }
 }
 
@@ -114,17 +532,19 @@ where
     offset: usize,
     entry: Entry<'stream, R>,
     order: Option<Order>,
+    by_stream: bool,
 }
 
 impl<'stream, R> CmpEntry<'stream, R>
 where
     R: Record,
 {
-    fn new(offset: usize, entry: Entry<'stream, R>, order: Option<Order>) -> Self {
+    fn new(offset: usize, entry: Entry<'stream, R>, order: Option<Order>, by_stream: bool) -> Self {
         Self {
             offset,
             entry,
             order,
+            by_stream,
         }
     }
 }
@@ -154,19 +574,28 @@ where
     R: Record,
 {
     fn cmp(&self, other: &Self) -> Ordering {
-        let natural_ordering = self
-            .entry
-            .key()
-            .cmp(&other.entry.key())
-            .then(self.offset.cmp(&other.offset));
-
-        // BinaryHeap is a max-heap, so we need to reverse the ordering for ascending order
-        // For ascending order (normal scan), reverse the natural ordering
-        // For descending order (reverse scan), use the natural ordering
+        if self.by_stream {
+            let keys = self.entry.key().value.cmp(&other.entry.key().value);
+            let keys = if self.order == Some(Order::Desc) {
+                keys
+            } else {
+                keys.reverse()
+            };
+            // the entry of the first stream pops first
+            return keys.then(other.offset.cmp(&self.offset));
+        }
+        // BinaryHeap is a max-heap, so the greatest entry pops first. The newest version of a key
+        // pops first in both orders, and of equal entries the one of the first stream.
+        let (key, other_key) = (self.entry.key(), other.entry.key());
         if self.order == Some(Order::Desc) {
-            natural_ordering
+            key.value
+                .cmp(&other_key.value)
+                .then(key.ts.cmp(&other_key.ts))
+                .then(other.offset.cmp(&self.offset))
         } else {
-            natural_ordering.reverse()
+            key.cmp(&other_key)
+                .then(self.offset.cmp(&other.offset))
+                .reverse()
         }
     }
 }
@@ -475,6 +904,141 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn merge_mutable_offset() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fs = Arc::new(TokioFs) as Arc<dyn DynFs>;
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &StringSchema,
+        );
+
+        fs.create_dir_all(&option.wal_dir_path()).await.unwrap();
+
+        let trigger = TriggerFactory::create(option.trigger_type);
+
+        let m1 =
+            MutableMemTable::<String>::new(&option, trigger, fs.clone(), Arc::new(StringSchema))
+                .await
+                .unwrap();
+        for key in ["1", "2", "3", "4"] {
+            m1.insert(LogType::Full, key.into(), 0_u32.into())
+                .await
+                .unwrap();
+        }
+
+        let merge = |offset| {
+            let m1 = &m1;
+            async move {
+                MergeStream::<String>::from_vec(
+                    vec![m1
+                        .scan((Bound::Unbounded, Bound::Unbounded), 0.into(), None)
+                        .into()],
+                    0.into(),
+                    None,
+                )
+                .await
+                .unwrap()
+                .offset(offset)
+                .limit(2)
+                .map(|entry| entry.unwrap().key().value.to_string())
+                .collect::<Vec<_>>()
+                .await
+            }
+        };
+        assert_eq!(merge(1).await, vec!["2", "3"]);
+        assert_eq!(merge(3).await, vec!["4"]);
+        assert!(merge(4).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn merge_mutable_removed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fs = Arc::new(TokioFs) as Arc<dyn DynFs>;
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &StringSchema,
+        );
+
+        fs.create_dir_all(&option.wal_dir_path()).await.unwrap();
+
+        let trigger = TriggerFactory::create(option.trigger_type);
+
+        let m1 =
+            MutableMemTable::<String>::new(&option, trigger, fs.clone(), Arc::new(StringSchema))
+                .await
+                .unwrap();
+        for key in ["1", "2", "4"] {
+            m1.insert(LogType::Full, key.into(), 0_u32.into())
+                .await
+                .unwrap();
+        }
+        for key in ["2", "3"] {
+            m1.remove(LogType::Full, key.into(), 1_u32.into())
+                .await
+                .unwrap();
+        }
+
+        let merge = |keep_removed: Option<u32>, include_removed| {
+            let m1 = &m1;
+            async move {
+                MergeStream::<String>::from_vec(
+                    vec![m1
+                        .scan((Bound::Unbounded, Bound::Unbounded), 1.into(), None)
+                        .into()],
+                    1.into(),
+                    None,
+                )
+                .await
+                .unwrap()
+                .keep_removed(keep_removed.map(Into::into))
+                .include_removed(include_removed)
+                .map(|entry| {
+                    let entry = entry.unwrap();
+                    (
+                        entry.key().value.to_string(),
+                        u32::from(entry.key().ts),
+                        entry.value().is_some(),
+                    )
+                })
+                .collect::<Vec<_>>()
+                .await
+            }
+        };
+        let entry = |key: &str, ts, value| (key.to_string(), ts, value);
+
+        // the value a removal replaced is returned in its place
+        assert_eq!(
+            merge(None, true).await,
+            vec![
+                entry("1", 0, true),
+                entry("2", 0, true),
+                entry("3", 1, false),
+                entry("4", 0, true),
+            ]
+        );
+        // or right after it, as long as the removal is recent enough
+        assert_eq!(
+            merge(Some(1), false).await,
+            vec![
+                entry("1", 0, true),
+                entry("2", 1, false),
+                entry("2", 0, true),
+                entry("3", 1, false),
+                entry("4", 0, true),
+            ]
+        );
+        assert_eq!(
+            merge(Some(2), false).await,
+            vec![
+                entry("1", 0, true),
+                entry("2", 1, false),
+                entry("3", 1, false),
+                entry("4", 0, true),
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn merge_mutable_reverse() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -580,6 +1144,88 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn merge_mutable_reverse_versions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fs = Arc::new(TokioFs) as Arc<dyn DynFs>;
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &StringSchema,
+        );
+
+        fs.create_dir_all(&option.wal_dir_path()).await.unwrap();
+
+        let trigger = TriggerFactory::create(option.trigger_type);
+        let m1 =
+            MutableMemTable::<String>::new(&option, trigger, fs.clone(), Arc::new(StringSchema))
+                .await
+                .unwrap();
+        m1.insert(LogType::Full, "a".into(), 1.into())
+            .await
+            .unwrap();
+        m1.insert(LogType::Full, "a".into(), 5.into())
+            .await
+            .unwrap();
+        m1.remove(LogType::Full, "b".into(), 4.into())
+            .await
+            .unwrap();
+
+        let trigger = TriggerFactory::create(option.trigger_type);
+        let m2 =
+            MutableMemTable::<String>::new(&option, trigger, fs.clone(), Arc::new(StringSchema))
+                .await
+                .unwrap();
+        m2.insert(LogType::Full, "a".into(), 3.into())
+            .await
+            .unwrap();
+        m2.insert(LogType::Full, "b".into(), 2.into())
+            .await
+            .unwrap();
+        m2.insert(LogType::Full, "c".into(), 2.into())
+            .await
+            .unwrap();
+        m2.insert(LogType::Full, "c".into(), 7.into())
+            .await
+            .unwrap();
+
+        let bound = (Bound::Unbounded, Bound::Unbounded);
+        // the newest version of each key up to the read timestamp wins in both orders
+        for (order, expected) in [
+            (None, [("a", 5, true), ("b", 4, false), ("c", 2, true)]),
+            (
+                Some(Order::Desc),
+                [("c", 2, true), ("b", 4, false), ("a", 5, true)],
+            ),
+        ] {
+            let merge = MergeStream::<String>::from_vec(
+                vec![
+                    m1.scan(bound, 6.into(), order).into(),
+                    m2.scan(bound, 6.into(), order).into(),
+                ],
+                6.into(),
+                order,
+            )
+            .await
+            .unwrap();
+            let entries = merge
+                .map(|entry| {
+                    let entry = entry.unwrap();
+                    let key = entry.key();
+                    (
+                        key.value.to_string(),
+                        u32::from(key.ts),
+                        entry.value().is_some(),
+                    )
+                })
+                .collect::<Vec<_>>()
+                .await;
+            let expected = expected
+                .map(|(key, ts, live)| (key.to_string(), ts, live))
+                .to_vec();
+            assert_eq!(entries, expected, "{order:?}");
+        }
+    }
+
     #[tokio::test]
     async fn test_mutable_scan_directly() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -1,7 +1,9 @@
 pub(crate) mod level;
 pub(crate) mod mem_projection;
+pub mod memory;
 pub(crate) mod merge;
 pub(crate) mod package;
+pub(crate) mod reader;
 pub(crate) mod record_batch;
 
 use std::{
@@ -12,6 +14,7 @@ use std::{
     task::{Context, Poll},
 };
 
+use arrow::datatypes::Schema as ArrowSchema;
 use futures_core::Stream;
 use futures_util::{ready, stream};
 use parquet::arrow::ProjectionMask;
@@ -19,10 +22,13 @@ use pin_project_lite::pin_project;
 use record_batch::RecordBatchEntry;
 
 use crate::{
-    inmem::{immutable::ImmutableScan, mutable::MutableScan},
+    inmem::{
+        immutable::ImmutableScan,
+        mutable::{MutableScan, OperandScan},
+    },
     ondisk::scan::SsTableScan,
     record::{Key, Record, RecordRef, Schema},
-    stream::{level::LevelStream, mem_projection::MemProjectionStream},
+    stream::{level::LevelStream, mem_projection::MemProjectionStream, merge::MergeStream},
     transaction::TransactionScan,
     version::timestamp::Ts,
 };
@@ -38,8 +44,12 @@ where
         ),
     ),
     Mutable(crossbeam_skiplist::map::Entry<'entry, Ts<<R::Schema as Schema>::Key>, Option<R>>),
-    Projection((Box<Entry<'entry, R>>, Arc<ProjectionMask>)),
+    Projection((Box<Entry<'entry, R>>, Arc<ProjectionMask>, Arc<ArrowSchema>)),
     RecordBatch(RecordBatchEntry<R>),
+    /// An operand of [`DB::merge`](crate::DB::merge) that is not folded into a record yet
+    Operand(crossbeam_skiplist::map::Entry<'entry, Ts<<R::Schema as Schema>::Key>, R>),
+    /// A record folded from operands of [`DB::merge`](crate::DB::merge)
+    Merged((Ts<<R::Schema as Schema>::Key>, Option<R>)),
 }
 
 impl<R> Entry<'_, R>
@@ -62,7 +72,12 @@ where
                 unsafe { transmute(key.as_key_ref()) }
             }),
             Entry::RecordBatch(entry) => entry.internal_key(),
-            Entry::Projection((entry, _)) => entry.key(),
+            Entry::Projection((entry, _, _)) => entry.key(),
+            Entry::Operand(entry) => entry.key().map(|key| {
+                // Safety: shorter lifetime must be safe
+                unsafe { transmute(key.as_key_ref()) }
+            }),
+            Entry::Merged((key, _)) => key.map(|key| key.as_key_ref()),
         }
     }
 
@@ -71,10 +86,14 @@ where
             Entry::Transaction((_, value)) => value.as_ref().map(R::as_record_ref),
             Entry::Mutable(entry) => entry.value().as_ref().map(R::as_record_ref),
             Entry::RecordBatch(entry) => entry.get(),
-            Entry::Projection((entry, projection_mask)) => entry.value().map(|mut val_ref| {
-                val_ref.projection(projection_mask);
-                val_ref
-            }),
+            Entry::Projection((entry, projection_mask, full_schema)) => {
+                entry.value().map(|mut val_ref| {
+                    val_ref.projection_with_schema(projection_mask, full_schema);
+                    val_ref
+                })
+            }
+            Entry::Operand(entry) => Some(entry.value().as_record_ref()),
+            Entry::Merged((_, value)) => value.as_ref().map(R::as_record_ref),
         }
     }
 }
@@ -96,9 +115,16 @@ where
                 mutable.value()
             ),
             Entry::RecordBatch(sstable) => write!(f, "Entry::SsTable({sstable:?})"),
-            Entry::Projection((entry, projection_mask)) => {
+            Entry::Projection((entry, projection_mask, _)) => {
                 write!(f, "Entry::Projection({entry:?} -> {projection_mask:?})")
             }
+            Entry::Operand(operand) => write!(
+                f,
+                "Entry::Operand({:?} -> {:?})",
+                operand.key(),
+                operand.value()
+            ),
+            Entry::Merged((key, value)) => write!(f, "Entry::Merged({key:?} -> {value:?})"),
         }
     }
 }
@@ -117,6 +143,10 @@ pin_project! {
             #[pin]
             inner: stream::Iter<MutableScan<'scan, R>>,
         },
+        Operand {
+            #[pin]
+            inner: stream::Iter<OperandScan<'scan, R>>,
+        },
         Immutable {
             #[pin]
             inner: stream::Iter<ImmutableScan<'scan, R>>,
@@ -132,6 +162,11 @@ pin_project! {
         MemProjection {
             #[pin]
             inner: MemProjectionStream<'scan, R>,
+        },
+        // A whole scan of another DB, e.g. a shard
+        Merge {
+            #[pin]
+            inner: MergeStream<'scan, R>,
         }
     }
 }
@@ -158,6 +193,17 @@ where
     }
 }
 
+impl<'scan, R> From<OperandScan<'scan, R>> for ScanStream<'scan, R>
+where
+    R: Record,
+{
+    fn from(inner: OperandScan<'scan, R>) -> Self {
+        ScanStream::Operand {
+            inner: stream::iter(inner),
+        }
+    }
+}
+
 impl<'scan, R> From<ImmutableScan<'scan, R>> for ScanStream<'scan, R>
 where
     R: Record,
@@ -187,6 +233,15 @@ where
     }
 }
 
+impl<'scan, R> From<MergeStream<'scan, R>> for ScanStream<'scan, R>
+where
+    R: Record,
+{
+    fn from(inner: MergeStream<'scan, R>) -> Self {
+        ScanStream::Merge { inner }
+    }
+}
+
 impl<R> fmt::Debug for ScanStream<'_, R>
 where
     R: Record,
@@ -195,10 +250,12 @@ where
         match self {
             ScanStream::Transaction { .. } => write!(f, "ScanStream::Transaction"),
             ScanStream::Mutable { .. } => write!(f, "ScanStream::Mutable"),
+            ScanStream::Operand { .. } => write!(f, "ScanStream::Operand"),
             ScanStream::SsTable { .. } => write!(f, "ScanStream::SsTable"),
             ScanStream::Immutable { .. } => write!(f, "ScanStream::Immutable"),
             ScanStream::Level { .. } => write!(f, "ScanStream::Level"),
             ScanStream::MemProjection { .. } => write!(f, "ScanStream::MemProjection"),
+            ScanStream::Merge { .. } => write!(f, "ScanStream::Merge"),
         }
     }
 }
@@ -217,6 +274,9 @@ where
             ScanStreamProject::Mutable { inner } => {
                 Poll::Ready(ready!(inner.poll_next(cx)).map(Entry::Mutable).map(Ok))
             }
+            ScanStreamProject::Operand { inner } => {
+                Poll::Ready(ready!(inner.poll_next(cx)).map(Entry::Operand).map(Ok))
+            }
             ScanStreamProject::SsTable { inner } => {
                 Poll::Ready(ready!(inner.poll_next(cx)).map(|entry| entry.map(Entry::RecordBatch)))
             }
@@ -227,6 +287,7 @@ where
                 Poll::Ready(ready!(inner.poll_next(cx)).map(|entry| entry.map(Entry::RecordBatch)))
             }
             ScanStreamProject::MemProjection { inner } => Poll::Ready(ready!(inner.poll_next(cx))),
+            ScanStreamProject::Merge { inner } => Poll::Ready(ready!(inner.poll_next(cx))),
         }
     }
 }
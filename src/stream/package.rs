@@ -6,33 +6,35 @@ use std::{
 
 use arrow::datatypes::Schema as ArrowSchema;
 use futures_core::Stream;
+use parquet::errors::ParquetError;
 use pin_project_lite::pin_project;
 
 use crate::{
     record::{ArrowArrays, ArrowArraysBuilder, Record, Schema},
-    stream::merge::MergeStream,
+    stream::Entry,
 };
 
 pin_project! {
-    pub struct PackageStream<'package, R>
+    pub struct PackageStream<R, S>
     where
         R: Record,
     {
         row_count: usize,
         batch_size: usize,
-        inner: MergeStream<'package, R>,
+        #[pin]
+        inner: S,
         builder: <<R::Schema as Schema>::Columns as ArrowArrays>::Builder,
         projection_indices: Option<Vec<usize>>,
     }
 }
 
-impl<'package, R> PackageStream<'package, R>
+impl<R, S> PackageStream<R, S>
 where
     R: Record,
 {
     pub(crate) fn new(
         batch_size: usize,
-        merge: MergeStream<'package, R>,
+        merge: S,
         projection_indices: Option<Vec<usize>>,
         schema: Arc<ArrowSchema>,
     ) -> Self {
@@ -46,17 +48,18 @@ where
     }
 }
 
-impl<R> Stream for PackageStream<'_, R>
+impl<'package, R, S> Stream for PackageStream<R, S>
 where
     R: Record,
+    S: Stream<Item = Result<Entry<'package, R>, ParquetError>>,
 {
-    type Item = Result<<R::Schema as Schema>::Columns, parquet::errors::ParquetError>;
+    type Item = Result<<R::Schema as Schema>::Columns, ParquetError>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let mut project = self.project();
 
         while project.row_count <= project.batch_size {
-            match Pin::new(&mut project.inner).poll_next(cx) {
+            match project.inner.as_mut().poll_next(cx) {
                 Poll::Ready(Some(Ok(entry))) => {
                     if let Some(record) = entry.value() {
                         // filter null
@@ -0,0 +1,41 @@
+use arrow::{
+    datatypes::SchemaRef,
+    error::ArrowError,
+    record_batch::{RecordBatch, RecordBatchReader},
+};
+use flume::Receiver;
+
+/// Blocking [`RecordBatchReader`] over a scan that runs on an
+/// [`Executor`](crate::executor::Executor)
+///
+/// Created by [`DB::batch_reader`](crate::DB::batch_reader). Reading blocks the calling thread
+/// until the scan task produced the next batch, so the reader is meant for synchronous Arrow
+/// consumers and must not be used on a thread that drives async tasks.
+pub struct ScanBatchReader {
+    schema: SchemaRef,
+    batches: Receiver<Result<RecordBatch, ArrowError>>,
+}
+
+impl ScanBatchReader {
+    pub(crate) fn new(
+        schema: SchemaRef,
+        batches: Receiver<Result<RecordBatch, ArrowError>>,
+    ) -> Self {
+        Self { schema, batches }
+    }
+}
+
+impl Iterator for ScanBatchReader {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the scan task closes the channel once the scan is exhausted or failed
+        self.batches.recv().ok()
+    }
+}
+
+impl RecordBatchReader for ScanBatchReader {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
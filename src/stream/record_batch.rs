@@ -11,7 +11,8 @@ use parquet::arrow::ProjectionMask;
 use crate::{
     option::Order,
     record::{option::OptionRecordRef, Key, Record, RecordRef, Schema as RecordSchema},
-    version::timestamp::Ts,
+    stream::memory::Reservation,
+    version::timestamp::{Timestamp, Ts},
 };
 
 pub struct RecordBatchEntry<R>
@@ -20,6 +21,7 @@ where
 {
     _record_batch: RecordBatch,
     record_ref: OptionRecordRef<'static, R::Ref<'static>>,
+    _reservation: Option<Arc<Reservation>>,
 }
 
 impl<R> RecordBatchEntry<R>
@@ -33,6 +35,7 @@ where
         Self {
             _record_batch,
             record_ref,
+            _reservation: None,
         }
     }
 
@@ -44,6 +47,11 @@ where
         self.internal_key().value().clone()
     }
 
+    /// Timestamp of the write
+    pub fn ts(&self) -> Timestamp {
+        self.internal_key().ts()
+    }
+
     pub fn get(&self) -> Option<R::Ref<'_>> {
         // Safety: shorter lifetime of the key must be safe
         unsafe { transmute(self.record_ref.get()) }
@@ -67,6 +75,7 @@ pub struct RecordBatchIterator<R> {
     step: isize,
     projection_mask: ProjectionMask,
     full_schema: Arc<Schema>,
+    reservation: Option<Arc<Reservation>>,
     _marker: PhantomData<R>,
 }
 
@@ -95,9 +104,18 @@ where
             step,
             projection_mask,
             full_schema,
+            reservation: None,
             _marker: PhantomData,
         }
     }
+
+    /// Keep `reservation` until the batch and every entry read from it are dropped
+    pub(crate) fn reservation(self, reservation: Reservation) -> Self {
+        Self {
+            reservation: Some(Arc::new(reservation)),
+            ..self
+        }
+    }
 }
 
 impl<R> Iterator for RecordBatchIterator<R>
@@ -119,12 +137,13 @@ where
             &self.projection_mask,
             &self.full_schema,
         );
-        let entry = RecordBatchEntry::new(record_batch, unsafe {
+        let mut entry = RecordBatchEntry::new(record_batch, unsafe {
             // Safety: self-referring lifetime is safe
             transmute::<OptionRecordRef<'_, R::Ref<'_>>, OptionRecordRef<'static, R::Ref<'static>>>(
                 record,
             )
         });
+        entry._reservation = self.reservation.clone();
 
         // Update offset and remaining count
         self.offset = (self.offset as isize + self.step) as usize;
@@ -3,29 +3,32 @@ use std::{
         btree_map::{Entry, Range},
         BTreeMap, Bound,
     },
+    fmt::{self, Debug, Formatter},
     io,
     iter::Rev,
     mem::transmute,
 };
 
+use arrow::array::RecordBatch;
 use flume::SendError;
+use fusio::{IoBuf, IoBufMut, SeqRead, Write};
+use fusio_log::{Decode, Encode};
+use futures_core::Stream;
+use futures_util::StreamExt;
 use lockable::AsyncLimit;
-use parquet::{
-    arrow::{ArrowSchemaConverter, ProjectionMask},
-    errors::ParquetError,
-};
+use parquet::{arrow::ProjectionMask, errors::ParquetError};
 use thiserror::Error;
 
 use crate::{
+    batch::{self, BatchError},
     compaction::CompactTask,
-    inmem::mutable::WriteResult,
+    error::{io_kind, parquet_kind, ErrorKind},
     option::Order,
-    record::{Key, KeyRef, RecordRef, Schema},
+    record::{Key, KeyRef, PrefixKey, RecordRef, Schema},
     snapshot::Snapshot,
     stream::{self, mem_projection::MemProjectionStream},
     version::timestamp::{Timestamp, Ts},
-    wal::log::LogType,
-    DbError, DbStorage, LockMap, Projection, Record, Scan,
+    DbError, LockMap, Projection, Record, Scan,
 };
 
 pub(crate) enum TransactionScanInner<'scan, R: Record> {
@@ -58,6 +61,19 @@ where
         }
     }
 }
+/// Which commits of other transactions the reads of a [`Transaction`] see, see
+/// [`DB::transaction_with`](crate::DB::transaction_with)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IsolationLevel {
+    /// Every read sees the DB as of the start of the transaction, and the commit fails with
+    /// [`CommitError::WriteConflict`] if another transaction committed one of its keys since
+    #[default]
+    Snapshot,
+    /// Every read sees the commits made before it, so two reads of a key may differ. The commit
+    /// does not check for conflicts, the last commit of a key wins.
+    ReadCommitted,
+}
+
 /// Optimistic ACID transaction, open with
 /// [`DB::transaction`](crate::DB::transaction) method
 ///
@@ -73,6 +89,11 @@ where
     local: BTreeMap<<R::Schema as Schema>::Key, Option<R>>,
     snapshot: Snapshot<'txn, R, E>,
     lock_map: LockMap<<R::Schema as Schema>::Key>,
+    isolation: IsolationLevel,
+    // Size of the records and removed keys in `local`
+    buffered_bytes: usize,
+    // Whether a write was dropped for exceeding the buffer limits, which fails the commit
+    overflowed: bool,
 }
 
 impl<'txn, R, E> Transaction<'txn, R, E>
@@ -85,11 +106,28 @@ where
     pub(crate) fn new(
         snapshot: Snapshot<'txn, R, E>,
         lock_map: LockMap<<R::Schema as Schema>::Key>,
+        isolation: IsolationLevel,
     ) -> Self {
         Self {
             local: BTreeMap::new(),
             snapshot,
             lock_map,
+            isolation,
+            buffered_bytes: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Isolation level the transaction was opened with
+    pub fn isolation(&self) -> IsolationLevel {
+        self.isolation
+    }
+
+    // Timestamp the next read is made at
+    fn read_ts(&self) -> Timestamp {
+        match self.isolation {
+            IsolationLevel::Snapshot => self.snapshot.ts(),
+            IsolationLevel::ReadCommitted => self.snapshot.load_ts(),
         }
     }
 
@@ -104,42 +142,81 @@ where
             Some(v) => v.as_ref().map(|v| {
                 let mut record_ref = v.as_record_ref();
                 if let Projection::Parts(projection) = projection {
-                    let pk_indices = self
-                        .snapshot
-                        .mem_storage()
-                        .record_schema
-                        .primary_key_indices();
-                    let schema = self.snapshot.mem_storage().record_schema.arrow_schema();
-                    let mut projection = projection
-                        .iter()
-                        .map(|name| {
-                            schema
-                                .index_of(name)
-                                .unwrap_or_else(|_| panic!("unexpected field {name}"))
-                        })
-                        .collect::<Vec<usize>>();
-
-                    let mut fixed_projection = vec![0, 1];
-                    fixed_projection.extend_from_slice(pk_indices);
-                    fixed_projection.append(&mut projection);
-                    fixed_projection.dedup();
-
-                    let mask = ProjectionMask::roots(
-                        &ArrowSchemaConverter::new().convert(schema).unwrap(),
-                        fixed_projection.clone(),
-                    );
-                    record_ref.projection(&mask);
+                    let record_schema = &self.snapshot.mem_storage().record_schema;
+                    let mask = record_schema.projection(projection);
+                    record_ref.projection_with_schema(&mask, record_schema.arrow_schema());
                 }
                 TransactionEntry::Local(record_ref)
             }),
             None => self
                 .snapshot
-                .get(key, projection)
+                .get_at(key, projection, self.read_ts())
                 .await?
                 .map(TransactionEntry::Stream),
         })
     }
 
+    /// Get the records of `keys`, in the order of `keys`, with the SSTable reads of the keys this
+    /// transaction did not write batched, see [`DB::get_many`](crate::DB::get_many)
+    pub async fn get_many<'get>(
+        &'get self,
+        keys: &[&'get <R::Schema as Schema>::Key],
+        projection: Projection<'get>,
+    ) -> Result<Vec<Option<TransactionEntry<'get, R>>>, DbError> {
+        let mask = match &projection {
+            Projection::All => None,
+            Projection::Parts(projection) => Some(
+                self.snapshot
+                    .mem_storage()
+                    .record_schema
+                    .projection(projection.clone()),
+            ),
+        };
+        let unwritten = keys
+            .iter()
+            .filter(|key| !self.local.contains_key(**key))
+            .copied()
+            .collect::<Vec<_>>();
+        let mut read = self
+            .snapshot
+            .get_many_at(&unwritten, projection, self.read_ts())
+            .await?
+            .into_iter();
+
+        Ok(keys
+            .iter()
+            .map(|key| match self.local.get(*key) {
+                Some(v) => v.as_ref().map(|v| {
+                    let mut record_ref = v.as_record_ref();
+                    if let Some(mask) = &mask {
+                        record_ref.projection_with_schema(
+                            mask,
+                            self.snapshot.mem_storage().record_schema.arrow_schema(),
+                        );
+                    }
+                    TransactionEntry::Local(record_ref)
+                }),
+                None => read
+                    .next()
+                    .expect("every key without a local write is read")
+                    .map(TransactionEntry::Stream),
+            })
+            .collect())
+    }
+
+    /// Every retained version of the record with `key` as the primary key, newest first
+    ///
+    /// The versions are read from the memtables and the SSTables as of the reads of the
+    /// transaction, including the removals of the key, e.g. to audit when a record changed.
+    /// Versions that compactions already merged away are gone, and writes of this transaction
+    /// are not included.
+    pub async fn get_versions<'get>(
+        &'get self,
+        key: &'get <R::Schema as Schema>::Key,
+    ) -> Result<Vec<KeyVersion<'get, R>>, DbError> {
+        self.snapshot.versions_at(key, self.read_ts()).await
+    }
+
     /// scan records with primary keys in the `range`, return a [`Scan`] that can be convert to a
     /// [`futures_core::Stream`] by using [`Scan::take`].
     ///
@@ -169,12 +246,19 @@ where
             Bound<&'range <R::Schema as Schema>::Key>,
         ),
     ) -> Scan<'scan, 'range, R> {
-        let ts = self.snapshot.ts();
+        let ts = self.read_ts();
         let local = &self.local;
+        let full_schema = self
+            .snapshot
+            .mem_storage()
+            .record_schema
+            .arrow_schema()
+            .clone();
         self.snapshot._scan(
             range,
+            ts,
             Box::new(
-                move |projection_mask: Option<ProjectionMask>, order: Option<Order>| {
+                move |range, projection_mask: Option<ProjectionMask>, order: Option<Order>| {
                     let inner = if order == Some(Order::Desc) {
                         TransactionScanInner::Reverse(local.range(range).rev())
                     } else {
@@ -182,7 +266,9 @@ where
                     };
                     let mut transaction_scan = TransactionScan { inner, ts }.into();
                     if let Some(mask) = projection_mask {
-                        transaction_scan = MemProjectionStream::new(transaction_scan, mask).into();
+                        transaction_scan =
+                            MemProjectionStream::new(transaction_scan, mask, full_schema.clone())
+                                .into();
                     }
                     Some(transaction_scan)
                 },
@@ -190,23 +276,140 @@ where
         )
     }
 
+    /// Number of records with primary keys in `range`, including the writes of this
+    /// transaction, see [`Scan::count`]
+    pub async fn count(
+        &self,
+        range: (
+            Bound<&<R::Schema as Schema>::Key>,
+            Bound<&<R::Schema as Schema>::Key>,
+        ),
+    ) -> Result<u64, DbError> {
+        self.scan(range).count().await
+    }
+
+    /// Scan the records whose primary key starts with `prefix` as [`Transaction::scan`] does,
+    /// see [`DB::scan_prefix`](crate::DB::scan_prefix), and process them using closure `f`
+    pub fn scan_prefix<'scan, T: 'scan>(
+        &'scan self,
+        prefix: &'scan <R::Schema as Schema>::Key,
+        mut f: impl FnMut(stream::Entry<'_, R>) -> T + 'scan,
+    ) -> impl Stream<Item = Result<T, DbError>> + 'scan
+    where
+        <R::Schema as Schema>::Key: PrefixKey,
+    {
+        async_stream::stream! {
+            let upper = prefix.prefix_successor();
+            let mut scan = self
+                .scan((
+                    Bound::Included(prefix),
+                    upper.as_ref().map_or(Bound::Unbounded, Bound::Excluded),
+                ))
+                .prefix(prefix.prefix_bytes())
+                .take()
+                .await?;
+
+            while let Some(entry) = scan.next().await {
+                yield Ok(f(entry?));
+            }
+        }
+    }
+
     /// insert a sequence of data as a single batch on this transaction
+    ///
+    /// If the write exceeds the limits of [`DbOption::max_transaction_entries`] or
+    /// [`DbOption::max_transaction_bytes`], the writes of the transaction are dropped and its
+    /// commit fails with [`CommitError::BufferFull`], see [`Transaction::try_insert`] to commit
+    /// before that instead.
+    ///
+    /// [`DbOption::max_transaction_entries`]: crate::DbOption::max_transaction_entries
+    /// [`DbOption::max_transaction_bytes`]: crate::DbOption::max_transaction_bytes
     pub fn insert(&mut self, value: R) {
-        self.entry(value.key().to_key(), Some(value))
+        let key = value.key().to_key();
+        self.entry_or_overflow(key, Some(value))
+    }
+
+    /// Insert the rows of `batch`, which has the columns of the schema without `_null` and
+    /// `_ts`, on this transaction, see [`batch`](crate::batch)
+    ///
+    /// Each row counts against the buffer limits like [`Transaction::insert`].
+    pub async fn insert_record_batch(&mut self, batch: &RecordBatch) -> Result<(), BatchError> {
+        let schema = self.snapshot.mem_storage().record_schema.clone();
+        for record in batch::records::<R>(&schema, batch)? {
+            self.insert(record);
+        }
+        Ok(())
     }
 
     /// delete the record with the primary key as the `key` on this transaction
+    ///
+    /// Counts against the buffer limits like [`Transaction::insert`].
     pub fn remove(&mut self, key: <R::Schema as Schema>::Key) {
+        self.entry_or_overflow(key, None)
+    }
+
+    /// [`Transaction::insert`] that returns the value if the write exceeds the buffer limits,
+    /// leaving the earlier writes of the transaction to be committed
+    pub fn try_insert(&mut self, value: R) -> Result<(), BufferFull<R>> {
+        let key = value.key().to_key();
+        self.entry(key, Some(value))
+    }
+
+    /// [`Transaction::remove`] that returns the key if the write exceeds the buffer limits,
+    /// leaving the earlier writes of the transaction to be committed
+    pub fn try_remove(&mut self, key: <R::Schema as Schema>::Key) -> Result<(), BufferFull<R>> {
         self.entry(key, None)
     }
 
-    fn entry(&mut self, key: <R::Schema as Schema>::Key, value: Option<R>) {
+    fn entry_or_overflow(&mut self, key: <R::Schema as Schema>::Key, value: Option<R>) {
+        if self.overflowed || self.entry(key, value).is_err() {
+            // the commit fails anyway, so the buffer is freed right away
+            self.overflowed = true;
+            self.local.clear();
+            self.buffered_bytes = 0;
+        }
+    }
+
+    fn entry(
+        &mut self,
+        key: <R::Schema as Schema>::Key,
+        value: Option<R>,
+    ) -> Result<(), BufferFull<R>> {
+        let size = |key: &<R::Schema as Schema>::Key, value: &Option<R>| match value {
+            Some(record) => record.size(),
+            None => key.size(),
+        };
+        let added = size(&key, &value);
+        let option = &self.snapshot.mem_storage().option;
+
         match self.local.entry(key) {
             Entry::Vacant(v) => {
+                let bytes = self.buffered_bytes + added;
+                if self.overflowed
+                    || self.local.len() >= option.max_transaction_entries
+                    || bytes > option.max_transaction_bytes
+                {
+                    return Err(BufferFull {
+                        key: v.into_key(),
+                        value,
+                    });
+                }
+                self.buffered_bytes = bytes;
                 v.insert(value);
             }
-            Entry::Occupied(mut o) => *o.get_mut() = value,
+            Entry::Occupied(mut o) => {
+                let bytes = self.buffered_bytes - size(o.key(), o.get()) + added;
+                if self.overflowed || bytes > option.max_transaction_bytes {
+                    return Err(BufferFull {
+                        key: o.key().clone(),
+                        value,
+                    });
+                }
+                self.buffered_bytes = bytes;
+                *o.get_mut() = value;
+            }
         }
+        Ok(())
     }
 
     /// commit the data in the [`Transaction`] to the corresponding
@@ -214,8 +417,12 @@ where
     ///
     /// # Error
     /// This function will return an error if the mutation in the transaction conflict with
-    /// other committed transaction
+    /// other committed transaction, unless it is [`IsolationLevel::ReadCommitted`], or if a write
+    /// exceeded the buffer limits of the transaction
     pub async fn commit(mut self) -> Result<(), CommitError<R>> {
+        if self.overflowed {
+            return Err(CommitError::BufferFull);
+        }
         let mut _key_guards = Vec::new();
 
         for (key, _) in self.local.iter() {
@@ -227,68 +434,28 @@ where
                     .unwrap(),
             );
         }
-        for (key, _) in self.local.iter() {
-            if self
-                .snapshot
-                .mem_storage()
-                .check_conflict(key, self.snapshot.ts())
-            {
-                return Err(CommitError::WriteConflict(key.clone()));
+        if self.isolation == IsolationLevel::Snapshot {
+            for (key, _) in self.local.iter() {
+                if self
+                    .snapshot
+                    .mem_storage()
+                    .check_conflict(key, self.snapshot.ts())
+                {
+                    return Err(CommitError::WriteConflict(key.clone()));
+                }
             }
         }
 
-        let len = self.local.len();
-        let is_excess = match len {
-            0 => false,
-            1 => {
-                let new_ts = self.snapshot.increase_ts();
-                let (key, record) = self.local.pop_first().unwrap();
-                let write_result = Self::append(
-                    self.snapshot.mem_storage(),
-                    LogType::Full,
-                    key,
-                    record,
-                    new_ts,
-                )
-                .await?;
-                write_result.needs_compaction()
-            }
-            _ => {
-                let new_ts = self.snapshot.increase_ts();
-                let mut iter = self.local.into_iter();
-
-                let (key, record) = iter.next().unwrap();
-                Self::append(
-                    self.snapshot.mem_storage(),
-                    LogType::First,
-                    key,
-                    record,
-                    new_ts,
-                )
-                .await?;
-
-                for (key, record) in (&mut iter).take(len - 2) {
-                    Self::append(
-                        self.snapshot.mem_storage(),
-                        LogType::Middle,
-                        key,
-                        record,
-                        new_ts,
-                    )
-                    .await?;
-                }
-
-                let (key, record) = iter.next().unwrap();
-                let write_result = Self::append(
-                    self.snapshot.mem_storage(),
-                    LogType::Last,
-                    key,
-                    record,
-                    new_ts,
-                )
-                .await?;
-                write_result.needs_compaction()
-            }
+        let is_excess = if self.local.is_empty() {
+            false
+        } else {
+            let new_ts = self.snapshot.increase_ts();
+            let entries = self.local.into_iter().collect();
+            self.snapshot
+                .mem_storage()
+                .write_commit(entries, new_ts)
+                .await?
+                .needs_compaction()
         };
         if is_excess {
             let _ = self
@@ -299,19 +466,6 @@ where
         }
         Ok(())
     }
-
-    async fn append(
-        schema: &DbStorage<R>,
-        log_ty: LogType,
-        key: <R::Schema as Schema>::Key,
-        record: Option<R>,
-        new_ts: Timestamp,
-    ) -> Result<WriteResult, CommitError<R>> {
-        Ok(match record {
-            Some(record) => schema.write(log_ty, record, new_ts).await?,
-            None => schema.remove(log_ty, key, new_ts).await?,
-        })
-    }
 }
 
 pub enum TransactionEntry<'entry, R>
@@ -338,6 +492,90 @@ where
     }
 }
 
+/// A version of a key committed at [`KeyVersion::ts`], see [`Transaction::get_versions`]
+pub struct KeyVersion<'entry, R>
+where
+    R: Record,
+{
+    pub ts: Timestamp,
+    entry: stream::Entry<'entry, R>,
+}
+
+impl<'entry, R> KeyVersion<'entry, R>
+where
+    R: Record,
+{
+    pub(crate) fn new(ts: Timestamp, entry: stream::Entry<'entry, R>) -> Self {
+        Self { ts, entry }
+    }
+
+    /// The record of the version, `None` if the key was removed
+    pub fn value(&self) -> Option<R::Ref<'_>> {
+        self.entry.value()
+    }
+
+    /// Owned copy of the record of the version, which goes through the encoding of the WAL
+    pub(crate) async fn to_record(&self) -> Result<Option<R>, fusio::Error> {
+        to_record(self.entry.value()).await
+    }
+}
+
+/// Owned copy of `record`, which goes through the encoding of the WAL
+pub(crate) async fn to_record<R: Record>(
+    record: Option<R::Ref<'_>>,
+) -> Result<Option<R>, fusio::Error> {
+    let mut buf = Buffer::default();
+    record.encode(&mut buf).await?;
+    Option::<R>::decode(&mut buf).await
+}
+
+/// Bytes that are read back in the order they were written
+#[derive(Default)]
+pub(crate) struct Buffer {
+    bytes: Vec<u8>,
+    pos: usize,
+}
+
+impl Buffer {
+    pub(crate) fn new(bytes: Vec<u8>) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl Write for Buffer {
+    async fn write_all<B: IoBuf>(&mut self, buf: B) -> (Result<(), fusio::Error>, B) {
+        self.bytes.extend_from_slice(buf.as_slice());
+        (Ok(()), buf)
+    }
+
+    async fn flush(&mut self) -> Result<(), fusio::Error> {
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), fusio::Error> {
+        Ok(())
+    }
+}
+
+impl SeqRead for Buffer {
+    async fn read_exact<B: IoBufMut>(&mut self, mut buf: B) -> (Result<(), fusio::Error>, B) {
+        let len = buf.bytes_init();
+        let Some(bytes) = self.bytes.get(self.pos..self.pos + len) else {
+            return (
+                Err(fusio::Error::Io(io::ErrorKind::UnexpectedEof.into())),
+                buf,
+            );
+        };
+        buf.as_slice_mut().copy_from_slice(bytes);
+        self.pos += len;
+        (Ok(()), buf)
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum CommitError<R>
 where
@@ -355,6 +593,59 @@ where
     SendCompactTaskError(#[from] SendError<CompactTask>),
     #[error("Channel is closed")]
     ChannelClose,
+    #[error("transaction writes exceed the transaction buffer limits")]
+    BufferFull,
+}
+
+/// A write rejected by [`Transaction::try_insert`] or [`Transaction::try_remove`] because the
+/// transaction already buffers [`DbOption::max_transaction_entries`] keys, or the write would take
+/// it past [`DbOption::max_transaction_bytes`]
+///
+/// [`DbOption::max_transaction_entries`]: crate::DbOption::max_transaction_entries
+/// [`DbOption::max_transaction_bytes`]: crate::DbOption::max_transaction_bytes
+#[derive(Error)]
+#[error("transaction buffer is full")]
+pub struct BufferFull<R>
+where
+    R: Record,
+{
+    pub key: <R::Schema as Schema>::Key,
+    /// The inserted record, `None` for a removal
+    pub value: Option<R>,
+}
+
+impl<R> Debug for BufferFull<R>
+where
+    R: Record,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferFull")
+            .field("key", &self.key)
+            .finish()
+    }
+}
+
+impl<R> CommitError<R>
+where
+    R: Record,
+{
+    /// Category of the error, a write conflict is [`ErrorKind::Conflict`]
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            CommitError::Io(err) => io_kind(err),
+            CommitError::Parquet(err) => parquet_kind(err),
+            CommitError::Database(err) => err.kind(),
+            CommitError::WriteConflict(_) => ErrorKind::Conflict,
+            CommitError::BufferFull => ErrorKind::InvalidArgument,
+            CommitError::SendCompactTaskError(_) | CommitError::ChannelClose => ErrorKind::Io,
+        }
+    }
+
+    /// Whether the commit may succeed if the transaction is run again, see
+    /// [`ErrorKind::is_retryable`]
+    pub fn is_retryable(&self) -> bool {
+        self.kind().is_retryable()
+    }
 }
 
 #[cfg(all(test, feature = "tokio"))]
@@ -366,21 +657,133 @@ mod tests {
     use futures_util::StreamExt;
     use tempfile::TempDir;
 
+    #[cfg(feature = "dyn-record")]
+    use crate::record::{
+        dynamic::{test::test_dyn_item_schema, DynRecord, Value},
+        ValueRef,
+    };
     use crate::{
         compaction::tests::build_version,
+        error::ErrorKind,
         executor::tokio::TokioExecutor,
         fs::manager::StoreManager,
         inmem::immutable::tests::TestSchema,
-        record::{
-            dynamic::{test::test_dyn_item_schema, DynRecord, Value},
-            test::StringSchema,
-            ValueRef,
-        },
+        record::test::StringSchema,
         tests::{build_db, build_schema, Test},
-        transaction::CommitError,
+        transaction::{CommitError, IsolationLevel},
         DbOption, Projection, DB,
     };
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn transaction_get_versions() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db = DB::<Test, TokioExecutor>::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        let key = "key".to_string();
+        let record = |vu32| Test {
+            vstring: key.clone(),
+            vu32,
+            vbool: None,
+        };
+
+        db.insert(record(1)).await.unwrap();
+        db.insert(record(2)).await.unwrap();
+        // older versions are read from the SSTables as well
+        db.flush().await.unwrap();
+        db.remove(key.clone()).await.unwrap();
+        db.insert(record(3)).await.unwrap();
+
+        let txn = db.transaction().await;
+        db.insert(record(4)).await.unwrap();
+        let versions = txn
+            .get_versions(&key)
+            .await
+            .unwrap()
+            .iter()
+            .map(|version| {
+                let vu32 = version.value().map(|record| record.vu32.unwrap());
+                (u32::from(version.ts), vu32)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            versions,
+            vec![(4, Some(3)), (3, None), (2, Some(2)), (1, Some(1))]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn transaction_buffer_limits() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let db = DB::<String, TokioExecutor>::new(
+            DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &StringSchema,
+            )
+            .max_transaction_entries(2),
+            TokioExecutor::default(),
+            StringSchema,
+        )
+        .await
+        .unwrap();
+
+        let mut txn = db.transaction().await;
+        txn.try_insert("a".to_string()).unwrap();
+        txn.try_remove("b".to_string()).unwrap();
+        // overwriting a buffered key takes no extra entry
+        txn.try_insert("b".to_string()).unwrap();
+        let full = txn.try_insert("c".to_string()).unwrap_err();
+        assert_eq!(full.key, "c");
+        assert_eq!(full.value.as_deref(), Some("c"));
+        txn.commit().await.unwrap();
+
+        let mut txn = db.transaction().await;
+        for key in ["d", "e", "f"] {
+            txn.insert(key.to_string());
+        }
+        let err = txn.commit().await.unwrap_err();
+        assert!(matches!(err, CommitError::BufferFull));
+        assert_eq!(err.kind(), ErrorKind::InvalidArgument);
+
+        let txn = db.transaction().await;
+        for (key, found) in [("a", true), ("b", true), ("c", false), ("d", false)] {
+            let entry = txn.get(&key.to_string(), Projection::All).await.unwrap();
+            assert_eq!(entry.is_some(), found);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn transaction_buffer_bytes() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let db = DB::<String, TokioExecutor>::new(
+            DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &StringSchema,
+            )
+            // the size of a `String` record is its length
+            .max_transaction_bytes(12),
+            TokioExecutor::default(),
+            StringSchema,
+        )
+        .await
+        .unwrap();
+
+        let mut txn = db.transaction().await;
+        for key in ["aaaa", "bbbb", "cccc"] {
+            txn.try_insert(key.to_string()).unwrap();
+        }
+        assert!(txn.try_insert("d".to_string()).is_err());
+        // a record of a buffered key only adds the difference of the sizes
+        txn.try_insert("aaaa".to_string()).unwrap();
+        txn.commit().await.unwrap();
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn transaction_read_write() {
         let temp_dir = TempDir::new().unwrap();
@@ -585,7 +988,10 @@ mod tests {
 
         txn_0.commit().await.unwrap();
 
-        if let Err(CommitError::WriteConflict(conflict_key)) = txn_1.commit().await {
+        let err = txn_1.commit().await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Conflict);
+        assert!(err.is_retryable());
+        if let CommitError::WriteConflict(conflict_key) = err {
             assert_eq!(conflict_key, 1.to_string());
             txn_2.commit().await.unwrap();
             return;
@@ -593,6 +999,60 @@ mod tests {
         unreachable!();
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn read_committed() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &StringSchema,
+        );
+
+        let db = DB::<String, TokioExecutor>::new(option, TokioExecutor::default(), StringSchema)
+            .await
+            .unwrap();
+
+        let mut snapshot_txn = db.transaction().await;
+        let mut read_committed_txn = db.transaction_with(IsolationLevel::ReadCommitted).await;
+        assert_eq!(
+            read_committed_txn.isolation(),
+            IsolationLevel::ReadCommitted
+        );
+
+        let mut txn = db.transaction().await;
+        txn.insert(0.to_string());
+        txn.insert(1.to_string());
+        txn.commit().await.unwrap();
+
+        let key = 0.to_string();
+        assert!(snapshot_txn
+            .get(&key, Projection::All)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(read_committed_txn
+            .get(&key, Projection::All)
+            .await
+            .unwrap()
+            .is_some());
+        let scanned = read_committed_txn
+            .scan((Bound::Unbounded, Bound::Unbounded))
+            .take()
+            .await
+            .unwrap()
+            .count()
+            .await;
+        assert_eq!(scanned, 2);
+
+        // the last commit wins instead of conflicting
+        snapshot_txn.insert(1.to_string());
+        read_committed_txn.insert(1.to_string());
+        assert_eq!(
+            snapshot_txn.commit().await.unwrap_err().kind(),
+            ErrorKind::Conflict
+        );
+        read_committed_txn.commit().await.unwrap();
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn transaction_projection() {
         let temp_dir = TempDir::new().unwrap();
@@ -983,6 +1443,7 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "dyn-record")]
     #[tokio::test(flavor = "multi_thread")]
     async fn test_dyn_record() {
         let temp_dir = TempDir::new().unwrap();
@@ -1033,7 +1494,7 @@ mod tests {
                 .await
                 .unwrap();
             while let Some(entry) = scan.next().await.transpose().unwrap() {
-                assert_eq!(entry.value().unwrap().primary_index, 0);
+                assert_eq!(entry.value().unwrap().primary_indices, vec![0]);
                 assert_eq!(entry.value().unwrap().columns.len(), 3);
                 let columns = entry.value().unwrap().columns;
                 dbg!(columns.clone());
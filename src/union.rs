@@ -0,0 +1,105 @@
+//! Scans over several [`DB`](crate::DB)s of the same schema
+//!
+//! Data is sometimes kept in one DB per day or per tenant. [`union_scan`] answers the occasional
+//! query over all of them with a single stream, as if they were one DB.
+
+use futures_core::Stream;
+use parquet::errors::ParquetError;
+
+use crate::{record::Record, stream::merge::MergeStream, DbError, Entry, Scan};
+
+/// Merge `scans` of different DBs into one stream in key order
+///
+/// The scans are set up as usual, e.g. from a [`Snapshot`](crate::DB::snapshot) or a
+/// [`Transaction`](crate::transaction::Transaction) of each DB, and must all have the same
+/// order. If several DBs hold the same key, only the entry of the first scan in `scans` that
+/// holds it is returned, so the DBs should be listed by priority, e.g. the newest day first. That
+/// entry may be a removal, which then hides the key of the later DBs as well. The limit of a scan
+/// applies to its own DB only.
+pub async fn union_scan<'scan, R>(
+    scans: Vec<Scan<'scan, '_, R>>,
+) -> Result<impl Stream<Item = Result<Entry<'scan, R>, ParquetError>> + 'scan, DbError>
+where
+    R: Record,
+{
+    let order = scans.first().and_then(|scan| scan.order);
+    let mut streams = Vec::with_capacity(scans.len());
+    for scan in scans {
+        streams.push(scan.merge_stream().await?.into());
+    }
+
+    Ok(MergeStream::from_vec_by_stream(streams, order).await?)
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::ops::Bound;
+
+    use fusio::path::Path;
+    use futures_util::StreamExt;
+    use tempfile::TempDir;
+
+    use super::union_scan;
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    async fn day(
+        temp_dir: &TempDir,
+        name: &str,
+        keys: &[&str],
+        vu32: u32,
+    ) -> DB<Test, TokioExecutor> {
+        let path = temp_dir.path().join(name);
+        std::fs::create_dir_all(&path).unwrap();
+        let option = DbOption::new(Path::from_filesystem_path(&path).unwrap(), &TestSchema);
+        let db = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        for key in keys {
+            db.insert(Test {
+                vstring: key.to_string(),
+                vu32,
+                vbool: None,
+            })
+            .await
+            .unwrap();
+        }
+        db
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn union_of_databases() {
+        let temp_dir = TempDir::new().unwrap();
+        let today = day(&temp_dir, "today", &["b", "d"], 2).await;
+        let yesterday = day(&temp_dir, "yesterday", &["a", "b", "c", "e"], 1).await;
+        today.remove("c".to_string()).await.unwrap();
+        yesterday.flush().await.unwrap();
+
+        let (newer, older) = (today.snapshot().await, yesterday.snapshot().await);
+        let upper = "d".to_string();
+        let range = (Bound::Unbounded, Bound::Included(&upper));
+        let mut scan = std::pin::pin!(union_scan(vec![newer.scan(range), older.scan(range)])
+            .await
+            .unwrap());
+
+        let mut entries = Vec::new();
+        while let Some(entry) = scan.next().await {
+            let entry = entry.unwrap();
+            entries.push((
+                entry.key().value.to_string(),
+                entry.value().map(|value| value.vu32.unwrap()),
+            ));
+        }
+        assert_eq!(
+            entries,
+            vec![
+                ("a".to_string(), Some(1)),
+                ("b".to_string(), Some(2)),
+                ("c".to_string(), None),
+                ("d".to_string(), Some(2)),
+            ]
+        );
+    }
+}
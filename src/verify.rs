@@ -0,0 +1,270 @@
+//! Consistency checks of the SSTables of a [`DB`](crate::DB)
+//!
+//! [`DB::verify_integrity`](crate::DB::verify_integrity) reads every SSTable of the current
+//! version and reports what does not match the manifest, instead of letting a read or a
+//! compaction fail on it later:
+//!
+//! - tables that are missing or whose footer cannot be read
+//! - records that do not match the record checksum of their table, see
+//!   [`DbOption::record_checksums`](crate::DbOption::record_checksums)
+//! - rows out of key order or outside the key range of their table in the manifest
+//! - row counts that differ from the ones in the manifest
+//! - tables of the same level, below level 0, whose key ranges overlap
+
+use std::{fmt::Display, ops::Bound, pin::pin};
+
+use futures_util::StreamExt;
+use parquet::arrow::ProjectionMask;
+
+use crate::{
+    context::Context,
+    fs::{FileId, FileType},
+    ondisk::{
+        checksum::{RecordChecksum, RECORD_CHECKSUM_KEY},
+        sstable::SsTable,
+    },
+    record::{KeyRef, Record, Schema},
+    scope::Scope,
+    version::Version,
+    DbOption,
+};
+
+/// Outcome of [`DB::verify_integrity`](crate::DB::verify_integrity)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// Number of SSTables that were checked
+    pub tables: usize,
+    /// Number of rows of the tables that could be read
+    pub rows: u64,
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    /// Whether no issue was found
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// An inconsistency between an SSTable and the manifest, see the [module docs](self)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityIssue {
+    MissingTable {
+        level: usize,
+        gen: FileId,
+    },
+    /// The footer or the rows of the table could not be read
+    Unreadable {
+        level: usize,
+        gen: FileId,
+        reason: String,
+    },
+    Checksum {
+        level: usize,
+        gen: FileId,
+    },
+    /// Rows of the table are not sorted by key and descending timestamp
+    Unsorted {
+        level: usize,
+        gen: FileId,
+    },
+    /// A key of the table is outside of the key range of its scope
+    OutOfRange {
+        level: usize,
+        gen: FileId,
+    },
+    RowCount {
+        level: usize,
+        gen: FileId,
+        expected: u64,
+        actual: u64,
+    },
+    /// The key ranges of two neighbouring tables of a level overlap
+    Overlap {
+        level: usize,
+        gens: [FileId; 2],
+    },
+}
+
+/// Check the tables of `version`, which are read with the paths of `option`
+pub(crate) async fn verify<R: Record>(
+    version: &Version<R>,
+    option: &DbOption,
+    ctx: &Context<R>,
+    schema: &R::Schema,
+) -> IntegrityReport {
+    let mut report = IntegrityReport::default();
+    for (level, scopes) in version.level_slice.iter().enumerate() {
+        // tables of level 0 may overlap each other
+        if level > 0 {
+            for pair in scopes.windows(2) {
+                if pair[1].min <= pair[0].max {
+                    report.issues.push(IntegrityIssue::Overlap {
+                        level,
+                        gens: [pair[0].gen, pair[1].gen],
+                    });
+                }
+            }
+        }
+        for scope in scopes {
+            report.tables += 1;
+            match verify_table(option, ctx, schema, scope, level).await {
+                Ok(rows) => report.rows += rows,
+                Err(issue) => report.issues.push(issue),
+            }
+        }
+    }
+
+    report
+}
+
+/// Number of rows of the table of `scope` at `level`, or its first issue
+async fn verify_table<R: Record>(
+    option: &DbOption,
+    ctx: &Context<R>,
+    schema: &R::Schema,
+    scope: &Scope<<R::Schema as Schema>::Key>,
+    level: usize,
+) -> Result<u64, IntegrityIssue> {
+    let gen = scope.gen;
+    let metadata = open(option, ctx, gen, level)
+        .await?
+        .metadata()
+        .await
+        .map_err(|err| unreadable(level, gen, &err))?;
+    let expected_checksum = metadata
+        .file_metadata()
+        .key_value_metadata()
+        .and_then(|metadata| metadata.iter().find(|kv| kv.key == RECORD_CHECKSUM_KEY))
+        .map(|kv| kv.value.clone());
+
+    let mut rows = pin!(open(option, ctx, gen, level)
+        .await?
+        .scan(
+            (Bound::Unbounded, Bound::Unbounded),
+            u32::MAX.into(),
+            None,
+            ProjectionMask::all(),
+            None,
+            schema.primary_key_indices(),
+        )
+        .await
+        .map_err(|err| unreadable(level, gen, &err))?);
+    let mut checksum = RecordChecksum::new(expected_checksum.is_some());
+    let mut last = None;
+    let mut count = 0;
+    while let Some(row) = rows.next().await {
+        let row = row.map_err(|err| unreadable(level, gen, &err))?;
+        let (key, ts) = (row.key().to_key(), row.ts());
+        if key < scope.min || key > scope.max {
+            return Err(IntegrityIssue::OutOfRange { level, gen });
+        }
+        if let Some((last_key, last_ts)) = &last {
+            // the same key again has to come with an earlier write
+            if *last_key > key || (*last_key == key && *last_ts <= ts) {
+                return Err(IntegrityIssue::Unsorted { level, gen });
+            }
+        }
+        checksum
+            .update(&row.internal_key(), &row.get())
+            .await
+            .map_err(|err| unreadable(level, gen, &err))?;
+        last = Some((key, ts));
+        count += 1;
+    }
+
+    if let Some(expected) = expected_checksum {
+        if !matches!(checksum.take(), Some(actual) if actual.value == expected) {
+            return Err(IntegrityIssue::Checksum { level, gen });
+        }
+    }
+    // manifests written before row counts were recorded hold 0 for every table
+    if scope.rows != 0 && scope.rows != count {
+        return Err(IntegrityIssue::RowCount {
+            level,
+            gen,
+            expected: scope.rows,
+            actual: count,
+        });
+    }
+
+    Ok(count)
+}
+
+// Every read consumes the table, so each of them opens the file
+async fn open<R: Record>(
+    option: &DbOption,
+    ctx: &Context<R>,
+    gen: FileId,
+    level: usize,
+) -> Result<SsTable<R>, IntegrityIssue> {
+    let (fs, path) = ctx.manager.table(option, gen, level);
+    let file = fs
+        .open_options(&path, FileType::Parquet.open_options(true))
+        .await
+        .map_err(|_| IntegrityIssue::MissingTable { level, gen })?;
+    SsTable::open(ctx.parquet_lru.clone(), gen, file)
+        .await
+        .map_err(|err| unreadable(level, gen, &err))
+}
+
+fn unreadable(level: usize, gen: FileId, err: &dyn Display) -> IntegrityIssue {
+    IntegrityIssue::Unreadable {
+        level,
+        gen,
+        reason: err.to_string(),
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use fusio::path::{path_to_local, Path};
+    use tempfile::TempDir;
+
+    use super::IntegrityIssue;
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema,
+        record::test::test_items, tests::Test, DbOption, DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn verify_integrity() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        )
+        .record_checksums(true);
+        let db: DB<Test, TokioExecutor> =
+            DB::new(option.clone(), TokioExecutor::default(), TestSchema)
+                .await
+                .unwrap();
+        for item in test_items(0u32..10) {
+            db.insert(item).await.unwrap();
+        }
+        db.remove("3".to_string()).await.unwrap();
+        db.flush().await.unwrap();
+
+        let report = db.verify_integrity().await;
+        assert!(report.is_ok(), "{report:?}");
+        assert_eq!(report.tables, 1);
+        assert_eq!(report.rows, 11);
+
+        let gen = db.ctx.manifest().current().await.level_slice[0][0].gen;
+        let path = path_to_local(&option.table_path(gen, 0)).unwrap();
+        std::fs::write(&path, b"not a parquet file").unwrap();
+        let report = db.verify_integrity().await;
+        assert!(matches!(
+            report.issues[..],
+            [IntegrityIssue::Unreadable { level: 0, gen: unreadable, .. }] if unreadable == gen
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+        let report = db.verify_integrity().await;
+        assert_eq!(
+            report.issues,
+            vec![IntegrityIssue::MissingTable { level: 0, gen }]
+        );
+        assert_eq!(report.rows, 0);
+    }
+}
@@ -1,23 +1,47 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    sync::Arc,
+};
 
 use flume::{Receiver, Sender};
 
 use crate::{
     fs::{manager::StoreManager, FileId},
     ondisk::sstable::SsTableID,
-    version::timestamp::Timestamp,
     DbOption,
 };
 
+/// Requests to the [`Cleaner`]
+///
+/// Versions are identified by their [`Version::id`](crate::version::Version::id), which grows in
+/// creation order. SSTables that are no longer part of the current version may still be read
+/// through older versions, so they are only removed once the version they were registered with
+/// and every version created before it are dropped.
 pub enum CleanTag {
-    Add { ts: Timestamp, gens: Vec<SsTableID> },
-    Clean { ts: Timestamp },
-    RecoverClean { wal_id: FileId, level: usize },
+    /// SSTables that the version `version_id` and all later versions no longer reference
+    Add {
+        version_id: u64,
+        gens: Vec<SsTableID>,
+    },
+    /// The version `version_id` was dropped
+    Clean {
+        version_id: u64,
+    },
+    RecoverClean {
+        wal_id: FileId,
+        level: usize,
+    },
 }
 
 pub(crate) struct Cleaner {
     tag_recv: Receiver<CleanTag>,
-    gens_map: BTreeMap<Timestamp, (Vec<SsTableID>, bool)>,
+    // SSTables to remove, by the version they were registered with
+    gens_map: BTreeMap<u64, Vec<SsTableID>>,
+    // Dropped versions that are newer than a version that is still alive
+    dropped: BTreeSet<u64>,
+    // Every version older than this one is dropped
+    oldest_alive: u64,
     option: Arc<DbOption>,
     manager: Arc<StoreManager>,
 }
@@ -33,6 +57,8 @@ impl Cleaner {
             Cleaner {
                 tag_recv,
                 gens_map: Default::default(),
+                dropped: Default::default(),
+                oldest_alive: 0,
                 option,
                 manager,
             },
@@ -43,63 +69,97 @@ impl Cleaner {
     pub(crate) async fn listen(&mut self) -> Result<(), fusio::Error> {
         while let Ok(tag) = self.tag_recv.recv_async().await {
             match tag {
-                CleanTag::Add { ts, gens } => {
-                    let _ = self.gens_map.insert(ts, (gens, false));
+                CleanTag::Add { version_id, gens } => {
+                    self.gens_map.entry(version_id).or_default().extend(gens);
+                    // the versions may have been dropped before their tables were registered
+                    self.remove_unreferenced().await?;
                 }
-                CleanTag::Clean { ts: version_num } => {
-                    if let Some((_, dropped)) = self.gens_map.get_mut(&version_num) {
-                        *dropped = true;
-                    }
-                    while let Some((first_version, (gens, dropped))) = self.gens_map.pop_first() {
-                        if !dropped {
-                            let _ = self.gens_map.insert(first_version, (gens, false));
-                            break;
-                        }
-                        for gen in gens {
-                            let fs = self
-                                .option
-                                .level_fs_path(gen.level())
-                                .map(|path| self.manager.get_fs(path))
-                                .unwrap_or(self.manager.base_fs());
-                            fs.remove(&self.option.table_path(gen.file_id(), gen.level()))
-                                .await?;
-                        }
+                CleanTag::Clean { version_id } => {
+                    self.dropped.insert(version_id);
+                    while self.dropped.remove(&self.oldest_alive) {
+                        self.oldest_alive += 1;
                     }
+                    self.remove_unreferenced().await?;
                 }
                 CleanTag::RecoverClean { wal_id: gen, level } => {
-                    let fs = self
-                        .option
-                        .level_fs_path(level)
-                        .map(|path| self.manager.get_fs(path))
-                        .unwrap_or(self.manager.base_fs());
-                    fs.remove(&self.option.table_path(gen, level)).await?;
+                    self.remove_table(gen, level).await?;
                 }
             }
         }
 
         Ok(())
     }
+
+    // Remove the tables of the versions that are dropped along with every older version
+    async fn remove_unreferenced(&mut self) -> Result<(), fusio::Error> {
+        while let Some(entry) = self.gens_map.first_entry() {
+            if *entry.key() >= self.oldest_alive {
+                break;
+            }
+            for gen in entry.remove() {
+                self.remove_table(gen.file_id(), gen.level()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn remove_table(&self, gen: FileId, level: usize) -> Result<(), fusio::Error> {
+        let (fs, path) = self.manager.table(&self.option, gen, level);
+        self.manager.readers().evict(gen);
+        self.manager.readers().footers().evict(gen);
+        match fs.remove(&path).await {
+            // the removal of a table is replayed when the manifest is recovered
+            Err(fusio::Error::Io(err)) if err.kind() == io::ErrorKind::NotFound => (),
+            result => result?,
+        }
+        self.manager.pinned().unpin(gen);
+
+        Ok(())
+    }
 }
 
 #[cfg(all(test, feature = "tokio"))]
 pub(crate) mod tests {
     use std::{
+        ops::{Bound, Range},
         sync::Arc,
         time::{Duration, Instant},
     };
 
     use fusio::path::{path_to_local, Path};
     use fusio_dispatch::FsOptions;
+    use futures_util::StreamExt;
+    use parquet::arrow::ProjectionMask;
+    use parquet_lru::NoCache;
     use tempfile::TempDir;
     use tokio::time::sleep;
     use tracing::error;
 
     use crate::{
+        compaction::{
+            leveled::{LeveledCompactor, LeveledOptions},
+            tests::build_parquet_table,
+            Compactor,
+        },
+        context::Context,
         executor::{tokio::TokioExecutor, Executor},
         fs::{generate_file_id, manager::StoreManager, FileId, FileType},
         inmem::immutable::tests::TestSchema,
+        manifest::ManifestStorage,
         ondisk::sstable::SsTableID,
-        version::cleaner::{CleanTag, Cleaner},
+        record::{KeyRef, Schema},
+        scope::Scope,
+        stream::{memory::MemoryBudget, merge::MergeStream},
+        tests::Test,
+        version::{
+            cleaner::{CleanTag, Cleaner},
+            edit::VersionEdit,
+            hot_range::HotRanges,
+            negative_cache::NegativeCache,
+            set::VersionSet,
+            TransactionTs,
+        },
+        wal::log::LogType,
         DbOption,
     };
 
@@ -179,25 +239,25 @@ pub(crate) mod tests {
         });
 
         tx.send_async(CleanTag::Add {
-            ts: 1.into(),
+            version_id: 1,
             gens: vec![SsTableID::new(gen_1, 0)],
         })
         .await
         .unwrap();
         tx.send_async(CleanTag::Add {
-            ts: 0.into(),
+            version_id: 0,
             gens: vec![SsTableID::new(gen_0, 0)],
         })
         .await
         .unwrap();
         tx.send_async(CleanTag::Add {
-            ts: 2.into(),
+            version_id: 2,
             gens: vec![SsTableID::new(gen_2, 0)],
         })
         .await
         .unwrap();
 
-        tx.send_async(CleanTag::Clean { ts: 2.into() })
+        tx.send_async(CleanTag::Clean { version_id: 2 })
             .await
             .unwrap();
 
@@ -215,7 +275,7 @@ pub(crate) mod tests {
             .unwrap()
             .exists());
 
-        tx.send_async(CleanTag::Clean { ts: 0.into() })
+        tx.send_async(CleanTag::Clean { version_id: 0 })
             .await
             .unwrap();
         // Wait for gen_0 to be removed deterministically.
@@ -230,7 +290,7 @@ pub(crate) mod tests {
             .unwrap()
             .exists());
 
-        tx.send_async(CleanTag::Clean { ts: 1.into() })
+        tx.send_async(CleanTag::Clean { version_id: 1 })
             .await
             .unwrap();
         // Wait for gen_1 and gen_2 to be removed deterministically.
@@ -249,4 +309,173 @@ pub(crate) mod tests {
         // Wait for gen_3 to be removed deterministically.
         wait_removed(&option, gen_3, 0, 2_000).await;
     }
+
+    // Writes a level 0 table with the keys in `keys` and adds it to the current version
+    async fn add_table(ctx: &Context<Test>, option: &DbOption, keys: Range<u32>) -> FileId {
+        let gen = generate_file_id();
+        let records = keys
+            .clone()
+            .map(|i| {
+                (
+                    LogType::Full,
+                    Test {
+                        vstring: format!("{i:02}"),
+                        vu32: i,
+                        vbool: Some(true),
+                    },
+                    0.into(),
+                )
+            })
+            .collect();
+        build_parquet_table::<Test>(
+            option,
+            gen,
+            records,
+            &Arc::new(TestSchema),
+            0,
+            ctx.manager.base_fs(),
+        )
+        .await
+        .unwrap();
+
+        let version = ctx.manifest().current().await;
+        ctx.manifest()
+            .update(
+                vec![
+                    VersionEdit::Add {
+                        level: 0,
+                        scope: Scope {
+                            min: format!("{:02}", keys.start),
+                            max: format!("{:02}", keys.end - 1),
+                            gen,
+                            wal_ids: None,
+                            file_size: 0,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
+                        },
+                    },
+                    VersionEdit::LatestTimeStamp {
+                        ts: version.increase_ts(),
+                    },
+                ],
+                None,
+            )
+            .await
+            .unwrap();
+        gen
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pinned_version_survives_compactions() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(StoreManager::new(FsOptions::Local, vec![]).unwrap());
+        let option = Arc::new(DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        ));
+        manager
+            .base_fs()
+            .create_dir_all(&option.version_log_dir_path())
+            .await
+            .unwrap();
+        manager
+            .base_fs()
+            .create_dir_all(&option.wal_dir_path())
+            .await
+            .unwrap();
+
+        let (mut cleaner, clean_sender) = Cleaner::new(option.clone(), manager.clone());
+        TokioExecutor::default().spawn(async move {
+            if let Err(err) = cleaner.listen().await {
+                error!("[Cleaner Error]: {}", err)
+            }
+        });
+        let manifest = Box::new(
+            VersionSet::<Test, TokioExecutor>::new(clean_sender, option.clone(), manager.clone())
+                .await
+                .unwrap(),
+        );
+        let ctx = Arc::new(Context::new(
+            manager.clone(),
+            Arc::new(NoCache::default()),
+            manifest,
+            TestSchema.arrow_schema().clone(),
+            NegativeCache::new(0),
+            HotRanges::new(0, 0),
+            MemoryBudget::new(None),
+        ));
+        let compactor = LeveledCompactor::<Test>::new(
+            LeveledOptions::default().major_threshold_with_sst_size(2),
+            Arc::new(TestSchema),
+            option.clone(),
+            ctx.clone(),
+        );
+
+        let pinned_gens = [
+            add_table(&ctx, &option, 0..5).await,
+            add_table(&ctx, &option, 5..10).await,
+        ];
+        let pinned = ctx.manifest().current().await;
+
+        let schema = TestSchema;
+        let mut streams = Vec::new();
+        pinned
+            .streams(
+                &ctx,
+                &mut streams,
+                (Bound::Unbounded, Bound::Unbounded),
+                u32::MAX.into(),
+                None,
+                ProjectionMask::all(),
+                None,
+                schema.primary_key_indices(),
+                None,
+                None,
+                &[],
+                ctx.manager.readers(),
+            )
+            .await
+            .unwrap();
+        let mut scan = MergeStream::<Test>::from_vec(streams, u32::MAX.into(), None)
+            .await
+            .unwrap();
+
+        let slow_scan = async {
+            let mut keys = Vec::new();
+            while let Some(entry) = scan.next().await {
+                keys.push(entry.unwrap().key().value.to_key());
+                sleep(Duration::from_millis(20)).await;
+            }
+            keys
+        };
+        let compactions = async {
+            // every round rewrites all tables of the previous round into new ones
+            for _ in 0..3 {
+                add_table(&ctx, &option, 1..10).await;
+                add_table(&ctx, &option, 2..10).await;
+                compactor
+                    .check_then_compaction(None, None, false)
+                    .await
+                    .unwrap();
+            }
+            // gives the cleaner time to act on the removed tables
+            sleep(Duration::from_millis(100)).await;
+            for gen in pinned_gens {
+                assert!(path_to_local(&option.table_path(gen, 0)).unwrap().exists());
+            }
+        };
+        let (keys, _) = futures::join!(slow_scan, compactions);
+
+        assert!(!ctx.manifest().current().await.level_slice[0]
+            .iter()
+            .any(|scope| pinned_gens.contains(&scope.gen)));
+        assert_eq!(keys, (0..10).map(|i| format!("{i:02}")).collect::<Vec<_>>());
+
+        drop(scan);
+        drop(pinned);
+        for gen in pinned_gens {
+            wait_removed(&option, gen, 0, 2_000).await;
+        }
+    }
 }
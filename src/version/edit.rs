@@ -4,14 +4,54 @@ use fusio::{SeqRead, Write};
 use fusio_log::{Decode, Encode, FsOptions, Options, Path};
 use futures_util::TryStreamExt;
 
-use crate::{fs::FileId, record::Key, scope::Scope, version::timestamp::Timestamp};
+use crate::{
+    aggregate::Group,
+    fs::FileId,
+    record::Key,
+    scope::Scope,
+    version::{range_tombstone::RangeTombstone, schema::SchemaVersion, timestamp::Timestamp},
+};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum VersionEdit<K: Key> {
-    Add { level: u8, scope: Scope<K> },
-    Remove { level: u8, gen: FileId },
-    LatestTimeStamp { ts: Timestamp },
-    NewLogLength { len: u32 },
+    Add {
+        level: u8,
+        scope: Scope<K>,
+    },
+    Remove {
+        level: u8,
+        gen: FileId,
+    },
+    LatestTimeStamp {
+        ts: Timestamp,
+    },
+    NewLogLength {
+        len: u32,
+    },
+    DeleteRange {
+        tombstone: RangeTombstone<K>,
+    },
+    NewSchema {
+        schema: SchemaVersion,
+    },
+    NewAggregate {
+        name: String,
+    },
+    /// The state of `group` of the aggregate `name`, which an empty state removes
+    AggregateGroup {
+        name: String,
+        group: K,
+        state: Group,
+    },
+    RemoveAggregate {
+        name: String,
+    },
+    /// Point-in-time recovery to `ts` skipped the later commits of the WALs up to `wal`, the WALs
+    /// after it are replayed in full
+    RecoveredUntil {
+        ts: Timestamp,
+        wal: FileId,
+    },
 }
 
 impl<K> VersionEdit<K>
@@ -65,6 +105,34 @@ where
                 3u8.encode(writer).await?;
                 len.encode(writer).await?;
             }
+            VersionEdit::DeleteRange { tombstone } => {
+                4u8.encode(writer).await?;
+                tombstone.encode(writer).await?;
+            }
+            VersionEdit::NewSchema { schema } => {
+                5u8.encode(writer).await?;
+                schema.encode(writer).await?;
+            }
+            VersionEdit::NewAggregate { name } => {
+                6u8.encode(writer).await?;
+                name.encode(writer).await?;
+            }
+            VersionEdit::AggregateGroup { name, group, state } => {
+                7u8.encode(writer).await?;
+                name.encode(writer).await?;
+                group.encode(writer).await?;
+                state.encode(writer).await?;
+            }
+            VersionEdit::RemoveAggregate { name } => {
+                8u8.encode(writer).await?;
+                name.encode(writer).await?;
+            }
+            VersionEdit::RecoveredUntil { ts, wal } => {
+                9u8.encode(writer).await?;
+                ts.encode(writer).await?;
+                let (result, _) = writer.write_all(&wal.to_bytes()[..]).await;
+                result?;
+            }
         }
 
         Ok(())
@@ -78,6 +146,15 @@ where
                 VersionEdit::Remove { .. } => 16,
                 VersionEdit::LatestTimeStamp { ts } => ts.size(),
                 VersionEdit::NewLogLength { .. } => size_of::<u32>(),
+                VersionEdit::DeleteRange { tombstone } => tombstone.size(),
+                VersionEdit::NewSchema { schema } => schema.size(),
+                VersionEdit::NewAggregate { name } | VersionEdit::RemoveAggregate { name } => {
+                    name.size()
+                }
+                VersionEdit::AggregateGroup { name, group, state } => {
+                    name.size() + group.size() + state.size()
+                }
+                VersionEdit::RecoveredUntil { ts, .. } => ts.size() + 16,
             }
     }
 }
@@ -114,6 +191,38 @@ where
                 let len = u32::decode(reader).await?;
                 VersionEdit::NewLogLength { len }
             }
+            4 => {
+                let tombstone = RangeTombstone::<K>::decode(reader).await?;
+                VersionEdit::DeleteRange { tombstone }
+            }
+            5 => {
+                let schema = SchemaVersion::decode(reader).await?;
+                VersionEdit::NewSchema { schema }
+            }
+            6 => {
+                let name = String::decode(reader).await?;
+                VersionEdit::NewAggregate { name }
+            }
+            7 => {
+                let name = String::decode(reader).await?;
+                let group = K::decode(reader).await?;
+                let state = Group::decode(reader).await?;
+                VersionEdit::AggregateGroup { name, group, state }
+            }
+            8 => {
+                let name = String::decode(reader).await?;
+                VersionEdit::RemoveAggregate { name }
+            }
+            9 => {
+                let ts = Timestamp::decode(reader).await?;
+                let wal = {
+                    let mut buf = [0u8; 16];
+                    let (result, _) = reader.read_exact(&mut buf[..]).await;
+                    result?;
+                    FileId::from_bytes(buf)
+                };
+                VersionEdit::RecoveredUntil { ts, wal }
+            }
             _ => unreachable!(),
         })
     }
@@ -121,12 +230,18 @@ where
 
 #[cfg(test)]
 mod tests {
-    use std::io::Cursor;
+    use std::{io::Cursor, ops::Bound, sync::Arc};
 
+    use arrow::datatypes::{DataType, Field, Schema as ArrowSchema};
     use fusio_log::{Decode, Encode};
     use tokio::io::AsyncSeekExt;
 
-    use crate::{fs::generate_file_id, scope::Scope, version::edit::VersionEdit};
+    use crate::{
+        aggregate::Group,
+        fs::generate_file_id,
+        scope::Scope,
+        version::{edit::VersionEdit, range_tombstone::RangeTombstone, schema::SchemaVersion},
+    };
 
     #[tokio::test]
     async fn encode_and_decode() {
@@ -139,6 +254,9 @@ mod tests {
                     gen: Default::default(),
                     wal_ids: Some(vec![generate_file_id(), generate_file_id()]),
                     file_size: 13,
+                    rows: 0,
+                    tombstones: 0,
+                    expires: None,
                 },
             },
             VersionEdit::Remove {
@@ -147,6 +265,46 @@ mod tests {
             },
             VersionEdit::LatestTimeStamp { ts: 10.into() },
             VersionEdit::NewLogLength { len: 233 },
+            VersionEdit::DeleteRange {
+                tombstone: RangeTombstone {
+                    lower: Bound::Included("a".to_string()),
+                    upper: Bound::Unbounded,
+                    ts: 12.into(),
+                    tables: vec![generate_file_id()],
+                },
+            },
+            VersionEdit::NewSchema {
+                schema: SchemaVersion::new(
+                    1,
+                    Arc::new(ArrowSchema::new(vec![Field::new(
+                        "id",
+                        DataType::Utf8,
+                        false,
+                    )])),
+                    &[0],
+                )
+                .unwrap(),
+            },
+            VersionEdit::NewAggregate {
+                name: "by_letter".to_string(),
+            },
+            VersionEdit::AggregateGroup {
+                name: "by_letter".to_string(),
+                group: "a".to_string(),
+                state: {
+                    let mut state = Group::default();
+                    state.replace(None, Some(Some(-3)));
+                    state.replace(None, Some(None));
+                    state
+                },
+            },
+            VersionEdit::RemoveAggregate {
+                name: "by_letter".to_string(),
+            },
+            VersionEdit::RecoveredUntil {
+                ts: 3.into(),
+                wal: generate_file_id(),
+            },
         ];
 
         let mut buf = Vec::new();
@@ -2,7 +2,10 @@ use flume::SendError;
 use fusio_log::error::LogError;
 use thiserror::Error;
 
-use crate::version::cleaner::CleanTag;
+use crate::{
+    error::{fusio_kind, io_kind, parquet_kind, source_kind, ErrorKind},
+    version::cleaner::CleanTag,
+};
 
 /// Errors for `Version`
 #[derive(Debug, Error)]
@@ -22,3 +25,17 @@ pub enum VersionError {
     #[error("log error: {0}")]
     Logger(#[from] LogError),
 }
+
+impl VersionError {
+    pub(crate) fn kind(&self) -> ErrorKind {
+        match self {
+            VersionError::Encode(err) | VersionError::Fusio(err) => fusio_kind(err),
+            VersionError::Io(err) => io_kind(err),
+            VersionError::Parquet(err) => parquet_kind(err),
+            VersionError::UlidDecode(_) => ErrorKind::Corruption,
+            // the cleaner is gone
+            VersionError::Send(_) => ErrorKind::Io,
+            VersionError::Logger(err) => source_kind(err),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use parquet::arrow::ProjectionMask;
+
+use crate::{
+    context::Context,
+    fs::FileId,
+    record::{Record, Schema},
+    version::{
+        error::VersionError,
+        timestamp::{Timestamp, TsRef},
+        Version, MAX_LEVEL,
+    },
+};
+
+/// Number of keys whose reads are tracked
+pub(crate) const HOT_RANGE_KEY_CAPACITY: usize = 4096;
+
+/// Maximum number of hot keys that are read again from a single rewritten table
+const MAX_WARM_KEYS_PER_TABLE: usize = 64;
+
+/// Read frequency of keys that were looked up in the SSTables
+///
+/// Compactions replace the tables that hold the hottest keys, so the pages of those keys are no
+/// longer in the [`ParquetLru`](crate::ParquetLru) right after a new version is installed. After
+/// every version change the tables of the new version that hold the most reads are warmed by
+/// repeating the lookups of their hot keys, before the first reader pays for the cold pages.
+pub(crate) struct HotRanges<R: Record> {
+    capacity: usize,
+    tables: usize,
+    reads: Mutex<HashMap<<R::Schema as Schema>::Key, u64>>,
+}
+
+impl<R: Record> HotRanges<R> {
+    /// Track up to `capacity` keys and warm up to `tables` tables per version change. Tracking is
+    /// disabled if either is 0.
+    pub(crate) fn new(capacity: usize, tables: usize) -> Self {
+        Self {
+            capacity,
+            tables,
+            reads: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.capacity > 0 && self.tables > 0
+    }
+
+    /// Count a lookup of `key` in the SSTables
+    pub(crate) fn record(&self, key: &<R::Schema as Schema>::Key) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut reads = self.reads.lock().expect("hot range lock should not fail");
+
+        if let Some(count) = reads.get_mut(key) {
+            *count += 1;
+            return;
+        }
+        if reads.len() >= self.capacity {
+            // Age all counts, so keys that were hot a long time ago make room for new ones
+            reads.retain(|_, count| {
+                *count /= 2;
+                *count > 0
+            });
+            if reads.len() >= self.capacity {
+                return;
+            }
+        }
+        reads.insert(key.clone(), 1);
+    }
+
+    /// Hot keys per table of `version` that is not part of `previous`, hottest tables first
+    #[allow(clippy::type_complexity)]
+    fn rewritten_tables(
+        &self,
+        version: &Version<R>,
+        previous: &Version<R>,
+    ) -> Vec<(usize, FileId, Vec<<R::Schema as Schema>::Key>)> {
+        let previous_gens = previous
+            .level_slice
+            .iter()
+            .flatten()
+            .map(|scope| scope.gen)
+            .collect::<HashSet<_>>();
+        let mut tables: HashMap<(usize, FileId), (u64, Vec<(u64, _)>)> = HashMap::new();
+
+        let reads = self.reads.lock().expect("hot range lock should not fail");
+        for (key, count) in reads.iter() {
+            for level in 0..MAX_LEVEL {
+                let scopes = &version.level_slice[level];
+                let candidates = if level == 0 {
+                    &scopes[..]
+                } else if scopes.is_empty() {
+                    continue;
+                } else {
+                    let index = Version::<R>::scope_search(key, scopes);
+                    &scopes[index..index + 1]
+                };
+                for scope in candidates {
+                    if !scope.contains(key) || previous_gens.contains(&scope.gen) {
+                        continue;
+                    }
+                    let (total, keys) = tables.entry((level, scope.gen)).or_default();
+                    *total += count;
+                    keys.push((*count, key.clone()));
+                }
+            }
+        }
+        drop(reads);
+
+        let mut tables = tables.into_iter().collect::<Vec<_>>();
+        tables.sort_by(|(_, (a, _)), (_, (b, _))| b.cmp(a));
+        tables
+            .into_iter()
+            .take(self.tables)
+            .map(|((level, gen), (_, mut keys))| {
+                keys.sort_by(|(a, _), (b, _)| b.cmp(a));
+                keys.truncate(MAX_WARM_KEYS_PER_TABLE);
+                (level, gen, keys.into_iter().map(|(_, key)| key).collect())
+            })
+            .collect()
+    }
+
+    /// Load the pages of the hot keys in the tables that `version` added since `previous` into
+    /// the cache of `ctx`
+    pub(crate) async fn warm(
+        &self,
+        ctx: &Context<R>,
+        version: &Version<R>,
+        previous: &Version<R>,
+        pk_indices: &[usize],
+    ) -> Result<(), VersionError> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        for (level, gen, keys) in self.rewritten_tables(version, previous) {
+            for key in keys.iter() {
+                version
+                    .table_query(
+                        &ctx.manager,
+                        TsRef::new(key, Timestamp::from(u32::MAX)),
+                        level,
+                        gen,
+                        ProjectionMask::all(),
+                        ctx.parquet_lru.clone(),
+                        pk_indices,
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::{atomic::AtomicU32, Arc};
+
+    use flume::bounded;
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::HotRanges;
+    use crate::{
+        fs::generate_file_id, inmem::immutable::tests::TestSchema, scope::Scope, tests::Test,
+        version::Version, DbOption,
+    };
+
+    fn scope(min: &str, max: &str) -> Scope<String> {
+        Scope {
+            min: min.to_string(),
+            max: max.to_string(),
+            gen: generate_file_id(),
+            wal_ids: None,
+            file_size: 0,
+            rows: 0,
+            tombstones: 0,
+            expires: None,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn hottest_rewritten_tables() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = Arc::new(DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        ));
+        let (sender, _) = bounded(1);
+        let mut previous =
+            Version::<Test>::new(option.clone(), sender, Arc::new(AtomicU32::new(0)));
+        let kept = scope("a", "c");
+        previous.level_slice[1].push(kept.clone());
+
+        let mut version = previous.clone();
+        let cold = scope("d", "f");
+        let hot = scope("g", "i");
+        version.level_slice[1].push(cold.clone());
+        version.level_slice[1].push(hot.clone());
+
+        let hot_ranges = HotRanges::<Test>::new(3, 1);
+        for _ in 0..4 {
+            hot_ranges.record(&"b".to_string());
+            hot_ranges.record(&"h".to_string());
+        }
+        hot_ranges.record(&"e".to_string());
+
+        let tables = hot_ranges.rewritten_tables(&version, &previous);
+        assert_eq!(tables, vec![(1, hot.gen, vec!["h".to_string()])]);
+
+        // ages the counts to make room for a new key, which drops "e"
+        hot_ranges.record(&"x".to_string());
+        let reads = hot_ranges.reads.lock().unwrap();
+        assert_eq!(reads.get("h"), Some(&2));
+        assert_eq!(reads.get("e"), None);
+        assert_eq!(reads.get("x"), Some(&1));
+    }
+}
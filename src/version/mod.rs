@@ -1,27 +1,33 @@
 pub(crate) mod cleaner;
 pub mod edit;
 pub(crate) mod error;
+pub(crate) mod hot_range;
+pub(crate) mod negative_cache;
+pub mod range_tombstone;
+pub mod schema;
 pub(crate) mod set;
 pub(crate) mod timestamp;
 
 use std::{
+    collections::BTreeMap,
     ops::Bound,
     sync::{
-        atomic::{AtomicU32, Ordering},
+        atomic::{AtomicU32, AtomicU64, Ordering},
         Arc,
     },
 };
 
 use flume::Sender;
-use fusio::DynFs;
+use futures_util::future::join_all;
 use parquet::arrow::ProjectionMask;
 use tracing::error;
 
 use crate::{
+    aggregate::Groups,
     context::Context,
-    fs::{manager::StoreManager, FileId, FileType},
+    fs::{manager::StoreManager, pool::ReaderPool, FileId},
     ondisk::sstable::SsTable,
-    option::Order,
+    option::{Order, ReadHint},
     record::{Record, Schema},
     scope::Scope,
     stream::{level::LevelStream, record_batch::RecordBatchEntry, ScanStream},
@@ -29,6 +35,8 @@ use crate::{
         cleaner::CleanTag,
         edit::VersionEdit,
         error::VersionError,
+        range_tombstone::RangeTombstone,
+        schema::SchemaVersion,
         timestamp::{Timestamp, TsRef},
     },
     DbOption, ParquetLru,
@@ -45,15 +53,35 @@ pub trait TransactionTs {
 }
 
 /// Tracks the current metadata of the `DB`
+///
+/// A version is an immutable view of the SSTables, and every scan or point lookup reads from the
+/// version it started on. The SSTables that later compactions remove from the current version are
+/// only deleted by the cleaner once this version and all versions created before it are dropped, so
+/// holding a version keeps all of its SSTables readable no matter how many compactions run
+/// meanwhile.
 #[derive(Debug)]
 pub struct Version<R>
 where
     R: Record,
 {
-    // Timestamp at creation
-    ts: Timestamp,
+    // Id of the version, unique among the versions of a `VersionSet` and increasing in creation
+    // order. Every id is handed out to exactly one version, which reports it to the cleaner when
+    // dropped.
+    id: u64,
+    // Allocates the ids of the versions sharing the counter
+    ids: Arc<AtomicU64>,
     // Holds the SSTable file ids and their min/max values for every level
     pub level_slice: [Vec<Scope<<R::Schema as Schema>::Key>>; MAX_LEVEL],
+    // Removals of key ranges that still hide records of SSTables, oldest first
+    pub(crate) range_tombstones: Vec<RangeTombstone<<R::Schema as Schema>::Key>>,
+    // Groups of the aggregates of `DbOption::aggregate` by name, counting the records of the
+    // SSTables
+    pub(crate) aggregates: Arc<BTreeMap<String, Groups<<R::Schema as Schema>::Key>>>,
+    // Schema the SSTables are read as, `None` until the DB was opened with one
+    pub(crate) schema: Option<SchemaVersion>,
+    // Target timestamp of the point-in-time recovery that was applied and the latest WAL it
+    // replayed, see `DbOption::recover_until`
+    pub(crate) recovered_until: Option<(Timestamp, FileId)>,
     clean_sender: Sender<CleanTag>,
     option: Arc<DbOption>,
     timestamp: Arc<AtomicU32>,
@@ -72,9 +100,16 @@ where
         clean_sender: Sender<CleanTag>,
         timestamp: Arc<AtomicU32>,
     ) -> Self {
+        let ids = Arc::new(AtomicU64::new(0));
+
         Version {
-            ts: Timestamp::from(0),
+            id: ids.fetch_add(1, Ordering::Relaxed),
+            ids,
             level_slice: [const { Vec::new() }; MAX_LEVEL],
+            range_tombstones: Vec::new(),
+            aggregates: Default::default(),
+            schema: None,
+            recovered_until: None,
             clean_sender,
             option: option.clone(),
             timestamp,
@@ -86,6 +121,28 @@ where
     pub(crate) fn option(&self) -> &Arc<DbOption> {
         &self.option
     }
+
+    /// Id of the version, see [`CleanTag`]
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Schema the SSTables of the version are read as
+    pub fn schema(&self) -> Option<&SchemaVersion> {
+        self.schema.as_ref()
+    }
+
+    // Arrow schema of `Version::schema`, which the tables written with other columns are read as
+    pub(crate) fn arrow_schema(&self) -> Option<Arc<arrow::datatypes::Schema>> {
+        self.schema.as_ref().map(|schema| schema.schema().clone())
+    }
+
+    // Arrow schema new tables are written with, the one of `Version::schema` with the field ids
+    // of its columns, or the one of `schema` if none is stored
+    pub(crate) fn table_schema(&self, schema: &R::Schema) -> Arc<arrow::datatypes::Schema> {
+        self.arrow_schema()
+            .unwrap_or_else(|| schema.arrow_schema().clone())
+    }
 }
 
 // Handles Timestamp operations for `Version`
@@ -114,8 +171,13 @@ where
         }
 
         Self {
-            ts: self.ts,
+            id: self.ids.fetch_add(1, Ordering::Relaxed),
+            ids: self.ids.clone(),
             level_slice,
+            range_tombstones: self.range_tombstones.clone(),
+            aggregates: self.aggregates.clone(),
+            schema: self.schema.clone(),
+            recovered_until: self.recovered_until,
             clean_sender: self.clean_sender.clone(),
             option: self.option.clone(),
             timestamp: self.timestamp.clone(),
@@ -137,12 +199,6 @@ where
         parquet_lru: ParquetLru,
         pk_indices: &[usize],
     ) -> Result<Option<RecordBatchEntry<R>>, VersionError> {
-        let level_0_path = self
-            .option
-            .level_fs_path(0)
-            .unwrap_or(&self.option.base_path);
-        let level_0_fs = manager.get_fs(level_0_path);
-
         // For level 0, check if the scope contains the key. If found we do a query into the level
         for scope in self.level_slice[0].iter().rev() {
             if !scope.contains(key.value()) {
@@ -150,7 +206,7 @@ where
             }
             if let Some(entry) = self
                 .table_query(
-                    level_0_fs,
+                    manager,
                     key,
                     0,
                     scope.gen,
@@ -170,11 +226,6 @@ where
                 continue;
             }
             let level = i + 1;
-            let level_path = self
-                .option
-                .level_fs_path(level)
-                .unwrap_or(&self.option.base_path);
-            let level_fs = manager.get_fs(level_path);
 
             let index = Self::scope_search(key.value(), sort_runs);
             if !sort_runs[index].contains(key.value()) {
@@ -182,7 +233,7 @@ where
             }
             if let Some(entry) = self
                 .table_query(
-                    level_fs,
+                    manager,
                     key,
                     level,
                     sort_runs[index].gen,
@@ -199,11 +250,88 @@ where
         Ok(None)
     }
 
-    // Opens the file by `FileId` and does a get operation on the SsTable
+    /// Queries for the gets of `keys` at `ts`, with the entries in the order of `keys`
+    ///
+    /// The keys are grouped by the tables they fall into and the gets of a level run at the same
+    /// time, so a lookup of many keys costs one round of reads per level, or per table of level
+    /// 0, rather than one per key. The gets of a table share the footer and the readers of the
+    /// [`ReaderPool`].
+    pub(crate) async fn query_many(
+        &self,
+        manager: &StoreManager,
+        keys: &[&<R::Schema as Schema>::Key],
+        ts: Timestamp,
+        projection_mask: ProjectionMask,
+        parquet_lru: ParquetLru,
+        pk_indices: &[usize],
+    ) -> Result<Vec<Option<RecordBatchEntry<R>>>, VersionError> {
+        let mut entries = keys.iter().map(|_| None).collect::<Vec<_>>();
+        let mut pending = (0..keys.len()).collect::<Vec<_>>();
+        let (projection_mask, parquet_lru) = (&projection_mask, &parquet_lru);
+
+        // tables of level 0 may overlap, so the newer ones are read first
+        let level_0 = self.level_slice[0]
+            .iter()
+            .rev()
+            .map(|scope| (0, std::slice::from_ref(scope)));
+        let levels = self.level_slice[1..MAX_LEVEL]
+            .iter()
+            .enumerate()
+            .map(|(i, scopes)| (i + 1, scopes.as_slice()));
+        for (level, scopes) in level_0.chain(levels) {
+            if pending.is_empty() {
+                break;
+            }
+            if scopes.is_empty() {
+                continue;
+            }
+            let gets = pending
+                .iter()
+                .filter_map(|&index| {
+                    let key = keys[index];
+                    let scope = &scopes[Self::scope_search(key, scopes)];
+                    scope.contains(key).then(|| async move {
+                        let entry = self
+                            .table_query(
+                                manager,
+                                TsRef::new(key, ts),
+                                level,
+                                scope.gen,
+                                projection_mask.clone(),
+                                parquet_lru.clone(),
+                                pk_indices,
+                            )
+                            .await?;
+                        Ok::<_, VersionError>((index, entry))
+                    })
+                })
+                .collect::<Vec<_>>();
+            for result in join_all(gets).await {
+                if let (index, Some(entry)) = result? {
+                    entries[index] = Some(entry);
+                }
+            }
+            pending.retain(|&index| entries[index].is_none());
+        }
+
+        Ok(entries)
+    }
+
+    /// Whether the key range of any SSTable a get of `key` reads contains it
+    pub(crate) fn may_contain(&self, key: &<R::Schema as Schema>::Key) -> bool {
+        self.level_slice[0].iter().any(|scope| scope.contains(key))
+            || self.level_slice[1..MAX_LEVEL]
+                .iter()
+                .filter(|sort_runs| !sort_runs.is_empty())
+                .any(|sort_runs| sort_runs[Self::scope_search(key, sort_runs)].contains(key))
+    }
+
+    // Takes a reader of the table `gen` from the reader pool and does a get operation on the
+    // SsTable
     #[allow(clippy::too_many_arguments)]
     async fn table_query(
         &self,
-        store: &Arc<dyn DynFs>,
+        manager: &StoreManager,
         key: &TsRef<<R::Schema as Schema>::Key>,
         level: usize,
         gen: FileId,
@@ -211,15 +339,16 @@ where
         parquet_lru: ParquetLru,
         pk_indices: &[usize],
     ) -> Result<Option<RecordBatchEntry<R>>, VersionError> {
-        let file = store
-            .open_options(
-                &self.option.table_path(gen, level),
-                FileType::Parquet.open_options(true),
-            )
+        let (fs, path) = manager.table(&self.option, gen, level);
+        let reader = manager
+            .readers()
+            .open(fs, &path, gen)
             .await
             .map_err(VersionError::Fusio)?;
-        SsTable::<R>::open(parquet_lru, gen, file)
-            .await?
+        SsTable::<R>::from_reader(parquet_lru, gen, reader)
+            .await
+            .bloom_filters(self.option.bloom_filters)
+            .schema(self.arrow_schema())
             .get(key, projection_mask, pk_indices)
             .await
             .map_err(VersionError::Parquet)
@@ -235,6 +364,32 @@ where
             .unwrap_or_else(|index| index.saturating_sub(1))
     }
 
+    /// Index of the first and the last table of a level below level 0 whose key range meets
+    /// `range`, found by binary search as the tables are sorted by key and do not overlap
+    pub(crate) fn tables_in_range(
+        scopes: &[Scope<<R::Schema as Schema>::Key>],
+        range: (
+            Bound<&<R::Schema as Schema>::Key>,
+            Bound<&<R::Schema as Schema>::Key>,
+        ),
+    ) -> Option<(usize, usize)> {
+        let start = scopes.partition_point(|scope| match range.0 {
+            Bound::Included(lower) => &scope.max < lower,
+            Bound::Excluded(lower) => &scope.max <= lower,
+            Bound::Unbounded => false,
+        });
+        let end = scopes.partition_point(|scope| match range.1 {
+            Bound::Included(upper) => &scope.min <= upper,
+            Bound::Excluded(upper) => &scope.min < upper,
+            Bound::Unbounded => true,
+        });
+        if start < end && scopes[start].meets_range(range) {
+            Some((start, end - 1))
+        } else {
+            None
+        }
+    }
+
     /// Returns the length of a level
     pub fn tables_len(&self, level: usize) -> usize {
         self.level_slice[level].len()
@@ -255,25 +410,26 @@ where
         projection_mask: ProjectionMask,
         order: Option<Order>,
         pk_indices: &'streams [usize],
+        read_hint: Option<ReadHint>,
+        prefix: Option<Arc<[u8]>>,
+        skip: &[FileId],
+        readers: &ReaderPool,
     ) -> Result<(), VersionError> {
-        let level_0_path = self
-            .option
-            .level_fs_path(0)
-            .unwrap_or(&self.option.base_path);
-        let level_0_fs = ctx.manager.get_fs(level_0_path);
-
         for scope in self.level_slice[0].iter() {
-            if !scope.meets_range(range) {
+            if !scope.meets_range(range) || skip.contains(&scope.gen) {
                 continue;
             }
-            let file = level_0_fs
-                .open_options(
-                    &self.option.table_path(scope.gen, 0),
-                    FileType::Parquet.open_options(true),
-                )
+            let (fs, path) = ctx.manager.table(&self.option, scope.gen, 0);
+            let reader = readers
+                .open(fs, &path, scope.gen)
                 .await
                 .map_err(VersionError::Fusio)?;
-            let table = SsTable::open(ctx.parquet_lru.clone(), scope.gen, file).await?;
+            let table = SsTable::from_reader(ctx.parquet_lru.clone(), scope.gen, reader)
+                .await
+                .read_hint(read_hint)
+                .memory_budget(Some(ctx.scan_memory().clone()))
+                .prefix(prefix.clone())
+                .schema(self.arrow_schema());
 
             streams.push(ScanStream::SsTable {
                 inner: table
@@ -287,44 +443,42 @@ where
             if scopes.is_empty() {
                 continue;
             }
-            let level_path = self
-                .option
-                .level_fs_path(i + 1)
-                .unwrap_or(&self.option.base_path);
-            let level_fs = ctx.manager.get_fs(level_path);
-
-            let (mut start, mut end) = (None, None);
-
-            for (idx, scope) in scopes.iter().enumerate() {
-                if scope.meets_range(range) {
-                    if start.is_none() {
-                        start = Some(idx);
-                    }
-                    end = Some(idx);
-                }
-            }
-            if start.is_none() {
+            let Some((start, end)) = Self::tables_in_range(scopes, range) else {
                 continue;
-            }
+            };
 
-            streams.push(ScanStream::Level {
-                // SAFETY: checked scopes no empty
-                inner: LevelStream::new(
-                    self,
-                    i + 1,
-                    start.unwrap(),
-                    end.unwrap(),
-                    range,
-                    ts,
-                    limit,
-                    projection_mask.clone(),
-                    level_fs.clone(),
-                    ctx.parquet_lru.clone(),
-                    order,
-                    pk_indices,
-                )
-                .unwrap(),
-            });
+            // one stream for each run of the tables in range that are not skipped
+            let mut run_start = start;
+            for index in start..=end + 1 {
+                if index <= end && !skip.contains(&scopes[index].gen) {
+                    continue;
+                }
+                if run_start < index {
+                    streams.push(ScanStream::Level {
+                        // SAFETY: checked the run is not empty
+                        inner: LevelStream::new(
+                            self,
+                            i + 1,
+                            run_start,
+                            index - 1,
+                            range,
+                            ts,
+                            limit,
+                            projection_mask.clone(),
+                            ctx.manager.clone(),
+                            ctx.parquet_lru.clone(),
+                            order,
+                            pk_indices,
+                        )
+                        .unwrap()
+                        .read_hint(read_hint)
+                        .memory_budget(Some(ctx.scan_memory().clone()))
+                        .prefix(prefix.clone())
+                        .readers(readers.clone()),
+                    });
+                }
+                run_start = index + 1;
+            }
         }
         Ok(())
     }
@@ -341,6 +495,29 @@ where
                 })
             }
         }
+        for tombstone in &self.range_tombstones {
+            edits.push(VersionEdit::DeleteRange {
+                tombstone: tombstone.clone(),
+            });
+        }
+        for (name, groups) in self.aggregates.iter() {
+            edits.push(VersionEdit::NewAggregate { name: name.clone() });
+            for (group, state) in groups {
+                edits.push(VersionEdit::AggregateGroup {
+                    name: name.clone(),
+                    group: group.clone(),
+                    state: state.clone(),
+                });
+            }
+        }
+        if let Some(schema) = &self.schema {
+            edits.push(VersionEdit::NewSchema {
+                schema: schema.clone(),
+            });
+        }
+        if let Some((ts, wal)) = self.recovered_until {
+            edits.push(VersionEdit::RecoveredUntil { ts, wal });
+        }
         edits.push(VersionEdit::LatestTimeStamp { ts: self.load_ts() });
         edits.push(VersionEdit::NewLogLength { len: 0 });
         edits
@@ -352,7 +529,9 @@ where
     R: Record,
 {
     fn drop(&mut self) {
-        if let Err(err) = self.clean_sender.send(CleanTag::Clean { ts: self.ts }) {
+        if let Err(err) = self.clean_sender.send(CleanTag::Clean {
+            version_id: self.id,
+        }) {
             error!("[Version Drop Error]: {}", err)
         }
     }
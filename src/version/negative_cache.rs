@@ -0,0 +1,173 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    mem::size_of,
+    sync::{Arc, Mutex, Weak},
+};
+
+use fusio_log::Encode;
+
+use crate::{
+    record::{Record, Schema},
+    version::{timestamp::Timestamp, Version, VersionRef},
+};
+
+/// Cache of point lookups that found no record in the SSTables of a [`Version`]
+///
+/// SSTables never change within a version, so a key that is absent as of some timestamp stays
+/// absent for every lookup at or before that timestamp until the version is replaced. Writes to
+/// the key are served by the memtables, which are checked before the cache. The cache only keeps
+/// misses of the latest version it has seen and is emptied once another version shows up.
+pub(crate) struct NegativeCache<R: Record> {
+    capacity: usize,
+    inner: Mutex<NegativeCacheInner<R>>,
+}
+
+struct NegativeCacheInner<R: Record> {
+    // Not upgraded, only used to tell versions apart. Keeping the allocation alive makes sure a
+    // new version never reuses the address of the cached one.
+    version: Weak<Version<R>>,
+    absent: HashMap<<R::Schema as Schema>::Key, Timestamp>,
+    // Insertion order for evicting the oldest miss
+    order: VecDeque<<R::Schema as Schema>::Key>,
+}
+
+impl<R: Record> NegativeCache<R> {
+    /// Create a cache of up to `capacity` keys, a capacity of 0 disables it
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::new(NegativeCacheInner {
+                version: Weak::new(),
+                absent: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Whether `key` is known to have no record as of `ts` in the SSTables of `version`
+    pub(crate) fn is_absent(
+        &self,
+        version: &VersionRef<R>,
+        key: &<R::Schema as Schema>::Key,
+        ts: Timestamp,
+    ) -> bool {
+        if self.capacity == 0 {
+            return false;
+        }
+        let inner = self
+            .inner
+            .lock()
+            .expect("negative cache lock should not fail");
+
+        inner.version.as_ptr() == Arc::as_ptr(version)
+            && inner
+                .absent
+                .get(key)
+                .is_some_and(|absent_ts| ts <= *absent_ts)
+    }
+
+    /// Record that the SSTables of `version` have no record of `key` as of `ts`
+    pub(crate) fn insert(
+        &self,
+        version: &VersionRef<R>,
+        key: &<R::Schema as Schema>::Key,
+        ts: Timestamp,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("negative cache lock should not fail");
+
+        if inner.version.as_ptr() != Arc::as_ptr(version) {
+            inner.version = Arc::downgrade(version);
+            inner.absent.clear();
+            inner.order.clear();
+        }
+        if let Some(absent_ts) = inner.absent.get_mut(key) {
+            *absent_ts = (*absent_ts).max(ts);
+            return;
+        }
+        if inner.absent.len() >= self.capacity {
+            if let Some(oldest) = inner.order.pop_front() {
+                inner.absent.remove(&oldest);
+            }
+        }
+        inner.order.push_back(key.clone());
+        inner.absent.insert(key.clone(), ts);
+    }
+
+    /// Approximate bytes of the cached keys, which are held twice for the eviction order
+    pub(crate) fn memory_size(&self) -> usize {
+        let inner = self
+            .inner
+            .lock()
+            .expect("negative cache lock should not fail");
+
+        inner
+            .order
+            .iter()
+            .map(|key| {
+                2 * (size_of::<<R::Schema as Schema>::Key>() + key.size()) + size_of::<Timestamp>()
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::AtomicU32, Arc};
+
+    use flume::bounded;
+    use fusio::path::Path;
+    use tempfile::TempDir;
+
+    use super::NegativeCache;
+    use crate::{inmem::immutable::tests::TestSchema, tests::Test, version::Version, DbOption};
+
+    fn version(option: &Arc<DbOption>) -> Arc<Version<Test>> {
+        let (sender, _) = bounded(1);
+        Arc::new(Version::new(
+            option.clone(),
+            sender,
+            Arc::new(AtomicU32::default()),
+        ))
+    }
+
+    #[test]
+    fn absent_until_version_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = Arc::new(DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        ));
+        let cache = NegativeCache::<Test>::new(2);
+        let first = version(&option);
+        let key = "a".to_string();
+
+        assert!(!cache.is_absent(&first, &key, 5.into()));
+        cache.insert(&first, &key, 5.into());
+        assert!(cache.is_absent(&first, &key, 5.into()));
+        assert!(cache.is_absent(&first, &key, 3.into()));
+        // a later read may see records written after the miss
+        assert!(!cache.is_absent(&first, &key, 6.into()));
+
+        // the oldest miss is evicted at capacity
+        cache.insert(&first, &"b".to_string(), 5.into());
+        cache.insert(&first, &"c".to_string(), 5.into());
+        assert!(!cache.is_absent(&first, &key, 5.into()));
+        assert!(cache.is_absent(&first, &"c".to_string(), 5.into()));
+
+        let second = version(&option);
+        assert!(!cache.is_absent(&second, &"c".to_string(), 5.into()));
+        cache.insert(&second, &key, 5.into());
+        assert!(!cache.is_absent(&first, &key, 5.into()));
+        assert!(cache.is_absent(&second, &key, 5.into()));
+
+        let disabled = NegativeCache::<Test>::new(0);
+        disabled.insert(&second, &key, 5.into());
+        assert!(!disabled.is_absent(&second, &key, 5.into()));
+    }
+}
@@ -0,0 +1,123 @@
+use std::ops::{Bound, RangeBounds};
+
+use fusio::{SeqRead, Write};
+use fusio_log::{Decode, Encode};
+
+use crate::{fs::FileId, record::Key, version::timestamp::Timestamp};
+
+/// A removal of every key in a range, see [`DB::delete_range`](crate::DB::delete_range)
+///
+/// The tombstone hides the writes of its keys at or before its timestamp in the SSTables it
+/// lists, which held keys of the range when it was written, and compactions drop the records it
+/// hides. Once none of the tables is left in the version, the tombstone has nothing left to hide
+/// and is dropped as well. The memtables of the range got a removal of each of their keys
+/// instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeTombstone<K: Key> {
+    pub lower: Bound<K>,
+    pub upper: Bound<K>,
+    pub ts: Timestamp,
+    pub tables: Vec<FileId>,
+}
+
+impl<K> RangeTombstone<K>
+where
+    K: Key,
+{
+    pub fn contains(&self, key: &K) -> bool {
+        (self.lower.as_ref(), self.upper.as_ref()).contains(key)
+    }
+
+    /// Whether the tombstone hides the write of `key` at `ts` from a read at `read_ts`
+    pub(crate) fn hides(&self, key: &K, ts: Timestamp, read_ts: Timestamp) -> bool {
+        self.ts <= read_ts && ts <= self.ts && self.contains(key)
+    }
+}
+
+impl<K> Encode for RangeTombstone<K>
+where
+    K: Key,
+{
+    async fn encode<W>(&self, writer: &mut W) -> Result<(), fusio::Error>
+    where
+        W: Write,
+    {
+        encode_bound(&self.lower, writer).await?;
+        encode_bound(&self.upper, writer).await?;
+        self.ts.encode(writer).await?;
+        (self.tables.len() as u32).encode(writer).await?;
+        for gen in &self.tables {
+            let (result, _) = writer.write_all(&gen.to_bytes()[..]).await;
+            result?;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        let bound_size = |bound: &Bound<K>| match bound {
+            Bound::Included(key) | Bound::Excluded(key) => size_of::<u8>() + key.size(),
+            Bound::Unbounded => size_of::<u8>(),
+        };
+        bound_size(&self.lower)
+            + bound_size(&self.upper)
+            + self.ts.size()
+            + size_of::<u32>()
+            + 16 * self.tables.len()
+    }
+}
+
+impl<K> Decode for RangeTombstone<K>
+where
+    K: Key,
+{
+    async fn decode<R: SeqRead>(reader: &mut R) -> Result<Self, fusio::Error> {
+        let lower = decode_bound(reader).await?;
+        let upper = decode_bound(reader).await?;
+        let ts = Timestamp::decode(reader).await?;
+        let len = u32::decode(reader).await? as usize;
+        let mut tables = Vec::with_capacity(len);
+        for _ in 0..len {
+            let mut buf = [0u8; 16];
+            let (result, _) = reader.read_exact(&mut buf[..]).await;
+            result?;
+            tables.push(FileId::from_bytes(buf));
+        }
+
+        Ok(RangeTombstone {
+            lower,
+            upper,
+            ts,
+            tables,
+        })
+    }
+}
+
+async fn encode_bound<K, W>(bound: &Bound<K>, writer: &mut W) -> Result<(), fusio::Error>
+where
+    K: Key,
+    W: Write,
+{
+    match bound {
+        Bound::Unbounded => 0u8.encode(writer).await,
+        Bound::Included(key) => {
+            1u8.encode(writer).await?;
+            key.encode(writer).await
+        }
+        Bound::Excluded(key) => {
+            2u8.encode(writer).await?;
+            key.encode(writer).await
+        }
+    }
+}
+
+async fn decode_bound<K, R>(reader: &mut R) -> Result<Bound<K>, fusio::Error>
+where
+    K: Key,
+    R: SeqRead,
+{
+    Ok(match u8::decode(reader).await? {
+        0 => Bound::Unbounded,
+        1 => Bound::Included(K::decode(reader).await?),
+        _ => Bound::Excluded(K::decode(reader).await?),
+    })
+}
@@ -0,0 +1,573 @@
+use std::sync::Arc;
+
+use arrow::{
+    datatypes::{Field, Schema as ArrowSchema},
+    error::ArrowError,
+    ipc::{reader::StreamReader, writer::StreamWriter},
+};
+use fusio::{SeqRead, Write};
+use fusio_log::{Decode, Encode};
+use parquet::arrow::PARQUET_FIELD_ID_META_KEY;
+use thiserror::Error;
+
+// Key of the schema metadata that holds the field id of the next added column, as the ids of
+// dropped columns are never given to another column
+const NEXT_FIELD_ID_KEY: &str = "tonbo:next_field_id";
+// Key of the schema metadata that holds the field ids of the columns of the primary key
+const PRIMARY_KEY_KEY: &str = "tonbo:primary_key";
+
+/// Schema the SSTables of a DB are read as, persisted in the version log
+///
+/// Each column has a field id, stored as the `PARQUET:field_id` metadata of its field, which the
+/// SSTables written with the schema keep in their Parquet schema. Columns of tables are matched
+/// by these ids, so a column can be renamed or dropped without rewriting the tables, see
+/// [`DbOption::rename_column`](crate::DbOption::rename_column) and
+/// [`DbOption::drop_column`](crate::DbOption::drop_column). Tables written before a column was
+/// added read it as nulls, and compactions rewrite tables with the columns of the schema, which
+/// removes the dropped ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaVersion {
+    version: u32,
+    schema: Arc<ArrowSchema>,
+    next_field_id: u32,
+    // the schema as an Arrow IPC stream, which is what the version log stores
+    encoded: Vec<u8>,
+}
+
+impl SchemaVersion {
+    /// Version `version` of `schema` with the primary key of the columns at `primary_key`,
+    /// whose fields without a field id get the id of their position, as do the columns of
+    /// tables written without field ids
+    pub(crate) fn new(
+        version: u32,
+        schema: Arc<ArrowSchema>,
+        primary_key: &[usize],
+    ) -> Result<Self, ArrowError> {
+        Self::from_schema(version, schema, Some(primary_key))
+    }
+
+    fn from_schema(
+        version: u32,
+        schema: Arc<ArrowSchema>,
+        primary_key: Option<&[usize]>,
+    ) -> Result<Self, ArrowError> {
+        let ids = schema
+            .fields()
+            .iter()
+            .enumerate()
+            .map(|(i, field)| field_id(field).unwrap_or(i as u32))
+            .collect::<Vec<_>>();
+        let next_field_id = schema
+            .metadata()
+            .get(NEXT_FIELD_ID_KEY)
+            .and_then(|id| id.parse().ok())
+            .unwrap_or_else(|| ids.iter().max().map_or(0, |id| id + 1));
+
+        Self::with_field_ids(version, &schema, &ids, next_field_id, primary_key)
+    }
+
+    fn with_field_ids(
+        version: u32,
+        schema: &ArrowSchema,
+        ids: &[u32],
+        next_field_id: u32,
+        primary_key: Option<&[usize]>,
+    ) -> Result<Self, ArrowError> {
+        let fields = schema
+            .fields()
+            .iter()
+            .zip(ids)
+            .map(|(field, id)| {
+                let mut metadata = field.metadata().clone();
+                metadata.insert(PARQUET_FIELD_ID_META_KEY.to_string(), id.to_string());
+                field.as_ref().clone().with_metadata(metadata)
+            })
+            .collect::<Vec<_>>();
+        let mut metadata = schema.metadata().clone();
+        metadata.insert(NEXT_FIELD_ID_KEY.to_string(), next_field_id.to_string());
+        if let Some(primary_key) = primary_key {
+            let primary_key = primary_key
+                .iter()
+                .map(|index| ids[*index].to_string())
+                .collect::<Vec<_>>();
+            metadata.insert(PRIMARY_KEY_KEY.to_string(), primary_key.join(","));
+        }
+        let schema = Arc::new(ArrowSchema::new_with_metadata(fields, metadata));
+
+        let mut writer = StreamWriter::try_new(Vec::new(), &schema)?;
+        writer.finish()?;
+        let encoded = writer.into_inner()?;
+
+        Ok(Self {
+            version,
+            schema,
+            next_field_id,
+            encoded,
+        })
+    }
+
+    /// Number of the schema, which starts at 0 and grows by 1 with each change
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Arrow schema, including the `_null` and `_ts` columns, with the field id of each column
+    pub fn schema(&self) -> &Arc<ArrowSchema> {
+        &self.schema
+    }
+
+    // Field ids of the columns of the primary key, `None` if the schema was stored before the
+    // primary key was
+    fn primary_key(&self) -> Option<Vec<u32>> {
+        self.schema
+            .metadata()
+            .get(PRIMARY_KEY_KEY)?
+            .split(',')
+            .map(|id| id.parse().ok())
+            .collect()
+    }
+
+    /// The version of `schema`, with the primary key of the columns at `primary_key`, when a DB
+    /// of this version is opened with it and `changes`, `None` if it is the schema of this
+    /// version
+    ///
+    /// A change only applies while the stored schema has the column it changes and `schema`
+    /// does not, so the changes may be given each time the DB is opened.
+    pub(crate) fn evolve(
+        &self,
+        schema: &Arc<ArrowSchema>,
+        primary_key: &[usize],
+        changes: &[ColumnChange],
+    ) -> Result<Option<SchemaVersion>, SchemaMismatch> {
+        let mut stored = self
+            .ids()
+            .into_iter()
+            .zip(
+                self.schema
+                    .fields()
+                    .iter()
+                    .map(|field| field.as_ref().clone()),
+            )
+            .collect::<Vec<_>>();
+        let position = |stored: &[(u32, Field)], name: &str| {
+            stored.iter().position(|(_, field)| field.name() == name)
+        };
+        let mut changed = false;
+        for change in changes {
+            match change {
+                ColumnChange::Rename { from, to } => {
+                    let Some(i) = position(&stored, from) else {
+                        continue;
+                    };
+                    if schema.field_with_name(from).is_ok() {
+                        continue;
+                    }
+                    if position(&stored, to).is_some() {
+                        return Err(SchemaMismatch::DuplicateColumn(to.clone()));
+                    }
+                    stored[i].1 = stored[i].1.clone().with_name(to);
+                    changed = true;
+                }
+                ColumnChange::Drop(name) => {
+                    let Some(i) = position(&stored, name) else {
+                        continue;
+                    };
+                    if schema.field_with_name(name).is_ok() {
+                        continue;
+                    }
+                    stored.remove(i);
+                    changed = true;
+                }
+            }
+        }
+
+        let fields = schema.fields();
+        for (i, (_, field)) in stored.iter().enumerate() {
+            let new = fields.get(i).filter(|new| new.name() == field.name());
+            let Some(new) = new else {
+                return Err(
+                    match (fields.get(i), schema.field_with_name(field.name())) {
+                        (Some(found), Ok(_)) => SchemaMismatch::MovedColumn {
+                            name: field.name().clone(),
+                            found: found.name().clone(),
+                        },
+                        _ => SchemaMismatch::MissingColumn(field.name().clone()),
+                    },
+                );
+            };
+            if new.data_type() != field.data_type() || new.is_nullable() != field.is_nullable() {
+                return Err(SchemaMismatch::ChangedColumn {
+                    name: field.name().clone(),
+                    stored: describe(field),
+                    found: describe(new),
+                });
+            }
+        }
+        if let Some(field) = fields[stored.len()..]
+            .iter()
+            .find(|field| !field.is_nullable())
+        {
+            return Err(SchemaMismatch::NotNullable(field.name().clone()));
+        }
+
+        let added = (fields.len() - stored.len()) as u32;
+        let ids = stored
+            .iter()
+            .map(|(id, _)| *id)
+            .chain(self.next_field_id..self.next_field_id + added)
+            .collect::<Vec<_>>();
+        let stored_key = self.primary_key();
+        if let Some(stored_key) = &stored_key {
+            if primary_key
+                .iter()
+                .map(|index| ids[*index])
+                .ne(stored_key.iter().copied())
+            {
+                let names = |schema: &ArrowSchema, ids: &[u32], key: &[u32]| {
+                    key.iter()
+                        .filter_map(|id| ids.iter().position(|column| column == id))
+                        .map(|index| schema.field(index).name().clone())
+                        .collect()
+                };
+                return Err(SchemaMismatch::PrimaryKey {
+                    stored: names(&self.schema, &self.ids(), stored_key),
+                    found: primary_key
+                        .iter()
+                        .map(|index| fields[*index].name().clone())
+                        .collect(),
+                });
+            }
+        }
+        let changed = changed || added > 0;
+        if !changed && stored_key.is_some() {
+            return Ok(None);
+        }
+
+        // a schema stored before its primary key only records it, without a new version
+        let version = if changed {
+            self.version + 1
+        } else {
+            self.version
+        };
+        SchemaVersion::with_field_ids(
+            version,
+            schema,
+            &ids,
+            self.next_field_id + added,
+            Some(primary_key),
+        )
+        .map(Some)
+        .map_err(SchemaMismatch::Arrow)
+    }
+
+    // Field id of each column
+    fn ids(&self) -> Vec<u32> {
+        self.schema
+            .fields()
+            .iter()
+            .enumerate()
+            .map(|(i, field)| field_id(field).unwrap_or(i as u32))
+            .collect()
+    }
+
+    /// Name of a column of this version that `next` dropped, if any
+    pub(crate) fn dropped(&self, next: &SchemaVersion) -> Option<&str> {
+        let ids = next
+            .schema
+            .fields()
+            .iter()
+            .filter_map(|field| field_id(field))
+            .collect::<Vec<_>>();
+        self.schema
+            .fields()
+            .iter()
+            .enumerate()
+            .find(|(i, field)| !ids.contains(&field_id(field).unwrap_or(*i as u32)))
+            .map(|(_, field)| field.name().as_str())
+    }
+}
+
+/// Field id of the column of `field`, `None` if it was written without one
+pub(crate) fn field_id(field: &Field) -> Option<u32> {
+    field
+        .metadata()
+        .get(PARQUET_FIELD_ID_META_KEY)?
+        .parse()
+        .ok()
+}
+
+// Data type and nullability of the column of `field`
+fn describe(field: &Field) -> String {
+    if field.is_nullable() {
+        format!("{} (nullable)", field.data_type())
+    } else {
+        format!("{} (not null)", field.data_type())
+    }
+}
+
+/// Change of a column of the stored schema that is recorded when the DB is opened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ColumnChange {
+    Rename { from: String, to: String },
+    Drop(String),
+}
+
+/// Why a DB can not be opened with a schema, as it is not the schema the DB was written with nor
+/// one that appends nullable columns to it, after renaming and dropping columns
+#[derive(Debug, Error)]
+pub enum SchemaMismatch {
+    #[error(
+        "column {0} of the stored schema is missing, see DbOption::rename_column and \
+         DbOption::drop_column"
+    )]
+    MissingColumn(String),
+    #[error(
+        "column {found} is where the stored schema has column {name}, columns can only be appended"
+    )]
+    MovedColumn { name: String, found: String },
+    #[error("column {name} is {found}, but {stored} in the stored schema")]
+    ChangedColumn {
+        name: String,
+        stored: String,
+        found: String,
+    },
+    #[error("primary key {found:?} is not the primary key {stored:?} of the stored schema")]
+    PrimaryKey {
+        stored: Vec<String>,
+        found: Vec<String>,
+    },
+    #[error("added column {0} is not nullable")]
+    NotNullable(String),
+    #[error("a column can not be renamed to {0}, a column of the stored schema")]
+    DuplicateColumn(String),
+    #[error("column {0} can not be dropped while the WAL holds records written with it")]
+    UnflushedDrop(String),
+    #[error("schema can not be stored: {0}")]
+    Arrow(#[source] ArrowError),
+}
+
+impl Encode for SchemaVersion {
+    async fn encode<W>(&self, writer: &mut W) -> Result<(), fusio::Error>
+    where
+        W: Write,
+    {
+        self.version.encode(writer).await?;
+        self.encoded.encode(writer).await
+    }
+
+    fn size(&self) -> usize {
+        self.version.size() + self.encoded.size()
+    }
+}
+
+impl Decode for SchemaVersion {
+    async fn decode<R>(reader: &mut R) -> Result<Self, fusio::Error>
+    where
+        R: SeqRead,
+    {
+        let version = u32::decode(reader).await?;
+        let encoded = Vec::<u8>::decode(reader).await?;
+        let schema = StreamReader::try_new(&encoded[..], None)
+            .map_err(|err| fusio::Error::Other(Box::new(err)))?
+            .schema();
+        let next_field_id = schema
+            .metadata()
+            .get(NEXT_FIELD_ID_KEY)
+            .and_then(|id| id.parse().ok());
+
+        match next_field_id {
+            Some(next_field_id) => Ok(Self {
+                version,
+                schema,
+                next_field_id,
+                encoded,
+            }),
+            // written before columns had field ids
+            None => Self::from_schema(version, schema, None)
+                .map_err(|err| fusio::Error::Other(Box::new(err))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::datatypes::{DataType, Field, Schema as ArrowSchema};
+    use fusio_log::{Decode, Encode};
+
+    use super::{field_id, ColumnChange, SchemaMismatch, SchemaVersion};
+    use crate::transaction::Buffer;
+
+    fn schema(fields: &[(&str, DataType, bool)]) -> Arc<ArrowSchema> {
+        Arc::new(ArrowSchema::new(
+            fields
+                .iter()
+                .map(|(name, data_type, nullable)| Field::new(*name, data_type.clone(), *nullable))
+                .collect::<Vec<_>>(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn encode_and_decode() {
+        let version = SchemaVersion::new(
+            3,
+            schema(&[
+                ("id", DataType::Int64, false),
+                ("name", DataType::Utf8, true),
+            ]),
+            &[0],
+        )
+        .unwrap();
+
+        let mut buf = Buffer::default();
+        version.encode(&mut buf).await.unwrap();
+        let bytes = buf.into_bytes();
+        assert_eq!(bytes.len(), version.size());
+
+        let decoded = SchemaVersion::decode(&mut Buffer::new(bytes))
+            .await
+            .unwrap();
+        assert_eq!(decoded, version);
+    }
+
+    fn ids(version: &SchemaVersion) -> Vec<(&str, u32)> {
+        version
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| (field.name().as_str(), field_id(field).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn evolve() {
+        let stored = SchemaVersion::new(
+            0,
+            schema(&[
+                ("id", DataType::Int64, false),
+                ("name", DataType::Utf8, true),
+            ]),
+            &[0],
+        )
+        .unwrap();
+        assert_eq!(ids(&stored), vec![("id", 0), ("name", 1)]);
+
+        assert!(stored.evolve(stored.schema(), &[0], &[]).unwrap().is_none());
+        let added = schema(&[
+            ("id", DataType::Int64, false),
+            ("name", DataType::Utf8, true),
+            ("age", DataType::UInt8, true),
+        ]);
+        let next = stored.evolve(&added, &[0], &[]).unwrap().unwrap();
+        assert_eq!(next.version(), 1);
+        assert_eq!(ids(&next), vec![("id", 0), ("name", 1), ("age", 2)]);
+
+        assert!(matches!(
+            stored.evolve(&schema(&[("id", DataType::Int64, false)]), &[0], &[]),
+            Err(SchemaMismatch::MissingColumn(name)) if name == "name"
+        ));
+        assert!(matches!(
+            stored.evolve(
+                &schema(&[
+                    ("id", DataType::Int64, false),
+                    ("name", DataType::Binary, true)
+                ]),
+                &[0],
+                &[]
+            ),
+            Err(SchemaMismatch::ChangedColumn { name, stored, found })
+                if name == "name" && stored == "Utf8 (nullable)" && found == "Binary (nullable)"
+        ));
+        assert!(matches!(
+            stored.evolve(
+                &schema(&[
+                    ("id", DataType::Int64, false),
+                    ("age", DataType::UInt8, true),
+                    ("name", DataType::Utf8, true)
+                ]),
+                &[0],
+                &[]
+            ),
+            Err(SchemaMismatch::MovedColumn { name, found }) if name == "name" && found == "age"
+        ));
+        assert!(matches!(
+            stored.evolve(&added, &[2], &[]),
+            Err(SchemaMismatch::PrimaryKey { stored, found })
+                if stored == ["id"] && found == ["age"]
+        ));
+        assert!(matches!(
+            stored.evolve(
+                &schema(&[
+                    ("id", DataType::Int64, false),
+                    ("name", DataType::Utf8, true),
+                    ("age", DataType::UInt8, false)
+                ]),
+                &[0],
+                &[]
+            ),
+            Err(SchemaMismatch::NotNullable(name)) if name == "age"
+        ));
+    }
+
+    #[test]
+    fn record_primary_key() {
+        let fields = schema(&[
+            ("id", DataType::Int64, false),
+            ("name", DataType::Utf8, true),
+        ]);
+        // stored before the primary key was
+        let stored = SchemaVersion::from_schema(2, fields.clone(), None).unwrap();
+
+        let next = stored.evolve(&fields, &[0], &[]).unwrap().unwrap();
+        assert_eq!(next.version(), 2);
+        assert_eq!(next.primary_key(), Some(vec![0]));
+        assert!(next.evolve(&fields, &[0], &[]).unwrap().is_none());
+    }
+
+    #[test]
+    fn rename_and_drop() {
+        let stored = SchemaVersion::new(
+            0,
+            schema(&[
+                ("id", DataType::Int64, false),
+                ("name", DataType::Utf8, true),
+                ("age", DataType::UInt8, true),
+            ]),
+            &[0],
+        )
+        .unwrap();
+        let changes = [
+            ColumnChange::Rename {
+                from: "name".into(),
+                to: "nickname".into(),
+            },
+            ColumnChange::Drop("age".into()),
+        ];
+
+        let renamed = schema(&[
+            ("id", DataType::Int64, false),
+            ("nickname", DataType::Utf8, true),
+            ("email", DataType::Utf8, true),
+        ]);
+        let next = stored.evolve(&renamed, &[0], &changes).unwrap().unwrap();
+        // the id of the dropped column is not given to the added one
+        assert_eq!(ids(&next), vec![("id", 0), ("nickname", 1), ("email", 3)]);
+        assert_eq!(stored.dropped(&next), Some("age"));
+        assert_eq!(next.dropped(&next), None);
+        // the changes were applied, so giving them again changes nothing
+        assert!(next.evolve(&renamed, &[0], &changes).unwrap().is_none());
+
+        assert!(matches!(
+            stored.evolve(
+                &schema(&[("id", DataType::Int64, false), ("age", DataType::UInt8, true)]),
+                &[0],
+                &[ColumnChange::Rename {
+                    from: "name".into(),
+                    to: "age".into(),
+                }]
+            ),
+            Err(SchemaMismatch::DuplicateColumn(name)) if name == "age"
+        ));
+    }
+}
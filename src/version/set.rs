@@ -1,8 +1,8 @@
 use std::{
-    collections::{BinaryHeap, HashMap},
-    mem,
+    collections::{BinaryHeap, HashMap, HashSet},
+    io, mem,
     sync::{
-        atomic::{AtomicU32, Ordering},
+        atomic::{AtomicU32, AtomicU64, Ordering},
         Arc,
     },
 };
@@ -13,11 +13,12 @@ use fusio::{fs::FileMeta, DynFs};
 use fusio_log::{Logger, Options};
 use futures_util::StreamExt;
 use itertools::Itertools;
+use tracing::error;
 
 use super::{TransactionTs, MAX_LEVEL};
 use crate::{
     executor::RwLock,
-    fs::{generate_file_id, manager::StoreManager, parse_file_id, FileId, FileType},
+    fs::{copy, manager::StoreManager, parse_file_id, FileId, FileType},
     manifest::{ManifestStorage, ManifestStorageError},
     ondisk::sstable::SsTableID,
     record::{Record, Schema},
@@ -26,7 +27,7 @@ use crate::{
         cleaner::CleanTag, edit::VersionEdit, timestamp::Timestamp, Version, VersionError,
         VersionRef,
     },
-    DbOption,
+    DbOption, WalRetention,
 };
 
 // Need to implement `PartialOrd` and `Ord` for heap insertion
@@ -63,8 +64,6 @@ where
     log_id: FileId,
     // List of WAL file ids that can be deleted
     deleted_wal: Vec<FileId>,
-    // List of SST file ids that can be deleted
-    deleted_sst: Vec<SsTableID>,
 }
 
 /// Coordinator for tracking and managing versions of on-disk state
@@ -182,7 +181,7 @@ where
             // If the log id does not already exist, we generate a new one and create version log
             // path for it
             None => {
-                let log_id = generate_file_id();
+                let log_id = option.generate_file_id();
                 let base_fs = manager.base_fs();
                 let mut log = Self::open_version_log(&option, base_fs.clone(), log_id).await?;
                 log.close().await?;
@@ -195,8 +194,13 @@ where
         let set = VersionSet::<R, E> {
             inner: Arc::new(E::rw_lock(VersionSetInner {
                 current: Arc::new(Version::<R> {
-                    ts: Timestamp::from(0),
+                    id: 0,
+                    ids: Arc::new(AtomicU64::new(1)),
                     level_slice: [const { Vec::new() }; MAX_LEVEL],
+                    range_tombstones: Vec::new(),
+                    aggregates: Default::default(),
+                    schema: None,
+                    recovered_until: None,
                     clean_sender: clean_sender.clone(),
                     option: option.clone(),
                     timestamp: timestamp.clone(),
@@ -204,7 +208,6 @@ where
                 }),
                 log_id,
                 deleted_wal: Default::default(),
-                deleted_sst: Default::default(),
             })),
             clean_sender,
             timestamp,
@@ -237,6 +240,8 @@ where
         let option = &self.option;
         let mut guard = self.inner.write().await;
         let mut new_version = Version::clone(&guard.current);
+        let mut deleted_sst = delete_gens.unwrap_or_default();
+        let mut retired_wal = Vec::new();
         let log_id = &mut guard.log_id;
         let edit_len = new_version.log_length + version_edits.len() as u32;
 
@@ -259,9 +264,8 @@ where
                 // [`VersionEdit::Add`]: the WAL is garbage collected and we push the new
                 // SST into the specified level
                 VersionEdit::Add { mut scope, level } => {
-                    // TODO: remove after apply
                     if let Some(wal_ids) = scope.wal_ids.take() {
-                        guard.deleted_wal.extend(wal_ids);
+                        retired_wal.extend(wal_ids);
                     }
 
                     if level == 0 {
@@ -280,7 +284,7 @@ where
                     }
                     if is_recover {
                         // issue: https://github.com/tonbo-io/tonbo/issues/123
-                        guard.deleted_sst.push(SsTableID::new(gen, level as usize));
+                        deleted_sst.push(SsTableID::new(gen, level as usize));
                     }
                 }
                 // [`VersionEdit::LatestTimestamp`]: update the latest timestamp
@@ -289,12 +293,44 @@ where
                         // Start from last persisted timestamp
                         timestamp.store(u32::from(ts), Ordering::Release);
                     }
-                    new_version.ts = ts;
                 }
                 // [`VersionEdit::NewLogLength`]: the version log is updated
                 VersionEdit::NewLogLength { len } => {
                     new_version.log_length = len;
                 }
+                // [`VersionEdit::DeleteRange`]: the tombstone hides the records of its range
+                VersionEdit::DeleteRange { tombstone } => {
+                    new_version.range_tombstones.push(tombstone);
+                }
+                // [`VersionEdit::NewSchema`]: the tables are read as the schema from now on
+                VersionEdit::NewSchema { schema } => {
+                    new_version.schema = Some(schema);
+                }
+                // [`VersionEdit::NewAggregate`]: the groups of the aggregate are stored from now on
+                VersionEdit::NewAggregate { name } => {
+                    Arc::make_mut(&mut new_version.aggregates)
+                        .entry(name)
+                        .or_default();
+                }
+                // [`VersionEdit::AggregateGroup`]: the state of the group replaces the stored one
+                VersionEdit::AggregateGroup { name, group, state } => {
+                    if let Some(groups) = Arc::make_mut(&mut new_version.aggregates).get_mut(&name)
+                    {
+                        if state.is_empty() {
+                            groups.remove(&group);
+                        } else {
+                            groups.insert(group, state);
+                        }
+                    }
+                }
+                // [`VersionEdit::RemoveAggregate`]: the aggregate is not registered anymore
+                VersionEdit::RemoveAggregate { name } => {
+                    Arc::make_mut(&mut new_version.aggregates).remove(&name);
+                }
+                // [`VersionEdit::RecoveredUntil`]: later opens only cut the WALs up to `wal`
+                VersionEdit::RecoveredUntil { ts, wal } => {
+                    new_version.recovered_until = Some((ts, wal));
+                }
             }
         }
 
@@ -314,15 +350,52 @@ where
             }
         }
 
-        if let Some(delete_gens) = delete_gens {
-            guard.deleted_sst.extend(delete_gens);
-        }
         log.close().await?;
 
+        // a tombstone whose tables were all compacted away has nothing left to hide, which
+        // replaying the edits derives again
+        let gens = new_version
+            .level_slice
+            .iter()
+            .flatten()
+            .map(|scope| scope.gen)
+            .collect::<HashSet<_>>();
+        new_version
+            .range_tombstones
+            .retain(|tombstone| tombstone.tables.iter().any(|gen| gens.contains(gen)));
+
+        // tables that moved to another level keep their files
+        deleted_sst.retain(|id| {
+            new_version
+                .level_slice
+                .iter()
+                .flatten()
+                .all(|scope| scope.gen != id.file_id())
+        });
+        if !deleted_sst.is_empty() {
+            // Only the current version and older ones may still read the removed SSTables, so
+            // the cleaner removes them as soon as those versions are dropped
+            if let Err(err) = self
+                .clean_sender
+                .send_async(CleanTag::Add {
+                    version_id: guard.current.id(),
+                    gens: deleted_sst,
+                })
+                .await
+            {
+                error!("[Version Clean Error]: {}", err)
+            }
+        }
         guard.current = Arc::new(new_version);
+        if option.wal_retention == WalRetention::Deferred {
+            guard.deleted_wal.append(&mut retired_wal);
+        }
 
         drop(guard);
 
+        if !retired_wal.is_empty() {
+            self.retire_wals(retired_wal).await?;
+        }
         // Rewrite + clean if edit is not empty or during recovery
         if edit_len >= option.version_log_snapshot_threshold || is_recover {
             self.compact_log().await?;
@@ -337,7 +410,7 @@ where
         let mut new_version = Version::clone(&guard.current);
         let fs = self.manager.local_fs();
         let log_id = &mut guard.log_id;
-        let old_log_id = mem::replace(log_id, generate_file_id());
+        let old_log_id = mem::replace(log_id, self.option.generate_file_id());
 
         new_version.log_length = 0;
         let edits = new_version.to_edits();
@@ -355,10 +428,9 @@ where
         Ok(())
     }
 
-    // Delete the remaining WAL and SST files
+    // Delete the remaining WAL files
     async fn clean(&self) -> Result<(), VersionError> {
         let mut guard = self.inner.write().await;
-        let version = Version::clone(&guard.current);
         if !guard.deleted_wal.is_empty() {
             for wal_id in guard.deleted_wal.iter() {
                 // may have been removed after multiple starts
@@ -370,19 +442,66 @@ where
             }
             guard.deleted_wal.clear();
         }
-        if !guard.deleted_sst.is_empty() {
-            version
-                .clean_sender
-                .send_async(CleanTag::Add {
-                    ts: version.ts,
-                    gens: guard.deleted_sst.clone(),
-                })
-                .await
-                .map_err(VersionError::Send)?;
-            guard.deleted_sst.clear();
+        Ok(())
+    }
+
+    // Remove or move the WALs of flushed memtables as the `WalRetention` of the options says
+    async fn retire_wals(&self, wal_ids: Vec<FileId>) -> Result<(), VersionError> {
+        let fs = self.manager.base_fs();
+        let retained_dir = self.option.retained_wal_dir_path();
+
+        for wal_id in wal_ids {
+            let path = self.option.wal_path(wal_id);
+            if let Some(dir) = &retained_dir {
+                let retained_path = dir.child(format!("{}.{}", wal_id, FileType::Wal));
+                match copy(fs.as_ref(), &path, fs.as_ref(), &retained_path).await {
+                    Ok(()) => (),
+                    // already retired before a restart
+                    Err(fusio::Error::Io(err)) if err.kind() == io::ErrorKind::NotFound => continue,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            // may have been removed after multiple starts
+            let _ = fs.remove(&path).await;
+        }
+        self.prune_retained_wals().await
+    }
+
+    // Remove the kept WALs beyond the limit of `WalRetention::KeepFiles` or
+    // `WalRetention::KeepFor`
+    async fn prune_retained_wals(&self) -> Result<(), VersionError> {
+        let (keep_files, keep_for) = match &self.option.wal_retention {
+            WalRetention::KeepFiles(files) => (*files, None),
+            WalRetention::KeepFor(age) => (usize::MAX, Some(age.as_millis() as u64)),
+            _ => return Ok(()),
+        };
+        let Some(dir) = self.option.retained_wal_dir_path() else {
+            return Ok(());
+        };
+        let fs = self.manager.base_fs();
+        // the clock the ids, and therefore the ages, of new WALs are taken from
+        let now = self.option.now_ms();
+
+        let mut retained = Vec::new();
+        let mut stream = fs.list(&dir).await?;
+        while let Some(meta) = stream.next().await {
+            let meta = meta?;
+            if let Ok(Some(wal_id)) = parse_file_id(&meta.path, FileType::Wal) {
+                retained.push((wal_id, meta.path));
+            }
+        }
+        drop(stream);
+
+        // newest first
+        retained.sort_unstable_by(|(a, _), (b, _)| b.cmp(a));
+        for (i, (wal_id, path)) in retained.into_iter().enumerate() {
+            let expired =
+                keep_for.is_some_and(|age| now.saturating_sub(wal_id.timestamp_ms()) >= age);
+            if i >= keep_files || expired {
+                fs.remove(&path).await?;
+            }
         }
 
-        guard.current = Arc::new(version);
         Ok(())
     }
 
@@ -455,6 +574,16 @@ where
                 }
             }
         }
+        if let Some((pinned_path, _)) = &self.option.pinned_tables {
+            let local_fs = self.manager.local_fs();
+            let mut stream = local_fs.list(pinned_path).await?;
+            while let Some(meta) = stream.next().await.transpose()? {
+                if let Ok(Some(gen)) = parse_file_id(&meta.path, FileType::Parquet) {
+                    self.manager.pinned().unpin(gen);
+                }
+                local_fs.remove(&meta.path).await?;
+            }
+        }
 
         Ok(())
     }
@@ -519,7 +648,7 @@ pub(crate) mod tests {
             set::{VersionSet, VersionSetInner},
             TransactionTs, Version, VersionError,
         },
-        DbOption,
+        DbOption, WalRetention,
     };
 
     pub(crate) async fn build_version_set<R, E>(
@@ -541,7 +670,6 @@ pub(crate) mod tests {
                 current: Arc::new(version),
                 log_id,
                 deleted_wal: Default::default(),
-                deleted_sst: Default::default(),
             })),
             clean_sender,
             timestamp,
@@ -627,6 +755,9 @@ pub(crate) mod tests {
                         gen: gen_0,
                         wal_ids: None,
                         file_size: 7,
+                        rows: 0,
+                        tombstones: 0,
+                        expires: None,
                     },
                 }],
                 None,
@@ -645,6 +776,9 @@ pub(crate) mod tests {
                         gen: gen_1,
                         wal_ids: None,
                         file_size: 7,
+                        rows: 0,
+                        tombstones: 0,
+                        expires: None,
                     },
                 }],
                 None,
@@ -662,6 +796,9 @@ pub(crate) mod tests {
                         gen: gen_2,
                         wal_ids: None,
                         file_size: 7,
+                        rows: 0,
+                        tombstones: 0,
+                        expires: None,
                     },
                 }],
                 None,
@@ -689,7 +826,10 @@ pub(crate) mod tests {
                             max: "1".to_string(),
                             gen: gen_0,
                             wal_ids: None,
-                            file_size: 7
+                            file_size: 7,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
                         },
                     },
                     VersionEdit::NewLogLength { len: 1 },
@@ -700,7 +840,10 @@ pub(crate) mod tests {
                             max: "3".to_string(),
                             gen: gen_1,
                             wal_ids: None,
-                            file_size: 7
+                            file_size: 7,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
                         },
                     },
                     VersionEdit::NewLogLength { len: 2 },
@@ -711,7 +854,10 @@ pub(crate) mod tests {
                             max: "5".to_string(),
                             gen: gen_2,
                             wal_ids: None,
-                            file_size: 7
+                            file_size: 7,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
                         },
                     },
                     VersionEdit::NewLogLength { len: 3 },
@@ -752,7 +898,10 @@ pub(crate) mod tests {
                             max: "3".to_string(),
                             gen: gen_1,
                             wal_ids: None,
-                            file_size: 7
+                            file_size: 7,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
                         },
                     },
                     VersionEdit::LatestTimeStamp { ts: 0.into() },
@@ -800,6 +949,9 @@ pub(crate) mod tests {
                 gen: gen_d,
                 wal_ids: None,
                 file_size: 0,
+                rows: 0,
+                tombstones: 0,
+                expires: None,
             });
             guard.current = Arc::new(v);
         }
@@ -818,6 +970,9 @@ pub(crate) mod tests {
                             gen: gen_b,
                             wal_ids: None,
                             file_size: 0,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
                         },
                     },
                     VersionEdit::Add {
@@ -828,6 +983,9 @@ pub(crate) mod tests {
                             gen: gen_a,
                             wal_ids: None,
                             file_size: 0,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
                         },
                     },
                     VersionEdit::Add {
@@ -838,6 +996,9 @@ pub(crate) mod tests {
                             gen: gen_c,
                             wal_ids: None,
                             file_size: 0,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
                         },
                     },
                 ],
@@ -893,6 +1054,9 @@ pub(crate) mod tests {
                 gen: gen_d,
                 wal_ids: None,
                 file_size: 0,
+                rows: 0,
+                tombstones: 0,
+                expires: None,
             });
             v.level_slice[1].push(Scope {
                 min: "8".to_string(),
@@ -900,6 +1064,9 @@ pub(crate) mod tests {
                 gen: gen_d,
                 wal_ids: None,
                 file_size: 0,
+                rows: 0,
+                tombstones: 0,
+                expires: None,
             });
             guard.current = Arc::new(v);
         }
@@ -918,6 +1085,9 @@ pub(crate) mod tests {
                             gen: gen_b,
                             wal_ids: None,
                             file_size: 0,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
                         },
                     },
                     VersionEdit::Add {
@@ -928,6 +1098,9 @@ pub(crate) mod tests {
                             gen: gen_a,
                             wal_ids: None,
                             file_size: 0,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
                         },
                     },
                     VersionEdit::Add {
@@ -938,6 +1111,9 @@ pub(crate) mod tests {
                             gen: gen_c,
                             wal_ids: None,
                             file_size: 0,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
                         },
                     },
                 ],
@@ -994,6 +1170,9 @@ pub(crate) mod tests {
                             gen: gen_0,
                             wal_ids: None,
                             file_size: 7,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
                         },
                     },
                     VersionEdit::Add {
@@ -1004,6 +1183,9 @@ pub(crate) mod tests {
                             gen: gen_1,
                             wal_ids: None,
                             file_size: 7,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
                         },
                     },
                     VersionEdit::Add {
@@ -1014,6 +1196,9 @@ pub(crate) mod tests {
                             gen: gen_2,
                             wal_ids: None,
                             file_size: 7,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
                         },
                     },
                     VersionEdit::Remove {
@@ -1050,7 +1235,10 @@ pub(crate) mod tests {
                         max: "3".to_string(),
                         gen: gen_1,
                         wal_ids: None,
-                        file_size: 7
+                        file_size: 7,
+                        rows: 0,
+                        tombstones: 0,
+                        expires: None,
                     },
                 },
                 VersionEdit::LatestTimeStamp { ts: 0.into() },
@@ -1083,7 +1271,10 @@ pub(crate) mod tests {
                         max: "3".to_string(),
                         gen: gen_1,
                         wal_ids: None,
-                        file_size: 7
+                        file_size: 7,
+                        rows: 0,
+                        tombstones: 0,
+                        expires: None,
                     },
                 },
                 VersionEdit::LatestTimeStamp { ts: 0.into() },
@@ -1177,6 +1368,9 @@ pub(crate) mod tests {
                         gen: gen_0,
                         wal_ids: None,
                         file_size: 7,
+                        rows: 0,
+                        tombstones: 0,
+                        expires: None,
                     },
                 }],
                 None,
@@ -1195,6 +1389,9 @@ pub(crate) mod tests {
                             gen: gen_1,
                             wal_ids: None,
                             file_size: 7,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
                         },
                     },
                     VersionEdit::Add {
@@ -1205,6 +1402,9 @@ pub(crate) mod tests {
                             gen: gen_2,
                             wal_ids: None,
                             file_size: 7,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
                         },
                     },
                     VersionEdit::Add {
@@ -1215,6 +1415,9 @@ pub(crate) mod tests {
                             gen: gen_3,
                             wal_ids: None,
                             file_size: 7,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
                         },
                     },
                 ],
@@ -1239,4 +1442,73 @@ pub(crate) mod tests {
             ]
         );
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keep_retired_wals() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(StoreManager::new(FsOptions::Local, vec![]).unwrap());
+        let option = Arc::new(
+            DbOption::new(
+                Path::from_filesystem_path(temp_dir.path()).unwrap(),
+                &StringSchema,
+            )
+            .wal_retention(WalRetention::KeepFiles(1)),
+        );
+        for dir in [
+            option.version_log_dir_path(),
+            option.wal_dir_path(),
+            option.retained_wal_dir_path().unwrap(),
+        ] {
+            manager.base_fs().create_dir_all(&dir).await.unwrap();
+        }
+
+        let (sender, _) = bounded(1);
+        let version_set: VersionSet<String, crate::executor::tokio::TokioExecutor> =
+            VersionSet::new(sender, option.clone(), manager)
+                .await
+                .unwrap();
+        let wal_ids = [generate_file_id(), generate_file_id()];
+        for (i, wal_id) in wal_ids.into_iter().enumerate() {
+            std::fs::write(
+                temp_dir.path().join("wal").join(format!("{wal_id}.wal")),
+                b"wal",
+            )
+            .unwrap();
+            version_set
+                .apply_edits(
+                    vec![VersionEdit::Add {
+                        level: 0,
+                        scope: Scope {
+                            min: i.to_string(),
+                            max: i.to_string(),
+                            gen: generate_file_id(),
+                            wal_ids: Some(vec![wal_id]),
+                            file_size: 7,
+                            rows: 0,
+                            tombstones: 0,
+                            expires: None,
+                        },
+                    }],
+                    None,
+                    false,
+                )
+                .await
+                .unwrap();
+        }
+
+        // flushed WALs are not replayed, and only the newest one is kept
+        assert_eq!(
+            std::fs::read_dir(temp_dir.path().join("wal"))
+                .unwrap()
+                .count(),
+            0
+        );
+        let retained = std::fs::read_dir(temp_dir.path().join("wal_retained"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert_eq!(retained.len(), 1);
+        assert!(retained[0].ends_with(format!("{}.wal", wal_ids[1])));
+        assert_eq!(std::fs::read(&retained[0]).unwrap(), b"wal");
+    }
 }
@@ -13,6 +13,8 @@ pub enum LogType {
     First,
     Middle,
     Last,
+    // A commit of a single operand of `DB::merge`
+    Merge,
 }
 
 impl From<u8> for LogType {
@@ -22,6 +24,7 @@ impl From<u8> for LogType {
             1 => Self::First,
             2 => Self::Middle,
             3 => Self::Last,
+            4 => Self::Merge,
             _ => unreachable!(),
         }
     }
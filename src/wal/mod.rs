@@ -1,6 +1,6 @@
 pub(crate) mod log;
 
-use std::{pin::pin, sync::Arc};
+use std::{collections::HashMap, pin::pin, sync::Arc};
 
 use async_stream::stream;
 use fusio::{disk::LocalFs, DynFs};
@@ -9,7 +9,27 @@ use futures_core::Stream;
 use futures_util::{StreamExt, TryStreamExt};
 use thiserror::Error;
 
-use crate::{fs::FileId, record::Record, wal::log::Log};
+use crate::{
+    error::{fusio_kind, io_kind, source_kind, ErrorKind},
+    fs::FileId,
+    record::{Record, Schema},
+    version::timestamp::Timestamp,
+    wal::log::{Log, LogType},
+};
+
+/// Entries of a single commit recovered from the WAL. They must be replayed under one timestamp.
+pub(crate) type RecoveredCommit<R> = Vec<(<<R as Record>::Schema as Schema>::Key, Option<R>)>;
+
+/// A commit recovered from the WAL, see [`WalFile::recover_commits`]
+pub(crate) enum Recovered<R>
+where
+    R: Record,
+{
+    /// Records and removals
+    Commit(RecoveredCommit<R>),
+    /// An operand of [`DB::merge`](crate::DB::merge)
+    Merge(R),
+}
 
 pub(crate) struct WalFile<R>
 where
@@ -73,6 +93,20 @@ where
         self.file.as_mut().unwrap().write(data).await
     }
 
+    /// Write the entries of a commit as one batch, which is checksummed and recovered as a whole
+    pub(crate) async fn write_batch(&mut self, data: &[Log<R>]) -> Result<(), LogError> {
+        if self.file.is_none() {
+            self.file = Some(
+                Options::new(self.path.clone())
+                    .buf_size(self.wal_buffer_size)
+                    .build_with_fs::<Log<R>>(self.local_fs.clone())
+                    .await?,
+            );
+        }
+
+        self.file.as_mut().unwrap().write_batch(data.iter()).await
+    }
+
     pub(crate) async fn flush(&mut self) -> Result<(), LogError> {
         match self.file.take() {
             Some(mut file) => {
@@ -131,6 +165,55 @@ where
                 }
         }
     }
+
+    /// Decodes the whole WAL file at `path` into the commits it contains together with their
+    /// original commit timestamp, in the order they were completed. Multipart commits are
+    /// reassembled from their `First`/`Middle`/`Last` entries, a trailing commit without its `Last`
+    /// entry (crash mid-commit) is dropped. Every operand of a merge is a commit of its own.
+    pub(crate) async fn recover_commits(
+        fs_option: FsOptions,
+        path: Path,
+    ) -> Result<Vec<(Timestamp, Recovered<R>)>, RecoverError<fusio::Error>> {
+        let mut commits = Vec::new();
+        let mut transaction_map: HashMap<Timestamp, RecoveredCommit<R>> = HashMap::new();
+
+        let mut recover_stream = pin!(Self::recover(fs_option, path).await);
+        while let Some(record_batch) = recover_stream.next().await {
+            for Log {
+                key,
+                value,
+                log_type,
+            } in record_batch?
+            {
+                let ts = key.ts;
+                let key = key.value;
+
+                match log_type.unwrap() {
+                    LogType::Full => commits.push((ts, Recovered::Commit(vec![(key, value)]))),
+                    LogType::First => {
+                        transaction_map.insert(ts, vec![(key, value)]);
+                    }
+                    LogType::Middle => {
+                        if let Some(records) = transaction_map.get_mut(&ts) {
+                            records.push((key, value));
+                        }
+                    }
+                    LogType::Last => {
+                        if let Some(mut records) = transaction_map.remove(&ts) {
+                            records.push((key, value));
+                            commits.push((ts, Recovered::Commit(records)));
+                        }
+                    }
+                    LogType::Merge => {
+                        if let Some(operand) = value {
+                            commits.push((ts, Recovered::Merge(operand)));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(commits)
+    }
 }
 
 #[derive(Debug, Error)]
@@ -145,6 +228,20 @@ pub enum RecoverError<E: std::error::Error> {
     Fusio(#[from] fusio::Error),
     #[error("wal recover log error")]
     Logger(#[from] LogError),
+    #[error("wal recover task was canceled")]
+    Canceled,
+}
+
+impl<E: std::error::Error> RecoverError<E> {
+    pub(crate) fn kind(&self) -> ErrorKind {
+        match self {
+            RecoverError::Decode(_) | RecoverError::Checksum => ErrorKind::Corruption,
+            RecoverError::Io(err) => io_kind(err),
+            RecoverError::Fusio(err) => fusio_kind(err),
+            RecoverError::Logger(err) => source_kind(err),
+            RecoverError::Canceled => ErrorKind::Io,
+        }
+    }
 }
 
 #[cfg(all(test, feature = "tokio"))]
@@ -0,0 +1,256 @@
+//! Notifications of the changes to the keys of a [`DB`](crate::DB), see
+//! [`DB::watch`](crate::DB::watch)
+
+use std::{
+    fmt::{Debug, Formatter},
+    ops::{Bound, RangeBounds},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    task::{Context, Poll},
+};
+
+use flume::{r#async::RecvStream, TrySendError};
+use futures_core::Stream;
+use thiserror::Error;
+
+use crate::{record::Key, version::timestamp::Timestamp};
+
+/// The keys a [`Watch`] is notified of
+#[derive(Clone)]
+pub enum WatchKeys<K> {
+    /// A single key
+    Key(K),
+    /// The keys within a range
+    Range(Bound<K>, Bound<K>),
+    /// The keys `matches` returns true for, e.g. the keys with a common prefix
+    Matching(Arc<dyn Fn(&K) -> bool + Send + Sync>),
+}
+
+impl<K: Key> WatchKeys<K> {
+    fn contains(&self, key: &K) -> bool {
+        match self {
+            WatchKeys::Key(watched) => watched == key,
+            WatchKeys::Range(lower, upper) => (lower.as_ref(), upper.as_ref()).contains(key),
+            WatchKeys::Matching(matches) => matches(key),
+        }
+    }
+}
+
+impl<K: Debug> Debug for WatchKeys<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchKeys::Key(key) => f.debug_tuple("Key").field(key).finish(),
+            WatchKeys::Range(lower, upper) => {
+                f.debug_tuple("Range").field(lower).field(upper).finish()
+            }
+            WatchKeys::Matching(_) => f.write_str("Matching"),
+        }
+    }
+}
+
+/// What a change did to a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeOp {
+    /// A record was written
+    Insert,
+    /// The record was removed
+    Remove,
+}
+
+/// A write or removal of a watched key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change<K> {
+    /// The changed key
+    pub key: K,
+    /// Whether the key was written or removed
+    pub op: ChangeOp,
+    /// Timestamp of the commit that changed the key
+    pub ts: Timestamp,
+}
+
+/// A [`Watch`] fell behind and `missed` changes were dropped since its buffer was full
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+#[error("watch lagged behind by {missed} changes")]
+pub struct WatchLagged {
+    /// Number of changes dropped since the last item of the stream
+    pub missed: u64,
+}
+
+/// Stream of the changes to the watched keys of a [`DB`](crate::DB), in commit order
+///
+/// Changes are buffered up to the capacity the watch was created with. Changes that do not fit
+/// are dropped, and the next item of the stream is a [`WatchLagged`] error with how many were,
+/// so the consumer knows to resynchronize, e.g. by reading the watched keys again.
+pub struct Watch<K>
+where
+    K: Key,
+{
+    changes: RecvStream<'static, Change<K>>,
+    missed: Arc<AtomicU64>,
+}
+
+impl<K> Stream for Watch<K>
+where
+    K: Key,
+{
+    type Item = Result<Change<K>, WatchLagged>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let missed = self.missed.swap(0, Ordering::AcqRel);
+        if missed > 0 {
+            return Poll::Ready(Some(Err(WatchLagged { missed })));
+        }
+        Pin::new(&mut self.changes)
+            .poll_next(cx)
+            .map(|change| change.map(Ok))
+    }
+}
+
+struct Watcher<K> {
+    keys: WatchKeys<K>,
+    changes: flume::Sender<Change<K>>,
+    missed: Arc<AtomicU64>,
+}
+
+/// The watches of a DB, notified by the write path
+pub(crate) struct Watchers<K> {
+    watchers: Mutex<Vec<Watcher<K>>>,
+}
+
+impl<K> Default for Watchers<K> {
+    fn default() -> Self {
+        Self {
+            watchers: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<K> Watchers<K>
+where
+    K: Key,
+{
+    pub(crate) fn watch(&self, keys: WatchKeys<K>, capacity: usize) -> Watch<K> {
+        let (tx, rx) = flume::bounded(capacity);
+        let missed = Arc::new(AtomicU64::new(0));
+
+        self.lock().push(Watcher {
+            keys,
+            changes: tx,
+            missed: missed.clone(),
+        });
+        Watch {
+            changes: rx.into_stream(),
+            missed,
+        }
+    }
+
+    /// Notify the watches of `key` that it was changed by the commit at `ts`
+    pub(crate) fn notify(&self, key: &K, op: ChangeOp, ts: Timestamp) {
+        let mut watchers = self.lock();
+        if watchers.is_empty() {
+            return;
+        }
+
+        watchers.retain(|watcher| {
+            if !watcher.keys.contains(key) {
+                return !watcher.changes.is_disconnected();
+            }
+            let change = Change {
+                key: key.clone(),
+                op,
+                ts,
+            };
+            match watcher.changes.try_send(change) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    watcher.missed.fetch_add(1, Ordering::AcqRel);
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Watcher<K>>> {
+        self.watchers.lock().expect("watchers lock should not fail")
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{ops::Bound, sync::Arc};
+
+    use fusio::path::Path;
+    use futures_util::StreamExt;
+    use tempfile::TempDir;
+
+    use super::{ChangeOp, WatchKeys, WatchLagged};
+    use crate::{
+        executor::tokio::TokioExecutor, inmem::immutable::tests::TestSchema, tests::Test, DbOption,
+        DB,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn watch_keys_and_lag() {
+        let temp_dir = TempDir::new().unwrap();
+        let option = DbOption::new(
+            Path::from_filesystem_path(temp_dir.path()).unwrap(),
+            &TestSchema,
+        );
+        let db: DB<Test, TokioExecutor> = DB::new(option, TokioExecutor::default(), TestSchema)
+            .await
+            .unwrap();
+        let record = |key: &str| Test {
+            vstring: key.to_string(),
+            vu32: 0,
+            vbool: None,
+        };
+
+        let mut key = db.watch(WatchKeys::Key("b".to_string()), 8).await;
+        let mut range = db
+            .watch(
+                WatchKeys::Range(
+                    Bound::Included("a".to_string()),
+                    Bound::Excluded("c".to_string()),
+                ),
+                8,
+            )
+            .await;
+        let mut prefix = db
+            .watch(
+                WatchKeys::Matching(Arc::new(|key: &String| key.starts_with("user/"))),
+                1,
+            )
+            .await;
+
+        db.insert(record("a")).await.unwrap();
+        db.insert(record("b")).await.unwrap();
+        db.remove("b".to_string()).await.unwrap();
+        let mut txn = db.transaction().await;
+        txn.insert(record("user/1"));
+        txn.insert(record("user/2"));
+        txn.insert(record("user/3"));
+        txn.commit().await.unwrap();
+
+        let change = key.next().await.unwrap().unwrap();
+        assert_eq!((change.key.as_str(), change.op), ("b", ChangeOp::Insert));
+        let removal = key.next().await.unwrap().unwrap();
+        assert_eq!(removal.op, ChangeOp::Remove);
+        assert!(removal.ts > change.ts);
+
+        let keys = [
+            range.next().await.unwrap().unwrap(),
+            range.next().await.unwrap().unwrap(),
+            range.next().await.unwrap().unwrap(),
+        ]
+        .map(|change| change.key);
+        assert_eq!(keys, ["a", "b", "b"]);
+
+        // the buffer of one change dropped the two others of the transaction
+        assert_eq!(prefix.next().await.unwrap(), Err(WatchLagged { missed: 2 }));
+        assert_eq!(prefix.next().await.unwrap().unwrap().key, "user/1");
+    }
+}
@@ -1,4 +1,4 @@
-use tonbo::record::F32;
+use tonbo::record::{FixedSizeBinary, Uuid, F32};
 use tonbo_macros::Record;
 
 #[derive(Record, Debug, PartialEq)]
@@ -18,12 +18,29 @@ pub struct Point {
     y: i32,
 }
 
+#[derive(Record, Debug, PartialEq)]
+pub struct Event {
+    #[record(primary_key)]
+    tenant: String,
+    payload: Option<String>,
+    #[record(primary_key)]
+    time: i64,
+}
+
+#[derive(Record, Debug, PartialEq)]
+pub struct Device {
+    #[record(primary_key)]
+    id: Uuid,
+    tag: Option<FixedSizeBinary<4>>,
+}
+
 #[cfg(test)]
 mod tests {
     use std::{io::Cursor, sync::Arc};
 
     use arrow::array::{
-        BooleanArray, Float32Array, RecordBatch, StringArray, UInt32Array, UInt8Array,
+        BooleanArray, FixedSizeBinaryArray, Float32Array, Int64Array, RecordBatch, StringArray,
+        UInt32Array, UInt8Array,
     };
     use fusio_log::{Decode, Encode};
     use parquet::{
@@ -33,11 +50,14 @@ mod tests {
     };
     use tokio::io::AsyncSeekExt;
     use tonbo::{
-        record::{Record, RecordRef, Schema},
+        record::{Key2, Key2Ref, KeyRef, Record, RecordRef, Schema},
         ArrowArrays, ArrowArraysBuilder, Ts, TS,
     };
 
-    use crate::{Point, User, UserImmutableArrays, UserRef, UserSchema};
+    use crate::{
+        Device, DeviceImmutableArrays, DeviceSchema, Event, EventImmutableArrays, EventSchema,
+        Point, User, UserImmutableArrays, UserRef, UserSchema,
+    };
 
     #[tokio::test]
     async fn test_record_info() {
@@ -399,4 +419,133 @@ mod tests {
         let decoded = Point::decode(&mut cursor).await.unwrap();
         assert_eq!(original, decoded);
     }
+
+    #[tokio::test]
+    async fn test_composite_primary_key() {
+        let event = Event {
+            tenant: "acme".to_string(),
+            payload: Some("login".to_string()),
+            time: 42,
+        };
+
+        assert_eq!(event.key(), Key2Ref::new("acme", 42));
+        assert_eq!(event.key().to_key(), Key2("acme".to_string(), 42));
+        assert_eq!(event.as_record_ref().key(), event.key());
+        assert_eq!(EventSchema {}.primary_key_indices(), &[2, 4]);
+        let (paths, sorting) = EventSchema {}.primary_key_paths_and_sorting();
+        assert_eq!(
+            paths,
+            &[
+                ColumnPath::new(vec![TS.to_string(), "tenant".to_string()]),
+                ColumnPath::new(vec![TS.to_string(), "time".to_string()])
+            ]
+        );
+        assert_eq!(
+            sorting,
+            &[
+                SortingColumn::new(1, true, true),
+                SortingColumn::new(2, false, true),
+                SortingColumn::new(4, false, true)
+            ]
+        );
+        assert!(Key2("acme".to_string(), 42) < Key2("acme".to_string(), 43));
+        assert!(Key2("acme".to_string(), 42) < Key2("beta".to_string(), 0));
+
+        let mut builder = EventImmutableArrays::builder(EventSchema {}.arrow_schema().clone(), 2);
+        builder.push(
+            Ts {
+                ts: 0.into(),
+                value: event.key(),
+            },
+            Some(event.as_record_ref()),
+        );
+        builder.push(
+            Ts {
+                ts: 1.into(),
+                value: Key2Ref::new("beta", 7),
+            },
+            None,
+        );
+        let arrays = builder.finish(Some(&[0, 1, 2, 3, 4]));
+
+        // tombstones keep every column of their key
+        assert_eq!(
+            arrays.as_record_batch(),
+            &RecordBatch::try_new(
+                EventSchema {}.arrow_schema().clone(),
+                vec![
+                    Arc::new(BooleanArray::from(vec![false, true])),
+                    Arc::new(UInt32Array::from(vec![0, 1])),
+                    Arc::new(StringArray::from(vec!["acme", "beta"])),
+                    Arc::new(StringArray::from(vec![Some("login"), None])),
+                    Arc::new(Int64Array::from(vec![42, 7])),
+                ],
+            )
+            .unwrap()
+        );
+
+        let mut bytes = Vec::new();
+        let mut cursor = Cursor::new(&mut bytes);
+        event.as_record_ref().encode(&mut cursor).await.unwrap();
+        cursor.seek(std::io::SeekFrom::Start(0)).await.unwrap();
+        assert_eq!(Event::decode(&mut cursor).await.unwrap(), event);
+    }
+
+    #[tokio::test]
+    async fn test_fixed_size_binary_key() {
+        let device = Device {
+            id: Uuid::from([1; 16]),
+            tag: Some([1, 2, 3, 4].into()),
+        };
+
+        assert_eq!(device.key(), Uuid::from([1; 16]));
+        assert!(Uuid::from([1; 16]) < Uuid::from([2; 16]));
+
+        let mut builder = DeviceImmutableArrays::builder(DeviceSchema {}.arrow_schema().clone(), 2);
+        builder.push(
+            Ts {
+                ts: 0.into(),
+                value: device.key(),
+            },
+            Some(device.as_record_ref()),
+        );
+        builder.push(
+            Ts {
+                ts: 1.into(),
+                value: Uuid::from([2; 16]),
+            },
+            None,
+        );
+        let arrays = builder.finish(None);
+
+        assert_eq!(
+            arrays.as_record_batch(),
+            &RecordBatch::try_new(
+                DeviceSchema {}.arrow_schema().clone(),
+                vec![
+                    Arc::new(BooleanArray::from(vec![false, true])),
+                    Arc::new(UInt32Array::from(vec![0, 1])),
+                    Arc::new(FixedSizeBinaryArray::from(vec![
+                        &[1_u8; 16][..],
+                        &[2_u8; 16][..],
+                    ])),
+                    Arc::new(FixedSizeBinaryArray::from(vec![
+                        Some(&[1_u8, 2, 3, 4][..]),
+                        None,
+                    ])),
+                ],
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            arrays.get(0, &ProjectionMask::all()),
+            Some(Some(device.as_record_ref()))
+        );
+
+        let mut bytes = Vec::new();
+        let mut cursor = Cursor::new(&mut bytes);
+        device.as_record_ref().encode(&mut cursor).await.unwrap();
+        cursor.seek(std::io::SeekFrom::Start(0)).await.unwrap();
+        assert_eq!(Device::decode(&mut cursor).await.unwrap(), device);
+    }
 }
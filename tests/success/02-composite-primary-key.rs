@@ -0,0 +1,12 @@
+use tonbo_macros::Record;
+
+#[derive(Record, Debug)]
+pub struct Event {
+    #[record(primary_key)]
+    tenant: String,
+    #[record(primary_key)]
+    time: i64,
+    payload: Option<String>,
+}
+
+fn main() {}
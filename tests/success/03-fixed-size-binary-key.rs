@@ -0,0 +1,12 @@
+use tonbo::record::{FixedSizeBinary, Uuid};
+use tonbo_macros::Record;
+
+#[derive(Record, Debug)]
+pub struct Session {
+    #[record(primary_key)]
+    id: Uuid,
+    device: FixedSizeBinary<8>,
+    parent: Option<Uuid>,
+}
+
+fn main() {}
@@ -15,6 +15,8 @@ pub(crate) enum DataType {
     Bytes,
     Float32,
     Float64,
+    /// `FixedSizeBinary<N>` of `N` bytes, or `Uuid` of 16
+    FixedSizeBinary(usize),
 }
 
 impl DataType {
@@ -45,11 +47,37 @@ impl DataType {
             DataType::Float32
         } else if path.is_ident("F64") {
             DataType::Float64
+        } else if path.is_ident("Uuid") {
+            DataType::FixedSizeBinary(16)
+        } else if let Some(width) = Self::fixed_size_binary_width(path) {
+            DataType::FixedSizeBinary(width)
         } else {
             todo!()
         }
     }
 
+    /// Width `N` of a `FixedSizeBinary<N>` path, whose width is an integer literal
+    fn fixed_size_binary_width(path: &syn::Path) -> Option<usize> {
+        let segment = path.segments.last()?;
+        if segment.ident != "FixedSizeBinary" {
+            return None;
+        }
+        let syn::PathArguments::AngleBracketed(generic_args) = &segment.arguments else {
+            return None;
+        };
+        match generic_args.args.first()? {
+            syn::GenericArgument::Const(syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Int(width),
+                ..
+            })) => width.base10_parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn width_literal(width: usize) -> proc_macro2::Literal {
+        proc_macro2::Literal::usize_unsuffixed(width)
+    }
+
     pub(crate) fn to_field_ty(&self) -> proc_macro2::TokenStream {
         match self {
             DataType::UInt8 => {
@@ -91,6 +119,10 @@ impl DataType {
             DataType::Float64 => {
                 quote!(::tonbo::record::F64)
             }
+            DataType::FixedSizeBinary(width) => {
+                let width = Self::width_literal(*width);
+                quote!(::tonbo::record::FixedSizeBinary<#width>)
+            }
         }
     }
 
@@ -135,6 +167,10 @@ impl DataType {
             DataType::Float64 => {
                 quote!(::tonbo::arrow::datatypes::DataType::Float64)
             }
+            DataType::FixedSizeBinary(width) => {
+                let width = Self::width_literal(*width);
+                quote!(::tonbo::arrow::datatypes::DataType::FixedSizeBinary(#width))
+            }
         }
     }
 
@@ -183,6 +219,9 @@ impl DataType {
             DataType::Float64 => {
                 quote!(::tonbo::arrow::array::Float64Array)
             }
+            DataType::FixedSizeBinary(_) => {
+                quote!(::tonbo::arrow::array::FixedSizeBinaryArray)
+            }
         }
     }
 
@@ -227,6 +266,9 @@ impl DataType {
             DataType::Float64 => {
                 quote!(as_primitive::<::tonbo::arrow::datatypes::Float64Type>())
             }
+            DataType::FixedSizeBinary(_) => {
+                quote!(as_fixed_size_binary())
+            }
         }
     }
 
@@ -297,6 +339,12 @@ impl DataType {
                     ::tonbo::arrow::datatypes::Float64Type,
                 >::with_capacity(capacity))
             }
+            DataType::FixedSizeBinary(width) => {
+                let width = Self::width_literal(*width);
+                quote!(::tonbo::arrow::array::FixedSizeBinaryBuilder::with_capacity(
+                    capacity, #width
+                ))
+            }
         }
     }
 
@@ -385,6 +433,9 @@ impl DataType {
                     >
                 )
             }
+            DataType::FixedSizeBinary(_) => {
+                quote!(::tonbo::arrow::array::FixedSizeBinaryBuilder)
+            }
         }
     }
     pub(crate) fn to_size_method(&self, field_name: &Ident) -> proc_macro2::TokenStream {
@@ -428,6 +479,9 @@ impl DataType {
             DataType::Float64 => {
                 quote!(std::mem::size_of_val(self.#field_name.values_slice()))
             }
+            DataType::FixedSizeBinary(_) => {
+                quote!(self.#field_name.values_slice().len())
+            }
         }
     }
     pub(crate) fn to_size_field(
@@ -483,6 +537,10 @@ impl DataType {
             DataType::Float64 => {
                 quote! {std::mem::size_of::<::tonbo::record::F64>()}
             }
+            DataType::FixedSizeBinary(width) => {
+                let width = Self::width_literal(*width);
+                quote!(#width)
+            }
         }
     }
 }
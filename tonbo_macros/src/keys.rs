@@ -1,6 +1,10 @@
-use proc_macro2::{Ident, TokenStream};
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
 use syn::Type;
 
+/// Most fields a primary key is made of, as many as the composite keys of Tonbo have
+pub(crate) const MAX_PRIMARY_KEY_FIELDS: usize = 4;
+
 #[derive(Clone)]
 pub(crate) struct PrimaryKey {
     pub(crate) name: Ident,
@@ -9,3 +13,43 @@ pub(crate) struct PrimaryKey {
     pub(crate) builder_append_value: TokenStream,
     pub(crate) index: usize,
 }
+
+/// Fields of the primary key in the order of the struct, whose key is a composite key if there
+/// are several of them
+pub(crate) struct PrimaryKeys(pub(crate) Vec<PrimaryKey>);
+
+impl PrimaryKeys {
+    /// First field of the key, whose array tells how many rows the arrays have
+    pub(crate) fn first(&self) -> &PrimaryKey {
+        &self.0[0]
+    }
+
+    /// Type of the key of the schema
+    pub(crate) fn key_ty(&self) -> TokenStream {
+        match &self.0[..] {
+            [key] => {
+                let ty = &key.base_ty;
+                quote!(#ty)
+            }
+            keys => {
+                let name = self.composite_ident("");
+                let tys = keys.iter().map(|key| &key.base_ty);
+                quote!(::tonbo::record::#name<#(#tys),*>)
+            }
+        }
+    }
+
+    /// Reference to the key made of the references `components` to its fields
+    pub(crate) fn key_ref(&self, components: impl IntoIterator<Item = TokenStream>) -> TokenStream {
+        let mut components = components.into_iter().collect::<Vec<_>>();
+        if components.len() == 1 {
+            return components.remove(0);
+        }
+        let name = self.composite_ident("Ref");
+        quote!(::tonbo::record::#name::new(#(#components),*))
+    }
+
+    fn composite_ident(&self, suffix: &str) -> Ident {
+        Ident::new(&format!("Key{}{suffix}", self.0.len()), Span::call_site())
+    }
+}
@@ -29,6 +29,23 @@ use crate::data_type::DataType;
 ///     pub is_favorite: bool,
 /// }
 /// ```
+///
+/// Several fields marked `#[record(primary_key)]`, at most four, form a composite key such as
+/// `tonbo::record::Key2`, whose fields are in the order of the struct, so the records are
+/// ordered by the first one, then by the next one and so on:
+///
+/// ```no_rust
+/// #[derive(Record)]
+/// pub struct Event {
+///     #[record(primary_key)]
+///     pub tenant_id: u32,
+///     #[record(primary_key)]
+///     pub event_time: i64,
+///     pub payload: String,
+/// }
+///
+/// let key: Key2<u32, i64> = Key2(7, 1_700_000_000);
+/// ```
 #[proc_macro_derive(Record, attributes(record))]
 pub fn tonbo_record(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
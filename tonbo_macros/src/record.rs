@@ -4,7 +4,11 @@ use proc_macro2::{Ident, TokenStream};
 use quote::quote;
 use syn::{DeriveInput, Error, GenericArgument, Type};
 
-use crate::{keys::PrimaryKey, utils::ident_generator::IdentGenerator, DataType};
+use crate::{
+    keys::{PrimaryKey, PrimaryKeys, MAX_PRIMARY_KEY_FIELDS},
+    utils::ident_generator::IdentGenerator,
+    DataType,
+};
 #[derive(Debug, FromDeriveInput)]
 #[darling(attributes(record))]
 struct RecordOpts {
@@ -64,75 +68,112 @@ pub(crate) fn handle(ast: DeriveInput) -> Result<TokenStream, Error> {
         ));
     };
 
-    // todo: deny multiple primary_key definition
-    let Some((primary_key_field_index, primary_key_field)) = data_struct
+    let primary_key_fields = data_struct
         .fields
         .iter()
         .enumerate()
-        .find(|field| field.1.primary_key == Some(true))
-    else {
+        .filter(|(_, field)| field.primary_key == Some(true))
+        .collect::<Vec<_>>();
+    if primary_key_fields.is_empty() {
         return Err(syn::Error::new_spanned(
             struct_name,
             "missing primary key field, use #[record(primary_key)] to define one",
         ));
-    };
-
-    // check if primary key is nullable
-    let primary_key_data_type = primary_key_field
-        .to_data_type()
-        .expect("only Path ty is supported");
-    if primary_key_data_type.1 {
+    }
+    if primary_key_fields.len() > MAX_PRIMARY_KEY_FIELDS {
         return Err(syn::Error::new_spanned(
             struct_name,
-            "primary key cannot be nullable",
+            format!("a primary key has at most {MAX_PRIMARY_KEY_FIELDS} fields"),
         ));
     }
-    let primary_key_ident = primary_key_field
-        .ident
-        .as_ref()
-        .expect("cannot find primary key ident");
-    let primary_key_value = match primary_key_data_type.0 {
-        DataType::Float32 | DataType::Float64 => quote!(key.value.into()),
-        _ => quote!(key.value),
-    };
-    let primary_key_definitions = PrimaryKey {
-        name: primary_key_ident.clone(),
-        builder_append_value: quote! {
-            self.#primary_key_ident .append_value(#primary_key_value);
-        },
-        base_ty: primary_key_field.ty.clone(),
-        index: primary_key_field_index + 2,
-        fn_key: if matches!(primary_key_data_type.0, DataType::String) {
-            quote!(&self.#primary_key_ident)
+
+    let is_composite = primary_key_fields.len() > 1;
+    let mut primary_keys = Vec::with_capacity(primary_key_fields.len());
+    for (position, (primary_key_field_index, primary_key_field)) in
+        primary_key_fields.into_iter().enumerate()
+    {
+        // check if primary key is nullable
+        let primary_key_data_type = primary_key_field
+            .to_data_type()
+            .expect("only Path ty is supported");
+        if primary_key_data_type.1 {
+            return Err(syn::Error::new_spanned(
+                struct_name,
+                "primary key cannot be nullable",
+            ));
+        }
+        let primary_key_ident = primary_key_field
+            .ident
+            .as_ref()
+            .expect("cannot find primary key ident");
+        // the columns of a composite key are the fields of its tuple struct
+        let key_value = if is_composite {
+            let position = syn::Index::from(position);
+            quote!(key.value.#position)
         } else {
-            quote!(self.#primary_key_ident)
-        },
-    };
+            quote!(key.value)
+        };
+        let primary_key_value = match primary_key_data_type.0 {
+            DataType::Float32 | DataType::Float64 => quote!(#key_value.into()),
+            _ => key_value,
+        };
+        // a fixed size builder rejects values of another width, which the key type rules out
+        let builder_append_value =
+            if matches!(primary_key_data_type.0, DataType::FixedSizeBinary(_)) {
+                quote! {
+                    self.#primary_key_ident
+                        .append_value(#primary_key_value)
+                        .expect("the key has the width of its column");
+                }
+            } else {
+                quote! {
+                    self.#primary_key_ident .append_value(#primary_key_value);
+                }
+            };
+        primary_keys.push(PrimaryKey {
+            name: primary_key_ident.clone(),
+            builder_append_value,
+            base_ty: primary_key_field.ty.clone(),
+            index: primary_key_field_index + 2,
+            fn_key: if matches!(primary_key_data_type.0, DataType::String) {
+                quote!(self.#primary_key_ident.as_str())
+            } else {
+                quote!(self.#primary_key_ident)
+            },
+        });
+    }
+    let primary_keys = PrimaryKeys(primary_keys);
 
-    let builder_append_primary_key = &primary_key_definitions.builder_append_value;
+    let builder_append_primary_key = primary_keys
+        .0
+        .iter()
+        .map(|key| key.builder_append_value.clone())
+        .collect::<TokenStream>();
 
-    let record_codegen =
-        trait_record_codegen(&data_struct.fields, struct_name, &primary_key_definitions);
+    let record_codegen = trait_record_codegen(&data_struct.fields, struct_name, &primary_keys);
 
     let decode_codegen = trait_decode_codegen(struct_name, &data_struct.fields);
 
     let struct_ref_codegen = struct_ref_codegen(struct_name, &data_struct.fields);
 
     let struct_schema_codegen =
-        struct_schema_codegen(struct_name, &data_struct.fields, &primary_key_definitions);
+        struct_schema_codegen(struct_name, &data_struct.fields, &primary_keys);
 
     let decode_ref_codegen =
-        trait_decode_ref_codegen(&struct_name, primary_key_ident, &data_struct.fields);
+        trait_decode_ref_codegen(&struct_name, &primary_keys, &data_struct.fields);
 
     let encode_codegen = trait_encode_codegen(struct_name, &data_struct.fields);
 
     let struct_array_codegen = struct_array_codegen(struct_name, &data_struct.fields);
 
     let arrow_array_codegen =
-        trait_arrow_array_codegen(struct_name, primary_key_ident, &data_struct.fields);
+        trait_arrow_array_codegen(struct_name, &primary_keys.first().name, &data_struct.fields);
 
-    let builder_codegen =
-        struct_builder_codegen(struct_name, builder_append_primary_key, &data_struct.fields);
+    let builder_codegen = struct_builder_codegen(
+        struct_name,
+        &builder_append_primary_key,
+        &data_struct.fields,
+    );
 
     let gen = quote! {
 
@@ -162,7 +203,7 @@ pub(crate) fn handle(ast: DeriveInput) -> Result<TokenStream, Error> {
 fn trait_record_codegen(
     fields: &[RecordStructFieldOpt],
     struct_name: &Ident,
-    primary_key: &PrimaryKey,
+    primary_keys: &PrimaryKeys,
 ) -> TokenStream {
     let mut size_fields: Vec<TokenStream> = Vec::new();
 
@@ -219,10 +260,7 @@ fn trait_record_codegen(
     };
     let struct_schema_name = struct_name.to_schema_ident();
 
-    let PrimaryKey {
-        fn_key: fn_primary_key,
-        ..
-    } = primary_key;
+    let fn_primary_key = primary_keys.key_ref(primary_keys.0.iter().map(|key| key.fn_key.clone()));
 
     quote! {
         impl ::tonbo::record::Record for #struct_name {
@@ -265,7 +303,7 @@ fn trait_decode_codegen(struct_name: &Ident, fields: &[RecordStructFieldOpt]) ->
 
         if field.primary_key.unwrap_or_default() {
             decode_method_fields.push(quote! {
-                let #field_name = #field_ty::decode(reader).await?;
+                let #field_name = <#field_ty as ::tonbo::Decode>::decode(reader).await?;
             });
         } else if is_nullable {
             decode_method_fields.push(quote! {
@@ -348,19 +386,24 @@ fn struct_ref_codegen(struct_name: &Ident, fields: &[RecordStructFieldOpt]) -> T
 fn struct_schema_codegen(
     struct_name: &Ident,
     fields: &[RecordStructFieldOpt],
-    primary_key: &PrimaryKey,
+    primary_keys: &PrimaryKeys,
 ) -> TokenStream {
     let struct_schema_name = struct_name.to_schema_ident();
     let struct_arrays_name = struct_name.to_immutable_array_ident();
     let mut schema_fields: Vec<TokenStream> = Vec::new();
 
-    let PrimaryKey {
-        name: primary_key_name,
-        base_ty: primary_key_ty,
-        builder_append_value: _builder_append_primary_key,
-        index: primary_key_index,
-        ..
-    } = primary_key;
+    let primary_key_ty = primary_keys.key_ty();
+    let primary_key_names = primary_keys
+        .0
+        .iter()
+        .map(|key| &key.name)
+        .collect::<Vec<_>>();
+    let primary_key_indices = primary_keys
+        .0
+        .iter()
+        .map(|key| key.index)
+        .collect::<Vec<_>>();
+    let primary_key_len = primary_keys.0.len();
 
     for field in fields.iter() {
         let field_name = field.ident.as_ref().unwrap();
@@ -385,16 +428,16 @@ fn struct_schema_codegen(
             type Key = #primary_key_ty;
 
             fn primary_key_indices(&self) -> &[usize] {
-                const INDICES: [usize; 1] = [#primary_key_index];
+                const INDICES: [usize; #primary_key_len] = [#(#primary_key_indices),*];
                 &INDICES
             }
 
             fn primary_key_paths_and_sorting(&self) -> (&[::tonbo::parquet::schema::types::ColumnPath], &[::tonbo::parquet::format::SortingColumn]) {
                 static PATHS: ::tonbo::once_cell::sync::Lazy<Vec<::tonbo::parquet::schema::types::ColumnPath>> = ::tonbo::once_cell::sync::Lazy::new(|| {
-                    vec![::tonbo::parquet::schema::types::ColumnPath::new(vec![::tonbo::TS.to_string(), stringify!(#primary_key_name).to_string()])]
+                    vec![#(::tonbo::parquet::schema::types::ColumnPath::new(vec![::tonbo::TS.to_string(), stringify!(#primary_key_names).to_string()])),*]
                 });
                 static SORTING: ::tonbo::once_cell::sync::Lazy<Vec<::tonbo::parquet::format::SortingColumn>> = ::tonbo::once_cell::sync::Lazy::new(|| {
-                    vec![::tonbo::parquet::format::SortingColumn::new(1_i32, true, true), ::tonbo::parquet::format::SortingColumn::new(#primary_key_index as i32, false, true)]
+                    vec![::tonbo::parquet::format::SortingColumn::new(1_i32, true, true), #(::tonbo::parquet::format::SortingColumn::new(#primary_key_indices as i32, false, true)),*]
                 });
                 (&PATHS[..], &SORTING[..])
             }
@@ -410,19 +453,30 @@ fn struct_schema_codegen(
 
                 &SCHEMA
             }
+
+            fn parquet_schema(&self) -> &'static ::tonbo::parquet::schema::types::SchemaDescriptor {
+                static SCHEMA: ::tonbo::once_cell::sync::Lazy<::tonbo::parquet::schema::types::SchemaDescriptor> = ::tonbo::once_cell::sync::Lazy::new(|| {
+                    ::tonbo::parquet::arrow::ArrowSchemaConverter::new()
+                        .convert(<#struct_schema_name as ::tonbo::record::Schema>::arrow_schema(&#struct_schema_name))
+                        .unwrap()
+                });
+
+                &SCHEMA
+            }
         }
     }
 }
 
 fn trait_decode_ref_codegen(
     struct_name: &&Ident,
-    primary_key_name: &Ident,
+    primary_keys: &PrimaryKeys,
     fields: &[RecordStructFieldOpt],
 ) -> TokenStream {
     let mut ref_projection_fields: Vec<TokenStream> = Vec::new();
 
     let mut from_record_batch_fields: Vec<TokenStream> = Vec::new();
     let mut field_names: Vec<TokenStream> = Vec::new();
+    let mut to_owned_fields: Vec<TokenStream> = Vec::new();
     let mut has_ref = false;
 
     for (i, field) in fields.iter().enumerate() {
@@ -440,7 +494,24 @@ fn trait_decode_ref_codegen(
 
         field_names.push(quote!(#field_name,));
 
-        if field.primary_key.unwrap_or_default() {
+        let is_primary_key = field.primary_key.unwrap_or_default();
+        let owned = match (&data_type, is_primary_key, is_nullable) {
+            (DataType::String, true, _) => quote!(self.#field_name.to_string()),
+            (DataType::String, false, true) => quote!(self.#field_name.map(str::to_string)),
+            (DataType::String, false, false) => quote!(self.#field_name?.to_string()),
+            (DataType::Bytes, true, _) => quote!(bytes::Bytes::copy_from_slice(self.#field_name)),
+            (DataType::Bytes, false, true) => {
+                quote!(self.#field_name.map(bytes::Bytes::copy_from_slice))
+            }
+            (DataType::Bytes, false, false) => {
+                quote!(bytes::Bytes::copy_from_slice(self.#field_name?))
+            }
+            (_, false, false) => quote!(::std::clone::Clone::clone(&self.#field_name)?),
+            (_, _, _) => quote!(::std::clone::Clone::clone(&self.#field_name)),
+        };
+        to_owned_fields.push(quote!(#field_name: #owned,));
+
+        if is_primary_key {
             from_record_batch_fields.push(quote! {
                 let #field_name = record_batch
                     .column(column_i)
@@ -502,19 +573,29 @@ fn trait_decode_ref_codegen(
             #struct_ref_name
         }
     };
+    let primary_key = primary_keys.key_ref(primary_keys.0.iter().map(|key| {
+        let name = &key.name;
+        quote!(self.#name)
+    }));
 
     quote! {
         impl<'r> ::tonbo::record::RecordRef<'r> for #struct_ref_type {
             type Record = #struct_name;
 
             fn key(self) -> <<<<#struct_ref_type as ::tonbo::record::RecordRef<'r>>::Record as ::tonbo::record::Record>::Schema as ::tonbo::record::Schema>::Key as ::tonbo::record::Key>::Ref<'r> {
-                self.#primary_key_name
+                #primary_key
             }
 
             fn projection(&mut self, projection_mask: &::tonbo::parquet::arrow::ProjectionMask) {
                 #(#ref_projection_fields)*
             }
 
+            fn to_owned_record(&self) -> Option<Self::Record> {
+                Some(#struct_name {
+                    #(#to_owned_fields)*
+                })
+            }
+
             fn from_record_batch(
                 record_batch: &'r ::tonbo::arrow::record_batch::RecordBatch,
                 offset: usize,
@@ -755,6 +836,7 @@ fn struct_builder_codegen(
         let is_string = matches!(data_type, DataType::String);
         let is_bytes = matches!(data_type, DataType::Bytes);
         let is_float = matches!(data_type, DataType::Float32 | DataType::Float64);
+        let is_fixed = matches!(data_type, DataType::FixedSizeBinary(_));
         let builder = data_type.to_builder();
         let size_method = data_type.to_size_method(field_name);
 
@@ -779,12 +861,21 @@ fn struct_builder_codegen(
         } else {
             quote!(#field_name)
         };
+        let append_value = if is_fixed {
+            quote! {
+                self.#field_name
+                    .append_value(#append_val)
+                    .expect("the value has the width of its column")
+            }
+        } else {
+            quote!(self.#field_name.append_value(#append_val))
+        };
 
         if field.primary_key.unwrap_or_default() {
         } else if is_nullable {
             builder_push_some_fields.push(quote! {
                 match row.#field_name {
-                    Some(#field_name) => self.#field_name.append_value(#append_val),
+                    Some(#field_name) => #append_value,
                     None => self.#field_name.append_null(),
                 }
             });
@@ -796,12 +887,19 @@ fn struct_builder_codegen(
                 quote!(self.#field_name.append_value(""))
             } else if is_bytes {
                 quote!(self.#field_name.append_value(&[]))
+            } else if is_fixed {
+                let field_ty = data_type.to_field_ty();
+                quote! {
+                    self.#field_name
+                        .append_value(<#field_ty as ::std::default::Default>::default())
+                        .expect("the value has the width of its column")
+                }
             } else {
                 quote!(self.#field_name.append_value(Default::default()))
             };
             builder_push_some_fields.push(quote! {
                 match row.#field_name {
-                    Some(#field_name) => self.#field_name.append_value(#append_val),
+                    Some(#field_name) => #append_value,
                     None => #append_default,
                 }
             });